@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use wasmer::*;
+use wasmer_engine_jit::JIT;
+
+/// The dispatch loop of an interpreter: each iteration calls one of the
+/// handlers of a function table, like the opcode handlers of an interpreter
+/// compiled to WebAssembly.
+static DISPATCH_WAT: &str = r#"(module
+    (type $handler (func (param i32) (result i32)))
+    (table 4 funcref)
+    (elem (i32.const 0) $add $sub $xor $shl)
+    (func $add (type $handler) (i32.add (local.get 0) (i32.const 3)))
+    (func $sub (type $handler) (i32.sub (local.get 0) (i32.const 1)))
+    (func $xor (type $handler) (i32.xor (local.get 0) (i32.const 0x55)))
+    (func $shl (type $handler) (i32.shl (local.get 0) (i32.const 1)))
+    (func (export "dispatch") (param $iterations i32) (result i32)
+        (local $acc i32)
+        (local $i i32)
+        (block $done
+            (loop $next
+                (br_if $done (i32.ge_u (local.get $i) (local.get $iterations)))
+                (local.set $acc
+                    (call_indirect (type $handler)
+                        (local.get $acc)
+                        (i32.and (local.get $i) (i32.const 3))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $next)))
+        (local.get $acc))
+)"#;
+
+const ITERATIONS: i32 = 10_000;
+
+pub fn run_dispatch(store: &Store, compiler_name: &str, c: &mut Criterion) {
+    let module = Module::new(&store, DISPATCH_WAT).unwrap();
+    let instance = Instance::new(&module, &imports! {}).unwrap();
+    let dispatch: NativeFunc<i32, i32> = instance.exports.get_native_function("dispatch").unwrap();
+
+    c.bench_function(
+        &format!("call_indirect dispatch loop {}", compiler_name),
+        |b| {
+            b.iter(|| {
+                black_box(dispatch.call(black_box(ITERATIONS)).unwrap());
+            })
+        },
+    );
+}
+
+fn run_call_indirect_benchmarks(c: &mut Criterion) {
+    #[cfg(feature = "llvm")]
+    {
+        let store = Store::new(&JIT::new(wasmer_compiler_llvm::LLVM::new()).engine());
+        run_dispatch(&store, "llvm", c);
+    }
+
+    #[cfg(feature = "cranelift")]
+    {
+        let store = Store::new(&JIT::new(wasmer_compiler_cranelift::Cranelift::new()).engine());
+        run_dispatch(&store, "cranelift", c);
+    }
+
+    #[cfg(feature = "singlepass")]
+    {
+        let store = Store::new(&JIT::new(wasmer_compiler_singlepass::Singlepass::new()).engine());
+        run_dispatch(&store, "singlepass", c);
+    }
+}
+
+criterion_group!(benches, run_call_indirect_benchmarks);
+
+criterion_main!(benches);
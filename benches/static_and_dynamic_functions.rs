@@ -110,6 +110,16 @@ pub fn run_basic_dynamic_function(store: &Store, compiler_name: &str, c: &mut Cr
         })
     });
 
+    c.bench_function(&format!("basic dynfunc call_into {}", compiler_name), |b| {
+        let mut results = [Val::I32(0)];
+        b.iter(|| {
+            dyn_f
+                .call_into(&[Val::I32(4), Val::I32(6)], &mut results)
+                .unwrap();
+            assert_eq!(black_box(&results)[0], Val::I32(10));
+        })
+    });
+
     let dyn_f_many: &Function = instance.exports.get("add20").unwrap();
     c.bench_function(
         &format!("basic dynfunc with many args {}", compiler_name),
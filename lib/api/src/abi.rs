@@ -0,0 +1,335 @@
+//! Static validation of the ABI of guest modules.
+//!
+//! Hosts running guests of a known kind (for example smart contracts)
+//! usually check, before accepting a module, that it exports the entry
+//! points they call, only imports what they provide and stays within
+//! memory limits. [`validate`] checks a [`Module`] against an
+//! [`AbiSpec`] declaring all of that, and reports every violation at
+//! once.
+//!
+//! # Usage
+//!
+//! ```
+//! # use wasmer::*;
+//! # fn main() -> anyhow::Result<()> {
+//! # let store = Store::default();
+//! use wasmer::abi::{self, AbiSpec};
+//!
+//! let mut spec = AbiSpec::new();
+//! spec.require_export("memory", ExternType::Memory(MemoryType::new(Pages(1), None, false)))
+//!     .require_function("allocate", &[Type::I32], &[Type::I32])
+//!     .allow_import("env", "db_read", FunctionType::new(vec![Type::I32], vec![Type::I32]))
+//!     .max_memories(1)
+//!     .max_memory_pages(Pages(512));
+//!
+//! let module = Module::new(&store, r#"(module (memory (export "memory") 1))"#)?;
+//! let report = abi::validate(&module, &spec);
+//! assert!(!report.is_ok());
+//! assert_eq!(report.violations().len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+use crate::{ExternType, FunctionType, Module, Pages, Type};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The ABI a module must follow, checked by [`validate`].
+#[derive(Debug, Clone, Default)]
+pub struct AbiSpec {
+    required_exports: Vec<(String, ExternType)>,
+    allowed_imports: HashMap<(String, String), Option<ExternType>>,
+    max_memories: Option<u32>,
+    max_memory_pages: Option<Pages>,
+}
+
+impl AbiSpec {
+    /// Creates a spec without requirements, which doesn't allow any
+    /// import.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the module to export `name` with the type `ty`.
+    ///
+    /// Functions and globals must match exactly. For memories and
+    /// tables, only the kind is checked; use the memory constraints
+    /// to limit sizes.
+    pub fn require_export(&mut self, name: &str, ty: ExternType) -> &mut Self {
+        self.required_exports.push((name.to_string(), ty));
+        self
+    }
+
+    /// Requires the module to export the function `name` with the given
+    /// signature.
+    pub fn require_function(&mut self, name: &str, params: &[Type], results: &[Type]) -> &mut Self {
+        let ty = FunctionType::new(params.to_vec(), results.to_vec());
+        self.require_export(name, ExternType::Function(ty))
+    }
+
+    /// Allows the module to import the function `module`.`name` with the
+    /// given signature.
+    pub fn allow_import(&mut self, module: &str, name: &str, ty: FunctionType) -> &mut Self {
+        self.allowed_imports.insert(
+            (module.to_string(), name.to_string()),
+            Some(ExternType::Function(ty)),
+        );
+        self
+    }
+
+    /// Allows the module to import `module`.`name` with any type.
+    pub fn allow_import_of_any_type(&mut self, module: &str, name: &str) -> &mut Self {
+        self.allowed_imports
+            .insert((module.to_string(), name.to_string()), None);
+        self
+    }
+
+    /// Limits the number of memories, imported or defined.
+    pub fn max_memories(&mut self, max_memories: u32) -> &mut Self {
+        self.max_memories = Some(max_memories);
+        self
+    }
+
+    /// Limits the initial size of each memory, imported or defined.
+    pub fn max_memory_pages(&mut self, max_memory_pages: Pages) -> &mut Self {
+        self.max_memory_pages = Some(max_memory_pages);
+        self
+    }
+}
+
+/// A way a module doesn't follow an [`AbiSpec`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbiViolation {
+    /// A required export is missing.
+    MissingExport {
+        /// The name of the export.
+        name: String,
+        /// The required type.
+        expected: ExternType,
+    },
+    /// A required export has the wrong type.
+    ExportTypeMismatch {
+        /// The name of the export.
+        name: String,
+        /// The required type.
+        expected: ExternType,
+        /// The type of the export.
+        found: ExternType,
+    },
+    /// An import is not allowed.
+    ImportNotAllowed {
+        /// The module of the import.
+        module: String,
+        /// The name of the import.
+        name: String,
+        /// The type of the import.
+        ty: ExternType,
+    },
+    /// An allowed import has the wrong type.
+    ImportTypeMismatch {
+        /// The module of the import.
+        module: String,
+        /// The name of the import.
+        name: String,
+        /// The allowed type.
+        expected: ExternType,
+        /// The type of the import.
+        found: ExternType,
+    },
+    /// The module has too many memories.
+    TooManyMemories {
+        /// The number of memories of the module.
+        count: u32,
+        /// The maximum allowed.
+        max: u32,
+    },
+    /// A memory is initially too large.
+    MemoryTooLarge {
+        /// The index of the memory.
+        index: u32,
+        /// The initial size of the memory.
+        minimum: Pages,
+        /// The maximum allowed.
+        max: Pages,
+    },
+}
+
+impl fmt::Display for AbiViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingExport { name, expected } => write!(
+                f,
+                "missing export `{}` of type {}",
+                name,
+                DisplayExternType(expected)
+            ),
+            Self::ExportTypeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "export `{}` has type {} instead of {}",
+                name,
+                DisplayExternType(found),
+                DisplayExternType(expected)
+            ),
+            Self::ImportNotAllowed { module, name, ty } => write!(
+                f,
+                "import `{}`.`{}` of type {} is not allowed",
+                module,
+                name,
+                DisplayExternType(ty)
+            ),
+            Self::ImportTypeMismatch {
+                module,
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "import `{}`.`{}` has type {} instead of {}",
+                module,
+                name,
+                DisplayExternType(found),
+                DisplayExternType(expected)
+            ),
+            Self::TooManyMemories { count, max } => write!(
+                f,
+                "the module has {} memories, at most {} are allowed",
+                count, max
+            ),
+            Self::MemoryTooLarge {
+                index,
+                minimum,
+                max,
+            } => write!(
+                f,
+                "memory {} has {} initial pages, at most {} are allowed",
+                index, minimum.0, max.0
+            ),
+        }
+    }
+}
+
+struct DisplayExternType<'a>(&'a ExternType);
+
+impl fmt::Display for DisplayExternType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            ExternType::Function(ty) => write!(f, "function {}", ty),
+            ExternType::Global(ty) => write!(f, "global {}", ty),
+            ExternType::Table(ty) => write!(f, "table {}", ty),
+            ExternType::Memory(ty) => write!(f, "memory {}", ty),
+        }
+    }
+}
+
+/// The result of [`validate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AbiReport {
+    violations: Vec<AbiViolation>,
+}
+
+impl AbiReport {
+    /// Returns `true` if the module follows the spec.
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Returns all the violations, exports first, then imports, then
+    /// memories.
+    pub fn violations(&self) -> &[AbiViolation] {
+        &self.violations
+    }
+}
+
+impl fmt::Display for AbiReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "the module follows the ABI");
+        }
+        write!(f, "the module doesn't follow the ABI:")?;
+        for violation in self.violations.iter() {
+            write!(f, "\n  - {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if `found` satisfies the `expected` type of an export.
+fn export_type_matches(expected: &ExternType, found: &ExternType) -> bool {
+    match (expected, found) {
+        (ExternType::Memory(_), ExternType::Memory(_))
+        | (ExternType::Table(_), ExternType::Table(_)) => true,
+        _ => expected == found,
+    }
+}
+
+/// Checks `module` against `spec` and reports all the violations.
+pub fn validate(module: &Module, spec: &AbiSpec) -> AbiReport {
+    let mut violations = Vec::new();
+
+    let exports = module
+        .exports()
+        .map(|export| (export.name().to_string(), export.ty().clone()))
+        .collect::<HashMap<_, _>>();
+    for (name, expected) in spec.required_exports.iter() {
+        match exports.get(name) {
+            None => violations.push(AbiViolation::MissingExport {
+                name: name.clone(),
+                expected: expected.clone(),
+            }),
+            Some(found) if !export_type_matches(expected, found) => {
+                violations.push(AbiViolation::ExportTypeMismatch {
+                    name: name.clone(),
+                    expected: expected.clone(),
+                    found: found.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    for import in module.imports() {
+        let key = (import.module().to_string(), import.name().to_string());
+        match spec.allowed_imports.get(&key) {
+            None => violations.push(AbiViolation::ImportNotAllowed {
+                module: key.0,
+                name: key.1,
+                ty: import.ty().clone(),
+            }),
+            Some(Some(expected)) if expected != import.ty() => {
+                violations.push(AbiViolation::ImportTypeMismatch {
+                    module: key.0,
+                    name: key.1,
+                    expected: expected.clone(),
+                    found: import.ty().clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    let memories = &module.artifact().module_ref().memories;
+    if let Some(max) = spec.max_memories {
+        if memories.len() as u32 > max {
+            violations.push(AbiViolation::TooManyMemories {
+                count: memories.len() as u32,
+                max,
+            });
+        }
+    }
+    if let Some(max) = spec.max_memory_pages {
+        for (index, memory) in memories.values().enumerate() {
+            if memory.minimum > max {
+                violations.push(AbiViolation::MemoryTooLarge {
+                    index: index as u32,
+                    minimum: memory.minimum,
+                    max,
+                });
+            }
+        }
+    }
+
+    AbiReport { violations }
+}
@@ -0,0 +1,359 @@
+//! Static analysis of WebAssembly modules: call graph, recursion cycles
+//! and native stack estimates.
+//!
+//! Hosts can use it to reject, before running them, modules that could
+//! exceed their stack budget (see [`Store::with_stack_budget`]).
+//!
+//! The analysis works on the WebAssembly binary, which should have been
+//! validated first (for example with [`Module::validate`]).
+//!
+//! # Usage
+//!
+//! ```
+//! # use wasmer::*;
+//! # fn main() -> anyhow::Result<()> {
+//! use wasmer::analysis;
+//!
+//! let wasm = wat2wasm(br#"(module
+//!     (func $leaf)
+//!     (func $even (param i32) (call $odd (local.get 0)))
+//!     (func $odd (param i32) (call $even (local.get 0)))
+//!     (func (export "run") (call $leaf)))"#)?;
+//! let analysis = analysis::analyze(&wasm)?;
+//! assert_eq!(analysis.callees(3), &[0]);
+//! assert_eq!(analysis.recursion_cycles(), vec![vec![1, 2]]);
+//! assert!(analysis.max_stack_usage(3).is_some());
+//! assert_eq!(analysis.max_stack_usage(1), None);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Store::with_stack_budget`]: crate::Store::with_stack_budget
+//! [`Module::validate`]: crate::Module::validate
+use crate::wasmparser::{
+    BinaryReaderError, ElementItem, FuncType, ImportSectionEntryType, Operator, Parser, Payload,
+    TypeDef,
+};
+use crate::{CompileError, WasmError};
+
+/// The model used to estimate the native stack frame of a function.
+///
+/// The estimate is a heuristic: the actual frame size depends on the
+/// compiler and on its register allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackCostModel {
+    /// The bytes every frame uses: return address, saved registers,
+    /// spill area.
+    pub frame_overhead: u64,
+    /// The bytes used by each parameter and local.
+    pub bytes_per_local: u64,
+}
+
+impl Default for StackCostModel {
+    fn default() -> Self {
+        Self {
+            frame_overhead: 128,
+            bytes_per_local: 16,
+        }
+    }
+}
+
+/// What the analysis found about a function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionSummary {
+    /// Whether the function is imported.
+    pub imported: bool,
+    /// The number of parameters and locals.
+    pub locals: u64,
+    /// Whether the function makes indirect calls.
+    pub calls_indirect: bool,
+    /// The estimated native stack frame size of the function, in bytes.
+    /// Imported functions are counted as `0`.
+    pub frame_size: u64,
+    /// The functions it may call, sorted: the direct callees and, for
+    /// indirect calls, every function of the same signature that can be
+    /// stored in a table.
+    callees: Vec<u32>,
+}
+
+/// The result of [`analyze`].
+#[derive(Debug, Clone)]
+pub struct ModuleAnalysis {
+    functions: Vec<FunctionSummary>,
+    /// The strongly connected components of the call graph, callees
+    /// first.
+    components: Vec<Vec<u32>>,
+    /// The maximum stack usage of each function, `None` if unbounded.
+    max_stack_usage: Vec<Option<u64>>,
+}
+
+impl ModuleAnalysis {
+    /// Returns the summaries of all the functions, imported ones first.
+    pub fn functions(&self) -> &[FunctionSummary] {
+        &self.functions
+    }
+
+    /// Returns the functions `function_index` may call, sorted.
+    pub fn callees(&self, function_index: u32) -> &[u32] {
+        &self.functions[function_index as usize].callees
+    }
+
+    /// Returns the recursion cycles: the groups of functions that can
+    /// call themselves, directly or through each other. Each group and
+    /// the list of groups are sorted.
+    pub fn recursion_cycles(&self) -> Vec<Vec<u32>> {
+        let mut cycles = self
+            .components
+            .iter()
+            .filter(|component| self.is_cyclic(component))
+            .cloned()
+            .collect::<Vec<_>>();
+        cycles.sort();
+        cycles
+    }
+
+    /// Returns the estimated maximum native stack used by a call to
+    /// `function_index`, including its callees, or `None` if it may
+    /// recurse without bound.
+    pub fn max_stack_usage(&self, function_index: u32) -> Option<u64> {
+        self.max_stack_usage[function_index as usize]
+    }
+
+    fn is_cyclic(&self, component: &[u32]) -> bool {
+        component.len() > 1 || self.callees(component[0]).contains(&component[0])
+    }
+}
+
+fn to_compile_error(error: BinaryReaderError) -> CompileError {
+    CompileError::Wasm(WasmError::InvalidWebAssembly {
+        message: error.message().to_string(),
+        offset: error.offset(),
+    })
+}
+
+fn same_signature(a: &Option<FuncType>, b: &Option<FuncType>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.params == b.params && a.returns == b.returns,
+        _ => false,
+    }
+}
+
+/// Analyzes a WebAssembly binary with the default [`StackCostModel`].
+pub fn analyze(wasm: &[u8]) -> Result<ModuleAnalysis, CompileError> {
+    analyze_with_model(wasm, &StackCostModel::default())
+}
+
+/// Analyzes a WebAssembly binary, estimating the stack frames with
+/// `model`.
+pub fn analyze_with_model(
+    wasm: &[u8],
+    model: &StackCostModel,
+) -> Result<ModuleAnalysis, CompileError> {
+    let mut types = Vec::new();
+    let mut function_types = Vec::new();
+    let mut functions = Vec::new();
+    let mut num_imported = 0;
+    // Functions that can end up in a table, hence be called indirectly.
+    let mut referenced = Vec::new();
+    // The type indices used by the indirect calls of each function.
+    let mut indirect_types = Vec::new();
+
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.map_err(to_compile_error)? {
+            Payload::TypeSection(section) => {
+                for entry in section {
+                    types.push(match entry.map_err(to_compile_error)? {
+                        TypeDef::Func(ty) => Some(ty),
+                        _ => None,
+                    });
+                }
+            }
+            Payload::ImportSection(section) => {
+                for import in section {
+                    if let ImportSectionEntryType::Function(ty) =
+                        import.map_err(to_compile_error)?.ty
+                    {
+                        function_types.push(ty);
+                        functions.push(FunctionSummary {
+                            imported: true,
+                            ..Default::default()
+                        });
+                        indirect_types.push(Vec::new());
+                        num_imported += 1;
+                    }
+                }
+            }
+            Payload::FunctionSection(section) => {
+                for ty in section {
+                    function_types.push(ty.map_err(to_compile_error)?);
+                }
+            }
+            Payload::ElementSection(section) => {
+                for element in section {
+                    let element = element.map_err(to_compile_error)?;
+                    let items = element.items.get_items_reader().map_err(to_compile_error)?;
+                    for item in items {
+                        if let ElementItem::Func(index) = item.map_err(to_compile_error)? {
+                            referenced.push(index);
+                        }
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let index = functions.len();
+                let params = function_types
+                    .get(index)
+                    .and_then(|ty| types.get(*ty as usize))
+                    .and_then(Option::as_ref)
+                    .map_or(0, |ty: &FuncType| ty.params.len() as u64);
+                let mut locals = params;
+                for local in body.get_locals_reader().map_err(to_compile_error)? {
+                    let (count, _) = local.map_err(to_compile_error)?;
+                    locals += u64::from(count);
+                }
+
+                let mut summary = FunctionSummary {
+                    imported: false,
+                    locals,
+                    frame_size: model.frame_overhead + model.bytes_per_local * locals,
+                    ..Default::default()
+                };
+                let mut calls_types = Vec::new();
+                let mut reader = body.get_operators_reader().map_err(to_compile_error)?;
+                while !reader.eof() {
+                    match reader.read().map_err(to_compile_error)? {
+                        Operator::Call { function_index }
+                        | Operator::ReturnCall { function_index } => {
+                            summary.callees.push(function_index)
+                        }
+                        Operator::CallIndirect { index, .. }
+                        | Operator::ReturnCallIndirect { index, .. } => {
+                            summary.calls_indirect = true;
+                            calls_types.push(index);
+                        }
+                        Operator::RefFunc { function_index } => referenced.push(function_index),
+                        _ => {}
+                    }
+                }
+                functions.push(summary);
+                indirect_types.push(calls_types);
+            }
+            _ => {}
+        }
+    }
+    debug_assert!(num_imported <= functions.len());
+
+    // Resolve the indirect calls to the referenced functions with the
+    // same signature.
+    referenced.sort_unstable();
+    referenced.dedup();
+    for (summary, calls_types) in functions.iter_mut().zip(indirect_types.iter()) {
+        for ty in calls_types {
+            let expected = types.get(*ty as usize).cloned().flatten();
+            for target in referenced.iter() {
+                let target_ty = function_types
+                    .get(*target as usize)
+                    .and_then(|ty| types.get(*ty as usize))
+                    .cloned()
+                    .flatten();
+                if same_signature(&expected, &target_ty) {
+                    summary.callees.push(*target);
+                }
+            }
+        }
+        summary.callees.sort_unstable();
+        summary.callees.dedup();
+    }
+
+    let components = strongly_connected_components(&functions);
+    let mut analysis = ModuleAnalysis {
+        max_stack_usage: vec![None; functions.len()],
+        functions,
+        components,
+    };
+
+    // The components are sorted callees first, so the usage of the
+    // callees of a function is known when we reach it.
+    for component in analysis.components.iter() {
+        if analysis.is_cyclic(component) {
+            continue;
+        }
+        let index = component[0] as usize;
+        let summary = &analysis.functions[index];
+        let callees_usage = summary
+            .callees
+            .iter()
+            .map(|callee| analysis.max_stack_usage[*callee as usize])
+            .try_fold(0, |max, usage| usage.map(|usage| max.max(usage)));
+        analysis.max_stack_usage[index] = callees_usage.map(|usage| summary.frame_size + usage);
+    }
+
+    Ok(analysis)
+}
+
+/// Returns the strongly connected components of the call graph, in
+/// reverse topological order (callees first), using Tarjan's algorithm
+/// without recursion.
+fn strongly_connected_components(functions: &[FunctionSummary]) -> Vec<Vec<u32>> {
+    const UNVISITED: usize = usize::MAX;
+    let mut index = vec![UNVISITED; functions.len()];
+    let mut low_link = vec![0; functions.len()];
+    let mut on_stack = vec![false; functions.len()];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut next_index = 0;
+
+    for root in 0..functions.len() {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // The DFS path: the function and the position of its next callee.
+        let mut path = vec![(root, 0)];
+        index[root] = next_index;
+        low_link[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some((function, next_callee)) = path.last_mut() {
+            let function = *function;
+            if let Some(callee) = functions[function].callees.get(*next_callee) {
+                *next_callee += 1;
+                let callee = *callee as usize;
+                if callee >= functions.len() {
+                    continue;
+                }
+                if index[callee] == UNVISITED {
+                    index[callee] = next_index;
+                    low_link[callee] = next_index;
+                    next_index += 1;
+                    stack.push(callee);
+                    on_stack[callee] = true;
+                    path.push((callee, 0));
+                } else if on_stack[callee] {
+                    low_link[function] = low_link[function].min(index[callee]);
+                }
+                continue;
+            }
+
+            path.pop();
+            if let Some((caller, _)) = path.last() {
+                low_link[*caller] = low_link[*caller].min(low_link[function]);
+            }
+            if low_link[function] == index[function] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member as u32);
+                    if member == function {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                components.push(component);
+            }
+        }
+    }
+
+    components
+}
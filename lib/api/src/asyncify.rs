@@ -0,0 +1,314 @@
+//! Suspending and resuming guests transformed with [Asyncify], so that
+//! blocking-style guest code can wait for asynchronous host operations
+//! without threads.
+//!
+//! Asyncify (`wasm-opt --asyncify`) instruments a module so that its call
+//! stack can be unwound into a buffer in its memory, and rewound from it
+//! later. A host function calling [`Asyncify::suspend`] unwinds the guest
+//! back to [`Asyncify::call`], which waits for the host operation and then
+//! rewinds the guest into the host function, which returns the result of
+//! the operation.
+//!
+//! # Usage
+//!
+//! ```
+//! # use wasmer::*;
+//! # use wasmer::asyncify::Asyncify;
+//! fn sleep(asyncify: &Asyncify, ms: u32) -> Result<u32, RuntimeError> {
+//!     let slept = asyncify.suspend(move || async move {
+//!         // Await a timer of the async runtime here.
+//!         ms
+//!     })?;
+//!     // While unwinding, the value returned is ignored by the guest.
+//!     Ok(slept.unwrap_or(0))
+//! }
+//!
+//! # async fn run(store: &Store, module: &Module) -> anyhow::Result<()> {
+//! let asyncify = Asyncify::new();
+//! let import_object = imports! {
+//!     "env" => {
+//!         "sleep" => Function::new_native_with_env(store, asyncify.clone(), sleep),
+//!     },
+//! };
+//! let instance = Instance::new(module, &import_object)?;
+//! let main = instance.exports.get_function("main")?;
+//! asyncify.call(main, &[]).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [Asyncify]: https://github.com/WebAssembly/binaryen/blob/main/src/passes/Asyncify.cpp
+use crate::{
+    ExportError, Exports, Function, HostEnvInitError, Instance, Memory, Module, RuntimeError, Val,
+    WasmTypeList, WasmerEnv, WASM_PAGE_SIZE,
+};
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// The functions exported by a module transformed with Asyncify.
+pub const ASYNCIFY_EXPORTS: [&str; 5] = [
+    "asyncify_start_unwind",
+    "asyncify_stop_unwind",
+    "asyncify_start_rewind",
+    "asyncify_stop_rewind",
+    "asyncify_get_state",
+];
+
+/// The default size of the buffer holding the unwound call stack.
+pub const DEFAULT_BUFFER_SIZE: u32 = WASM_PAGE_SIZE as u32;
+
+/// The states returned by `asyncify_get_state`.
+const STATE_NORMAL: i32 = 0;
+const STATE_REWINDING: i32 = 2;
+
+type PendingOperation = Pin<Box<dyn Future<Output = Box<dyn Any + Send>> + Send>>;
+
+/// Returns whether `module` exports the Asyncify functions.
+pub fn is_asyncified(module: &Module) -> bool {
+    ASYNCIFY_EXPORTS.iter().all(|name| {
+        module
+            .exports()
+            .functions()
+            .any(|export| export.name() == *name)
+    })
+}
+
+/// The Asyncify exports of an instance.
+struct AsyncifyExports {
+    memory: Memory,
+    start_unwind: Function,
+    stop_unwind: Function,
+    start_rewind: Function,
+    stop_rewind: Function,
+    get_state: Function,
+}
+
+impl AsyncifyExports {
+    fn new(exports: &Exports) -> Result<Self, ExportError> {
+        Ok(Self {
+            memory: exports.get_memory("memory")?.clone(),
+            start_unwind: Self::function::<i32, ()>(exports, "asyncify_start_unwind")?,
+            stop_unwind: Self::function::<(), ()>(exports, "asyncify_stop_unwind")?,
+            start_rewind: Self::function::<i32, ()>(exports, "asyncify_start_rewind")?,
+            stop_rewind: Self::function::<(), ()>(exports, "asyncify_stop_rewind")?,
+            get_state: Self::function::<(), i32>(exports, "asyncify_get_state")?,
+        })
+    }
+
+    /// Returns the function `name`, checking its signature: the functions
+    /// are kept dynamic, and made native at every call.
+    fn function<Args, Rets>(exports: &Exports, name: &str) -> Result<Function, ExportError>
+    where
+        Args: WasmTypeList,
+        Rets: WasmTypeList,
+    {
+        let function = exports.get_function(name)?;
+        function
+            .native::<Args, Rets>()
+            .map_err(|_| ExportError::IncompatibleType)?;
+        Ok(function.clone())
+    }
+
+    fn start_unwind(&self, offset: u32) -> Result<(), RuntimeError> {
+        self.start_unwind.native::<i32, ()>()?.call(offset as i32)
+    }
+
+    fn stop_unwind(&self) -> Result<(), RuntimeError> {
+        self.stop_unwind.native::<(), ()>()?.call()
+    }
+
+    fn start_rewind(&self, offset: u32) -> Result<(), RuntimeError> {
+        self.start_rewind.native::<i32, ()>()?.call(offset as i32)
+    }
+
+    fn stop_rewind(&self) -> Result<(), RuntimeError> {
+        self.stop_rewind.native::<(), ()>()?.call()
+    }
+
+    fn get_state(&self) -> Result<i32, RuntimeError> {
+        self.get_state.native::<(), i32>()?.call()
+    }
+}
+
+#[derive(Default)]
+struct AsyncifyState {
+    exports: Option<Arc<AsyncifyExports>>,
+    /// The offset and the size of the buffer in the guest memory, once
+    /// it is known.
+    buffer: Option<(u32, u32)>,
+    buffer_size: u32,
+    /// The operation the guest is suspended on.
+    pending: Option<PendingOperation>,
+    /// The result of the operation, given back to the guest when it
+    /// rewinds.
+    resumed: Option<Box<dyn Any + Send>>,
+}
+
+/// Suspends and resumes an instance transformed with Asyncify.
+///
+/// Clones share the same state: the host functions get a clone as their
+/// environment, and the embedder calls the guest through another.
+#[derive(Clone)]
+pub struct Asyncify {
+    state: Arc<Mutex<AsyncifyState>>,
+}
+
+impl Default for Asyncify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Asyncify {
+    /// Creates an `Asyncify` whose buffer is [`DEFAULT_BUFFER_SIZE`]
+    /// bytes, allocated by growing the memory of the guest the first time
+    /// it suspends.
+    pub fn new() -> Self {
+        Self::with_buffer_size(DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Creates an `Asyncify` whose buffer is `size` bytes, allocated by
+    /// growing the memory of the guest the first time it suspends.
+    pub fn with_buffer_size(size: u32) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(AsyncifyState {
+                buffer_size: size,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Creates an `Asyncify` using `size` bytes of the guest memory at
+    /// `offset` as its buffer, for guests reserving it themselves.
+    ///
+    /// The offset must be 4-byte aligned.
+    pub fn with_buffer(offset: u32, size: u32) -> Self {
+        let asyncify = Self::with_buffer_size(size);
+        asyncify.state.lock().unwrap().buffer = Some((offset, size));
+        asyncify
+    }
+
+    /// Looks up the Asyncify exports and the memory of `instance`.
+    ///
+    /// This is done when the `Asyncify` is the environment of a host
+    /// function of the instance; it only has to be called by embedders
+    /// embedding it in their own environment.
+    pub fn attach(&self, instance: &Instance) -> Result<(), ExportError> {
+        let exports = AsyncifyExports::new(&instance.exports)?;
+        self.state.lock().unwrap().exports = Some(Arc::new(exports));
+        Ok(())
+    }
+
+    fn exports(&self) -> Result<Arc<AsyncifyExports>, RuntimeError> {
+        self.state
+            .lock()
+            .unwrap()
+            .exports
+            .clone()
+            .ok_or_else(|| RuntimeError::new("Asyncify is not attached to an instance"))
+    }
+
+    /// Suspends the guest on the operation returned by `operation`, from
+    /// a host function it called.
+    ///
+    /// The first time, it starts unwinding the guest and returns `None`:
+    /// the host function then returns right away, with any result, and
+    /// [`Asyncify::call`] awaits the operation. When the guest is rewound
+    /// into the host function, it returns the output of the operation.
+    pub fn suspend<F, Fut>(&self, operation: F) -> Result<Option<Fut::Output>, RuntimeError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let exports = self.exports()?;
+        match exports.get_state()? {
+            STATE_NORMAL => {}
+            STATE_REWINDING => {
+                exports.stop_rewind()?;
+                let resumed = self.state.lock().unwrap().resumed.take();
+                let output = resumed
+                    .and_then(|output| output.downcast::<Fut::Output>().ok())
+                    .ok_or_else(|| {
+                        RuntimeError::new("the guest was rewound without the output it awaits")
+                    })?;
+                return Ok(Some(*output));
+            }
+            _ => return Err(RuntimeError::new("the guest is already unwinding")),
+        }
+
+        let operation = operation();
+        let pending: PendingOperation =
+            Box::pin(async move { Box::new(operation.await) as Box<dyn Any + Send> });
+        let offset = self.prepare_buffer(&exports)?;
+        self.state.lock().unwrap().pending = Some(pending);
+        exports.start_unwind(offset)?;
+        Ok(None)
+    }
+
+    /// Returns the offset of the buffer, ready for unwinding: its first
+    /// two words are the current and the end positions of the data.
+    fn prepare_buffer(&self, exports: &AsyncifyExports) -> Result<u32, RuntimeError> {
+        let mut state = self.state.lock().unwrap();
+        let (offset, size) = match state.buffer {
+            Some(buffer) => buffer,
+            None => {
+                let page_size = WASM_PAGE_SIZE as u32;
+                let pages = (state.buffer_size + page_size - 1) / page_size;
+                let previous = exports
+                    .memory
+                    .grow(pages)
+                    .map_err(|error| RuntimeError::new(error.to_string()))?;
+                let buffer = (previous.0 * page_size, state.buffer_size);
+                state.buffer = Some(buffer);
+                buffer
+            }
+        };
+        if offset % 4 != 0 || size < 8 {
+            return Err(RuntimeError::new(
+                "the Asyncify buffer is misaligned or too small",
+            ));
+        }
+
+        let view = exports.memory.view::<u32>();
+        let index = (offset / 4) as usize;
+        let header = view
+            .get(index..index + 2)
+            .ok_or_else(|| RuntimeError::new("the Asyncify buffer is out of bounds"))?;
+        header[0].set(offset + 8);
+        header[1].set(offset + size);
+        Ok(offset)
+    }
+
+    /// Calls `function`, an export of the guest, until it returns without
+    /// being suspended, awaiting the operations it is suspended on.
+    pub async fn call(
+        &self,
+        function: &Function,
+        params: &[Val],
+    ) -> Result<Box<[Val]>, RuntimeError> {
+        let exports = self.exports()?;
+        loop {
+            let results = function.call(params)?;
+            let pending = self.state.lock().unwrap().pending.take();
+            let pending = match pending {
+                Some(pending) => pending,
+                None => return Ok(results),
+            };
+            exports.stop_unwind()?;
+            let output = pending.await;
+            self.state.lock().unwrap().resumed = Some(output);
+            let (offset, _) = self.state.lock().unwrap().buffer.unwrap_or_default();
+            exports.start_rewind(offset)?;
+        }
+    }
+}
+
+impl WasmerEnv for Asyncify {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        self.attach(instance)?;
+        Ok(())
+    }
+}
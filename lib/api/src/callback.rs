@@ -0,0 +1,182 @@
+//! Guest callbacks.
+//!
+//! This module creates the helper `GuestCallback` that lets the host keep
+//! hold of a function the guest registered through a table slot (usually
+//! the `__indirect_function_table`) and call it later with typed arguments:
+//!
+//! ```ignore
+//! let table = instance.exports.get_table("__indirect_function_table")?;
+//! let on_event: GuestCallback<i32, ()> = GuestCallback::new(&instance, table, index);
+//! on_event.call(42)?;
+//! ```
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::{
+    ExportError, FromToNativeWasmType, Instance, NativeFunc, RuntimeError, Table, Val, WasmTypeList,
+};
+
+/// A function registered by the guest in a table, which the host
+/// can call later.
+///
+/// The table slot is resolved on every call, so a guest that replaces
+/// the element at `index` changes the function being called. The
+/// `GuestCallback` keeps the [`Instance`] owning the function alive.
+///
+/// By default a callback refuses to be re-entered: calling it while a
+/// previous call of the same callback (or one of its clones) is still
+/// running returns a [`RuntimeError`]. Use
+/// [`GuestCallback::allow_reentrancy`] for callbacks that are known to
+/// be re-entrant.
+///
+/// A `GuestCallback` is neither `Send` nor `Sync`: it calls into its
+/// instance from the thread it was created on, the one using the store
+/// of the instance, and can't be handed over to another thread.
+pub struct GuestCallback<Args = (), Rets = ()> {
+    instance: Instance,
+    table: Table,
+    index: u32,
+    allow_reentrancy: bool,
+    running: Arc<AtomicBool>,
+    /// Keeps the callback on its thread, see the type documentation.
+    _phantom: PhantomData<(Args, Rets, *const ())>,
+}
+
+impl<Args, Rets> Clone for GuestCallback<Args, Rets> {
+    fn clone(&self) -> Self {
+        Self {
+            instance: self.instance.clone(),
+            table: self.table.clone(),
+            index: self.index,
+            allow_reentrancy: self.allow_reentrancy,
+            running: self.running.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<Args, Rets> GuestCallback<Args, Rets>
+where
+    Args: WasmTypeList,
+    Rets: WasmTypeList,
+{
+    /// Creates a new `GuestCallback` for the element at `index` of `table`.
+    ///
+    /// `instance` is the instance owning the functions stored in `table`.
+    pub fn new(instance: &Instance, table: &Table, index: u32) -> Self {
+        Self {
+            instance: instance.clone(),
+            table: table.clone(),
+            index,
+            allow_reentrancy: false,
+            running: Arc::new(AtomicBool::new(false)),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Creates a new `GuestCallback` for the element at `index` of the
+    /// table exported by `instance` under `table_name`.
+    pub fn from_export(
+        instance: &Instance,
+        table_name: &str,
+        index: u32,
+    ) -> Result<Self, ExportError> {
+        let table = instance.exports.get_table(table_name)?;
+        Ok(Self::new(instance, table, index))
+    }
+
+    /// Allows (or forbids) this callback to be called while a previous
+    /// call is still running.
+    pub fn allow_reentrancy(mut self, allow: bool) -> Self {
+        self.allow_reentrancy = allow;
+        self
+    }
+
+    /// Returns the [`Instance`] owning this callback.
+    pub fn instance(&self) -> &Instance {
+        &self.instance
+    }
+
+    /// Returns the index of this callback in its table.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Resolves the table slot into a [`NativeFunc`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of bounds, the slot is null,
+    /// or the function signature doesn't match `Args` and `Rets`.
+    pub fn resolve(&self) -> Result<NativeFunc<Args, Rets>, RuntimeError> {
+        match self.table.get(self.index) {
+            Some(Val::FuncRef(function)) => function.native(),
+            Some(_) => Err(RuntimeError::new(format!(
+                "guest callback at table index `{}` is null",
+                self.index
+            ))),
+            None => Err(RuntimeError::new(format!(
+                "guest callback table index `{}` is out of bounds (table size is `{}`)",
+                self.index,
+                self.table.size()
+            ))),
+        }
+    }
+
+    fn enter(&self) -> Result<RunningGuard, RuntimeError> {
+        if self.allow_reentrancy {
+            return Ok(RunningGuard(None));
+        }
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Err(RuntimeError::new(format!(
+                "guest callback at table index `{}` is already running",
+                self.index
+            )));
+        }
+        Ok(RunningGuard(Some(self.running.clone())))
+    }
+}
+
+/// Clears the running flag of a callback when its call ends, even if
+/// the call traps.
+struct RunningGuard(Option<Arc<AtomicBool>>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        if let Some(running) = &self.0 {
+            running.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+macro_rules! impl_guest_callback {
+    (  $( $x:ident ),* ) => {
+        #[allow(unused_parens, non_snake_case)]
+        impl<$( $x , )* Rets> GuestCallback<( $( $x ),* ), Rets>
+        where
+            $( $x: FromToNativeWasmType, )*
+            Rets: WasmTypeList,
+        {
+            /// Call the guest callback and return results.
+            pub fn call(&self, $( $x: $x, )* ) -> Result<Rets, RuntimeError> {
+                let _guard = self.enter()?;
+                self.resolve()?.call($( $x, )*)
+            }
+        }
+    };
+}
+
+impl_guest_callback!();
+impl_guest_callback!(A1);
+impl_guest_callback!(A1, A2);
+impl_guest_callback!(A1, A2, A3);
+impl_guest_callback!(A1, A2, A3, A4);
+impl_guest_callback!(A1, A2, A3, A4, A5);
+impl_guest_callback!(A1, A2, A3, A4, A5, A6);
+impl_guest_callback!(A1, A2, A3, A4, A5, A6, A7);
+impl_guest_callback!(A1, A2, A3, A4, A5, A6, A7, A8);
+impl_guest_callback!(A1, A2, A3, A4, A5, A6, A7, A8, A9);
+impl_guest_callback!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10);
+impl_guest_callback!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+impl_guest_callback!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12);
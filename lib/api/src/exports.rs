@@ -88,14 +88,15 @@ impl Exports {
     }
 
     /// Insert a new export into this `Exports` map.
+    ///
+    /// If the map is shared with clones, it's copied first, so the clones
+    /// don't see the new export.
     pub fn insert<S, E>(&mut self, name: S, value: E)
     where
         S: Into<String>,
         E: Into<Extern>,
     {
-        Arc::get_mut(&mut self.map)
-            .unwrap()
-            .insert(name.into(), value.into());
+        Arc::make_mut(&mut self.map).insert(name.into(), value.into());
     }
 
     /// Get an export given a `name`.
@@ -6,6 +6,7 @@ use crate::FunctionType;
 use crate::NativeFunc;
 use crate::RuntimeError;
 use crate::WasmerEnv;
+use crate::{HostEnvInitError, Instance, LazyInit, Memory, Type};
 pub use inner::{FromToNativeWasmType, HostFunction, WasmTypeList, WithEnv, WithoutEnv};
 #[cfg(feature = "deprecated")]
 pub use inner::{UnsafeMutableEnv, WithUnsafeMutableEnv};
@@ -19,6 +20,25 @@ use wasmer_vm::{
     VMFunctionKind, VMTrampoline,
 };
 
+/// The number of params and results passed without allocating by
+/// [`Function::call_into`].
+const INLINE_VALUES: usize = 8;
+
+/// The environment of the functions created with [`Function::new_guest_abort`].
+struct GuestAbortEnv {
+    memory: LazyInit<Memory>,
+}
+
+impl WasmerEnv for GuestAbortEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        // A module without exported memory can only abort without a message.
+        if let Ok(memory) = instance.exports.get_memory("memory") {
+            self.memory.initialize(memory.clone());
+        }
+        Ok(())
+    }
+}
+
 /// A function defined in the Wasm module
 #[derive(Clone, PartialEq)]
 pub struct WasmFunctionDefinition {
@@ -356,6 +376,72 @@ impl Function {
         }
     }
 
+    /// Creates a new host `Function` that lets the guest abort with a
+    /// message.
+    ///
+    /// The function has the signature `(ptr: i32, len: i32) -> ()`. When
+    /// called, it reads `len` bytes (as UTF-8) at `ptr` from the memory
+    /// exported by the instance as `"memory"` and traps with a
+    /// [`RuntimeError`] whose [`RuntimeError::guest_abort_message`] is the
+    /// decoded message.
+    ///
+    /// By convention the guest imports it as `wasmer.abort`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::{imports, Function, Instance, Module, NativeFunc, Store};
+    /// # let store = Store::default();
+    /// let module = Module::new(&store, r#"
+    ///   (module
+    ///     (import "wasmer" "abort" (func $abort (param i32 i32)))
+    ///     (memory (export "memory") 1)
+    ///     (data (i32.const 0) "out of gas")
+    ///     (func (export "run")
+    ///       (call $abort (i32.const 0) (i32.const 10))))
+    /// "#).unwrap();
+    /// let import_object = imports! {
+    ///     "wasmer" => {
+    ///         "abort" => Function::new_guest_abort(&store),
+    ///     },
+    /// };
+    /// let instance = Instance::new(&module, &import_object).unwrap();
+    ///
+    /// let run: NativeFunc = instance.exports.get_native_function("run").unwrap();
+    /// let error = run.call().unwrap_err();
+    /// assert_eq!(error.guest_abort_message(), Some("out of gas"));
+    /// ```
+    pub fn new_guest_abort(store: &Store) -> Self {
+        let signature = FunctionType::new(vec![Type::I32, Type::I32], vec![]);
+        let env = GuestAbortEnv {
+            memory: LazyInit::new(),
+        };
+        Self::new_with_env(store, &signature, env, |env, args| {
+            let ptr = args[0].unwrap_i32() as u32 as usize;
+            let len = args[1].unwrap_i32() as u32 as usize;
+            let memory = env.memory.get_ref().ok_or_else(|| {
+                RuntimeError::guest_abort("<no exported memory to read the message from>")
+            })?;
+            let view = memory.view::<u8>();
+            let message = match ptr.checked_add(len) {
+                Some(end) if end <= view.len() => {
+                    let bytes = view[ptr..end]
+                        .iter()
+                        .map(|cell| cell.get())
+                        .collect::<Vec<_>>();
+                    String::from_utf8_lossy(&bytes).into_owned()
+                }
+                _ => format!(
+                    "<message out of bounds: ptr {}, len {}, memory size {}>",
+                    ptr,
+                    len,
+                    view.len()
+                ),
+            };
+            Err(RuntimeError::guest_abort(message))
+        })
+    }
+
     /// Returns the [`FunctionType`] of the `Function`.
     ///
     /// # Example
@@ -411,11 +497,21 @@ impl Function {
             )));
         }
 
-        let mut values_vec = vec![0; max(params.len(), results.len())];
+        // The values are passed in a buffer on the stack for the common
+        // arities, so that calls don't allocate.
+        let values_len = max(params.len(), results.len());
+        let mut inline_values = [0; INLINE_VALUES];
+        let mut heap_values;
+        let values_vec = if values_len <= INLINE_VALUES {
+            &mut inline_values[..values_len]
+        } else {
+            heap_values = vec![0; values_len];
+            &mut heap_values[..]
+        };
 
         // Store the argument values into `values_vec`.
         let param_tys = signature.params().iter();
-        for ((arg, slot), ty) in params.iter().zip(&mut values_vec).zip(param_tys) {
+        for ((arg, slot), ty) in params.iter().zip(values_vec.iter_mut()).zip(param_tys) {
             if arg.ty() != *ty {
                 let param_types = format_types_for_error_message(params);
                 return Err(RuntimeError::new(format!(
@@ -429,6 +525,7 @@ impl Function {
         }
 
         // Call the trampoline.
+        let _call_guard = self.store.enter_wasm_call()?;
         if let Err(error) = unsafe {
             wasmer_call_trampoline(
                 self.exported.vm_function.vmctx,
@@ -437,7 +534,12 @@ impl Function {
                 values_vec.as_mut_ptr() as *mut u8,
             )
         } {
-            return Err(RuntimeError::from_trap(error));
+            let error = RuntimeError::from_trap(error);
+            self.store.report_trap(
+                &error,
+                self.exported.vm_function.instance_allocator.as_ref(),
+            );
+            return Err(error);
         }
 
         // Load the return values out of `values_vec`.
@@ -498,6 +600,9 @@ impl Function {
     ///    for the function signature.
     /// 2. If the function is defined in the host (in a native way), it will
     ///    call the trampoline.
+    /// 3. If the function is a dynamic host function (created with
+    ///    [`Function::new`] or [`Function::new_with_env`]), it will call it
+    ///    directly.
     ///
     /// # Examples
     ///
@@ -522,15 +627,103 @@ impl Function {
     /// ```
     pub fn call(&self, params: &[Val]) -> Result<Box<[Val]>, RuntimeError> {
         let mut results = vec![Val::null(); self.result_arity()];
+        self.call_into(params, &mut results)?;
+        Ok(results.into_boxed_slice())
+    }
 
+    /// Call the `Function` function like [`Function::call`], but writing its
+    /// results into `results`, which must have [`Function::result_arity`]
+    /// values.
+    ///
+    /// Calling a function defined in WebAssembly this way doesn't allocate
+    /// when it has up to 8 params and results, so the buffers can be reused
+    /// across calls. Dynamic host functions still allocate the results they
+    /// return.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmer::{imports, wat2wasm, Function, Instance, Module, Store, Type, Value};
+    /// # let store = Store::default();
+    /// # let wasm_bytes = wat2wasm(r#"
+    /// # (module
+    /// #   (func (export "sum") (param $x i32) (param $y i32) (result i32)
+    /// #     local.get $x
+    /// #     local.get $y
+    /// #     i32.add
+    /// #   ))
+    /// # "#.as_bytes()).unwrap();
+    /// # let module = Module::new(&store, wasm_bytes).unwrap();
+    /// # let import_object = imports! {};
+    /// # let instance = Instance::new(&module, &import_object).unwrap();
+    /// #
+    /// let sum = instance.exports.get_function("sum").unwrap();
+    /// let mut results = [Value::I32(0)];
+    ///
+    /// sum.call_into(&[Value::I32(1), Value::I32(2)], &mut results).unwrap();
+    /// assert_eq!(results, [Value::I32(3)]);
+    /// ```
+    pub fn call_into(&self, params: &[Val], results: &mut [Val]) -> Result<(), RuntimeError> {
         match &self.definition {
-            FunctionDefinition::Wasm(wasm) => {
-                self.call_wasm(&wasm, params, &mut results)?;
+            FunctionDefinition::Wasm(wasm) => self.call_wasm(&wasm, params, results),
+            FunctionDefinition::Host(host)
+                if self.exported.vm_function.kind == VMFunctionKind::Dynamic =>
+            {
+                if results.len() != self.result_arity() {
+                    return Err(RuntimeError::new(format!(
+                        "Expected {} results, got a buffer of {}",
+                        self.result_arity(),
+                        results.len()
+                    )));
+                }
+                let returned = self.call_dynamic_host(host, params)?;
+                if returned.len() != results.len() {
+                    return Err(RuntimeError::new(format!(
+                        "Dynamic function returned wrong signature. Expected {:?} but got {:?}",
+                        self.ty().results(),
+                        returned.iter().map(Val::ty).collect::<Vec<_>>()
+                    )));
+                }
+                for (slot, value) in results.iter_mut().zip(returned) {
+                    *slot = value;
+                }
+                Ok(())
             }
             _ => unimplemented!("The function definition isn't supported for the moment"),
         }
+    }
 
-        Ok(results.into_boxed_slice())
+    /// Calls a host function created with [`Function::new`] or
+    /// [`Function::new_with_env`].
+    fn call_dynamic_host(
+        &self,
+        host: &HostFunctionDefinition,
+        params: &[Val],
+    ) -> Result<Vec<Val>, RuntimeError> {
+        let signature = self.ty();
+        let param_tys = params.iter().map(Val::ty).collect::<Vec<_>>();
+        if signature.params() != param_tys.as_slice() {
+            return Err(RuntimeError::new(format!(
+                "Parameters of type {:?} did not match signature {}",
+                param_tys, &signature
+            )));
+        }
+
+        let results = if !host.has_env {
+            type VMContextWithoutEnv = VMDynamicFunctionContext<VMDynamicFunctionWithoutEnv>;
+            unsafe {
+                let ctx = self.exported.vm_function.vmctx.host_env as *mut VMContextWithoutEnv;
+                (*ctx).ctx.call(params)?
+            }
+        } else {
+            type VMContextWithEnv =
+                VMDynamicFunctionContext<VMDynamicFunctionWithEnv<std::ffi::c_void>>;
+            unsafe {
+                let ctx = self.exported.vm_function.vmctx.host_env as *mut VMContextWithEnv;
+                (*ctx).ctx.call(params)?
+            }
+        };
+        Ok(results)
     }
 
     pub(crate) fn from_vm_export(store: &Store, wasmer_export: ExportFunction) -> Self {
@@ -677,6 +870,7 @@ impl Function {
             self.exported.vm_function.vmctx,
             self.exported.vm_function.kind,
             self.definition.clone(),
+            self.exported.vm_function.instance_allocator.clone(),
         ))
     }
 
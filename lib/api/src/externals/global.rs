@@ -15,6 +15,11 @@ use wasmer_vm::{Global as RuntimeGlobal, VMExportGlobal};
 /// A global instance is the runtime representation of a global variable.
 /// It consists of an individual value and a flag indicating whether it is mutable.
 ///
+/// A `Global` created by the host can be imported by any number of instances
+/// of its store, which then share its value: setting it once, for example to
+/// pause them all, is seen by all of them, including the ones running on other
+/// threads. The numbers are read and written atomically.
+///
 /// Spec: https://webassembly.github.io/spec/core/exec/runtime.html#global-instances
 #[derive(Clone)]
 pub struct Global {
@@ -36,10 +41,21 @@ impl Global {
     /// assert_eq!(g.get(), Value::I32(1));
     /// assert_eq!(g.ty().mutability, Mutability::Const);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is a reference from another store, see
+    /// [`Global::try_new`].
     pub fn new(store: &Store, val: Val) -> Self {
         Self::from_value(store, val, Mutability::Const).unwrap()
     }
 
+    /// Create a new `Global` with the initial value [`Val`], or returns an
+    /// error if the value is a reference from another store.
+    pub fn try_new(store: &Store, val: Val) -> Result<Self, RuntimeError> {
+        Self::from_value(store, val, Mutability::Const)
+    }
+
     /// Create a mutable `Global` with the initial value [`Val`].
     ///
     /// # Example
@@ -53,10 +69,21 @@ impl Global {
     /// assert_eq!(g.get(), Value::I32(1));
     /// assert_eq!(g.ty().mutability, Mutability::Var);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is a reference from another store, see
+    /// [`Global::try_new_mut`].
     pub fn new_mut(store: &Store, val: Val) -> Self {
         Self::from_value(store, val, Mutability::Var).unwrap()
     }
 
+    /// Create a mutable `Global` with the initial value [`Val`], or returns
+    /// an error if the value is a reference from another store.
+    pub fn try_new_mut(store: &Store, val: Val) -> Result<Self, RuntimeError> {
+        Self::from_value(store, val, Mutability::Var)
+    }
+
     /// Create a `Global` with the initial value [`Val`] and the provided [`Mutability`].
     fn from_value(store: &Store, val: Val, mutability: Mutability) -> Result<Self, RuntimeError> {
         if !val.comes_from_same_store(store) {
@@ -3,11 +3,12 @@ use crate::externals::Extern;
 use crate::store::Store;
 use crate::{MemoryType, MemoryView};
 use std::convert::TryInto;
+use std::ops::Range;
 use std::slice;
 use std::sync::Arc;
 use wasmer_engine::{Export, ExportMemory};
 use wasmer_types::{Pages, ValueType};
-use wasmer_vm::{Memory as RuntimeMemory, MemoryError, VMExportMemory};
+use wasmer_vm::{Memory as RuntimeMemory, MemoryError, MemoryGrowEvent, VMExportMemory};
 
 /// A WebAssembly `memory` instance.
 ///
@@ -182,6 +183,26 @@ impl Memory {
         self.memory.grow(delta.into())
     }
 
+    /// Returns the last failed attempt to grow this `Memory`, either from
+    /// the host or from the guest (`memory.grow` returning `-1`), if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::{Memory, MemoryGrowFailureReason, MemoryType, Pages, Store};
+    /// # let store = Store::default();
+    /// #
+    /// let m = Memory::new(&store, MemoryType::new(1, Some(1), false)).unwrap();
+    /// assert!(m.grow(1).is_err());
+    ///
+    /// let failure = m.last_grow_failure().unwrap();
+    /// assert_eq!(failure.current, Pages(1));
+    /// assert_eq!(failure.reason, MemoryGrowFailureReason::DeclaredMaximum);
+    /// ```
+    pub fn last_grow_failure(&self) -> Option<MemoryGrowEvent> {
+        self.memory.last_grow_failure()
+    }
+
     /// Return a "view" of the currently accessible memory. By
     /// default, the view is unsynchronized, using regular memory
     /// accesses. You can force a memory view to use atomic accesses
@@ -243,6 +264,131 @@ impl Memory {
     pub fn same(&self, other: &Self) -> bool {
         Arc::ptr_eq(&self.memory, &other.memory)
     }
+
+    /// Copies the contents of the `Memory`, to find what changed later
+    /// with [`Memory::diff`].
+    ///
+    /// The memory must not be modified while it's copied, like by another
+    /// thread sharing it.
+    pub fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot {
+            data: unsafe { self.data_unchecked() }.to_vec(),
+        }
+    }
+
+    /// Returns the ranges of the `Memory` that changed since `snapshot`
+    /// was taken, including the pages added by growing it.
+    ///
+    /// The memory is compared by blocks of [`MemoryDiff::BLOCK_SIZE`]
+    /// bytes, so the ranges are aligned to them. A transactional host can
+    /// persist only these ranges after each call, and update the snapshot
+    /// with [`MemorySnapshot::update`] instead of copying the whole memory
+    /// again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::{Memory, MemoryType, Store};
+    /// # let store = Store::default();
+    /// #
+    /// let m = Memory::new(&store, MemoryType::new(1, None, false)).unwrap();
+    /// let snapshot = m.snapshot();
+    /// unsafe { m.data_unchecked_mut()[5000] = 1 };
+    ///
+    /// let diff = m.diff(&snapshot);
+    /// assert_eq!(diff.ranges(), &[4096..5120]);
+    /// ```
+    pub fn diff(&self, snapshot: &MemorySnapshot) -> MemoryDiff {
+        let data = unsafe { self.data_unchecked() };
+        let common = data.len().min(snapshot.data.len());
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        let blocks = data[..common]
+            .chunks(MemoryDiff::BLOCK_SIZE)
+            .zip(snapshot.data[..common].chunks(MemoryDiff::BLOCK_SIZE));
+        for (index, (block, previous_block)) in blocks.enumerate() {
+            if block == previous_block {
+                continue;
+            }
+            let start = (index * MemoryDiff::BLOCK_SIZE) as u64;
+            let end = start + block.len() as u64;
+            match ranges.last_mut() {
+                Some(range) if range.end == start => range.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+        if data.len() > common {
+            match ranges.last_mut() {
+                Some(range) if range.end == common as u64 => range.end = data.len() as u64,
+                _ => ranges.push(common as u64..data.len() as u64),
+            }
+        }
+        MemoryDiff {
+            ranges,
+            size: data.len() as u64,
+        }
+    }
+}
+
+/// A copy of the contents of a [`Memory`], taken with
+/// [`Memory::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemorySnapshot {
+    data: Vec<u8>,
+}
+
+impl MemorySnapshot {
+    /// Returns the copied contents.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Brings the snapshot up to date with `memory`, copying only the
+    /// ranges of `diff`, which must be the diff of `memory` with this
+    /// snapshot.
+    pub fn update(&mut self, memory: &Memory, diff: &MemoryDiff) {
+        let data = unsafe { memory.data_unchecked() };
+        self.data.resize(diff.size as usize, 0);
+        for range in diff.ranges.iter() {
+            let range = range.start as usize..range.end as usize;
+            self.data[range.clone()].copy_from_slice(&data[range]);
+        }
+    }
+}
+
+/// The ranges of a [`Memory`] that changed since a [`MemorySnapshot`],
+/// returned by [`Memory::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDiff {
+    ranges: Vec<Range<u64>>,
+    size: u64,
+}
+
+impl MemoryDiff {
+    /// The size of the blocks the memory is compared by, in bytes.
+    pub const BLOCK_SIZE: usize = 1024;
+
+    /// Returns the changed ranges, sorted and without overlaps.
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.ranges
+    }
+
+    /// Returns the size of the memory when the diff was made, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the number of changed bytes.
+    pub fn changed_bytes(&self) -> u64 {
+        self.ranges
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum()
+    }
 }
 
 impl<'a> Exportable<'a> for Memory {
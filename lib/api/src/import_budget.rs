@@ -0,0 +1,186 @@
+//! Budgets for imported host functions.
+//!
+//! A budget bounds how often (and for how long) a guest can call an
+//! import, independently of any metering of the guest code itself.
+//!
+//! The wrapper enforcing the budget of an import also writes its
+//! [`ImportLog`], if any.
+use crate::externals::function::FunctionDefinition;
+use crate::import_log::{ImportCall, ImportLog};
+use crate::{
+    Extern, Function, HostEnvInitError, Instance, LazyInit, Memory, RuntimeError, Store, WasmerEnv,
+};
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use wasmer_vm::{ImportInitializerFuncPtr, VMDynamicFunctionContext, VMFunctionKind};
+
+/// The limits of an import, set with [`ImportObject::set_budget`].
+///
+/// [`ImportObject::set_budget`]: crate::ImportObject::set_budget
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportBudget {
+    max_calls: Option<u64>,
+    max_time: Option<Duration>,
+}
+
+impl ImportBudget {
+    /// Creates a new budget without any limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the number of times the import can be called.
+    pub fn max_calls(&mut self, max_calls: u64) -> &mut Self {
+        self.max_calls = Some(max_calls);
+        self
+    }
+
+    /// Limits the cumulative wall time spent in the import.
+    ///
+    /// A call is never interrupted: the guest traps when the call that
+    /// exceeds the budget returns.
+    pub fn max_time(&mut self, max_time: Duration) -> &mut Self {
+        self.max_time = Some(max_time);
+        self
+    }
+}
+
+/// How much of its [`ImportBudget`] an import has used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportUsage {
+    /// The number of calls.
+    pub calls: u64,
+    /// The cumulative wall time spent in the import.
+    pub time: Duration,
+}
+
+/// How an import is wrapped: with a budget and its usage, and with a log.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ImportWrapping {
+    pub(crate) budget: Option<(ImportBudget, Arc<Mutex<ImportUsage>>)>,
+    pub(crate) log: Option<ImportLog>,
+}
+
+/// The environment of a function wrapping an import.
+struct WrapperEnv {
+    name: String,
+    function: Function,
+    wrapping: ImportWrapping,
+    memory: LazyInit<Memory>,
+}
+
+impl WasmerEnv for WrapperEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        // The log reads the buffers of the guest in its memory.
+        let memory = instance.exports.get_memory("memory").ok().or_else(|| {
+            instance
+                .exports
+                .iter()
+                .find_map(|(_, export)| match export {
+                    Extern::Memory(memory) => Some(memory),
+                    _ => None,
+                })
+        });
+        if let Some(memory) = memory {
+            self.memory.initialize(memory.clone());
+        }
+
+        // The wrapped function is not an import of the instance anymore,
+        // so we initialize its environment ourselves.
+        let exported = &self.function.exported;
+        if let Some(init) = exported.import_init_function_ptr {
+            // The layout assumptions are the ones of `wasmer_engine::resolve_imports`
+            // for dynamic functions.
+            unsafe {
+                let ctx = exported.vm_function.vmctx.host_env
+                    as *mut VMDynamicFunctionContext<*mut c_void>;
+                let init = std::mem::transmute::<
+                    ImportInitializerFuncPtr,
+                    fn(*mut c_void, *const c_void) -> Result<(), HostEnvInitError>,
+                >(init);
+                init((*ctx).ctx, instance as *const _ as *const c_void)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wraps `function` into a host function enforcing the budget and writing
+/// the log of `wrapping`.
+///
+/// `action` describes the wrapping in the errors, like `set a budget on`.
+pub(crate) fn wrap_import(
+    store: &Store,
+    name: String,
+    function: Function,
+    wrapping: ImportWrapping,
+    action: &str,
+) -> Result<Function, RuntimeError> {
+    let is_host = matches!(function.definition, FunctionDefinition::Host(_));
+    if is_host && function.exported.vm_function.kind != VMFunctionKind::Dynamic {
+        return Err(RuntimeError::new(format!(
+            "cannot {} import `{}`: native host functions are not supported, \
+             use `Function::new` or `Function::new_with_env` instead",
+            action, name
+        )));
+    }
+
+    let ty = function.ty().clone();
+    let env = WrapperEnv {
+        name,
+        function,
+        wrapping,
+        memory: LazyInit::new(),
+    };
+    Ok(Function::new_with_env(store, &ty, env, |env, args| {
+        let budget = env.wrapping.budget.as_ref();
+        if let Some((budget, usage)) = budget {
+            let mut usage = usage.lock().unwrap();
+            if let Some(max_calls) = budget.max_calls {
+                if usage.calls >= max_calls {
+                    return Err(RuntimeError::new(format!(
+                        "import `{}` exceeded its budget of {} call(s)",
+                        env.name, max_calls
+                    )));
+                }
+            }
+            if let Some(max_time) = budget.max_time {
+                if usage.time >= max_time {
+                    return Err(RuntimeError::new(format!(
+                        "import `{}` exceeded its budget of {:?} of call time",
+                        env.name, max_time
+                    )));
+                }
+            }
+            usage.calls += 1;
+        }
+
+        let log = env.wrapping.log.as_ref().filter(|log| log.sample());
+        let start = Instant::now();
+        let results = env.function.call(args);
+        let elapsed = start.elapsed();
+
+        if let Some(log) = log {
+            log.record(&ImportCall {
+                name: &env.name,
+                args,
+                results: results.as_ref().map(|results| &results[..]),
+                memory: env.memory.get_ref(),
+            });
+        }
+        if let Some((budget, usage)) = budget {
+            let mut usage = usage.lock().unwrap();
+            usage.time += elapsed;
+            if let Some(max_time) = budget.max_time {
+                if usage.time > max_time {
+                    return Err(RuntimeError::new(format!(
+                        "import `{}` exceeded its budget of {:?} of call time (used {:?})",
+                        env.name, max_time, usage.time
+                    )));
+                }
+            }
+        }
+        Ok(results?.into_vec())
+    }))
+}
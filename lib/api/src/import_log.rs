@@ -0,0 +1,153 @@
+//! Logs of the calls of imported host functions.
+//!
+//! A log records the arguments and the results of the calls a guest makes
+//! to an import, or of a sample of them, as an audit trail of how an
+//! untrusted guest uses the host API.
+use crate::{Memory, RuntimeError, Val};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A call of an import, as given to the formatter of an [`ImportLog`].
+pub struct ImportCall<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) args: &'a [Val],
+    pub(crate) results: Result<&'a [Val], &'a RuntimeError>,
+    pub(crate) memory: Option<&'a Memory>,
+}
+
+impl<'a> ImportCall<'a> {
+    /// Returns the name of the import, like `env.read`.
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Returns the arguments of the call.
+    pub fn args(&self) -> &[Val] {
+        self.args
+    }
+
+    /// Returns the results of the call, or the error it trapped with.
+    pub fn results(&self) -> Result<&[Val], &RuntimeError> {
+        self.results
+    }
+
+    /// Returns the `len` bytes at `ptr` in the memory of the guest, to
+    /// show the buffers passed as a pointer and a length.
+    ///
+    /// Returns `None` if the bytes are out of bounds, or if the guest
+    /// doesn't export its memory.
+    pub fn read_bytes(&self, ptr: u32, len: u32) -> Option<Vec<u8>> {
+        let view = self.memory?.view::<u8>();
+        let start = ptr as usize;
+        let end = start.checked_add(len as usize)?;
+        if end > view.len() {
+            return None;
+        }
+        Some(view[start..end].iter().map(|byte| byte.get()).collect())
+    }
+
+    /// Returns the `len` bytes at `ptr` in the memory of the guest as a
+    /// string, replacing the invalid UTF-8 sequences, like
+    /// [`ImportCall::read_bytes`].
+    pub fn read_string(&self, ptr: u32, len: u32) -> Option<String> {
+        self.read_bytes(ptr, len)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// Formats the call like `env.read(3, 1024) -> [12]`, or
+/// `env.read(3, 1024) -> trap: message`.
+impl fmt::Display for ImportCall<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        write_values(f, self.args)?;
+        match self.results {
+            Ok(results) => {
+                write!(f, ") -> [")?;
+                write_values(f, results)?;
+                write!(f, "]")
+            }
+            Err(error) => write!(f, ") -> trap: {}", error.message()),
+        }
+    }
+}
+
+fn write_values(f: &mut fmt::Formatter<'_>, values: &[Val]) -> fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        match value {
+            Val::I32(value) => write!(f, "{}", value)?,
+            Val::I64(value) => write!(f, "{}", value)?,
+            Val::F32(value) => write!(f, "{}", value)?,
+            Val::F64(value) => write!(f, "{}", value)?,
+            value => write!(f, "{:?}", value)?,
+        }
+    }
+    Ok(())
+}
+
+/// The log of the calls of an import, set with [`ImportObject::set_log`].
+///
+/// [`ImportObject::set_log`]: crate::ImportObject::set_log
+#[derive(Clone)]
+pub struct ImportLog {
+    sink: Arc<dyn Fn(&str) + Send + Sync>,
+    formatter: Option<Arc<dyn Fn(&ImportCall<'_>) -> String + Send + Sync>>,
+    sample_every: u64,
+    calls: Arc<AtomicU64>,
+}
+
+impl ImportLog {
+    /// Creates a log writing a line per call to `sink`, formatted like
+    /// `env.read(3, 1024) -> [12]`.
+    pub fn new(sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            formatter: None,
+            sample_every: 1,
+            calls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Logs only the first call out of every `n` calls.
+    pub fn sample_every(mut self, n: u64) -> Self {
+        self.sample_every = n.max(1);
+        self
+    }
+
+    /// Formats the lines with `formatter`, for example to show the
+    /// buffers passed as a pointer and a length with
+    /// [`ImportCall::read_string`].
+    pub fn formatter(
+        mut self,
+        formatter: impl Fn(&ImportCall<'_>) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.formatter = Some(Arc::new(formatter));
+        self
+    }
+
+    /// Counts a call, and returns whether it's sampled.
+    pub(crate) fn sample(&self) -> bool {
+        self.calls.fetch_add(1, Ordering::Relaxed) % self.sample_every == 0
+    }
+
+    /// Writes the line of `call` to the sink.
+    pub(crate) fn record(&self, call: &ImportCall<'_>) {
+        match &self.formatter {
+            Some(formatter) => (self.sink)(&formatter(call)),
+            None => (self.sink)(&call.to_string()),
+        }
+    }
+}
+
+impl fmt::Debug for ImportLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImportLog")
+            .field("sample_every", &self.sample_every)
+            .field("calls", &self.calls.load(Ordering::Relaxed))
+            .finish()
+    }
+}
@@ -1,11 +1,14 @@
 //! The import module contains the implementation data structures and helper functions used to
 //! manipulate and access a wasm module's imports including memories, tables, globals, and
 //! functions.
+use crate::import_budget::{wrap_import, ImportBudget, ImportUsage, ImportWrapping};
+use crate::import_log::ImportLog;
+use crate::{Exportable, Function, RuntimeError, Store};
 use std::borrow::{Borrow, BorrowMut};
 use std::collections::VecDeque;
 use std::collections::{hash_map::Entry, HashMap};
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use wasmer_engine::{Export, NamedResolver};
 
 /// The `LikeNamespace` trait represents objects that act as a namespace for imports.
@@ -42,6 +45,14 @@ pub trait LikeNamespace {
 #[derive(Clone, Default)]
 pub struct ImportObject {
     map: Arc<Mutex<HashMap<String, Box<dyn LikeNamespace>>>>,
+    wrapped: Arc<Mutex<HashMap<(String, String), WrappedImport>>>,
+}
+
+/// An import wrapped to enforce an [`ImportBudget`], or to write an
+/// [`ImportLog`].
+struct WrappedImport {
+    export: Export,
+    wrapping: ImportWrapping,
 }
 
 impl ImportObject {
@@ -59,7 +70,13 @@ impl ImportObject {
     /// import_object.get_export("module", "name");
     /// ```
     pub fn get_export(&self, module: &str, name: &str) -> Option<Export> {
-        let guard = self.map.lock().unwrap();
+        if let Some(wrapped) = self
+            .wrapped_imports()
+            .get(&(module.to_string(), name.to_string()))
+        {
+            return Some(wrapped.export.clone());
+        }
+        let guard = self.namespaces();
         let map_ref = guard.borrow();
         if map_ref.contains_key(module) {
             let namespace = map_ref[module].as_ref();
@@ -70,7 +87,7 @@ impl ImportObject {
 
     /// Returns true if the ImportObject contains namespace with the provided name.
     pub fn contains_namespace(&self, name: &str) -> bool {
-        self.map.lock().unwrap().borrow().contains_key(name)
+        self.namespaces().borrow().contains_key(name)
     }
 
     /// Register anything that implements `LikeNamespace` as a namespace.
@@ -89,7 +106,7 @@ impl ImportObject {
         S: Into<String>,
         N: LikeNamespace + 'static,
     {
-        let mut guard = self.map.lock().unwrap();
+        let mut guard = self.namespaces();
         let map = guard.borrow_mut();
 
         match map.entry(name.into()) {
@@ -101,13 +118,180 @@ impl ImportObject {
         }
     }
 
+    /// Limits the calls of the function import `module.name` with `budget`.
+    ///
+    /// When the budget is exceeded, the guest traps with a
+    /// [`RuntimeError`] describing which budget was exceeded. The usage is
+    /// shared by all the instances created with this `ImportObject`.
+    ///
+    /// Setting a new budget for an import resets its usage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the import doesn't exist, isn't a function, or
+    /// is a native host function (created with [`Function::new_native`]
+    /// or [`Function::new_native_with_env`]), which can't be wrapped.
+    ///
+    /// # Usage
+    /// ```
+    /// # use wasmer::{imports, Function, FunctionType, ImportBudget, Store, Type};
+    /// # let store = Store::default();
+    /// let query = Function::new(&store, &FunctionType::new(vec![], vec![]), |_| Ok(vec![]));
+    /// let mut import_object = imports! {
+    ///     "env" => {
+    ///         "query_chain" => query,
+    ///     },
+    /// };
+    ///
+    /// import_object
+    ///     .set_budget(&store, "env", "query_chain", *ImportBudget::new().max_calls(10))
+    ///     .unwrap();
+    /// ```
+    pub fn set_budget(
+        &mut self,
+        store: &Store,
+        module: &str,
+        name: &str,
+        budget: ImportBudget,
+    ) -> Result<(), RuntimeError> {
+        self.wrap(store, module, name, "set a budget on", |wrapping| {
+            wrapping.budget = Some((budget, Arc::new(Mutex::new(ImportUsage::default()))));
+        })
+    }
+
+    /// Returns the usage of the import `module.name`, if it has a budget.
+    pub fn import_usage(&self, module: &str, name: &str) -> Option<ImportUsage> {
+        self.wrapped_imports()
+            .get(&(module.to_string(), name.to_string()))
+            .and_then(|wrapped| wrapped.wrapping.budget.as_ref())
+            .map(|(_, usage)| *usage.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Logs the calls of the function import `module.name` to `log`, with
+    /// their arguments and results.
+    ///
+    /// The log is shared by all the instances created with this
+    /// `ImportObject`, and applies with the budget of the import, if any:
+    /// the calls rejected by the budget are not logged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in the cases of [`ImportObject::set_budget`].
+    ///
+    /// # Usage
+    /// ```
+    /// # use wasmer::{imports, Function, FunctionType, ImportLog, Store, Type};
+    /// # let store = Store::default();
+    /// let ty = FunctionType::new(vec![Type::I32, Type::I32], vec![]);
+    /// let print = Function::new(&store, &ty, |_| Ok(vec![]));
+    /// let mut import_object = imports! {
+    ///     "env" => {
+    ///         "print" => print,
+    ///     },
+    /// };
+    ///
+    /// // Shows the string passed as a pointer and a length.
+    /// let log = ImportLog::new(|line| eprintln!("{}", line)).formatter(|call| {
+    ///     let (ptr, len) = (call.args()[0].unwrap_i32(), call.args()[1].unwrap_i32());
+    ///     format!("{}({:?})", call.name(), call.read_string(ptr as u32, len as u32))
+    /// });
+    /// import_object.set_log(&store, "env", "print", log).unwrap();
+    /// ```
+    pub fn set_log(
+        &mut self,
+        store: &Store,
+        module: &str,
+        name: &str,
+        log: ImportLog,
+    ) -> Result<(), RuntimeError> {
+        self.wrap(store, module, name, "log", |wrapping| {
+            wrapping.log = Some(log);
+        })
+    }
+
+    /// Wraps the function import `module.name` again, after changing its
+    /// wrapping with `configure`.
+    fn wrap(
+        &mut self,
+        store: &Store,
+        module: &str,
+        name: &str,
+        action: &str,
+        configure: impl FnOnce(&mut ImportWrapping),
+    ) -> Result<(), RuntimeError> {
+        // Always wrap the original import, not a previous wrapper.
+        let export = {
+            let guard = self.namespaces();
+            guard
+                .borrow()
+                .get(module)
+                .and_then(|namespace| namespace.get_namespace_export(name))
+        };
+        let function = match export {
+            Some(Export::Function(function)) => Function::from_vm_export(store, function),
+            Some(_) => {
+                return Err(RuntimeError::new(format!(
+                    "cannot {} import `{}.{}`: it is not a function",
+                    action, module, name
+                )))
+            }
+            None => {
+                return Err(RuntimeError::new(format!(
+                    "cannot {} import `{}.{}`: it doesn't exist",
+                    action, module, name
+                )))
+            }
+        };
+        let key = (module.to_string(), name.to_string());
+        let mut wrapped = self.wrapped_imports();
+        let mut wrapping = wrapped
+            .get(&key)
+            .map(|wrapped| wrapped.wrapping.clone())
+            .unwrap_or_default();
+        configure(&mut wrapping);
+        let wrapper = wrap_import(
+            store,
+            format!("{}.{}", module, name),
+            function,
+            wrapping.clone(),
+            action,
+        )?;
+        wrapped.insert(
+            key,
+            WrappedImport {
+                export: wrapper.to_export(),
+                wrapping,
+            },
+        );
+        Ok(())
+    }
+
+    /// Locks the namespaces.
+    ///
+    /// A panic while the namespaces are locked leaves them consistent, so
+    /// a poisoned lock is recovered instead of panicking.
+    fn namespaces(&self) -> MutexGuard<'_, HashMap<String, Box<dyn LikeNamespace>>> {
+        self.map.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the wrapped imports, see [`ImportObject::namespaces`].
+    fn wrapped_imports(&self) -> MutexGuard<'_, HashMap<(String, String), WrappedImport>> {
+        self.wrapped.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
     fn get_objects(&self) -> VecDeque<((String, String), Export)> {
         let mut out = VecDeque::new();
-        let guard = self.map.lock().unwrap();
+        let guard = self.namespaces();
         let map = guard.borrow();
+        let wrapped = self.wrapped_imports();
         for (name, ns) in map.iter() {
             for (id, exp) in ns.get_namespace_exports() {
-                out.push_back(((name.clone(), id), exp));
+                let key = (name.clone(), id);
+                let exp = match wrapped.get(&key) {
+                    Some(wrapped) => wrapped.export.clone(),
+                    None => exp,
+                };
+                out.push_back((key, exp));
             }
         }
         out
@@ -184,10 +368,7 @@ impl fmt::Debug for ImportObject {
         }
 
         f.debug_struct("ImportObject")
-            .field(
-                "map",
-                &SecretMap::new(self.map.lock().unwrap().borrow().len()),
-            )
+            .field("map", &SecretMap::new(self.namespaces().borrow().len()))
             .finish()
     }
 }
@@ -421,4 +602,136 @@ mod test {
             }
         };
     }
+
+    #[test]
+    fn budget_limits_calls() {
+        use crate::{Function, FunctionType, ImportBudget, Instance, Module, NativeFunc};
+
+        let store = Store::default();
+        let module = Module::new(
+            &store,
+            r#"
+            (module
+              (import "env" "expensive" (func $expensive (param i32) (result i32)))
+              (func (export "run") (param i32) (result i32)
+                (call $expensive (local.get 0))))
+            "#,
+        )
+        .unwrap();
+
+        let expensive = Function::new(
+            &store,
+            &FunctionType::new(vec![Type::I32], vec![Type::I32]),
+            |args| Ok(vec![Val::I32(args[0].unwrap_i32() * 2)]),
+        );
+        let mut import_object = imports! {
+            "env" => {
+                "expensive" => expensive,
+            },
+        };
+        import_object
+            .set_budget(
+                &store,
+                "env",
+                "expensive",
+                *ImportBudget::new().max_calls(2),
+            )
+            .unwrap();
+        assert!(import_object
+            .set_budget(&store, "env", "missing", ImportBudget::new())
+            .is_err());
+
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let run: NativeFunc<i32, i32> = instance.exports.get_native_function("run").unwrap();
+        assert_eq!(run.call(1).unwrap(), 2);
+        assert_eq!(run.call(2).unwrap(), 4);
+        let error = run.call(3).unwrap_err();
+        assert!(error.message().contains("exceeded its budget of 2 call(s)"));
+        assert_eq!(
+            import_object
+                .import_usage("env", "expensive")
+                .unwrap()
+                .calls,
+            2
+        );
+    }
+
+    #[test]
+    fn log_records_sampled_calls() {
+        use crate::{
+            Function, FunctionType, ImportBudget, ImportLog, Instance, Module, NativeFunc,
+        };
+
+        let store = Store::default();
+        let module = Module::new(
+            &store,
+            r#"
+            (module
+              (import "env" "print" (func $print (param i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 8) "hello")
+              (func (export "run") (result i32)
+                (call $print (i32.const 8) (i32.const 5))))
+            "#,
+        )
+        .unwrap();
+
+        let print = Function::new(
+            &store,
+            &FunctionType::new(vec![Type::I32, Type::I32], vec![Type::I32]),
+            |args| Ok(vec![args[1].clone()]),
+        );
+        let mut import_object = imports! {
+            "env" => {
+                "print" => print,
+            },
+        };
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        let log = ImportLog::new(move |line| sink.lock().unwrap().push(line.to_string()))
+            .sample_every(2)
+            .formatter(|call| {
+                let (ptr, len) = (call.args()[0].unwrap_i32(), call.args()[1].unwrap_i32());
+                let text = call.read_string(ptr as u32, len as u32).unwrap();
+                format!("{}({:?}) = {}", call.name(), text, call)
+            });
+        import_object.set_log(&store, "env", "print", log).unwrap();
+        import_object
+            .set_budget(&store, "env", "print", *ImportBudget::new().max_calls(3))
+            .unwrap();
+
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let run: NativeFunc<(), i32> = instance.exports.get_native_function("run").unwrap();
+        for _ in 0..3 {
+            assert_eq!(run.call().unwrap(), 5);
+        }
+        assert!(run.call().is_err());
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![
+                r#"env.print("hello") = env.print(8, 5) -> [5]"#,
+                r#"env.print("hello") = env.print(8, 5) -> [5]"#,
+            ]
+        );
+        assert_eq!(import_object.import_usage("env", "print").unwrap().calls, 3);
+    }
+
+    #[test]
+    fn budget_rejects_native_functions() {
+        use crate::{Function, ImportBudget};
+
+        fn func(arg: i32) -> i32 {
+            arg + 1
+        }
+
+        let store = Store::default();
+        let mut import_object = imports! {
+            "env" => {
+                "func" => Function::new_native(&store, func),
+            },
+        };
+        assert!(import_object
+            .set_budget(&store, "env", "func", ImportBudget::new())
+            .is_err());
+    }
 }
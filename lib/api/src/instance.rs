@@ -2,12 +2,13 @@ use crate::exports::Exports;
 use crate::externals::Extern;
 use crate::module::Module;
 use crate::store::Store;
-use crate::{HostEnvInitError, LinkError, RuntimeError};
+use crate::{HostEnvInitError, LinkError, NativeFunc, RuntimeError};
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use thiserror::Error;
 use wasmer_engine::Resolver;
-use wasmer_vm::{InstanceHandle, VMContext};
+use wasmer_types::DataInitializer;
+use wasmer_vm::{InstanceHandle, VMContext, WeakInstanceRef};
 
 /// A WebAssembly Instance is a stateful, executable
 /// instance of a WebAssembly [`Module`].
@@ -16,6 +17,20 @@ use wasmer_vm::{InstanceHandle, VMContext};
 /// functions, memories, tables and globals that allow
 /// interacting with WebAssembly.
 ///
+/// ## Threads
+///
+/// An instance, its exports and its [`Store`] are `Send`: between two
+/// calls, they can be moved to another thread and called there, for
+/// example by a work-stealing executor. A call runs entirely on the
+/// thread that made it.
+///
+/// The host functions imported by the instance move with it, so their
+/// closures and environments must be safe to send to another thread as
+/// well. The closures of the native host functions are required to be
+/// `Send`, but the environments and the closures of the dynamic host
+/// functions are not checked: keep them free of thread-bound data, like
+/// `Rc` or raw pointers, to move the instance.
+///
 /// Spec: https://webassembly.github.io/spec/core/exec/runtime.html#module-instances
 #[derive(Clone)]
 pub struct Instance {
@@ -39,6 +54,41 @@ mod send_test {
     }
 }
 
+// The types moved across threads between calls, checked at compile time.
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Instance>();
+    assert_send::<Exports>();
+    assert_send::<Extern>();
+    assert_send::<NativeFunc<(), ()>>();
+};
+
+/// An initialization convention run by [`Instance::run_initializers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Initializer {
+    /// `_initialize`, exported by WASI reactors. It runs the static
+    /// constructors.
+    Initialize,
+    /// `_start`, exported by WASI commands. It runs the static
+    /// constructors and then the program.
+    Start,
+    /// `__wasm_call_ctors`, exported by modules linked without a C
+    /// runtime, which don't run their static constructors by
+    /// themselves.
+    CallCtors,
+}
+
+impl Initializer {
+    /// The name of the export.
+    pub fn export_name(self) -> &'static str {
+        match self {
+            Self::Initialize => "_initialize",
+            Self::Start => "_start",
+            Self::CallCtors => "__wasm_call_ctors",
+        }
+    }
+}
+
 /// An error while instantiating a module.
 ///
 /// This is not a common WebAssembly error, however
@@ -112,17 +162,42 @@ impl Instance {
     ///  * Link errors that happen when plugging the imports into the instance
     ///  * Runtime errors that happen when running the module `start` function.
     pub fn new(module: &Module, resolver: &dyn Resolver) -> Result<Self, InstantiationError> {
-        let store = module.store();
+        Self::check_features(module)?;
         let handle = module.instantiate(resolver)?;
-        let exports = module
-            .exports()
-            .map(|export| {
-                let name = export.name().to_string();
-                let export = handle.lookup(&name).expect("export");
-                let extern_ = Extern::from_vm_export(store, export.into());
-                (name, extern_)
-            })
-            .collect::<Exports>();
+        Self::from_handle(module, handle, &module.export_names())
+    }
+
+    /// Checks that the store of `module` allows the features it uses.
+    pub(crate) fn check_features(module: &Module) -> Result<(), InstantiationError> {
+        let disallowed = module
+            .store()
+            .disallowed_features(module.artifact().features());
+        if !disallowed.is_empty() {
+            return Err(InstantiationError::Link(LinkError::DisallowedFeatures(
+                disallowed,
+            )));
+        }
+        Ok(())
+    }
+
+    /// Creates the instance of `module` from its instance `handle`, looking
+    /// up the exports named `export_names`.
+    pub(crate) fn from_handle(
+        module: &Module,
+        handle: InstanceHandle,
+        export_names: &[String],
+    ) -> Result<Self, InstantiationError> {
+        let store = module.store();
+        let mut exports = Exports::with_capacity(export_names.len());
+        for name in export_names {
+            let export = handle.lookup(name).ok_or_else(|| {
+                InstantiationError::Link(LinkError::Resource(format!(
+                    "the export `{}` is missing from the instance",
+                    name
+                )))
+            })?;
+            exports.insert(name.clone(), Extern::from_vm_export(store, export.into()));
+        }
 
         let instance = Self {
             handle: Arc::new(Mutex::new(handle)),
@@ -140,9 +215,7 @@ impl Instance {
         // parameter.
         unsafe {
             instance
-                .handle
-                .lock()
-                .unwrap()
+                .lock_handle()
                 .initialize_host_envs::<HostEnvInitError>(&instance as *const _ as *const _)?;
         }
 
@@ -159,9 +232,137 @@ impl Instance {
         self.module.store()
     }
 
+    /// Prepares the instance for latency-sensitive calls, and returns the
+    /// number of memory pages pre-faulted.
+    ///
+    /// The memory pages covered by the data segments are pre-faulted,
+    /// then the `warmup` export, if any, is called. It must be a function
+    /// without parameters nor results, and can for example run the lazy
+    /// initializations of the guest.
+    ///
+    /// To bound the work of `warmup` when using the metering middleware,
+    /// see `wasmer_middlewares::Metering::prewarm`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = r#"(module
+    ///     (memory 1)
+    ///     (data (i32.const 0) "some data")
+    ///     (func (export "warmup")))"#;
+    /// let module = Module::new(&store, wat)?;
+    /// let instance = Instance::new(&module, &imports! {})?;
+    /// assert_eq!(instance.prewarm(Some("warmup"))?, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prewarm(&self, warmup: Option<&str>) -> Result<usize, RuntimeError> {
+        let data_initializers = self
+            .module
+            .artifact()
+            .data_initializers()
+            .iter()
+            .map(|init| DataInitializer {
+                location: init.location.clone(),
+                data: &*init.data,
+            })
+            .collect::<Vec<_>>();
+        let touched = self
+            .lock_handle()
+            .prefault_data_segments(&data_initializers);
+
+        if let Some(warmup) = warmup {
+            let warmup: NativeFunc<(), ()> = self
+                .exports
+                .get_native_function(warmup)
+                .map_err(|e| RuntimeError::new(format!("invalid warmup export: {}", e)))?;
+            warmup.call()?;
+        }
+        Ok(touched)
+    }
+
+    /// Runs the initialization convention of the module, and returns
+    /// what it ran.
+    ///
+    /// The conventions are checked in this order, and only the first
+    /// one exported runs:
+    ///
+    /// 1. [`Initializer::Initialize`]: the module is a WASI reactor.
+    /// 2. [`Initializer::Start`]: the module is a WASI command; its
+    ///    `_start` runs the whole program.
+    /// 3. [`Initializer::CallCtors`]: the module has static constructors
+    ///    but no entry point calling them.
+    ///
+    /// Modules following none of them, such as CosmWasm contracts, don't
+    /// need any initialization: `Ok(None)` is returned. The `start`
+    /// function of the module is not a convention, it already ran in
+    /// [`Instance::new`].
+    ///
+    /// The export must be a function without parameters nor results.
+    /// Errors of the initializer, including the exit of a WASI program,
+    /// are returned as is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = r#"(module
+    ///     (global $ready (export "ready") (mut i32) (i32.const 0))
+    ///     (func (export "_initialize") (global.set $ready (i32.const 1))))"#;
+    /// let module = Module::new(&store, wat)?;
+    /// let instance = Instance::new(&module, &imports! {})?;
+    /// assert_eq!(instance.run_initializers()?, Some(Initializer::Initialize));
+    /// assert_eq!(instance.exports.get_global("ready")?.get(), Val::I32(1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_initializers(&self) -> Result<Option<Initializer>, RuntimeError> {
+        let initializer = [
+            Initializer::Initialize,
+            Initializer::Start,
+            Initializer::CallCtors,
+        ]
+        .iter()
+        .copied()
+        .find(|initializer| self.exports.contains(initializer.export_name()));
+        if let Some(initializer) = initializer {
+            let function: NativeFunc<(), ()> = self
+                .exports
+                .get_native_function(initializer.export_name())
+                .map_err(|e| {
+                    RuntimeError::new(format!(
+                        "invalid `{}` export: {}",
+                        initializer.export_name(),
+                        e
+                    ))
+                })?;
+            function.call()?;
+        }
+        Ok(initializer)
+    }
+
     #[doc(hidden)]
     pub fn vmctx_ptr(&self) -> *mut VMContext {
-        self.handle.lock().unwrap().vmctx_ptr()
+        self.lock_handle().vmctx_ptr()
+    }
+
+    /// Returns a weak reference to the instance, to find out when it's
+    /// deallocated.
+    pub(crate) fn downgrade(&self) -> WeakInstanceRef {
+        self.lock_handle().downgrade()
+    }
+
+    /// Locks the instance handle.
+    ///
+    /// The handle is not left in an inconsistent state by a panic while
+    /// it's locked, so a poisoned lock is recovered instead of panicking.
+    fn lock_handle(&self) -> MutexGuard<'_, InstanceHandle> {
+        self.handle.lock().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
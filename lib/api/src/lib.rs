@@ -28,15 +28,27 @@
     )
 )]
 
+pub mod abi;
+#[cfg(feature = "compiler")]
+pub mod analysis;
+pub mod asyncify;
+mod callback;
 mod env;
 mod exports;
 mod externals;
+mod import_budget;
+mod import_log;
 mod import_object;
 mod instance;
+mod linker;
 mod module;
+mod module_kind;
+mod multi_target;
 mod native;
 mod ptr;
+mod rust_panic;
 mod store;
+mod store_scope;
 mod tunables;
 mod types;
 mod utils;
@@ -54,17 +66,28 @@ pub mod internals {
     pub use crate::externals::{WithEnv, WithoutEnv};
 }
 
+pub use crate::callback::GuestCallback;
 pub use crate::env::{HostEnvInitError, LazyInit, WasmerEnv};
 pub use crate::exports::{ExportError, Exportable, Exports, ExportsIterator};
 pub use crate::externals::{
-    Extern, FromToNativeWasmType, Function, Global, HostFunction, Memory, Table, WasmTypeList,
+    Extern, FromToNativeWasmType, Function, Global, HostFunction, Memory, MemoryDiff,
+    MemorySnapshot, Table, WasmTypeList,
 };
+pub use crate::import_budget::{ImportBudget, ImportUsage};
+pub use crate::import_log::{ImportCall, ImportLog};
 pub use crate::import_object::{ImportObject, ImportObjectIterator, LikeNamespace};
-pub use crate::instance::{Instance, InstantiationError};
-pub use crate::module::Module;
+pub use crate::instance::{Initializer, Instance, InstantiationError};
+pub use crate::linker::{Linker, LinkerError};
+pub use crate::module::{IoCompileError, Module, SharedModule};
+pub use crate::module_kind::ModuleKind;
+pub use crate::multi_target::MULTI_TARGET_EXTENSION;
 pub use crate::native::NativeFunc;
 pub use crate::ptr::{Array, Item, WasmPtr};
-pub use crate::store::{Store, StoreObject};
+pub use crate::rust_panic::RustPanic;
+pub use crate::store::{
+    call_with_stack_size, ReentrancyPolicy, Store, StoreBuildError, StoreBuilder, StoreObject,
+};
+pub use crate::store_scope::StoreScope;
 pub use crate::tunables::Tunables;
 pub use crate::types::{
     ExportType, ExternRef, ExternType, FunctionType, GlobalType, HostInfo, HostRef, ImportType,
@@ -75,22 +98,31 @@ pub use crate::utils::is_wasm;
 pub use target_lexicon::{Architecture, CallingConvention, OperatingSystem, Triple, HOST};
 #[cfg(feature = "compiler")]
 pub use wasmer_compiler::{
-    wasmparser, CompilerConfig, FunctionMiddleware, MiddlewareReaderState, ModuleMiddleware,
+    wasmparser, CompilerConfig, FunctionMiddleware, MiddlewareError, MiddlewareReaderState,
+    ModuleMiddleware,
 };
 pub use wasmer_compiler::{
-    CompileError, CpuFeature, Features, ParseCpuFeatureError, Target, WasmError,
+    CodeHardening, CompileError, CompileErrorLocation, CpuFeature, Features, ParseCpuFeatureError,
+    Target, WasmError,
 };
 pub use wasmer_engine::{
-    ChainableNamedResolver, DeserializeError, Engine, Export, FrameInfo, LinkError, NamedResolver,
-    NamedResolverChain, Resolver, RuntimeError, SerializeError,
+    deserialize_symbols, serialize_symbols, ChainableNamedResolver, DeserializeError, Engine,
+    Export, FrameInfo, ImportError, LinkError, NamedResolver, NamedResolverChain, Resolver,
+    RuntimeError, SerializeError, SourceLocation, SourceMap, SourceMapError, TrapRecord, TrapSink,
+    UnresolvedImport,
 };
 pub use wasmer_types::{
-    Atomically, Bytes, ExportIndex, GlobalInit, LocalFunctionIndex, MemoryView, Pages, ValueType,
+    Atomically, Bytes, DataIndex, ExportIndex, FunctionIndex, GlobalIndex, GlobalInit,
+    LocalFunctionIndex, MemoryIndex, MemoryView, Pages, SignatureIndex, TableIndex, ValueType,
     WASM_MAX_PAGES, WASM_MIN_PAGES, WASM_PAGE_SIZE,
 };
 
 // TODO: should those be moved into wasmer::vm as well?
-pub use wasmer_vm::{raise_user_trap, MemoryError, VMExport};
+pub use wasmer_vm::{
+    host_panic_policy, raise_user_trap, set_host_panic_policy, DataSegmentLoader, HostPanicPolicy,
+    MemoryAccessFault, MemoryError, MemoryGrowEvent, MemoryGrowFailureReason, MiddlewareExport,
+    ModuleInfo, ModuleNames, ModuleSymbols, TrapCode, VMExport, MIDDLEWARE_REGISTRY_SECTION,
+};
 pub mod vm {
     //! We use the vm module for re-exporting wasmer-vm types
 
@@ -0,0 +1,135 @@
+//! The linker resolves the imports of modules against items registered
+//! under a module name, such as the exports of previously created
+//! instances.
+use crate::{Exportable, Extern, Instance, InstantiationError, Module};
+use indexmap::IndexMap;
+use thiserror::Error;
+use wasmer_engine::{Export, NamedResolver};
+
+/// An error while registering an item in a [`Linker`].
+#[derive(Error, Debug)]
+pub enum LinkerError {
+    /// The item is already defined and the linker doesn't allow
+    /// shadowing.
+    #[error("`{0}`.`{1}` is already defined")]
+    AlreadyDefined(String, String),
+}
+
+/// Resolves the imports of modules against named items.
+///
+/// Items are registered with [`Linker::define`], or all at once from the
+/// exports of an instance with [`Linker::instance`]. Every module
+/// instantiated with [`Linker::instantiate`] then has its imports
+/// resolved against them, which supports sharing an environment module
+/// between many guest modules.
+///
+/// By default, defining an item twice is an error. With
+/// [`Linker::allow_shadowing`], the last definition wins.
+///
+/// # Usage
+///
+/// ```
+/// # use wasmer::*;
+/// # fn main() -> anyhow::Result<()> {
+/// # let store = Store::default();
+/// let env = Module::new(&store, r#"(module (memory (export "memory") 1))"#)?;
+/// let guest = Module::new(&store, r#"(module (import "env" "memory" (memory 1)))"#)?;
+///
+/// let mut linker = Linker::new();
+/// let env = linker.instantiate(&env)?;
+/// linker.instance("env", &env)?;
+///
+/// let first = linker.instantiate(&guest)?;
+/// let second = linker.instantiate(&guest)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Linker {
+    items: IndexMap<(String, String), Extern>,
+    allow_shadowing: bool,
+}
+
+impl Linker {
+    /// Creates an empty `Linker`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures whether an item can be redefined, replacing the
+    /// previous definition.
+    ///
+    /// Instances created before the redefinition keep the previous item.
+    pub fn allow_shadowing(&mut self, allow_shadowing: bool) -> &mut Self {
+        self.allow_shadowing = allow_shadowing;
+        self
+    }
+
+    /// Defines the item `module`.`name`.
+    pub fn define(
+        &mut self,
+        module: &str,
+        name: &str,
+        item: impl Into<Extern>,
+    ) -> Result<&mut Self, LinkerError> {
+        let key = (module.to_string(), name.to_string());
+        if !self.allow_shadowing && self.items.contains_key(&key) {
+            return Err(LinkerError::AlreadyDefined(key.0, key.1));
+        }
+        self.items.insert(key, item.into());
+        Ok(self)
+    }
+
+    /// Defines all the exports of `instance` under the module name
+    /// `module`.
+    ///
+    /// Without shadowing, nothing is defined if any export is already
+    /// defined.
+    pub fn instance(
+        &mut self,
+        module: &str,
+        instance: &Instance,
+    ) -> Result<&mut Self, LinkerError> {
+        if !self.allow_shadowing {
+            if let Some((name, _)) = instance
+                .exports
+                .iter()
+                .find(|(name, _)| self.get(module, name).is_some())
+            {
+                return Err(LinkerError::AlreadyDefined(
+                    module.to_string(),
+                    name.clone(),
+                ));
+            }
+        }
+        for (name, item) in instance.exports.iter() {
+            self.items
+                .insert((module.to_string(), name.clone()), item.clone());
+        }
+        Ok(self)
+    }
+
+    /// Returns the item `module`.`name`, if defined.
+    pub fn get(&self, module: &str, name: &str) -> Option<&Extern> {
+        self.items.get(&(module.to_string(), name.to_string()))
+    }
+
+    /// Returns an iterator over the defined items, in definition order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &Extern)> {
+        self.items
+            .iter()
+            .map(|((module, name), item)| (module.as_str(), name.as_str(), item))
+    }
+
+    /// Instantiates `module`, resolving its imports against the defined
+    /// items.
+    pub fn instantiate(&self, module: &Module) -> Result<Instance, InstantiationError> {
+        Instance::new(module, self)
+    }
+}
+
+impl NamedResolver for Linker {
+    fn resolve_by_name(&self, module: &str, name: &str) -> Option<Export> {
+        self.get(module, name).map(Exportable::to_export)
+    }
+}
@@ -1,16 +1,27 @@
+use crate::multi_target;
 use crate::store::Store;
 use crate::types::{ExportType, ImportType};
-use crate::InstantiationError;
+use crate::{Instance, InstantiationError};
 use std::fmt;
 use std::io;
+#[cfg(feature = "compiler")]
+use std::io::Read;
 use std::path::Path;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use thiserror::Error;
+#[cfg(feature = "compiler")]
+use wasmer_compiler::wasmparser::{Chunk, Parser, Payload};
 use wasmer_compiler::CompileError;
 #[cfg(feature = "wat")]
 use wasmer_compiler::WasmError;
-use wasmer_engine::{Artifact, DeserializeError, Resolver, SerializeError};
-use wasmer_vm::{ExportsIterator, ImportsIterator, InstanceHandle, ModuleInfo};
+use wasmer_engine::{Artifact, DeserializeError, Engine, Resolver, SerializeError};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{DataIndex, FunctionIndex, LocalFunctionIndex};
+use wasmer_vm::{
+    DataSegmentLoader, ExportsIterator, Imports, ImportsIterator, InstanceHandle, MiddlewareExport,
+    ModuleInfo, ModuleNames, ModuleSymbols,
+};
 
 #[derive(Error, Debug)]
 pub enum IoCompileError {
@@ -22,6 +33,10 @@ pub enum IoCompileError {
     Compile(#[from] CompileError),
 }
 
+/// How many bytes [`Module::from_reader`] reads at a time.
+#[cfg(feature = "compiler")]
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
 /// A WebAssembly Module contains stateless WebAssembly
 /// code that has already been compiled and can be instantiated
 /// multiple times.
@@ -30,6 +45,12 @@ pub enum IoCompileError {
 ///
 /// Cloning a module is cheap: it does a shallow copy of the compiled
 /// contents rather than a deep copy.
+///
+/// ## Threads
+///
+/// A module is `Send` and `Sync`: it can be cloned into other threads
+/// and instantiated there. To use it with another [`Store`] of the same
+/// [`Engine`], see [`Module::share`].
 #[derive(Clone)]
 pub struct Module {
     store: Store,
@@ -122,6 +143,52 @@ impl Module {
         Ok(module)
     }
 
+    /// Creates a new WebAssembly module from a binary read from `reader`,
+    /// for example while it is being downloaded.
+    ///
+    /// The binary is parsed as it is read, so a malformed module is
+    /// rejected at its first malformed section, without waiting for the
+    /// rest of it. Once it is read entirely, the module is validated and
+    /// compiled like with [`Module::from_binary`]: the compilers work on
+    /// whole modules.
+    #[cfg(feature = "compiler")]
+    pub fn from_reader(store: &Store, mut reader: impl Read) -> Result<Self, IoCompileError> {
+        let mut binary = Vec::new();
+        let mut parser = Parser::new(0);
+        let mut parsed = 0;
+        let mut eof = false;
+        loop {
+            let needs_data = match parser.parse(&binary[parsed..], eof) {
+                Ok(Chunk::NeedMoreData(_)) => true,
+                Ok(Chunk::Parsed { consumed, payload }) => {
+                    parsed += consumed;
+                    if let Payload::End = payload {
+                        break;
+                    }
+                    false
+                }
+                Err(e) => return Err(CompileError::Validate(format!("{}", e)).into()),
+            };
+            if needs_data {
+                let len = binary.len();
+                binary.resize(len + READ_CHUNK_SIZE, 0);
+                let read = match reader.read(&mut binary[len..]) {
+                    Ok(read) => read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                        binary.truncate(len);
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                binary.truncate(len + read);
+                eof = read == 0;
+            }
+        }
+        // Trailing bytes make the module invalid.
+        reader.read_to_end(&mut binary)?;
+        Ok(Self::from_binary(store, &binary)?)
+    }
+
     /// Creates a new WebAssembly module from a binary.
     ///
     /// Opposed to [`Module::new`], this function is not compatible with
@@ -157,8 +224,38 @@ impl Module {
         store.engine().validate(binary)
     }
 
+    /// Checks that the Store Engine can compile a WebAssembly Module,
+    /// without compiling it.
+    ///
+    /// Besides validating the Module, it scans its code for the features
+    /// and the operators the compiler doesn't support, and returns all of
+    /// them, each located at its first use. It's about as fast as
+    /// [`Module::validate`], so it can reject incompatible modules before
+    /// spending the time to compile them.
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = "(module (func (export \"nop\") nop))";
+    /// let binary = wat2wasm(wat.as_bytes())?;
+    /// if let Err(errors) = Module::precompile_validate(&store, &binary) {
+    ///     for error in errors {
+    ///         eprintln!("{}", error);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn precompile_validate(store: &Store, binary: &[u8]) -> Result<(), Vec<CompileError>> {
+        store.engine().precompile_validate(binary)
+    }
+
     fn compile(store: &Store, binary: &[u8]) -> Result<Self, CompileError> {
-        let artifact = store.engine().compile(binary, store.tunables())?;
+        let mut artifact = store.engine().compile(binary, store.tunables())?;
+        if let Some(module_info) = Arc::get_mut(&mut artifact).and_then(|a| a.module_mut()) {
+            module_info.hash = Some(blake3::hash(binary).into());
+        }
         Ok(Self::from_artifact(store, artifact))
     }
 
@@ -180,6 +277,34 @@ impl Module {
         self.artifact.serialize()
     }
 
+    /// Serializes a module like [`Module::serialize`], compressing the
+    /// artifact with zstd at `level`: from 1 (fastest) to 21 (smallest),
+    /// 3 being a good default.
+    ///
+    /// [`Module::deserialize`] decompresses it transparently. Only the
+    /// metadata and data of the JIT artifacts are compressed, not their
+    /// code, so the artifacts of data-heavy modules are about half the
+    /// size; the artifacts of the native engine, shared objects, aren't
+    /// compressed.
+    /// The compression requires the `compression` feature, without which
+    /// the artifact is serialized uncompressed.
+    ///
+    /// # Usage
+    ///
+    /// ```ignore
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// # let module = Module::from_file(&store, "path/to/foo.wasm")?;
+    /// let serialized = module.serialize_compressed(3)?;
+    /// let module = unsafe { Module::deserialize(&store, &serialized)? };
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serialize_compressed(&self, level: i32) -> Result<Vec<u8>, SerializeError> {
+        self.artifact.serialize_compressed(level)
+    }
+
     /// Serializes a module into a file that the `Engine`
     /// can later process via [`Module::deserialize_from_file`].
     ///
@@ -251,6 +376,64 @@ impl Module {
         Ok(Self::from_artifact(store, artifact))
     }
 
+    /// Serializes modules compiled from the same WebAssembly binary for
+    /// different targets into one `.wasmu-multi` container, that
+    /// [`Module::deserialize_best_match`] loads on any of these targets.
+    ///
+    /// The target of a module is the target of its engine.
+    ///
+    /// # Usage
+    ///
+    /// ```ignore
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let x86_64_store = Store::default();
+    /// # let aarch64_store = Store::default();
+    /// let modules = [
+    ///     Module::from_file(&x86_64_store, "path/to/foo.wasm")?,
+    ///     Module::from_file(&aarch64_store, "path/to/foo.wasm")?,
+    /// ];
+    /// let serialized = Module::serialize_multi_target(&modules)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serialize_multi_target(modules: &[Self]) -> Result<Vec<u8>, SerializeError> {
+        if let Some(module) = modules
+            .iter()
+            .find(|module| module.hash() != modules[0].hash())
+        {
+            return Err(SerializeError::Generic(format!(
+                "the module `{}` was compiled from another binary than the first module",
+                module.name().unwrap_or("<unnamed>")
+            )));
+        }
+        let artifacts = modules
+            .iter()
+            .map(|module| Ok((module.engine().target().clone(), module.serialize()?)))
+            .collect::<Result<Vec<_>, SerializeError>>()?;
+        multi_target::encode(&artifacts)
+    }
+
+    /// Deserializes the artifact of a `.wasmu-multi` container, created
+    /// with [`Module::serialize_multi_target`], that best matches the
+    /// target of the engine of `store`.
+    ///
+    /// The best match is the artifact compiled for the same triple that
+    /// uses the most CPU features, all supported by the target. It fails
+    /// with [`DeserializeError::Incompatible`] when there is none.
+    ///
+    /// # Safety
+    ///
+    /// Please check [`Module::deserialize`].
+    pub unsafe fn deserialize_best_match(
+        store: &Store,
+        bytes: &[u8],
+    ) -> Result<Self, DeserializeError> {
+        let artifacts = multi_target::decode(bytes)?;
+        let artifact = multi_target::select(&artifacts, store.engine().target())?;
+        Self::deserialize(store, artifact)
+    }
+
     fn from_artifact(store: &Store, artifact: Arc<dyn Artifact>) -> Self {
         Self {
             store: store.clone(),
@@ -261,11 +444,21 @@ impl Module {
     pub(crate) fn instantiate(
         &self,
         resolver: &dyn Resolver,
+    ) -> Result<InstanceHandle, InstantiationError> {
+        let imports = self.artifact.link_imports(resolver)?;
+        self.instantiate_with_imports(imports)
+    }
+
+    fn instantiate_with_imports(
+        &self,
+        imports: Imports,
     ) -> Result<InstanceHandle, InstantiationError> {
         unsafe {
-            let instance_handle =
-                self.artifact
-                    .instantiate(self.store.tunables(), resolver, Box::new(()))?;
+            let instance_handle = self.artifact.instantiate_with_imports(
+                self.store.tunables(),
+                imports,
+                Box::new(()),
+            )?;
 
             // After the instance handle is created, we need to initialize
             // the data, call the start function and so. However, if any
@@ -278,6 +471,52 @@ impl Module {
         }
     }
 
+    /// Creates `n` instances of the module, with the same imports.
+    ///
+    /// This is equivalent to calling [`Instance::new`] `n` times, but the
+    /// imports are resolved and the exports are listed only once, which
+    /// makes fan-out workloads creating many short-lived instances of a
+    /// module cheaper. Each instance still gets its own memories, tables
+    /// and globals.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let module = Module::new(&store, "(module (memory 1))")?;
+    /// let instances = module.instantiate_batch(&imports! {}, 3)?;
+    /// assert_eq!(instances.len(), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// ## Errors
+    ///
+    /// Fails like [`Instance::new`], on the first instance failing to be
+    /// created.
+    pub fn instantiate_batch(
+        &self,
+        resolver: &dyn Resolver,
+        n: usize,
+    ) -> Result<Vec<Instance>, InstantiationError> {
+        Instance::check_features(self)?;
+        let imports = self.artifact.link_imports(resolver)?;
+        let export_names = self.export_names();
+        let mut instances = Vec::with_capacity(n);
+        for _ in 0..n {
+            let handle = self.instantiate_with_imports(imports.clone())?;
+            instances.push(Instance::from_handle(self, handle, &export_names)?);
+        }
+        Ok(instances)
+    }
+
+    /// Returns the names of the exports of the module.
+    pub(crate) fn export_names(&self) -> Vec<String> {
+        self.artifact.module_ref().exports.keys().cloned().collect()
+    }
+
     /// Returns the name of the current module.
     ///
     /// This name is normally set in the WebAssembly bytecode by some
@@ -299,6 +538,110 @@ impl Module {
         self.artifact.module_ref().name.as_deref()
     }
 
+    /// Returns the URL of the source map of the module, written in its
+    /// `sourceMappingURL` custom section.
+    ///
+    /// Source maps embedded in the URL are applied to the backtraces of
+    /// the traps (see [`FrameInfo::source_location`]). The others can be
+    /// loaded by the embedder with [`SourceMap::from_json`].
+    ///
+    /// [`FrameInfo::source_location`]: crate::FrameInfo::source_location
+    /// [`SourceMap::from_json`]: crate::SourceMap::from_json
+    pub fn source_mapping_url(&self) -> Option<String> {
+        wasmer_engine::source_mapping_url(self.artifact.module_ref())
+    }
+
+    /// Returns the names of the locals, labels, types, tables, memories
+    /// and globals of the module, found in its name section.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = "(module (func (param $amount i32)))";
+    /// let module = Module::new(&store, wat)?;
+    /// let locals = &module.names().locals[&FunctionIndex::from_u32(0)];
+    /// assert_eq!(locals[&0], "amount");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn names(&self) -> &ModuleNames {
+        &self.artifact.module_ref().names
+    }
+
+    /// Returns the number of calls of each local function of the module,
+    /// if the module was compiled by an engine counting them (see
+    /// `JIT::count_function_calls`), with a compiler emitting the counters.
+    ///
+    /// The counters are shared by all the instances of the module, and
+    /// include the calls made from the host and from the other modules.
+    pub fn function_call_counts(&self) -> Option<Vec<(FunctionIndex, u64)>> {
+        let module = self.artifact.module_ref();
+        let counters = self.artifact.call_counters()?;
+        Some(
+            counters
+                .iter()
+                .enumerate()
+                .map(|(index, counter)| {
+                    let index = module.func_index(LocalFunctionIndex::new(index));
+                    (index, counter.load(Ordering::Relaxed))
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the globals exported by the middlewares the module was
+    /// compiled with, like the remaining points of the metering, as
+    /// recorded by the middlewares themselves.
+    ///
+    /// This lets tools find the state of the middlewares without
+    /// hard-coding the names of the exports.
+    pub fn middleware_exports(&self) -> Vec<MiddlewareExport> {
+        self.artifact.module_ref().middleware_exports()
+    }
+
+    /// Returns the BLAKE3 hash of the WebAssembly binary the module was
+    /// compiled from (after converting it from the text format, if
+    /// needed).
+    ///
+    /// The hash is kept when the module is serialized, so it's available
+    /// on deserialized modules too. It only depends on the WebAssembly
+    /// binary and is stable across Wasmer versions: it's the same as
+    /// `wasmer_cache::Hash::generate` over the binary, and can be used as
+    /// a cache key without reading the binary again.
+    ///
+    /// It returns `None` for modules created from artifacts compiled
+    /// outside of [`Module::new`] and friends.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let module = Module::new(&store, "(module)")?;
+    /// let other = Module::new(&store, "(module (type (func)))")?;
+    /// assert!(module.hash().is_some());
+    /// assert_ne!(module.hash(), other.hash());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn hash(&self) -> Option<[u8; 32]> {
+        self.artifact.module_ref().hash
+    }
+
+    /// Returns the fingerprint of the compiled artifact of the module:
+    /// the BLAKE3 hash of [`Module::serialize`].
+    ///
+    /// Unlike [`Module::hash`], it depends on the engine, the compiler
+    /// and the configuration the module was compiled with. It's stable
+    /// within a Wasmer patch release series.
+    pub fn fingerprint(&self) -> Result<[u8; 32], SerializeError> {
+        self.artifact.fingerprint()
+    }
+
     /// Sets the name of the current module.
     /// This is normally useful for stacktraces and debugging.
     ///
@@ -330,6 +673,125 @@ impl Module {
             .unwrap_or(false)
     }
 
+    /// Removes the names and debug information from the module, so that
+    /// [`Module::serialize`] produces a smaller artifact, and returns them.
+    ///
+    /// The symbols can be stored separately with [`serialize_symbols`]
+    /// and attached back with [`Module::attach_symbols`], for example to
+    /// symbolicate the backtraces of a deserialized module.
+    ///
+    /// It will return `None` if the module is already instantiated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = "(module $moduleName)";
+    /// let mut module = Module::new(&store, wat)?;
+    /// let symbols = module.strip_symbols().unwrap();
+    /// assert_eq!(module.name(), None);
+    ///
+    /// let artifact = module.serialize()?;
+    /// let sidecar = serialize_symbols(&symbols)?;
+    ///
+    /// let mut module = unsafe { Module::deserialize(&store, &artifact)? };
+    /// module.attach_symbols(deserialize_symbols(&sidecar)?);
+    /// assert_eq!(module.name(), Some("moduleName"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`serialize_symbols`]: crate::serialize_symbols
+    pub fn strip_symbols(&mut self) -> Option<ModuleSymbols> {
+        Arc::get_mut(&mut self.artifact)
+            .and_then(|artifact| artifact.module_mut())
+            .map(|module_info| module_info.strip_symbols())
+    }
+
+    /// Attaches symbols removed with [`Module::strip_symbols`], replacing
+    /// the ones of the module.
+    ///
+    /// It will return `true` if the symbols were attached successfully,
+    /// and return `false` otherwise (in case the module is already
+    /// instantiated).
+    pub fn attach_symbols(&mut self, symbols: ModuleSymbols) -> bool {
+        Arc::get_mut(&mut self.artifact)
+            .and_then(|artifact| artifact.module_mut())
+            .map(|module_info| {
+                module_info.attach_symbols(symbols);
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// Removes the passive data segments of at least `min_len` bytes from
+    /// the module, and returns them so the embedder can store them
+    /// elsewhere, for example on disk.
+    ///
+    /// The removed segments are not part of the serialized module. They
+    /// are loaded with the loader set by [`Module::set_data_loader`] the
+    /// first time `memory.init` uses them in an instance, so they don't
+    /// need to be resident before. Instantiation doesn't load them.
+    ///
+    /// It will return `None` if the module is already instantiated.
+    pub fn defer_passive_data(&mut self, min_len: usize) -> Option<Vec<(DataIndex, Arc<[u8]>)>> {
+        Arc::get_mut(&mut self.artifact)
+            .and_then(|artifact| artifact.module_mut())
+            .map(|module_info| module_info.defer_passive_data(min_len))
+    }
+
+    /// Sets the loader of the passive data segments deferred with
+    /// [`Module::defer_passive_data`], including on a deserialized
+    /// module.
+    ///
+    /// If a deferred segment is used without a loader, or the loader
+    /// fails, `memory.init` traps.
+    ///
+    /// It will return `true` if the loader was set successfully, and
+    /// return `false` otherwise (in case the module is already
+    /// instantiated).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # use std::sync::Arc;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = r#"(module
+    ///     (memory (export "memory") 1)
+    ///     (data "a large asset")
+    ///     (func (export "load") (memory.init 0 (i32.const 0) (i32.const 0) (i32.const 13))))"#;
+    /// let mut module = Module::new(&store, wat)?;
+    /// let deferred = module.defer_passive_data(8).unwrap();
+    /// assert_eq!(deferred.len(), 1);
+    ///
+    /// module.set_data_loader(move |data_index: DataIndex| {
+    ///     // A real loader would read the segment from disk, for example.
+    ///     deferred
+    ///         .iter()
+    ///         .find(|(index, _)| *index == data_index)
+    ///         .map(|(_, data)| data.clone())
+    ///         .ok_or_else(|| "unknown segment".to_string())
+    /// });
+    ///
+    /// let instance = Instance::new(&module, &imports! {})?;
+    /// instance.exports.get_function("load")?.call(&[])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_data_loader(&mut self, loader: impl DataSegmentLoader + 'static) -> bool {
+        Arc::get_mut(&mut self.artifact)
+            .and_then(|artifact| artifact.module_mut())
+            .map(|module_info| {
+                module_info.set_data_loader(Arc::new(loader));
+                true
+            })
+            .unwrap_or(false)
+    }
+
     /// Returns an iterator over the imported types in the Module.
     ///
     /// The order of the imports is guaranteed to be the same as in the
@@ -420,8 +882,81 @@ impl Module {
     pub fn artifact(&self) -> &Arc<dyn Artifact> {
         &self.artifact
     }
+
+    /// Returns a handle to the compiled code of this module, bound to its
+    /// [`Engine`] rather than to its [`Store`].
+    ///
+    /// The handle is cheap to clone and to send to other threads, where
+    /// [`SharedModule::to_module`] turns it back into a `Module` of any
+    /// `Store` using the same engine, without compiling nor deserializing
+    /// it again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let module = Module::new(&store, "(module)")?;
+    /// let shared = module.share();
+    /// std::thread::spawn(move || {
+    ///     let store = Store::new(&**shared.engine());
+    ///     let module = shared.to_module(&store).expect("same engine");
+    ///     Instance::new(&module, &imports! {}).unwrap();
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn share(&self) -> SharedModule {
+        SharedModule {
+            engine: self.store.engine().clone(),
+            artifact: self.artifact.clone(),
+        }
+    }
+}
+
+/// The compiled code of a [`Module`], bound to an [`Engine`], created with
+/// [`Module::share`].
+#[derive(Clone)]
+pub struct SharedModule {
+    engine: Arc<dyn Engine + Send + Sync>,
+    artifact: Arc<dyn Artifact>,
+}
+
+impl SharedModule {
+    /// Returns the engine the code was compiled for.
+    pub fn engine(&self) -> &Arc<dyn Engine + Send + Sync> {
+        &self.engine
+    }
+
+    /// Returns a [`Module`] of `store` sharing this code, or `None` if
+    /// `store` uses another engine.
+    pub fn to_module(&self, store: &Store) -> Option<Module> {
+        if store.engine().id() != self.engine.id() {
+            return None;
+        }
+        Some(Module::from_artifact(store, self.artifact.clone()))
+    }
 }
 
+impl fmt::Debug for SharedModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedModule")
+            .field("name", &self.artifact.module_ref().name)
+            .finish()
+    }
+}
+
+// The types shared across threads, checked at compile time.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Store>();
+    assert_send_sync::<Module>();
+    assert_send_sync::<SharedModule>();
+};
+
 impl fmt::Debug for Module {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Module")
@@ -0,0 +1,99 @@
+use crate::{ExternType, Module};
+
+/// The import namespaces of WASI.
+const WASI_NAMESPACES: &[&str] = &["wasi_unstable", "wasi_snapshot_preview1"];
+
+/// The import namespaces of WASIX.
+const WASIX_NAMESPACES: &[&str] = &["wasix_32v1", "wasix_64v1"];
+
+/// Functions only emscripten imports from `env`.
+const EMSCRIPTEN_IMPORTS: &[&str] = &[
+    "_emscripten_memcpy_big",
+    "emscripten_memcpy_big",
+    "__map_file",
+];
+
+/// What kind of program a module is, which decides how to run it.
+///
+/// The kind is detected from the imports, exports and custom sections of
+/// the module with [`ModuleKind::detect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModuleKind {
+    /// A WASI program with an entry point, `_start`.
+    WasiCommand,
+    /// A WASI library, initialized with `_initialize` if exported, whose
+    /// exports are then called by the host.
+    WasiReactor,
+    /// A WASIX program, which may spawn threads (it imports
+    /// `wasi`.`thread-spawn`, a WASIX namespace or a shared memory).
+    Wasix,
+    /// A program compiled with emscripten.
+    Emscripten,
+    /// A module without any known runtime ABI.
+    Plain,
+}
+
+impl ModuleKind {
+    /// Detects the kind of `module`.
+    ///
+    /// The checks are made in this order: emscripten (which also
+    /// imports shared memories with pthreads), WASIX, WASI, plain.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let wat = r#"(module
+    ///     (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+    ///     (func (export "_start")))"#;
+    /// let module = Module::new(&store, wat)?;
+    /// assert_eq!(ModuleKind::detect(&module), ModuleKind::WasiCommand);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn detect(module: &Module) -> Self {
+        let emscripten = module
+            .custom_sections("emscripten_metadata")
+            .next()
+            .is_some()
+            || module.imports().functions().any(|import| {
+                import.module() == "env" && EMSCRIPTEN_IMPORTS.contains(&import.name())
+            });
+        if emscripten {
+            return Self::Emscripten;
+        }
+
+        let mut wasi = false;
+        for import in module.imports() {
+            let namespace = import.module();
+            let threaded = match import.ty() {
+                ExternType::Memory(memory) => memory.shared,
+                ExternType::Function(_) => namespace == "wasi" && import.name() == "thread-spawn",
+                _ => false,
+            };
+            if threaded || WASIX_NAMESPACES.contains(&namespace) {
+                return Self::Wasix;
+            }
+            wasi |= WASI_NAMESPACES.contains(&namespace);
+        }
+
+        if !wasi {
+            Self::Plain
+        } else if module
+            .exports()
+            .functions()
+            .any(|export| export.name() == "_start")
+        {
+            Self::WasiCommand
+        } else {
+            Self::WasiReactor
+        }
+    }
+
+    /// Returns `true` for the kinds run with a WASI environment.
+    pub fn is_wasi(self) -> bool {
+        matches!(self, Self::WasiCommand | Self::WasiReactor | Self::Wasix)
+    }
+}
@@ -0,0 +1,192 @@
+//! The `.wasmu-multi` container, holding the artifacts of a module
+//! compiled for several targets.
+//!
+//! The container starts with a selection header listing the target of
+//! every artifact with its position, followed by the artifacts:
+//!
+//! ```text
+//! magic "\0wasmu-multi" | version: u32 | count: u32
+//! count × (triple: str | cpu features: str | offset: u64 | length: u64)
+//! artifacts
+//! ```
+//!
+//! The integers are little-endian, and the strings are prefixed by their
+//! length as an `u32`. The CPU features are comma-separated.
+
+use std::convert::{TryFrom, TryInto};
+use std::str::FromStr;
+use wasmer_compiler::{CpuFeature, Target, Triple};
+use wasmer_engine::{DeserializeError, SerializeError};
+
+/// The extension of the files holding a multi-target container.
+pub const MULTI_TARGET_EXTENSION: &str = "wasmu-multi";
+
+const MAGIC: &[u8] = b"\0wasmu-multi";
+
+const VERSION: u32 = 1;
+
+/// Encodes the `artifacts`, each serialized for its target, into a
+/// container.
+pub(crate) fn encode(artifacts: &[(Target, Vec<u8>)]) -> Result<Vec<u8>, SerializeError> {
+    let mut container = Vec::new();
+    container.extend_from_slice(MAGIC);
+    container.extend_from_slice(&VERSION.to_le_bytes());
+    write_len(&mut container, artifacts.len())?;
+    let entries = artifacts
+        .iter()
+        .map(|(target, _)| {
+            let features = target
+                .cpu_features()
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            (target.triple().to_string(), features)
+        })
+        .collect::<Vec<_>>();
+    // The offsets are relative to the end of the selection header, whose
+    // size is known once the strings are.
+    let mut offset = 0u64;
+    for ((triple, features), (_, artifact)) in entries.iter().zip(artifacts) {
+        write_str(&mut container, triple)?;
+        write_str(&mut container, features)?;
+        container.extend_from_slice(&offset.to_le_bytes());
+        container.extend_from_slice(&(artifact.len() as u64).to_le_bytes());
+        offset += artifact.len() as u64;
+    }
+    for (_, artifact) in artifacts {
+        container.extend_from_slice(artifact);
+    }
+    Ok(container)
+}
+
+/// Returns whether `bytes` hold a multi-target container.
+pub(crate) fn is_multi_target(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Decodes the selection header of a container, and returns the
+/// artifacts with their target.
+pub(crate) fn decode(bytes: &[u8]) -> Result<Vec<(Target, &[u8])>, DeserializeError> {
+    if !is_multi_target(bytes) {
+        return Err(DeserializeError::Incompatible(
+            "the binary is not a multi-target container".to_string(),
+        ));
+    }
+    let mut reader = Reader {
+        bytes: &bytes[MAGIC.len()..],
+    };
+    let version = reader.read_u32()?;
+    if version != VERSION {
+        return Err(DeserializeError::Incompatible(format!(
+            "unsupported multi-target container version {}",
+            version
+        )));
+    }
+    let count = reader.read_u32()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let triple = reader.read_str()?;
+        let triple = Triple::from_str(triple).map_err(|error| {
+            DeserializeError::CorruptedBinary(format!("invalid triple `{}`: {}", triple, error))
+        })?;
+        let mut cpu_features = CpuFeature::set();
+        for feature in reader.read_str()?.split(',').filter(|f| !f.is_empty()) {
+            let feature = CpuFeature::from_str(feature)
+                .map_err(|error| DeserializeError::CorruptedBinary(error.to_string()))?;
+            cpu_features.insert(feature);
+        }
+        let offset = reader.read_u64()?;
+        let len = reader.read_u64()?;
+        entries.push((Target::new(triple, cpu_features), offset, len));
+    }
+    let artifacts = reader.bytes;
+    entries
+        .into_iter()
+        .map(|(target, offset, len)| {
+            let artifact = usize::try_from(offset)
+                .ok()
+                .zip(usize::try_from(len).ok())
+                .and_then(|(offset, len)| artifacts.get(offset..offset.checked_add(len)?))
+                .ok_or_else(|| {
+                    DeserializeError::CorruptedBinary(
+                        "the artifact is out of the container".to_string(),
+                    )
+                })?;
+            Ok((target, artifact))
+        })
+        .collect()
+}
+
+/// Selects the artifact that best matches `target`: the one for the same
+/// triple using the most CPU features, all supported by `target`.
+pub(crate) fn select<'a>(
+    artifacts: &[(Target, &'a [u8])],
+    target: &Target,
+) -> Result<&'a [u8], DeserializeError> {
+    artifacts
+        .iter()
+        .filter(|(candidate, _)| {
+            candidate.triple() == target.triple()
+                && candidate.cpu_features().is_subset(*target.cpu_features())
+        })
+        .max_by_key(|(candidate, _)| candidate.cpu_features().len())
+        .map(|(_, artifact)| *artifact)
+        .ok_or_else(|| {
+            let available = artifacts
+                .iter()
+                .map(|(candidate, _)| candidate.triple().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            DeserializeError::Incompatible(format!(
+                "no artifact for the target `{}` in the container (available: [{}])",
+                target.triple(),
+                available
+            ))
+        })
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) -> Result<(), SerializeError> {
+    let len: u32 = len
+        .try_into()
+        .map_err(|_| SerializeError::Generic("too large for a container".to_string()))?;
+    out.extend_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) -> Result<(), SerializeError> {
+    write_len(out, s.len())?;
+    out.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DeserializeError> {
+        if self.bytes.len() < len {
+            return Err(DeserializeError::CorruptedBinary(
+                "the multi-target container is truncated".to_string(),
+            ));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DeserializeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, DeserializeError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn read_str(&mut self) -> Result<&'a str, DeserializeError> {
+        let len = self.read_u32()? as usize;
+        std::str::from_utf8(self.take(len)?)
+            .map_err(|error| DeserializeError::CorruptedBinary(error.to_string()))
+    }
+}
@@ -14,12 +14,12 @@ use crate::externals::function::{
     VMDynamicFunctionWithoutEnv, WasmFunctionDefinition,
 };
 use crate::{FromToNativeWasmType, Function, FunctionType, RuntimeError, Store, WasmTypeList};
-use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
 use wasmer_engine::ExportFunction;
 use wasmer_types::NativeWasmType;
 use wasmer_vm::{
-    VMDynamicFunctionContext, VMExportFunction, VMFunctionBody, VMFunctionEnvironment,
-    VMFunctionKind,
+    HostCallTrap, InstanceAllocator, Trap, VMDynamicFunctionContext, VMExportFunction,
+    VMFunctionBody, VMFunctionEnvironment, VMFunctionKind,
 };
 
 /// A WebAssembly function that can be called natively
@@ -30,6 +30,7 @@ pub struct NativeFunc<Args = (), Rets = ()> {
     address: *const VMFunctionBody,
     vmctx: VMFunctionEnvironment,
     arg_kind: VMFunctionKind,
+    instance_allocator: Option<InstanceAllocator>,
     // exported: ExportFunction,
     _phantom: PhantomData<(Args, Rets)>,
 }
@@ -47,6 +48,7 @@ where
         vmctx: VMFunctionEnvironment,
         arg_kind: VMFunctionKind,
         definition: FunctionDefinition,
+        instance_allocator: Option<InstanceAllocator>,
     ) -> Self {
         Self {
             definition,
@@ -54,6 +56,7 @@ where
             address,
             vmctx,
             arg_kind,
+            instance_allocator,
             _phantom: PhantomData,
         }
     }
@@ -94,7 +97,7 @@ where
                 signature,
                 kind: other.arg_kind,
                 call_trampoline: None,
-                instance_allocator: None,
+                instance_allocator: other.instance_allocator.clone(),
             },
         }
     }
@@ -119,7 +122,7 @@ where
                     signature,
                     kind: other.arg_kind,
                     call_trampoline: None,
-                    instance_allocator: None,
+                    instance_allocator: other.instance_allocator,
                 },
             },
         }
@@ -156,6 +159,7 @@ macro_rules! impl_native_traits {
                             }
                             rets_list.as_mut()
                         };
+                        let _call_guard = self.store.enter_wasm_call()?;
                         unsafe {
                             wasmer_vm::wasmer_call_trampoline(
                                 self.vmctx,
@@ -163,7 +167,11 @@ macro_rules! impl_native_traits {
                                 self.address,
                                 args_rets.as_mut_ptr() as *mut u8,
                             )
-                        }?;
+                        }.map_err(|trap| {
+                            let error = RuntimeError::from_trap(trap);
+                            self.store.report_trap(&error, self.instance_allocator.as_ref());
+                            error
+                        })?;
                         let num_rets = rets_list.len();
                         if !using_rets_array && num_rets > 0 {
                             let src_pointer = params_list.as_ptr();
@@ -199,7 +207,12 @@ macro_rules! impl_native_traits {
                                     let f = std::mem::transmute::<_, unsafe extern "C" fn( VMFunctionEnvironment, $( $x, )*) -> Rets::CStruct>(self.address);
                                     // We always pass the vmctx
                                     f( self.vmctx, $( $x, )* )
-                                })).map_err(|e| RuntimeError::new(format!("{:?}", e)))?;
+                                })).map_err(|panic| match panic.downcast::<HostCallTrap>() {
+                                    // The host function returned an error.
+                                    Ok(trap) => RuntimeError::from_trap(Trap::new_from_user(trap.0)),
+                                    // The host function panicked: resurface the panic.
+                                    Err(panic) => resume_unwind(panic),
+                                })?;
                                 Ok(Rets::from_c_struct(results))
                             },
                             VMFunctionKind::Dynamic => {
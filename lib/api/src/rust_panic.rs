@@ -0,0 +1,317 @@
+use crate::module::Module;
+use crate::RuntimeError;
+use std::error::Error;
+use std::fmt;
+use wasmer_engine::FrameInfo;
+use wasmer_vm::{Trap, TrapCode};
+
+/// The functions of the Rust standard library a panicking guest goes
+/// through before it aborts, as prefixes of their demangled names.
+const PANIC_FUNCTIONS: &[&str] = &[
+    "core::panicking::",
+    "core::result::unwrap_failed",
+    "core::option::expect_failed",
+    "std::panicking::",
+    "std::panic::",
+    "std::sys::wasm",
+    "std::process::abort",
+    "rust_begin_unwind",
+    "rust_panic",
+    "__rust_start_panic",
+    "__rust_panic",
+    "__rust_abort",
+];
+
+/// The prefix of the panic messages printed by the Rust standard library.
+const PANICKED_AT: &str = "panicked at ";
+
+/// The panic of a Rust guest, decoded from the [`RuntimeError`] its call
+/// ended with.
+///
+/// Rust guests compiled with `panic=abort` end a panic with an
+/// `unreachable` instruction, after printing the panic message to
+/// `stderr` (WASI guests) or passing it to a host function (like the
+/// ones created with [`Function::new_guest_abort`]). `RustPanic`
+/// recognizes these traps, and gathers the panic message and the guest
+/// backtrace, symbolized with the `name` section and the source map of
+/// the module when present.
+///
+/// # Example
+///
+/// ```ignore
+/// # use wasmer::*;
+/// # fn main() -> anyhow::Result<()> {
+/// # let store = Store::default();
+/// # let module = Module::from_file(&store, "path/to/contract.wasm")?;
+/// # let instance = Instance::new(&module, &imports! {})?;
+/// let execute = instance.exports.get_function("execute")?;
+/// if let Err(error) = execute.call(&[]) {
+///     match RustPanic::decode(&error) {
+///         Some(panic) => return Err(panic.with_output(&captured_stderr).into()),
+///         None => return Err(error.into()),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Function::new_guest_abort`]: crate::Function::new_guest_abort
+#[derive(Debug, Clone)]
+pub struct RustPanic {
+    message: Option<String>,
+    location: Option<String>,
+    backtrace: Vec<String>,
+    error: RuntimeError,
+}
+
+impl RustPanic {
+    /// Returns whether `module` was compiled from Rust, according to its
+    /// `producers` section or to the names of its functions.
+    pub fn is_rust_module(module: &Module) -> bool {
+        let produced_by_rust = module.custom_sections("producers").any(|section| {
+            producers_languages(&section)
+                .iter()
+                .any(|lang| lang == "Rust")
+        });
+        produced_by_rust
+            || module
+                .info()
+                .function_names
+                .values()
+                .any(|name| is_panic_symbol(name))
+    }
+
+    /// Decodes the panic of a Rust guest from `error`, or returns `None`
+    /// if `error` isn't the trap of a Rust panic.
+    ///
+    /// The message is the one of the guest abort, if the guest aborted
+    /// through a host function, see [`RustPanic::with_output`] otherwise.
+    pub fn decode(error: &RuntimeError) -> Option<Self> {
+        let aborted = error.trap_code() == Some(TrapCode::UnreachableCodeReached)
+            || error.guest_abort_message().is_some();
+        if !aborted || !error.trace().iter().any(is_panic_frame) {
+            return None;
+        }
+        // The frames of the panic machinery are at the top of the trace.
+        let backtrace = error
+            .trace()
+            .iter()
+            .skip_while(|frame| is_panic_frame(frame))
+            .map(format_frame)
+            .collect();
+        Some(Self {
+            message: error.guest_abort_message().map(str::to_string),
+            location: None,
+            backtrace,
+            error: error.clone(),
+        })
+    }
+
+    /// Takes the message and the location of the panic from the output
+    /// of the guest, usually its `stderr`, where the Rust standard library
+    /// prints them.
+    ///
+    /// The last panic of the output is used. The panic is left unchanged
+    /// if the output has none.
+    pub fn with_output(mut self, output: &str) -> Self {
+        if let Some((message, location)) = parse_panic_output(output) {
+            self.message = Some(message);
+            self.location = Some(location);
+        }
+        self
+    }
+
+    /// Returns the panic message, if known.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Returns the location of the panic in the sources of the guest, as
+    /// `file:line:column`, if known.
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// Returns the frames of the guest that led to the panic, innermost
+    /// first, without the frames of the panic machinery.
+    pub fn backtrace(&self) -> &[String] {
+        &self.backtrace
+    }
+
+    /// Returns the error the call ended with.
+    pub fn runtime_error(&self) -> &RuntimeError {
+        &self.error
+    }
+
+    /// Converts the panic into a [`RuntimeError`] whose message is the
+    /// panic message, and that [`RuntimeError::downcast`]s to `RustPanic`.
+    pub fn into_runtime_error(self) -> RuntimeError {
+        RuntimeError::from_trap(Trap::new_from_user(Box::new(self)))
+    }
+}
+
+impl fmt::Display for RustPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "guest panicked")?;
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        match &self.message {
+            Some(message) => write!(f, ": {}", message)?,
+            None => write!(f, " (the message is unknown)")?,
+        }
+        for frame in &self.backtrace {
+            write!(f, "\n    at {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for RustPanic {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+fn is_panic_function(name: &str) -> bool {
+    PANIC_FUNCTIONS
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Returns whether the symbol `name`, mangled or not, is a function of the
+/// panic machinery.
+fn is_panic_symbol(name: &str) -> bool {
+    // `core::panicking::` and `std::panicking::` once mangled.
+    is_panic_function(name) || name.contains("9panicking")
+}
+
+fn is_panic_frame(frame: &FrameInfo) -> bool {
+    frame
+        .demangled_function_name()
+        .map_or(false, |name| is_panic_function(&name))
+}
+
+fn format_frame(frame: &FrameInfo) -> String {
+    let name = frame
+        .demangled_function_name()
+        .unwrap_or_else(|| "<unnamed>".to_string());
+    match frame.source_location() {
+        Some(location) => format!("{} at {}", name, location),
+        None => format!(
+            "{} ({}[{}]:0x{:x})",
+            name,
+            frame.module_name(),
+            frame.func_index(),
+            frame.module_offset()
+        ),
+    }
+}
+
+/// Parses the message and the location of the last panic printed in
+/// `output`, in the formats of Rust before and since 1.73:
+///
+/// ```text
+/// thread 'main' panicked at 'message', src/main.rs:2:5
+/// thread 'main' panicked at src/main.rs:2:5:
+/// message
+/// ```
+fn parse_panic_output(output: &str) -> Option<(String, String)> {
+    let start = output.rfind(PANICKED_AT)? + PANICKED_AT.len();
+    let rest = &output[start..];
+    if let Some(quoted) = rest.strip_prefix('\'') {
+        let line = quoted.lines().next().unwrap_or("");
+        let end = line.rfind("', ")?;
+        return Some((line[..end].to_string(), line[end + 3..].trim().to_string()));
+    }
+    let mut lines = rest.lines();
+    let location = lines.next()?.trim().trim_end_matches(':');
+    let message = lines
+        .take_while(|line| !line.starts_with("note: "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some((message.trim_end().to_string(), location.to_string()))
+}
+
+/// Returns the languages listed in a `producers` custom section.
+fn producers_languages(section: &[u8]) -> Vec<String> {
+    fn read_u32(bytes: &mut &[u8]) -> Option<u32> {
+        let mut result = 0u32;
+        for shift in (0..35).step_by(7) {
+            let (&byte, rest) = bytes.split_first()?;
+            *bytes = rest;
+            result |= u32::from(byte & 0x7f).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+        None
+    }
+    fn read_str<'a>(bytes: &mut &'a [u8]) -> Option<&'a str> {
+        let len = read_u32(bytes)? as usize;
+        if bytes.len() < len {
+            return None;
+        }
+        let (string, rest) = bytes.split_at(len);
+        *bytes = rest;
+        std::str::from_utf8(string).ok()
+    }
+
+    let mut bytes = section;
+    let mut languages = Vec::new();
+    let field_count = read_u32(&mut bytes).unwrap_or(0);
+    for _ in 0..field_count {
+        let field = match read_str(&mut bytes) {
+            Some(field) => field,
+            None => break,
+        };
+        let value_count = read_u32(&mut bytes).unwrap_or(0);
+        for _ in 0..value_count {
+            match (read_str(&mut bytes), read_str(&mut bytes)) {
+                (Some(name), Some(_version)) if field == "language" => {
+                    languages.push(name.to_string())
+                }
+                (Some(_), Some(_)) => {}
+                _ => return languages,
+            }
+        }
+    }
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panic_output_is_parsed() {
+        let output = "thread 'main' panicked at 'index out of bounds', src/lib.rs:3:5\n\
+                      note: run with `RUST_BACKTRACE=1`\n";
+        assert_eq!(
+            parse_panic_output(output),
+            Some((
+                "index out of bounds".to_string(),
+                "src/lib.rs:3:5".to_string()
+            ))
+        );
+        let output = "starting\nthread 'main' panicked at src/main.rs:2:5:\nno more gas\n\
+                      note: run with `RUST_BACKTRACE=1`\n";
+        assert_eq!(
+            parse_panic_output(output),
+            Some(("no more gas".to_string(), "src/main.rs:2:5".to_string()))
+        );
+        assert_eq!(parse_panic_output("all good\n"), None);
+    }
+
+    #[test]
+    fn producers_languages_are_read() {
+        let mut section = vec![1, 8];
+        section.extend_from_slice(b"language");
+        section.push(1);
+        section.push(4);
+        section.extend_from_slice(b"Rust");
+        section.push(0);
+        assert_eq!(producers_languages(&section), vec!["Rust".to_string()]);
+        assert!(producers_languages(&section[..5]).is_empty());
+    }
+}
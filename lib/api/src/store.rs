@@ -1,10 +1,20 @@
+use crate::store_scope::StoreScope;
 use crate::tunables::Tunables;
+use crate::RuntimeError;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
+use std::io;
+use std::panic;
 use std::sync::Arc;
+use std::thread;
+use thiserror::Error;
 #[cfg(all(feature = "compiler", feature = "engine"))]
 use wasmer_compiler::CompilerConfig;
-use wasmer_engine::Engine;
+use wasmer_compiler::Features;
 use wasmer_engine::Tunables as BaseTunables;
+use wasmer_engine::{Engine, TrapRecord};
+use wasmer_vm::{remaining_native_stack, InstanceAllocator, Trap, TrapCode};
 
 /// The store represents all global state that can be manipulated by
 /// WebAssembly programs. It consists of the runtime representation
@@ -15,14 +25,81 @@ use wasmer_engine::Tunables as BaseTunables;
 /// the Wasm bytes into a valid module artifact), in addition to the
 /// [`Tunables`] (that are used to create the memories, tables and globals).
 ///
+/// A `Store` is `Send` and `Sync`. Its instances can be moved to another
+/// thread between calls, see [`Instance`](crate::Instance).
+///
 /// Spec: https://webassembly.github.io/spec/core/exec/runtime.html#store
 #[derive(Clone)]
 pub struct Store {
     engine: Arc<dyn Engine + Send + Sync>,
     tunables: Arc<dyn BaseTunables + Send + Sync>,
+    reentrancy_policy: ReentrancyPolicy,
+    stack_budget: Option<usize>,
+    allowed_features: Option<Features>,
+    /// Identifies the call depth counters of this store (and its clones).
+    call_depth_key: Arc<()>,
+}
+
+/// Controls whether WebAssembly code can be re-entered from a host
+/// function it called (guest → host → guest).
+///
+/// Re-entering the same instance while a host function holds a lock
+/// used by its imports easily deadlocks; denying or bounding re-entrancy
+/// turns that into a clean [`RuntimeError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReentrancyPolicy {
+    /// Host functions can call back into WebAssembly without limits.
+    Allow,
+    /// Calling into WebAssembly from a host function returns an error.
+    Deny,
+    /// Host functions can call back into WebAssembly up to the given
+    /// number of nested levels.
+    Limit(usize),
+}
+
+impl Default for ReentrancyPolicy {
+    fn default() -> Self {
+        Self::Allow
+    }
+}
+
+thread_local! {
+    /// Number of active host → WebAssembly calls on this thread, per store.
+    static CALL_DEPTHS: RefCell<HashMap<usize, usize>> = RefCell::new(HashMap::new());
+}
+
+/// Leaves a host → WebAssembly call when dropped.
+pub(crate) struct WasmCallGuard {
+    key: usize,
+}
+
+impl Drop for WasmCallGuard {
+    fn drop(&mut self) {
+        CALL_DEPTHS.with(|depths| {
+            let mut depths = depths.borrow_mut();
+            if let Some(depth) = depths.get_mut(&self.key) {
+                *depth -= 1;
+                if *depth == 0 {
+                    depths.remove(&self.key);
+                }
+            }
+        });
+    }
 }
 
 impl Store {
+    /// Returns a [`StoreBuilder`], to set up all the options of a `Store`
+    /// at once and check that they are compatible.
+    pub fn builder() -> StoreBuilder {
+        StoreBuilder::default()
+    }
+
+    /// Returns a [`StoreScope`], owning the instances created with it
+    /// until it's dropped.
+    pub fn scope(&self) -> StoreScope {
+        StoreScope::new(self)
+    }
+
     /// Creates a new `Store` with a specific [`Engine`].
     pub fn new<E>(engine: &E) -> Self
     where
@@ -31,6 +108,10 @@ impl Store {
         Self {
             engine: engine.cloned(),
             tunables: Arc::new(Tunables::for_target(engine.target())),
+            reentrancy_policy: ReentrancyPolicy::default(),
+            stack_budget: None,
+            allowed_features: None,
+            call_depth_key: Arc::new(()),
         }
     }
 
@@ -45,6 +126,145 @@ impl Store {
         Self {
             engine: engine.cloned(),
             tunables: Arc::new(tunables),
+            reentrancy_policy: ReentrancyPolicy::default(),
+            stack_budget: None,
+            allowed_features: None,
+            call_depth_key: Arc::new(()),
+        }
+    }
+
+    /// Sets the [`ReentrancyPolicy`] of this `Store`.
+    ///
+    /// The policy applies to the modules created with the returned
+    /// `Store`, so it should be set before compiling them.
+    pub fn with_reentrancy_policy(mut self, policy: ReentrancyPolicy) -> Self {
+        self.reentrancy_policy = policy;
+        self
+    }
+
+    /// Returns the [`ReentrancyPolicy`].
+    pub fn reentrancy_policy(&self) -> ReentrancyPolicy {
+        self.reentrancy_policy
+    }
+
+    /// Sets the native stack, in bytes, that must be available when
+    /// calling into WebAssembly.
+    ///
+    /// Calls made with less native stack left fail right away with a
+    /// `StackOverflow` trap, instead of overflowing at a point that
+    /// depends on the platform. This makes deep recursions between
+    /// the host and the guest behave the same across operating systems.
+    /// To run a call with a larger stack, see [`call_with_stack_size`].
+    ///
+    /// The budget can't be checked on all platforms; it's ignored on
+    /// the ones where the native stack bounds are unknown.
+    pub fn with_stack_budget(mut self, bytes: usize) -> Self {
+        self.stack_budget = Some(bytes);
+        self
+    }
+
+    /// Returns the native stack budget set with
+    /// [`Store::with_stack_budget`].
+    pub fn stack_budget(&self) -> Option<usize> {
+        self.stack_budget
+    }
+
+    /// Restricts the WebAssembly features of the modules that can be
+    /// instantiated in this `Store`.
+    ///
+    /// Instantiating a module whose artifact was compiled with a feature
+    /// disabled in `features` fails with
+    /// [`LinkError::DisallowedFeatures`]. Headless stores, which
+    /// only run artifacts compiled elsewhere, can enforce a policy on
+    /// them this way.
+    ///
+    /// [`LinkError::DisallowedFeatures`]: crate::LinkError::DisallowedFeatures
+    pub fn with_allowed_features(mut self, features: Features) -> Self {
+        self.allowed_features = Some(features);
+        self
+    }
+
+    /// Returns the features allowed with [`Store::with_allowed_features`].
+    pub fn allowed_features(&self) -> Option<&Features> {
+        self.allowed_features.as_ref()
+    }
+
+    /// Returns the names of the features enabled in `features` that this
+    /// `Store` doesn't allow.
+    pub(crate) fn disallowed_features(&self, features: &Features) -> Vec<&'static str> {
+        let allowed = match &self.allowed_features {
+            Some(allowed) => allowed,
+            None => return Vec::new(),
+        };
+        let features = [
+            ("threads", features.threads, allowed.threads),
+            (
+                "reference_types",
+                features.reference_types,
+                allowed.reference_types,
+            ),
+            ("simd", features.simd, allowed.simd),
+            ("bulk_memory", features.bulk_memory, allowed.bulk_memory),
+            ("multi_value", features.multi_value, allowed.multi_value),
+            ("tail_call", features.tail_call, allowed.tail_call),
+            (
+                "module_linking",
+                features.module_linking,
+                allowed.module_linking,
+            ),
+            ("multi_memory", features.multi_memory, allowed.multi_memory),
+            ("memory64", features.memory64, allowed.memory64),
+        ];
+        features
+            .iter()
+            .filter(|(_, enabled, allowed)| *enabled && !*allowed)
+            .map(|(name, _, _)| *name)
+            .collect()
+    }
+
+    /// Enters a host → WebAssembly call, checking the [`ReentrancyPolicy`].
+    ///
+    /// The call is left when the returned guard is dropped.
+    pub(crate) fn enter_wasm_call(&self) -> Result<WasmCallGuard, RuntimeError> {
+        let key = Arc::as_ptr(&self.call_depth_key) as usize;
+        CALL_DEPTHS.with(|depths| {
+            let mut depths = depths.borrow_mut();
+            let depth = depths.entry(key).or_insert(0);
+            let allowed = match self.reentrancy_policy {
+                ReentrancyPolicy::Allow => true,
+                ReentrancyPolicy::Deny => *depth == 0,
+                ReentrancyPolicy::Limit(limit) => *depth <= limit,
+            };
+            if !allowed {
+                return Err(RuntimeError::new(format!(
+                    "re-entering WebAssembly from a host function is not allowed (nesting depth `{}`, policy `{:?}`)",
+                    depth, self.reentrancy_policy
+                )));
+            }
+            // The stack is only looked up with a budget: it's a system call
+            // on some platforms.
+            if let Some(budget) = self.stack_budget {
+                if remaining_native_stack().map_or(false, |remaining| remaining < budget) {
+                    return Err(RuntimeError::from_trap(Trap::new_from_runtime(
+                        TrapCode::StackOverflow,
+                    )));
+                }
+            }
+            *depth += 1;
+            Ok(WasmCallGuard { key })
+        })
+    }
+
+    /// Reports `error`, returned by a call into a function exported by
+    /// `instance`, to the [`TrapSink`] of the engine.
+    ///
+    /// Nothing is reported for the host functions, which don't belong to
+    /// an instance.
+    pub(crate) fn report_trap(&self, error: &RuntimeError, instance: Option<&InstanceAllocator>) {
+        if let (Some(trap_sink), Some(instance)) = (self.engine.trap_sink(), instance) {
+            let module_hash = instance.module_ref().hash;
+            let instance_id = instance.vmctx_ptr() as usize;
+            trap_sink.on_trap(&TrapRecord::new(error, module_hash, instance_id));
         }
     }
 
@@ -59,13 +279,196 @@ impl Store {
     }
 
     /// Checks whether two stores are identical. A store is considered
-    /// equal to another store if both have the same engine. The
-    /// tunables are excluded from the logic.
+    /// equal to another store if both have the same engine, or clones
+    /// of the same engine. The tunables are excluded from the logic.
     pub fn same(a: &Self, b: &Self) -> bool {
         a.engine.id() == b.engine.id()
     }
 }
 
+/// An error when building a [`Store`] with a [`StoreBuilder`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StoreBuildError {
+    /// No engine was set, and there is no default one.
+    #[error("no engine was set, and there is no default engine")]
+    MissingEngine,
+
+    /// A native stack budget was set on a platform where the native stack
+    /// bounds can't be determined, so the budget would be ignored.
+    #[error("the native stack budget can't be checked on this platform")]
+    StackBudgetUnsupported,
+
+    /// The allowed features enable a feature without one it requires.
+    #[error("the allowed feature `{0}` requires the feature `{1}`")]
+    MissingFeatureDependency(&'static str, &'static str),
+}
+
+/// A builder of [`Store`], created with [`Store::builder`].
+///
+/// Unlike the `with_*` methods of [`Store`], the builder checks that its
+/// options are compatible with each other, and with the platform, when
+/// building the `Store`.
+///
+/// # Example
+///
+/// ```
+/// # use wasmer::*;
+/// # fn main() -> anyhow::Result<()> {
+/// # let engine = Store::default().engine().clone();
+/// let store = Store::builder()
+///     .engine(&*engine)
+///     .reentrancy_policy(ReentrancyPolicy::Deny)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct StoreBuilder {
+    engine: Option<Arc<dyn Engine + Send + Sync>>,
+    tunables: Option<Arc<dyn BaseTunables + Send + Sync>>,
+    reentrancy_policy: ReentrancyPolicy,
+    stack_budget: Option<usize>,
+    allowed_features: Option<Features>,
+}
+
+impl StoreBuilder {
+    /// Sets the [`Engine`]. Without it, the default engine is used if
+    /// there is one.
+    pub fn engine<E>(mut self, engine: &E) -> Self
+    where
+        E: Engine + ?Sized,
+    {
+        self.engine = Some(engine.cloned());
+        self
+    }
+
+    /// Sets the [`Tunables`]. Without them, the default tunables of the
+    /// target of the engine are used.
+    pub fn tunables(mut self, tunables: impl BaseTunables + Send + Sync + 'static) -> Self {
+        self.tunables = Some(Arc::new(tunables));
+        self
+    }
+
+    /// Sets the [`ReentrancyPolicy`], see [`Store::with_reentrancy_policy`].
+    pub fn reentrancy_policy(mut self, policy: ReentrancyPolicy) -> Self {
+        self.reentrancy_policy = policy;
+        self
+    }
+
+    /// Sets the native stack budget, see [`Store::with_stack_budget`].
+    pub fn stack_budget(mut self, bytes: usize) -> Self {
+        self.stack_budget = Some(bytes);
+        self
+    }
+
+    /// Restricts the features of the modules that can be instantiated,
+    /// see [`Store::with_allowed_features`].
+    pub fn allowed_features(mut self, features: Features) -> Self {
+        self.allowed_features = Some(features);
+        self
+    }
+
+    /// Builds the [`Store`], checking that the options are compatible.
+    pub fn build(self) -> Result<Store, StoreBuildError> {
+        if self.stack_budget.is_some() && !cfg!(unix) {
+            return Err(StoreBuildError::StackBudgetUnsupported);
+        }
+        if let Some(features) = &self.allowed_features {
+            // Both proposals depend on the bulk memory one.
+            let dependencies = [
+                (
+                    "threads",
+                    features.threads,
+                    "bulk_memory",
+                    features.bulk_memory,
+                ),
+                (
+                    "reference_types",
+                    features.reference_types,
+                    "bulk_memory",
+                    features.bulk_memory,
+                ),
+            ];
+            if let Some((feature, _, dependency, _)) = dependencies
+                .iter()
+                .find(|(_, enabled, _, dependency_enabled)| *enabled && !*dependency_enabled)
+            {
+                return Err(StoreBuildError::MissingFeatureDependency(
+                    feature, dependency,
+                ));
+            }
+        }
+        let engine = match self.engine {
+            Some(engine) => engine,
+            None => Self::default_engine()?,
+        };
+        let tunables = match self.tunables {
+            Some(tunables) => tunables,
+            None => Arc::new(Tunables::for_target(engine.target())),
+        };
+        Ok(Store {
+            engine,
+            tunables,
+            reentrancy_policy: self.reentrancy_policy,
+            stack_budget: self.stack_budget,
+            allowed_features: self.allowed_features,
+            call_depth_key: Arc::new(()),
+        })
+    }
+
+    #[cfg(all(feature = "default-compiler", feature = "default-engine"))]
+    fn default_engine() -> Result<Arc<dyn Engine + Send + Sync>, StoreBuildError> {
+        Ok(Store::default().engine)
+    }
+
+    #[cfg(not(all(feature = "default-compiler", feature = "default-engine")))]
+    fn default_engine() -> Result<Arc<dyn Engine + Send + Sync>, StoreBuildError> {
+        Err(StoreBuildError::MissingEngine)
+    }
+}
+
+impl fmt::Debug for StoreBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StoreBuilder")
+            .field("reentrancy_policy", &self.reentrancy_policy)
+            .field("stack_budget", &self.stack_budget)
+            .field("allowed_features", &self.allowed_features)
+            .finish()
+    }
+}
+
+/// Runs `f` on a new thread with a native stack of `stack_size` bytes,
+/// and returns its result.
+///
+/// Deeply recursive guests need a larger native stack than the default
+/// one of the current thread, whose size differs across operating
+/// systems. Panics in `f` are propagated to the caller.
+///
+/// # Example
+///
+/// ```
+/// # use wasmer::*;
+/// # fn main() -> anyhow::Result<()> {
+/// # let store = Store::default();
+/// let module = Module::new(&store, "(module (func (export \"run\")))")?;
+/// let instance = Instance::new(&module, &imports! {})?;
+/// let run = instance.exports.get_function("run")?.clone();
+/// call_with_stack_size(64 * 1024 * 1024, move || run.call(&[]))??;
+/// # Ok(())
+/// # }
+/// ```
+pub fn call_with_stack_size<F, R>(stack_size: usize, f: F) -> io::Result<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let thread = thread::Builder::new().stack_size(stack_size).spawn(f)?;
+    match thread.join() {
+        Ok(result) => Ok(result),
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
 impl PartialEq for Store {
     fn eq(&self, other: &Self) -> bool {
         Self::same(self, other)
@@ -115,6 +518,10 @@ impl Default for Store {
         Store {
             engine: Arc::new(engine),
             tunables: Arc::new(tunables),
+            reentrancy_policy: ReentrancyPolicy::default(),
+            stack_budget: None,
+            allowed_features: None,
+            call_depth_key: Arc::new(()),
         }
     }
 }
@@ -0,0 +1,120 @@
+use crate::instance::{Instance, InstantiationError};
+use crate::module::Module;
+use crate::store::Store;
+use crate::LinkError;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use wasmer_engine::Resolver;
+use wasmer_vm::WeakInstanceRef;
+
+/// A scope owning the instances created with it, released together when
+/// the scope is dropped.
+///
+/// [`StoreScope::instantiate`] returns the instances by reference, so they
+/// can't outlive the scope. The instances are reference-counted though: an
+/// instance is deallocated (with its memories, tables and globals) once the
+/// scope, the clones of the instance and its exports are all dropped.
+/// [`StoreScope::release`] tells how many instances outlived the scope
+/// because the host kept some of their clones or exports.
+///
+/// # Example
+///
+/// ```
+/// # use wasmer::*;
+/// # fn main() -> anyhow::Result<()> {
+/// # let store = Store::default();
+/// let module = Module::new(&store, "(module (memory (export \"memory\") 1))")?;
+/// let scope = store.scope();
+/// for _ in 0..3 {
+///     let instance = scope.instantiate(&module, &imports! {})?;
+///     let memory = instance.exports.get_memory("memory")?;
+///     assert_eq!(memory.size(), Pages(1));
+/// }
+/// assert_eq!(scope.len(), 3);
+/// assert_eq!(scope.release(), 0);
+/// # Ok(())
+/// # }
+/// ```
+pub struct StoreScope {
+    store: Store,
+    /// The instances are boxed so that they don't move while borrowed.
+    instances: Mutex<Vec<(Box<Instance>, WeakInstanceRef)>>,
+}
+
+impl StoreScope {
+    pub(crate) fn new(store: &Store) -> Self {
+        Self {
+            store: store.clone(),
+            instances: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the [`Store`] of the scope.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Creates an instance of `module` with [`Instance::new`], owned by
+    /// the scope.
+    ///
+    /// The module must belong to the store of the scope.
+    pub fn instantiate(
+        &self,
+        module: &Module,
+        resolver: &dyn Resolver,
+    ) -> Result<&Instance, InstantiationError> {
+        if !Store::same(module.store(), &self.store) {
+            return Err(InstantiationError::Link(LinkError::Resource(
+                "the module doesn't belong to the store of the scope".to_string(),
+            )));
+        }
+        let instance = Box::new(Instance::new(module, resolver)?);
+        let weak = instance.downgrade();
+        let instance_ptr: *const Instance = &*instance;
+        self.lock_instances().push((instance, weak));
+        // SAFETY: the boxed instance is only dropped by `release` and by
+        // dropping the scope, which can't happen while `self` is borrowed.
+        Ok(unsafe { &*instance_ptr })
+    }
+
+    /// Returns the number of instances created with the scope.
+    pub fn len(&self) -> usize {
+        self.lock_instances().len()
+    }
+
+    /// Returns `true` if no instance was created with the scope.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Releases the instances of the scope, and returns the number of
+    /// instances that are still alive because the host holds some of
+    /// their handles or exports.
+    ///
+    /// Dropping the scope releases the instances as well, without
+    /// reporting them.
+    pub fn release(self) -> usize {
+        let instances = std::mem::take(&mut *self.lock_instances());
+        // Drop all the instances of the scope before checking which are
+        // still alive.
+        let weak_refs = instances
+            .into_iter()
+            .map(|(_instance, weak)| weak)
+            .collect::<Vec<_>>();
+        weak_refs.iter().filter(|weak| weak.is_alive()).count()
+    }
+
+    fn lock_instances(&self) -> MutexGuard<'_, Vec<(Box<Instance>, WeakInstanceRef)>> {
+        self.instances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for StoreScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StoreScope")
+            .field("instances", &self.len())
+            .finish()
+    }
+}
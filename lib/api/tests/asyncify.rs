@@ -0,0 +1,83 @@
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use wasmer::asyncify::{self, Asyncify};
+use wasmer::*;
+
+/// Polls a future which never waits, without an async runtime.
+fn block_on<F: Future>(future: F) -> F::Output {
+    fn raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    let waker = unsafe { Waker::from_raw(raw_waker()) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+#[test]
+fn asyncify_suspends_the_guest_on_host_operations() -> Result<()> {
+    let store = Store::default();
+    // What Asyncify generates for `return sleep(10) + 1`, without any
+    // local to save: the call is replayed when rewinding.
+    let module = Module::new(
+        &store,
+        r#"(module
+        (import "env" "sleep" (func $sleep (param i32) (result i32)))
+        (memory (export "memory") 1)
+        (global $state (mut i32) (i32.const 0))
+        (global $data (mut i32) (i32.const 0))
+        (func (export "asyncify_start_unwind") (param i32)
+           (global.set $state (i32.const 1))
+           (global.set $data (local.get 0)))
+        (func (export "asyncify_stop_unwind")
+           (global.set $state (i32.const 0)))
+        (func (export "asyncify_start_rewind") (param i32)
+           (global.set $state (i32.const 2))
+           (global.set $data (local.get 0)))
+        (func (export "asyncify_stop_rewind")
+           (global.set $state (i32.const 0)))
+        (func (export "asyncify_get_state") (result i32)
+           (global.get $state))
+        (func (export "main") (result i32)
+           (local $slept i32)
+           (local.set $slept (call $sleep (i32.const 10)))
+           (if (i32.eq (global.get $state) (i32.const 1))
+              (then (return (i32.const 0))))
+           (i32.add (local.get $slept) (i32.const 1))))"#,
+    )?;
+    assert!(asyncify::is_asyncified(&module));
+
+    fn sleep(asyncify: &Asyncify, ms: i32) -> Result<i32, RuntimeError> {
+        let slept = asyncify.suspend(move || async move { ms * 2 })?;
+        Ok(slept.unwrap_or(0))
+    }
+    let asyncify = Asyncify::new();
+    let import_object = imports! {
+        "env" => {
+            "sleep" => Function::new_native_with_env(&store, asyncify.clone(), sleep),
+        },
+    };
+    let instance = Instance::new(&module, &import_object)?;
+    let main = instance.exports.get_function("main")?;
+    let results = block_on(asyncify.call(main, &[]))?;
+    assert_eq!(results.to_vec(), vec![Value::I32(21)]);
+
+    // The buffer was allocated in a new page, and set up for unwinding.
+    let memory = instance.exports.get_memory("memory")?;
+    assert_eq!(memory.size(), Pages(2));
+    let header = &memory.view::<u32>()[WASM_PAGE_SIZE / 4..WASM_PAGE_SIZE / 4 + 2];
+    assert_eq!(header[0].get(), WASM_PAGE_SIZE as u32 + 8);
+    assert_eq!(header[1].get(), 2 * WASM_PAGE_SIZE as u32);
+    Ok(())
+}
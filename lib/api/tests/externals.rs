@@ -25,6 +25,33 @@ fn global_new() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn global_try_new_rejects_values_from_other_stores() -> Result<()> {
+    let store = Store::default();
+    let other_store = Store::default();
+    let function = Function::new_native(&other_store, || {});
+    assert!(Global::try_new(&store, Value::FuncRef(function.clone())).is_err());
+    assert!(Global::try_new_mut(&store, Value::FuncRef(function)).is_err());
+
+    let global = Global::try_new_mut(&store, Value::I32(10))?;
+    assert_eq!(global.get(), Value::I32(10));
+    Ok(())
+}
+
+#[test]
+fn exports_can_be_extended_when_shared() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, "(module (global (export \"g\") i32 (i32.const 1)))")?;
+    let instance = Instance::new(&module, &imports! {})?;
+
+    let mut exports = instance.exports.clone();
+    exports.insert("h", Global::new(&store, Value::I32(2)));
+    assert_eq!(exports.len(), 2);
+    assert_eq!(instance.exports.len(), 1);
+    assert_eq!(exports.get_global("g")?.get(), Value::I32(1));
+    Ok(())
+}
+
 #[test]
 fn global_get() -> Result<()> {
     let store = Store::default();
@@ -58,6 +85,115 @@ fn global_set() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn global_shared_between_instances() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module
+        (import "env" "height" (global $height (mut i64)))
+        (import "env" "paused" (global $paused (mut i32)))
+        (func (export "height") (result i64) (global.get $height))
+        (func (export "bump") (global.set $height (i64.add (global.get $height) (i64.const 1))))
+        (func (export "wait") (result i32) (local $spins i64)
+            (loop $spin
+                (if (i32.eqz (global.get $paused)) (then (return (i32.const 1))))
+                (local.set $spins (i64.add (local.get $spins) (i64.const 1)))
+                (br_if $spin (i64.lt_u (local.get $spins) (i64.const 0x100000000))))
+            (i32.const 0)))"#,
+    )?;
+    let height = Global::new_mut(&store, Value::I64(100));
+    let paused = Global::new_mut(&store, Value::I32(1));
+    let import_object = imports! {
+        "env" => {
+            "height" => height.clone(),
+            "paused" => paused.clone(),
+        },
+    };
+    let first = Instance::new(&module, &import_object)?;
+    let second = Instance::new(&module, &import_object)?;
+    let height_of = |instance: &Instance| -> Result<i64> {
+        let height = instance.exports.get_native_function::<(), i64>("height")?;
+        Ok(height.call()?)
+    };
+
+    // A single set is seen by every instance.
+    height.set(Value::I64(101))?;
+    assert_eq!(height_of(&first)?, 101);
+    assert_eq!(height_of(&second)?, 101);
+    // And so are the sets of the instances.
+    second
+        .exports
+        .get_native_function::<(), ()>("bump")?
+        .call()?;
+    assert_eq!(height_of(&first)?, 102);
+    assert_eq!(height.get(), Value::I64(102));
+
+    // The host can set the global while an instance runs. The wait gives
+    // up after a few seconds, instead of hanging, if the set isn't seen.
+    let wait = first.exports.get_native_function::<(), i32>("wait")?;
+    let resume = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        paused.set(Value::I32(0)).unwrap();
+    });
+    assert_eq!(wait.call()?, 1, "the instance didn't see the global set");
+    resume.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn global_float_bits() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module
+        (import "env" "f32" (global $f32 (mut f32)))
+        (import "env" "f64" (global $f64 (mut f64)))
+        (func (export "f32") (result i32) (i32.reinterpret_f32 (global.get $f32)))
+        (func (export "f64") (result i64) (i64.reinterpret_f64 (global.get $f64))))"#,
+    )?;
+    let f32_global = Global::new_mut(&store, Value::F32(1.5));
+    let f64_global = Global::new_mut(&store, Value::F64(-2.25));
+    assert_eq!(f32_global.get(), Value::F32(1.5));
+    assert_eq!(f64_global.get(), Value::F64(-2.25));
+    let instance = Instance::new(
+        &module,
+        &imports! {
+            "env" => {
+                "f32" => f32_global.clone(),
+                "f64" => f64_global.clone(),
+            },
+        },
+    )?;
+    let f32_bits = instance.exports.get_native_function::<(), i32>("f32")?;
+    let f64_bits = instance.exports.get_native_function::<(), i64>("f64")?;
+
+    // The bits are kept exactly, including the sign of zero and the
+    // payload of NaNs.
+    for &bits in &[0x3fc0_0000_u32, 0x8000_0000, 0x7fc0_1234, 0xffa0_0001] {
+        f32_global.set(Value::F32(f32::from_bits(bits)))?;
+        match f32_global.get() {
+            Value::F32(value) => assert_eq!(value.to_bits(), bits),
+            value => panic!("unexpected value: {:?}", value),
+        }
+        assert_eq!(f32_bits.call()? as u32, bits);
+    }
+    for &bits in &[
+        0xc002_0000_0000_0000_u64,
+        0x8000_0000_0000_0000,
+        0x7ff8_0000_dead_beef,
+        0xfff4_0000_0000_0001,
+    ] {
+        f64_global.set(Value::F64(f64::from_bits(bits)))?;
+        match f64_global.get() {
+            Value::F64(value) => assert_eq!(value.to_bits(), bits),
+            value => panic!("unexpected value: {:?}", value),
+        }
+        assert_eq!(f64_bits.call()? as u64, bits);
+    }
+    Ok(())
+}
+
 #[test]
 fn table_new() -> Result<()> {
     let store = Store::default();
@@ -168,6 +304,14 @@ fn memory_grow() -> Result<()> {
             attempted_delta: 10.into(),
         })
     );
+    assert_eq!(
+        memory.last_grow_failure(),
+        Some(MemoryGrowEvent {
+            current: 12.into(),
+            attempted_delta: 10.into(),
+            reason: MemoryGrowFailureReason::DeclaredMaximum,
+        })
+    );
 
     let bad_desc = MemoryType::new(Pages(15), Some(Pages(10)), false);
     let bad_result = Memory::new(&store, bad_desc);
@@ -177,6 +321,31 @@ fn memory_grow() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn memory_diff() -> Result<()> {
+    let store = Store::default();
+    let memory = Memory::new(&store, MemoryType::new(Pages(1), None, false))?;
+    let mut snapshot = memory.snapshot();
+    assert!(memory.diff(&snapshot).is_empty());
+
+    unsafe {
+        let data = memory.data_unchecked_mut();
+        data[0] = 1;
+        data[1500] = 1;
+        data[2047] = 1;
+        data[60000] = 1;
+    }
+    memory.grow(Pages(1))?;
+    let diff = memory.diff(&snapshot);
+    assert_eq!(diff.ranges(), &[0..2048, 59392..60416, 65536..131072]);
+    assert_eq!(diff.size(), 131072);
+
+    snapshot.update(&memory, &diff);
+    assert_eq!(snapshot, memory.snapshot());
+    assert!(memory.diff(&snapshot).is_empty());
+    Ok(())
+}
+
 #[test]
 fn function_new() -> Result<()> {
     let store = Store::default();
@@ -352,6 +521,44 @@ fn native_function_works() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn function_call_into() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module
+        (func (export "swap") (param i32 i64) (result i64 i32)
+            (local.get 1) (local.get 0))
+        (func (export "sum10")
+            (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 9))))"#,
+    )?;
+    let instance = Instance::new(&module, &imports! {})?;
+
+    let swap = instance.exports.get_function("swap")?;
+    let mut results = [Value::I32(0), Value::I32(0)];
+    swap.call_into(&[Value::I32(1), Value::I64(2)], &mut results)?;
+    assert_eq!(results, [Value::I64(2), Value::I32(1)]);
+    assert!(swap
+        .call_into(&[Value::I32(1), Value::I64(2)], &mut results[..1])
+        .is_err());
+
+    // More values than fit on the stack.
+    let sum10 = instance.exports.get_function("sum10")?;
+    let params = (1..=10).map(Value::I32).collect::<Vec<_>>();
+    sum10.call_into(&params, &mut results[..1])?;
+    assert_eq!(results[0], Value::I32(11));
+
+    let function_type = FunctionType::new(vec![Type::I32], vec![Type::I32]);
+    let double = Function::new(&store, &function_type, |values| {
+        Ok(vec![Value::I32(values[0].unwrap_i32() * 2)])
+    });
+    double.call_into(&[Value::I32(4)], &mut results[..1])?;
+    assert_eq!(results[0], Value::I32(8));
+    assert!(double.call_into(&[Value::I32(4)], &mut results).is_err());
+    Ok(())
+}
+
 #[test]
 fn function_outlives_instance() -> Result<()> {
     let store = Store::default();
@@ -37,3 +37,377 @@ fn exports_work_after_multiple_instances_have_been_freed() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn guest_callbacks_can_be_called_from_the_host() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        "
+    (module
+      (type $callback_t (func (param i32) (result i32)))
+      (func $double (type $callback_t) (param $x i32) (result i32)
+        local.get $x
+        i32.const 2
+        i32.mul)
+      (table $table (export \"table\") 2 funcref)
+      (elem (i32.const 0) $double))
+",
+    )?;
+
+    let import_object = ImportObject::new();
+    let instance = Instance::new(&module, &import_object)?;
+
+    let double: GuestCallback<i32, i32> = GuestCallback::from_export(&instance, "table", 0)?;
+    drop(instance);
+    assert_eq!(double.call(21)?, 42);
+
+    // Null slots, out of bounds indices and mismatched signatures are errors.
+    let null: GuestCallback<i32, i32> = GuestCallback::from_export(double.instance(), "table", 1)?;
+    assert!(null.call(1).is_err());
+    let out_of_bounds: GuestCallback<i32, i32> =
+        GuestCallback::from_export(double.instance(), "table", 2)?;
+    assert!(out_of_bounds.call(1).is_err());
+    let mismatched: GuestCallback<i64, i32> =
+        GuestCallback::from_export(double.instance(), "table", 0)?;
+    assert!(mismatched.call(1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn reentrancy_policy_is_enforced() -> Result<()> {
+    #[derive(WasmerEnv, Clone)]
+    struct Env {
+        #[wasmer(export)]
+        run: LazyInit<NativeFunc<i32, i32>>,
+    }
+
+    fn instantiate(policy: ReentrancyPolicy) -> Result<NativeFunc<i32, i32>> {
+        let store = Store::default().with_reentrancy_policy(policy);
+        let module = Module::new(
+            &store,
+            "
+    (module
+      (import \"host\" \"call_back\" (func $call_back (param i32) (result i32)))
+      (func (export \"run\") (param $n i32) (result i32)
+        local.get $n
+        call $call_back))
+",
+        )?;
+
+        let env = Env {
+            run: LazyInit::default(),
+        };
+        let call_back = Function::new_with_env(
+            &store,
+            &FunctionType::new(vec![Type::I32], vec![Type::I32]),
+            env,
+            |env, values| {
+                let n = values[0].unwrap_i32();
+                if n == 0 {
+                    return Ok(vec![Value::I32(0)]);
+                }
+                let result = env.run_ref().unwrap().call(n - 1)?;
+                Ok(vec![Value::I32(result + 1)])
+            },
+        );
+        let instance = Instance::new(
+            &module,
+            &imports! {
+                "host" => {
+                    "call_back" => call_back,
+                },
+            },
+        )?;
+        Ok(instance.exports.get_native_function("run")?)
+    }
+
+    let run = instantiate(ReentrancyPolicy::Allow)?;
+    assert_eq!(run.call(5)?, 5);
+
+    let run = instantiate(ReentrancyPolicy::Deny)?;
+    assert_eq!(run.call(0)?, 0);
+    assert!(run.call(1).is_err());
+    // The call depth is restored after the error.
+    assert_eq!(run.call(0)?, 0);
+
+    let run = instantiate(ReentrancyPolicy::Limit(2))?;
+    assert_eq!(run.call(2)?, 2);
+    assert!(run.call(3).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn linker_shares_an_env_instance_between_guests() -> Result<()> {
+    let store = Store::default();
+    let env = Module::new(
+        &store,
+        r#"(module
+            (global (export "counter") (mut i32) (i32.const 0))
+            (func (export "bump") (result i32)
+                (global.set 0 (i32.add (global.get 0) (i32.const 1)))
+                (global.get 0)))"#,
+    )?;
+    let guest = Module::new(
+        &store,
+        r#"(module
+            (import "env" "bump" (func $bump (result i32)))
+            (import "host" "offset" (global $offset i32))
+            (func (export "run") (result i32)
+                (i32.add (call $bump) (global.get $offset))))"#,
+    )?;
+
+    let mut linker = Linker::new();
+    let env = linker.instantiate(&env)?;
+    linker.instance("env", &env)?;
+    linker.define("host", "offset", Global::new(&store, Value::I32(100)))?;
+
+    let first = linker.instantiate(&guest)?;
+    let second = linker.instantiate(&guest)?;
+    let run = |instance: &Instance| -> Result<i32> {
+        Ok(instance
+            .exports
+            .get_native_function::<(), i32>("run")?
+            .call()?)
+    };
+    assert_eq!(run(&first)?, 101);
+    assert_eq!(run(&second)?, 102);
+    assert_eq!(env.exports.get_global("counter")?.get(), Value::I32(2));
+
+    assert!(matches!(
+        linker.define("host", "offset", Global::new(&store, Value::I32(0))),
+        Err(LinkerError::AlreadyDefined(_, _))
+    ));
+    linker.allow_shadowing(true);
+    linker.define("host", "offset", Global::new(&store, Value::I32(0)))?;
+    assert_eq!(run(&linker.instantiate(&guest)?)?, 3);
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn stack_budget_is_checked_when_entering_wasm() -> Result<()> {
+    let store = Store::default().with_stack_budget(1 << 20);
+    let module = Module::new(&store, r#"(module (func (export "run")))"#)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let run = instance.exports.get_function("run")?.clone();
+
+    // The native stack of a small thread is below the budget...
+    let small = run.clone();
+    let result = call_with_stack_size(256 * 1024, move || small.call(&[]).map(|_| ()))?;
+    assert!(result.is_err());
+
+    // ...while a large one has enough room.
+    call_with_stack_size(8 << 20, move || run.call(&[]).map(|_| ()))??;
+    Ok(())
+}
+
+#[test]
+fn run_initializers_picks_the_module_convention() -> Result<()> {
+    let store = Store::default();
+
+    let ctors = Module::new(
+        &store,
+        r#"(module
+            (global $ready (export "ready") (mut i32) (i32.const 0))
+            (func (export "__wasm_call_ctors") (global.set $ready (i32.const 1))))"#,
+    )?;
+    let instance = Instance::new(&ctors, &imports! {})?;
+    assert_eq!(instance.run_initializers()?, Some(Initializer::CallCtors));
+    assert_eq!(instance.exports.get_global("ready")?.get(), Val::I32(1));
+
+    // `_start` runs the constructors itself, so they don't run twice.
+    let command = Module::new(
+        &store,
+        r#"(module
+            (global $count (export "count") (mut i32) (i32.const 0))
+            (func $ctors (export "__wasm_call_ctors")
+                (global.set $count (i32.add (global.get $count) (i32.const 1))))
+            (func (export "_start") (call $ctors)))"#,
+    )?;
+    let instance = Instance::new(&command, &imports! {})?;
+    assert_eq!(instance.run_initializers()?, Some(Initializer::Start));
+    assert_eq!(instance.exports.get_global("count")?.get(), Val::I32(1));
+
+    let contract = Module::new(&store, r#"(module (func (export "instantiate")))"#)?;
+    let instance = Instance::new(&contract, &imports! {})?;
+    assert_eq!(instance.run_initializers()?, None);
+
+    let invalid = Module::new(
+        &store,
+        r#"(module (func (export "_initialize") (param i32)))"#,
+    )?;
+    let instance = Instance::new(&invalid, &imports! {})?;
+    assert!(instance.run_initializers().is_err());
+    Ok(())
+}
+
+#[test]
+fn store_allowed_features_are_enforced() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, "(module)")?;
+    let serialized = module.serialize()?;
+
+    let mut allowed = Features::new();
+    let permissive = store.clone().with_allowed_features(allowed.clone());
+    let module = unsafe { Module::deserialize(&permissive, &serialized)? };
+    Instance::new(&module, &imports! {})?;
+
+    allowed.multi_value(false);
+    let strict = store.with_allowed_features(allowed);
+    let module = unsafe { Module::deserialize(&strict, &serialized)? };
+    match Instance::new(&module, &imports! {}) {
+        Err(InstantiationError::Link(LinkError::DisallowedFeatures(features))) => {
+            assert_eq!(features, vec!["multi_value"])
+        }
+        _ => panic!("multi_value should be disallowed"),
+    }
+    Ok(())
+}
+
+#[test]
+fn instances_move_between_threads_between_calls() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[derive(WasmerEnv, Clone)]
+    struct Env {
+        calls: Arc<AtomicUsize>,
+    }
+
+    fn count(env: &Env, x: i32) -> i32 {
+        env.calls.fetch_add(1, Ordering::SeqCst);
+        x + 1
+    }
+
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"(module
+            (import "host" "count" (func $count (param i32) (result i32)))
+            (func (export "run") (param i32) (result i32)
+              (call $count (local.get 0))))"#,
+    )?;
+    let calls = Arc::new(AtomicUsize::new(0));
+    let env = Env {
+        calls: calls.clone(),
+    };
+    let import_object = imports! {
+        "host" => { "count" => Function::new_native_with_env(&store, env, count) },
+    };
+    let mut instance = Instance::new(&module, &import_object)?;
+
+    for i in 0..4 {
+        instance = thread::spawn(move || {
+            let run: NativeFunc<i32, i32> = instance.exports.get_native_function("run").unwrap();
+            assert_eq!(run.call(i).unwrap(), i + 1);
+            instance
+        })
+        .join()
+        .unwrap();
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    Ok(())
+}
+
+#[test]
+fn store_builder_checks_its_options() -> Result<()> {
+    let engine = Store::default().engine().clone();
+    let store = Store::builder()
+        .engine(&*engine)
+        .reentrancy_policy(ReentrancyPolicy::Deny)
+        .build()?;
+    assert!(Store::same(&store, &Store::new(&*engine)));
+    assert_eq!(store.engine().id(), engine.id());
+    assert_eq!(store.reentrancy_policy(), ReentrancyPolicy::Deny);
+
+    let mut features = Features::new();
+    features.reference_types = true;
+    features.bulk_memory = false;
+    let error = Store::builder()
+        .allowed_features(features)
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        error,
+        StoreBuildError::MissingFeatureDependency("reference_types", "bulk_memory")
+    );
+
+    let result = Store::builder().stack_budget(1 << 20).build();
+    if cfg!(unix) {
+        assert_eq!(result?.stack_budget(), Some(1 << 20));
+    } else {
+        assert_eq!(result.unwrap_err(), StoreBuildError::StackBudgetUnsupported);
+    }
+    Ok(())
+}
+
+#[test]
+fn instantiate_batch_creates_independent_instances() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        "
+    (module
+      (import \"host\" \"input\" (func $input (result i32)))
+      (global $counter (export \"counter\") (mut i32) (i32.const 0))
+      (func (export \"step\") (result i32)
+        global.get $counter
+        call $input
+        i32.add
+        global.set $counter
+        global.get $counter))
+",
+    )?;
+    let import_object = imports! {
+        "host" => {
+            "input" => Function::new_native(&store, || 10),
+        },
+    };
+
+    let instances = module.instantiate_batch(&import_object, 3)?;
+    assert_eq!(instances.len(), 3);
+    let first: NativeFunc<(), i32> = instances[0].exports.get_native_function("step")?;
+    assert_eq!(first.call()?, 10);
+    assert_eq!(first.call()?, 20);
+    for instance in &instances[1..] {
+        let step: NativeFunc<(), i32> = instance.exports.get_native_function("step")?;
+        assert_eq!(step.call()?, 10);
+    }
+
+    assert!(module.instantiate_batch(&imports! {}, 2).is_err());
+    assert!(module.instantiate_batch(&import_object, 0)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn store_scope_releases_its_instances() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, "(module (memory (export \"memory\") 1))")?;
+
+    let scope = store.scope();
+    assert!(scope.is_empty());
+    let first = scope.instantiate(&module, &imports! {})?;
+    let kept = scope.instantiate(&module, &imports! {})?;
+    // The earlier instances stay borrowed while the scope grows.
+    assert_eq!(first.exports.get_memory("memory")?.size(), Pages(1));
+    let memory = kept.exports.get_memory("memory")?.clone();
+    assert_eq!(scope.len(), 2);
+    // The memory export keeps its instance alive.
+    assert_eq!(scope.release(), 1);
+    assert_eq!(memory.size(), Pages(1));
+
+    let scope = store.scope();
+    scope.instantiate(&module, &imports! {})?;
+    assert_eq!(scope.release(), 0);
+
+    let other_store = Store::default();
+    assert!(other_store
+        .scope()
+        .instantiate(&module, &imports! {})
+        .is_err());
+    Ok(())
+}
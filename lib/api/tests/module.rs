@@ -246,3 +246,257 @@ fn calling_host_functions_with_negative_values_works() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn abi_validation_reports_all_violations() -> Result<()> {
+    use wasmer::abi::{self, AbiSpec, AbiViolation};
+
+    let store = Store::default();
+    let wat = r#"(module
+        (import "env" "db_read" (func (param i32) (result i32)))
+        (import "env" "debug" (func (param i32)))
+        (memory (export "memory") 1024)
+        (func (export "allocate") (param i64) (result i32) (i32.const 0)))"#;
+    let module = Module::new(&store, wat)?;
+
+    let mut spec = AbiSpec::new();
+    spec.require_export(
+        "memory",
+        ExternType::Memory(MemoryType::new(Pages(1), None, false)),
+    )
+    .require_function("allocate", &[Type::I32], &[Type::I32])
+    .require_function("instantiate", &[Type::I32], &[Type::I32])
+    .allow_import(
+        "env",
+        "db_read",
+        FunctionType::new(vec![Type::I32], vec![Type::I32]),
+    )
+    .max_memory_pages(Pages(512));
+
+    let i32_to_i32 = ExternType::Function(FunctionType::new(vec![Type::I32], vec![Type::I32]));
+    let report = abi::validate(&module, &spec);
+    assert!(!report.is_ok());
+    assert_eq!(
+        report.violations(),
+        &[
+            AbiViolation::ExportTypeMismatch {
+                name: "allocate".to_string(),
+                expected: i32_to_i32.clone(),
+                found: ExternType::Function(FunctionType::new(vec![Type::I64], vec![Type::I32])),
+            },
+            AbiViolation::MissingExport {
+                name: "instantiate".to_string(),
+                expected: i32_to_i32,
+            },
+            AbiViolation::ImportNotAllowed {
+                module: "env".to_string(),
+                name: "debug".to_string(),
+                ty: ExternType::Function(FunctionType::new(vec![Type::I32], vec![])),
+            },
+            AbiViolation::MemoryTooLarge {
+                index: 0,
+                minimum: Pages(1024),
+                max: Pages(512),
+            },
+        ][..]
+    );
+    Ok(())
+}
+
+#[test]
+fn analysis_finds_recursion_and_stack_usage() -> Result<()> {
+    use wasmer::analysis::{self, StackCostModel};
+
+    let wat = r#"(module
+        (type $unary (func (param i32) (result i32)))
+        (import "env" "log" (func $log (param i32)))
+        (table 2 funcref)
+        (elem (i32.const 0) $double $fact)
+        (func $double (type $unary) (i32.add (local.get 0) (local.get 0)))
+        (func $fact (type $unary)
+            (if (result i32) (i32.eqz (local.get 0))
+                (then (i32.const 1))
+                (else (i32.mul (local.get 0) (call $fact (i32.sub (local.get 0) (i32.const 1)))))))
+        (func $dispatch (param i32 i32) (result i32) (local i64)
+            (call $log (local.get 0))
+            (call_indirect (type $unary) (local.get 1) (local.get 0)))
+        (func (export "run") (result i32)
+            (call $double (i32.const 21))))"#;
+    let wasm = wat2wasm(wat.as_bytes())?;
+    let model = StackCostModel {
+        frame_overhead: 100,
+        bytes_per_local: 10,
+    };
+    let analysis = analysis::analyze_with_model(&wasm, &model)?;
+
+    assert_eq!(analysis.functions().len(), 5);
+    assert!(analysis.functions()[0].imported);
+    assert_eq!(analysis.callees(2), &[2]);
+    assert!(analysis.functions()[3].calls_indirect);
+    assert_eq!(analysis.callees(3), &[0, 1, 2]);
+    assert_eq!(analysis.recursion_cycles(), vec![vec![2]]);
+
+    assert_eq!(analysis.functions()[3].frame_size, 130);
+    assert_eq!(analysis.max_stack_usage(4), Some(100 + 110));
+    assert_eq!(analysis.max_stack_usage(2), None);
+    assert_eq!(analysis.max_stack_usage(3), None);
+    Ok(())
+}
+
+#[test]
+fn module_kind_detection() -> Result<()> {
+    let store = Store::default();
+    let kind =
+        |wat: &str| -> Result<ModuleKind> { Ok(ModuleKind::detect(&Module::new(&store, wat)?)) };
+
+    assert_eq!(
+        kind(r#"(module (func (export "add")))"#)?,
+        ModuleKind::Plain
+    );
+    assert_eq!(
+        kind(
+            r#"(module
+                (import "wasi_unstable" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+                (func (export "_start")))"#
+        )?,
+        ModuleKind::WasiCommand
+    );
+    assert_eq!(
+        kind(
+            r#"(module
+                (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                (func (export "_initialize")))"#
+        )?,
+        ModuleKind::WasiReactor
+    );
+    assert_eq!(
+        kind(
+            r#"(module
+                (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                (import "wasi" "thread-spawn" (func (param i32) (result i32))))"#
+        )?,
+        ModuleKind::Wasix
+    );
+    assert_eq!(
+        kind(
+            r#"(module
+                (import "env" "emscripten_memcpy_big" (func (param i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                (func (export "_start")))"#
+        )?,
+        ModuleKind::Emscripten
+    );
+    assert!(ModuleKind::WasiReactor.is_wasi());
+    assert!(!ModuleKind::Emscripten.is_wasi());
+    Ok(())
+}
+
+#[test]
+fn module_reflects_the_extended_names() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        "(module (func (param $amount i32) (local $total i64)))",
+    )?;
+    let locals = &module.names().locals[&FunctionIndex::from_u32(0)];
+    assert_eq!(locals[&0], "amount");
+    assert_eq!(locals[&1], "total");
+
+    #[rustfmt::skip]
+    let wasm = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // A memory of one page.
+        0x05, 0x03, 0x01, 0x00, 0x01,
+        // A global `i32` initialized to 0.
+        0x06, 0x06, 0x01, 0x7f, 0x00, 0x41, 0x00, 0x0b,
+        // The name section, with the memory names (6) and the global names (7).
+        0x00, 0x14, 0x04, b'n', b'a', b'm', b'e',
+        0x06, 0x07, 0x01, 0x00, 0x04, b'h', b'e', b'a', b'p',
+        0x07, 0x04, 0x01, 0x00, 0x01, b'g',
+    ];
+    let mut module = Module::new(&store, &wasm[..])?;
+    assert_eq!(module.names().memories[&MemoryIndex::from_u32(0)], "heap");
+    assert_eq!(module.names().globals[&GlobalIndex::from_u32(0)], "g");
+
+    let symbols = module
+        .strip_symbols()
+        .expect("the module is not instantiated");
+    assert!(module.names().globals.is_empty());
+    assert_eq!(symbols.names.memories[&MemoryIndex::from_u32(0)], "heap");
+    Ok(())
+}
+
+#[test]
+fn validation_errors_are_located() -> Result<()> {
+    let store = Store::default();
+    let wat = r#"(module
+        (func $ok)
+        (func $verify_round (result i32) (i64.const 1)))"#;
+    let error = Module::validate(&store, &wat2wasm(wat.as_bytes())?).unwrap_err();
+    let location = error.location().expect("the error is in a function");
+    assert_eq!(
+        location.local_function_index,
+        LocalFunctionIndex::from_u32(1)
+    );
+    assert_eq!(location.function_name.as_deref(), Some("verify_round"));
+    assert!(matches!(error.unlocated(), CompileError::Validate(_)));
+    assert!(error
+        .render()
+        .contains("\n  --> function `verify_round` (local function 1) at offset 0x"));
+    Ok(())
+}
+
+#[test]
+fn module_from_reader() -> Result<()> {
+    let store = Store::default();
+    let wat = r#"(module
+        (func (export "add") (param i32 i32) (result i32)
+           (i32.add (local.get 0) (local.get 1))))"#;
+    let binary = wat2wasm(wat.as_bytes())?;
+    let module = Module::from_reader(&store, &binary[..])?;
+    assert_eq!(module.exports().functions().count(), 1);
+
+    let truncated = Module::from_reader(&store, &binary[..binary.len() - 1]);
+    assert!(matches!(truncated, Err(IoCompileError::Compile(_))));
+    Ok(())
+}
+
+#[test]
+fn module_from_reader_rejects_malformed_modules_early() -> Result<()> {
+    /// A download failing after a malformed section.
+    struct Download(Option<&'static [u8]>);
+    impl std::io::Read for Download {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let data = self.0.take().ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::Other, "connection reset")
+            })?;
+            buf[..data.len()].copy_from_slice(data);
+            Ok(data.len())
+        }
+    }
+
+    let store = Store::default();
+    let download = Download(Some(b"\0asm\x01\0\0\0\x7f\x00"));
+    let error = Module::from_reader(&store, download).unwrap_err();
+    assert!(matches!(
+        error,
+        IoCompileError::Compile(CompileError::Validate(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn shared_module_is_bound_to_its_engine() -> Result<()> {
+    let store = Store::default();
+    let shared = Module::new(&store, "(module)")?.share();
+
+    let same_engine = Store::new(&**store.engine());
+    assert!(Store::same(&store, &same_engine));
+    let module = shared.to_module(&same_engine).expect("same engine");
+    Instance::new(&module, &imports! {})?;
+
+    let other_engine = Store::new(&*Store::default().engine().clone());
+    assert!(!Store::same(&store, &other_engine));
+    assert!(shared.to_module(&other_engine).is_none());
+    Ok(())
+}
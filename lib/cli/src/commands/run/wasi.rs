@@ -2,7 +2,7 @@ use crate::utils::{parse_envvar, parse_mapdir};
 use anyhow::{Context, Result};
 use std::path::PathBuf;
 use wasmer::{Instance, Module};
-use wasmer_wasi::{get_wasi_version, WasiError, WasiState, WasiVersion};
+use wasmer_wasi::{get_wasi_version, WasiCallOutcome, WasiState, WasiVersion};
 
 use structopt::StructOpt;
 
@@ -59,21 +59,15 @@ impl Wasi {
         let instance = Instance::new(&module, &import_object)?;
 
         let start = instance.exports.get_function("_start")?;
-        let result = start.call(&[]);
+        let result = wasi_env.call(start, &[]);
 
         match result {
-            Ok(_) => Ok(()),
-            Err(err) => {
-                let err: anyhow::Error = match err.downcast::<WasiError>() {
-                    Ok(WasiError::Exit(exit_code)) => {
-                        // We should exit with the provided exit code
-                        std::process::exit(exit_code as _);
-                    }
-                    Ok(err) => err.into(),
-                    Err(err) => err.into(),
-                };
-                Err(err)
+            Ok(WasiCallOutcome::Returned(_)) => Ok(()),
+            Ok(WasiCallOutcome::Exited(exit)) => {
+                // We should exit with the provided exit code
+                std::process::exit(exit.0 as _);
             }
+            Err(err) => Err(anyhow::Error::from(err)),
         }
         .with_context(|| "failed to run WASI `_start` function")
     }
@@ -10,8 +10,8 @@ use crate::trampoline::{
     make_trampoline_dynamic_function, make_trampoline_function_call, FunctionBuilderContext,
 };
 use crate::translator::{
-    compiled_function_unwind_info, signature_to_cranelift_ir, transform_jump_table,
-    CraneliftUnwindInfo, FuncTranslator,
+    compiled_function_unwind_info, is_unimplemented_operator, signature_to_cranelift_ir,
+    transform_jump_table, CraneliftUnwindInfo, FuncTranslator,
 };
 use cranelift_codegen::ir;
 use cranelift_codegen::print_errors::pretty_error;
@@ -20,8 +20,9 @@ use cranelift_codegen::{binemit, Context};
 use gimli::write::{Address, EhFrame, FrameTable};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use std::sync::Arc;
-use wasmer_compiler::CompileError;
-use wasmer_compiler::{CallingConvention, ModuleTranslationState, Target};
+use wasmer_compiler::wasmparser::Operator;
+use wasmer_compiler::{operator_name, CallingConvention, ModuleTranslationState, Target};
+use wasmer_compiler::{CodeHardening, CompileError, CpuFeature, EnumSet};
 use wasmer_compiler::{
     Compilation, CompileModuleInfo, CompiledFunction, CompiledFunctionFrameInfo,
     CompiledFunctionUnwindInfo, Compiler, Dwarf, FunctionBody, FunctionBodyData,
@@ -49,6 +50,25 @@ impl CraneliftCompiler {
 }
 
 impl Compiler for CraneliftCompiler {
+    fn code_hardening(&self) -> CodeHardening {
+        self.config.code_hardening
+    }
+
+    fn supports_call_counters(&self) -> bool {
+        true
+    }
+
+    fn feature_tiers(&self) -> Vec<EnumSet<CpuFeature>> {
+        self.config.feature_tiers.clone()
+    }
+
+    fn unsupported_operator(&self, operator: &Operator) -> Option<String> {
+        if !is_unimplemented_operator(operator) {
+            return None;
+        }
+        Some(format!("operator `{}`", operator_name(operator)))
+    }
+
     /// Compile the module using Cranelift, producing a compilation result with
     /// associated relocations.
     fn compile_module(
@@ -58,13 +78,15 @@ impl Compiler for CraneliftCompiler {
         module_translation_state: &ModuleTranslationState,
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
     ) -> Result<Compilation, CompileError> {
+        self.config().check_code_hardening()?;
         let isa = self.config().isa(target);
         let frontend_config = isa.frontend_config();
         let memory_styles = &compile_info.memory_styles;
         let table_styles = &compile_info.table_styles;
-        let mut module = (*compile_info.module).clone();
-        self.config.middlewares.apply_on_module_info(&mut module);
-        compile_info.module = Arc::new(module);
+        let count_function_calls = compile_info.count_function_calls;
+        self.config
+            .middlewares
+            .apply_on_compile_module_info(compile_info)?;
         let module = &compile_info.module;
         let signatures = module
             .signatures
@@ -110,6 +132,8 @@ impl Compiler for CraneliftCompiler {
                     &signatures,
                     &memory_styles,
                     &table_styles,
+                    self.config.code_hardening.spectre_table_index_masking,
+                    Some(*i).filter(|_| count_function_calls),
                 );
                 context.func.name = get_function_name(func_index);
                 context.func.signature = signatures[module.functions[func_index]].clone();
@@ -117,15 +141,20 @@ impl Compiler for CraneliftCompiler {
                 //     context.func.collect_debug_info();
                 // }
 
-                func_translator.translate(
-                    module_translation_state,
-                    input.data,
-                    input.module_offset,
-                    &mut context.func,
-                    &mut func_env,
-                    *i,
-                    &self.config,
-                )?;
+                let locate =
+                    |error: CompileError| error.in_function(module, *i, input.module_offset);
+                func_translator
+                    .translate(
+                        module,
+                        module_translation_state,
+                        input.data,
+                        input.module_offset,
+                        &mut context.func,
+                        &mut func_env,
+                        *i,
+                        &self.config,
+                    )
+                    .map_err(|error| locate(error.into()))?;
 
                 let mut code_buf: Vec<u8> = Vec::new();
                 let mut reloc_sink = RelocSink::new(&module, func_index);
@@ -140,7 +169,11 @@ impl Compiler for CraneliftCompiler {
                         &mut stackmap_sink,
                     )
                     .map_err(|error| {
-                        CompileError::Codegen(pretty_error(&context.func, Some(&*isa), error))
+                        locate(CompileError::Codegen(pretty_error(
+                            &context.func,
+                            Some(&*isa),
+                            error,
+                        )))
                     })?;
 
                 let unwind_info = match compiled_function_unwind_info(&*isa, &context)? {
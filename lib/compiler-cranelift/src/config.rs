@@ -3,7 +3,8 @@ use cranelift_codegen::isa::{lookup, TargetIsa};
 use cranelift_codegen::settings::{self, Configurable};
 use std::sync::Arc;
 use wasmer_compiler::{
-    Architecture, Compiler, CompilerConfig, CpuFeature, ModuleMiddleware, Target,
+    Architecture, CodeHardening, CompileError, Compiler, CompilerConfig, CpuFeature, EnumSet,
+    ModuleMiddleware, Target,
 };
 
 // Runtime Environment
@@ -34,8 +35,11 @@ pub struct Cranelift {
     enable_simd: bool,
     enable_pic: bool,
     opt_level: OptLevel,
+    pub(crate) code_hardening: CodeHardening,
     /// The middleware chain.
     pub(crate) middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    /// The CPU features of the variants of the compiled modules.
+    pub(crate) feature_tiers: Vec<EnumSet<CpuFeature>>,
 }
 
 impl Cranelift {
@@ -48,7 +52,12 @@ impl Cranelift {
             opt_level: OptLevel::Speed,
             enable_pic: false,
             enable_simd: true,
+            code_hardening: CodeHardening {
+                spectre_bounds_checks: true,
+                ..CodeHardening::none()
+            },
             middlewares: vec![],
+            feature_tiers: vec![],
         }
     }
 
@@ -73,6 +82,35 @@ impl Cranelift {
         self
     }
 
+    /// The hardening to apply to the generated code against
+    /// speculative execution attacks.
+    ///
+    /// The Spectre bounds checks are enabled by default. The other
+    /// hardenings against control-flow hijacking, and the retpolines, are
+    /// only available with LLVM.
+    pub fn code_hardening(&mut self, code_hardening: CodeHardening) -> &mut Self {
+        self.code_hardening = code_hardening;
+        self
+    }
+
+    /// Returns an error if the code hardening can't be applied by
+    /// Cranelift.
+    pub(crate) fn check_code_hardening(&self) -> Result<(), CompileError> {
+        let unsupported = if self.code_hardening.branch_target_identification {
+            "branch target identification"
+        } else if self.code_hardening.return_address_signing {
+            "return address signing"
+        } else if self.code_hardening.retpolines {
+            "retpolines"
+        } else {
+            return Ok(());
+        };
+        Err(CompileError::UnsupportedFeature(format!(
+            "{} with Cranelift",
+            unsupported
+        )))
+    }
+
     /// Generates the ISA for the provided target
     pub fn isa(&self, target: &Target) -> Box<dyn TargetIsa> {
         let mut builder =
@@ -180,6 +218,18 @@ impl Cranelift {
             .set("enable_nan_canonicalization", enable_nan_canonicalization)
             .expect("should be valid flag");
 
+        let enable_heap_access_spectre_mitigation = if self.code_hardening.spectre_bounds_checks {
+            "true"
+        } else {
+            "false"
+        };
+        flags
+            .set(
+                "enable_heap_access_spectre_mitigation",
+                enable_heap_access_spectre_mitigation,
+            )
+            .expect("should be valid flag");
+
         settings::Flags::new(flags)
     }
 }
@@ -202,6 +252,10 @@ impl CompilerConfig for Cranelift {
     fn push_middleware(&mut self, middleware: Arc<dyn ModuleMiddleware>) {
         self.middlewares.push(middleware);
     }
+
+    fn feature_tiers(&mut self, tiers: Vec<EnumSet<CpuFeature>>) {
+        self.feature_tiers = tiers;
+    }
 }
 
 impl Default for Cranelift {
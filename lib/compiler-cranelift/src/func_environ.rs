@@ -17,7 +17,9 @@ use wasmer_compiler::wasmparser::Type;
 use wasmer_compiler::{WasmError, WasmResult};
 use wasmer_types::entity::EntityRef;
 use wasmer_types::entity::PrimaryMap;
-use wasmer_types::{FunctionIndex, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex};
+use wasmer_types::{
+    FunctionIndex, GlobalIndex, LocalFunctionIndex, MemoryIndex, SignatureIndex, TableIndex,
+};
 use wasmer_vm::VMBuiltinFunctionIndex;
 use wasmer_vm::VMOffsets;
 use wasmer_vm::{MemoryStyle, ModuleInfo, TableStyle};
@@ -91,6 +93,14 @@ pub struct FuncEnvironment<'module_environment> {
 
     /// The table styles
     table_styles: &'module_environment PrimaryMap<TableIndex, TableStyle>,
+
+    /// Whether the table index of `call_indirect` is masked after its
+    /// bounds check.
+    spectre_table_index_masking: bool,
+
+    /// The function whose call counter is incremented on entry, if the
+    /// calls are counted.
+    call_counter: Option<LocalFunctionIndex>,
 }
 
 impl<'module_environment> FuncEnvironment<'module_environment> {
@@ -100,6 +110,8 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         signatures: &'module_environment PrimaryMap<SignatureIndex, ir::Signature>,
         memory_styles: &'module_environment PrimaryMap<MemoryIndex, MemoryStyle>,
         table_styles: &'module_environment PrimaryMap<TableIndex, TableStyle>,
+        spectre_table_index_masking: bool,
+        call_counter: Option<LocalFunctionIndex>,
     ) -> Self {
         Self {
             target_config,
@@ -118,6 +130,8 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             offsets: VMOffsets::new(target_config.pointer_bytes(), module),
             memory_styles,
             table_styles,
+            spectre_table_index_masking,
+            call_counter,
         }
     }
 
@@ -481,6 +495,32 @@ impl<'module_environment> BaseFuncEnvironment for FuncEnvironment<'module_enviro
         index >= 1
     }
 
+    fn translate_function_entry(&mut self, mut pos: FuncCursor) -> WasmResult<()> {
+        let index = match self.call_counter {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let pointer_type = self.pointer_type();
+        let vmctx = self.vmctx(&mut pos.func);
+        let base = pos.ins().global_value(pointer_type, vmctx);
+
+        let mut counters_mem_flags = ir::MemFlags::trusted();
+        counters_mem_flags.set_readonly();
+        let counters_offset = i32::try_from(self.offsets.vmctx_call_counters()).unwrap();
+        let counters = pos
+            .ins()
+            .load(pointer_type, counters_mem_flags, base, counters_offset);
+
+        // A plain (non-atomic) increment: the counters are approximate
+        // when the function is called concurrently.
+        let mem_flags = ir::MemFlags::trusted();
+        let counter_offset = i32::try_from(index.index() * 8).unwrap();
+        let count = pos.ins().load(I64, mem_flags, counters, counter_offset);
+        let count = pos.ins().iadd_imm(count, 1);
+        pos.ins().store(mem_flags, count, counters, counter_offset);
+        Ok(())
+    }
+
     fn make_table(&mut self, func: &mut ir::Function, index: TableIndex) -> WasmResult<ir::Table> {
         let pointer_type = self.pointer_type();
 
@@ -783,7 +823,7 @@ impl<'module_environment> BaseFuncEnvironment for FuncEnvironment<'module_enviro
 
     fn translate_call_indirect(
         &mut self,
-        mut pos: FuncCursor<'_>,
+        builder: &mut FunctionBuilder,
         table_index: TableIndex,
         table: ir::Table,
         sig_index: SignatureIndex,
@@ -793,54 +833,91 @@ impl<'module_environment> BaseFuncEnvironment for FuncEnvironment<'module_enviro
     ) -> WasmResult<ir::Inst> {
         let pointer_type = self.pointer_type();
 
-        let table_entry_addr = pos.ins().table_addr(pointer_type, table, callee, 0);
+        let callee = if self.spectre_table_index_masking {
+            // Zero the index when it's out of bounds, without branching,
+            // so that no function is selected speculatively past the
+            // bounds check of `table_addr`.
+            let index_type = builder.func.dfg.value_type(callee);
+            let bound_gv = builder.func.tables[table].bound_gv;
+            let bound = builder.ins().global_value(index_type, bound_gv);
+            let in_bounds = builder.ins().icmp(IntCC::UnsignedLessThan, callee, bound);
+            let in_bounds = builder.ins().bint(index_type, in_bounds);
+            let zero = builder.ins().iconst(index_type, 0);
+            let mask = builder.ins().isub(zero, in_bounds);
+            builder.ins().band(callee, mask)
+        } else {
+            callee
+        };
+        let table_entry_addr = builder.ins().table_addr(pointer_type, table, callee, 0);
 
-        // Dereference table_entry_addr to get the function address.
         let mem_flags = ir::MemFlags::trusted();
-        let func_addr = pos.ins().load(
-            pointer_type,
-            mem_flags,
-            table_entry_addr,
-            i32::from(self.offsets.vmcaller_checked_anyfunc_func_ptr()),
-        );
-
-        // Check whether `func_addr` is null.
-        pos.ins().trapz(func_addr, ir::TrapCode::IndirectCallToNull);
+        let func_ptr_offset = i32::from(self.offsets.vmcaller_checked_anyfunc_func_ptr());
 
-        // If necessary, check the signature.
+        // Check the signature. The uninitialized elements have the reserved
+        // signature index, which matches no caller ID: the element being null
+        // only needs to be checked to report the right trap once the signature
+        // check failed, out of the path of the call.
         match self.table_styles[table_index] {
             TableStyle::CallerChecksSignature => {
                 let sig_id_size = self.offsets.size_of_vmshared_signature_index();
                 let sig_id_type = ir::Type::int(u16::from(sig_id_size) * 8).unwrap();
-                let vmctx = self.vmctx(pos.func);
-                let base = pos.ins().global_value(pointer_type, vmctx);
+                let vmctx = self.vmctx(builder.func);
+                let base = builder.ins().global_value(pointer_type, vmctx);
                 let offset =
                     i32::try_from(self.offsets.vmctx_vmshared_signature_id(sig_index)).unwrap();
 
                 // Load the caller ID.
-                let mut mem_flags = ir::MemFlags::trusted();
-                mem_flags.set_readonly();
-                let caller_sig_id = pos.ins().load(sig_id_type, mem_flags, base, offset);
+                let mut caller_mem_flags = ir::MemFlags::trusted();
+                caller_mem_flags.set_readonly();
+                let caller_sig_id = builder
+                    .ins()
+                    .load(sig_id_type, caller_mem_flags, base, offset);
 
                 // Load the callee ID.
-                let mem_flags = ir::MemFlags::trusted();
-                let callee_sig_id = pos.ins().load(
+                let callee_sig_id = builder.ins().load(
                     sig_id_type,
                     mem_flags,
                     table_entry_addr,
                     i32::from(self.offsets.vmcaller_checked_anyfunc_type_index()),
                 );
 
-                // Check that they match.
-                let cmp = pos.ins().icmp(IntCC::Equal, callee_sig_id, caller_sig_id);
-                pos.ins().trapz(cmp, ir::TrapCode::BadSignature);
+                // Check that they match. The mismatch block is filled first,
+                // so it's laid out right after the check and the jump to it
+                // is elided.
+                let cmp = builder
+                    .ins()
+                    .icmp(IntCC::Equal, callee_sig_id, caller_sig_id);
+                let call_block = builder.create_block();
+                let mismatch_block = builder.create_block();
+                builder.ins().brnz(cmp, call_block, &[]);
+                builder.ins().jump(mismatch_block, &[]);
+
+                builder.switch_to_block(mismatch_block);
+                builder.seal_block(mismatch_block);
+                let func_addr =
+                    builder
+                        .ins()
+                        .load(pointer_type, mem_flags, table_entry_addr, func_ptr_offset);
+                builder
+                    .ins()
+                    .trapz(func_addr, ir::TrapCode::IndirectCallToNull);
+                builder.ins().trap(ir::TrapCode::BadSignature);
+
+                builder.switch_to_block(call_block);
+                builder.seal_block(call_block);
             }
         }
 
+        // Dereference table_entry_addr to get the function address.
+        let func_addr =
+            builder
+                .ins()
+                .load(pointer_type, mem_flags, table_entry_addr, func_ptr_offset);
+
         let mut real_call_args = Vec::with_capacity(call_args.len() + 2);
 
         // First append the callee vmctx address.
-        let vmctx = pos.ins().load(
+        let vmctx = builder.ins().load(
             pointer_type,
             mem_flags,
             table_entry_addr,
@@ -851,7 +928,9 @@ impl<'module_environment> BaseFuncEnvironment for FuncEnvironment<'module_enviro
         // Then append the regular call arguments.
         real_call_args.extend_from_slice(call_args);
 
-        Ok(pos.ins().call_indirect(sig_ref, func_addr, &real_call_args))
+        Ok(builder
+            .ins()
+            .call_indirect(sig_ref, func_addr, &real_call_args))
     }
 
     fn translate_call(
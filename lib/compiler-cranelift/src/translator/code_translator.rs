@@ -49,6 +49,25 @@ use wasmer_compiler::{to_wasm_error, WasmResult};
 use wasmer_compiler::{wasm_unsupported, ModuleTranslationState};
 use wasmer_types::{FunctionIndex, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex};
 
+/// Returns whether [`translate_operator`] can't translate `op` yet.
+pub fn is_unimplemented_operator(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::I8x16Bitmask
+            | Operator::I16x8Bitmask
+            | Operator::I32x4Bitmask
+            | Operator::F32x4PMax
+            | Operator::F64x2PMax
+            | Operator::F32x4PMin
+            | Operator::F64x2PMin
+            | Operator::I32x4DotI16x8S
+            | Operator::V128Load32Zero { .. }
+            | Operator::V128Load64Zero { .. }
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. }
+    )
+}
+
 // Clippy warns about "align: _" but its important to document that the align field is ignored
 #[cfg_attr(
     feature = "cargo-clippy",
@@ -541,7 +560,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             bitcast_arguments(args, &types, builder);
 
             let call = environ.translate_call_indirect(
-                builder.cursor(),
+                builder,
                 TableIndex::from_u32(*table_index),
                 table,
                 SignatureIndex::from_u32(*index),
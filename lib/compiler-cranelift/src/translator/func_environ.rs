@@ -155,11 +155,12 @@ pub trait FuncEnvironment: TargetEnvironment {
         index: FunctionIndex,
     ) -> WasmResult<ir::FuncRef>;
 
-    /// Translate a `call_indirect` WebAssembly instruction at `pos`.
+    /// Translate a `call_indirect` WebAssembly instruction.
     ///
-    /// Insert instructions at `pos` for an indirect call to the function `callee` in the table
-    /// `table_index` with WebAssembly signature `sig_index`. The `callee` value will have type
-    /// `i32`.
+    /// Insert instructions with `builder` for an indirect call to the function `callee` in the
+    /// table `table_index` with WebAssembly signature `sig_index`. The `callee` value will have
+    /// type `i32`. The checks may end the current block, but the call is inserted in the block
+    /// `builder` is left in.
     ///
     /// The signature `sig_ref` was previously created by `make_indirect_sig()`.
     ///
@@ -167,7 +168,7 @@ pub trait FuncEnvironment: TargetEnvironment {
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
     fn translate_call_indirect(
         &mut self,
-        pos: FuncCursor,
+        builder: &mut FunctionBuilder,
         table_index: TableIndex,
         table: ir::Table,
         sig_index: SignatureIndex,
@@ -437,6 +438,13 @@ pub trait FuncEnvironment: TargetEnvironment {
         count: ir::Value,
     ) -> WasmResult<ir::Value>;
 
+    /// Emit code at the entry of the function, after its locals are
+    /// declared.
+    fn translate_function_entry(&mut self, _pos: FuncCursor) -> WasmResult<()> {
+        // By default, don't emit anything.
+        Ok(())
+    }
+
     /// Emit code at the beginning of every wasm loop.
     ///
     /// This can be used to insert explicit interrupt or safepoint checking at
@@ -23,6 +23,7 @@ use wasmer_compiler::{
     ModuleTranslationState, WasmResult,
 };
 use wasmer_types::LocalFunctionIndex;
+use wasmer_vm::ModuleInfo;
 
 /// WebAssembly to Cranelift IR function translator.
 ///
@@ -63,6 +64,7 @@ impl FuncTranslator {
     ///
     pub fn translate<FE: FuncEnvironment + ?Sized>(
         &mut self,
+        module: &ModuleInfo,
         module_translation_state: &ModuleTranslationState,
         code: &[u8],
         code_offset: usize,
@@ -75,7 +77,7 @@ impl FuncTranslator {
         reader.set_middleware_chain(
             config
                 .middlewares
-                .generate_function_middleware_chain(local_function_index),
+                .generate_function_middleware_chain(module, local_function_index)?,
         );
         self.translate_from_reader(module_translation_state, reader, func, environ)
     }
@@ -119,6 +121,7 @@ impl FuncTranslator {
         self.state.initialize(&builder.func.signature, exit_block);
 
         parse_local_decls(&mut reader, &mut builder, num_params, environ)?;
+        environ.translate_function_entry(builder.cursor())?;
         parse_function_body(
             module_translation_state,
             reader,
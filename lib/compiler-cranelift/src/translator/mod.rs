@@ -7,6 +7,7 @@ mod func_translator;
 mod translation_utils;
 mod unwind;
 
+pub use self::code_translator::is_unimplemented_operator;
 pub use self::func_environ::{FuncEnvironment, GlobalVariable, ReturnMode, TargetEnvironment};
 pub use self::func_state::FuncTranslationState;
 pub use self::func_translator::FuncTranslator;
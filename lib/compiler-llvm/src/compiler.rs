@@ -8,11 +8,11 @@ use inkwell::module::{Linkage, Module};
 use inkwell::targets::FileType;
 use inkwell::DLLStorageClass;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-use std::sync::Arc;
 use wasmer_compiler::{
-    Compilation, CompileError, CompileModuleInfo, Compiler, CustomSection, CustomSectionProtection,
-    Dwarf, FunctionBodyData, ModuleMiddlewareChain, ModuleTranslationState, RelocationTarget,
-    SectionBody, SectionIndex, Symbol, SymbolRegistry, Target,
+    CodeHardening, Compilation, CompileError, CompileModuleInfo, Compiler, CpuFeature,
+    CustomSection, CustomSectionProtection, Dwarf, EnumSet, FunctionBodyData,
+    ModuleMiddlewareChain, ModuleTranslationState, RelocationTarget, SectionBody, SectionIndex,
+    Symbol, SymbolRegistry, Target,
 };
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{FunctionIndex, LocalFunctionIndex, SignatureIndex};
@@ -82,6 +82,7 @@ impl LLVMCompiler {
         symbol_registry: &dyn SymbolRegistry,
         wasmer_metadata: &[u8],
     ) -> Result<Vec<u8>, CompileError> {
+        self.config().check_code_hardening(target)?;
         let target_machine = self.config().target_machine(target);
         let ctx = Context::create();
         let merged_module = ctx.create_module("");
@@ -206,6 +207,14 @@ impl LLVMCompiler {
 }
 
 impl Compiler for LLVMCompiler {
+    fn code_hardening(&self) -> CodeHardening {
+        self.config.code_hardening
+    }
+
+    fn feature_tiers(&self) -> Vec<EnumSet<CpuFeature>> {
+        self.config.feature_tiers.clone()
+    }
+
     fn experimental_native_compile_module<'data, 'module>(
         &self,
         target: &Target,
@@ -217,9 +226,13 @@ impl Compiler for LLVMCompiler {
         // The metadata to inject into the wasmer_metadata section of the object file.
         wasmer_metadata: &[u8],
     ) -> Option<Result<Vec<u8>, CompileError>> {
-        let mut module = (*compile_info.module).clone();
-        self.config.middlewares.apply_on_module_info(&mut module);
-        compile_info.module = Arc::new(module);
+        if let Err(error) = self
+            .config
+            .middlewares
+            .apply_on_compile_module_info(compile_info)
+        {
+            return Some(Err(error.into()));
+        }
 
         Some(self.compile_native_object(
             target,
@@ -240,13 +253,14 @@ impl Compiler for LLVMCompiler {
         module_translation: &ModuleTranslationState,
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'data>>,
     ) -> Result<Compilation, CompileError> {
+        self.config().check_code_hardening(target)?;
         //let data = Arc::new(Mutex::new(0));
         let memory_styles = &compile_info.memory_styles;
         let table_styles = &compile_info.table_styles;
 
-        let mut module = (*compile_info.module).clone();
-        self.config.middlewares.apply_on_module_info(&mut module);
-        compile_info.module = Arc::new(module);
+        self.config
+            .middlewares
+            .apply_on_compile_module_info(compile_info)?;
         let module = &compile_info.module;
 
         // TODO: merge constants in sections.
@@ -266,16 +280,18 @@ impl Compiler for LLVMCompiler {
                 |func_translator, (i, input)| {
                     // TODO: remove (to serialize)
                     //let _data = data.lock().unwrap();
-                    func_translator.translate(
-                        module,
-                        module_translation,
-                        i,
-                        input,
-                        self.config(),
-                        memory_styles,
-                        &table_styles,
-                        &ShortNames {},
-                    )
+                    func_translator
+                        .translate(
+                            module,
+                            module_translation,
+                            i,
+                            input,
+                            self.config(),
+                            memory_styles,
+                            &table_styles,
+                            &ShortNames {},
+                        )
+                        .map_err(|error| error.in_function(module, *i, input.module_offset))
                 },
             )
             .collect::<Result<Vec<_>, CompileError>>()?
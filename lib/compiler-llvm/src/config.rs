@@ -1,14 +1,21 @@
 use crate::compiler::LLVMCompiler;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::context::Context;
+use inkwell::module::FlagBehavior;
 use inkwell::targets::{
     CodeModel, InitializationConfig, RelocMode, Target as InkwellTarget, TargetMachine,
     TargetTriple,
 };
+use inkwell::values::FunctionValue;
 use inkwell::OptimizationLevel;
 use itertools::Itertools;
 use std::fmt::Debug;
 use std::sync::Arc;
 use target_lexicon::Architecture;
-use wasmer_compiler::{Compiler, CompilerConfig, ModuleMiddleware, Target, Triple};
+use wasmer_compiler::{
+    CodeHardening, CompileError, Compiler, CompilerConfig, CpuFeature, EnumSet, ModuleMiddleware,
+    Target, Triple,
+};
 use wasmer_types::{FunctionType, LocalFunctionIndex};
 
 /// The InkWell ModuleInfo type
@@ -43,9 +50,12 @@ pub struct LLVM {
     pub(crate) enable_verifier: bool,
     pub(crate) opt_level: OptimizationLevel,
     is_pic: bool,
+    pub(crate) code_hardening: CodeHardening,
     pub(crate) callbacks: Option<Arc<dyn LLVMCallbacks>>,
     /// The middleware chain.
     pub(crate) middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    /// The CPU features of the variants of the compiled modules.
+    pub(crate) feature_tiers: Vec<EnumSet<CpuFeature>>,
 }
 
 impl LLVM {
@@ -57,8 +67,10 @@ impl LLVM {
             enable_verifier: false,
             opt_level: OptimizationLevel::Aggressive,
             is_pic: false,
+            code_hardening: CodeHardening::none(),
             callbacks: None,
             middlewares: vec![],
+            feature_tiers: vec![],
         }
     }
 
@@ -77,6 +89,18 @@ impl LLVM {
         self
     }
 
+    /// The hardening to apply to the generated code against
+    /// control-flow hijacking and speculative execution attacks.
+    ///
+    /// The engines refuse to load the artifacts compiled without the
+    /// hardening of their compiler against control-flow hijacking, see
+    /// [`CodeHardening`]. Return address signing is only
+    /// available on aarch64, and retpolines on x86_64.
+    pub fn code_hardening(&mut self, code_hardening: CodeHardening) -> &mut Self {
+        self.code_hardening = code_hardening;
+        self
+    }
+
     /// Callbacks that will triggered in the different compilation
     /// phases in LLVM.
     pub fn callbacks(&mut self, callbacks: Option<Arc<dyn LLVMCallbacks>>) -> &mut Self {
@@ -84,6 +108,99 @@ impl LLVM {
         self
     }
 
+    /// Checks that the code hardening is available on `target`.
+    pub(crate) fn check_code_hardening(&self, target: &Target) -> Result<(), CompileError> {
+        let architecture = target.triple().architecture;
+        let is_aarch64 = matches!(architecture, Architecture::Aarch64(_));
+        let unsupported = if self.code_hardening.return_address_signing && !is_aarch64 {
+            "return address signing"
+        } else if self.code_hardening.retpolines && architecture != Architecture::X86_64 {
+            "retpolines"
+        } else {
+            return Ok(());
+        };
+        Err(CompileError::UnsupportedFeature(format!(
+            "{} on {}",
+            unsupported, architecture
+        )))
+    }
+
+    /// Applies the code hardening to `function`, generated by
+    /// `target_machine`.
+    pub(crate) fn harden_function<'ctx>(
+        &self,
+        context: &'ctx Context,
+        module: &InkwellModule<'ctx>,
+        function: FunctionValue<'ctx>,
+        target_machine: &TargetMachine,
+    ) {
+        let is_aarch64 = target_machine
+            .get_triple()
+            .as_str()
+            .to_string_lossy()
+            .starts_with("aarch64");
+        if self.code_hardening.branch_target_identification {
+            if is_aarch64 {
+                function.add_attribute(
+                    AttributeLoc::Function,
+                    context.create_string_attribute("branch-target-enforcement", "true"),
+                );
+                // The objects of the modules flagged for BTI carry the
+                // GNU property note with which the dynamic loader maps the
+                // shared objects of the native engine with `PROT_BTI`.
+                module.add_basic_value_flag(
+                    "branch-target-enforcement",
+                    FlagBehavior::Override,
+                    context.i32_type().const_int(1, false),
+                );
+            } else {
+                // LLVM only emits `endbr64` in the modules flagged for IBT.
+                module.add_basic_value_flag(
+                    "cf-protection-branch",
+                    FlagBehavior::Override,
+                    context.i32_type().const_int(1, false),
+                );
+            }
+        }
+        if self.code_hardening.return_address_signing {
+            function.add_attribute(
+                AttributeLoc::Function,
+                context.create_string_attribute("sign-return-address", "non-leaf"),
+            );
+            function.add_attribute(
+                AttributeLoc::Function,
+                context.create_string_attribute("sign-return-address-key", "a_key"),
+            );
+        }
+        if self.code_hardening.spectre_bounds_checks
+            || self.code_hardening.spectre_table_index_masking
+        {
+            function.add_attribute(
+                AttributeLoc::Function,
+                context.create_enum_attribute(
+                    Attribute::get_named_enum_kind_id("speculative_load_hardening"),
+                    0,
+                ),
+            );
+        }
+        if self.code_hardening.retpolines {
+            // The features of the function replace the ones of the target
+            // machine.
+            let mut features = target_machine
+                .get_feature_string()
+                .to_string_lossy()
+                .into_owned();
+            if !features.is_empty() {
+                features.push(',');
+            }
+            features.push_str("+retpoline-indirect-calls,+retpoline-indirect-branches");
+            function.add_attribute(
+                AttributeLoc::Function,
+                context.create_string_attribute("target-features", &features),
+            );
+        }
+    }
+
     fn reloc_mode(&self) -> RelocMode {
         if self.is_pic {
             RelocMode::PIC
@@ -215,6 +332,10 @@ impl CompilerConfig for LLVM {
     fn push_middleware(&mut self, middleware: Arc<dyn ModuleMiddleware>) {
         self.middlewares.push(middleware);
     }
+
+    fn feature_tiers(&mut self, tiers: Vec<EnumSet<CpuFeature>>) {
+        self.feature_tiers = tiers;
+    }
 }
 
 impl Default for LLVM {
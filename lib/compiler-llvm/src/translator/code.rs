@@ -115,6 +115,7 @@ impl FuncTranslator {
         // TODO: figure out how many bytes long vmctx is, and mark it dereferenceable. (no need to mark it nonnull once we do this.)
         // TODO: mark vmctx nofree
         func.add_attribute(AttributeLoc::Function, intrinsics.stack_probe);
+        config.harden_function(&self.ctx, &module, func, target_machine);
         func.set_personality_function(intrinsics.personality);
         func.as_global_value().set_section(FUNCTION_SECTION);
         func.set_linkage(Linkage::DLLExport);
@@ -150,7 +151,7 @@ impl FuncTranslator {
         reader.set_middleware_chain(
             config
                 .middlewares
-                .generate_function_middleware_chain(*local_func_index),
+                .generate_function_middleware_chain(wasm_module, *local_func_index)?,
         );
 
         let mut params = vec![];
@@ -957,6 +958,25 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
         Ok(())
     }
 
+    /// Makes an access to an imported mutable global atomic. The global
+    /// may be shared with other instances and set by the host while this
+    /// one runs, so its accesses mustn't be merged or hoisted out of loops.
+    fn mark_global_access_shared(&self, global_index: GlobalIndex, access: InstructionValue<'ctx>) {
+        if !self.wasm_module.is_imported_global(global_index) {
+            return;
+        }
+        let alignment = match self.wasm_module.globals[global_index].ty {
+            Type::I32 | Type::F32 => 4,
+            Type::I64 | Type::F64 => 8,
+            // LLVM has no atomic vector accesses.
+            _ => return,
+        };
+        access.set_alignment(alignment).unwrap();
+        access
+            .set_atomic_ordering(AtomicOrdering::Monotonic)
+            .unwrap();
+    }
+
     fn annotate_user_memaccess(
         &mut self,
         memory_index: MemoryIndex,
@@ -2002,12 +2022,14 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
                     }
                     GlobalCache::Mut { ptr_to_value } => {
                         let value = self.builder.build_load(*ptr_to_value, "");
+                        let load = value.as_instruction_value().unwrap();
                         tbaa_label(
                             self.module,
                             self.intrinsics,
                             format!("global {}", global_index.as_u32()),
-                            value.as_instruction_value().unwrap(),
+                            load,
                         );
+                        self.mark_global_access_shared(global_index, load);
                         self.state.push1(value);
                     }
                 }
@@ -2035,6 +2057,7 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
                             format!("global {}", global_index.as_u32()),
                             store,
                         );
+                        self.mark_global_access_shared(global_index, store);
                     }
                 }
             }
@@ -2297,11 +2320,10 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
                 self.builder.build_unreachable();
                 self.builder.position_at_end(in_bounds_continue_block);
 
-                // Next, check if the table element is initialized.
-
-                let elem_initialized = self.builder.build_is_not_null(func_ptr, "");
-
-                // Next, check if the signature id is correct.
+                // Next, check if the signature id is correct. The uninitialized
+                // elements have the reserved signature id, which matches no
+                // expected one: whether the element is initialized is only
+                // checked to report the right trap.
 
                 let sigindices_equal = self.builder.build_int_compare(
                     IntPredicate::EQ,
@@ -2310,23 +2332,19 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
                     "sigindices_equal",
                 );
 
-                let initialized_and_sigindices_match =
-                    self.builder
-                        .build_and(elem_initialized, sigindices_equal, "");
-
                 // Tell llvm that `expected_dynamic_sigindex` should equal `found_dynamic_sigindex`.
-                let initialized_and_sigindices_match = self
+                let sigindices_equal = self
                     .builder
                     .build_call(
                         self.intrinsics.expect_i1,
                         &[
-                            initialized_and_sigindices_match.as_basic_value_enum(),
+                            sigindices_equal.as_basic_value_enum(),
                             self.intrinsics
                                 .i1_ty
                                 .const_int(1, false)
                                 .as_basic_value_enum(),
                         ],
-                        "initialized_and_sigindices_match_expect",
+                        "sigindices_equal_expect",
                     )
                     .try_as_basic_value()
                     .left()
@@ -2340,12 +2358,13 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
                     .context
                     .append_basic_block(self.function, "sigindices_notequal_block");
                 self.builder.build_conditional_branch(
-                    initialized_and_sigindices_match,
+                    sigindices_equal,
                     continue_block,
                     sigindices_notequal_block,
                 );
 
                 self.builder.position_at_end(sigindices_notequal_block);
+                let elem_initialized = self.builder.build_is_not_null(func_ptr, "");
                 let trap_code = self.builder.build_select(
                     elem_initialized,
                     self.intrinsics.trap_call_indirect_sig,
@@ -250,6 +250,51 @@ struct I2O1 {
     ret: Location,
 }
 
+/// Calls `$callback!($arg; ...)` with the names of the operators implemented by
+/// [`FuncGen::feed_operator`], the only ones it accepts. Each of them has an arm in its
+/// `match`, which the test `singlepass_operators_have_an_arm` checks.
+macro_rules! singlepass_operators {
+    ($callback:ident, $arg:expr) => {
+        $callback!($arg;
+            AtomicFence Block Br BrIf BrTable Call CallIndirect Drop Else End F32Abs F32Add F32Ceil
+            F32Const F32ConvertI32S F32ConvertI32U F32ConvertI64S F32ConvertI64U F32Copysign
+            F32DemoteF64 F32Div F32Eq F32Floor F32Ge F32Gt F32Le F32Load F32Lt F32Max F32Min F32Mul
+            F32Ne F32Nearest F32Neg F32ReinterpretI32 F32Sqrt F32Store F32Sub F32Trunc F64Abs F64Add
+            F64Ceil F64Const F64ConvertI32S F64ConvertI32U F64ConvertI64S F64ConvertI64U F64Copysign
+            F64Div F64Eq F64Floor F64Ge F64Gt F64Le F64Load F64Lt F64Max F64Min F64Mul F64Ne
+            F64Nearest F64Neg F64PromoteF32 F64ReinterpretI64 F64Sqrt F64Store F64Sub F64Trunc
+            GlobalGet GlobalSet I32Add I32And I32AtomicLoad I32AtomicLoad16U I32AtomicLoad8U
+            I32AtomicRmw16AddU I32AtomicRmw16AndU I32AtomicRmw16CmpxchgU I32AtomicRmw16OrU
+            I32AtomicRmw16SubU I32AtomicRmw16XchgU I32AtomicRmw16XorU I32AtomicRmw8AddU
+            I32AtomicRmw8AndU I32AtomicRmw8CmpxchgU I32AtomicRmw8OrU I32AtomicRmw8SubU
+            I32AtomicRmw8XchgU I32AtomicRmw8XorU I32AtomicRmwAdd I32AtomicRmwAnd I32AtomicRmwCmpxchg
+            I32AtomicRmwOr I32AtomicRmwSub I32AtomicRmwXchg I32AtomicRmwXor I32AtomicStore
+            I32AtomicStore16 I32AtomicStore8 I32Clz I32Const I32Ctz I32DivS I32DivU I32Eq I32Eqz
+            I32Extend16S I32Extend8S I32GeS I32GeU I32GtS I32GtU I32LeS I32LeU I32Load I32Load16S
+            I32Load16U I32Load8S I32Load8U I32LtS I32LtU I32Mul I32Ne I32Or I32Popcnt
+            I32ReinterpretF32 I32RemS I32RemU I32Rotl I32Rotr I32Shl I32ShrS I32ShrU I32Store
+            I32Store16 I32Store8 I32Sub I32TruncF32S I32TruncF32U I32TruncF64S I32TruncF64U
+            I32TruncSatF32S I32TruncSatF32U I32TruncSatF64S I32TruncSatF64U I32WrapI64 I32Xor I64Add
+            I64And I64AtomicLoad I64AtomicLoad16U I64AtomicLoad32U I64AtomicLoad8U
+            I64AtomicRmw16AddU I64AtomicRmw16AndU I64AtomicRmw16CmpxchgU I64AtomicRmw16OrU
+            I64AtomicRmw16SubU I64AtomicRmw16XchgU I64AtomicRmw16XorU I64AtomicRmw32AddU
+            I64AtomicRmw32AndU I64AtomicRmw32CmpxchgU I64AtomicRmw32OrU I64AtomicRmw32SubU
+            I64AtomicRmw32XchgU I64AtomicRmw32XorU I64AtomicRmw8AddU I64AtomicRmw8AndU
+            I64AtomicRmw8CmpxchgU I64AtomicRmw8OrU I64AtomicRmw8SubU I64AtomicRmw8XchgU
+            I64AtomicRmw8XorU I64AtomicRmwAdd I64AtomicRmwAnd I64AtomicRmwCmpxchg I64AtomicRmwOr
+            I64AtomicRmwSub I64AtomicRmwXchg I64AtomicRmwXor I64AtomicStore I64AtomicStore16
+            I64AtomicStore32 I64AtomicStore8 I64Clz I64Const I64Ctz I64DivS I64DivU I64Eq I64Eqz
+            I64Extend16S I64Extend32S I64Extend8S I64ExtendI32S I64ExtendI32U I64GeS I64GeU I64GtS
+            I64GtU I64LeS I64LeU I64Load I64Load16S I64Load16U I64Load32S I64Load32U I64Load8S
+            I64Load8U I64LtS I64LtU I64Mul I64Ne I64Or I64Popcnt I64ReinterpretF64 I64RemS I64RemU
+            I64Rotl I64Rotr I64Shl I64ShrS I64ShrU I64Store I64Store16 I64Store32 I64Store8 I64Sub
+            I64TruncF32S I64TruncF32U I64TruncF64S I64TruncF64U I64TruncSatF32S I64TruncSatF32U
+            I64TruncSatF64S I64TruncSatF64U I64Xor If LocalGet LocalSet LocalTee Loop MemoryGrow
+            MemorySize Nop Return Select Unreachable
+        )
+    };
+}
+
 impl<'a> FuncGen<'a> {
     fn get_location_released(&mut self, loc: Location) -> Location {
         self.machine.release_locations(&mut self.assembler, &[loc]);
@@ -1828,6 +1873,17 @@ impl<'a> FuncGen<'a> {
         !self.control_stack.is_empty()
     }
 
+    /// Returns whether [`FuncGen::feed_operator`] implements `op`: whether it is
+    /// one of the `singlepass_operators`.
+    pub fn supports_operator(op: &Operator) -> bool {
+        macro_rules! is_one_of {
+            ($op:expr; $($name:ident)*) => {
+                matches!($op, $(Operator::$name { .. })|*)
+            };
+        }
+        singlepass_operators!(is_one_of, op)
+    }
+
     pub fn feed_operator(&mut self, op: Operator) -> Result<(), CodegenError> {
         assert!(self.fp_stack.len() <= self.value_stack.len());
 
@@ -1865,6 +1921,11 @@ impl<'a> FuncGen<'a> {
             was_unreachable = false;
         }
 
+        if !Self::supports_operator(&op) {
+            return Err(CodegenError {
+                message: format!("not yet implemented: {:?}", op),
+            });
+        }
         match op {
             Operator::GlobalGet { global_index } => {
                 let global_index = GlobalIndex::from_u32(global_index);
@@ -8582,3 +8643,30 @@ const LEF64_GT_U32_MAX: f64 = 4294967296.0;
 const GEF64_LT_U64_MIN: f64 = -1.0;
 /// Least Exact Float (64 bits) greater-than u64::MAX when rounding towards zero.
 const LEF64_GT_U64_MAX: f64 = 18446744073709551616.0;
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn singlepass_operators_have_an_arm() {
+        macro_rules! names {
+            ($arg:expr; $($name:ident)*) => {
+                vec![$(stringify!($name)),*]
+            };
+        }
+        let mut supported: Vec<&str> = singlepass_operators!(names, ());
+        supported.sort();
+
+        // The arms of the `match` of `feed_operator`, one operator per arm.
+        let source = include_str!("codegen_x64.rs");
+        let feed_operator = &source[source.find("    pub fn feed_operator(").unwrap()..];
+        let start = feed_operator.find("\n        match op {\n").unwrap();
+        let end = feed_operator.find("\n    pub fn finalize(").unwrap();
+        let mut arms = feed_operator[start..end]
+            .lines()
+            .filter_map(|line| line.strip_prefix("            Operator::"))
+            .map(|arm| arm.split(|c: char| !c.is_alphanumeric()).next().unwrap())
+            .collect::<Vec<_>>();
+        arms.sort();
+        assert_eq!(arms, supported);
+    }
+}
@@ -8,9 +8,8 @@ use crate::codegen_x64::{
 };
 use crate::config::Singlepass;
 use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
-use std::sync::Arc;
-use wasmer_compiler::wasmparser::BinaryReaderError;
-use wasmer_compiler::TrapInformation;
+use wasmer_compiler::wasmparser::{BinaryReaderError, Operator};
+use wasmer_compiler::{operator_name, TrapInformation};
 use wasmer_compiler::{Compilation, CompileError, CompiledFunction, Compiler, SectionIndex};
 use wasmer_compiler::{
     CompileModuleInfo, CompilerConfig, MiddlewareBinaryReader, ModuleMiddlewareChain,
@@ -18,7 +17,9 @@ use wasmer_compiler::{
 };
 use wasmer_compiler::{FunctionBody, FunctionBodyData};
 use wasmer_types::entity::{EntityRef, PrimaryMap};
-use wasmer_types::{FunctionIndex, FunctionType, LocalFunctionIndex, MemoryIndex, TableIndex};
+use wasmer_types::{
+    Features, FunctionIndex, FunctionType, LocalFunctionIndex, MemoryIndex, TableIndex,
+};
 use wasmer_vm::{ModuleInfo, TrapCode, VMOffsets};
 
 /// A compiler that compiles a WebAssembly module with Singlepass.
@@ -40,6 +41,21 @@ impl SinglepassCompiler {
 }
 
 impl Compiler for SinglepassCompiler {
+    fn unsupported_feature(&self, features: &Features) -> Option<String> {
+        if features.multi_value {
+            Some("multivalue".to_string())
+        } else {
+            None
+        }
+    }
+
+    fn unsupported_operator(&self, operator: &Operator) -> Option<String> {
+        if FuncGen::supports_operator(operator) {
+            return None;
+        }
+        Some(format!("operator `{}`", operator_name(operator)))
+    }
+
     /// Compile the module using Singlepass, producing a compilation result with
     /// associated relocations.
     fn compile_module(
@@ -49,14 +65,14 @@ impl Compiler for SinglepassCompiler {
         _module_translation: &ModuleTranslationState,
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
     ) -> Result<Compilation, CompileError> {
-        if compile_info.features.multi_value {
-            return Err(CompileError::UnsupportedFeature("multivalue".to_string()));
+        if let Some(feature) = self.unsupported_feature(&compile_info.features) {
+            return Err(CompileError::UnsupportedFeature(feature));
         }
         let memory_styles = &compile_info.memory_styles;
         let table_styles = &compile_info.table_styles;
-        let mut module = (*compile_info.module).clone();
-        self.config.middlewares.apply_on_module_info(&mut module);
-        compile_info.module = Arc::new(module);
+        self.config
+            .middlewares
+            .apply_on_compile_module_info(compile_info)?;
         let vmoffsets = VMOffsets::new(8, &compile_info.module);
         let module = &compile_info.module;
         let import_trampolines: PrimaryMap<SectionIndex, _> = (0..module.num_imported_functions)
@@ -74,41 +90,44 @@ impl Compiler for SinglepassCompiler {
             .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
             .par_iter()
             .map(|(i, input)| {
-                let middleware_chain = self
-                    .config
-                    .middlewares
-                    .generate_function_middleware_chain(*i);
-                let mut reader =
-                    MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
-                reader.set_middleware_chain(middleware_chain);
-
-                // This local list excludes arguments.
-                let mut locals = vec![];
-                let num_locals = reader.read_local_count().map_err(to_compile_error)?;
-                for _ in 0..num_locals {
-                    let (count, ty) = reader.read_local_decl().map_err(to_compile_error)?;
-                    for _ in 0..count {
-                        locals.push(ty);
+                let compile = || -> Result<CompiledFunction, CompileError> {
+                    let middleware_chain = self
+                        .config
+                        .middlewares
+                        .generate_function_middleware_chain(module, *i)?;
+                    let mut reader =
+                        MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
+                    reader.set_middleware_chain(middleware_chain);
+
+                    // This local list excludes arguments.
+                    let mut locals = vec![];
+                    let num_locals = reader.read_local_count().map_err(to_compile_error)?;
+                    for _ in 0..num_locals {
+                        let (count, ty) = reader.read_local_decl().map_err(to_compile_error)?;
+                        for _ in 0..count {
+                            locals.push(ty);
+                        }
                     }
-                }
-
-                let mut generator = FuncGen::new(
-                    module,
-                    &self.config,
-                    &vmoffsets,
-                    &memory_styles,
-                    &table_styles,
-                    *i,
-                    &locals,
-                )
-                .map_err(to_compile_error)?;
-
-                while generator.has_control_frames() {
-                    let op = reader.read_operator().map_err(to_compile_error)?;
-                    generator.feed_operator(op).map_err(to_compile_error)?;
-                }
-
-                Ok(generator.finalize())
+
+                    let mut generator = FuncGen::new(
+                        module,
+                        &self.config,
+                        &vmoffsets,
+                        &memory_styles,
+                        &table_styles,
+                        *i,
+                        &locals,
+                    )
+                    .map_err(to_compile_error)?;
+
+                    while generator.has_control_frames() {
+                        let op = reader.read_operator().map_err(to_compile_error)?;
+                        generator.feed_operator(op).map_err(to_compile_error)?;
+                    }
+
+                    Ok(generator.finalize())
+                };
+                compile().map_err(|error| error.in_function(module, *i, input.module_offset))
             })
             .collect::<Result<Vec<CompiledFunction>, CompileError>>()?
             .into_iter()
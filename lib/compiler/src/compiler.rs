@@ -3,17 +3,20 @@
 
 use crate::error::CompileError;
 use crate::function::Compilation;
+use crate::hardening::CodeHardening;
 use crate::lib::std::boxed::Box;
+use crate::lib::std::string::String;
 use crate::lib::std::sync::Arc;
+use crate::lib::std::vec::Vec;
 use crate::module::CompileModuleInfo;
-use crate::target::Target;
-use crate::translator::ModuleMiddleware;
+use crate::target::{CpuFeature, EnumSet, Target};
+use crate::translator::{locate_function_body, ModuleMiddleware};
 use crate::FunctionBodyData;
 use crate::ModuleTranslationState;
 use crate::SectionIndex;
 use wasmer_types::entity::PrimaryMap;
 use wasmer_types::{Features, FunctionIndex, LocalFunctionIndex, SignatureIndex};
-use wasmparser::{Validator, WasmFeatures};
+use wasmparser::{Operator, Parser, Payload, Validator, WasmFeatures};
 
 /// The compiler configuration options.
 pub trait CompilerConfig {
@@ -46,6 +49,21 @@ pub trait CompilerConfig {
 
     /// Pushes a middleware onto the back of the middleware chain.
     fn push_middleware(&mut self, middleware: Arc<dyn ModuleMiddleware>);
+
+    /// Compile the modules again for each of the given sets of CPU
+    /// features, on top of the target of the engine.
+    ///
+    /// The JIT engine keeps all the variants in the artifact, and loads
+    /// the one using the most CPU features supported by the host. This
+    /// allows to compile for the baseline of a fleet, while running the
+    /// code for newer CPUs (like AVX2) where they are available.
+    ///
+    /// Each tier compiles the module again, so the middlewares must
+    /// accept being applied to the same module several times.
+    fn feature_tiers(&mut self, _tiers: Vec<EnumSet<CpuFeature>>) {
+        // By default we do nothing, each backend will need to customize this
+        // in case it generates code depending on the CPU features.
+    }
 }
 
 impl<T> From<T> for Box<dyn CompilerConfig + 'static>
@@ -81,12 +99,102 @@ pub trait Compiler: Send {
             deterministic_only: false,
         };
         validator.wasm_features(wasm_features);
-        validator
-            .validate_all(data)
-            .map_err(|e| CompileError::Validate(format!("{}", e)))?;
+        validator.validate_all(data).map_err(|e| {
+            let error = CompileError::Validate(format!("{}", e));
+            match locate_function_body(data, e.offset()) {
+                Some(location) => error.located(location),
+                None => error,
+            }
+        })?;
         Ok(())
     }
 
+    /// Returns the name of the first enabled feature the compiler
+    /// doesn't support, if any.
+    fn unsupported_feature(&self, _features: &Features) -> Option<String> {
+        None
+    }
+
+    /// Returns why the compiler can't compile `operator`, if it can't.
+    ///
+    /// Operators sharing the same reason are reported once by
+    /// [`Compiler::check_module_support`].
+    fn unsupported_operator(&self, _operator: &Operator) -> Option<String> {
+        None
+    }
+
+    /// Checks that the compiler can compile a module, without compiling
+    /// it: it validates the module and scans its code for the features
+    /// and the operators the compiler doesn't support.
+    ///
+    /// It returns all the [`CompileError`]s found: the validation error,
+    /// or the unsupported feature and operators, each located at its
+    /// first use.
+    fn check_module_support<'data>(
+        &self,
+        features: &Features,
+        data: &'data [u8],
+    ) -> Result<(), Vec<CompileError>> {
+        self.validate_module(features, data)
+            .map_err(|error| vec![error])?;
+
+        let mut errors = Vec::new();
+        if let Some(feature) = self.unsupported_feature(features) {
+            errors.push(CompileError::UnsupportedFeature(feature));
+        }
+        let mut reasons = Vec::new();
+        for payload in Parser::new(0).parse_all(data) {
+            let code = match payload {
+                Ok(Payload::CodeSectionEntry(code)) => code,
+                Ok(_) => continue,
+                // The module is valid, so it parses.
+                Err(_) => break,
+            };
+            let mut operators = match code.get_operators_reader() {
+                Ok(operators) => operators,
+                Err(_) => continue,
+            };
+            while !operators.eof() {
+                let (operator, offset) = match operators.read_with_offset() {
+                    Ok(operator) => operator,
+                    Err(_) => break,
+                };
+                let reason = match self.unsupported_operator(&operator) {
+                    Some(reason) if !reasons.contains(&reason) => reason,
+                    _ => continue,
+                };
+                let error = CompileError::UnsupportedFeature(reason.clone());
+                errors.push(match locate_function_body(data, offset) {
+                    Some(location) => error.located(location),
+                    None => error,
+                });
+                reasons.push(reason);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns the hardening applied to the code this compiler generates.
+    fn code_hardening(&self) -> CodeHardening {
+        CodeHardening::none()
+    }
+
+    /// Returns whether the compiler emits the call counters of the
+    /// functions, see `CompileModuleInfo::count_function_calls`.
+    fn supports_call_counters(&self) -> bool {
+        false
+    }
+
+    /// Returns the sets of CPU features the modules are compiled for on
+    /// top of the target, see [`CompilerConfig::feature_tiers`].
+    fn feature_tiers(&self) -> Vec<EnumSet<CpuFeature>> {
+        Vec::new()
+    }
+
     /// Compiles a parsed module.
     ///
     /// It returns the [`Compilation`] or a [`CompileError`].
@@ -117,6 +225,16 @@ pub trait Compiler: Send {
     }
 }
 
+/// Returns the name of the variant of `operator`, like `I32Add`, for
+/// the reasons of [`Compiler::unsupported_operator`].
+pub fn operator_name(operator: &Operator) -> String {
+    let debug = format!("{:?}", operator);
+    match debug.find(|c: char| !c.is_alphanumeric()) {
+        Some(end) => debug[..end].to_string(),
+        None => debug,
+    }
+}
+
 /// The kinds of wasmer_types objects that might be found in a native object file.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Symbol {
@@ -1,6 +1,11 @@
+use crate::lib::std::boxed::Box;
+use crate::lib::std::fmt;
 use crate::lib::std::string::String;
 #[cfg(feature = "std")]
 use thiserror::Error;
+use wasmer_types::entity::EntityRef;
+use wasmer_types::LocalFunctionIndex;
+use wasmer_vm::ModuleInfo;
 
 // Compilation Errors
 //
@@ -35,6 +40,107 @@ pub enum CompileError {
     /// Insufficient resources available for execution.
     #[cfg_attr(feature = "std", error("Insufficient resources: {0}"))]
     Resource(String),
+
+    /// An error in the code of a function.
+    #[cfg_attr(feature = "std", error("{error} (in {location})"))]
+    Located {
+        /// Where the error happened.
+        location: CompileErrorLocation,
+        /// The error.
+        error: Box<CompileError>,
+    },
+}
+
+impl CompileError {
+    /// Attaches the location of the error, unless it already has one.
+    pub fn located(self, location: CompileErrorLocation) -> Self {
+        match self {
+            Self::Located { .. } => self,
+            error => Self::Located {
+                location,
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Attaches the location of an error in the function `index` of
+    /// `module`, whose body starts at `body_offset` in the module.
+    ///
+    /// The offset is the one of the error if it has one, and the one of
+    /// the body otherwise.
+    pub fn in_function(
+        self,
+        module: &ModuleInfo,
+        index: LocalFunctionIndex,
+        body_offset: usize,
+    ) -> Self {
+        let offset = match &self {
+            Self::Wasm(WasmError::InvalidWebAssembly { offset, .. }) => *offset,
+            _ => body_offset,
+        };
+        let function_index = module.func_index(index);
+        self.located(CompileErrorLocation {
+            local_function_index: index,
+            offset,
+            function_name: module.function_names.get(&function_index).cloned(),
+        })
+    }
+
+    /// Returns where the error happened, if it is known.
+    pub fn location(&self) -> Option<&CompileErrorLocation> {
+        match self {
+            Self::Located { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Returns the error without its location.
+    pub fn unlocated(&self) -> &Self {
+        match self {
+            Self::Located { error, .. } => &**error,
+            error => error,
+        }
+    }
+
+    /// Returns a multi-line description of the error for humans, with
+    /// its location on its own line:
+    ///
+    /// ```text
+    /// error: Validation error: type mismatch: ...
+    ///   --> function `verify_round` (local function 3) at offset 0x1a2
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn render(&self) -> String {
+        match self.location() {
+            Some(location) => format!("error: {}\n  --> {}", self.unlocated(), location),
+            None => format!("error: {}", self),
+        }
+    }
+}
+
+/// Where a [`CompileError`] happened in the code of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileErrorLocation {
+    /// The index of the function among the functions defined by the
+    /// module, the imported functions excluded.
+    pub local_function_index: LocalFunctionIndex,
+    /// The offset of the error from the start of the module, or the offset
+    /// of the function body when the error can't be pinned to an
+    /// instruction.
+    pub offset: usize,
+    /// The name of the function, from the name section of the module.
+    pub function_name: Option<String>,
+}
+
+impl fmt::Display for CompileErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = self.local_function_index.index();
+        match &self.function_name {
+            Some(name) => write!(f, "function `{}` (local function {})", name, index)?,
+            None => write!(f, "local function {}", index)?,
+        }
+        write!(f, " at offset 0x{:x}", self.offset)
+    }
 }
 
 /// A WebAssembly translation error.
@@ -69,11 +175,49 @@ pub enum WasmError {
     #[cfg_attr(feature = "std", error("Implementation limit exceeded"))]
     ImplLimitExceeded,
 
+    /// A middleware can't transform the module.
+    #[cfg_attr(feature = "std", error("{0}"))]
+    Middleware(MiddlewareError),
+
     /// A generic error.
     #[cfg_attr(feature = "std", error("{0}"))]
     Generic(String),
 }
 
+impl From<MiddlewareError> for WasmError {
+    fn from(error: MiddlewareError) -> Self {
+        Self::Middleware(error)
+    }
+}
+
+impl From<MiddlewareError> for CompileError {
+    fn from(error: MiddlewareError) -> Self {
+        Self::Wasm(WasmError::Middleware(error))
+    }
+}
+
+/// The error of a middleware that can't transform a module, like a
+/// middleware used with a module it doesn't support.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Error))]
+#[cfg_attr(feature = "std", error("Error in middleware {name}: {message}"))]
+pub struct MiddlewareError {
+    /// The name of the middleware.
+    pub name: String,
+    /// The description of the error.
+    pub message: String,
+}
+
+impl MiddlewareError {
+    /// Creates a new error of the middleware `name`.
+    pub fn new<N: Into<String>, M: Into<String>>(name: N, message: M) -> Self {
+        Self {
+            name: name.into(),
+            message: message.into(),
+        }
+    }
+}
+
 /// The error that can happen while parsing a `str`
 /// to retrieve a [`CpuFeature`].
 #[derive(Debug)]
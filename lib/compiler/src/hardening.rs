@@ -0,0 +1,119 @@
+//! Hardening of the generated code against control-flow hijacking and
+//! speculative execution attacks.
+use crate::lib::std::vec::Vec;
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+
+/// The hardening a compiler applies to the code it generates.
+///
+/// It's recorded in the artifacts, so that an engine refuses to load
+/// artifacts compiled with less hardening against control-flow hijacking
+/// than its own compiler applies. The mitigations against speculative
+/// execution attacks, whose defaults differ between the compilers, are
+/// only enforced when the engine requires them.
+#[cfg_attr(feature = "enable-serde", derive(Deserialize, Serialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CodeHardening {
+    /// Whether the functions start with landing pads for the indirect
+    /// branches: BTI instructions on aarch64, and `endbr64` (Intel IBT)
+    /// on x86_64.
+    pub branch_target_identification: bool,
+    /// Whether the return addresses are signed with pointer
+    /// authentication before being spilled to the stack, and checked
+    /// before returning. Only available on aarch64.
+    pub return_address_signing: bool,
+    /// Whether the memory accesses can't be speculatively executed past
+    /// a failed bounds check (Spectre variant 1).
+    ///
+    /// Memories with guard pages have no bounds checks, hence no cost.
+    /// Cranelift masks the address of the accesses to the other memories,
+    /// a conditional move each. LLVM uses speculative load hardening,
+    /// which hardens every load behind a branch and can slow the code
+    /// down by a third.
+    pub spectre_bounds_checks: bool,
+    /// Whether the table index of `call_indirect` is masked after its
+    /// bounds check, so that an out-of-bounds index can't select a
+    /// function speculatively (Spectre variant 1).
+    ///
+    /// Cranelift masks the index with a few arithmetic instructions per
+    /// indirect call. LLVM uses speculative load hardening, like for
+    /// [`CodeHardening::spectre_bounds_checks`].
+    pub spectre_table_index_masking: bool,
+    /// Whether the indirect calls and branches go through retpolines,
+    /// against branch target injection (Spectre variant 2). Only
+    /// available on x86_64 with LLVM.
+    ///
+    /// An indirect call then always mispredicts, which costs tens of
+    /// cycles: guests dispatching through their tables, like
+    /// interpreters, are the most affected.
+    pub retpolines: bool,
+}
+
+impl CodeHardening {
+    /// No hardening.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// All the hardening available.
+    pub fn all() -> Self {
+        Self {
+            branch_target_identification: true,
+            return_address_signing: true,
+            spectre_bounds_checks: true,
+            spectre_table_index_masking: true,
+            retpolines: true,
+        }
+    }
+
+    /// Returns the names of the hardenings of `self` missing from
+    /// `other`.
+    pub fn missing_from(&self, other: &Self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.branch_target_identification && !other.branch_target_identification {
+            missing.push("branch target identification");
+        }
+        if self.return_address_signing && !other.return_address_signing {
+            missing.push("return address signing");
+        }
+        if self.spectre_bounds_checks && !other.spectre_bounds_checks {
+            missing.push("Spectre bounds checks");
+        }
+        if self.spectre_table_index_masking && !other.spectre_table_index_masking {
+            missing.push("Spectre table index masking");
+        }
+        if self.retpolines && !other.retpolines {
+            missing.push("retpolines");
+        }
+        missing
+    }
+
+    /// Returns the hardenings of `self` against control-flow hijacking,
+    /// without the mitigations against speculative execution attacks.
+    pub fn control_flow(&self) -> Self {
+        Self {
+            branch_target_identification: self.branch_target_identification,
+            return_address_signing: self.return_address_signing,
+            ..Self::none()
+        }
+    }
+
+    /// Returns the hardening applying every hardening of `self` and of
+    /// `other`.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            branch_target_identification: self.branch_target_identification
+                || other.branch_target_identification,
+            return_address_signing: self.return_address_signing || other.return_address_signing,
+            spectre_bounds_checks: self.spectre_bounds_checks || other.spectre_bounds_checks,
+            spectre_table_index_masking: self.spectre_table_index_masking
+                || other.spectre_table_index_masking,
+            retpolines: self.retpolines || other.retpolines,
+        }
+    }
+
+    /// Returns whether `self` applies every hardening of `other`.
+    pub fn covers(&self, other: &Self) -> bool {
+        other.missing_from(self).is_empty()
+    }
+}
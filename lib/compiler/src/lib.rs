@@ -54,6 +54,7 @@ mod address_map;
 mod compiler;
 mod error;
 mod function;
+mod hardening;
 mod jump_table;
 mod module;
 mod relocation;
@@ -68,20 +69,24 @@ mod sourceloc;
 
 pub use crate::address_map::{FunctionAddressMap, InstructionAddressMap};
 #[cfg(feature = "translator")]
-pub use crate::compiler::{Compiler, CompilerConfig, Symbol, SymbolRegistry};
-pub use crate::error::{CompileError, ParseCpuFeatureError, WasmError, WasmResult};
+pub use crate::compiler::{operator_name, Compiler, CompilerConfig, Symbol, SymbolRegistry};
+pub use crate::error::{
+    CompileError, CompileErrorLocation, MiddlewareError, ParseCpuFeatureError, WasmError,
+    WasmResult,
+};
 pub use crate::function::{
     Compilation, CompiledFunction, CompiledFunctionFrameInfo, CustomSections, Dwarf, FunctionBody,
     Functions,
 };
+pub use crate::hardening::CodeHardening;
 pub use crate::jump_table::{JumpTable, JumpTableOffsets};
 pub use crate::module::CompileModuleInfo;
 pub use crate::relocation::{Relocation, RelocationKind, RelocationTarget, Relocations};
 pub use crate::section::{CustomSection, CustomSectionProtection, SectionBody, SectionIndex};
 pub use crate::sourceloc::SourceLoc;
 pub use crate::target::{
-    Architecture, BinaryFormat, CallingConvention, CpuFeature, Endianness, OperatingSystem,
-    PointerWidth, Target, Triple,
+    Architecture, BinaryFormat, CallingConvention, CpuFeature, Endianness, EnumSet,
+    OperatingSystem, PointerWidth, Target, Triple,
 };
 #[cfg(feature = "translator")]
 pub use crate::translator::{
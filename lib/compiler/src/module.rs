@@ -1,4 +1,5 @@
 use crate::lib::std::sync::Arc;
+use crate::CodeHardening;
 #[cfg(feature = "enable-serde")]
 use serde::{Deserialize, Serialize};
 use wasmer_types::entity::PrimaryMap;
@@ -10,7 +11,7 @@ use wasmer_vm::{MemoryStyle, ModuleInfo, TableStyle};
 /// This differs from [`ModuleInfo`] because it have extra info only
 /// possible after translation (such as the features used for compiling,
 /// or the `MemoryStyle` and `TableStyle`).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "enable-serde", derive(Deserialize, Serialize))]
 pub struct CompileModuleInfo {
     /// The features used for compiling the module
@@ -24,4 +25,18 @@ pub struct CompileModuleInfo {
     pub memory_styles: PrimaryMap<MemoryIndex, MemoryStyle>,
     /// The table plans used for compiling.
     pub table_styles: PrimaryMap<TableIndex, TableStyle>,
+    /// The hardening applied to the code.
+    pub hardening: CodeHardening,
+    /// Whether the compiled functions increment their call counter on
+    /// entry.
+    ///
+    /// The counters of the local functions are an array of `u64`, pointed
+    /// to by the `VMContext` (see `VMOffsets::vmctx_call_counters`).
+    pub count_function_calls: bool,
+    /// Whether the middlewares already transformed `module`, in which
+    /// case the compilers don't transform it again.
+    ///
+    /// It's set when a module is compiled again for another target, such
+    /// as the feature tiers of a compiler.
+    pub middlewares_applied: bool,
 }
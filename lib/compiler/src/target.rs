@@ -2,7 +2,8 @@
 use crate::error::ParseCpuFeatureError;
 use crate::lib::std::str::FromStr;
 use crate::lib::std::string::{String, ToString};
-use enumset::{EnumSet, EnumSetType};
+pub use enumset::EnumSet;
+use enumset::EnumSetType;
 pub use target_lexicon::{
     Architecture, BinaryFormat, CallingConvention, Endianness, OperatingSystem, PointerWidth,
     Triple,
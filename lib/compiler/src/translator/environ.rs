@@ -17,7 +17,7 @@ use wasmer_types::{
     LocalFunctionIndex, MemoryIndex, MemoryType, SignatureIndex, TableIndex, TableInitializer,
     TableType,
 };
-use wasmer_vm::ModuleInfo;
+use wasmer_vm::{ModuleInfo, ModuleNames, MIDDLEWARE_REGISTRY_SECTION};
 
 /// Contains function data: bytecode and its offset in the module.
 #[derive(Hash)]
@@ -406,8 +406,9 @@ impl<'data> ModuleEnvironment<'data> {
         Ok(())
     }
 
-    pub(crate) fn reserve_passive_data(&mut self, count: u32) -> WasmResult<()> {
-        self.result.module.passive_data.reserve(count as usize);
+    pub(crate) fn reserve_passive_data(&mut self, _count: u32) -> WasmResult<()> {
+        // The passive data segments are kept in a `BTreeMap`, for a
+        // deterministic serialization, which can't reserve space.
         Ok(())
     }
 
@@ -445,6 +446,11 @@ impl<'data> ModuleEnvironment<'data> {
         Ok(())
     }
 
+    pub(crate) fn declare_names(&mut self, names: ModuleNames) -> WasmResult<()> {
+        self.result.module.names = names;
+        Ok(())
+    }
+
     /// Provides the number of imports up front. By default this does nothing, but
     /// implementations can use this to preallocate memory if desired.
     pub(crate) fn reserve_imports(&mut self, _num: u32) -> WasmResult<()> {
@@ -457,7 +463,13 @@ impl<'data> ModuleEnvironment<'data> {
     }
 
     /// Indicates that a custom section has been found in the wasm file
+    ///
+    /// The sections reserved for the middleware registry are dropped, so
+    /// that a module can't pass for the state of a middleware.
     pub(crate) fn custom_section(&mut self, name: &'data str, data: &'data [u8]) -> WasmResult<()> {
+        if name == MIDDLEWARE_REGISTRY_SECTION {
+            return Ok(());
+        }
         let custom_section = CustomSectionIndex::from_u32(
             self.result
                 .module
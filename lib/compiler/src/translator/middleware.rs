@@ -1,28 +1,38 @@
 //! The middleware parses the function binary bytecodes and transform them
 //! with the chosen functions.
 
+use crate::{CompileModuleInfo, MiddlewareError};
 use smallvec::SmallVec;
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::ops::Deref;
+use std::sync::Arc;
 use wasmer_types::LocalFunctionIndex;
 use wasmer_vm::ModuleInfo;
 use wasmparser::{BinaryReader, Operator, Result as WpResult, Type};
 
 /// A shared builder for function middlewares.
 pub trait ModuleMiddleware: Debug + Send + Sync {
-    /// Generates a `FunctionMiddleware` for a given function.
+    /// Generates a `FunctionMiddleware` for a given function of `module_info`, as transformed by
+    /// `transform_module_info`.
     ///
     /// Here we generate a separate object for each function instead of executing directly on per-function operators,
     /// in order to enable concurrent middleware application. Takes immutable `&self` because this function can be called
-    /// concurrently from multiple compilation threads.
+    /// concurrently from multiple compilation threads, for the functions of multiple modules.
+    ///
+    /// It fails if `module_info` wasn't transformed by this middleware.
     fn generate_function_middleware(
         &self,
+        module_info: &ModuleInfo,
         local_function_index: LocalFunctionIndex,
-    ) -> Box<dyn FunctionMiddleware>;
+    ) -> Result<Box<dyn FunctionMiddleware>, MiddlewareError>;
 
     /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
-    fn transform_module_info(&self, _: &mut ModuleInfo) {}
+    ///
+    /// It fails if the middleware can't be used with the module, which then can't be compiled.
+    fn transform_module_info(&self, _: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        Ok(())
+    }
 }
 
 /// A function middleware specialized for a single function.
@@ -56,36 +66,68 @@ pub struct MiddlewareReaderState<'a> {
 
     /// The pending operations added by the middleware.
     pending_operations: VecDeque<Operator<'a>>,
+
+    /// The offset of the function body in the module.
+    function_offset: usize,
+
+    /// The offset of the original operator being processed in the module.
+    operator_offset: usize,
 }
 
 /// Trait for generating middleware chains from "prototype" (generator) chains.
 pub trait ModuleMiddlewareChain {
-    /// Generates a function middleware chain.
+    /// Generates a function middleware chain for a given function of `module_info`.
     fn generate_function_middleware_chain(
         &self,
+        module_info: &ModuleInfo,
         local_function_index: LocalFunctionIndex,
-    ) -> Vec<Box<dyn FunctionMiddleware>>;
+    ) -> Result<Vec<Box<dyn FunctionMiddleware>>, MiddlewareError>;
 
     /// Applies the chain on a `ModuleInfo` struct.
-    fn apply_on_module_info(&self, module_info: &mut ModuleInfo);
+    fn apply_on_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError>;
+
+    /// Applies the chain on the module of `compile_info`, unless it was
+    /// already applied on it.
+    fn apply_on_compile_module_info(
+        &self,
+        compile_info: &mut CompileModuleInfo,
+    ) -> Result<(), MiddlewareError>;
 }
 
 impl<T: Deref<Target = dyn ModuleMiddleware>> ModuleMiddlewareChain for [T] {
-    /// Generates a function middleware chain.
+    /// Generates a function middleware chain for a given function of `module_info`.
     fn generate_function_middleware_chain(
         &self,
+        module_info: &ModuleInfo,
         local_function_index: LocalFunctionIndex,
-    ) -> Vec<Box<dyn FunctionMiddleware>> {
+    ) -> Result<Vec<Box<dyn FunctionMiddleware>>, MiddlewareError> {
         self.iter()
-            .map(|x| x.generate_function_middleware(local_function_index))
+            .map(|x| x.generate_function_middleware(module_info, local_function_index))
             .collect()
     }
 
     /// Applies the chain on a `ModuleInfo` struct.
-    fn apply_on_module_info(&self, module_info: &mut ModuleInfo) {
+    fn apply_on_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
         for item in self {
-            item.transform_module_info(module_info);
+            item.transform_module_info(module_info)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the chain on the module of `compile_info`, unless it was
+    /// already applied on it.
+    fn apply_on_compile_module_info(
+        &self,
+        compile_info: &mut CompileModuleInfo,
+    ) -> Result<(), MiddlewareError> {
+        if compile_info.middlewares_applied {
+            return Ok(());
         }
+        let mut module = (*compile_info.module).clone();
+        self.apply_on_module_info(&mut module)?;
+        compile_info.module = Arc::new(module);
+        compile_info.middlewares_applied = true;
+        Ok(())
     }
 }
 
@@ -94,6 +136,17 @@ impl<'a> MiddlewareReaderState<'a> {
     pub fn push_operator(&mut self, operator: Operator<'a>) {
         self.pending_operations.push_back(operator);
     }
+
+    /// Returns the offset of the function body in the module.
+    pub fn function_offset(&self) -> usize {
+        self.function_offset
+    }
+
+    /// Returns the offset in the module of the original operator being
+    /// processed, the one which went through the first middleware.
+    pub fn operator_offset(&self) -> usize {
+        self.operator_offset
+    }
 }
 
 impl<'a> Extend<Operator<'a>> for MiddlewareReaderState<'a> {
@@ -116,6 +169,8 @@ impl<'a> MiddlewareBinaryReader<'a> {
             state: MiddlewareReaderState {
                 inner,
                 pending_operations: VecDeque::new(),
+                function_offset: original_offset,
+                operator_offset: original_offset,
             },
             chain: vec![],
         }
@@ -147,6 +202,7 @@ impl<'a> MiddlewareBinaryReader<'a> {
 
         // Try to fill the `self.pending_operations` buffer, until it is non-empty.
         while self.state.pending_operations.is_empty() {
+            self.state.operator_offset = self.state.inner.original_position();
             let raw_op = self.state.inner.read_operator()?;
 
             // Fill the initial raw operator into pending buffer.
@@ -19,6 +19,6 @@ pub use self::middleware::{
     FunctionMiddleware, MiddlewareBinaryReader, MiddlewareReaderState, ModuleMiddleware,
     ModuleMiddlewareChain,
 };
-pub use self::module::translate_module;
+pub use self::module::{locate_function_body, translate_module};
 pub use self::sections::wptype_to_type;
 pub use self::state::ModuleTranslationState;
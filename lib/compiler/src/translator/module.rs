@@ -6,13 +6,16 @@
 use super::environ::ModuleEnvironment;
 use super::error::to_wasm_error;
 use super::sections::{
-    parse_data_section, parse_element_section, parse_export_section, parse_function_section,
-    parse_global_section, parse_import_section, parse_memory_section, parse_name_section,
-    parse_start_section, parse_table_section, parse_type_section,
+    parse_data_section, parse_element_section, parse_export_section, parse_extended_name_section,
+    parse_function_name_subsection, parse_function_section, parse_global_section,
+    parse_import_section, parse_memory_section, parse_name_section, parse_start_section,
+    parse_table_section, parse_type_section,
 };
 use super::state::ModuleTranslationState;
-use crate::WasmResult;
-use wasmparser::{NameSectionReader, Parser, Payload};
+use crate::lib::std::string::ToString;
+use crate::{CompileErrorLocation, WasmResult};
+use wasmer_types::{FunctionIndex, LocalFunctionIndex};
+use wasmparser::{ImportSectionEntryType, Name, NameSectionReader, Parser, Payload};
 
 /// Translate a sequence of bytes forming a valid Wasm binary into a
 /// parsed ModuleInfo `ModuleTranslationState`.
@@ -94,10 +97,13 @@ pub fn translate_module<'data>(
                 name: "name",
                 data,
                 data_offset,
-            } => parse_name_section(
-                NameSectionReader::new(data, data_offset).map_err(to_wasm_error)?,
-                environ,
-            )?,
+            } => {
+                parse_name_section(
+                    NameSectionReader::new(data, data_offset).map_err(to_wasm_error)?,
+                    environ,
+                )?;
+                parse_extended_name_section(data, environ)?;
+            }
 
             Payload::CustomSection { name, data, .. } => environ.custom_section(name, data)?,
 
@@ -107,3 +113,63 @@ pub fn translate_module<'data>(
 
     Ok(module_translation_state)
 }
+
+/// Returns the location of the function body holding `offset`, to locate
+/// the errors that wasmparser reports with an offset in the module.
+pub fn locate_function_body(data: &[u8], offset: usize) -> Option<CompileErrorLocation> {
+    let mut num_imported_functions = 0;
+    let mut num_local_functions = 0;
+    let mut local_function_index = None;
+    let mut function_name = None;
+    for payload in Parser::new(0).parse_all(data) {
+        match payload {
+            Ok(Payload::ImportSection(imports)) => {
+                for import in imports.into_iter().flatten() {
+                    if let ImportSectionEntryType::Function(_) = import.ty {
+                        num_imported_functions += 1;
+                    }
+                }
+            }
+            Ok(Payload::CodeSectionEntry(code)) => {
+                let code = code.get_binary_reader();
+                let start = code.original_position();
+                if start <= offset && offset < start + code.bytes_remaining() {
+                    local_function_index = Some(LocalFunctionIndex::from_u32(num_local_functions));
+                }
+                num_local_functions += 1;
+            }
+            Ok(Payload::CustomSection {
+                name: "name",
+                data,
+                data_offset,
+            }) => {
+                let local_function_index = match local_function_index {
+                    Some(index) => index,
+                    None => continue,
+                };
+                let function_index =
+                    FunctionIndex::from_u32(num_imported_functions + local_function_index.as_u32());
+                let mut names = match NameSectionReader::new(data, data_offset) {
+                    Ok(names) => names,
+                    Err(_) => continue,
+                };
+                while let Ok(subsection) = names.read() {
+                    if let Name::Function(function_subsection) = subsection {
+                        function_name = function_subsection
+                            .get_map()
+                            .ok()
+                            .and_then(parse_function_name_subsection)
+                            .and_then(|names| Some(names.get(&function_index)?.to_string()));
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    Some(CompileErrorLocation {
+        local_function_index: local_function_index?,
+        offset,
+        function_name,
+    })
+}
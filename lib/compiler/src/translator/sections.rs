@@ -17,7 +17,8 @@ use crate::wasm_unsupported;
 use crate::{WasmError, WasmResult};
 use core::convert::TryFrom;
 use std::boxed::Box;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::string::String;
 use std::vec::Vec;
 use wasmer_types::entity::packed_option::ReservedValue;
 use wasmer_types::entity::EntityRef;
@@ -25,6 +26,7 @@ use wasmer_types::{
     DataIndex, ElemIndex, FunctionIndex, FunctionType, GlobalIndex, GlobalInit, GlobalType,
     MemoryIndex, MemoryType, Pages, SignatureIndex, TableIndex, TableType, Type, V128,
 };
+use wasmer_vm::ModuleNames;
 use wasmparser::{
     self, Data, DataKind, DataSectionReader, Element, ElementItem, ElementItems, ElementKind,
     ElementSectionReader, Export, ExportSectionReader, ExternalKind, FuncType as WPFunctionType,
@@ -450,7 +452,7 @@ pub fn parse_name_section<'data>(
     Ok(())
 }
 
-fn parse_function_name_subsection(
+pub(crate) fn parse_function_name_subsection(
     mut naming_reader: NamingReader<'_>,
 ) -> Option<HashMap<FunctionIndex, &str>> {
     let mut function_names = HashMap::new();
@@ -473,3 +475,101 @@ fn parse_function_name_subsection(
     }
     Some(function_names)
 }
+
+/// Parses the subsections of the Name section that wasmparser doesn't read:
+/// the names of the locals, labels, types, tables, memories and globals.
+///
+/// The names are only used for diagnostics, so the subsections that are
+/// malformed are skipped.
+pub fn parse_extended_name_section<'data>(
+    data: &'data [u8],
+    environ: &mut ModuleEnvironment<'data>,
+) -> WasmResult<()> {
+    let mut names = ModuleNames::default();
+    let mut section = NameReader { data };
+    while let Some((id, mut subsection)) = section.subsection() {
+        match id {
+            2 => names.locals = subsection.indirect_name_map().unwrap_or_default(),
+            3 => names.labels = subsection.indirect_name_map().unwrap_or_default(),
+            4 => names.types = subsection.name_map().unwrap_or_default(),
+            5 => names.tables = subsection.name_map().unwrap_or_default(),
+            6 => names.memories = subsection.name_map().unwrap_or_default(),
+            7 => names.globals = subsection.name_map().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    environ.declare_names(names)
+}
+
+/// A reader of the encoding of the Name section.
+struct NameReader<'data> {
+    data: &'data [u8],
+}
+
+impl<'data> NameReader<'data> {
+    fn u32(&mut self) -> Option<u32> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = self.data.split_first()?;
+            self.data = rest;
+            if shift == 28 && byte > 0x0f {
+                return None;
+            }
+            result |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'data [u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        std::str::from_utf8(bytes).ok().map(str::to_string)
+    }
+
+    /// Reads the id and the contents of the next subsection.
+    fn subsection(&mut self) -> Option<(u8, NameReader<'data>)> {
+        let id = *self.bytes(1)?.first()?;
+        let len = self.u32()? as usize;
+        let data = self.bytes(len)?;
+        Some((id, NameReader { data }))
+    }
+
+    fn name_map<I: EntityRef + Ord>(&mut self) -> Option<BTreeMap<I, String>> {
+        let count = self.u32()?;
+        let mut names = BTreeMap::new();
+        for _ in 0..count {
+            let index = self.u32()?;
+            names.insert(I::new(index as usize), self.string()?);
+        }
+        Some(names)
+    }
+
+    fn indirect_name_map(&mut self) -> Option<BTreeMap<FunctionIndex, BTreeMap<u32, String>>> {
+        let count = self.u32()?;
+        let mut names = BTreeMap::new();
+        for _ in 0..count {
+            let function = FunctionIndex::from_u32(self.u32()?);
+            let inner = self.u32()?;
+            let mut function_names = BTreeMap::new();
+            for _ in 0..inner {
+                let index = self.u32()?;
+                function_names.insert(index, self.string()?);
+            }
+            names.insert(function, function_names);
+        }
+        Some(names)
+    }
+}
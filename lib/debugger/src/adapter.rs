@@ -0,0 +1,444 @@
+//! The debug adapter: it answers the Debug Adapter Protocol requests by running the guest
+//! with a [`DebugSession`](wasmer_middlewares::debugger::DebugSession).
+
+use crate::source::SourceMap;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use wasmer::{Instance, InstantiationError, RuntimeError, Value as WasmValue};
+use wasmer_middlewares::debugger::{DebugStop, Debugger, StopReason};
+
+/// The id of the only thread of the guest.
+const THREAD_ID: i64 = 1;
+
+/// The reference of the variables of the parameters of the innermost frame.
+const PARAMETERS_REFERENCE: i64 = 1;
+
+/// The guest program being debugged, implemented by the embedder.
+///
+/// The program is run from its start at every resume, so its runs must be deterministic.
+pub trait Debuggee {
+    /// Creates a fresh instance of the program, compiled with the [`Debugger`] of the
+    /// [`DebugAdapter`].
+    fn instantiate(&mut self) -> Result<Instance, InstantiationError>;
+
+    /// Runs the program on `instance`, for example by calling its `_start` export.
+    fn run(&mut self, instance: &Instance) -> Result<(), RuntimeError>;
+}
+
+/// Returns the instruction reference of an instruction, `<function>:<offset>`.
+///
+/// The offset is from the start of the function body, as in
+/// [`FrameInfo::func_offset`](wasmer::FrameInfo::func_offset).
+pub fn instruction_reference(function: u32, offset: usize) -> String {
+    format!("{}:{}", function, offset)
+}
+
+fn parse_instruction_reference(reference: &str) -> Option<(u32, usize)> {
+    let mut parts = reference.splitn(2, ':');
+    let function = parts.next()?.parse().ok()?;
+    let offset = parts.next()?.parse().ok()?;
+    Some((function, offset))
+}
+
+/// A debug adapter, answering the requests of a Debug Adapter Protocol client.
+pub struct DebugAdapter<D: Debuggee> {
+    debugger: Arc<Debugger>,
+    debuggee: D,
+    /// The sequence number of the next message sent.
+    seq: i64,
+    /// The breakpoints of the debugger enabled by `setInstructionBreakpoints`.
+    instruction_breakpoints: Vec<usize>,
+    /// The breakpoints of the debugger enabled by `setBreakpoints`, by source path.
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    source_map: SourceMap,
+    /// The last stop of the guest, if it is stopped.
+    last_stop: Option<DebugStop>,
+    disconnected: bool,
+}
+
+impl<D: Debuggee> DebugAdapter<D> {
+    /// Creates an adapter running `debuggee`, whose module is compiled with `debugger`.
+    ///
+    /// All the breakpoints are disabled until the client sets them.
+    pub fn new(debugger: Arc<Debugger>, debuggee: D) -> Self {
+        Self {
+            debugger,
+            debuggee,
+            seq: 1,
+            instruction_breakpoints: Vec::new(),
+            source_breakpoints: HashMap::new(),
+            source_map: SourceMap::default(),
+            last_stop: None,
+            disconnected: false,
+        }
+    }
+
+    /// Sets the mapping of the module of the debuggee to its sources, to locate the stack
+    /// frames in the sources and to set breakpoints on source lines.
+    ///
+    /// The source breakpoints can only be set on the statements compiled with a breakpoint,
+    /// see [`SourceMap::breakpoints`].
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        self.source_map = source_map;
+    }
+
+    /// Returns whether the client disconnected.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    /// Handles a request, and returns the messages to send back: its response, then the
+    /// events it caused.
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let mut events = Vec::new();
+        let body = match command {
+            "initialize" => {
+                events.push(self.event("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsSteppingGranularity": true,
+                }))
+            }
+            "launch" | "attach" => Ok(json!({})),
+            "setBreakpoints" => Ok(self.set_source_breakpoints(arguments)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(arguments)),
+            "configurationDone" | "continue" => {
+                events = self.resume(None);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => match &self.last_stop {
+                Some(stop) => {
+                    let limit = stop.steps + 1;
+                    events = self.resume(Some(limit));
+                    Ok(json!({}))
+                }
+                None => Err("the guest is not stopped".to_string()),
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(self.scopes()),
+            "variables" => Ok(self.variables()),
+            "disconnect" => {
+                self.disconnected = true;
+                Ok(json!({}))
+            }
+            _ => Err(format!("unsupported request `{}`", command)),
+        };
+
+        let mut response = json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::String(message),
+        }
+        let mut messages = vec![response];
+        messages.extend(events);
+        messages
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        let seq = self.seq;
+        self.seq += 1;
+        seq
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        json!({
+            "seq": self.next_seq(),
+            "type": "event",
+            "event": event,
+            "body": body,
+        })
+    }
+
+    fn set_source_breakpoints(&mut self, arguments: &Value) -> Value {
+        let path = arguments["source"]["path"].as_str().unwrap_or_default();
+        let compiled = self.debugger.breakpoints();
+        let mut enabled = Vec::new();
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let breakpoints = requested
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or_default();
+                let found = self.source_map.line_breakpoints(Path::new(path), line);
+                let (line, breakpoints) = match found {
+                    Some(found) => found,
+                    None => {
+                        return json!({
+                            "verified": false,
+                            "message": "no statement of the guest is at or after this line",
+                        })
+                    }
+                };
+                let indices = breakpoints
+                    .iter()
+                    .filter_map(|breakpoint| compiled.iter().position(|c| c == breakpoint))
+                    .collect::<Vec<_>>();
+                if indices.is_empty() {
+                    return json!({
+                        "verified": false,
+                        "message": "no breakpoint was compiled at this line",
+                    });
+                }
+                enabled.extend(indices);
+                json!({ "verified": true, "line": line })
+            })
+            .collect::<Vec<_>>();
+        self.source_breakpoints.insert(path.to_string(), enabled);
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Value {
+        let compiled = self.debugger.breakpoints();
+        let mut enabled = Vec::new();
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let breakpoints = requested
+            .iter()
+            .map(|breakpoint| {
+                let reference = breakpoint["instructionReference"]
+                    .as_str()
+                    .unwrap_or_default();
+                let index =
+                    parse_instruction_reference(reference).and_then(|(function, offset)| {
+                        compiled.iter().position(|compiled| {
+                            compiled.function == function && compiled.offset == offset
+                        })
+                    });
+                match index {
+                    Some(index) => {
+                        enabled.push(index);
+                        json!({ "verified": true, "instructionReference": reference })
+                    }
+                    None => json!({
+                        "verified": false,
+                        "message": "no breakpoint was compiled at this instruction",
+                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+        self.instruction_breakpoints = enabled;
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn stack_trace(&self) -> Value {
+        let frames = self
+            .last_stop
+            .iter()
+            .flat_map(|stop| stop.backtrace.iter())
+            .enumerate()
+            .map(|(id, frame)| {
+                let name = frame
+                    .function_name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("func[{}]", frame.func_index()));
+                let mut stack_frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference":
+                        instruction_reference(frame.func_index(), frame.func_offset()),
+                });
+                if let Some(location) = self.source_map.location(frame.module_offset()) {
+                    let name = location.path.file_name().map(|name| name.to_string_lossy());
+                    stack_frame["source"] = json!({
+                        "name": name,
+                        "path": location.path.to_string_lossy(),
+                    });
+                    stack_frame["line"] = json!(location.line);
+                    stack_frame["column"] = json!(location.column.max(1));
+                }
+                stack_frame
+            })
+            .collect::<Vec<_>>();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    /// Returns the scopes of the innermost frame: its parameters, the only variables saved
+    /// at a stop.
+    fn scopes(&self) -> Value {
+        let scopes = match &self.last_stop {
+            Some(stop) if !stop.params.is_empty() => vec![json!({
+                "name": "Parameters",
+                "variablesReference": PARAMETERS_REFERENCE,
+                "expensive": false,
+            })],
+            _ => Vec::new(),
+        };
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&self) -> Value {
+        let variables = self
+            .last_stop
+            .iter()
+            .flat_map(|stop| stop.params.iter())
+            .enumerate()
+            .map(|(index, param)| {
+                let value = match param {
+                    Some(WasmValue::I32(value)) => value.to_string(),
+                    Some(WasmValue::I64(value)) => value.to_string(),
+                    Some(WasmValue::F32(value)) => value.to_string(),
+                    Some(WasmValue::F64(value)) => value.to_string(),
+                    _ => "unavailable".to_string(),
+                };
+                json!({
+                    "name": format!("param{}", index),
+                    "value": value,
+                    "variablesReference": 0,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "variables": variables })
+    }
+
+    /// Runs the guest from its start until the next stop, past the last stop, and returns
+    /// the events telling how it went.
+    fn resume(&mut self, step_limit: Option<u64>) -> Vec<Value> {
+        let break_after = self.last_stop.take().map_or(0, |stop| stop.steps + 1);
+        let instance = match self.debuggee.instantiate() {
+            Ok(instance) => instance,
+            Err(error) => return self.terminate(Some(error.to_string())),
+        };
+
+        let session = match self.debugger.session(&instance) {
+            Ok(session) => session,
+            Err(error) => return self.terminate(Some(error.to_string())),
+        };
+        let mut enabled = vec![false; self.debugger.breakpoints().len()];
+        let source_breakpoints = self.source_breakpoints.values().flatten();
+        for &index in self
+            .instruction_breakpoints
+            .iter()
+            .chain(source_breakpoints)
+        {
+            enabled[index] = true;
+        }
+        for (index, enabled) in enabled.into_iter().enumerate() {
+            session.set_breakpoint_enabled(index, enabled);
+        }
+        session.set_break_after(break_after);
+        session.set_step_limit(step_limit);
+
+        let error = match self.debuggee.run(&instance) {
+            Ok(()) => return self.terminate(None),
+            Err(error) => error,
+        };
+        match session.stop(&error) {
+            Some(stop) => {
+                let reason = match stop.reason {
+                    StopReason::Step => "step",
+                    StopReason::Breakpoint(_) => "breakpoint",
+                };
+                self.last_stop = Some(stop);
+                vec![self.event(
+                    "stopped",
+                    json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+                )]
+            }
+            None => self.terminate(Some(error.message())),
+        }
+    }
+
+    /// Returns the events of the end of the guest, with the error it failed with.
+    fn terminate(&mut self, error: Option<String>) -> Vec<Value> {
+        let mut events = Vec::new();
+        if let Some(error) = &error {
+            let output = format!("{}\n", error);
+            events.push(self.event("output", json!({ "category": "stderr", "output": output })));
+        }
+        let exit_code = if error.is_some() { 1 } else { 0 };
+        events.push(self.event("exited", json!({ "exitCode": exit_code })));
+        events.push(self.event("terminated", json!({})));
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::source::fixture;
+
+    /// A guest that is never run.
+    struct Unreachable;
+
+    impl Debuggee for Unreachable {
+        fn instantiate(&mut self) -> Result<Instance, InstantiationError> {
+            unreachable!()
+        }
+
+        fn run(&mut self, _instance: &Instance) -> Result<(), RuntimeError> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn source_breakpoints_move_to_the_next_statement() {
+        let wasm = wasmer::wat2wasm(
+            br#"(module
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0
+                    local.get 1
+                    i32.add))"#,
+        )
+        .unwrap();
+        let wasm = fixture::with_lines(&wasm, "src/add.c", &[3, 5, 5, 5]);
+        let source_map = SourceMap::new(&wasm).unwrap();
+        // Only the statements of the line 5 are compiled with a breakpoint.
+        let compiled = source_map.breakpoints()[1..].to_vec();
+        let debugger = Arc::new(Debugger::new(compiled));
+        let mut adapter = DebugAdapter::new(debugger, Unreachable);
+        adapter.set_source_map(source_map);
+
+        let messages = adapter.handle(&json!({
+            "seq": 1,
+            "type": "request",
+            "command": "setBreakpoints",
+            "arguments": {
+                "source": { "path": "/guest/src/add.c" },
+                "breakpoints": [{ "line": 2 }, { "line": 4 }, { "line": 6 }],
+            },
+        }));
+        assert_eq!(
+            messages[0]["body"]["breakpoints"],
+            json!([
+                { "verified": false, "message": "no breakpoint was compiled at this line" },
+                { "verified": true, "line": 5 },
+                {
+                    "verified": false,
+                    "message": "no statement of the guest is at or after this line",
+                },
+            ])
+        );
+        assert_eq!(
+            adapter.source_breakpoints["/guest/src/add.c"],
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn instruction_references_round_trip() {
+        let reference = instruction_reference(3, 42);
+        assert_eq!(reference, "3:42");
+        assert_eq!(parse_instruction_reference(&reference), Some((3, 42)));
+        assert_eq!(parse_instruction_reference("0x2a"), None);
+    }
+}
@@ -0,0 +1,26 @@
+//! The `wasmer-debugger` crate serves the [Debug Adapter Protocol] (DAP) over TCP, so that
+//! editors can debug the WebAssembly guests run by an embedder.
+//!
+//! The guest module is compiled with the [`Debugger`] middleware, which provides the
+//! breakpoints and the stepping, and the embedder implements [`Debuggee`] to run it.
+//! The execution is replayed from the start at every resume, so the guest must be
+//! deterministic.
+//!
+//! The stack frames and the instruction breakpoints are located with the function index
+//! and the offset in the function body (see [`instruction_reference`]). When the guest
+//! carries DWARF line information, a [`SourceMap`] given to the adapter also locates the
+//! stack frames in the sources, and sets the breakpoints on source lines.
+//!
+//! [Debug Adapter Protocol]: https://microsoft.github.io/debug-adapter-protocol/
+//! [`Debugger`]: wasmer_middlewares::Debugger
+
+#![deny(missing_docs, unused_extern_crates)]
+
+mod adapter;
+pub mod protocol;
+mod server;
+mod source;
+
+pub use crate::adapter::{instruction_reference, DebugAdapter, Debuggee};
+pub use crate::server::DapServer;
+pub use crate::source::{SourceLocation, SourceMap, SourceMapError};
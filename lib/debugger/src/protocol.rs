@@ -0,0 +1,80 @@
+//! The framing of the Debug Adapter Protocol messages: a `Content-Length` header, an empty
+//! line, and the JSON body.
+
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads a message, or returns `None` at the end of the stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(length) = line.strip_prefix("Content-Length:") {
+            let length = length
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| invalid_data("invalid `Content-Length` header"))?;
+            content_length = Some(length);
+        }
+    }
+
+    let content_length =
+        content_length.ok_or_else(|| invalid_data("missing `Content-Length` header"))?;
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Writes a message.
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = serde_json::to_vec(message)?;
+    write!(writer, "Content-Length: {}\r\n\r\n", body.len())?;
+    writer.write_all(&body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            json!({"seq": 1, "type": "request", "command": "initialize"}),
+            json!({"seq": 2, "type": "request", "command": "threads"}),
+        ];
+        let mut stream = Vec::new();
+        for message in messages.iter() {
+            write_message(&mut stream, message).unwrap();
+        }
+
+        let mut reader = &stream[..];
+        assert_eq!(
+            read_message(&mut reader).unwrap().as_ref(),
+            Some(&messages[0])
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap().as_ref(),
+            Some(&messages[1])
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn missing_content_length_is_an_error() {
+        let mut reader = &b"Content-Type: json\r\n\r\n{}"[..];
+        assert!(read_message(&mut reader).is_err());
+    }
+}
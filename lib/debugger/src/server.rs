@@ -0,0 +1,43 @@
+//! The TCP server of the Debug Adapter Protocol.
+
+use crate::adapter::{DebugAdapter, Debuggee};
+use crate::protocol::{read_message, write_message};
+use std::io::{self, BufReader};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+
+/// A server to which a Debug Adapter Protocol client, like VS Code, attaches over TCP.
+#[derive(Debug)]
+pub struct DapServer {
+    listener: TcpListener,
+}
+
+impl DapServer {
+    /// Creates a server listening on `addr`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for a client, and serves it with `adapter` until it disconnects or closes
+    /// the connection.
+    pub fn serve<D: Debuggee>(&self, adapter: &mut DebugAdapter<D>) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        while let Some(request) = read_message(&mut reader)? {
+            for message in adapter.handle(&request) {
+                write_message(&mut writer, &message)?;
+            }
+            if adapter.is_disconnected() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
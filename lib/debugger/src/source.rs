@@ -0,0 +1,426 @@
+//! The mapping between the instructions of a module and its sources, read from the DWARF
+//! line information the module carries in its custom sections.
+//!
+//! The DWARF addresses of a WebAssembly module are offsets from the start of the content
+//! of its code section.
+
+use gimli::{EndianSlice, LittleEndian};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use wasmer::wasmparser::{BinaryReaderError, ImportSectionEntryType, Parser, Payload};
+use wasmer_middlewares::debugger::Breakpoint;
+
+/// An error reading the [`SourceMap`] of a module.
+#[derive(Error, Debug)]
+pub enum SourceMapError {
+    /// The module is malformed.
+    #[error("invalid module: {0}")]
+    Wasm(#[from] BinaryReaderError),
+    /// The DWARF information of the module is malformed.
+    #[error("invalid DWARF information: {0}")]
+    Dwarf(#[from] gimli::Error),
+}
+
+/// A location in the sources of the guest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The path of the source file, as recorded when compiling the guest.
+    pub path: PathBuf,
+    /// The line, starting at 1.
+    pub line: u64,
+    /// The column, starting at 1, or 0 if unknown.
+    pub column: u64,
+}
+
+/// A row of the line programs: the instructions of `addresses` come from a location.
+#[derive(Debug)]
+struct LineRow {
+    addresses: Range<u64>,
+    file: usize,
+    line: u64,
+    column: u64,
+    is_stmt: bool,
+}
+
+/// A function body of the module.
+#[derive(Debug)]
+struct FunctionBody {
+    /// The index of the function, imported functions included.
+    index: u32,
+    /// The range of the body in the module.
+    range: Range<usize>,
+    /// The offsets of the operators of the body in the module.
+    operators: Vec<usize>,
+}
+
+/// The mapping between the instructions of a module and its sources.
+///
+/// A module without DWARF line information has an empty mapping.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// The offset in the module of the content of the code section.
+    code_section_offset: usize,
+    functions: Vec<FunctionBody>,
+    files: Vec<PathBuf>,
+    /// The rows of the line programs, by address.
+    rows: Vec<LineRow>,
+}
+
+impl SourceMap {
+    /// Reads the mapping of the module `wasm`, from its `.debug_*` custom sections.
+    pub fn new(wasm: &[u8]) -> Result<Self, SourceMapError> {
+        let mut source_map = Self::default();
+        let mut sections = HashMap::new();
+        let mut num_imported_functions = 0;
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::ImportSection(imports) => {
+                    for import in imports {
+                        if let ImportSectionEntryType::Function(_) = import?.ty {
+                            num_imported_functions += 1;
+                        }
+                    }
+                }
+                Payload::CodeSectionStart { range, .. } => {
+                    source_map.code_section_offset = range.start;
+                }
+                Payload::CodeSectionEntry(body) => {
+                    let reader = body.get_binary_reader();
+                    let start = reader.original_position();
+                    let mut operators = Vec::new();
+                    let mut operators_reader = body.get_operators_reader()?;
+                    while !operators_reader.eof() {
+                        operators.push(operators_reader.read_with_offset()?.1);
+                    }
+                    source_map.functions.push(FunctionBody {
+                        index: num_imported_functions + source_map.functions.len() as u32,
+                        range: start..start + reader.bytes_remaining(),
+                        operators,
+                    });
+                }
+                Payload::CustomSection { name, data, .. } if name.starts_with(".debug_") => {
+                    sections.insert(name, data);
+                }
+                _ => {}
+            }
+        }
+        if sections.contains_key(".debug_line") {
+            source_map.read_line_programs(&sections)?;
+        }
+        Ok(source_map)
+    }
+
+    /// Returns whether the module has no line information.
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn read_line_programs(&mut self, sections: &HashMap<&str, &[u8]>) -> gimli::Result<()> {
+        let load = |id: gimli::SectionId| -> gimli::Result<_> {
+            let data = sections.get(id.name()).cloned().unwrap_or_default();
+            Ok(EndianSlice::new(data, LittleEndian))
+        };
+        let no_supplementary = |_| Ok(EndianSlice::new(&[][..], LittleEndian));
+        let dwarf = gimli::Dwarf::load(load, no_supplementary)?;
+
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+            let comp_dir = unit
+                .comp_dir
+                .map(|dir| PathBuf::from(dir.to_string_lossy().as_ref()));
+            let mut files = HashMap::new();
+            let mut previous: Option<LineRow> = None;
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                // A row covers the instructions up to the next row of its sequence.
+                if let Some(mut previous) = previous.take() {
+                    previous.addresses.end = row.address();
+                    if !previous.addresses.is_empty() {
+                        self.rows.push(previous);
+                    }
+                }
+                if row.end_sequence() {
+                    continue;
+                }
+
+                let file = match files.get(&row.file_index()) {
+                    Some(&file) => file,
+                    None => {
+                        let entry = match row.file(header) {
+                            Some(entry) => entry,
+                            None => continue,
+                        };
+                        let mut path = comp_dir.clone().unwrap_or_default();
+                        if let Some(directory) = entry.directory(header) {
+                            let directory = dwarf.attr_string(&unit, directory)?;
+                            path.push(directory.to_string_lossy().as_ref());
+                        }
+                        let name = dwarf.attr_string(&unit, entry.path_name())?;
+                        path.push(name.to_string_lossy().as_ref());
+                        self.files.push(path);
+                        files.insert(row.file_index(), self.files.len() - 1);
+                        self.files.len() - 1
+                    }
+                };
+                let column = match row.column() {
+                    gimli::ColumnType::LeftEdge => 0,
+                    gimli::ColumnType::Column(column) => column,
+                };
+                previous = Some(LineRow {
+                    addresses: row.address()..row.address(),
+                    file,
+                    line: row.line().unwrap_or(0),
+                    column,
+                    is_stmt: row.is_stmt(),
+                });
+            }
+        }
+        self.rows.sort_by_key(|row| row.addresses.start);
+        Ok(())
+    }
+
+    /// Returns the source location of the instruction at `module_offset`, as reported by
+    /// [`FrameInfo::module_offset`](wasmer::FrameInfo::module_offset).
+    pub fn location(&self, module_offset: usize) -> Option<SourceLocation> {
+        let address = module_offset.checked_sub(self.code_section_offset)? as u64;
+        let index = match self
+            .rows
+            .binary_search_by_key(&address, |row| row.addresses.start)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let row = &self.rows[index];
+        if !row.addresses.contains(&address) || row.line == 0 {
+            return None;
+        }
+        Some(SourceLocation {
+            path: self.files[row.file].clone(),
+            line: row.line,
+            column: row.column,
+        })
+    }
+
+    /// Returns the breakpoints at the start of every statement of the sources, to compile
+    /// the module with, so that breakpoints can be set on any line.
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        let mut breakpoints = self
+            .rows
+            .iter()
+            .filter(|row| row.is_stmt && row.line != 0)
+            .filter_map(|row| self.breakpoint(row.addresses.start))
+            .collect::<Vec<_>>();
+        breakpoints.sort_by_key(|breakpoint| (breakpoint.function, breakpoint.offset));
+        breakpoints.dedup();
+        breakpoints
+    }
+
+    /// Returns the first line from `line` of the source file `path` which has statements,
+    /// and the breakpoints at the start of these statements.
+    ///
+    /// `path` matches the files recorded with a relative path when it ends with it.
+    pub fn line_breakpoints(&self, path: &Path, line: u64) -> Option<(u64, Vec<Breakpoint>)> {
+        let files = self
+            .files
+            .iter()
+            .map(|file| path == file || (file.is_relative() && path.ends_with(file)))
+            .collect::<Vec<_>>();
+        let rows = self
+            .rows
+            .iter()
+            .filter(|row| files[row.file] && row.is_stmt && row.line >= line);
+        let line = rows.clone().map(|row| row.line).min()?;
+        let mut breakpoints = rows
+            .filter(|row| row.line == line)
+            .filter_map(|row| self.breakpoint(row.addresses.start))
+            .collect::<Vec<_>>();
+        breakpoints.sort_by_key(|breakpoint| (breakpoint.function, breakpoint.offset));
+        breakpoints.dedup();
+        Some((line, breakpoints))
+    }
+
+    /// Returns the breakpoint at the first operator from `address` in its function.
+    fn breakpoint(&self, address: u64) -> Option<Breakpoint> {
+        let module_offset = self.code_section_offset + address as usize;
+        let function = self
+            .functions
+            .iter()
+            .find(|function| function.range.contains(&module_offset))?;
+        let operator = function
+            .operators
+            .iter()
+            .find(|&&operator| operator >= module_offset)?;
+        Some(Breakpoint {
+            function: function.index,
+            offset: operator - function.range.start,
+        })
+    }
+}
+
+/// Builds the modules carrying DWARF line information of the tests.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use gimli::write::{
+        Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections,
+    };
+    use gimli::LittleEndian;
+    use wasmer::wasmparser::{Parser, Payload};
+
+    /// The compilation directory of the sources of the fixtures.
+    pub const COMP_DIR: &str = "/guest";
+
+    /// Returns `wasm` with the DWARF line information mapping the operators of its first
+    /// function body to lines of `file`: the operator `i` comes from the line `lines[i]`.
+    pub fn with_lines(wasm: &[u8], file: &str, lines: &[u64]) -> Vec<u8> {
+        let mut code_section_offset = 0;
+        let mut operators = Vec::new();
+        let mut body_end = 0;
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload.unwrap() {
+                Payload::CodeSectionStart { range, .. } => code_section_offset = range.start,
+                Payload::CodeSectionEntry(body) if operators.is_empty() => {
+                    let reader = body.get_binary_reader();
+                    body_end = reader.original_position() + reader.bytes_remaining();
+                    let mut operators_reader = body.get_operators_reader().unwrap();
+                    while !operators_reader.eof() {
+                        operators.push(operators_reader.read_with_offset().unwrap().1);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+        let mut program = LineProgram::new(
+            encoding,
+            gimli::LineEncoding::default(),
+            LineString::String(COMP_DIR.as_bytes().to_vec()),
+            LineString::String(file.as_bytes().to_vec()),
+            None,
+        );
+        let directory = program.default_directory();
+        let file = program.add_file(
+            LineString::String(file.as_bytes().to_vec()),
+            directory,
+            None,
+        );
+        program.begin_sequence(Some(Address::Constant(0)));
+        for (operator, line) in operators.iter().zip(lines) {
+            program.row().address_offset = (operator - code_section_offset) as u64;
+            program.row().file = file;
+            program.row().line = *line;
+            program.generate_row();
+        }
+        program.end_sequence((body_end - code_section_offset) as u64);
+
+        let mut dwarf = DwarfUnit::new(encoding);
+        dwarf.unit.line_program = program;
+        let root = dwarf.unit.root();
+        dwarf.unit.get_mut(root).set(
+            gimli::DW_AT_comp_dir,
+            AttributeValue::String(COMP_DIR.as_bytes().to_vec()),
+        );
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+
+        let mut module = wasm.to_vec();
+        sections
+            .for_each(|id, data| -> gimli::write::Result<()> {
+                if !data.slice().is_empty() {
+                    append_custom_section(&mut module, id.name(), data.slice());
+                }
+                Ok(())
+            })
+            .unwrap();
+        module
+    }
+
+    fn append_custom_section(module: &mut Vec<u8>, name: &str, data: &[u8]) {
+        let mut content = Vec::new();
+        write_u32(&mut content, name.len() as u32);
+        content.extend(name.as_bytes());
+        content.extend(data);
+        module.push(0);
+        write_u32(module, content.len() as u32);
+        module.extend(content);
+    }
+
+    fn write_u32(bytes: &mut Vec<u8>, mut value: u32) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const WAT: &str = r#"(module
+        (import "env" "log" (func $log (param i32)))
+        (func (export "add") (param i32 i32) (result i32)
+            local.get 0
+            local.get 1
+            i32.add))"#;
+
+    #[test]
+    fn source_map_locates_the_instructions() {
+        let wasm = wasmer::wat2wasm(WAT.as_bytes()).unwrap();
+        assert!(SourceMap::new(&wasm).unwrap().is_empty());
+
+        // `local.get 0` comes from the line 3, the rest from the line 5.
+        let wasm = fixture::with_lines(&wasm, "src/add.c", &[3, 5, 5, 5]);
+        let source_map = SourceMap::new(&wasm).unwrap();
+        let add = &source_map.functions[0];
+        assert_eq!(add.index, 1);
+        let location = |operator: usize| source_map.location(add.operators[operator]);
+        let line = |line| SourceLocation {
+            path: PathBuf::from("/guest/src/add.c"),
+            line,
+            column: 0,
+        };
+        assert_eq!(location(0), Some(line(3)));
+        assert_eq!(location(2), Some(line(5)));
+        assert_eq!(source_map.location(add.range.start), None);
+
+        // The local declarations are 1 byte, and each `local.get` 2 bytes.
+        let breakpoint = |offset| Breakpoint {
+            function: 1,
+            offset,
+        };
+        assert_eq!(
+            source_map.breakpoints(),
+            vec![breakpoint(1), breakpoint(3), breakpoint(5), breakpoint(6)]
+        );
+        assert_eq!(
+            source_map.line_breakpoints(Path::new("/work/guest/src/add.c"), 4),
+            None
+        );
+        assert_eq!(
+            source_map.line_breakpoints(Path::new("/guest/src/add.c"), 4),
+            Some((5, vec![breakpoint(3), breakpoint(5), breakpoint(6)]))
+        );
+        assert_eq!(
+            source_map.line_breakpoints(Path::new("/guest/src/add.c"), 6),
+            None
+        );
+    }
+}
@@ -56,6 +56,7 @@ mod pthread;
 mod ptr;
 mod signal;
 mod storage;
+mod syscall_filter;
 mod syscalls;
 mod time;
 mod ucontext;
@@ -64,9 +65,11 @@ mod utils;
 mod varargs;
 
 pub use self::storage::{align_memory, static_alloc};
+pub use self::syscall_filter::SyscallFilter;
 pub use self::utils::{
-    allocate_cstr_on_stack, allocate_on_stack, get_emscripten_memory_size, get_emscripten_metadata,
-    get_emscripten_table_size, is_emscripten_module,
+    allocate_cstr_on_stack, allocate_on_stack, get_emscripten_dylink_info,
+    get_emscripten_memory_size, get_emscripten_metadata, get_emscripten_table_size,
+    is_emscripten_module, DylinkInfo,
 };
 
 #[derive(Clone)]
@@ -530,6 +533,17 @@ pub fn generate_emscripten_env(
     store: &Store,
     globals: &mut EmscriptenGlobals,
     env: &EmEnv,
+) -> ImportObject {
+    generate_emscripten_env_with_syscalls(store, globals, env, &SyscallFilter::allow_all())
+}
+
+/// Generates the emscripten imports, forwarding to the host only the
+/// syscalls allowed by `syscalls`; the other ones return `-ENOSYS`.
+pub fn generate_emscripten_env_with_syscalls(
+    store: &Store,
+    globals: &mut EmscriptenGlobals,
+    env: &EmEnv,
+    syscalls: &SyscallFilter,
 ) -> ImportObject {
     let abort_on_cannot_grow_memory_export = if globals.data.use_old_abort_on_cannot_grow_memory {
         Function::new_native_with_env(
@@ -1008,6 +1022,8 @@ pub fn generate_emscripten_env(
         );
     }
 
+    syscalls.apply(store, &mut env_ns);
+
     let import_object: ImportObject = imports! {
         "env" => env_ns,
         "global" => {
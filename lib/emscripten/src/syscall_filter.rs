@@ -0,0 +1,118 @@
+//! The set of syscalls emulated for emscripten programs.
+//!
+//! Emscripten programs import their syscalls as `env.___syscallN`. By
+//! default all of them are forwarded to the host; a [`SyscallFilter`]
+//! restricts them to an allow-list; the other ones fail with `-ENOSYS`
+//! without reaching the host. `exit` is never filtered out: it only ends
+//! the program.
+
+use std::collections::BTreeSet;
+use wasmer::{Exports, Extern, Function, Store, Val};
+
+/// The import name prefix of the emscripten syscalls.
+const SYSCALL_PREFIX: &str = "___syscall";
+
+/// The errno returned by the filtered out syscalls.
+const ENOSYS: i32 = 38;
+
+/// The `exit` syscall, always allowed.
+const SYS_EXIT: u32 = 1;
+
+/// Syscalls reading or writing files and directories.
+const FILESYSTEM_SYSCALLS: &[u32] = &[
+    3, 4, 5, 6, 9, 10, 12, 15, 38, 39, 40, 54, 57, 60, 63, 83, 85, 110, 118, 122, 140, 145, 146,
+    148, 168, 180, 181, 183, 192, 194, 195, 196, 197, 198, 207, 212, 219, 220, 221, 268, 272, 295,
+    296, 297, 298, 300, 320, 324, 330, 333, 334, 340,
+];
+
+/// Syscalls about the process and its resources, without side effects
+/// on the host.
+const PROCESS_SYSCALLS: &[u32] = &[20, 36, 64, 75, 77, 91, 114, 191, 199, 200, 201, 202];
+
+/// Which emscripten syscalls are forwarded to the host.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyscallFilter {
+    /// The allowed syscall numbers, `None` to allow them all.
+    allowed: Option<BTreeSet<u32>>,
+}
+
+impl SyscallFilter {
+    /// Creates a filter allowing all the syscalls, the default.
+    pub fn allow_all() -> Self {
+        Self { allowed: None }
+    }
+
+    /// Creates a filter denying all the syscalls; allow some with
+    /// [`SyscallFilter::allow`].
+    pub fn deny_all() -> Self {
+        Self {
+            allowed: Some(BTreeSet::new()),
+        }
+    }
+
+    /// Allows the syscall `number`.
+    pub fn allow(&mut self, number: u32) -> &mut Self {
+        if let Some(allowed) = &mut self.allowed {
+            allowed.insert(number);
+        }
+
+        self
+    }
+
+    /// Allows the syscalls accessing files and directories. The paths in
+    /// the mapped directories of the [`EmEnv`](crate::EmEnv) are
+    /// translated to their host directories.
+    pub fn allow_filesystem(&mut self) -> &mut Self {
+        for number in FILESYSTEM_SYSCALLS {
+            self.allow(*number);
+        }
+
+        self
+    }
+
+    /// Allows the syscalls querying or managing the process itself:
+    /// ids, limits, memory mappings.
+    pub fn allow_process(&mut self) -> &mut Self {
+        for number in PROCESS_SYSCALLS {
+            self.allow(*number);
+        }
+
+        self
+    }
+
+    /// Returns whether the syscall `number` is allowed.
+    pub fn is_allowed(&self, number: u32) -> bool {
+        number == SYS_EXIT
+            || self
+                .allowed
+                .as_ref()
+                .map_or(true, |allowed| allowed.contains(&number))
+    }
+
+    /// Replaces the syscall imports of `env_ns` not allowed by the filter
+    /// with functions of the same type returning `-ENOSYS`.
+    pub(crate) fn apply(&self, store: &Store, env_ns: &mut Exports) {
+        let denied = env_ns
+            .iter()
+            .filter_map(|(name, export)| {
+                let number = name.strip_prefix(SYSCALL_PREFIX)?.parse().ok()?;
+                match export {
+                    Extern::Function(function) if !self.is_allowed(number) => {
+                        Some((name.clone(), function.ty().clone()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (name, ty) in denied {
+            let returns = ty.results().len();
+            let stub_name = name.clone();
+            let stub = Function::new(store, &ty, move |_args| {
+                debug!("emscripten::{} denied by the syscall filter", stub_name);
+                Ok(vec![Val::I32(-ENOSYS); returns])
+            });
+            env_ns.insert(name, stub);
+        }
+    }
+}
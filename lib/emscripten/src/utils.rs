@@ -8,22 +8,11 @@ use std::mem::size_of;
 use std::os::raw::c_char;
 use std::path::PathBuf;
 use std::slice;
-use wasmer::{GlobalInit, Memory, Module, Pages};
+use wasmer::{GlobalInit, Memory, Module, ModuleKind, Pages};
 
 /// We check if a provided module is an Emscripten generated one
 pub fn is_emscripten_module(module: &Module) -> bool {
-    for import in module.imports().functions() {
-        let name = import.name();
-        let module = import.module();
-        if (name == "_emscripten_memcpy_big"
-            || name == "emscripten_memcpy_big"
-            || name == "__map_file")
-            && module == "env"
-        {
-            return true;
-        }
-    }
-    false
+    ModuleKind::detect(module) == ModuleKind::Emscripten
 }
 
 pub fn get_emscripten_table_size(module: &Module) -> Result<(u32, Option<u32>), String> {
@@ -93,6 +82,97 @@ pub fn get_emscripten_metadata(module: &Module) -> Result<Option<(u32, u32)>, St
     }
 }
 
+/// The dynamic linking metadata of an Emscripten side module, as
+/// stored in its `dylink` custom section.
+///
+/// Side modules are position independent: the loader reserves
+/// `memory_size` bytes of linear memory (aligned to `2^memory_alignment`)
+/// and `table_size` slots in the shared table before instantiating
+/// them, and loads every library in `needed_dynlibs` first.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DylinkInfo {
+    pub memory_size: u32,
+    pub memory_alignment: u32,
+    pub table_size: u32,
+    pub table_alignment: u32,
+    pub needed_dynlibs: Vec<String>,
+}
+
+/// Reads the `dylink` custom section of an Emscripten side module.
+///
+/// Returns `Ok(None)` if the module is not a side module.
+pub fn get_emscripten_dylink_info(module: &Module) -> Result<Option<DylinkInfo>, String> {
+    let section = match module.custom_sections("dylink").next() {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let mut reader = DylinkReader {
+        bytes: &section,
+        position: 0,
+    };
+    let memory_size = reader.read_var_u32()?;
+    let memory_alignment = reader.read_var_u32()?;
+    let table_size = reader.read_var_u32()?;
+    let table_alignment = reader.read_var_u32()?;
+    let needed_count = reader.read_var_u32()?;
+    let mut needed_dynlibs = Vec::with_capacity(needed_count as usize);
+    for _ in 0..needed_count {
+        needed_dynlibs.push(reader.read_string()?);
+    }
+    Ok(Some(DylinkInfo {
+        memory_size,
+        memory_alignment,
+        table_size,
+        table_alignment,
+        needed_dynlibs,
+    }))
+}
+
+struct DylinkReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> DylinkReader<'a> {
+    fn read_byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or_else(|| "unexpected end of the `dylink` section".to_string())?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn read_var_u32(&mut self) -> Result<u32, String> {
+        let mut result: u32 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_byte()?;
+            if shift == 28 && byte > 0x0f {
+                return Err("invalid LEB128 value in the `dylink` section".to_string());
+            }
+            result |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        let len = self.read_var_u32()? as usize;
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| "unexpected end of the `dylink` section".to_string())?;
+        let string = std::str::from_utf8(&self.bytes[self.position..end])
+            .map_err(|e| format!("invalid library name in the `dylink` section: {}", e))?;
+        self.position = end;
+        Ok(string.to_string())
+    }
+}
+
 pub unsafe fn write_to_buf(ctx: &EmEnv, string: *const c_char, buf: u32, max: u32) -> u32 {
     let buf_addr = emscripten_memory_pointer!(ctx.memory(0), buf) as *mut c_char;
 
@@ -1,18 +1,23 @@
 //! Define `JITArtifact` to allow compiling and instantiating to be
 //! done as separate steps.
 
+use crate::code_memory::WritableCode;
 use crate::engine::{JITEngine, JITEngineInner};
-use crate::link::link_module;
-#[cfg(feature = "compiler")]
-use crate::serialize::SerializableCompilation;
-use crate::serialize::SerializableModule;
+use crate::link::{is_position_independent, link_module};
+use crate::serialize::{
+    SerializableCode, SerializableCompilation, SerializableFeatureTier, SerializableModule,
+};
+use std::collections::HashSet;
+#[cfg(feature = "compression")]
+use std::io::Read;
+use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, Mutex};
-use wasmer_compiler::{CompileError, Features, Triple};
+use wasmer_compiler::{CodeHardening, CompileError, CpuFeature, EnumSet, Features, Triple};
 #[cfg(feature = "compiler")]
-use wasmer_compiler::{CompileModuleInfo, ModuleEnvironment};
+use wasmer_compiler::{CompileModuleInfo, ModuleEnvironment, Target};
 use wasmer_engine::{
-    register_frame_info, Artifact, DeserializeError, FunctionExtent, GlobalFrameInfoRegistration,
-    SerializeError,
+    read_format_version, register_frame_info, write_format_version, Artifact, DeserializeError,
+    FunctionExtent, GlobalFrameInfoRegistration, SerializeError,
 };
 #[cfg(feature = "compiler")]
 use wasmer_engine::{Engine, SerializableFunctionFrameInfo, Tunables};
@@ -34,10 +39,27 @@ pub struct JITArtifact {
     signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
     frame_info_registration: Mutex<Option<GlobalFrameInfoRegistration>>,
     finished_function_lengths: BoxedSlice<LocalFunctionIndex, usize>,
+    /// The functions sharing the body of a function allocated earlier.
+    shared_functions: HashSet<LocalFunctionIndex>,
+    /// The call counters of the local functions, if the module was
+    /// compiled to count their calls.
+    call_counters: Option<Arc<[AtomicU64]>>,
+    /// The index of the feature tier whose code is loaded, if any.
+    feature_tier: Option<usize>,
 }
 
 impl JITArtifact {
     const MAGIC_HEADER: &'static [u8] = b"\0wasmer-jit";
+    /// The header of the artifacts whose metadata is compressed with zstd.
+    /// It starts with `MAGIC_HEADER`, so it must be checked first.
+    ///
+    /// It is followed by the format version, the length of the code as a
+    /// little-endian `u64`, the code uncompressed, and the rest of the
+    /// module compressed.
+    const MAGIC_HEADER_COMPRESSED: &'static [u8] = b"\0wasmer-jit-zstd";
+    /// The maximum size of the decompressed metadata of an artifact, so
+    /// that a small corrupted artifact can't exhaust the memory.
+    const MAX_DECOMPRESSED_SIZE: u64 = 1 << 30;
 
     /// Check if the provided bytes look like a serialized `JITArtifact`.
     pub fn is_deserializable(bytes: &[u8]) -> bool {
@@ -51,8 +73,43 @@ impl JITArtifact {
         data: &[u8],
         tunables: &dyn Tunables,
     ) -> Result<Self, CompileError> {
-        let environ = ModuleEnvironment::new();
         let mut inner_jit = jit.inner_mut();
+        let mut serializable = Self::compile(&inner_jit, jit.target(), data, tunables, None)?;
+
+        // Compile the variants for the feature tiers of the compiler. They
+        // reuse the module transformed by the middlewares, which can only
+        // transform a single module.
+        let compile_info = &serializable.compile_info;
+        serializable.feature_tiers = inner_jit
+            .compiler()?
+            .feature_tiers()
+            .into_iter()
+            .map(|cpu_features| {
+                let target = Target::new(jit.target().triple().clone(), cpu_features);
+                let compilation =
+                    Self::compile(&inner_jit, &target, data, tunables, Some(compile_info))?
+                        .compilation;
+                Ok(SerializableFeatureTier::new(cpu_features, compilation))
+            })
+            .collect::<Result<Vec<_>, CompileError>>()?;
+
+        Self::from_parts(&mut inner_jit, serializable)
+    }
+
+    /// Compiles a data buffer for `target`, without feature tiers.
+    ///
+    /// The module of `compile_info` is compiled if it's given, instead of
+    /// the translated one: it's the module already compiled for another
+    /// target.
+    #[cfg(feature = "compiler")]
+    fn compile(
+        inner_jit: &JITEngineInner,
+        target: &Target,
+        data: &[u8],
+        tunables: &dyn Tunables,
+        compile_info: Option<&CompileModuleInfo>,
+    ) -> Result<SerializableModule, CompileError> {
+        let environ = ModuleEnvironment::new();
         let features = inner_jit.features();
 
         let translation = environ.translate(data).map_err(CompileError::Wasm)?;
@@ -70,18 +127,24 @@ impl JITArtifact {
             .map(|table_type| tunables.table_style(table_type))
             .collect();
 
-        let mut compile_info = CompileModuleInfo {
-            module: Arc::new(translation.module),
-            features: features.clone(),
-            memory_styles,
-            table_styles,
-        };
-
         let compiler = inner_jit.compiler()?;
+        let mut compile_info = match compile_info {
+            Some(compile_info) => compile_info.clone(),
+            None => CompileModuleInfo {
+                module: Arc::new(translation.module),
+                features: features.clone(),
+                memory_styles,
+                table_styles,
+                hardening: compiler.code_hardening(),
+                count_function_calls: inner_jit.count_function_calls
+                    && compiler.supports_call_counters(),
+                middlewares_applied: false,
+            },
+        };
 
         // Compile the Module
         let compilation = compiler.compile_module(
-            &jit.target(),
+            target,
             &mut compile_info,
             // SAFETY: Calling `unwrap` is correct since
             // `environ.translate()` above will write some data into
@@ -116,12 +179,12 @@ impl JITArtifact {
             custom_section_relocations: compilation.get_custom_section_relocations(),
             debug: compilation.get_debug(),
         };
-        let serializable = SerializableModule {
+        Ok(SerializableModule {
             compilation: serializable_compilation,
             compile_info,
             data_initializers,
-        };
-        Self::from_parts(&mut inner_jit, serializable)
+            feature_tiers: Vec::new(),
+        })
     }
 
     /// Compile a data buffer into a `JITArtifact`, which may then be instantiated.
@@ -132,6 +195,34 @@ impl JITArtifact {
         ))
     }
 
+    /// Decompresses the metadata of a compressed artifact, failing if it
+    /// is larger than `max_size`.
+    #[cfg(feature = "compression")]
+    fn decompress(compressed: &[u8], max_size: u64) -> Result<Vec<u8>, DeserializeError> {
+        let decoder = zstd::stream::read::Decoder::new(compressed)?;
+        let mut decompressed = Vec::new();
+        decoder
+            .take(max_size + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
+        if decompressed.len() as u64 > max_size {
+            return Err(DeserializeError::CorruptedBinary(format!(
+                "The decompressed artifact is larger than {} bytes",
+                max_size
+            )));
+        }
+        Ok(decompressed)
+    }
+
+    /// Decompresses the metadata of a compressed artifact, failing if it
+    /// is larger than `max_size`.
+    #[cfg(not(feature = "compression"))]
+    fn decompress(_compressed: &[u8], _max_size: u64) -> Result<Vec<u8>, DeserializeError> {
+        Err(DeserializeError::Incompatible(
+            "The artifact is compressed, but the `compression` feature is not enabled".to_string(),
+        ))
+    }
+
     /// Deserialize a JITArtifact
     pub fn deserialize(jit: &JITEngine, bytes: &[u8]) -> Result<Self, DeserializeError> {
         if !Self::is_deserializable(bytes) {
@@ -140,15 +231,48 @@ impl JITArtifact {
             ));
         }
 
-        let inner_bytes = &bytes[Self::MAGIC_HEADER.len()..];
+        let serializable = if bytes.starts_with(Self::MAGIC_HEADER_COMPRESSED) {
+            let inner_bytes = read_format_version(&bytes[Self::MAGIC_HEADER_COMPRESSED.len()..])?;
+            Self::deserialize_compressed(inner_bytes)?
+        } else {
+            let inner_bytes = read_format_version(&bytes[Self::MAGIC_HEADER.len()..])?;
 
-        // let r = flexbuffers::Reader::get_root(bytes).map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
-        // let serializable = SerializableModule::deserialize(r).map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
+            // let r = flexbuffers::Reader::get_root(bytes).map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
+            // let serializable = SerializableModule::deserialize(r).map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
 
-        let serializable: SerializableModule = bincode::deserialize(inner_bytes)
-            .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
+            bincode::deserialize(inner_bytes)
+                .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?
+        };
 
-        Self::from_parts(&mut jit.inner_mut(), serializable).map_err(DeserializeError::Compiler)
+        let mut inner_jit = jit.inner_mut();
+        inner_jit.check_hardening(&serializable.compile_info.hardening)?;
+        Self::from_parts(&mut inner_jit, serializable).map_err(DeserializeError::Compiler)
+    }
+
+    /// Deserializes the module of a compressed artifact, after its
+    /// format version.
+    fn deserialize_compressed(bytes: &[u8]) -> Result<SerializableModule, DeserializeError> {
+        let corrupted = |e: bincode::Error| DeserializeError::CorruptedBinary(format!("{:?}", e));
+        if bytes.len() < 8 {
+            return Err(DeserializeError::CorruptedBinary(
+                "The compressed artifact is truncated".to_string(),
+            ));
+        }
+        let mut code_len = [0; 8];
+        code_len.copy_from_slice(&bytes[..8]);
+        let code_len = u64::from_le_bytes(code_len);
+        if code_len > (bytes.len() - 8) as u64 {
+            return Err(DeserializeError::CorruptedBinary(
+                "The compressed artifact is truncated".to_string(),
+            ));
+        }
+        let (code, compressed) = bytes[8..].split_at(code_len as usize);
+        let codes: Vec<SerializableCode> = bincode::deserialize(code).map_err(corrupted)?;
+        let decompressed = Self::decompress(compressed, Self::MAX_DECOMPRESSED_SIZE)?;
+        let mut serializable: SerializableModule =
+            bincode::deserialize(&decompressed).map_err(corrupted)?;
+        serializable.restore_code(codes)?;
+        Ok(serializable)
     }
 
     /// Construct a `JITArtifact` from component parts.
@@ -156,46 +280,54 @@ impl JITArtifact {
         inner_jit: &mut JITEngineInner,
         serializable: SerializableModule,
     ) -> Result<Self, CompileError> {
+        // Compute indices into the shared signature table.
+        let signatures = {
+            let signature_registry = inner_jit.signatures();
+            serializable
+                .compile_info
+                .module
+                .signatures
+                .values()
+                .map(|sig| signature_registry.register(sig))
+                .collect::<PrimaryMap<_, _>>()
+        };
+
+        let feature_tier = Self::select_feature_tier(&serializable.feature_tiers);
+        let compilation = match feature_tier {
+            Some(index) => &serializable.feature_tiers[index].compilation,
+            None => &serializable.compilation,
+        };
+
         let (
             finished_functions,
             finished_function_call_trampolines,
             finished_dynamic_function_trampolines,
             custom_sections,
+            shared_functions,
         ) = inner_jit.allocate(
             &serializable.compile_info.module,
-            &serializable.compilation.function_bodies,
-            &serializable.compilation.function_call_trampolines,
-            &serializable.compilation.dynamic_function_trampolines,
-            &serializable.compilation.custom_sections,
+            &compilation.function_bodies,
+            &compilation.function_relocations,
+            &signatures,
+            &compilation.function_call_trampolines,
+            &compilation.dynamic_function_trampolines,
+            &compilation.custom_sections,
         )?;
 
+        let writable_code = WritableCode::new();
         link_module(
             &serializable.compile_info.module,
             &finished_functions,
-            &serializable.compilation.function_jt_offsets,
-            serializable.compilation.function_relocations.clone(),
+            &compilation.function_jt_offsets,
+            compilation.function_relocations.clone(),
             &custom_sections,
-            &serializable.compilation.custom_section_relocations,
+            &compilation.custom_section_relocations,
         );
+        drop(writable_code);
 
-        // Compute indices into the shared signature table.
-        let signatures = {
-            let signature_registry = inner_jit.signatures();
-            serializable
-                .compile_info
-                .module
-                .signatures
-                .values()
-                .map(|sig| signature_registry.register(sig))
-                .collect::<PrimaryMap<_, _>>()
-        };
-
-        let eh_frame = match &serializable.compilation.debug {
+        let eh_frame = match &compilation.debug {
             Some(debug) => {
-                let eh_frame_section_size = serializable.compilation.custom_sections
-                    [debug.eh_frame]
-                    .bytes
-                    .len();
+                let eh_frame_section_size = compilation.custom_sections[debug.eh_frame].bytes.len();
                 let eh_frame_section_pointer = custom_sections[debug.eh_frame];
                 Some(unsafe {
                     std::slice::from_raw_parts(*eh_frame_section_pointer, eh_frame_section_size)
@@ -203,8 +335,16 @@ impl JITArtifact {
             }
             None => None,
         };
-        // Make all code compiled thus far executable.
-        inner_jit.publish_compiled_code();
+        // Make all code compiled thus far executable. The functions sharing
+        // the body of another artifact depend on where it's loaded.
+        let position_independent = shared_functions.is_empty()
+            && is_position_independent(
+                &compilation.function_relocations,
+                &compilation.custom_sections,
+                &compilation.custom_section_relocations,
+            );
+        inner_jit
+            .publish_compiled_code(position_independent, &serializable.compile_info.hardening)?;
 
         inner_jit.publish_eh_frame(eh_frame)?;
 
@@ -223,6 +363,16 @@ impl JITArtifact {
         let finished_dynamic_function_trampolines =
             finished_dynamic_function_trampolines.into_boxed_slice();
         let signatures = signatures.into_boxed_slice();
+        let call_counters = if serializable.compile_info.count_function_calls {
+            Some(
+                finished_functions
+                    .values()
+                    .map(|_| AtomicU64::new(0))
+                    .collect(),
+            )
+        } else {
+            None
+        };
 
         Ok(Self {
             serializable,
@@ -232,9 +382,44 @@ impl JITArtifact {
             signatures,
             frame_info_registration: Mutex::new(None),
             finished_function_lengths,
+            shared_functions,
+            call_counters,
+            feature_tier,
         })
     }
 
+    /// Returns the index of the feature tier using the most CPU features,
+    /// all supported by the host, if any.
+    fn select_feature_tier(feature_tiers: &[SerializableFeatureTier]) -> Option<usize> {
+        let host_features = CpuFeature::for_host();
+        feature_tiers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, tier)| Some((index, tier.cpu_features()?)))
+            .filter(|(_, cpu_features)| cpu_features.is_subset(host_features))
+            .max_by_key(|(_, cpu_features)| cpu_features.len())
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the compilation loaded for the host: the one of the
+    /// selected feature tier, or the one for the target of the engine.
+    fn compilation(&self) -> &SerializableCompilation {
+        match self.feature_tier {
+            Some(index) => &self.serializable.feature_tiers[index].compilation,
+            None => &self.serializable.compilation,
+        }
+    }
+
+    /// Returns the CPU features of the feature tier loaded for the host,
+    /// or `None` if the code compiled for the target of the engine is
+    /// loaded, see [`CompilerConfig::feature_tiers`].
+    ///
+    /// [`CompilerConfig::feature_tiers`]: wasmer_compiler::CompilerConfig::feature_tiers
+    pub fn loaded_feature_tier(&self) -> Option<EnumSet<CpuFeature>> {
+        let tier = &self.serializable.feature_tiers[self.feature_tier?];
+        tier.cpu_features()
+    }
+
     /// Get the default extension when serializing this artifact
     pub fn get_default_extension(_triple: &Triple) -> &'static str {
         // `.wjit` is the default extension for all the triples
@@ -271,10 +456,15 @@ impl Artifact for JITArtifact {
             .collect::<PrimaryMap<LocalFunctionIndex, _>>()
             .into_boxed_slice();
 
-        let frame_infos = &self.serializable.compilation.function_frame_info;
+        let frame_infos = &self.compilation().function_frame_info;
+        let (shared_function_extents, function_extents): (Vec<_>, Vec<_>) =
+            finished_function_extents
+                .iter()
+                .partition(|(index, _)| self.shared_functions.contains(index));
         *info = register_frame_info(
             self.serializable.compile_info.module.clone(),
-            &finished_function_extents,
+            function_extents,
+            shared_function_extents,
             frame_infos.clone(),
         );
     }
@@ -283,10 +473,18 @@ impl Artifact for JITArtifact {
         &self.serializable.compile_info.features
     }
 
+    fn code_hardening(&self) -> CodeHardening {
+        self.serializable.compile_info.hardening
+    }
+
     fn data_initializers(&self) -> &[OwnedDataInitializer] {
         &*self.serializable.data_initializers
     }
 
+    fn call_counters(&self) -> Option<&Arc<[AtomicU64]>> {
+        self.call_counters.as_ref()
+    }
+
     fn memory_styles(&self) -> &PrimaryMap<MemoryIndex, MemoryStyle> {
         &self.serializable.compile_info.memory_styles
     }
@@ -320,7 +518,58 @@ impl Artifact for JITArtifact {
 
         // Prepend the header.
         let mut serialized = Self::MAGIC_HEADER.to_vec();
+        write_format_version(&mut serialized);
         serialized.extend(bytes);
         Ok(serialized)
     }
+
+    /// Only the metadata (the module information, relocations, frame
+    /// info, data initializers...) is compressed: the code is stored as
+    /// it is, so that it can be read or mapped without decompressing it.
+    #[cfg(feature = "compression")]
+    fn serialize_compressed(&self, level: i32) -> Result<Vec<u8>, SerializeError> {
+        let (without_code, codes) = self.serializable.split_code();
+        let code =
+            bincode::serialize(&codes).map_err(|e| SerializeError::Generic(format!("{:?}", e)))?;
+        let metadata = bincode::serialize(&without_code)
+            .map_err(|e| SerializeError::Generic(format!("{:?}", e)))?;
+
+        // Prepend the header, then the code, and compress the metadata.
+        let mut serialized = Self::MAGIC_HEADER_COMPRESSED.to_vec();
+        write_format_version(&mut serialized);
+        serialized.extend(&(code.len() as u64).to_le_bytes());
+        serialized.extend(code);
+        zstd::stream::copy_encode(&metadata[..], &mut serialized, level)?;
+        Ok(serialized)
+    }
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompression_is_bounded() {
+        let compressed = zstd::stream::encode_all(&[0; 4097][..], 3).unwrap();
+        assert_eq!(
+            JITArtifact::decompress(&compressed, 4097).unwrap(),
+            vec![0; 4097]
+        );
+        assert!(matches!(
+            JITArtifact::decompress(&compressed, 4096),
+            Err(DeserializeError::CorruptedBinary(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_compressed_artifacts_are_rejected() {
+        assert!(matches!(
+            JITArtifact::deserialize_compressed(&[0; 4]),
+            Err(DeserializeError::CorruptedBinary(_))
+        ));
+        assert!(matches!(
+            JITArtifact::deserialize_compressed(&[1, 0, 0, 0, 0, 0, 0, 0]),
+            Err(DeserializeError::CorruptedBinary(_))
+        ));
+    }
 }
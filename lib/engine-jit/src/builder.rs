@@ -1,5 +1,8 @@
 use crate::JITEngine;
-use wasmer_compiler::{CompilerConfig, Features, Target};
+use std::path::PathBuf;
+use std::sync::Arc;
+use wasmer_compiler::{CodeHardening, CompilerConfig, Features, Target};
+use wasmer_engine::TrapSink;
 
 /// The JIT builder
 pub struct JIT {
@@ -7,6 +10,13 @@ pub struct JIT {
     compiler_config: Option<Box<dyn CompilerConfig>>,
     target: Option<Target>,
     features: Option<Features>,
+    strict_w_xor_x: bool,
+    deduplicate_functions: bool,
+    share_code_mappings: bool,
+    shared_code_directory: Option<PathBuf>,
+    count_function_calls: bool,
+    trap_sink: Option<Arc<dyn TrapSink>>,
+    required_code_hardening: CodeHardening,
 }
 
 impl JIT {
@@ -19,6 +29,13 @@ impl JIT {
             compiler_config: Some(compiler_config.into()),
             target: None,
             features: None,
+            strict_w_xor_x: false,
+            deduplicate_functions: false,
+            share_code_mappings: false,
+            shared_code_directory: None,
+            count_function_calls: false,
+            trap_sink: None,
+            required_code_hardening: CodeHardening::none(),
         }
     }
 
@@ -28,6 +45,13 @@ impl JIT {
             compiler_config: None,
             target: None,
             features: None,
+            strict_w_xor_x: false,
+            deduplicate_functions: false,
+            share_code_mappings: false,
+            shared_code_directory: None,
+            count_function_calls: false,
+            trap_sink: None,
+            required_code_hardening: CodeHardening::none(),
         }
     }
 
@@ -43,11 +67,112 @@ impl JIT {
         self
     }
 
+    /// Strictly enforce W^X on the code memory.
+    ///
+    /// The code is then never writable and executable at the same time,
+    /// which is verified when publishing it. On macOS, the code is mapped
+    /// with `MAP_JIT`, so that it runs in the hardened runtime with only
+    /// the `com.apple.security.cs.allow-jit` entitlement. On aarch64
+    /// macOS, `pthread_jit_write_protect_np` switches the pages between
+    /// writable and executable.
+    pub fn strict_w_xor_x(mut self, enable: bool) -> Self {
+        self.strict_w_xor_x = enable;
+        self
+    }
+
+    /// Share the code of the byte-identical functions of the artifacts
+    /// loaded in the engine.
+    ///
+    /// This reduces the code memory when loading many similar modules,
+    /// like Rust guests sharing monomorphized standard library code. Only
+    /// the functions without relocations are shared, that is the ones
+    /// calling neither other functions nor libcalls. The frames of a
+    /// shared function are attributed to the first module that loaded it
+    /// in the trap backtraces.
+    pub fn deduplicate_functions(mut self, enable: bool) -> Self {
+        self.deduplicate_functions = enable;
+        self
+    }
+
+    /// Map the code of the artifacts from read-only files shared by all
+    /// the engines of the process loading the same code.
+    ///
+    /// The code of an artifact is written once to a file of the shared
+    /// code directory (see [`JIT::shared_code_directory`]), and every
+    /// engine loading the same artifact maps it from there, so that the
+    /// process holds a single copy of it, in pages of the page cache
+    /// which the system can reclaim. The files are created by the process
+    /// and removed once the engines mapping them are dropped: the files of
+    /// the other processes are never mapped, since they could be rewritten
+    /// once checked.
+    ///
+    /// Only the code that doesn't depend on where it's loaded is shared,
+    /// that is the code of the artifacts whose relocations are all
+    /// relative to the code itself, and none of whose functions share
+    /// the body of an earlier artifact (see
+    /// [`JIT::deduplicate_functions`]). Not supported on Windows.
+    pub fn share_code_mappings(mut self, enable: bool) -> Self {
+        self.share_code_mappings = enable;
+        self
+    }
+
+    /// Set the directory of the code files, see
+    /// [`JIT::share_code_mappings`].
+    ///
+    /// The directory is created if needed. It must belong to the user of
+    /// the process, and not be writable by the other users: the code is
+    /// not shared otherwise. It's the `wasmer-code-<uid>` directory of the
+    /// temporary directory by default, only accessible by the user.
+    pub fn shared_code_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.shared_code_directory = Some(directory.into());
+        self
+    }
+
+    /// Count the calls of the functions of the compiled modules.
+    ///
+    /// Every function increments its counter when it's entered, in a
+    /// table shared by the instances of its module and read with
+    /// `Module::function_call_counts`. This is much cheaper than metering
+    /// the modules to find their hot functions. The counters are not
+    /// atomic: concurrent calls from several threads may be missed.
+    ///
+    /// Only the Cranelift compiler emits the counters: the modules compiled
+    /// by the other compilers have none.
+    pub fn count_function_calls(mut self, enable: bool) -> Self {
+        self.count_function_calls = enable;
+        self
+    }
+
+    /// Send the traps of the WebAssembly code run by the engine to
+    /// `trap_sink`.
+    ///
+    /// Every call from the host into WebAssembly that fails is reported
+    /// to the sink with its trap code, frames, module hash and instance,
+    /// see [`TrapSink`].
+    pub fn trap_sink(mut self, trap_sink: Arc<dyn TrapSink>) -> Self {
+        self.trap_sink = Some(trap_sink);
+        self
+    }
+
+    /// Refuse to load the artifacts compiled without `hardening`, on top
+    /// of the hardening against control-flow hijacking the compiler of the
+    /// engine applies.
+    ///
+    /// Headless engines, which only load artifacts compiled elsewhere,
+    /// enforce a hardening policy this way, as do the engines requiring
+    /// the mitigations against speculative execution attacks. The branch target
+    /// identification of the artifacts compiled with it is enforced on
+    /// aarch64 Linux, whether it's required or not.
+    pub fn required_code_hardening(mut self, hardening: CodeHardening) -> Self {
+        self.required_code_hardening = hardening;
+        self
+    }
+
     /// Build the `JITEngine` for this configuration
     #[cfg(feature = "compiler")]
     pub fn engine(self) -> JITEngine {
         let target = self.target.unwrap_or_default();
-        if let Some(compiler_config) = self.compiler_config {
+        let engine = if let Some(compiler_config) = self.compiler_config {
             let features = self
                 .features
                 .unwrap_or_else(|| compiler_config.default_features_for_target(&target));
@@ -55,12 +180,28 @@ impl JIT {
             JITEngine::new(compiler, target, features)
         } else {
             JITEngine::headless()
-        }
+        };
+        engine.set_strict_w_xor_x(self.strict_w_xor_x);
+        engine.set_deduplicate_functions(self.deduplicate_functions);
+        engine.set_share_code_mappings(self.share_code_mappings);
+        engine.set_shared_code_directory(self.shared_code_directory);
+        engine.set_count_function_calls(self.count_function_calls);
+        engine.set_trap_sink(self.trap_sink);
+        engine.set_required_code_hardening(self.required_code_hardening);
+        engine
     }
 
     /// Build the `JITEngine` for this configuration
     #[cfg(not(feature = "compiler"))]
     pub fn engine(self) -> JITEngine {
-        JITEngine::headless()
+        let engine = JITEngine::headless();
+        engine.set_strict_w_xor_x(self.strict_w_xor_x);
+        engine.set_deduplicate_functions(self.deduplicate_functions);
+        engine.set_share_code_mappings(self.share_code_mappings);
+        engine.set_shared_code_directory(self.shared_code_directory);
+        engine.set_count_function_calls(self.count_function_calls);
+        engine.set_trap_sink(self.trap_sink);
+        engine.set_required_code_hardening(self.required_code_hardening);
+        engine
     }
 }
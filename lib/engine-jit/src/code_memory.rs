@@ -3,6 +3,27 @@
 
 //! Memory management for executable code.
 use crate::unwind::UnwindRegistry;
+#[cfg(not(target_os = "windows"))]
+use lazy_static::lazy_static;
+#[cfg(not(target_os = "windows"))]
+use std::collections::hash_map::DefaultHasher;
+#[cfg(not(target_os = "windows"))]
+use std::collections::HashMap;
+#[cfg(not(target_os = "windows"))]
+use std::fs::{self, File, OpenOptions};
+#[cfg(not(target_os = "windows"))]
+use std::hash::{Hash, Hasher};
+#[cfg(not(target_os = "windows"))]
+use std::io::{self, Write};
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::fs::{DirBuilderExt, FileExt, MetadataExt, OpenOptionsExt};
+use std::path::Path;
+#[cfg(not(target_os = "windows"))]
+use std::path::PathBuf;
+#[cfg(not(target_os = "windows"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(target_os = "windows"))]
+use std::sync::{Arc, Mutex, Weak};
 use wasmer_compiler::{CompiledFunctionUnwindInfo, CustomSection, FunctionBody};
 use wasmer_vm::{Mmap, VMFunctionBody};
 
@@ -17,11 +38,54 @@ const ARCH_FUNCTION_ALIGNMENT: usize = 16;
 ///
 const DATA_SECTION_ALIGNMENT: usize = 64;
 
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+extern "C" {
+    fn pthread_jit_write_protect_np(enabled: std::os::raw::c_int);
+    fn sys_icache_invalidate(start: *mut std::os::raw::c_void, len: usize);
+}
+
+/// Makes the `MAP_JIT` pages writable, and not executable, by the
+/// current thread until it's dropped.
+///
+/// On aarch64 macOS, a thread either writes or executes the `MAP_JIT`
+/// pages, as switched by `pthread_jit_write_protect_np`: the code must
+/// only be writable while it's being written, so that the thread can
+/// keep running the code published before. Elsewhere, this does nothing.
+pub(crate) struct WritableCode(());
+
+impl WritableCode {
+    pub(crate) fn new() -> Self {
+        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+        unsafe {
+            pthread_jit_write_protect_np(0);
+        }
+        Self(())
+    }
+}
+
+impl Drop for WritableCode {
+    fn drop(&mut self) {
+        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+        unsafe {
+            pthread_jit_write_protect_np(1);
+        }
+    }
+}
+
 /// Memory manager for executable code.
 pub struct CodeMemory {
     unwind_registry: UnwindRegistry,
     mmap: Mmap,
     start_of_nonexecutable_pages: usize,
+    strict_w_xor_x: bool,
+    #[cfg_attr(
+        not(all(target_os = "linux", target_arch = "aarch64")),
+        allow(dead_code)
+    )]
+    enforce_branch_targets: bool,
+    /// The file the code is mapped from, see [`CodeMemory::publish_shared`].
+    #[cfg(not(target_os = "windows"))]
+    shared_code_file: Option<Arc<SharedCodeFile>>,
 }
 
 impl CodeMemory {
@@ -31,9 +95,33 @@ impl CodeMemory {
             unwind_registry: UnwindRegistry::new(),
             mmap: Mmap::new(),
             start_of_nonexecutable_pages: 0,
+            strict_w_xor_x: false,
+            enforce_branch_targets: false,
+            #[cfg(not(target_os = "windows"))]
+            shared_code_file: None,
         }
     }
 
+    /// Create a new `CodeMemory` instance strictly enforcing W^X, see
+    /// [`JIT::strict_w_xor_x`].
+    ///
+    /// [`JIT::strict_w_xor_x`]: crate::JIT::strict_w_xor_x
+    pub fn strict() -> Self {
+        Self {
+            strict_w_xor_x: true,
+            ..Self::new()
+        }
+    }
+
+    /// Enforce the branch target identification of the code once it's
+    /// published, for the code compiled with BTI landing pads.
+    ///
+    /// Only aarch64 Linux can enforce it: the code is then mapped with
+    /// `PROT_BTI`. Elsewhere, the landing pads are only hints.
+    pub fn set_enforce_branch_targets(&mut self, enable: bool) {
+        self.enforce_branch_targets = enable;
+    }
+
     /// Mutably get the UnwindRegistry.
     pub fn unwind_registry_mut(&mut self) -> &mut UnwindRegistry {
         &mut self.unwind_registry
@@ -78,7 +166,8 @@ impl CodeMemory {
 
         // 2. Allocate the pages. Mark them all read-write.
 
-        self.mmap = Mmap::with_at_least(total_len)?;
+        self.mmap = self.map(total_len)?;
+        let _writable = WritableCode::new();
 
         // 3. Determine where the pointers to each function, executable section
         // or data section are. Copy the functions. Collect the addresses of each and return them.
@@ -135,12 +224,36 @@ impl CodeMemory {
         ))
     }
 
+    /// Maps the pages, writable by the current thread while it holds a
+    /// [`WritableCode`].
+    fn map(&self, size: usize) -> Result<Mmap, String> {
+        #[cfg(target_os = "macos")]
+        if self.strict_w_xor_x {
+            return Mmap::jit_with_at_least(size);
+        }
+        Mmap::with_at_least(size)
+    }
+
     /// Apply the page permissions.
-    pub fn publish(&mut self) {
+    pub fn publish(&mut self) -> Result<(), String> {
         if self.mmap.is_empty() || self.start_of_nonexecutable_pages == 0 {
-            return;
+            return Ok(());
         }
         assert!(self.mmap.len() >= self.start_of_nonexecutable_pages);
+
+        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+        if self.strict_w_xor_x {
+            // The pages are already executable by the threads that don't
+            // hold a `WritableCode`.
+            unsafe {
+                sys_icache_invalidate(
+                    self.mmap.as_mut_ptr() as *mut _,
+                    self.start_of_nonexecutable_pages,
+                );
+            }
+            return Ok(());
+        }
+
         unsafe {
             region::protect(
                 self.mmap.as_mut_ptr(),
@@ -148,7 +261,74 @@ impl CodeMemory {
                 region::Protection::READ_EXECUTE,
             )
         }
-        .expect("unable to make memory readonly and executable");
+        .map_err(|error| format!("unable to make memory readonly and executable: {}", error))?;
+        self.protect_branch_targets()?;
+
+        if self.strict_w_xor_x {
+            let regions =
+                region::query_range(self.mmap.as_ptr(), self.start_of_nonexecutable_pages)
+                    .map_err(|error| format!("unable to query the code protection: {}", error))?;
+            if regions
+                .iter()
+                .any(|region| region.protection.contains(region::Protection::WRITE))
+            {
+                return Err("the code memory is still writable".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the page permissions like [`CodeMemory::publish`], mapping
+    /// the code from a file of `directory` shared by all the code memories
+    /// of the process publishing the same code, see
+    /// [`JIT::share_code_mappings`].
+    ///
+    /// `directory` is created if needed, and must be owned by the user of
+    /// the process and not writable by the other users. The files are
+    /// created by this process, and removed once the last code memory
+    /// mapping them is dropped: the files of the other processes are never
+    /// mapped, since they could be rewritten once checked. The code is
+    /// published without a file if it can't be shared.
+    ///
+    /// [`JIT::share_code_mappings`]: crate::JIT::share_code_mappings
+    #[cfg(not(target_os = "windows"))]
+    pub fn publish_shared(&mut self, directory: &Path) -> Result<(), String> {
+        // The `MAP_JIT` pages can't be replaced by a file mapping.
+        if cfg!(target_os = "macos") && self.strict_w_xor_x {
+            return self.publish();
+        }
+        if self.mmap.is_empty() || self.start_of_nonexecutable_pages == 0 {
+            return Ok(());
+        }
+        let len = round_up(self.start_of_nonexecutable_pages, region::page::size());
+        let code = &self.mmap.as_slice()[..len];
+        let shared_code_file = match SharedCodeFile::get_or_create(directory, code) {
+            Ok(shared_code_file) => shared_code_file,
+            Err(_) => return self.publish(),
+        };
+        self.mmap.map_file_executable(&shared_code_file.file, len)?;
+        self.shared_code_file = Some(shared_code_file);
+        self.protect_branch_targets()
+    }
+
+    /// Apply the page permissions like [`CodeMemory::publish`]: the code
+    /// can't be shared on Windows.
+    #[cfg(target_os = "windows")]
+    pub fn publish_shared(&mut self, _directory: &Path) -> Result<(), String> {
+        self.publish()
+    }
+
+    /// Enforce the branch target identification of the published code, if
+    /// enabled and supported.
+    fn protect_branch_targets(&mut self) -> Result<(), String> {
+        #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+        if self.enforce_branch_targets {
+            let len = round_up(self.start_of_nonexecutable_pages, region::page::size());
+            self.mmap
+                .enforce_branch_targets(len)
+                .map_err(|error| format!("unable to enforce the branch targets: {}", error))?;
+        }
+        Ok(())
     }
 
     /// Calculates the allocation size of the given compiled function.
@@ -208,6 +388,113 @@ impl CodeMemory {
     }
 }
 
+#[cfg(not(target_os = "windows"))]
+lazy_static! {
+    /// The code files created by this process, by directory and hash of their code.
+    static ref SHARED_CODE_FILES: Mutex<HashMap<(PathBuf, u64), Weak<SharedCodeFile>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// A file of code created by this process, see [`CodeMemory::publish_shared`]. It's
+/// removed when dropped.
+#[cfg(not(target_os = "windows"))]
+struct SharedCodeFile {
+    path: PathBuf,
+    file: File,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl SharedCodeFile {
+    /// Returns the file of `directory` holding `code` created by this process, creating it
+    /// if there is none.
+    fn get_or_create(directory: &Path, code: &[u8]) -> io::Result<Arc<Self>> {
+        let mut hasher = DefaultHasher::new();
+        code.hash(&mut hasher);
+        let key = (directory.to_path_buf(), hasher.finish());
+        let mut shared_code_files = SHARED_CODE_FILES.lock().unwrap();
+        shared_code_files.retain(|_, file| file.strong_count() > 0);
+        if let Some(file) = shared_code_files.get(&key).and_then(Weak::upgrade) {
+            if file.holds(code)? {
+                return Ok(file);
+            }
+        }
+        let file = Arc::new(Self::create(directory, key.1, code)?);
+        shared_code_files.insert(key, Arc::downgrade(&file));
+        Ok(file)
+    }
+
+    /// Creates a file of `directory` holding `code`, whose hash is `hash`.
+    fn create(directory: &Path, hash: u64, code: &[u8]) -> io::Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        check_private_directory(directory)?;
+        let path = directory.join(format!(
+            "{:016x}-{}-{}.code",
+            hash,
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o400)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&path)?;
+        let shared_code_file = Self { path, file };
+        (&shared_code_file.file).write_all(code)?;
+        if !is_private(&shared_code_file.file.metadata()?) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the shared code file can be written by other users",
+            ));
+        }
+        Ok(shared_code_file)
+    }
+
+    /// Returns whether the file holds `code`, and not another code with the same hash.
+    fn holds(&self, code: &[u8]) -> io::Result<bool> {
+        let mut contents = vec![0; code.len()];
+        self.file.read_exact_at(&mut contents, 0)?;
+        Ok(contents == code)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+impl Drop for SharedCodeFile {
+    fn drop(&mut self) {
+        // The code stays mapped by the processes which mapped it.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Creates `directory` if needed, only accessible by the user of the process, and checks
+/// that it's a directory of the user that the other users can't write to.
+#[cfg(not(target_os = "windows"))]
+fn check_private_directory(directory: &Path) -> io::Result<()> {
+    if let Err(error) = fs::DirBuilder::new().mode(0o700).create(directory) {
+        if error.kind() != io::ErrorKind::AlreadyExists {
+            return Err(error);
+        }
+    }
+    // A symbolic link isn't followed, but rejected.
+    let metadata = fs::symlink_metadata(directory)?;
+    if !metadata.is_dir() || !is_private(&metadata) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the shared code directory can be written by other users",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns whether the file of `metadata` belongs to the user of the process, and can't
+/// be written by the other users.
+#[cfg(not(target_os = "windows"))]
+fn is_private(metadata: &fs::Metadata) -> bool {
+    metadata.uid() == unsafe { libc::geteuid() } && metadata.mode() & 0o022 == 0
+}
+
 fn round_up(size: usize, multiple: usize) -> usize {
     debug_assert!(multiple.is_power_of_two());
     (size + (multiple - 1)) & !(multiple - 1)
@@ -216,8 +503,108 @@ fn round_up(size: usize, multiple: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::CodeMemory;
+    use wasmer_compiler::FunctionBody;
+
     fn _assert() {
         fn _assert_send_sync<T: Send + Sync>() {}
         _assert_send_sync::<CodeMemory>();
     }
+
+    #[test]
+    #[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+    fn strict_code_memory_is_never_writable_and_executable() {
+        let function = FunctionBody {
+            body: vec![0xc3; 64],
+            unwind_info: None,
+        };
+        let mut code_memory = CodeMemory::strict();
+        let (functions, _, _) = code_memory.allocate(&[&function], &[], &[]).unwrap();
+        let address = functions[0].as_ptr() as *const u8;
+        let protection = region::query(address).unwrap().protection;
+        assert!(!protection.contains(region::Protection::EXECUTE));
+
+        code_memory.publish().unwrap();
+        let protection = region::query(address).unwrap().protection;
+        assert_eq!(protection, region::Protection::READ_EXECUTE);
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn published_code_stays_executable_while_writing_other_code() {
+        #[cfg(target_arch = "x86_64")]
+        let ret = vec![0xc3];
+        #[cfg(target_arch = "aarch64")]
+        let ret = vec![0xc0, 0x03, 0x5f, 0xd6];
+        let function = FunctionBody {
+            body: ret,
+            unwind_info: None,
+        };
+        let mut published = CodeMemory::strict();
+        let (functions, _, _) = published.allocate(&[&function], &[], &[]).unwrap();
+        let address = functions[0].as_ptr();
+        published.publish().unwrap();
+        let call = || unsafe {
+            let function: extern "C" fn() = std::mem::transmute(address);
+            function()
+        };
+        call();
+
+        let mut unpublished = CodeMemory::strict();
+        unpublished.allocate(&[&function], &[], &[]).unwrap();
+        call();
+        unpublished.publish().unwrap();
+        call();
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn shared_code_is_mapped_from_one_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let directory = temp_dir.path().join("code");
+        let function = FunctionBody {
+            body: vec![0xc3; 64],
+            unwind_info: None,
+        };
+        let mut code_memories = vec![CodeMemory::new(), CodeMemory::new()];
+        for code_memory in code_memories.iter_mut() {
+            let (functions, _, _) = code_memory.allocate(&[&function], &[], &[]).unwrap();
+            let address = functions[0].as_ptr() as *const u8;
+            code_memory.publish_shared(&directory).unwrap();
+
+            let protection = region::query(address).unwrap().protection;
+            assert_eq!(protection, region::Protection::READ_EXECUTE);
+            let code = unsafe { std::slice::from_raw_parts(address, 64) };
+            assert_eq!(code, &function.body[..]);
+        }
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+        // The file is removed once no code memory maps it.
+        drop(code_memories);
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 0);
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn shared_code_is_not_mapped_from_a_directory_of_other_users() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let directory = temp_dir.path().join("code");
+        std::fs::create_dir(&directory).unwrap();
+        std::fs::set_permissions(&directory, std::fs::Permissions::from_mode(0o777)).unwrap();
+        let function = FunctionBody {
+            body: vec![0xc3; 64],
+            unwind_info: None,
+        };
+        let mut code_memory = CodeMemory::new();
+        let (functions, _, _) = code_memory.allocate(&[&function], &[], &[]).unwrap();
+        let address = functions[0].as_ptr() as *const u8;
+        code_memory.publish_shared(&directory).unwrap();
+
+        // The code is published without a file.
+        let protection = region::query(address).unwrap().protection;
+        assert_eq!(protection, region::Protection::READ_EXECUTE);
+        assert!(code_memory.shared_code_file.is_none());
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 0);
+    }
 }
@@ -1,13 +1,20 @@
 //! JIT compilation.
 
 use crate::{CodeMemory, JITArtifact};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 #[cfg(feature = "compiler")]
 use wasmer_compiler::Compiler;
 use wasmer_compiler::{
-    CompileError, CustomSection, CustomSectionProtection, FunctionBody, SectionIndex, Target,
+    CodeHardening, CompileError, CompiledFunctionUnwindInfo, CustomSection,
+    CustomSectionProtection, FunctionBody, Relocation, Relocations, SectionIndex, Target,
+};
+use wasmer_engine::{
+    Artifact, DeserializeError, Engine, EngineId, FunctionExtent, TrapSink, Tunables,
 };
-use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, FunctionExtent, Tunables};
 use wasmer_types::entity::PrimaryMap;
 use wasmer_types::Features;
 use wasmer_types::{FunctionIndex, FunctionType, LocalFunctionIndex, SignatureIndex};
@@ -34,6 +41,16 @@ impl JITEngine {
                 compiler: Some(compiler),
                 code_memory: vec![],
                 signatures: SignatureRegistry::new(),
+                function_call_trampolines: HashMap::new(),
+                deduplicate_functions: false,
+                share_code_mappings: false,
+                shared_code_directory: None,
+                count_function_calls: false,
+                trap_sink: None,
+                shared_function_bodies: HashMap::new(),
+                unpublished_function_bodies: vec![],
+                strict_w_xor_x: false,
+                required_hardening: CodeHardening::none(),
                 features,
             })),
             target: Arc::new(target),
@@ -61,6 +78,16 @@ impl JITEngine {
                 compiler: None,
                 code_memory: vec![],
                 signatures: SignatureRegistry::new(),
+                function_call_trampolines: HashMap::new(),
+                deduplicate_functions: false,
+                share_code_mappings: false,
+                shared_code_directory: None,
+                count_function_calls: false,
+                trap_sink: None,
+                shared_function_bodies: HashMap::new(),
+                unpublished_function_bodies: vec![],
+                strict_w_xor_x: false,
+                required_hardening: CodeHardening::none(),
                 features: Features::default(),
             })),
             target: Arc::new(Target::default()),
@@ -68,6 +95,74 @@ impl JITEngine {
         }
     }
 
+    /// Returns whether the code memory of the engine strictly enforces
+    /// W^X, see [`JIT::strict_w_xor_x`].
+    ///
+    /// [`JIT::strict_w_xor_x`]: crate::JIT::strict_w_xor_x
+    pub fn is_strict_w_xor_x(&self) -> bool {
+        self.inner().strict_w_xor_x
+    }
+
+    pub(crate) fn set_strict_w_xor_x(&self, strict_w_xor_x: bool) {
+        self.inner_mut().strict_w_xor_x = strict_w_xor_x;
+    }
+
+    /// Returns whether the engine shares the identical function bodies of
+    /// its artifacts, see [`JIT::deduplicate_functions`].
+    ///
+    /// [`JIT::deduplicate_functions`]: crate::JIT::deduplicate_functions
+    pub fn deduplicates_functions(&self) -> bool {
+        self.inner().deduplicate_functions
+    }
+
+    pub(crate) fn set_deduplicate_functions(&self, deduplicate_functions: bool) {
+        self.inner_mut().deduplicate_functions = deduplicate_functions;
+    }
+
+    /// Returns whether the engine maps the code of its artifacts from files
+    /// shared with the other engines, see [`JIT::share_code_mappings`].
+    ///
+    /// [`JIT::share_code_mappings`]: crate::JIT::share_code_mappings
+    pub fn shares_code_mappings(&self) -> bool {
+        self.inner().share_code_mappings
+    }
+
+    pub(crate) fn set_share_code_mappings(&self, share_code_mappings: bool) {
+        self.inner_mut().share_code_mappings = share_code_mappings;
+    }
+
+    pub(crate) fn set_shared_code_directory(&self, shared_code_directory: Option<PathBuf>) {
+        self.inner_mut().shared_code_directory = shared_code_directory;
+    }
+
+    /// Returns whether the code compiled by the engine counts the calls of
+    /// the functions, see [`JIT::count_function_calls`].
+    ///
+    /// [`JIT::count_function_calls`]: crate::JIT::count_function_calls
+    pub fn counts_function_calls(&self) -> bool {
+        self.inner().count_function_calls
+    }
+
+    pub(crate) fn set_count_function_calls(&self, count_function_calls: bool) {
+        self.inner_mut().count_function_calls = count_function_calls;
+    }
+
+    /// Returns the hardening the engine requires from the artifacts it
+    /// loads, see [`JIT::required_code_hardening`].
+    ///
+    /// [`JIT::required_code_hardening`]: crate::JIT::required_code_hardening
+    pub fn required_code_hardening(&self) -> CodeHardening {
+        self.inner().required_hardening
+    }
+
+    pub(crate) fn set_required_code_hardening(&self, required_hardening: CodeHardening) {
+        self.inner_mut().required_hardening = required_hardening;
+    }
+
+    pub(crate) fn set_trap_sink(&self, trap_sink: Option<Arc<dyn TrapSink>>) {
+        self.inner_mut().trap_sink = trap_sink;
+    }
+
     pub(crate) fn inner(&self) -> std::sync::MutexGuard<'_, JITEngineInner> {
         self.inner.lock().unwrap()
     }
@@ -100,6 +195,11 @@ impl Engine for JITEngine {
         self.inner().validate(binary)
     }
 
+    /// Checks that the compiler supports a WebAssembly module
+    fn precompile_validate(&self, binary: &[u8]) -> Result<(), Vec<CompileError>> {
+        self.inner().precompile_validate(binary)
+    }
+
     /// Compile a WebAssembly binary
     #[cfg(feature = "compiler")]
     fn compile(
@@ -128,6 +228,10 @@ impl Engine for JITEngine {
         Ok(Arc::new(JITArtifact::deserialize(&self, &bytes)?))
     }
 
+    fn trap_sink(&self) -> Option<Arc<dyn TrapSink>> {
+        self.inner().trap_sink.clone()
+    }
+
     fn id(&self) -> &EngineId {
         &self.engine_id
     }
@@ -150,6 +254,35 @@ pub struct JITEngineInner {
     /// The signature registry is used mainly to operate with trampolines
     /// performantly.
     signatures: SignatureRegistry,
+    /// The function call trampolines allocated so far, by signature.
+    ///
+    /// A trampoline only depends on its signature, so all the artifacts
+    /// of the engine (hence all the stores using it) share them. They
+    /// stay valid as long as the engine, since the code memory is never
+    /// freed.
+    function_call_trampolines: HashMap<VMSharedSignatureIndex, VMTrampoline>,
+    /// Whether identical function bodies are shared between artifacts.
+    deduplicate_functions: bool,
+    /// Whether the code is mapped from files shared between engines.
+    share_code_mappings: bool,
+    /// The directory of the shared code files, if not the default one.
+    shared_code_directory: Option<PathBuf>,
+    /// Whether the compiled code counts the calls of the functions.
+    pub(crate) count_function_calls: bool,
+    /// The sink receiving the traps of the WebAssembly code.
+    trap_sink: Option<Arc<dyn TrapSink>>,
+    /// The published function bodies that can be shared, by the hash of
+    /// their code. Like the trampolines, they stay valid as long as the
+    /// engine.
+    shared_function_bodies: HashMap<u64, Vec<FunctionExtent>>,
+    /// The function bodies that can be shared once their code memory is
+    /// published.
+    unpublished_function_bodies: Vec<(u64, FunctionExtent)>,
+    /// Whether the code memory strictly enforces W^X.
+    strict_w_xor_x: bool,
+    /// The hardening required from the artifacts, on top of the one of
+    /// the compiler.
+    required_hardening: CodeHardening,
 }
 
 impl JITEngineInner {
@@ -162,6 +295,30 @@ impl JITEngineInner {
         Ok(&**self.compiler.as_ref().unwrap())
     }
 
+    /// Checks that an artifact was compiled with at least the hardening
+    /// against control-flow hijacking the compiler of the engine applies,
+    /// and the hardening required by the engine.
+    pub fn check_hardening(&self, hardening: &CodeHardening) -> Result<(), DeserializeError> {
+        #[cfg(feature = "compiler")]
+        let required = match &self.compiler {
+            Some(compiler) => compiler
+                .code_hardening()
+                .control_flow()
+                .union(&self.required_hardening),
+            None => self.required_hardening,
+        };
+        #[cfg(not(feature = "compiler"))]
+        let required = self.required_hardening;
+        let missing = required.missing_from(hardening);
+        if missing.is_empty() {
+            return Ok(());
+        }
+        Err(DeserializeError::Incompatible(format!(
+            "the artifact was compiled without {}, required by the `JITEngine`",
+            missing.join(" and "),
+        )))
+    }
+
     /// Validate the module
     #[cfg(feature = "compiler")]
     pub fn validate<'data>(&self, data: &'data [u8]) -> Result<(), CompileError> {
@@ -177,17 +334,38 @@ impl JITEngineInner {
         ))
     }
 
+    /// Checks that the compiler supports the module
+    #[cfg(feature = "compiler")]
+    pub fn precompile_validate<'data>(&self, data: &'data [u8]) -> Result<(), Vec<CompileError>> {
+        self.compiler()
+            .map_err(|error| vec![error])?
+            .check_module_support(self.features(), data)
+    }
+
+    /// Checks that the compiler supports the module
+    #[cfg(not(feature = "compiler"))]
+    pub fn precompile_validate<'data>(&self, data: &'data [u8]) -> Result<(), Vec<CompileError>> {
+        self.validate(data).map_err(|error| vec![error])
+    }
+
     /// The Wasm features
     pub fn features(&self) -> &Features {
         &self.features
     }
 
     /// Allocate compiled functions into memory
+    ///
+    /// Function call trampolines are only allocated for the signatures
+    /// that don't have one yet in the engine. When deduplicating the
+    /// functions, the ones identical to a function allocated earlier share
+    /// its body, and are returned in the set of shared functions.
     #[allow(clippy::type_complexity)]
     pub(crate) fn allocate(
         &mut self,
         _module: &ModuleInfo,
         functions: &PrimaryMap<LocalFunctionIndex, FunctionBody>,
+        function_relocations: &Relocations,
+        signatures: &PrimaryMap<SignatureIndex, VMSharedSignatureIndex>,
         function_call_trampolines: &PrimaryMap<SignatureIndex, FunctionBody>,
         dynamic_function_trampolines: &PrimaryMap<FunctionIndex, FunctionBody>,
         custom_sections: &PrimaryMap<SectionIndex, CustomSection>,
@@ -197,18 +375,60 @@ impl JITEngineInner {
             PrimaryMap<SignatureIndex, VMTrampoline>,
             PrimaryMap<FunctionIndex, FunctionBodyPtr>,
             PrimaryMap<SectionIndex, SectionBodyPtr>,
+            HashSet<LocalFunctionIndex>,
         ),
         CompileError,
     > {
-        let function_bodies = functions
-            .values()
-            .chain(function_call_trampolines.values())
+        let mut new_signatures = Vec::new();
+        let mut new_trampolines = Vec::new();
+        let mut seen = HashSet::new();
+        for (index, trampoline) in function_call_trampolines.iter() {
+            let signature = signatures[index];
+            if !self.function_call_trampolines.contains_key(&signature) && seen.insert(signature) {
+                new_signatures.push(signature);
+                new_trampolines.push(trampoline);
+            }
+        }
+
+        // The functions sharing the body of an earlier function, and the
+        // new functions that can be shared, by the hash of their code.
+        let mut shared_functions = HashMap::new();
+        let mut new_shareable_functions = HashMap::<u64, Vec<LocalFunctionIndex>>::new();
+        let mut new_functions = Vec::new();
+        for (index, function) in functions.iter() {
+            if self.deduplicate_functions && is_shareable(function, &function_relocations[index]) {
+                let hash = hash_function_body(function);
+                if let Some(extent) = self.find_shared_function_body(hash, function) {
+                    shared_functions.insert(index, SharedBody::Published(extent));
+                    continue;
+                }
+                let candidates = new_shareable_functions.entry(hash).or_default();
+                if let Some(&other) = candidates
+                    .iter()
+                    .find(|other| functions[**other].body == function.body)
+                {
+                    shared_functions.insert(index, SharedBody::Function(other));
+                    continue;
+                }
+                candidates.push(index);
+            }
+            new_functions.push(function);
+        }
+
+        let function_bodies = new_functions
+            .iter()
+            .copied()
+            .chain(new_trampolines.iter().copied())
             .chain(dynamic_function_trampolines.values())
             .collect::<Vec<_>>();
         let (executable_sections, data_sections): (Vec<_>, _) = custom_sections
             .values()
             .partition(|section| section.protection == CustomSectionProtection::ReadExecute);
-        self.code_memory.push(CodeMemory::new());
+        self.code_memory.push(if self.strict_w_xor_x {
+            CodeMemory::strict()
+        } else {
+            CodeMemory::new()
+        });
 
         let (mut allocated_functions, allocated_executable_sections, allocated_data_sections) =
             self.code_memory
@@ -226,24 +446,58 @@ impl JITEngineInner {
                     ))
                 })?;
 
-        let allocated_functions_result = allocated_functions
-            .drain(0..functions.len())
+        let mut new_function_extents = allocated_functions
+            .drain(0..new_functions.len())
             .map(|slice| FunctionExtent {
                 ptr: FunctionBodyPtr(slice.as_ptr()),
                 length: slice.len(),
             })
-            .collect::<PrimaryMap<LocalFunctionIndex, _>>();
-
-        let mut allocated_function_call_trampolines: PrimaryMap<SignatureIndex, VMTrampoline> =
-            PrimaryMap::new();
-        for ptr in allocated_functions
-            .drain(0..function_call_trampolines.len())
-            .map(|slice| slice.as_ptr())
-        {
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut allocated_functions_result = PrimaryMap::with_capacity(functions.len());
+        for index in functions.keys() {
+            let extent = match shared_functions.get(&index) {
+                Some(SharedBody::Published(extent)) => FunctionExtent {
+                    ptr: extent.ptr,
+                    length: extent.length,
+                },
+                Some(SharedBody::Function(other)) => {
+                    let extent: &FunctionExtent = &allocated_functions_result[*other];
+                    FunctionExtent {
+                        ptr: extent.ptr,
+                        length: extent.length,
+                    }
+                }
+                None => new_function_extents.next().unwrap(),
+            };
+            allocated_functions_result.push(extent);
+        }
+        for (hash, indices) in new_shareable_functions {
+            for index in indices {
+                let extent = &allocated_functions_result[index];
+                self.unpublished_function_bodies.push((
+                    hash,
+                    FunctionExtent {
+                        ptr: extent.ptr,
+                        length: extent.length,
+                    },
+                ));
+            }
+        }
+
+        for (signature, ptr) in new_signatures.into_iter().zip(
+            allocated_functions
+                .drain(0..new_trampolines.len())
+                .map(|slice| slice.as_ptr()),
+        ) {
             let trampoline =
                 unsafe { std::mem::transmute::<*const VMFunctionBody, VMTrampoline>(ptr) };
-            allocated_function_call_trampolines.push(trampoline);
+            self.function_call_trampolines.insert(signature, trampoline);
         }
+        let allocated_function_call_trampolines = function_call_trampolines
+            .keys()
+            .map(|index| self.function_call_trampolines[&signatures[index]])
+            .collect::<PrimaryMap<SignatureIndex, VMTrampoline>>();
 
         let allocated_dynamic_function_trampolines = allocated_functions
             .drain(..)
@@ -272,12 +526,70 @@ impl JITEngineInner {
             allocated_function_call_trampolines,
             allocated_dynamic_function_trampolines,
             allocated_custom_sections,
+            shared_functions.keys().copied().collect(),
         ))
     }
 
+    /// Returns the published function body identical to `function`, if
+    /// any.
+    fn find_shared_function_body(
+        &self,
+        hash: u64,
+        function: &FunctionBody,
+    ) -> Option<FunctionExtent> {
+        self.shared_function_bodies
+            .get(&hash)?
+            .iter()
+            .find(|extent| {
+                // The shareable functions have no relocations, so their
+                // code is exactly their body.
+                let code =
+                    unsafe { std::slice::from_raw_parts(*extent.ptr as *const u8, extent.length) };
+                code == &function.body[..]
+            })
+            .map(|extent| FunctionExtent {
+                ptr: extent.ptr,
+                length: extent.length,
+            })
+    }
+
+    /// Returns the number of function call trampolines allocated by the
+    /// engine, which is the number of distinct signatures of the
+    /// artifacts created so far.
+    pub fn function_call_trampolines_count(&self) -> usize {
+        self.function_call_trampolines.len()
+    }
+
     /// Make memory containing compiled code executable.
-    pub(crate) fn publish_compiled_code(&mut self) {
-        self.code_memory.last_mut().unwrap().publish();
+    ///
+    /// When sharing the code mappings, the code is mapped from a shared
+    /// file if it's `position_independent`, that is if it's the same
+    /// wherever it's loaded.
+    pub(crate) fn publish_compiled_code(
+        &mut self,
+        position_independent: bool,
+        hardening: &CodeHardening,
+    ) -> Result<(), CompileError> {
+        let unpublished_function_bodies = std::mem::take(&mut self.unpublished_function_bodies);
+        let code_memory = self.code_memory.last_mut().unwrap();
+        code_memory.set_enforce_branch_targets(hardening.branch_target_identification);
+        if self.share_code_mappings && position_independent {
+            let directory = self
+                .shared_code_directory
+                .clone()
+                .unwrap_or_else(default_shared_code_directory);
+            code_memory.publish_shared(&directory)
+        } else {
+            code_memory.publish()
+        }
+        .map_err(|e| CompileError::Resource(format!("Error while publishing the code: {}", e)))?;
+        for (hash, extent) in unpublished_function_bodies {
+            self.shared_function_bodies
+                .entry(hash)
+                .or_default()
+                .push(extent);
+        }
+        Ok(())
     }
 
     /// Register DWARF-type exception handling information associated with the code.
@@ -298,3 +610,41 @@ impl JITEngineInner {
         &self.signatures
     }
 }
+
+/// Returns the default directory of the shared code files, a directory of
+/// the user of the process in the temporary directory.
+fn default_shared_code_directory() -> PathBuf {
+    #[cfg(not(target_os = "windows"))]
+    let name = format!("wasmer-code-{}", unsafe { libc::geteuid() });
+    #[cfg(target_os = "windows")]
+    let name = "wasmer-code".to_string();
+    std::env::temp_dir().join(name)
+}
+
+/// The body shared by a function.
+enum SharedBody {
+    /// The body of a function of an artifact created earlier.
+    Published(FunctionExtent),
+    /// The body of an earlier function of the same artifact.
+    Function(LocalFunctionIndex),
+}
+
+/// Returns whether the body of `function` can be shared with the other
+/// artifacts of the engine.
+///
+/// The functions with relocations can't, since the same code is linked
+/// differently in each artifact, and neither can the ones with Windows
+/// unwind information, which is written next to each copy of the code.
+fn is_shareable(function: &FunctionBody, relocations: &[Relocation]) -> bool {
+    relocations.is_empty()
+        && !matches!(
+            function.unwind_info,
+            Some(CompiledFunctionUnwindInfo::WindowsX64(_))
+        )
+}
+
+fn hash_function_body(function: &FunctionBody) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    function.body.hash(&mut hasher);
+    hasher.finish()
+}
@@ -2,8 +2,8 @@
 
 use std::ptr::write_unaligned;
 use wasmer_compiler::{
-    JumpTable, JumpTableOffsets, Relocation, RelocationKind, RelocationTarget, Relocations,
-    SectionIndex,
+    CustomSection, CustomSectionProtection, JumpTable, JumpTableOffsets, Relocation,
+    RelocationKind, RelocationTarget, Relocations, SectionIndex,
 };
 use wasmer_engine::FunctionExtent;
 use wasmer_types::entity::{EntityRef, PrimaryMap};
@@ -62,6 +62,36 @@ fn apply_relocation(
     }
 }
 
+/// Returns whether the code linked with the relocations of a module is
+/// the same wherever it's loaded: all the relocations of the functions and
+/// of the executable sections are relative, and target the code memory of
+/// the module.
+pub fn is_position_independent(
+    function_relocations: &Relocations,
+    custom_sections: &PrimaryMap<SectionIndex, CustomSection>,
+    section_relocations: &PrimaryMap<SectionIndex, Vec<Relocation>>,
+) -> bool {
+    let executable_section_relocations = section_relocations
+        .iter()
+        .filter(|(index, _)| {
+            custom_sections[*index].protection == CustomSectionProtection::ReadExecute
+        })
+        .map(|(_, relocations)| relocations);
+    function_relocations
+        .values()
+        .chain(executable_section_relocations)
+        .flatten()
+        .all(|r| {
+            matches!(
+                r.kind,
+                RelocationKind::X86PCRel4
+                    | RelocationKind::X86PCRel8
+                    | RelocationKind::X86PCRelRodata4
+                    | RelocationKind::X86CallPCRel4
+            ) && !matches!(r.reloc_target, RelocationTarget::LibCall(_))
+        })
+}
+
 /// Links a module, patching the allocated functions with the
 /// required relocations and jump tables.
 pub fn link_module(
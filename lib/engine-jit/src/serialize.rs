@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use wasmer_compiler::{
-    CompileModuleInfo, CustomSection, Dwarf, FunctionBody, JumpTableOffsets, Relocation,
-    SectionIndex,
+    CompileModuleInfo, CpuFeature, CustomSection, Dwarf, EnumSet, FunctionBody, JumpTableOffsets,
+    Relocation, SectionIndex,
 };
-use wasmer_engine::SerializableFunctionFrameInfo;
+use wasmer_engine::{DeserializeError, SerializableFunctionFrameInfo};
 use wasmer_types::entity::PrimaryMap;
 use wasmer_types::{FunctionIndex, LocalFunctionIndex, OwnedDataInitializer, SignatureIndex};
 
@@ -35,6 +36,50 @@ pub struct SerializableCompilation {
     pub debug: Option<Dwarf>,
 }
 
+/// The executable code of a `SerializableCompilation`: the bodies of the
+/// functions and trampolines, and the custom sections linked with them.
+///
+/// Compressed artifacts keep it out of the compressed payload.
+#[derive(Serialize, Deserialize)]
+pub struct SerializableCode {
+    pub function_bodies: PrimaryMap<LocalFunctionIndex, FunctionBody>,
+    pub function_call_trampolines: PrimaryMap<SignatureIndex, FunctionBody>,
+    pub dynamic_function_trampolines: PrimaryMap<FunctionIndex, FunctionBody>,
+    pub custom_sections: PrimaryMap<SectionIndex, CustomSection>,
+}
+
+impl SerializableCompilation {
+    /// Returns a copy of the compilation without its code, and the code.
+    fn split_code(&self) -> (Self, SerializableCode) {
+        let without_code = Self {
+            function_bodies: PrimaryMap::new(),
+            function_relocations: self.function_relocations.clone(),
+            function_jt_offsets: self.function_jt_offsets.clone(),
+            function_frame_info: self.function_frame_info.clone(),
+            function_call_trampolines: PrimaryMap::new(),
+            dynamic_function_trampolines: PrimaryMap::new(),
+            custom_sections: PrimaryMap::new(),
+            custom_section_relocations: self.custom_section_relocations.clone(),
+            debug: self.debug.clone(),
+        };
+        let code = SerializableCode {
+            function_bodies: self.function_bodies.clone(),
+            function_call_trampolines: self.function_call_trampolines.clone(),
+            dynamic_function_trampolines: self.dynamic_function_trampolines.clone(),
+            custom_sections: self.custom_sections.clone(),
+        };
+        (without_code, code)
+    }
+
+    /// Puts back the code taken by `split_code`.
+    fn restore_code(&mut self, code: SerializableCode) {
+        self.function_bodies = code.function_bodies;
+        self.function_call_trampolines = code.function_call_trampolines;
+        self.dynamic_function_trampolines = code.dynamic_function_trampolines;
+        self.custom_sections = code.custom_sections;
+    }
+}
+
 /// Serializable struct that is able to serialize from and to
 /// a `JITArtifactInfo`.
 #[derive(Serialize, Deserialize)]
@@ -42,4 +87,87 @@ pub struct SerializableModule {
     pub compilation: SerializableCompilation,
     pub compile_info: CompileModuleInfo,
     pub data_initializers: Box<[OwnedDataInitializer]>,
+    /// The variants of the compilation for other CPU features.
+    pub feature_tiers: Vec<SerializableFeatureTier>,
+}
+
+impl SerializableModule {
+    /// Returns a copy of the module without the code of its compilations,
+    /// and that code: the main compilation first, then the feature tiers.
+    pub fn split_code(&self) -> (Self, Vec<SerializableCode>) {
+        let (compilation, code) = self.compilation.split_code();
+        let mut codes = vec![code];
+        let feature_tiers = self
+            .feature_tiers
+            .iter()
+            .map(|tier| {
+                let (compilation, code) = tier.compilation.split_code();
+                codes.push(code);
+                SerializableFeatureTier {
+                    cpu_features: tier.cpu_features.clone(),
+                    compilation,
+                }
+            })
+            .collect();
+        let without_code = Self {
+            compilation,
+            compile_info: self.compile_info.clone(),
+            data_initializers: self.data_initializers.clone(),
+            feature_tiers,
+        };
+        (without_code, codes)
+    }
+
+    /// Puts back the code taken by `split_code`, failing if it doesn't
+    /// have an entry per compilation.
+    pub fn restore_code(&mut self, codes: Vec<SerializableCode>) -> Result<(), DeserializeError> {
+        if codes.len() != self.feature_tiers.len() + 1 {
+            return Err(DeserializeError::CorruptedBinary(format!(
+                "The artifact has the code of {} compilations instead of {}",
+                codes.len(),
+                self.feature_tiers.len() + 1
+            )));
+        }
+        let compilations = std::iter::once(&mut self.compilation).chain(
+            self.feature_tiers
+                .iter_mut()
+                .map(|tier| &mut tier.compilation),
+        );
+        for (compilation, code) in compilations.zip(codes) {
+            compilation.restore_code(code);
+        }
+        Ok(())
+    }
+}
+
+/// A variant of the compilation of a module, for a set of CPU features.
+#[derive(Serialize, Deserialize)]
+pub struct SerializableFeatureTier {
+    /// The names of the CPU features, so that the tiers using features
+    /// unknown to this version are skipped.
+    pub cpu_features: Vec<String>,
+    pub compilation: SerializableCompilation,
+}
+
+impl SerializableFeatureTier {
+    /// Creates the tier of a compilation for `cpu_features`.
+    pub fn new(cpu_features: EnumSet<CpuFeature>, compilation: SerializableCompilation) -> Self {
+        Self {
+            cpu_features: cpu_features
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect(),
+            compilation,
+        }
+    }
+
+    /// Returns the CPU features of the tier, or `None` if some are
+    /// unknown.
+    pub fn cpu_features(&self) -> Option<EnumSet<CpuFeature>> {
+        let mut cpu_features = CpuFeature::set();
+        for feature in &self.cpu_features {
+            cpu_features.insert(CpuFeature::from_str(feature).ok()?);
+        }
+        Some(cpu_features)
+    }
 }
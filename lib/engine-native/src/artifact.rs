@@ -14,14 +14,18 @@ use std::sync::Arc;
 use tempfile::NamedTempFile;
 #[cfg(feature = "compiler")]
 use tracing::trace;
-use wasmer_compiler::{CompileError, Features, OperatingSystem, Symbol, SymbolRegistry, Triple};
+use wasmer_compiler::{
+    CodeHardening, CompileError, Features, OperatingSystem, Symbol, SymbolRegistry, Triple,
+};
 #[cfg(feature = "compiler")]
 use wasmer_compiler::{
     CompileModuleInfo, FunctionBodyData, ModuleEnvironment, ModuleTranslationState,
 };
-use wasmer_engine::{Artifact, DeserializeError, InstantiationError, SerializeError};
+use wasmer_engine::{
+    read_format_version, Artifact, DeserializeError, InstantiationError, SerializeError,
+};
 #[cfg(feature = "compiler")]
-use wasmer_engine::{Engine, Tunables};
+use wasmer_engine::{write_format_version, Engine, Tunables};
 #[cfg(feature = "compiler")]
 use wasmer_object::{emit_compilation, emit_data, get_object_for_target};
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
@@ -98,6 +102,7 @@ impl NativeArtifact {
     fn generate_metadata<'data>(
         data: &'data [u8],
         features: &Features,
+        hardening: CodeHardening,
         tunables: &dyn Tunables,
     ) -> Result<
         (
@@ -128,6 +133,9 @@ impl NativeArtifact {
             features: features.clone(),
             memory_styles,
             table_styles,
+            hardening,
+            count_function_calls: false,
+            middlewares_applied: false,
         };
         Ok((
             compile_info,
@@ -148,7 +156,12 @@ impl NativeArtifact {
         let target = engine.target();
         let compiler = engine_inner.compiler()?;
         let (compile_info, function_body_inputs, data_initializers, module_translation) =
-            Self::generate_metadata(data, engine_inner.features(), tunables)?;
+            Self::generate_metadata(
+                data,
+                engine_inner.features(),
+                compiler.code_hardening(),
+                tunables,
+            )?;
 
         let data_initializers = data_initializers
             .iter()
@@ -183,7 +196,9 @@ impl NativeArtifact {
             function_body_lengths,
         };
 
-        let serialized_data = bincode::serialize(&metadata).map_err(to_compile_error)?;
+        let mut serialized_data = Vec::new();
+        write_format_version(&mut serialized_data);
+        serialized_data.extend(bincode::serialize(&metadata).map_err(to_compile_error)?);
         let mut metadata_binary = vec![0; 10];
         let mut writable = &mut metadata_binary[..];
         leb128::write::unsigned(&mut writable, serialized_data.len() as u64)
@@ -545,9 +560,11 @@ impl NativeArtifact {
         })?;
         let metadata_slice: &'static [u8] =
             slice::from_raw_parts(&size[10] as *const u8, metadata_len as usize);
-        let metadata: ModuleMetadata = bincode::deserialize(metadata_slice)
-            .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
+        let metadata: ModuleMetadata =
+            bincode::deserialize(read_format_version(metadata_slice)?)
+                .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
         let mut engine_inner = engine.inner_mut();
+        engine_inner.check_hardening(&metadata.compile_info.hardening)?;
 
         Self::from_parts(&mut engine_inner, metadata, shared_path, lib)
             .map_err(DeserializeError::Compiler)
@@ -575,6 +592,10 @@ impl Artifact for NativeArtifact {
         &self.metadata.compile_info.features
     }
 
+    fn code_hardening(&self) -> CodeHardening {
+        self.metadata.compile_info.hardening
+    }
+
     fn data_initializers(&self) -> &[OwnedDataInitializer] {
         &*self.metadata.data_initializers
     }
@@ -1,11 +1,12 @@
 use crate::NativeEngine;
-use wasmer_compiler::{CompilerConfig, Features, Target};
+use wasmer_compiler::{CodeHardening, CompilerConfig, Features, Target};
 
 /// The Native builder
 pub struct Native {
     compiler_config: Option<Box<dyn CompilerConfig>>,
     target: Option<Target>,
     features: Option<Features>,
+    required_code_hardening: CodeHardening,
 }
 
 impl Native {
@@ -22,6 +23,7 @@ impl Native {
             compiler_config: Some(compiler_config),
             target: None,
             features: None,
+            required_code_hardening: CodeHardening::none(),
         }
     }
 
@@ -31,6 +33,7 @@ impl Native {
             compiler_config: None,
             target: None,
             features: None,
+            required_code_hardening: CodeHardening::none(),
         }
     }
 
@@ -46,9 +49,21 @@ impl Native {
         self
     }
 
+    /// Refuse to load the artifacts compiled without `hardening`, on top
+    /// of the hardening against control-flow hijacking the compiler of the
+    /// engine applies.
+    ///
+    /// Headless engines, which only load artifacts compiled elsewhere,
+    /// enforce a hardening policy this way, as do the engines requiring
+    /// the mitigations against speculative execution attacks.
+    pub fn required_code_hardening(mut self, hardening: CodeHardening) -> Self {
+        self.required_code_hardening = hardening;
+        self
+    }
+
     /// Build the `NativeEngine` for this configuration
     pub fn engine(self) -> NativeEngine {
-        if let Some(_compiler_config) = self.compiler_config {
+        let engine = if let Some(_compiler_config) = self.compiler_config {
             #[cfg(feature = "compiler")]
             {
                 let compiler_config = _compiler_config;
@@ -66,7 +81,9 @@ impl Native {
             }
         } else {
             NativeEngine::headless()
-        }
+        };
+        engine.set_required_code_hardening(self.required_code_hardening);
+        engine
     }
 }
 
@@ -5,7 +5,7 @@ use libloading::Library;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
-use wasmer_compiler::{CompileError, Target};
+use wasmer_compiler::{CodeHardening, CompileError, Target};
 #[cfg(feature = "compiler")]
 use wasmer_compiler::{Compiler, Triple};
 use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, Tunables};
@@ -55,6 +55,7 @@ impl NativeEngine {
                 is_cross_compiling,
                 linker,
                 libraries: vec![],
+                required_hardening: CodeHardening::none(),
             })),
             target: Arc::new(target),
             engine_id: EngineId::default(),
@@ -86,6 +87,7 @@ impl NativeEngine {
                 is_cross_compiling: false,
                 linker: Linker::None,
                 libraries: vec![],
+                required_hardening: CodeHardening::none(),
             })),
             target: Arc::new(Target::default()),
             engine_id: EngineId::default(),
@@ -110,6 +112,18 @@ impl NativeEngine {
         inner.prefixer = Some(Box::new(prefixer));
     }
 
+    /// Returns the hardening the engine requires from the artifacts it
+    /// loads, see [`Native::required_code_hardening`].
+    ///
+    /// [`Native::required_code_hardening`]: crate::Native::required_code_hardening
+    pub fn required_code_hardening(&self) -> CodeHardening {
+        self.inner().required_hardening
+    }
+
+    pub(crate) fn set_required_code_hardening(&self, required_hardening: CodeHardening) {
+        self.inner_mut().required_hardening = required_hardening;
+    }
+
     pub(crate) fn inner(&self) -> std::sync::MutexGuard<'_, NativeEngineInner> {
         self.inner.lock().unwrap()
     }
@@ -142,6 +156,11 @@ impl Engine for NativeEngine {
         self.inner().validate(binary)
     }
 
+    /// Checks that the compiler supports a WebAssembly module
+    fn precompile_validate(&self, binary: &[u8]) -> Result<(), Vec<CompileError>> {
+        self.inner().precompile_validate(binary)
+    }
+
     /// Compile a WebAssembly binary
     #[cfg(feature = "compiler")]
     fn compile(
@@ -230,6 +249,9 @@ pub struct NativeEngineInner {
     linker: Linker,
     /// List of libraries loaded by this engine.
     libraries: Vec<Library>,
+    /// The hardening required from the artifacts, on top of the one of
+    /// the compiler.
+    required_hardening: CodeHardening,
 }
 
 impl NativeEngineInner {
@@ -245,6 +267,30 @@ impl NativeEngineInner {
             .expect("Can't get compiler reference"))
     }
 
+    /// Checks that an artifact was compiled with at least the hardening
+    /// against control-flow hijacking the compiler of the engine applies,
+    /// and the hardening required by the engine.
+    pub fn check_hardening(&self, hardening: &CodeHardening) -> Result<(), DeserializeError> {
+        #[cfg(feature = "compiler")]
+        let required = match &self.compiler {
+            Some(compiler) => compiler
+                .code_hardening()
+                .control_flow()
+                .union(&self.required_hardening),
+            None => self.required_hardening,
+        };
+        #[cfg(not(feature = "compiler"))]
+        let required = self.required_hardening;
+        let missing = required.missing_from(hardening);
+        if missing.is_empty() {
+            return Ok(());
+        }
+        Err(DeserializeError::Incompatible(format!(
+            "the artifact was compiled without {}, required by the `NativeEngine`",
+            missing.join(" and "),
+        )))
+    }
+
     #[cfg(feature = "compiler")]
     pub(crate) fn get_prefix(&self, bytes: &[u8]) -> String {
         if let Some(prefixer) = &self.prefixer {
@@ -273,6 +319,20 @@ impl NativeEngineInner {
         ))
     }
 
+    /// Checks that the compiler supports the module
+    #[cfg(feature = "compiler")]
+    pub fn precompile_validate<'data>(&self, data: &'data [u8]) -> Result<(), Vec<CompileError>> {
+        self.compiler()
+            .map_err(|error| vec![error])?
+            .check_module_support(self.features(), data)
+    }
+
+    /// Checks that the compiler supports the module
+    #[cfg(not(feature = "compiler"))]
+    pub fn precompile_validate<'data>(&self, data: &'data [u8]) -> Result<(), Vec<CompileError>> {
+        self.validate(data).map_err(|error| vec![error])
+    }
+
     /// Shared signature registry.
     pub fn signatures(&self) -> &SignatureRegistry {
         &self.signatures
@@ -7,14 +7,18 @@ use std::collections::BTreeMap;
 use std::error::Error;
 use std::mem;
 use std::sync::Arc;
-use wasmer_compiler::{CompileError, Features, OperatingSystem, SymbolRegistry, Triple};
+use wasmer_compiler::{
+    CodeHardening, CompileError, Features, OperatingSystem, SymbolRegistry, Triple,
+};
 #[cfg(feature = "compiler")]
 use wasmer_compiler::{
     CompileModuleInfo, FunctionBodyData, ModuleEnvironment, ModuleTranslationState,
 };
-use wasmer_engine::{Artifact, DeserializeError, InstantiationError, SerializeError};
+use wasmer_engine::{
+    read_format_version, Artifact, DeserializeError, InstantiationError, SerializeError,
+};
 #[cfg(feature = "compiler")]
-use wasmer_engine::{Engine, Tunables};
+use wasmer_engine::{write_format_version, Engine, Tunables};
 #[cfg(feature = "compiler")]
 use wasmer_object::{emit_compilation, emit_data, get_object_for_target};
 use wasmer_types::entity::EntityRef;
@@ -94,6 +98,7 @@ impl ObjectFileArtifact {
     fn generate_metadata<'data>(
         data: &'data [u8],
         features: &Features,
+        hardening: CodeHardening,
         tunables: &dyn Tunables,
     ) -> Result<
         (
@@ -123,6 +128,9 @@ impl ObjectFileArtifact {
             features: features.clone(),
             memory_styles,
             table_styles,
+            hardening,
+            count_function_calls: false,
+            middlewares_applied: false,
         };
 
         Ok((
@@ -145,7 +153,12 @@ impl ObjectFileArtifact {
         let target = engine.target();
         let compiler = engine_inner.compiler()?;
         let (compile_info, function_body_inputs, data_initializers, module_translation) =
-            Self::generate_metadata(data, engine_inner.features(), tunables)?;
+            Self::generate_metadata(
+                data,
+                engine_inner.features(),
+                compiler.code_hardening(),
+                tunables,
+            )?;
 
         let data_initializers = data_initializers
             .iter()
@@ -187,7 +200,9 @@ impl ObjectFileArtifact {
         - SignatureIndex -> VMSharedSignatureindextureIndex // signatures
          */
 
-        let serialized_data = bincode::serialize(&metadata).map_err(to_compile_error)?;
+        let mut serialized_data = Vec::new();
+        write_format_version(&mut serialized_data);
+        serialized_data.extend(bincode::serialize(&metadata).map_err(to_compile_error)?);
         let mut metadata_binary = vec![0; 10];
         let mut writable = &mut metadata_binary[..];
         leb128::write::unsigned(&mut writable, serialized_data.len() as u64)
@@ -298,7 +313,9 @@ impl ObjectFileArtifact {
         let mut reader = bytes;
         let data_len = leb128::read::unsigned(&mut reader).unwrap() as usize;
 
-        let metadata: ModuleMetadata = bincode::deserialize(&bytes[10..(data_len + 10)]).unwrap();
+        let metadata: ModuleMetadata =
+            bincode::deserialize(read_format_version(&bytes[10..(data_len + 10)])?)
+                .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))?;
 
         const WORD_SIZE: usize = mem::size_of::<usize>();
         let mut byte_buffer = [0u8; WORD_SIZE];
@@ -418,6 +435,10 @@ impl Artifact for ObjectFileArtifact {
         &self.metadata.compile_info.features
     }
 
+    fn code_hardening(&self) -> CodeHardening {
+        self.metadata.compile_info.hardening
+    }
+
     fn data_initializers(&self) -> &[OwnedDataInitializer] {
         &*self.metadata.data_initializers
     }
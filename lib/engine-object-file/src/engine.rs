@@ -114,6 +114,11 @@ impl Engine for ObjectFileEngine {
         self.inner().validate(binary)
     }
 
+    /// Checks that the compiler supports a WebAssembly module
+    fn precompile_validate(&self, binary: &[u8]) -> Result<(), Vec<CompileError>> {
+        self.inner().precompile_validate(binary)
+    }
+
     /// Compile a WebAssembly binary
     #[cfg(feature = "compiler")]
     fn compile(
@@ -222,6 +227,20 @@ impl ObjectFileEngineInner {
         ))
     }
 
+    /// Checks that the compiler supports the module
+    #[cfg(feature = "compiler")]
+    pub fn precompile_validate<'data>(&self, data: &'data [u8]) -> Result<(), Vec<CompileError>> {
+        self.compiler()
+            .map_err(|error| vec![error])?
+            .check_module_support(self.features(), data)
+    }
+
+    /// Checks that the compiler supports the module
+    #[cfg(not(feature = "compiler"))]
+    pub fn precompile_validate<'data>(&self, data: &'data [u8]) -> Result<(), Vec<CompileError>> {
+        self.validate(data).map_err(|error| vec![error])
+    }
+
     /// Shared signature registry.
     pub fn signatures(&self) -> &SignatureRegistry {
         &self.signatures
@@ -4,16 +4,17 @@ use crate::{
 use std::any::Any;
 use std::fs;
 use std::path::Path;
+use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
-use wasmer_compiler::Features;
+use wasmer_compiler::{CodeHardening, Features};
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
 use wasmer_types::{
     DataInitializer, FunctionIndex, LocalFunctionIndex, MemoryIndex, OwnedDataInitializer,
     SignatureIndex, TableIndex,
 };
 use wasmer_vm::{
-    FunctionBodyPtr, InstanceHandle, MemoryStyle, ModuleInfo, TableStyle, VMSharedSignatureIndex,
-    VMTrampoline,
+    FunctionBodyPtr, Imports, InstanceHandle, MemoryStyle, ModuleInfo, TableStyle,
+    VMSharedSignatureIndex, VMTrampoline,
 };
 
 /// An `Artifact` is the product that the `Engine`
@@ -42,6 +43,18 @@ pub trait Artifact: Send + Sync + Upcastable {
     /// Returns the features for this Artifact
     fn features(&self) -> &Features;
 
+    /// Returns the hardening applied to the code of this `Artifact`.
+    fn code_hardening(&self) -> CodeHardening {
+        CodeHardening::none()
+    }
+
+    /// Returns the call counters of the local functions, shared by all the
+    /// instances of the artifact, if it was compiled to count the calls of
+    /// its functions.
+    fn call_counters(&self) -> Option<&Arc<[AtomicU64]>> {
+        None
+    }
+
     /// Returns the memory styles associated with this `Artifact`.
     fn memory_styles(&self) -> &PrimaryMap<MemoryIndex, MemoryStyle>;
 
@@ -69,6 +82,18 @@ pub trait Artifact: Send + Sync + Upcastable {
     /// Serializes an artifact into bytes
     fn serialize(&self) -> Result<Vec<u8>, SerializeError>;
 
+    /// Serializes an artifact into bytes compressed with zstd at `level`,
+    /// that [`Engine::deserialize`] decompresses transparently.
+    ///
+    /// The artifacts that can't be compressed, like the shared objects of
+    /// the native engine, or the JIT artifacts when the `compression`
+    /// feature is disabled, are serialized as with [`Artifact::serialize`].
+    ///
+    /// [`Engine::deserialize`]: crate::Engine::deserialize
+    fn serialize_compressed(&self, _level: i32) -> Result<Vec<u8>, SerializeError> {
+        self.serialize()
+    }
+
     /// Serializes an artifact into a file path
     fn serialize_to_file(&self, path: &Path) -> Result<(), SerializeError> {
         let serialized = self.serialize()?;
@@ -76,11 +101,40 @@ pub trait Artifact: Send + Sync + Upcastable {
         Ok(())
     }
 
+    /// Returns the BLAKE3 hash of the serialized artifact.
+    ///
+    /// The serialized artifact includes the compiled code and the
+    /// configuration it was compiled with (features, memory and table
+    /// styles), so two artifacts with the same fingerprint are
+    /// interchangeable. The fingerprint of an artifact is stable within a
+    /// Wasmer patch release series, like the serialization format itself.
+    fn fingerprint(&self) -> Result<[u8; 32], SerializeError> {
+        let serialized = self.serialize()?;
+        Ok(blake3::hash(&serialized).into())
+    }
+
     /// Do preinstantiation logic that is executed before instantiating
     fn preinstantiate(&self) -> Result<(), InstantiationError> {
         Ok(())
     }
 
+    /// Resolves the imports of the module with `resolver`, to instantiate
+    /// it with [`Artifact::instantiate_with_imports`].
+    ///
+    /// The resolved imports can be cloned to create many instances of the
+    /// module while resolving them only once.
+    fn link_imports(&self, resolver: &dyn Resolver) -> Result<Imports, InstantiationError> {
+        self.preinstantiate()?;
+        resolve_imports(
+            self.module_ref(),
+            resolver,
+            &self.finished_dynamic_function_trampolines(),
+            self.memory_styles(),
+            self.table_styles(),
+        )
+        .map_err(InstantiationError::Link)
+    }
+
     /// Crate an `Instance` from this `Artifact`.
     ///
     /// # Safety
@@ -92,26 +146,28 @@ pub trait Artifact: Send + Sync + Upcastable {
         resolver: &dyn Resolver,
         host_state: Box<dyn Any>,
     ) -> Result<InstanceHandle, InstantiationError> {
-        self.preinstantiate()?;
+        let imports = self.link_imports(resolver)?;
+        self.instantiate_with_imports(tunables, imports, host_state)
+    }
 
+    /// Creates an `Instance` from this `Artifact`, with imports resolved by
+    /// [`Artifact::link_imports`].
+    ///
+    /// # Safety
+    ///
+    /// See [`InstanceHandle::new`].
+    unsafe fn instantiate_with_imports(
+        &self,
+        tunables: &dyn Tunables,
+        mut imports: Imports,
+        host_state: Box<dyn Any>,
+    ) -> Result<InstanceHandle, InstantiationError> {
         let module = self.module();
         let (instance_ptr, offsets) = InstanceHandle::allocate_instance(&module);
-        let (imports, import_initializers) = {
-            let mut imports = resolve_imports(
-                &module,
-                resolver,
-                &self.finished_dynamic_function_trampolines(),
-                self.memory_styles(),
-                self.table_styles(),
-            )
-            .map_err(InstantiationError::Link)?;
-
-            // Get the `WasmerEnv::init_with_instance` function pointers and the pointers
-            // to the envs to call it on.
-            let import_initializers: Vec<(_, _)> = imports.get_import_initializers();
-
-            (imports, import_initializers)
-        };
+
+        // Get the `WasmerEnv::init_with_instance` function pointers and the pointers
+        // to the envs to call it on.
+        let import_initializers: Vec<(_, _)> = imports.get_import_initializers();
 
         // Get pointers to where metadata about local memories should live in VM memory.
         let memory_definition_locations =
@@ -147,6 +203,7 @@ pub trait Artifact: Send + Sync + Upcastable {
             self.signatures().clone(),
             host_state,
             import_initializers,
+            self.call_counters().cloned(),
         )
         .map_err(|trap| InstantiationError::Start(RuntimeError::from_trap(trap)))?;
         Ok(handle)
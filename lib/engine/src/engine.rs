@@ -1,7 +1,7 @@
 //! JIT compilation.
 
 use crate::tunables::Tunables;
-use crate::{Artifact, DeserializeError};
+use crate::{Artifact, DeserializeError, TrapSink};
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use std::sync::Arc;
@@ -28,6 +28,15 @@ pub trait Engine {
     /// Validates a WebAssembly module
     fn validate(&self, binary: &[u8]) -> Result<(), CompileError>;
 
+    /// Checks that the engine can compile a WebAssembly module, without
+    /// compiling it.
+    ///
+    /// Besides validating the module, engines with a compiler report
+    /// all the features and the operators the compiler doesn't support.
+    fn precompile_validate(&self, binary: &[u8]) -> Result<(), Vec<CompileError>> {
+        self.validate(binary).map_err(|error| vec![error])
+    }
+
     /// Compile a WebAssembly binary
     fn compile(
         &self,
@@ -55,6 +64,12 @@ pub trait Engine {
         self.deserialize(&bytes)
     }
 
+    /// Returns the [`TrapSink`] receiving the traps of the WebAssembly
+    /// code run by the engine, if any.
+    fn trap_sink(&self) -> Option<Arc<dyn TrapSink>> {
+        None
+    }
+
     /// A unique identifier for this object.
     ///
     /// This exists to allow us to compare two Engines for equality. Otherwise,
@@ -66,9 +81,11 @@ pub trait Engine {
     fn cloned(&self) -> Arc<dyn Engine + Send + Sync>;
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(transparent)]
 /// A unique identifier for an Engine.
+///
+/// Clones of an engine share its state, and so its identifier.
 pub struct EngineId {
     id: usize,
 }
@@ -80,12 +97,6 @@ impl EngineId {
     }
 }
 
-impl Clone for EngineId {
-    fn clone(&self) -> Self {
-        Self::default()
-    }
-}
-
 impl Default for EngineId {
     fn default() -> Self {
         static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
@@ -56,6 +56,19 @@ pub enum ImportError {
     UnknownImport(ExternType),
 }
 
+/// An import of a module that can't be resolved, listed by
+/// [`LinkError::Imports`].
+#[derive(Error, Debug)]
+#[error("{module:?}.{name:?}: {error}")]
+pub struct UnresolvedImport {
+    /// The module of the import.
+    pub module: String,
+    /// The name of the import.
+    pub name: String,
+    /// Why the import can't be resolved.
+    pub error: ImportError,
+}
+
 /// The WebAssembly.LinkError object indicates an error during
 /// module instantiation (besides traps from the start function).
 ///
@@ -69,6 +82,11 @@ pub enum LinkError {
     #[error("Error while importing {0:?}.{1:?}: {2}")]
     Import(String, String, ImportError),
 
+    /// Several imports can't be resolved, in the order of the imports of
+    /// the module. A single one is reported as [`LinkError::Import`].
+    #[error("{} imports can't be resolved:{}", .0.len(), display_unresolved_imports(.0))]
+    Imports(Vec<UnresolvedImport>),
+
     /// A trap ocurred during linking.
     #[error("RuntimeError occurred during linking: {0}")]
     Trap(#[source] RuntimeError),
@@ -76,6 +94,18 @@ pub enum LinkError {
     /// Insufficient resources available for linking.
     #[error("Insufficient resources: {0}")]
     Resource(String),
+
+    /// The module was compiled with WebAssembly features that the store
+    /// doesn't allow.
+    #[error("the module uses WebAssembly features not allowed by the store: {}", .0.join(", "))]
+    DisallowedFeatures(Vec<&'static str>),
+}
+
+fn display_unresolved_imports(imports: &[UnresolvedImport]) -> String {
+    imports
+        .iter()
+        .map(|import| format!("\n    {}", import))
+        .collect()
 }
 
 /// An error while instantiating a module.
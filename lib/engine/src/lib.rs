@@ -32,14 +32,17 @@ mod tunables;
 pub use crate::artifact::Artifact;
 pub use crate::engine::{Engine, EngineId};
 pub use crate::error::{
-    DeserializeError, ImportError, InstantiationError, LinkError, SerializeError,
+    DeserializeError, ImportError, InstantiationError, LinkError, SerializeError, UnresolvedImport,
 };
 pub use crate::export::{Export, ExportFunction, ExportGlobal, ExportMemory, ExportTable};
 pub use crate::resolver::{
     resolve_imports, ChainableNamedResolver, NamedResolver, NamedResolverChain, NullResolver,
     Resolver,
 };
-pub use crate::serialize::SerializableFunctionFrameInfo;
+pub use crate::serialize::{
+    deserialize_symbols, read_format_version, serialize_symbols, write_format_version,
+    SerializableFunctionFrameInfo, ARTIFACT_FORMAT_VERSION,
+};
 pub use crate::trap::*;
 pub use crate::tunables::Tunables;
 
@@ -1,7 +1,7 @@
 //! Define the `Resolver` trait, allowing custom resolution for external
 //! references.
 
-use crate::{Export, ImportError, LinkError};
+use crate::{Export, ImportError, LinkError, UnresolvedImport};
 use more_asserts::assert_ge;
 use wasmer_types::entity::{BoxedSlice, EntityRef, PrimaryMap};
 use wasmer_types::{ExternType, FunctionIndex, ImportIndex, MemoryIndex, TableIndex};
@@ -118,6 +118,7 @@ fn get_extern_from_export(_module: &ModuleInfo, export: &Export) -> ExternType {
 /// a `Resolver`.
 ///
 /// If all imports are satisfied returns an `Imports` instance required for a module instantiation.
+/// Otherwise, the error lists all the imports that can't be resolved.
 pub fn resolve_imports(
     module: &ModuleInfo,
     resolver: &dyn Resolver,
@@ -132,26 +133,34 @@ pub fn resolve_imports(
     let mut memory_imports = PrimaryMap::with_capacity(module.num_imported_memories);
     let mut global_imports = PrimaryMap::with_capacity(module.num_imported_globals);
 
+    // All the imports are checked, to report all the unresolved ones at once.
+    let mut unresolved_imports = Vec::new();
     for ((module_name, field, import_idx), import_index) in module.imports.iter() {
         let resolved = resolver.resolve(*import_idx, module_name, field);
         let import_extern = get_extern_from_import(module, import_index);
         let resolved = match resolved {
             None => {
-                return Err(LinkError::Import(
-                    module_name.to_string(),
-                    field.to_string(),
-                    ImportError::UnknownImport(import_extern),
-                ));
+                unresolved_imports.push(UnresolvedImport {
+                    module: module_name.to_string(),
+                    name: field.to_string(),
+                    error: ImportError::UnknownImport(import_extern),
+                });
+                continue;
             }
             Some(r) => r,
         };
         let export_extern = get_extern_from_export(module, &resolved);
         if !export_extern.is_compatible_with(&import_extern) {
-            return Err(LinkError::Import(
-                module_name.to_string(),
-                field.to_string(),
-                ImportError::IncompatibleType(import_extern, export_extern),
-            ));
+            unresolved_imports.push(UnresolvedImport {
+                module: module_name.to_string(),
+                name: field.to_string(),
+                error: ImportError::IncompatibleType(import_extern, export_extern),
+            });
+            continue;
+        }
+        if !unresolved_imports.is_empty() {
+            // The instantiation fails anyway.
+            continue;
         }
         match resolved {
             Export::Function(ref f) => {
@@ -260,6 +269,14 @@ pub fn resolve_imports(
         }
     }
 
+    if unresolved_imports.len() == 1 {
+        let import = unresolved_imports.pop().unwrap();
+        return Err(LinkError::Import(import.module, import.name, import.error));
+    }
+    if !unresolved_imports.is_empty() {
+        return Err(LinkError::Imports(unresolved_imports));
+    }
+
     Ok(Imports::new(
         function_imports,
         host_function_env_initializers,
@@ -1,8 +1,11 @@
+use crate::{DeserializeError, SerializeError};
 use serde::de::{Deserializer, Visitor};
 use serde::ser::Serializer;
 use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
 use std::fmt;
 use wasmer_compiler::CompiledFunctionFrameInfo;
+use wasmer_vm::ModuleSymbols;
 
 /// This is the unserialized verison of `CompiledFunctionFrameInfo`.
 #[derive(Clone, Serialize, Deserialize)]
@@ -102,3 +105,58 @@ impl<'de> Deserialize<'de> for SerializableFunctionFrameInfo {
         ))
     }
 }
+
+/// The version of the format of the artifacts serialized by the engines.
+///
+/// It must be bumped by any change to the serialized types released in a
+/// version of Wasmer, so that the artifacts of another version are
+/// rejected instead of being misread.
+pub const ARTIFACT_FORMAT_VERSION: u32 = 1;
+
+/// Appends the [`ARTIFACT_FORMAT_VERSION`] to `serialized`, the header of
+/// an artifact being serialized.
+pub fn write_format_version(serialized: &mut Vec<u8>) {
+    serialized.extend_from_slice(&ARTIFACT_FORMAT_VERSION.to_le_bytes());
+}
+
+/// Checks the format version at the start of `bytes`, the rest of a
+/// serialized artifact after its header, and returns the bytes after it.
+pub fn read_format_version(bytes: &[u8]) -> Result<&[u8], DeserializeError> {
+    if bytes.len() < 4 {
+        return Err(DeserializeError::Incompatible(
+            "The artifact has no format version".to_string(),
+        ));
+    }
+    let version = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    if version != ARTIFACT_FORMAT_VERSION {
+        return Err(DeserializeError::Incompatible(format!(
+            "The artifact has the format version {}, but this engine reads version {}",
+            version, ARTIFACT_FORMAT_VERSION
+        )));
+    }
+    Ok(&bytes[4..])
+}
+
+/// The header of a serialized [`ModuleSymbols`] sidecar.
+const SYMBOLS_MAGIC_HEADER: &[u8] = b"\0wasmer-symbols";
+
+/// Serializes the symbols stripped from a module, so they can be stored
+/// next to the stripped artifact and attached to it later.
+pub fn serialize_symbols(symbols: &ModuleSymbols) -> Result<Vec<u8>, SerializeError> {
+    let bytes =
+        bincode::serialize(symbols).map_err(|e| SerializeError::Generic(format!("{:?}", e)))?;
+    let mut serialized = SYMBOLS_MAGIC_HEADER.to_vec();
+    serialized.extend(bytes);
+    Ok(serialized)
+}
+
+/// Deserializes symbols serialized with [`serialize_symbols`].
+pub fn deserialize_symbols(bytes: &[u8]) -> Result<ModuleSymbols, DeserializeError> {
+    if !bytes.starts_with(SYMBOLS_MAGIC_HEADER) {
+        return Err(DeserializeError::Incompatible(
+            "The provided bytes are not wasmer symbols".to_string(),
+        ));
+    }
+    bincode::deserialize(&bytes[SYMBOLS_MAGIC_HEADER.len()..])
+        .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))
+}
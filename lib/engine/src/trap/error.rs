@@ -4,7 +4,8 @@ use std::error::Error;
 use std::fmt;
 use std::sync::Arc;
 use std::sync::RwLockReadGuard;
-use wasmer_vm::{raise_user_trap, Trap, TrapCode};
+use wasmer_types::entity::EntityRef;
+use wasmer_vm::{raise_user_trap, MemoryAccessFault, Trap, TrapCode};
 
 /// A struct representing an aborted instruction execution, with a message
 /// indicating the cause.
@@ -19,6 +20,8 @@ enum RuntimeErrorSource {
     Generic(String),
     User(Box<dyn Error + Send + Sync>),
     Trap(TrapCode),
+    MemoryFault(TrapCode, MemoryAccessFault),
+    GuestAbort(String),
 }
 
 impl fmt::Display for RuntimeErrorSource {
@@ -27,6 +30,20 @@ impl fmt::Display for RuntimeErrorSource {
             Self::Generic(s) => write!(f, "{}", s),
             Self::User(s) => write!(f, "{}", s),
             Self::Trap(s) => write!(f, "{}", s.message()),
+            Self::MemoryFault(s, fault) => {
+                write!(
+                    f,
+                    "{} (memory {}, address 0x{:x}",
+                    s.message(),
+                    fault.memory_index.index(),
+                    fault.address
+                )?;
+                if let Some(access_size) = fault.access_size {
+                    write!(f, ", {}-byte access", access_size)?;
+                }
+                write!(f, ", memory size {} bytes)", fault.memory_size)
+            }
+            Self::GuestAbort(s) => write!(f, "guest aborted: {}", s),
         }
     }
 }
@@ -36,6 +53,8 @@ struct RuntimeErrorInner {
     source: RuntimeErrorSource,
     /// The reconstructed Wasm trace (from the native trace and the `GlobalFrameInfo`).
     wasm_trace: Vec<FrameInfo>,
+    /// The positions in `wasm_trace` of the host functions re-entering Wasm.
+    host_boundaries: Vec<usize>,
     /// The native backtrace
     native_trace: Backtrace,
 }
@@ -63,19 +82,43 @@ impl RuntimeError {
         )
     }
 
+    /// Creates a new `RuntimeError` for a guest that aborted with the
+    /// given `message`.
+    ///
+    /// # Example
+    /// ```
+    /// let trap = wasmer_engine::RuntimeError::guest_abort("out of gas");
+    /// assert_eq!(Some("out of gas"), trap.guest_abort_message());
+    /// assert_eq!("guest aborted: out of gas", trap.message());
+    /// ```
+    pub fn guest_abort<I: Into<String>>(message: I) -> Self {
+        let info = FRAME_INFO.read().unwrap();
+        Self::new_with_trace(
+            info,
+            None,
+            RuntimeErrorSource::GuestAbort(message.into()),
+            Backtrace::new_unresolved(),
+        )
+    }
+
     /// Create a new RuntimeError from a Trap.
     pub fn from_trap(trap: Trap) -> Self {
         let info = FRAME_INFO.read().unwrap();
         match trap {
-            Trap::User(error) => {
-                match error.downcast::<Self>() {
+            Trap::User { error, backtrace } => {
+                match (error.downcast::<Self>(), backtrace) {
+                    // The error is already a RuntimeError, raised again by a host function
+                    // called by Wasm: its trace continues with the frames of the caller
+                    (Ok(runtime_error), Some(backtrace)) => {
+                        runtime_error.continued_through_host(info, &backtrace)
+                    }
                     // The error is already a RuntimeError, we return it directly
-                    Ok(runtime_error) => *runtime_error,
-                    Err(e) => Self::new_with_trace(
+                    (Ok(runtime_error), None) => *runtime_error,
+                    (Err(e), backtrace) => Self::new_with_trace(
                         info,
                         None,
                         RuntimeErrorSource::User(e),
-                        Backtrace::new_unresolved(),
+                        backtrace.unwrap_or_else(Backtrace::new_unresolved),
                     ),
                 }
             }
@@ -84,6 +127,7 @@ impl RuntimeError {
                 pc,
                 signal_trap,
                 backtrace,
+                memory_fault,
             } => {
                 let info = if info.should_process_frame(pc).unwrap_or(false) {
                     drop(info);
@@ -99,7 +143,13 @@ impl RuntimeError {
                     .map_or(signal_trap.unwrap_or(TrapCode::StackOverflow), |info| {
                         info.trap_code
                     });
-                Self::new_with_trace(info, Some(pc), RuntimeErrorSource::Trap(code), backtrace)
+                let source = match memory_fault {
+                    Some(fault) if code == TrapCode::HeapAccessOutOfBounds => {
+                        RuntimeErrorSource::MemoryFault(code, fault)
+                    }
+                    _ => RuntimeErrorSource::Trap(code),
+                };
+                Self::new_with_trace(info, Some(pc), source, backtrace)
             }
             // A trap triggered manually from the Wasmer runtime
             Trap::Runtime {
@@ -120,78 +170,112 @@ impl RuntimeError {
         source: RuntimeErrorSource,
         native_trace: Backtrace,
     ) -> Self {
-        let frames: Vec<usize> = native_trace
-            .frames()
-            .iter()
-            .filter_map(|frame| {
-                let pc = frame.ip() as usize;
-                if pc == 0 {
-                    None
-                } else {
-                    // Note that we need to be careful about the pc we pass in here to
-                    // lookup frame information. This program counter is used to
-                    // translate back to an original source location in the origin wasm
-                    // module. If this pc is the exact pc that the trap happened at,
-                    // then we look up that pc precisely. Otherwise backtrace
-                    // information typically points at the pc *after* the call
-                    // instruction (because otherwise it's likely a call instruction on
-                    // the stack). In that case we want to lookup information for the
-                    // previous instruction (the call instruction) so we subtract one as
-                    // the lookup.
-                    let pc_to_lookup = if Some(pc) == trap_pc { pc } else { pc - 1 };
-                    Some(pc_to_lookup)
-                }
-            })
-            .collect();
-
-        // If any of the frames is not processed, we adquire the lock to
-        // modify the GlobalFrameInfo module.
-        let info = if frames
-            .iter()
-            .any(|pc| info.should_process_frame(*pc).unwrap_or(false))
-        {
-            // We drop the read lock, to get a write one.
-            // Note: this is not guaranteed because it's a RwLock:
-            // the following code may cause deadlocks.
-            // TODO: clean up this code
-            drop(info);
-            {
-                let mut info = FRAME_INFO.write().unwrap();
-                for pc in frames.iter() {
-                    info.maybe_process_frame(*pc);
-                }
-            }
-            FRAME_INFO.read().unwrap()
-        } else {
-            info
-        };
-
-        // Let's construct the trace
-        let wasm_trace = frames
-            .into_iter()
-            .filter_map(|pc| info.lookup_frame_info(pc))
-            .collect::<Vec<_>>();
-
+        let wasm_trace = lookup_wasm_trace(info, trap_pc, &native_trace);
         Self {
             inner: Arc::new(RuntimeErrorInner {
                 source,
                 wasm_trace,
+                host_boundaries: Vec::new(),
                 native_trace,
             }),
         }
     }
 
+    /// Continues the trace of a trap in a guest called by a host function with the frames of
+    /// the guest which called that host function, found in `host_trace`, the native backtrace
+    /// of the host function when it raised the trap again.
+    ///
+    /// The native backtrace of the trap only covers the frames of the callers when it could be
+    /// unwound through the frames of the host function, which depends on the platform.
+    fn continued_through_host(
+        self,
+        info: RwLockReadGuard<GlobalFrameInfo>,
+        host_trace: &Backtrace,
+    ) -> Self {
+        let caller_frames = lookup_wasm_trace(info, None, host_trace);
+        let mut inner = match Arc::try_unwrap(self.inner) {
+            Ok(inner) => inner,
+            // The error is shared, so it can't change anymore.
+            Err(inner) => return Self { inner },
+        };
+        let covered = inner.wasm_trace.len() >= caller_frames.len()
+            && inner.wasm_trace[inner.wasm_trace.len() - caller_frames.len()..]
+                .iter()
+                .zip(&caller_frames)
+                .all(|(frame, caller_frame)| frame.is_same_frame(caller_frame));
+        let boundary = if covered {
+            inner.wasm_trace.len() - caller_frames.len()
+        } else {
+            let boundary = inner.wasm_trace.len();
+            inner.wasm_trace.extend(caller_frames);
+            boundary
+        };
+        // A boundary is only kept between the frames of two guests: a trap raised by a host
+        // function called by the outermost guest has no guest frames before its boundary.
+        if boundary > 0
+            && boundary < inner.wasm_trace.len()
+            && !inner.host_boundaries.contains(&boundary)
+        {
+            inner.host_boundaries.push(boundary);
+        }
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
     /// Returns a reference the `message` stored in `Trap`.
     pub fn message(&self) -> String {
         format!("{}", self.inner.source)
     }
 
+    /// Returns the message the guest aborted with, if this `RuntimeError`
+    /// was created with [`RuntimeError::guest_abort`].
+    pub fn guest_abort_message(&self) -> Option<&str> {
+        match &self.inner.source {
+            RuntimeErrorSource::GuestAbort(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Returns the trap code of this `RuntimeError`, if it was raised by
+    /// the WebAssembly code or the runtime.
+    pub fn trap_code(&self) -> Option<TrapCode> {
+        match &self.inner.source {
+            RuntimeErrorSource::Trap(code) | RuntimeErrorSource::MemoryFault(code, _) => {
+                Some(*code)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the details of the out of bounds memory access that caused
+    /// this trap, if known.
+    ///
+    /// They are known when the access hit the guard pages of a memory of
+    /// the running instance.
+    pub fn memory_access_fault(&self) -> Option<&MemoryAccessFault> {
+        match &self.inner.source {
+            RuntimeErrorSource::MemoryFault(_, fault) => Some(fault),
+            _ => None,
+        }
+    }
+
     /// Returns a list of function frames in WebAssembly code that led to this
     /// trap happening.
     pub fn trace(&self) -> &[FrameInfo] {
         &self.inner.wasm_trace
     }
 
+    /// Returns the positions in [`RuntimeError::trace`] of the host
+    /// functions between the frames of nested guests, in increasing order.
+    ///
+    /// A position `i` means that the frame `i - 1` was called by a host
+    /// function, itself called by the frame `i`, like when a host import
+    /// of a guest calls another guest which traps.
+    pub fn host_boundaries(&self) -> &[usize] {
+        &self.inner.host_boundaries
+    }
+
     /// Attempts to downcast the `RuntimeError` to a concrete type.
     pub fn downcast<T: Error + 'static>(self) -> Result<T, Self> {
         match Arc::try_unwrap(self.inner) {
@@ -216,6 +300,66 @@ impl RuntimeError {
     }
 }
 
+/// Returns the Wasm frames of `native_trace`, `trap_pc` being the program counter of the trap,
+/// if in Wasm.
+fn lookup_wasm_trace(
+    info: RwLockReadGuard<GlobalFrameInfo>,
+    trap_pc: Option<usize>,
+    native_trace: &Backtrace,
+) -> Vec<FrameInfo> {
+    let frames: Vec<usize> = native_trace
+        .frames()
+        .iter()
+        .filter_map(|frame| {
+            let pc = frame.ip() as usize;
+            if pc == 0 {
+                None
+            } else {
+                // Note that we need to be careful about the pc we pass in here to
+                // lookup frame information. This program counter is used to
+                // translate back to an original source location in the origin wasm
+                // module. If this pc is the exact pc that the trap happened at,
+                // then we look up that pc precisely. Otherwise backtrace
+                // information typically points at the pc *after* the call
+                // instruction (because otherwise it's likely a call instruction on
+                // the stack). In that case we want to lookup information for the
+                // previous instruction (the call instruction) so we subtract one as
+                // the lookup.
+                let pc_to_lookup = if Some(pc) == trap_pc { pc } else { pc - 1 };
+                Some(pc_to_lookup)
+            }
+        })
+        .collect();
+
+    // If any of the frames is not processed, we adquire the lock to
+    // modify the GlobalFrameInfo module.
+    let info = if frames
+        .iter()
+        .any(|pc| info.should_process_frame(*pc).unwrap_or(false))
+    {
+        // We drop the read lock, to get a write one.
+        // Note: this is not guaranteed because it's a RwLock:
+        // the following code may cause deadlocks.
+        // TODO: clean up this code
+        drop(info);
+        {
+            let mut info = FRAME_INFO.write().unwrap();
+            for pc in frames.iter() {
+                info.maybe_process_frame(*pc);
+            }
+        }
+        FRAME_INFO.read().unwrap()
+    } else {
+        info
+    };
+
+    // Let's construct the trace
+    frames
+        .into_iter()
+        .filter_map(|pc| info.lookup_frame_info(pc))
+        .collect()
+}
+
 impl fmt::Debug for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("RuntimeError")
@@ -233,16 +377,17 @@ impl fmt::Display for RuntimeError {
         if trace.is_empty() {
             return Ok(());
         }
-        for frame in self.trace().iter() {
+        for (i, frame) in self.trace().iter().enumerate() {
+            if self.host_boundaries().contains(&i) {
+                writeln!(f)?;
+                write!(f, "    at <host>")?;
+            }
             let name = frame.module_name();
             let func_index = frame.func_index();
             writeln!(f)?;
             write!(f, "    at ")?;
-            match frame.function_name() {
-                Some(name) => match rustc_demangle::try_demangle(name) {
-                    Ok(name) => write!(f, "{}", name)?,
-                    Err(_) => write!(f, "{}", name)?,
-                },
+            match frame.demangled_function_name() {
+                Some(name) => write!(f, "{}", name)?,
                 None => write!(f, "<unnamed>")?,
             }
             write!(
@@ -252,6 +397,9 @@ impl fmt::Display for RuntimeError {
                 func_index,
                 frame.module_offset()
             )?;
+            if let Some(location) = frame.source_location() {
+                write!(f, " at {}", location)?;
+            }
         }
         Ok(())
     }
@@ -261,7 +409,7 @@ impl std::error::Error for RuntimeError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.inner.source {
             RuntimeErrorSource::User(err) => Some(&**err),
-            RuntimeErrorSource::Trap(err) => Some(err),
+            RuntimeErrorSource::Trap(err) | RuntimeErrorSource::MemoryFault(err, _) => Some(err),
             _ => None,
         }
     }
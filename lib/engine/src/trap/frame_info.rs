@@ -10,12 +10,14 @@
 //! let module: ModuleInfo = ...;
 //! FRAME_INFO.register(module, compiled_functions);
 //! ```
+use super::source_map::{SourceLocation, SourceMap};
 use crate::serialize::SerializableFunctionFrameInfo;
 use std::cmp;
+use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::sync::{Arc, RwLock};
 use wasmer_compiler::{CompiledFunctionFrameInfo, SourceLoc, TrapInformation};
-use wasmer_types::entity::{BoxedSlice, EntityRef, PrimaryMap};
+use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::LocalFunctionIndex;
 use wasmer_vm::{FunctionBodyPtr, ModuleInfo};
 
@@ -30,31 +32,46 @@ lazy_static::lazy_static! {
 
 #[derive(Default)]
 pub struct GlobalFrameInfo {
-    /// An internal map that keeps track of backtrace frame information for
-    /// each module.
+    /// The backtrace frame information of each module, by the key of its
+    /// registration.
+    modules: BTreeMap<usize, ModuleInfoFrameInfo>,
+    /// An internal map that keeps track of the code of each module.
     ///
-    /// This map is morally a map of ranges to a map of information for that
-    /// module. Each module is expected to reside in a disjoint section of
-    /// contiguous memory. No modules can overlap.
+    /// This map is morally a map of ranges to a module. Each module is
+    /// expected to reside in a disjoint section of contiguous memory. No
+    /// modules can overlap.
     ///
     /// The key of this map is the highest address in the module and the value
-    /// is the module's information, which also contains the start address.
-    ranges: BTreeMap<usize, ModuleInfoFrameInfo>,
+    /// is the key of the module's information, which also contains the start
+    /// address.
+    ranges: BTreeMap<usize, usize>,
+    /// The function bodies a module shares with the code of another module,
+    /// by their highest address, with the keys of the modules sharing them.
+    shared_functions: BTreeMap<usize, Vec<usize>>,
+    /// The key of the next registered module.
+    next_key: usize,
 }
 
 /// An RAII structure used to unregister a module's frame information when the
 /// module is destroyed.
 pub struct GlobalFrameInfoRegistration {
-    /// The key that will be removed from the global `ranges` map when this is
+    /// The key that will be removed from the global `modules` map when this is
     /// dropped.
     key: usize,
 }
 
 struct ModuleInfoFrameInfo {
-    start: usize,
+    /// The start and highest addresses of the code of the module, if it has
+    /// functions of its own.
+    range: Option<(usize, usize)>,
+    /// The highest addresses of the function bodies shared with the code of
+    /// another module.
+    shared_function_ends: Vec<usize>,
     functions: BTreeMap<usize, FunctionInfo>,
     module: Arc<ModuleInfo>,
     frame_infos: PrimaryMap<LocalFunctionIndex, SerializableFunctionFrameInfo>,
+    /// The source map embedded in the module, if any.
+    source_map: Option<SourceMap>,
 }
 
 impl ModuleInfoFrameInfo {
@@ -161,6 +178,10 @@ impl GlobalFrameInfo {
             function_name: module.module.function_names.get(&func_index).cloned(),
             instr,
             func_start: instr_map.start_srcloc,
+            source_location: module
+                .source_map
+                .as_ref()
+                .and_then(|source_map| source_map.lookup(instr.bits() as usize)),
         })
     }
 
@@ -192,29 +213,52 @@ impl GlobalFrameInfo {
         Some(())
     }
 
+    /// Gets the key of a module given a pc
+    fn module_key(&self, pc: usize) -> Option<usize> {
+        if let Some((_, key)) = self.ranges.range(pc..).next() {
+            if self.modules[key].function_info(pc).is_some() {
+                return Some(*key);
+            }
+        }
+        // A shared function body is in the code of a single module, which
+        // may not be registered: any module sharing it describes its frames.
+        let (_, keys) = self.shared_functions.range(pc..).next()?;
+        keys.iter()
+            .copied()
+            .find(|key| self.modules[key].function_info(pc).is_some())
+    }
+
     /// Gets a module given a pc
     fn module_info(&self, pc: usize) -> Option<&ModuleInfoFrameInfo> {
-        let (end, module_info) = self.ranges.range(pc..).next()?;
-        if pc < module_info.start || *end < pc {
-            return None;
-        }
-        Some(module_info)
+        let key = self.module_key(pc)?;
+        self.modules.get(&key)
     }
 
     /// Gets a module given a pc
     fn module_info_mut(&mut self, pc: usize) -> Option<&mut ModuleInfoFrameInfo> {
-        let (end, module_info) = self.ranges.range_mut(pc..).next()?;
-        if pc < module_info.start || *end < pc {
-            return None;
-        }
-        Some(module_info)
+        let key = self.module_key(pc)?;
+        self.modules.get_mut(&key)
     }
 }
 
 impl Drop for GlobalFrameInfoRegistration {
     fn drop(&mut self) {
         if let Ok(mut info) = FRAME_INFO.write() {
-            info.ranges.remove(&self.key);
+            let module = match info.modules.remove(&self.key) {
+                Some(module) => module,
+                None => return,
+            };
+            if let Some((_, end)) = module.range {
+                info.ranges.remove(&end);
+            }
+            for end in module.shared_function_ends.iter() {
+                if let Entry::Occupied(mut entry) = info.shared_functions.entry(*end) {
+                    entry.get_mut().retain(|key| *key != self.key);
+                    if entry.get().is_empty() {
+                        entry.remove();
+                    }
+                }
+            }
         }
     }
 }
@@ -237,9 +281,15 @@ pub struct FunctionExtent {
 /// compiled functions within `module`. If the `module` has no functions
 /// then `None` will be returned. Otherwise the returned object, when
 /// dropped, will be used to unregister all name information from this map.
-pub fn register(
+///
+/// The `shared_functions` share the body of a function of another module, or
+/// of one of the `finished_functions`. Their frames are attributed to the
+/// module whose code holds the body while it's registered, and to one of the
+/// modules sharing it otherwise.
+pub fn register<'a>(
     module: Arc<ModuleInfo>,
-    finished_functions: &BoxedSlice<LocalFunctionIndex, FunctionExtent>,
+    finished_functions: impl IntoIterator<Item = (LocalFunctionIndex, &'a FunctionExtent)>,
+    shared_functions: impl IntoIterator<Item = (LocalFunctionIndex, &'a FunctionExtent)>,
     frame_infos: PrimaryMap<LocalFunctionIndex, SerializableFunctionFrameInfo>,
 ) -> Option<GlobalFrameInfoRegistration> {
     let mut min = usize::max_value();
@@ -251,7 +301,7 @@ pub fn register(
             ptr: start,
             length: len,
         },
-    ) in finished_functions.iter()
+    ) in finished_functions
     {
         let start = **start as usize;
         let end = start + len;
@@ -263,32 +313,62 @@ pub fn register(
         };
         assert!(functions.insert(end, func).is_none());
     }
+    let range = if functions.is_empty() {
+        None
+    } else {
+        Some((min, max))
+    };
+    let mut shared_function_ends = Vec::new();
+    for (i, FunctionExtent { ptr, length }) in shared_functions {
+        let start = **ptr as usize;
+        let end = start + length;
+        // Several functions of the module may share the same body.
+        if let Entry::Vacant(entry) = functions.entry(end) {
+            entry.insert(FunctionInfo {
+                start,
+                local_index: i,
+            });
+            shared_function_ends.push(end);
+        }
+    }
     if functions.is_empty() {
         return None;
     }
 
+    // A malformed source map only costs the source locations.
+    let source_map = SourceMap::from_module(&module).and_then(Result::ok);
+
     let mut info = FRAME_INFO.write().unwrap();
-    // First up assert that our chunk of jit functions doesn't collide with
-    // any other known chunks of jit functions...
-    if let Some((_, prev)) = info.ranges.range(max..).next() {
-        assert!(prev.start > max);
+    let key = info.next_key;
+    info.next_key += 1;
+    if let Some((min, max)) = range {
+        // First up assert that our chunk of jit functions doesn't collide with
+        // any other known chunks of jit functions...
+        if let Some((_, prev)) = info.ranges.range(max..).next() {
+            assert!(info.modules[prev].range.unwrap().0 > max);
+        }
+        if let Some((prev_end, _)) = info.ranges.range(..=min).next_back() {
+            assert!(*prev_end < min);
+        }
+
+        // ... then insert our range and assert nothing was there previously
+        assert!(info.ranges.insert(max, key).is_none());
     }
-    if let Some((prev_end, _)) = info.ranges.range(..=min).next_back() {
-        assert!(*prev_end < min);
+    for end in shared_function_ends.iter() {
+        info.shared_functions.entry(*end).or_default().push(key);
     }
-
-    // ... then insert our range and assert nothing was there previously
-    let prev = info.ranges.insert(
-        max,
+    info.modules.insert(
+        key,
         ModuleInfoFrameInfo {
-            start: min,
+            range,
+            shared_function_ends,
             functions,
             module,
             frame_infos,
+            source_map,
         },
     );
-    assert!(prev.is_none());
-    Some(GlobalFrameInfoRegistration { key: max })
+    Some(GlobalFrameInfoRegistration { key })
 }
 
 /// Description of a frame in a backtrace for a [`Trap`].
@@ -305,6 +385,7 @@ pub struct FrameInfo {
     function_name: Option<String>,
     func_start: SourceLoc,
     instr: SourceLoc,
+    source_location: Option<SourceLocation>,
 }
 
 impl FrameInfo {
@@ -347,6 +428,17 @@ impl FrameInfo {
         self.function_name.as_deref()
     }
 
+    /// Returns the name of the function for this frame like
+    /// [`FrameInfo::function_name`], demangled if it's a mangled Rust
+    /// symbol.
+    pub fn demangled_function_name(&self) -> Option<String> {
+        let name = self.function_name()?;
+        Some(match rustc_demangle::try_demangle(name) {
+            Ok(name) => name.to_string(),
+            Err(_) => name.to_string(),
+        })
+    }
+
     /// Returns the offset within the original wasm module this frame's program
     /// counter was at.
     ///
@@ -356,6 +448,14 @@ impl FrameInfo {
         self.instr.bits() as usize
     }
 
+    /// Returns whether `self` and `other` are at the same instruction of
+    /// the same function.
+    pub(crate) fn is_same_frame(&self, other: &Self) -> bool {
+        self.module_name == other.module_name
+            && self.func_index == other.func_index
+            && self.instr == other.instr
+    }
+
     /// Returns the offset from the original wasm module's function to this
     /// frame's program counter.
     ///
@@ -365,4 +465,13 @@ impl FrameInfo {
     pub fn func_offset(&self) -> usize {
         (self.instr.bits() - self.func_start.bits()) as usize
     }
+
+    /// Returns the location in the original sources of this frame's
+    /// program counter, if the module embeds a source map.
+    ///
+    /// Source maps stored outside of the module can be applied to
+    /// [`FrameInfo::module_offset`] with [`SourceMap::lookup`].
+    pub fn source_location(&self) -> Option<&SourceLocation> {
+        self.source_location.as_ref()
+    }
 }
@@ -1,7 +1,13 @@
 mod error;
 mod frame_info;
+mod sink;
+mod source_map;
 pub use error::RuntimeError;
 pub use frame_info::{
     register as register_frame_info, FrameInfo, FunctionExtent, GlobalFrameInfoRegistration,
     FRAME_INFO,
 };
+pub use sink::{TrapRecord, TrapSink};
+pub use source_map::{
+    source_mapping_url, SourceLocation, SourceMap, SourceMapError, SOURCE_MAPPING_URL_SECTION,
+};
@@ -0,0 +1,54 @@
+use super::error::RuntimeError;
+use super::frame_info::FrameInfo;
+use std::time::SystemTime;
+use wasmer_vm::TrapCode;
+
+/// A receiver of the traps of the WebAssembly code run by an [`Engine`].
+///
+/// The sink installed on an engine receives a [`TrapRecord`] for every
+/// call from the host into WebAssembly that fails, so that the failures
+/// of the guests can be aggregated in one place instead of at every call
+/// site. It's called on the thread that made the call, before the error
+/// is returned to the host: it should be cheap and must not call into
+/// WebAssembly.
+///
+/// [`Engine`]: crate::Engine
+pub trait TrapSink: Send + Sync {
+    /// Receives the record of a trap.
+    fn on_trap(&self, record: &TrapRecord);
+}
+
+/// The structured record of a trap, received by a [`TrapSink`].
+#[derive(Debug, Clone)]
+pub struct TrapRecord {
+    /// The trap code, if the trap was raised by the WebAssembly code or
+    /// the runtime rather than by a host function.
+    pub trap_code: Option<TrapCode>,
+    /// The message of the error.
+    pub message: String,
+    /// The WebAssembly frames that led to the trap.
+    pub frames: Vec<FrameInfo>,
+    /// The hash of the module of the called function, see
+    /// `Module::hash`.
+    pub module_hash: Option<[u8; 32]>,
+    /// Identifies the instance of the called function. It's unique among
+    /// the instances alive at the time of the trap.
+    pub instance_id: usize,
+    /// When the trap was received.
+    pub timestamp: SystemTime,
+}
+
+impl TrapRecord {
+    /// Creates the record of `error`, raised by a call into the given
+    /// instance.
+    pub fn new(error: &RuntimeError, module_hash: Option<[u8; 32]>, instance_id: usize) -> Self {
+        Self {
+            trap_code: error.trap_code(),
+            message: error.message(),
+            frames: error.trace().to_vec(),
+            module_hash,
+            instance_id,
+            timestamp: SystemTime::now(),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Source maps, mapping the offsets of a WebAssembly module back to the
+//! sources it was compiled from.
+//!
+//! Toolchains like AssemblyScript, TinyGo or Emscripten write the URL of
+//! the source map in the `sourceMappingURL` custom section. The maps
+//! follow the [Source Map Revision 3] format, where the "columns" of the
+//! generated code are the offsets in the WebAssembly module.
+//!
+//! [Source Map Revision 3]: https://sourcemaps.info/spec.html
+use serde::Deserialize;
+use std::fmt;
+use thiserror::Error;
+use wasmer_vm::ModuleInfo;
+
+/// The name of the custom section holding the URL of the source map of
+/// a module.
+pub const SOURCE_MAPPING_URL_SECTION: &str = "sourceMappingURL";
+
+/// The prefix of the source map URLs embedding the source map.
+const DATA_URL_PREFIX: &str = "data:application/json;base64,";
+
+const BASE64_DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// An error reading a source map.
+#[derive(Error, Debug)]
+pub enum SourceMapError {
+    /// The source map isn't valid JSON, or misses required fields.
+    #[error("invalid source map: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The `mappings` field is malformed.
+    #[error("invalid source map mappings: {0}")]
+    Mappings(String),
+    /// The source map URL doesn't embed the source map.
+    #[error("the source map URL `{0}` doesn't embed a source map")]
+    NotEmbedded(String),
+}
+
+/// A location in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The path or the URL of the source file, as written in the source
+    /// map.
+    pub file: String,
+    /// The line, starting at 1.
+    pub line: u32,
+    /// The column, starting at 1.
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawSourceMap {
+    #[serde(default, rename = "sourceRoot")]
+    source_root: Option<String>,
+    sources: Vec<Option<String>>,
+    mappings: String,
+}
+
+/// A mapping from an offset in the module to a location in the sources.
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    offset: i64,
+    /// The index of the source, its line and its column, all starting at 0.
+    location: Option<(i64, i64, i64)>,
+}
+
+/// A parsed source map of a WebAssembly module.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    sources: Vec<String>,
+    /// The mappings, sorted by offset.
+    mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    /// Parses a source map from its JSON representation.
+    pub fn from_json(json: &str) -> Result<Self, SourceMapError> {
+        let raw: RawSourceMap = serde_json::from_str(json)?;
+        let root = raw
+            .source_root
+            .filter(|root| !root.is_empty())
+            .map(|root| {
+                if root.ends_with('/') {
+                    root
+                } else {
+                    root + "/"
+                }
+            })
+            .unwrap_or_default();
+        let sources = raw
+            .sources
+            .into_iter()
+            .map(|source| format!("{}{}", root, source.unwrap_or_default()))
+            .collect();
+        let mut mappings = parse_mappings(&raw.mappings)?;
+        mappings.sort_by_key(|mapping| mapping.offset);
+        Ok(Self { sources, mappings })
+    }
+
+    /// Parses a source map embedded in a `data:application/json;base64,`
+    /// URL.
+    pub fn from_data_url(url: &str) -> Result<Self, SourceMapError> {
+        let not_embedded = || SourceMapError::NotEmbedded(url.to_string());
+        let encoded = url.strip_prefix(DATA_URL_PREFIX).ok_or_else(not_embedded)?;
+        let json = base64::decode(encoded).map_err(|_| not_embedded())?;
+        let json = String::from_utf8(json).map_err(|_| not_embedded())?;
+        Self::from_json(&json)
+    }
+
+    /// Returns the source map embedded in a module, if its source map
+    /// URL is a data URL.
+    ///
+    /// Source maps stored elsewhere have to be loaded by the embedder, from
+    /// the URL returned by [`source_mapping_url`].
+    pub fn from_module(module: &ModuleInfo) -> Option<Result<Self, SourceMapError>> {
+        let url = source_mapping_url(module)?;
+        if !url.starts_with(DATA_URL_PREFIX) {
+            return None;
+        }
+        Some(Self::from_data_url(&url))
+    }
+
+    /// Returns the source location of the instruction at `module_offset`,
+    /// as returned by [`FrameInfo::module_offset`].
+    ///
+    /// [`FrameInfo::module_offset`]: crate::FrameInfo::module_offset
+    pub fn lookup(&self, module_offset: usize) -> Option<SourceLocation> {
+        let offset = module_offset as i64;
+        let index = match self
+            .mappings
+            .binary_search_by_key(&offset, |mapping| mapping.offset)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let (source, line, column) = self.mappings[index].location?;
+        Some(SourceLocation {
+            file: self.sources.get(source as usize)?.clone(),
+            line: line as u32 + 1,
+            column: column as u32 + 1,
+        })
+    }
+}
+
+/// Returns the URL of the source map of a module, written in its
+/// `sourceMappingURL` custom section.
+pub fn source_mapping_url(module: &ModuleInfo) -> Option<String> {
+    let index = module.custom_sections.get(SOURCE_MAPPING_URL_SECTION)?;
+    let data = &module.custom_sections_data[*index];
+
+    // The section holds a WebAssembly string: its length, then its bytes.
+    let mut len = 0usize;
+    let mut shift = 0;
+    let mut bytes = data.iter();
+    loop {
+        let byte = *bytes.next()?;
+        if shift > 28 {
+            return None;
+        }
+        len |= usize::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    let url = bytes.as_slice().get(..len)?;
+    String::from_utf8(url.to_vec()).ok()
+}
+
+/// Parses the mappings of the first line of the generated code: a
+/// WebAssembly module is a single line whose columns are offsets.
+fn parse_mappings(mappings: &str) -> Result<Vec<Mapping>, SourceMapError> {
+    let line = mappings.split(';').next().unwrap_or_default();
+    let mut result = Vec::new();
+    let (mut offset, mut source, mut source_line, mut column) = (0, 0, 0, 0);
+    for segment in line.split(',').filter(|segment| !segment.is_empty()) {
+        let fields = decode_vlq(segment)?;
+        offset += fields[0];
+        let location = match fields.len() {
+            1 => None,
+            4 | 5 => {
+                source += fields[1];
+                source_line += fields[2];
+                column += fields[3];
+                Some((source, source_line, column))
+            }
+            _ => {
+                return Err(SourceMapError::Mappings(format!(
+                    "segment `{}` has {} fields",
+                    segment,
+                    fields.len()
+                )))
+            }
+        };
+        if location.map_or(false, |(source, line, column)| {
+            source < 0 || line < 0 || column < 0
+        }) {
+            return Err(SourceMapError::Mappings(format!(
+                "segment `{}` has a negative location",
+                segment
+            )));
+        }
+        result.push(Mapping { offset, location });
+    }
+    Ok(result)
+}
+
+/// Decodes the base64 VLQ values of a segment.
+fn decode_vlq(segment: &str) -> Result<Vec<i64>, SourceMapError> {
+    let invalid = || SourceMapError::Mappings(format!("invalid segment `{}`", segment));
+    let mut values = Vec::new();
+    let mut value = 0i64;
+    let mut shift = 0;
+    for byte in segment.bytes() {
+        let digit = BASE64_DIGITS
+            .iter()
+            .position(|&digit| digit == byte)
+            .ok_or_else(invalid)? as i64;
+        if shift > 32 {
+            return Err(invalid());
+        }
+        value |= (digit & 0x1f) << shift;
+        if digit & 0x20 != 0 {
+            shift += 5;
+            continue;
+        }
+        let magnitude = value >> 1;
+        values.push(if value & 1 != 0 {
+            -magnitude
+        } else {
+            magnitude
+        });
+        value = 0;
+        shift = 0;
+    }
+    if shift != 0 {
+        return Err(invalid());
+    }
+    Ok(values)
+}
@@ -0,0 +1,106 @@
+//! The storage used by the key-value host functions.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ops::Bound;
+
+/// A key-value record.
+pub type Record = (Vec<u8>, Vec<u8>);
+
+/// The order in which [`KvBackend::scan`] iterates over the keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// From the smallest key to the largest one.
+    Ascending = 1,
+    /// From the largest key to the smallest one.
+    Descending = 2,
+}
+
+impl TryFrom<i32> for Order {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Ascending),
+            2 => Ok(Self::Descending),
+            other => Err(other),
+        }
+    }
+}
+
+/// A key-value store, provided by the embedder, backing the host
+/// functions.
+pub trait KvBackend: Send + 'static {
+    /// Returns the value of `key`, if any.
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+    /// Sets the value of `key`.
+    fn set(&mut self, key: &[u8], value: &[u8]);
+
+    /// Removes `key`.
+    fn remove(&mut self, key: &[u8]);
+
+    /// Returns an iterator over the records whose key is in
+    /// `[start, end)`, `None` meaning unbounded.
+    ///
+    /// The iterator must not borrow the backend: writes made while the
+    /// guest iterates must not invalidate it.
+    fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Record> + Send>;
+}
+
+/// A [`KvBackend`] storing everything in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryKvBackend {
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryKvBackend {
+    /// Returns the stored records.
+    pub fn data(&self) -> &BTreeMap<Vec<u8>, Vec<u8>> {
+        &self.data
+    }
+}
+
+impl KvBackend for MemoryKvBackend {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.data.get(key).cloned()
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.data.insert(key.to_vec(), value.to_vec());
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.data.remove(key);
+    }
+
+    fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Record> + Send> {
+        let start = start.map_or(Bound::Unbounded, |start| Bound::Included(start.to_vec()));
+        let end = end.map_or(Bound::Unbounded, |end| Bound::Excluded(end.to_vec()));
+        // An empty or inverted range has no records, but `BTreeMap::range` panics on it.
+        if let (Bound::Included(start), Bound::Excluded(end)) = (&start, &end) {
+            if start >= end {
+                return Box::new(std::iter::empty());
+            }
+        }
+        let records = self
+            .data
+            .range((start, end))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        match order {
+            Order::Ascending => Box::new(records.into_iter()),
+            Order::Descending => Box::new(records.into_iter().rev()),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Host functions giving WebAssembly guests access to a key-value store.
+//!
+//! The storage is provided by the embedder through the [`KvBackend`]
+//! trait. The host functions take care of reading the keys and values
+//! from the guest memory and of allocating the results in it; see the
+//! README for the guest ABI.
+//!
+//! # Usage
+//!
+//! ```ignore
+//! use wasmer_hostfns_kv::{generate_import_object, MemoryKvBackend};
+//!
+//! let import_object = generate_import_object(&store, MemoryKvBackend::default());
+//! let instance = Instance::new(&module, &import_object)?;
+//! ```
+
+#![deny(missing_docs)]
+
+mod backend;
+mod region;
+
+pub use crate::backend::{KvBackend, MemoryKvBackend, Order, Record};
+
+use crate::region::{read_region_data, write_region_data};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use wasmer::{
+    imports, Function, ImportObject, LazyInit, Memory, NativeFunc, RuntimeError, Store, WasmerEnv,
+};
+
+/// The open iterators of a guest, by identifier.
+#[derive(Default)]
+struct Iterators {
+    next_id: u32,
+    open: HashMap<u32, Box<dyn Iterator<Item = Record> + Send>>,
+}
+
+/// The environment provided to the key-value imports.
+#[derive(Clone, WasmerEnv)]
+pub struct KvEnv {
+    /// The backend storing the data.
+    ///
+    /// The key-value imports never hold this lock while calling into
+    /// the guest.
+    pub backend: Arc<Mutex<dyn KvBackend>>,
+    iterators: Arc<Mutex<Iterators>>,
+    #[wasmer(export)]
+    memory: LazyInit<Memory>,
+    #[wasmer(export)]
+    allocate: LazyInit<NativeFunc<u32, u32>>,
+}
+
+impl KvEnv {
+    /// Creates a new `KvEnv` storing its data in `backend`.
+    pub fn new<B: KvBackend>(backend: B) -> Self {
+        Self {
+            backend: Arc::new(Mutex::new(backend)),
+            iterators: Arc::new(Mutex::new(Iterators::default())),
+            memory: LazyInit::new(),
+            allocate: LazyInit::new(),
+        }
+    }
+
+    fn memory(&self) -> &Memory {
+        self.memory_ref()
+            .expect("Memory should be set on `KvEnv` first")
+    }
+
+    fn allocate(&self) -> &NativeFunc<u32, u32> {
+        self.allocate_ref()
+            .expect("`allocate` should be set on `KvEnv` first")
+    }
+
+    /// Returns the region pointer of `data` allocated in the guest.
+    fn write(&self, data: &[u8]) -> Result<u32, RuntimeError> {
+        write_region_data(self.memory(), self.allocate(), data)
+    }
+}
+
+/// Creates the key-value imports, in the `env` namespace, storing their
+/// data in `backend`.
+pub fn generate_import_object<B: KvBackend>(store: &Store, backend: B) -> ImportObject {
+    generate_import_object_from_env(store, KvEnv::new(backend))
+}
+
+/// Creates the key-value imports, in the `env` namespace, from an
+/// existing [`KvEnv`]. The embedder can keep a clone of `env` to access
+/// the backend.
+pub fn generate_import_object_from_env(store: &Store, env: KvEnv) -> ImportObject {
+    imports! {
+        "env" => {
+            "db_read" => Function::new_native_with_env(store, env.clone(), db_read),
+            "db_write" => Function::new_native_with_env(store, env.clone(), db_write),
+            "db_remove" => Function::new_native_with_env(store, env.clone(), db_remove),
+            "db_scan" => Function::new_native_with_env(store, env.clone(), db_scan),
+            "db_next" => Function::new_native_with_env(store, env, db_next),
+        }
+    }
+}
+
+/// Reads the value of a key; returns a region with the value, or `0` if
+/// the key doesn't exist.
+fn db_read(env: &KvEnv, key_ptr: u32) -> Result<u32, RuntimeError> {
+    let key = read_region_data(env.memory(), key_ptr)?;
+    let value = env.backend.lock().unwrap().get(&key);
+    match value {
+        Some(value) => env.write(&value),
+        None => Ok(0),
+    }
+}
+
+/// Sets the value of a key.
+fn db_write(env: &KvEnv, key_ptr: u32, value_ptr: u32) -> Result<(), RuntimeError> {
+    let key = read_region_data(env.memory(), key_ptr)?;
+    let value = read_region_data(env.memory(), value_ptr)?;
+    env.backend.lock().unwrap().set(&key, &value);
+    Ok(())
+}
+
+/// Removes a key.
+fn db_remove(env: &KvEnv, key_ptr: u32) -> Result<(), RuntimeError> {
+    let key = read_region_data(env.memory(), key_ptr)?;
+    env.backend.lock().unwrap().remove(&key);
+    Ok(())
+}
+
+/// Creates an iterator over the keys in `[start, end)`; a null region
+/// pointer means unbounded. Returns the iterator identifier.
+fn db_scan(env: &KvEnv, start_ptr: u32, end_ptr: u32, order: i32) -> Result<u32, RuntimeError> {
+    let order = Order::try_from(order)
+        .map_err(|order| RuntimeError::new(format!("invalid scan order `{}`", order)))?;
+    let bound = |ptr: u32| match ptr {
+        0 => Ok(None),
+        ptr => read_region_data(env.memory(), ptr).map(Some),
+    };
+    let start = bound(start_ptr)?;
+    let end = bound(end_ptr)?;
+    let iterator = env
+        .backend
+        .lock()
+        .unwrap()
+        .scan(start.as_deref(), end.as_deref(), order);
+
+    let mut iterators = env.iterators.lock().unwrap();
+    let id = iterators.next_id;
+    iterators.next_id = id
+        .checked_add(1)
+        .ok_or_else(|| RuntimeError::new("too many iterators"))?;
+    iterators.open.insert(id, iterator);
+    Ok(id)
+}
+
+/// Advances an iterator; returns a region with the next record (the key,
+/// the value, then the key length as a big-endian `u32`), or `0` when
+/// the iterator is exhausted. An exhausted iterator is closed.
+fn db_next(env: &KvEnv, iterator_id: u32) -> Result<u32, RuntimeError> {
+    let record = {
+        let mut iterators = env.iterators.lock().unwrap();
+        let iterator = iterators
+            .open
+            .get_mut(&iterator_id)
+            .ok_or_else(|| RuntimeError::new(format!("unknown iterator `{}`", iterator_id)))?;
+        let record = iterator.next();
+        if record.is_none() {
+            iterators.open.remove(&iterator_id);
+        }
+        record
+    };
+    match record {
+        Some((key, value)) => {
+            let mut data = Vec::with_capacity(key.len() + value.len() + 4);
+            data.extend_from_slice(&key);
+            data.extend_from_slice(&value);
+            data.extend_from_slice(&(key.len() as u32).to_be_bytes());
+            env.write(&data)
+        }
+        None => Ok(0),
+    }
+}
@@ -0,0 +1,98 @@
+//! Regions: buffers in the guest memory exchanged with the host.
+
+use wasmer::{Memory, NativeFunc, RuntimeError};
+
+/// The size of a region descriptor in the guest memory.
+const REGION_SIZE: usize = 12;
+
+/// A buffer in the guest memory, described by three little-endian `u32`s
+/// stored in the guest memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    offset: u32,
+    capacity: u32,
+    length: u32,
+}
+
+/// Copies `len` bytes at `ptr` out of the guest memory.
+fn read_bytes(memory: &Memory, ptr: usize, len: usize) -> Result<Vec<u8>, RuntimeError> {
+    let view = memory.view::<u8>();
+    match ptr.checked_add(len) {
+        Some(end) if end <= view.len() => {
+            Ok(view[ptr..end].iter().map(|cell| cell.get()).collect())
+        }
+        _ => Err(RuntimeError::new(format!(
+            "region out of bounds: ptr {}, len {}, memory size {}",
+            ptr,
+            len,
+            view.len()
+        ))),
+    }
+}
+
+/// Copies `data` into the guest memory at `ptr`.
+fn write_bytes(memory: &Memory, ptr: usize, data: &[u8]) -> Result<(), RuntimeError> {
+    let view = memory.view::<u8>();
+    match ptr.checked_add(data.len()) {
+        Some(end) if end <= view.len() => {
+            for (cell, byte) in view[ptr..end].iter().zip(data) {
+                cell.set(*byte);
+            }
+            Ok(())
+        }
+        _ => Err(RuntimeError::new(format!(
+            "region out of bounds: ptr {}, len {}, memory size {}",
+            ptr,
+            data.len(),
+            view.len()
+        ))),
+    }
+}
+
+fn read_region(memory: &Memory, region_ptr: u32) -> Result<Region, RuntimeError> {
+    let bytes = read_bytes(memory, region_ptr as usize, REGION_SIZE)?;
+    let field = |index: usize| {
+        let mut field = [0; 4];
+        field.copy_from_slice(&bytes[index * 4..index * 4 + 4]);
+        u32::from_le_bytes(field)
+    };
+    let region = Region {
+        offset: field(0),
+        capacity: field(1),
+        length: field(2),
+    };
+    if region.length > region.capacity {
+        return Err(RuntimeError::new(format!(
+            "invalid region at {}: length {} exceeds capacity {}",
+            region_ptr, region.length, region.capacity
+        )));
+    }
+    Ok(region)
+}
+
+/// Reads the content of the region at `region_ptr`.
+pub(crate) fn read_region_data(memory: &Memory, region_ptr: u32) -> Result<Vec<u8>, RuntimeError> {
+    let region = read_region(memory, region_ptr)?;
+    read_bytes(memory, region.offset as usize, region.length as usize)
+}
+
+/// Allocates a region in the guest with `allocate`, fills it with `data`
+/// and returns its pointer.
+pub(crate) fn write_region_data(
+    memory: &Memory,
+    allocate: &NativeFunc<u32, u32>,
+    data: &[u8],
+) -> Result<u32, RuntimeError> {
+    let length = data.len() as u32;
+    let region_ptr = allocate.call(length)?;
+    let region = read_region(memory, region_ptr)?;
+    if region.capacity < length {
+        return Err(RuntimeError::new(format!(
+            "`allocate({})` returned a region with a capacity of {}",
+            length, region.capacity
+        )));
+    }
+    write_bytes(memory, region.offset as usize, data)?;
+    write_bytes(memory, region_ptr as usize + 8, &length.to_le_bytes())?;
+    Ok(region_ptr)
+}
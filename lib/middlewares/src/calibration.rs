@@ -0,0 +1,293 @@
+//! `calibration` measures how long operators take to execute on the current
+//! machine, with the current engine and compiler, and turns it into a cost
+//! table for the [`Metering`] middleware.
+//!
+//! Operators are grouped into classes (see [`operator_class`]). For each
+//! class, a micro-benchmark runs a loop made of operators of that class;
+//! the time of an empty loop is subtracted and the remainder is divided by
+//! the number of executed operators. The resulting time per operator is
+//! then converted into points, given the target number of points per
+//! second.
+//!
+//! # Usage
+//!
+//! ```ignore
+//! use wasmer_middlewares::calibration::Calibration;
+//! use wasmer_middlewares::Metering;
+//!
+//! let table = Calibration::new(1_000_000_000).run(&store)?;
+//! std::fs::write("costs.toml", table.to_toml())?;
+//!
+//! // The cost function must be `Copy`, so it borrows a `'static` table.
+//! let table: &'static _ = Box::leak(Box::new(table));
+//! let metering = Arc::new(Metering::new(10_000_000, move |op| table.cost(op)));
+//! ```
+//!
+//! [`Metering`]: crate::Metering
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use wasmer::wasmparser::Operator;
+use wasmer::{imports, Instance, Module, NativeFunc, RuntimeError, Store};
+
+/// The class of operators used when no other class applies.
+pub const DEFAULT_CLASS: &str = "numeric";
+
+/// Number of times the body of a benchmark is repeated in each loop
+/// iteration, so the loop itself weighs less.
+const UNROLL: usize = 16;
+
+/// The micro-benchmarks: the class, the body of the loop and the number
+/// of operators in the body.
+const BENCHMARKS: &[(&str, &str, u64)] = &[
+    (
+        "numeric",
+        "local.get $a i64.const 7 i64.mul i64.const 3 i64.add local.set $a",
+        6,
+    ),
+    (
+        "division",
+        "local.get $a i64.const 3 i64.div_u i64.const 11 i64.add local.set $a",
+        6,
+    ),
+    (
+        "float",
+        "local.get $f f64.const 1.0001 f64.mul f64.const 0.5 f64.add local.set $f",
+        6,
+    ),
+    (
+        "memory",
+        "i32.const 8 i32.const 8 i64.load i64.const 1 i64.add i64.store",
+        6,
+    ),
+    (
+        "global",
+        "global.get $g i64.const 1 i64.add global.set $g",
+        4,
+    ),
+    ("call", "local.get $a call $id local.set $a", 3),
+    (
+        "call_indirect",
+        "local.get $a i32.const 0 call_indirect (type $id_t) local.set $a",
+        4,
+    ),
+    ("control", "block local.get $a i64.eqz br_if 0 end", 5),
+];
+
+/// Returns the class of `operator` in a [`CostTable`].
+///
+/// The classes are `call`, `call_indirect`, `control`, `global`,
+/// `division`, `memory`, `float` and `numeric` (for all the other
+/// operators).
+pub fn operator_class(operator: &Operator) -> &'static str {
+    match operator {
+        Operator::Call { .. } => "call",
+        Operator::CallIndirect { .. } => "call_indirect",
+        Operator::Unreachable
+        | Operator::Nop
+        | Operator::Block { .. }
+        | Operator::Loop { .. }
+        | Operator::If { .. }
+        | Operator::Else
+        | Operator::End
+        | Operator::Br { .. }
+        | Operator::BrIf { .. }
+        | Operator::BrTable { .. }
+        | Operator::Return => "control",
+        Operator::GlobalGet { .. } | Operator::GlobalSet { .. } => "global",
+        Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU => "division",
+        _ => {
+            let name = format!("{:?}", operator);
+            if name.contains("Load") || name.contains("Store") || name.starts_with("Memory") {
+                "memory"
+            } else if name.starts_with("F32") || name.starts_with("F64") {
+                "float"
+            } else {
+                DEFAULT_CLASS
+            }
+        }
+    }
+}
+
+/// A cost table: the points charged for each class of operators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostTable {
+    /// The number of points the table charges for one second of execution.
+    pub points_per_second: u64,
+    /// The cost of each operator class, in points.
+    pub costs: BTreeMap<String, u64>,
+}
+
+impl CostTable {
+    /// Returns the cost of `operator`, in points.
+    ///
+    /// Operators of a class missing from the table cost as much as the
+    /// [`DEFAULT_CLASS`], or 1 point if it's missing too.
+    pub fn cost(&self, operator: &Operator) -> u64 {
+        self.costs
+            .get(operator_class(operator))
+            .or_else(|| self.costs.get(DEFAULT_CLASS))
+            .copied()
+            .unwrap_or(1)
+    }
+
+    /// Serializes the table to TOML.
+    pub fn to_toml(&self) -> String {
+        let mut toml = format!(
+            "points_per_second = {}\n\n[costs]\n",
+            self.points_per_second
+        );
+        for (class, cost) in self.costs.iter() {
+            toml.push_str(&format!("{} = {}\n", class, cost));
+        }
+        toml
+    }
+
+    /// Parses a table serialized with [`CostTable::to_toml`].
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        let mut points_per_second = None;
+        let mut costs = BTreeMap::new();
+        let mut in_costs = false;
+        for (number, line) in toml.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_costs = line == "[costs]";
+                if !in_costs {
+                    return Err(format!("line {}: unknown section `{}`", number + 1, line));
+                }
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| format!("line {}: expected `key = value`", number + 1))?
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+            if in_costs {
+                costs.insert(key.to_string(), value);
+            } else if key == "points_per_second" {
+                points_per_second = Some(value);
+            } else {
+                return Err(format!("line {}: unknown key `{}`", number + 1, key));
+            }
+        }
+        Ok(Self {
+            points_per_second: points_per_second
+                .ok_or_else(|| "missing `points_per_second`".to_string())?,
+            costs,
+        })
+    }
+}
+
+/// Runs the calibration micro-benchmarks.
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    points_per_second: u64,
+    iterations: u32,
+    runs: u32,
+}
+
+impl Calibration {
+    /// Creates a calibration targeting `points_per_second` points for one
+    /// second of execution.
+    pub fn new(points_per_second: u64) -> Self {
+        Self {
+            points_per_second,
+            iterations: 100_000,
+            runs: 3,
+        }
+    }
+
+    /// Sets the number of loop iterations of each benchmark.
+    pub fn iterations(&mut self, iterations: u32) -> &mut Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Sets how many times each benchmark runs; the fastest run is kept.
+    pub fn runs(&mut self, runs: u32) -> &mut Self {
+        self.runs = runs.max(1);
+        self
+    }
+
+    /// Runs the benchmarks with the engine and compiler of `store` and
+    /// returns the recommended cost table.
+    ///
+    /// `store` should not have middlewares, since they would be measured
+    /// too.
+    pub fn run(&self, store: &Store) -> Result<CostTable, RuntimeError> {
+        let baseline = self.measure(store, "")?;
+        let mut costs = BTreeMap::new();
+        for (class, body, operators) in BENCHMARKS {
+            let elapsed = self.measure(store, body)?;
+            let executed = self.iterations as u64 * UNROLL as u64 * operators;
+            let nanos = elapsed.checked_sub(baseline).unwrap_or_default().as_nanos();
+            let points =
+                nanos * self.points_per_second as u128 / (executed as u128 * 1_000_000_000).max(1);
+            costs.insert(class.to_string(), (points as u64).max(1));
+        }
+        Ok(CostTable {
+            points_per_second: self.points_per_second,
+            costs,
+        })
+    }
+
+    /// Returns the fastest run of the loop repeating `body`.
+    fn measure(&self, store: &Store, body: &str) -> Result<Duration, RuntimeError> {
+        let wat = benchmark_wat(body);
+        let module = Module::new(store, wat).map_err(|e| RuntimeError::new(e.to_string()))?;
+        let instance =
+            Instance::new(&module, &imports! {}).map_err(|e| RuntimeError::new(e.to_string()))?;
+        let run: NativeFunc<i32, i64> = instance
+            .exports
+            .get_native_function("run")
+            .map_err(|e| RuntimeError::new(e.to_string()))?;
+
+        let mut fastest = Duration::from_secs(u64::MAX);
+        for _ in 0..self.runs {
+            let start = Instant::now();
+            run.call(self.iterations as i32)?;
+            fastest = fastest.min(start.elapsed());
+        }
+        Ok(fastest)
+    }
+}
+
+/// Returns a module exporting `run(n)`, which executes `body` `UNROLL`
+/// times in a loop of `n` iterations.
+fn benchmark_wat(body: &str) -> String {
+    let body = vec![body; UNROLL].join("\n        ");
+    format!(
+        r#"(module
+  (type $id_t (func (param i64) (result i64)))
+  (memory 1)
+  (global $g (mut i64) (i64.const 0))
+  (table 1 funcref)
+  (elem (i32.const 0) $id)
+  (func $id (type $id_t) (local.get 0))
+  (func (export "run") (param $n i32) (result i64)
+    (local $a i64) (local $f f64)
+    (local.set $a (i64.const 1))
+    (local.set $f (f64.const 1.5))
+    (block $exit
+      (loop $loop
+        (br_if $exit (i32.eqz (local.get $n)))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        {}
+        (br $loop)))
+    (i64.add (local.get $a) (i64.reinterpret_f64 (local.get $f)))))"#,
+        body
+    )
+}
@@ -0,0 +1,513 @@
+//! `debugger` is a middleware for a debug execution mode: it stops the execution at
+//! breakpoints or after a given number of operators, and tells where it stopped.
+//!
+//! Every operator is instrumented, so a module compiled with the [`Debugger`] runs much
+//! slower than without it.
+//!
+//! A stop traps: the call made by the host fails, and [`DebugSession::stop`] tells why and
+//! where, with the current values of the parameters of the stopped function. The other
+//! locals and the operand stack can't be inspected. The execution can't be resumed in
+//! place; a deterministic program is continued by calling it again on a fresh instance,
+//! with the breakpoints ignored until the steps of the stop
+//! ([`DebugSession::set_break_after`]). Stepping one operator at a time is done by raising
+//! the step limit by one at every run.
+
+use crate::exports::{registered_global, unused_export_name};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use wasmer::wasmparser::{Operator, Result as WpResult, Type as WpType, TypeOrFuncType};
+use wasmer::{
+    ExportError, ExportIndex, FrameInfo, FunctionMiddleware, Global, GlobalInit, GlobalType,
+    Instance, LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware,
+    Mutability, RuntimeError, Type, Value,
+};
+use wasmer_types::{FunctionIndex, GlobalIndex};
+use wasmer_vm::ModuleInfo;
+
+/// A breakpoint, at an operator of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Breakpoint {
+    /// The index of the function, imported functions included.
+    pub function: u32,
+    /// The offset of the operator from the start of the function body, as in
+    /// [`FrameInfo::func_offset`].
+    pub offset: usize,
+}
+
+/// Why the execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The step limit was reached.
+    Step,
+    /// The breakpoint of the given index was reached.
+    Breakpoint(usize),
+}
+
+/// Where and why the execution stopped.
+#[derive(Debug, Clone)]
+pub struct DebugStop {
+    /// Why the execution stopped.
+    pub reason: StopReason,
+    /// The number of operators executed before the stop.
+    pub steps: u64,
+    /// The offset in the module of the operator the execution stopped at.
+    pub module_offset: usize,
+    /// The index of the function the execution stopped in, imported functions included.
+    pub function: u32,
+    /// The current values of the parameters of that function, `None` for the `v128` and
+    /// reference parameters, which aren't inspected.
+    pub params: Vec<Option<Value>>,
+    /// The guest call stack, innermost frame first.
+    pub backtrace: Vec<FrameInfo>,
+}
+
+/// The globals added to the module by the middleware.
+#[derive(Debug, Clone)]
+struct DebugGlobals {
+    num_imported_functions: u32,
+    /// The number of operators executed (exported).
+    steps: GlobalIndex,
+    /// The number of operators to execute before stopping (exported).
+    step_limit: GlobalIndex,
+    /// The number of operators to execute before the breakpoints apply (exported).
+    break_after: GlobalIndex,
+    /// `0` without stop, `1` for a step stop, `2 + i` for the breakpoint `i` (exported).
+    stop_reason: GlobalIndex,
+    /// The offset of the operator of the stop (exported).
+    stop_offset: GlobalIndex,
+    /// The index of the function of the stop (exported).
+    stop_function: GlobalIndex,
+    /// Whether each breakpoint is enabled (exported).
+    breakpoints: Vec<GlobalIndex>,
+    /// The values of the parameters at the stop, by type (exported).
+    params: HashMap<Type, Vec<GlobalIndex>>,
+}
+
+/// Returns the slot of each parameter of `params` among the globals of its type: the
+/// number of parameters of the same type before it. `None` for the parameters which
+/// aren't saved at a stop.
+fn param_slots(params: &[Type]) -> Vec<Option<(Type, usize)>> {
+    let mut counts = HashMap::new();
+    params
+        .iter()
+        .map(|&ty| match ty {
+            Type::I32 | Type::I64 | Type::F32 | Type::F64 => {
+                let count = counts.entry(ty).or_insert(0);
+                *count += 1;
+                Some((ty, *count - 1))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the name of `ty`, for the names of the globals of the parameters.
+fn type_name(ty: Type) -> &'static str {
+    match ty {
+        Type::I32 => "i32",
+        Type::I64 => "i64",
+        Type::F32 => "f32",
+        _ => "f64",
+    }
+}
+
+/// The module-level debugger middleware.
+///
+/// The breakpoints are set when compiling the module; they can be enabled and disabled
+/// at runtime with [`DebugSession::set_breakpoint_enabled`].
+///
+/// # Errors
+///
+/// An instance of `Debugger` should not be shared among different modules, since it tracks
+/// module-specific information like the global indices of its state. Attempts to use a
+/// `Debugger` instance from multiple modules fail to compile the other modules.
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    globals: Mutex<Option<DebugGlobals>>,
+}
+
+/// The function-level debugger middleware.
+#[derive(Debug)]
+pub struct FunctionDebugger {
+    function: u32,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    /// The parameters saved at a stop, and their globals.
+    saved_params: Vec<(u32, GlobalIndex)>,
+    globals: DebugGlobals,
+}
+
+impl Debugger {
+    /// Creates a `Debugger` middleware with the given breakpoints, all enabled.
+    pub fn new(breakpoints: Vec<Breakpoint>) -> Self {
+        Self {
+            breakpoints,
+            globals: Mutex::new(None),
+        }
+    }
+
+    /// Returns the breakpoints, by index.
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Starts debugging `instance`.
+    ///
+    /// Fails if the instance Module wasn't processed with the `Debugger` middleware.
+    pub fn session<'a>(&'a self, instance: &'a Instance) -> Result<DebugSession<'a>, ExportError> {
+        let global = |purpose: &str, ty| registered_global(instance, "debugger", purpose, ty);
+        let breakpoints = (0..self.breakpoints.len())
+            .map(|i| global(&format!("breakpoint_{}", i), Type::I32))
+            .collect::<Result<_, _>>()?;
+        Ok(DebugSession {
+            instance,
+            steps: global("steps", Type::I64)?,
+            step_limit: global("step_limit", Type::I64)?,
+            break_after: global("break_after", Type::I64)?,
+            stop_reason: global("stop_reason", Type::I32)?,
+            stop_offset: global("stop_offset", Type::I64)?,
+            stop_function: global("stop_function", Type::I32)?,
+            breakpoints,
+        })
+    }
+}
+
+impl ModuleMiddleware for Debugger {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        module_info: &ModuleInfo,
+        local_function_index: LocalFunctionIndex,
+    ) -> Result<Box<dyn FunctionMiddleware>, MiddlewareError> {
+        let globals = self.globals.lock().unwrap().clone().ok_or_else(|| {
+            MiddlewareError::new(
+                "debugger",
+                "the module wasn't transformed by the middleware",
+            )
+        })?;
+        let function_index = module_info.func_index(local_function_index);
+        let signature = &module_info.signatures[module_info.functions[function_index]];
+        let saved_params = param_slots(signature.params())
+            .into_iter()
+            .enumerate()
+            .filter_map(|(local, slot)| {
+                let (ty, slot) = slot?;
+                Some((local as u32, globals.params[&ty][slot]))
+            })
+            .collect();
+        let function = function_index.as_u32();
+        Ok(Box::new(FunctionDebugger {
+            function,
+            saved_params,
+            breakpoints: self
+                .breakpoints
+                .iter()
+                .cloned()
+                .enumerate()
+                .filter(|(_, breakpoint)| breakpoint.function == function)
+                .collect(),
+            globals,
+        }))
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        let mut globals = self.globals.lock().unwrap();
+        if globals.is_some() {
+            return Err(MiddlewareError::new(
+                "debugger",
+                "a `Debugger` can't be used with several modules",
+            ));
+        }
+
+        // The globals of the parameters, for the most parameters of each type of a function.
+        let mut param_counts = HashMap::new();
+        for (_, signature) in module_info
+            .functions
+            .iter()
+            .skip(module_info.num_imported_functions)
+        {
+            let mut counts = HashMap::new();
+            for (ty, slot) in param_slots(module_info.signatures[*signature].params())
+                .into_iter()
+                .flatten()
+            {
+                counts.insert(ty, slot + 1);
+            }
+            for (ty, count) in counts {
+                let max = param_counts.entry(ty).or_insert(0);
+                *max = count.max(*max);
+            }
+        }
+
+        let mut push_global = |purpose: String, ty: Type, init: GlobalInit| {
+            module_info.global_initializers.push(init);
+            let index = module_info
+                .globals
+                .push(GlobalType::new(ty, Mutability::Var));
+            let name = unused_export_name(module_info, &format!("debug_{}", purpose));
+            module_info.register_middleware_export("debugger", &name, &purpose);
+            module_info.exports.insert(name, ExportIndex::Global(index));
+            index
+        };
+        let steps = push_global("steps".to_string(), Type::I64, GlobalInit::I64Const(0));
+        let step_limit = push_global(
+            "step_limit".to_string(),
+            Type::I64,
+            GlobalInit::I64Const(-1),
+        );
+        let break_after = push_global(
+            "break_after".to_string(),
+            Type::I64,
+            GlobalInit::I64Const(0),
+        );
+        let stop_reason = push_global(
+            "stop_reason".to_string(),
+            Type::I32,
+            GlobalInit::I32Const(0),
+        );
+        let stop_offset = push_global(
+            "stop_offset".to_string(),
+            Type::I64,
+            GlobalInit::I64Const(0),
+        );
+        let stop_function = push_global(
+            "stop_function".to_string(),
+            Type::I32,
+            GlobalInit::I32Const(0),
+        );
+        let breakpoints = (0..self.breakpoints.len())
+            .map(|i| {
+                push_global(
+                    format!("breakpoint_{}", i),
+                    Type::I32,
+                    GlobalInit::I32Const(1),
+                )
+            })
+            .collect();
+        let mut params = HashMap::new();
+        for &(ty, init) in &[
+            (Type::I32, GlobalInit::I32Const(0)),
+            (Type::I64, GlobalInit::I64Const(0)),
+            (Type::F32, GlobalInit::F32Const(0.0)),
+            (Type::F64, GlobalInit::F64Const(0.0)),
+        ] {
+            let count = param_counts.get(&ty).cloned().unwrap_or(0);
+            let globals = (0..count)
+                .map(|slot| push_global(format!("param_{}_{}", type_name(ty), slot), ty, init))
+                .collect();
+            params.insert(ty, globals);
+        }
+
+        *globals = Some(DebugGlobals {
+            num_imported_functions: module_info.num_imported_functions as u32,
+            steps,
+            step_limit,
+            break_after,
+            stop_reason,
+            stop_offset,
+            stop_function,
+            breakpoints,
+            params,
+        });
+        Ok(())
+    }
+}
+
+impl FunctionDebugger {
+    /// Pushes the operators stopping the execution at `offset` with the `reason` code.
+    fn stop<'a>(&self, state: &mut MiddlewareReaderState<'a>, reason: i32, offset: usize) {
+        for &(local_index, global) in &self.saved_params {
+            state.extend(&[
+                Operator::LocalGet { local_index },
+                Operator::GlobalSet {
+                    global_index: global.as_u32(),
+                },
+            ]);
+        }
+        state.extend(&[
+            Operator::I32Const {
+                value: self.function as i32,
+            },
+            Operator::GlobalSet {
+                global_index: self.globals.stop_function.as_u32(),
+            },
+            Operator::I64Const {
+                value: offset as i64,
+            },
+            Operator::GlobalSet {
+                global_index: self.globals.stop_offset.as_u32(),
+            },
+            Operator::I32Const { value: reason },
+            Operator::GlobalSet {
+                global_index: self.globals.stop_reason.as_u32(),
+            },
+            Operator::Unreachable,
+            Operator::End,
+        ]);
+    }
+}
+
+impl FunctionMiddleware for FunctionDebugger {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> WpResult<()> {
+        let offset = state.operator_offset();
+        let func_offset = offset - state.function_offset();
+        let empty_block = TypeOrFuncType::Type(WpType::EmptyBlockType);
+
+        for (index, _) in self
+            .breakpoints
+            .iter()
+            .filter(|(_, breakpoint)| breakpoint.offset == func_offset)
+        {
+            // if globals[breakpoint]
+            //     && unsigned(globals[steps]) >= unsigned(globals[break_after]) { stop(); }
+            let enabled = self.globals.breakpoints[*index].as_u32();
+            state.extend(&[
+                Operator::GlobalGet {
+                    global_index: enabled,
+                },
+                Operator::GlobalGet {
+                    global_index: self.globals.steps.as_u32(),
+                },
+                Operator::GlobalGet {
+                    global_index: self.globals.break_after.as_u32(),
+                },
+                Operator::I64GeU,
+                Operator::I32And,
+                Operator::If { ty: empty_block },
+            ]);
+            self.stop(state, 2 + *index as i32, offset);
+        }
+
+        let steps = self.globals.steps.as_u32();
+        state.extend(&[
+            // if unsigned(globals[steps]) >= unsigned(globals[step_limit]) { stop(); }
+            Operator::GlobalGet {
+                global_index: steps,
+            },
+            Operator::GlobalGet {
+                global_index: self.globals.step_limit.as_u32(),
+            },
+            Operator::I64GeU,
+            Operator::If { ty: empty_block },
+        ]);
+        self.stop(state, 1, offset);
+        state.extend(&[
+            // globals[steps] += 1;
+            Operator::GlobalGet {
+                global_index: steps,
+            },
+            Operator::I64Const { value: 1 },
+            Operator::I64Add,
+            Operator::GlobalSet {
+                global_index: steps,
+            },
+        ]);
+        state.push_operator(operator);
+
+        Ok(())
+    }
+}
+
+/// The debugging of an instance processed with a [`Debugger`].
+pub struct DebugSession<'a> {
+    instance: &'a Instance,
+    steps: &'a Global,
+    step_limit: &'a Global,
+    break_after: &'a Global,
+    stop_reason: &'a Global,
+    stop_offset: &'a Global,
+    stop_function: &'a Global,
+    breakpoints: Vec<&'a Global>,
+}
+
+/// Sets a global of the session, whose mutability and type were checked when the session
+/// started.
+fn set(global: &Global, value: Value) {
+    global
+        .set(value)
+        .expect("the globals of the debugger are mutable");
+}
+
+impl<'a> DebugSession<'a> {
+    /// Enables or disables the breakpoint `index`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no breakpoint `index`.
+    pub fn set_breakpoint_enabled(&self, index: usize, enabled: bool) {
+        let breakpoint = self
+            .breakpoints
+            .get(index)
+            .unwrap_or_else(|| panic!("no breakpoint {}", index));
+        set(breakpoint, Value::I32(enabled as i32));
+    }
+
+    /// Sets the number of operators executed before stopping, `None` to never stop.
+    ///
+    /// The count includes the operators already executed (see [`DebugSession::steps`]).
+    pub fn set_step_limit(&self, limit: Option<u64>) {
+        let limit = limit.unwrap_or(u64::MAX);
+        set(self.step_limit, Value::I64(limit as i64));
+    }
+
+    /// Ignores the breakpoints until `steps` operators have been executed.
+    ///
+    /// A replay continuing after a breakpoint sets it to the steps of the stop plus one,
+    /// so that it doesn't stop at the same place again.
+    pub fn set_break_after(&self, steps: u64) {
+        set(self.break_after, Value::I64(steps as i64));
+    }
+
+    /// Returns the number of operators executed.
+    pub fn steps(&self) -> u64 {
+        self.steps.get().unwrap_i64() as u64
+    }
+
+    /// Sets the number of operators executed back to zero.
+    pub fn reset_steps(&self) {
+        set(self.steps, Value::I64(0));
+    }
+
+    /// Returns the stop which made `error`, if any, and resets it.
+    pub fn stop(&self, error: &RuntimeError) -> Option<DebugStop> {
+        let reason = match self.stop_reason.get().unwrap_i32() {
+            0 => return None,
+            1 => StopReason::Step,
+            code => StopReason::Breakpoint(code as usize - 2),
+        };
+        set(self.stop_reason, Value::I32(0));
+
+        let function = self.stop_function.get().unwrap_i32() as u32;
+        Some(DebugStop {
+            reason,
+            steps: self.steps(),
+            module_offset: self.stop_offset.get().unwrap_i64() as usize,
+            function,
+            params: self.params(function),
+            backtrace: error.trace().to_vec(),
+        })
+    }
+
+    /// Returns the values of the parameters of `function` saved at the last stop.
+    fn params(&self, function: u32) -> Vec<Option<Value>> {
+        let module_info = self.instance.module().info();
+        let signature = match module_info.functions.get(FunctionIndex::from_u32(function)) {
+            Some(signature) => &module_info.signatures[*signature],
+            None => return Vec::new(),
+        };
+        param_slots(signature.params())
+            .into_iter()
+            .map(|slot| {
+                let (ty, slot) = slot?;
+                let purpose = format!("param_{}_{}", type_name(ty), slot);
+                let global = registered_global(self.instance, "debugger", &purpose, ty).ok()?;
+                Some(global.get())
+            })
+            .collect()
+    }
+}
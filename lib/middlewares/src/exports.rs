@@ -0,0 +1,59 @@
+//! The exports added by the middlewares to the modules, and registered in their
+//! `ModuleInfo::middleware_exports` so that they are found under any name.
+
+use wasmer::{ExportError, Global, GlobalType, Instance, Mutability, Type};
+use wasmer_vm::{MiddlewareExport, ModuleInfo};
+
+/// Returns `name`, or `name` with the first suffix like `_1` making it unused if the module
+/// already exports `name`.
+pub(crate) fn unused_export_name(module_info: &ModuleInfo, name: &str) -> String {
+    if !module_info.exports.contains_key(name) {
+        return name.to_string();
+    }
+    (1..)
+        .map(|suffix| format!("{}_{}", name, suffix))
+        .find(|name| !module_info.exports.contains_key(name))
+        .unwrap()
+}
+
+/// Returns the export registered for `purpose` by the middleware `middleware`, the last one
+/// if several middlewares of the same kind were pushed.
+pub(crate) fn registered_export<'a>(
+    exports: &'a [MiddlewareExport],
+    middleware: &str,
+    purpose: &str,
+) -> Option<&'a MiddlewareExport> {
+    exports
+        .iter()
+        .rev()
+        .find(|export| export.middleware == middleware && export.purpose == purpose)
+}
+
+/// Returns the name of the export of `instance` registered for `purpose` by the middleware
+/// `middleware`.
+pub(crate) fn registered_export_name(
+    instance: &Instance,
+    middleware: &str,
+    purpose: &str,
+) -> Result<String, ExportError> {
+    let exports = instance.module().middleware_exports();
+    registered_export(&exports, middleware, purpose)
+        .map(|export| export.export.clone())
+        .ok_or_else(|| ExportError::Missing(purpose.to_string()))
+}
+
+/// Returns the mutable global of `instance` registered for `purpose` by the middleware
+/// `middleware`, checking its type.
+pub(crate) fn registered_global<'a>(
+    instance: &'a Instance,
+    middleware: &str,
+    purpose: &str,
+    ty: Type,
+) -> Result<&'a Global, ExportError> {
+    let name = registered_export_name(instance, middleware, purpose)?;
+    let global = instance.exports.get_global(&name)?;
+    if *global.ty() != GlobalType::new(ty, Mutability::Var) {
+        return Err(ExportError::IncompatibleType);
+    }
+    Ok(global)
+}
@@ -1,3 +1,11 @@
+pub mod calibration;
+pub mod debugger;
+mod exports;
 pub mod metering;
+pub mod shadow_memory;
+pub mod watchpoints;
 
-pub use metering::Metering;
+pub use debugger::Debugger;
+pub use metering::{FunctionPoints, MeteredCall, Metering, MeteringPoints};
+pub use shadow_memory::ShadowMemory;
+pub use watchpoints::Watchpoints;
@@ -1,25 +1,63 @@
 //! `metering` is a middleware for tracking how many operators are executed in total
 //! and putting a limit on the total number of operators executed.
 
+use crate::exports::{registered_export, registered_global, unused_export_name};
+use std::collections::HashMap;
 use std::fmt;
-use std::sync::Mutex;
 use wasmer::wasmparser::{
     Operator, Result as WpResult, Type as WpType, TypeOrFuncType as WpTypeOrFuncType,
 };
 use wasmer::{
-    ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance, LocalFunctionIndex,
-    MiddlewareReaderState, ModuleMiddleware, Mutability, Type, Value,
+    ExportError, ExportIndex, FunctionMiddleware, Global, GlobalInit, GlobalType, Instance,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability,
+    RuntimeError, Type, Value,
 };
-use wasmer_types::GlobalIndex;
+use wasmer_types::{FunctionIndex, GlobalIndex};
 use wasmer_vm::ModuleInfo;
 
+/// The remaining points of an Instance, see [`Metering::get_points`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeteringPoints {
+    /// The points that are left.
+    Remaining(u64),
+    /// A call ran out of points: its trap was the exhaustion of the points, not a genuine
+    /// `unreachable`. The Instance can't run until it's given new points with
+    /// [`Metering::set_remaining_points`].
+    Exhausted,
+}
+
+/// The globals added to the module by the middleware.
+#[derive(Debug, Clone, Copy)]
+struct MeteringGlobals {
+    /// The remaining points (exported).
+    remaining_points: GlobalIndex,
+    /// `1` once a call ran out of points, `0` otherwise (exported).
+    points_exhausted: GlobalIndex,
+    /// The points consumed since the Instance was created (exported), with
+    /// [`Metering::with_points_used`].
+    points_used: Option<GlobalIndex>,
+    /// The points consumed by the first local function, followed by the ones of the other
+    /// local functions (exported), with [`Metering::with_function_points`].
+    function_points: Option<GlobalIndex>,
+}
+
+/// The points consumed by a function, see [`Metering::get_function_points`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionPoints {
+    /// The index of the function in the module.
+    pub index: FunctionIndex,
+    /// The name of the function in the name section, or else the name it's exported with.
+    pub name: Option<String>,
+    /// The points consumed by the function since the Instance was created, not including
+    /// the ones consumed by the functions it calls.
+    pub points: u64,
+}
+
 /// The module-level metering middleware.
 ///
-/// # Panic
-///
-/// An instance of `Metering` should not be shared among different modules, since it tracks
-/// module-specific information like the global index to store metering state. Attempts to use
-/// a `Metering` instance from multiple modules will result in a panic.
+/// A single `Metering` can meter any number of modules: the globals holding the metering
+/// state of a module are found in the module itself, so a `CompilerConfig` with metering can
+/// compile many modules, concurrently or not.
 pub struct Metering<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> {
     /// Initial limit of points.
     initial_limit: u64,
@@ -27,8 +65,18 @@ pub struct Metering<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> {
     /// Function that maps each operator to a cost in "points".
     cost_function: F,
 
-    /// The global index in the current module for remaining points.
-    remaining_points_index: Mutex<Option<GlobalIndex>>,
+    /// The names the globals of the remaining points and of their exhaustion are exported
+    /// with, if the module doesn't export these names already.
+    export_names: (String, String),
+
+    /// Whether the points consumed in total are counted.
+    points_used: bool,
+
+    /// Whether the points consumed by each function are counted.
+    function_points: bool,
+
+    /// Whether the counters of the consumed points saturate instead of wrapping.
+    saturating_counters: bool,
 }
 
 /// The function-level metering middleware.
@@ -36,8 +84,14 @@ pub struct FunctionMetering<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync
     /// Function that maps each operator to a cost in "points".
     cost_function: F,
 
-    /// The global index in the current module for remaining points.
-    remaining_points_index: GlobalIndex,
+    /// The global indices in the current module of the metering state.
+    globals: MeteringGlobals,
+
+    /// The global index of the points consumed by the current function, if counted.
+    function_points: Option<GlobalIndex>,
+
+    /// Whether the counters of the consumed points saturate instead of wrapping.
+    saturating_counters: bool,
 
     /// Accumulated cost of the current basic block.
     accumulated_cost: u64,
@@ -49,33 +103,308 @@ impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> Metering<F> {
         Self {
             initial_limit,
             cost_function,
-            remaining_points_index: Mutex::new(None),
+            export_names: (
+                "remaining_points".to_string(),
+                "points_exhausted".to_string(),
+            ),
+            points_used: false,
+            function_points: false,
+            saturating_counters: false,
         }
     }
 
+    /// Counts the points consumed in total, in a `points_used` global, so that
+    /// [`Metering::get_points_used`] keeps growing when the remaining points are topped up
+    /// with [`Metering::set_remaining_points`].
+    ///
+    /// Like the other globals, the global is exported with a suffix if the module already
+    /// exports `points_used`.
+    pub fn with_points_used(mut self) -> Self {
+        self.points_used = true;
+        self
+    }
+
+    /// Counts the points consumed by each local function of the module, in an exported
+    /// global per function, to find the functions using the most points with
+    /// [`Metering::get_function_points`].
+    ///
+    /// Counting the points makes each basic block a bit slower to run.
+    pub fn with_function_points(mut self) -> Self {
+        self.function_points = true;
+        self
+    }
+
+    /// Makes the counters of [`Metering::with_points_used`] and
+    /// [`Metering::with_function_points`] stop at `u64::MAX` instead of wrapping around to
+    /// small values, for hosts setting huge limits or cost functions returning large costs.
+    ///
+    /// The remaining points never wrap: a basic block costing more than the remaining points
+    /// traps before they are subtracted, and the cost of a basic block saturates at
+    /// `u64::MAX`. Saturating the counters makes each basic block a bit slower to run.
+    pub fn with_saturating_counters(mut self) -> Self {
+        self.saturating_counters = true;
+        self
+    }
+
+    /// Sets the names the globals of the remaining points and of their exhaustion are
+    /// exported with, `remaining_points` and `points_exhausted` by default.
+    ///
+    /// The exports of the module are never shadowed: if the module already exports a name,
+    /// a suffix is appended to it, like `remaining_points_1`. The accessors of `Metering` find
+    /// the actual names in the middleware exports of the module.
+    pub fn with_export_names(mut self, remaining_points: &str, points_exhausted: &str) -> Self {
+        self.export_names = (remaining_points.to_string(), points_exhausted.to_string());
+        self
+    }
+
     /// Get the remaining points in an Instance.
     ///
     /// Important: the instance Module must been processed with the `Metering` middleware.
+    /// Use [`Metering::try_get_remaining_points`] for Modules that may not have been.
     pub fn get_remaining_points(&self, instance: &Instance) -> u64 {
-        instance
-            .exports
-            .get_global("remaining_points")
+        self.try_get_remaining_points(instance)
             .expect("Can't get `remaining_points` from Instance")
-            .get()
-            .unwrap_i64() as _
     }
 
-    /// Set the provided remaining points in an Instance.
+    /// Get the remaining points in an Instance, or whether a call ran out of points.
+    ///
+    /// Important: the instance Module must been processed with the `Metering` middleware.
+    /// Use [`Metering::try_get_points`] for Modules that may not have been.
+    pub fn get_points(&self, instance: &Instance) -> MeteringPoints {
+        self.try_get_points(instance)
+            .expect("Can't get `points_exhausted` from Instance")
+    }
+
+    /// Set the provided remaining points in an Instance, clearing the exhaustion of the
+    /// points.
     ///
     /// Important: the instance Module must been processed with the `Metering` middleware.
+    /// Use [`Metering::try_set_remaining_points`] for Modules that may not have been.
     pub fn set_remaining_points(&self, instance: &Instance, points: u64) {
-        instance
-            .exports
-            .get_global("remaining_points")
-            .expect("Can't get `remaining_points` from Instance")
-            .set(Value::I64(points as _))
+        self.try_set_remaining_points(instance, points)
             .expect("Can't set `remaining_points` in Instance");
     }
+
+    /// Get the remaining points in an Instance, or an error if the instance Module wasn't
+    /// processed with the `Metering` middleware.
+    ///
+    /// The error is [`ExportError::Missing`] if the Module has no global of remaining points
+    /// registered in its middleware exports, and [`ExportError::IncompatibleType`] if the
+    /// registered export isn't a mutable `i64` global.
+    pub fn try_get_remaining_points(&self, instance: &Instance) -> Result<u64, ExportError> {
+        Ok(metering_global(instance, "remaining_points", Type::I64)?
+            .get()
+            .unwrap_i64() as _)
+    }
+
+    /// Get the remaining points in an Instance, or whether a call ran out of points, or an
+    /// error if the instance Module wasn't processed with the `Metering` middleware, like
+    /// [`Metering::try_get_remaining_points`].
+    pub fn try_get_points(&self, instance: &Instance) -> Result<MeteringPoints, ExportError> {
+        let points_exhausted = metering_global(instance, "points_exhausted", Type::I32)?;
+        if points_exhausted.get().unwrap_i32() != 0 {
+            return Ok(MeteringPoints::Exhausted);
+        }
+        Ok(MeteringPoints::Remaining(
+            self.try_get_remaining_points(instance)?,
+        ))
+    }
+
+    /// Set the provided remaining points in an Instance, or return an error if the instance
+    /// Module wasn't processed with the `Metering` middleware, like
+    /// [`Metering::try_get_remaining_points`].
+    pub fn try_set_remaining_points(
+        &self,
+        instance: &Instance,
+        points: u64,
+    ) -> Result<(), ExportError> {
+        let points_exhausted = metering_global(instance, "points_exhausted", Type::I32)?;
+        metering_global(instance, "remaining_points", Type::I64)?
+            .set(Value::I64(points as _))
+            // The globals have been checked to be mutable and of the right type.
+            .map_err(|_| ExportError::IncompatibleType)?;
+        points_exhausted
+            .set(Value::I32(0))
+            .map_err(|_| ExportError::IncompatibleType)
+    }
+
+    /// Get the points consumed in total by an Instance since it was created.
+    ///
+    /// Important: the instance Module must been processed with the `Metering` middleware,
+    /// [`Metering::with_points_used`].
+    /// Use [`Metering::try_get_points_used`] for Modules that may not have been.
+    pub fn get_points_used(&self, instance: &Instance) -> u64 {
+        self.try_get_points_used(instance)
+            .expect("Can't get `points_used` from Instance")
+    }
+
+    /// Get the points consumed in total by an Instance since it was created, or an error if
+    /// the instance Module wasn't processed with the `Metering` middleware,
+    /// [`Metering::with_points_used`], like [`Metering::try_get_remaining_points`].
+    pub fn try_get_points_used(&self, instance: &Instance) -> Result<u64, ExportError> {
+        Ok(metering_global(instance, "points_used", Type::I64)?
+            .get()
+            .unwrap_i64() as _)
+    }
+
+    /// Get the points consumed by each local function of an Instance, in the order of the
+    /// functions.
+    ///
+    /// Important: the instance Module must been processed with the `Metering` middleware,
+    /// [`Metering::with_function_points`].
+    /// Use [`Metering::try_get_function_points`] for Modules that may not have been.
+    pub fn get_function_points(&self, instance: &Instance) -> Vec<FunctionPoints> {
+        self.try_get_function_points(instance)
+            .expect("Can't get the points consumed by the functions from Instance")
+    }
+
+    /// Get the points consumed by each local function of an Instance, or an error if the
+    /// instance Module wasn't processed with the `Metering` middleware,
+    /// [`Metering::with_function_points`], like [`Metering::try_get_remaining_points`].
+    pub fn try_get_function_points(
+        &self,
+        instance: &Instance,
+    ) -> Result<Vec<FunctionPoints>, ExportError> {
+        let module_info = instance.module().info();
+        let first = module_globals(module_info)
+            .and_then(|globals| globals.function_points)
+            .ok_or_else(|| ExportError::Missing("function_points".to_string()))?;
+        let mut global_exports = HashMap::new();
+        let mut function_exports = HashMap::new();
+        for (name, index) in module_info.exports.iter() {
+            match index {
+                ExportIndex::Global(global_index) => {
+                    global_exports.insert(*global_index, name);
+                }
+                ExportIndex::Function(function_index) => {
+                    function_exports.entry(*function_index).or_insert(name);
+                }
+                _ => {}
+            }
+        }
+        let num_local_functions = module_info.functions.len() - module_info.num_imported_functions;
+        (0..num_local_functions as u32)
+            .map(|local_function_index| {
+                let global_index = GlobalIndex::from_u32(first.as_u32() + local_function_index);
+                let export = global_exports
+                    .get(&global_index)
+                    .ok_or_else(|| ExportError::Missing("function_points".to_string()))?;
+                let global = instance.exports.get_global(export)?;
+                if *global.ty() != GlobalType::new(Type::I64, Mutability::Var) {
+                    return Err(ExportError::IncompatibleType);
+                }
+                let index =
+                    module_info.func_index(LocalFunctionIndex::from_u32(local_function_index));
+                let name = module_info
+                    .function_names
+                    .get(&index)
+                    .or_else(|| function_exports.get(&index).copied())
+                    .cloned();
+                Ok(FunctionPoints {
+                    index,
+                    name,
+                    points: global.get().unwrap_i64() as _,
+                })
+            })
+            .collect()
+    }
+
+    /// Prewarms an Instance with [`Instance::prewarm`], running the
+    /// `warmup` export with at most `limit` points.
+    ///
+    /// The remaining points of the Instance are restored afterwards, so
+    /// the warmup doesn't use them.
+    ///
+    /// Important: the instance Module must been processed with the `Metering` middleware.
+    pub fn prewarm(
+        &self,
+        instance: &Instance,
+        warmup: &str,
+        limit: u64,
+    ) -> Result<usize, RuntimeError> {
+        let remaining_points = self.get_remaining_points(instance);
+        self.set_remaining_points(instance, limit);
+        let result = instance.prewarm(Some(warmup));
+        self.set_remaining_points(instance, remaining_points);
+        result
+    }
+
+    /// Runs `call`, a call of a function of `instance`, and returns its
+    /// result with the points it consumed, like
+    /// `metering.metered_call(&instance, || add.call(1, 2))`.
+    ///
+    /// Important: the instance Module must been processed with the `Metering` middleware.
+    pub fn metered_call<T>(
+        &self,
+        instance: &Instance,
+        call: impl FnOnce() -> Result<T, RuntimeError>,
+    ) -> MeteredCall<T> {
+        let remaining_points = metering_global(instance, "remaining_points", Type::I64)
+            .expect("Can't get `remaining_points` from Instance");
+        let before = remaining_points.get().unwrap_i64() as u64;
+        let result = call();
+        let after = remaining_points.get().unwrap_i64() as u64;
+        MeteredCall {
+            result,
+            consumed_points: before.saturating_sub(after),
+        }
+    }
+}
+
+/// Returns the global of `instance` registered for `purpose` by the `Metering` middleware,
+/// checking its type.
+fn metering_global<'a>(
+    instance: &'a Instance,
+    purpose: &str,
+    ty: Type,
+) -> Result<&'a Global, ExportError> {
+    registered_global(instance, "metering", purpose, ty)
+}
+
+/// Returns the globals added to `module_info` by `Metering::transform_module_info`.
+fn module_globals(module_info: &ModuleInfo) -> Option<MeteringGlobals> {
+    let exports = module_info.middleware_exports();
+    let global = |purpose| match module_info
+        .exports
+        .get(&registered_export(&exports, "metering", purpose)?.export)?
+    {
+        ExportIndex::Global(global_index) => Some(*global_index),
+        _ => None,
+    };
+    Some(MeteringGlobals {
+        remaining_points: global("remaining_points")?,
+        points_exhausted: global("points_exhausted")?,
+        points_used: global("points_used"),
+        function_points: global("function_points"),
+    })
+}
+
+/// The result of a call made with [`Metering::metered_call`], with the
+/// points it consumed.
+#[derive(Debug)]
+pub struct MeteredCall<T> {
+    result: Result<T, RuntimeError>,
+    consumed_points: u64,
+}
+
+impl<T> MeteredCall<T> {
+    /// Returns the points consumed by the call.
+    ///
+    /// When the call runs out of points, the cost of the basic block that
+    /// could not be executed is not included.
+    pub fn consumed_points(&self) -> u64 {
+        self.consumed_points
+    }
+
+    /// Returns the result of the call, or the trap that ended it.
+    pub fn result(&self) -> &Result<T, RuntimeError> {
+        &self.result
+    }
+
+    /// Returns the result of the call, or the trap that ended it.
+    pub fn into_result(self) -> Result<T, RuntimeError> {
+        self.result
+    }
 }
 
 impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> fmt::Debug for Metering<F> {
@@ -83,7 +412,10 @@ impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> fmt::Debug for Meteri
         f.debug_struct("Metering")
             .field("initial_limit", &self.initial_limit)
             .field("cost_function", &"<function>")
-            .field("remaining_points_index", &self.remaining_points_index)
+            .field("export_names", &self.export_names)
+            .field("points_used", &self.points_used)
+            .field("function_points", &self.function_points)
+            .field("saturating_counters", &self.saturating_counters)
             .finish()
     }
 }
@@ -92,36 +424,94 @@ impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync + 'static> ModuleMiddl
     for Metering<F>
 {
     /// Generates a `FunctionMiddleware` for a given function.
-    fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
-        Box::new(FunctionMetering {
+    fn generate_function_middleware(
+        &self,
+        module_info: &ModuleInfo,
+        local_function_index: LocalFunctionIndex,
+    ) -> Result<Box<dyn FunctionMiddleware>, MiddlewareError> {
+        let globals = module_globals(module_info).ok_or_else(|| {
+            MiddlewareError::new(
+                "metering",
+                "the module wasn't transformed by the middleware",
+            )
+        })?;
+        Ok(Box::new(FunctionMetering {
             cost_function: self.cost_function,
-            remaining_points_index: self.remaining_points_index.lock().unwrap().expect(
-                "Metering::generate_function_middleware: Remaining points index not set up.",
-            ),
+            globals,
+            function_points: globals
+                .function_points
+                .map(|first| GlobalIndex::from_u32(first.as_u32() + local_function_index.as_u32())),
+            saturating_counters: self.saturating_counters,
             accumulated_cost: 0,
-        })
+        }))
     }
 
     /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
-    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
-        let mut remaining_points_index = self.remaining_points_index.lock().unwrap();
-        if remaining_points_index.is_some() {
-            panic!("Metering::transform_module_info: Attempting to use a `Metering` middleware from multiple modules.");
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        // Append the globals for the remaining points and their exhaustion, and initialize them.
+        let mut push_global = |ty: Type, init: GlobalInit| {
+            module_info.global_initializers.push(init);
+            module_info
+                .globals
+                .push(GlobalType::new(ty, Mutability::Var))
+        };
+        let remaining_points =
+            push_global(Type::I64, GlobalInit::I64Const(self.initial_limit as _));
+        let points_exhausted = push_global(Type::I32, GlobalInit::I32Const(0));
+
+        // The accessors find the exported globals by their purpose.
+        for (name, global_index, purpose) in &[
+            (&self.export_names.0, remaining_points, "remaining_points"),
+            (&self.export_names.1, points_exhausted, "points_exhausted"),
+        ] {
+            let name = unused_export_name(module_info, name);
+            module_info.register_middleware_export("metering", &name, purpose);
+            module_info
+                .exports
+                .insert(name, ExportIndex::Global(*global_index));
         }
 
-        // Append a global for remaining points and initialize it.
-        let global_index = module_info
-            .globals
-            .push(GlobalType::new(Type::I64, Mutability::Var));
-        *remaining_points_index = Some(global_index.clone());
-        module_info
-            .global_initializers
-            .push(GlobalInit::I64Const(self.initial_limit as i64));
+        if self.points_used {
+            module_info
+                .global_initializers
+                .push(GlobalInit::I64Const(0));
+            let global_index = module_info
+                .globals
+                .push(GlobalType::new(Type::I64, Mutability::Var));
+            let name = unused_export_name(module_info, "points_used");
+            module_info.register_middleware_export("metering", &name, "points_used");
+            module_info
+                .exports
+                .insert(name, ExportIndex::Global(global_index));
+        }
 
-        module_info.exports.insert(
-            "remaining_points".to_string(),
-            ExportIndex::Global(global_index),
-        );
+        // The globals of the points consumed by the local functions follow, in the order of
+        // the functions. Only the first one is registered, to keep the registry small.
+        if self.function_points {
+            let num_local_functions =
+                module_info.functions.len() - module_info.num_imported_functions;
+            for local_function_index in 0..num_local_functions as u32 {
+                module_info
+                    .global_initializers
+                    .push(GlobalInit::I64Const(0));
+                let global_index = module_info
+                    .globals
+                    .push(GlobalType::new(Type::I64, Mutability::Var));
+                let function_index =
+                    module_info.func_index(LocalFunctionIndex::from_u32(local_function_index));
+                let name = unused_export_name(
+                    module_info,
+                    &format!("function_points_{}", function_index.as_u32()),
+                );
+                if local_function_index == 0 {
+                    module_info.register_middleware_export("metering", &name, "function_points");
+                }
+                module_info
+                    .exports
+                    .insert(name, ExportIndex::Global(global_index));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -129,11 +519,48 @@ impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> fmt::Debug for Functi
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("FunctionMetering")
             .field("cost_function", &"<function>")
-            .field("remaining_points_index", &self.remaining_points_index)
+            .field("globals", &self.globals)
             .finish()
     }
 }
 
+impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> FunctionMetering<F> {
+    /// Adds the cost of the current basic block to the counter `global_index`.
+    fn count_points(&self, global_index: GlobalIndex, state: &mut MiddlewareReaderState<'_>) {
+        let global_index = global_index.as_u32();
+        let cost = self.accumulated_cost as i64;
+        if self.saturating_counters {
+            state.extend(&[
+                // globals[global_index] =
+                //     if unsigned(globals[global_index]) > u64::MAX - self.accumulated_cost {
+                //         u64::MAX
+                //     } else {
+                //         globals[global_index] + self.accumulated_cost
+                //     };
+                Operator::I64Const { value: -1 },
+                Operator::GlobalGet { global_index },
+                Operator::I64Const { value: cost },
+                Operator::I64Add,
+                Operator::GlobalGet { global_index },
+                Operator::I64Const {
+                    value: !self.accumulated_cost as i64,
+                },
+                Operator::I64GtU,
+                Operator::Select,
+                Operator::GlobalSet { global_index },
+            ]);
+        } else {
+            state.extend(&[
+                // globals[global_index] += self.accumulated_cost;
+                Operator::GlobalGet { global_index },
+                Operator::I64Const { value: cost },
+                Operator::I64Add,
+                Operator::GlobalSet { global_index },
+            ]);
+        }
+    }
+}
+
 impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> FunctionMiddleware
     for FunctionMetering<F>
 {
@@ -145,7 +572,10 @@ impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> FunctionMiddleware
         // Get the cost of the current operator, and add it to the accumulator.
         // This needs to be done before the metering logic, to prevent operators like `Call` from escaping metering in some
         // corner cases.
-        self.accumulated_cost += (self.cost_function)(&operator);
+        // The cost saturates, so that a basic block can't wrap around to a cheap one.
+        self.accumulated_cost = self
+            .accumulated_cost
+            .saturating_add((self.cost_function)(&operator));
 
         // Possible sources and targets of a branch. Finalize the cost of the previous basic block and perform necessary checks.
         match operator {
@@ -160,22 +590,37 @@ impl<F: Fn(&Operator) -> u64 + Copy + Clone + Send + Sync> FunctionMiddleware
             | Operator::Return // end of function - branch source
             => {
                 if self.accumulated_cost > 0 {
+                    let remaining_points = self.globals.remaining_points.as_u32();
+                    let points_exhausted = self.globals.points_exhausted.as_u32();
                     state.extend(&[
-                        // if unsigned(globals[remaining_points_index]) < unsigned(self.accumulated_cost) { throw(); }
-                        Operator::GlobalGet { global_index: self.remaining_points_index.as_u32() },
+                        // if unsigned(globals[remaining_points]) < unsigned(self.accumulated_cost) {
+                        //     globals[points_exhausted] = 1;
+                        //     throw();
+                        // }
+                        Operator::GlobalGet { global_index: remaining_points },
                         Operator::I64Const { value: self.accumulated_cost as i64 },
                         Operator::I64LtU,
                         Operator::If { ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType) },
-                        Operator::Unreachable, // FIXME: Signal the error properly.
+                        Operator::I32Const { value: 1 },
+                        Operator::GlobalSet { global_index: points_exhausted },
+                        Operator::Unreachable,
                         Operator::End,
 
-                        // globals[remaining_points_index] -= self.accumulated_cost;
-                        Operator::GlobalGet { global_index: self.remaining_points_index.as_u32() },
+                        // globals[remaining_points] -= self.accumulated_cost;
+                        // (never wraps, as the points have been checked above)
+                        Operator::GlobalGet { global_index: remaining_points },
                         Operator::I64Const { value: self.accumulated_cost as i64 },
                         Operator::I64Sub,
-                        Operator::GlobalSet { global_index: self.remaining_points_index.as_u32() },
+                        Operator::GlobalSet { global_index: remaining_points },
                     ]);
 
+                    if let Some(points_used) = self.globals.points_used {
+                        self.count_points(points_used, state);
+                    }
+                    if let Some(function_points) = self.function_points {
+                        self.count_points(function_points, state);
+                    }
+
                     self.accumulated_cost = 0;
                 }
             }
@@ -0,0 +1,617 @@
+//! `shadow_memory` is a middleware catching the guest reads of linear memory that was never
+//! written, to track down the bugs which show up with some compilers only.
+//!
+//! The middleware keeps one shadow byte per byte of the linear memory, set to `1` once the
+//! byte is written. Every load checks the shadow bytes it covers: if one of them is not set,
+//! the load is not made, the execution traps and [`ShadowMemory::uninitialized_read`] tells
+//! which address was read and where.
+//!
+//! The shadow bytes live in the linear memory itself: a module supporting up to `P` pages
+//! of memory (see [`ShadowMemory::new`]) gets `P` more pages, and the shadow of the address
+//! `a` is at `P * 64KiB + a`. The middleware rewrites `memory.size`, `memory.grow` and the
+//! bounds of the accesses, so the guest still sees its own memory only; the host, through
+//! [`Memory`](wasmer::Memory), sees the whole memory.
+//!
+//! The bulk memory operators update the shadow bytes, `memory.copy` copies them; the SIMD
+//! and atomic operators don't. The host marks the memory it writes into with
+//! [`ShadowMemory::mark_initialized`], and the checks only start with
+//! [`ShadowMemory::enable`], once the data segments are marked.
+
+use crate::exports::{registered_export_name, registered_global, unused_export_name};
+use crate::watchpoints::{memory_access, WatchKind};
+use std::ops::Range;
+use std::sync::Mutex;
+use wasmer::wasmparser::{
+    MemoryImmediate, Operator, Result as WpResult, Type as WpType, TypeOrFuncType,
+};
+use wasmer::{
+    ExportIndex, FrameInfo, FunctionMiddleware, GlobalInit, GlobalType, Instance,
+    LocalFunctionIndex, MemoryIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware,
+    Mutability, Pages, RuntimeError, Type, Value, WASM_PAGE_SIZE,
+};
+use wasmer_engine::Artifact;
+use wasmer_types::GlobalIndex;
+use wasmer_vm::ModuleInfo;
+
+/// A read of linear memory that was never written.
+#[derive(Debug, Clone)]
+pub struct UninitializedRead {
+    /// The first read byte.
+    pub address: u64,
+    /// The number of bytes read, some of which were never written.
+    pub size: u64,
+    /// The guest frame of the read: its function and offset in the module.
+    pub frame: Option<FrameInfo>,
+}
+
+/// The globals added to the module by the middleware.
+#[derive(Debug, Clone, Copy)]
+struct ShadowGlobals {
+    /// The size of the memory seen by the guest, in pages.
+    guest_pages: GlobalIndex,
+    /// The address operand of the current access.
+    address: GlobalIndex,
+    /// The first accessed byte of the current access.
+    effective_address: GlobalIndex,
+    /// The value operands of the current store, by type, and the second operand of the
+    /// bulk memory operators.
+    value_i32: GlobalIndex,
+    value_i64: GlobalIndex,
+    value_f32: GlobalIndex,
+    value_f64: GlobalIndex,
+    /// The length operand of the bulk memory operators.
+    len: GlobalIndex,
+    /// Whether the loads are checked (exported).
+    enabled: GlobalIndex,
+    /// The first read byte of the uninitialized read (exported).
+    read_address: GlobalIndex,
+    /// The number of bytes of the uninitialized read, `0` without one (exported).
+    read_size: GlobalIndex,
+}
+
+/// The module-level shadow memory middleware.
+///
+/// # Errors
+///
+/// An instance of `ShadowMemory` should not be shared among different modules, since it
+/// tracks module-specific information like the global indices of its state. Attempts to use
+/// a `ShadowMemory` instance from multiple modules fail to compile the other modules.
+///
+/// The memory of the module must be defined by the module, and use at most the number of
+/// pages given to [`ShadowMemory::new`], or the module fails to compile.
+#[derive(Debug)]
+pub struct ShadowMemory {
+    max_guest_pages: Pages,
+    globals: Mutex<Option<ShadowGlobals>>,
+}
+
+/// The function-level shadow memory middleware.
+#[derive(Debug)]
+pub struct FunctionShadowMemory {
+    shadow_base: u32,
+    globals: ShadowGlobals,
+}
+
+impl ShadowMemory {
+    /// Creates a `ShadowMemory` middleware for modules using at most `max_guest_pages` pages
+    /// of memory.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `max_guest_pages` is half of the 4GiB of linear memory or more, the other
+    /// half holding the shadow bytes.
+    pub fn new(max_guest_pages: Pages) -> Self {
+        assert!(
+            max_guest_pages.0 < 32768,
+            "ShadowMemory::new: at most 32767 pages of memory can be shadowed"
+        );
+        Self {
+            max_guest_pages,
+            globals: Mutex::new(None),
+        }
+    }
+
+    /// Marks the data segments of the module of `instance` as written, and starts checking
+    /// the loads of the instance.
+    ///
+    /// The data segments with an imported base are not marked.
+    ///
+    /// Important: the instance Module must been processed with the `ShadowMemory` middleware.
+    pub fn enable(&self, instance: &Instance) {
+        for initializer in instance.module().artifact().data_initializers() {
+            let location = &initializer.location;
+            if location.memory_index == MemoryIndex::from_u32(0) && location.base.is_none() {
+                let start = location.offset as u64;
+                self.mark_initialized(instance, start..start + initializer.data.len() as u64);
+            }
+        }
+        registered_global(instance, "shadow_memory", "enabled", Type::I32)
+            .expect("Can't get `shadow_enabled` from Instance")
+            .set(Value::I32(1))
+            .expect("Can't set `shadow_enabled` in Instance");
+    }
+
+    /// Marks the bytes of `range` as written, for the memory written by the host, like the
+    /// buffers filled by host functions.
+    ///
+    /// The bytes out of the memory of the guest are ignored.
+    ///
+    /// Important: the instance Module must been processed with the `ShadowMemory` middleware.
+    pub fn mark_initialized(&self, instance: &Instance, range: Range<u64>) {
+        let memory = registered_export_name(instance, "shadow_memory", "memory")
+            .and_then(|name| instance.exports.get_memory(&name))
+            .expect("Can't get `shadow_memory` from Instance");
+        let shadow_base = self.shadow_base() as u64;
+        let guest_size = memory.size().bytes().0 as u64 - shadow_base;
+        let view = memory.view::<u8>();
+        for address in range.start..range.end.min(guest_size) {
+            view[(shadow_base + address) as usize].set(1);
+        }
+    }
+
+    /// Returns the uninitialized read which made `error`, if any, and resets it so the
+    /// instance can be called again.
+    ///
+    /// Important: the instance Module must been processed with the `ShadowMemory` middleware.
+    pub fn uninitialized_read(
+        &self,
+        instance: &Instance,
+        error: &RuntimeError,
+    ) -> Option<UninitializedRead> {
+        let read_size = registered_global(instance, "shadow_memory", "read_size", Type::I32)
+            .expect("Can't get `shadow_read_size` from Instance");
+        let size = match read_size.get().unwrap_i32() {
+            0 => return None,
+            size => size as u64,
+        };
+        read_size
+            .set(Value::I32(0))
+            .expect("Can't set `shadow_read_size` in Instance");
+        let address = registered_global(instance, "shadow_memory", "read_address", Type::I64)
+            .expect("Can't get `shadow_read_address` from Instance")
+            .get()
+            .unwrap_i64() as u64;
+
+        Some(UninitializedRead {
+            address,
+            size,
+            frame: error.trace().first().cloned(),
+        })
+    }
+
+    fn shadow_base(&self) -> u32 {
+        self.max_guest_pages.0 * WASM_PAGE_SIZE as u32
+    }
+}
+
+impl ModuleMiddleware for ShadowMemory {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        _: &ModuleInfo,
+        _: LocalFunctionIndex,
+    ) -> Result<Box<dyn FunctionMiddleware>, MiddlewareError> {
+        Ok(Box::new(FunctionShadowMemory {
+            shadow_base: self.shadow_base(),
+            globals: self.globals.lock().unwrap().ok_or_else(|| {
+                MiddlewareError::new(
+                    "shadow_memory",
+                    "the module wasn't transformed by the middleware",
+                )
+            })?,
+        }))
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        let mut globals = self.globals.lock().unwrap();
+        if globals.is_some() {
+            return Err(MiddlewareError::new(
+                "shadow_memory",
+                "a `ShadowMemory` can't be used with several modules",
+            ));
+        }
+        if module_info.num_imported_memories > 0 {
+            return Err(MiddlewareError::new(
+                "shadow_memory",
+                "imported memories can't be shadowed",
+            ));
+        }
+
+        // The memory holds the guest memory, up to `max_guest_pages`, then the shadow of
+        // the guest memory.
+        let mut guest_pages = 0;
+        if let Some(memory) = module_info.memories.get_mut(MemoryIndex::from_u32(0)) {
+            let max_pages = self.max_guest_pages;
+            if memory.minimum > max_pages {
+                return Err(MiddlewareError::new(
+                    "shadow_memory",
+                    "the memory is larger than the shadowed pages",
+                ));
+            }
+            guest_pages = memory.minimum.0;
+            let guest_maximum = memory
+                .maximum
+                .map_or(max_pages, |maximum| maximum.min(max_pages));
+            memory.minimum = Pages(max_pages.0 + memory.minimum.0);
+            memory.maximum = Some(Pages(max_pages.0 + guest_maximum.0));
+            let name = unused_export_name(module_info, "shadow_memory");
+            module_info.register_middleware_export("shadow_memory", &name, "memory");
+            module_info
+                .exports
+                .insert(name, ExportIndex::Memory(MemoryIndex::from_u32(0)));
+        }
+
+        let mut push_global = |ty: Type, init: GlobalInit| {
+            module_info.global_initializers.push(init);
+            module_info
+                .globals
+                .push(GlobalType::new(ty, Mutability::Var))
+        };
+        let shadow_globals = ShadowGlobals {
+            guest_pages: push_global(Type::I32, GlobalInit::I32Const(guest_pages as i32)),
+            address: push_global(Type::I32, GlobalInit::I32Const(0)),
+            effective_address: push_global(Type::I64, GlobalInit::I64Const(0)),
+            value_i32: push_global(Type::I32, GlobalInit::I32Const(0)),
+            value_i64: push_global(Type::I64, GlobalInit::I64Const(0)),
+            value_f32: push_global(Type::F32, GlobalInit::F32Const(0.0)),
+            value_f64: push_global(Type::F64, GlobalInit::F64Const(0.0)),
+            len: push_global(Type::I32, GlobalInit::I32Const(0)),
+            enabled: push_global(Type::I32, GlobalInit::I32Const(0)),
+            read_address: push_global(Type::I64, GlobalInit::I64Const(0)),
+            read_size: push_global(Type::I32, GlobalInit::I32Const(0)),
+        };
+
+        for (name, global, purpose) in &[
+            ("shadow_enabled", shadow_globals.enabled, "enabled"),
+            (
+                "shadow_read_address",
+                shadow_globals.read_address,
+                "read_address",
+            ),
+            ("shadow_read_size", shadow_globals.read_size, "read_size"),
+        ] {
+            let name = unused_export_name(module_info, name);
+            module_info.register_middleware_export("shadow_memory", &name, purpose);
+            module_info
+                .exports
+                .insert(name, ExportIndex::Global(*global));
+        }
+        *globals = Some(shadow_globals);
+        Ok(())
+    }
+}
+
+/// The memory immediate of the accesses to the shadow bytes.
+const SHADOW_MEMARG: MemoryImmediate = MemoryImmediate {
+    align: 0,
+    offset: 0,
+    memory: 0,
+};
+
+impl FunctionShadowMemory {
+    fn value_global(&self, ty: Type) -> u32 {
+        let global = match ty {
+            Type::I32 => self.globals.value_i32,
+            Type::I64 => self.globals.value_i64,
+            Type::F32 => self.globals.value_f32,
+            _ => self.globals.value_f64,
+        };
+        global.as_u32()
+    }
+
+    /// Pushes the size of the guest memory in bytes, as an `i64`.
+    fn push_guest_size(&self, state: &mut MiddlewareReaderState) {
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: self.globals.guest_pages.as_u32(),
+            },
+            Operator::I64ExtendI32U,
+            Operator::I64Const { value: 16 },
+            Operator::I64Shl,
+        ]);
+    }
+
+    /// Replaces the `i32` address in `global` by `-1` if the `len` bytes from it are out of
+    /// the guest memory, so that the access traps like it would without the shadow pages.
+    fn push_bounds_check(&self, state: &mut MiddlewareReaderState, global: GlobalIndex) {
+        let (global, len) = (global.as_u32(), self.globals.len.as_u32());
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: global,
+            },
+            Operator::I64ExtendI32U,
+            Operator::GlobalGet { global_index: len },
+            Operator::I64ExtendI32U,
+            Operator::I64Add,
+        ]);
+        self.push_guest_size(state);
+        state.extend(&[
+            Operator::I64GtU,
+            Operator::If {
+                ty: TypeOrFuncType::Type(WpType::EmptyBlockType),
+            },
+            Operator::I32Const { value: -1 },
+            Operator::GlobalSet {
+                global_index: global,
+            },
+            Operator::End,
+        ]);
+    }
+
+    /// Pushes the shadow address of the `i32` address in `global`.
+    fn push_shadow_address(&self, state: &mut MiddlewareReaderState, global: GlobalIndex) {
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: global.as_u32(),
+            },
+            Operator::I32Const {
+                value: self.shadow_base as i32,
+            },
+            Operator::I32Add,
+        ]);
+    }
+
+    /// Instruments a load or a store of `size` bytes.
+    fn push_access<'a>(
+        &self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+        (kind, memarg, size, value_ty): (WatchKind, MemoryImmediate, u64, Option<Type>),
+    ) {
+        let address = self.globals.address.as_u32();
+        let effective_address = self.globals.effective_address.as_u32();
+        // Save the operands, and compute the first accessed byte without overflow.
+        if let Some(ty) = value_ty {
+            state.push_operator(Operator::GlobalSet {
+                global_index: self.value_global(ty),
+            });
+        }
+        state.extend(&[
+            Operator::GlobalSet {
+                global_index: address,
+            },
+            Operator::GlobalGet {
+                global_index: address,
+            },
+            Operator::I64ExtendI32U,
+            Operator::I64Const {
+                value: i64::from(memarg.offset),
+            },
+            Operator::I64Add,
+            Operator::GlobalSet {
+                global_index: effective_address,
+            },
+            // if effective_address + size > guest size
+            Operator::GlobalGet {
+                global_index: effective_address,
+            },
+            Operator::I64Const { value: size as i64 },
+            Operator::I64Add,
+        ]);
+        self.push_guest_size(state);
+        state.extend(&[
+            Operator::I64GtU,
+            Operator::If {
+                ty: TypeOrFuncType::Type(WpType::EmptyBlockType),
+            },
+            // Access the address `-1` instead, out of the whole memory.
+            Operator::I32Const { value: -1 },
+            Operator::GlobalSet {
+                global_index: address,
+            },
+            Operator::Else,
+            // The shadow address, `effective_address` being in the guest memory.
+            Operator::GlobalGet {
+                global_index: effective_address,
+            },
+            Operator::I32WrapI64,
+            Operator::I32Const {
+                value: self.shadow_base as i32,
+            },
+            Operator::I32Add,
+        ]);
+
+        let (shadow_load, shadow_store, written, not_written) = match size {
+            1 => (
+                Operator::I32Load8U {
+                    memarg: SHADOW_MEMARG,
+                },
+                Operator::I32Store8 {
+                    memarg: SHADOW_MEMARG,
+                },
+                Operator::I32Const { value: 0x01 },
+                Operator::I32Ne,
+            ),
+            2 => (
+                Operator::I32Load16U {
+                    memarg: SHADOW_MEMARG,
+                },
+                Operator::I32Store16 {
+                    memarg: SHADOW_MEMARG,
+                },
+                Operator::I32Const { value: 0x0101 },
+                Operator::I32Ne,
+            ),
+            4 => (
+                Operator::I32Load {
+                    memarg: SHADOW_MEMARG,
+                },
+                Operator::I32Store {
+                    memarg: SHADOW_MEMARG,
+                },
+                Operator::I32Const { value: 0x0101_0101 },
+                Operator::I32Ne,
+            ),
+            _ => (
+                Operator::I64Load {
+                    memarg: SHADOW_MEMARG,
+                },
+                Operator::I64Store {
+                    memarg: SHADOW_MEMARG,
+                },
+                Operator::I64Const {
+                    value: 0x0101_0101_0101_0101,
+                },
+                Operator::I64Ne,
+            ),
+        };
+        if kind == WatchKind::Read {
+            state.extend(&[
+                shadow_load,
+                written,
+                not_written,
+                Operator::GlobalGet {
+                    global_index: self.globals.enabled.as_u32(),
+                },
+                Operator::I32And,
+                Operator::If {
+                    ty: TypeOrFuncType::Type(WpType::EmptyBlockType),
+                },
+                Operator::GlobalGet {
+                    global_index: effective_address,
+                },
+                Operator::GlobalSet {
+                    global_index: self.globals.read_address.as_u32(),
+                },
+                Operator::I32Const { value: size as i32 },
+                Operator::GlobalSet {
+                    global_index: self.globals.read_size.as_u32(),
+                },
+                Operator::Unreachable,
+                Operator::End,
+            ]);
+        } else {
+            state.extend(&[written, shadow_store]);
+        }
+        state.push_operator(Operator::End);
+
+        // Restore the operands and make the access.
+        state.push_operator(Operator::GlobalGet {
+            global_index: address,
+        });
+        if let Some(ty) = value_ty {
+            state.push_operator(Operator::GlobalGet {
+                global_index: self.value_global(ty),
+            });
+        }
+        state.push_operator(operator);
+    }
+}
+
+impl FunctionMiddleware for FunctionShadowMemory {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> WpResult<()> {
+        let guest_pages = self.globals.guest_pages.as_u32();
+        let address = self.globals.address;
+        let value = self.globals.value_i32;
+        let len = self.globals.len.as_u32();
+        match operator {
+            Operator::MemorySize { .. } => {
+                state.push_operator(Operator::GlobalGet {
+                    global_index: guest_pages,
+                });
+            }
+            Operator::MemoryGrow { .. } => {
+                let delta = value.as_u32();
+                // The whole memory grows by the pages of the guest memory and their shadow.
+                state.extend(&[
+                    Operator::GlobalSet {
+                        global_index: delta,
+                    },
+                    Operator::GlobalGet {
+                        global_index: delta,
+                    },
+                    operator,
+                    Operator::I32Const { value: -1 },
+                    Operator::I32Eq,
+                    Operator::If {
+                        ty: TypeOrFuncType::Type(WpType::I32),
+                    },
+                    Operator::I32Const { value: -1 },
+                    Operator::Else,
+                    Operator::GlobalGet {
+                        global_index: guest_pages,
+                    },
+                    Operator::GlobalGet {
+                        global_index: guest_pages,
+                    },
+                    Operator::GlobalGet {
+                        global_index: delta,
+                    },
+                    Operator::I32Add,
+                    Operator::GlobalSet {
+                        global_index: guest_pages,
+                    },
+                    Operator::End,
+                ]);
+            }
+            Operator::MemoryFill { .. } | Operator::MemoryInit { .. } => {
+                state.extend(&[
+                    Operator::GlobalSet { global_index: len },
+                    Operator::GlobalSet {
+                        global_index: value.as_u32(),
+                    },
+                    Operator::GlobalSet {
+                        global_index: address.as_u32(),
+                    },
+                ]);
+                self.push_bounds_check(state, address);
+                state.extend(&[
+                    Operator::GlobalGet {
+                        global_index: address.as_u32(),
+                    },
+                    Operator::GlobalGet {
+                        global_index: value.as_u32(),
+                    },
+                    Operator::GlobalGet { global_index: len },
+                    operator,
+                ]);
+                self.push_shadow_address(state, address);
+                state.extend(&[
+                    Operator::I32Const { value: 1 },
+                    Operator::GlobalGet { global_index: len },
+                    Operator::MemoryFill { mem: 0 },
+                ]);
+            }
+            Operator::MemoryCopy { .. } => {
+                state.extend(&[
+                    Operator::GlobalSet { global_index: len },
+                    Operator::GlobalSet {
+                        global_index: value.as_u32(),
+                    },
+                    Operator::GlobalSet {
+                        global_index: address.as_u32(),
+                    },
+                ]);
+                self.push_bounds_check(state, address);
+                self.push_bounds_check(state, value);
+                state.extend(&[
+                    Operator::GlobalGet {
+                        global_index: address.as_u32(),
+                    },
+                    Operator::GlobalGet {
+                        global_index: value.as_u32(),
+                    },
+                    Operator::GlobalGet { global_index: len },
+                    operator,
+                ]);
+                self.push_shadow_address(state, address);
+                self.push_shadow_address(state, value);
+                state.extend(&[
+                    Operator::GlobalGet { global_index: len },
+                    Operator::MemoryCopy { src: 0, dst: 0 },
+                ]);
+            }
+            _ => match memory_access(&operator) {
+                Some(access) => self.push_access(operator, state, access),
+                None => state.push_operator(operator),
+            },
+        }
+
+        Ok(())
+    }
+}
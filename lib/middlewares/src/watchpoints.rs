@@ -0,0 +1,355 @@
+//! `watchpoints` is a middleware stopping the execution when a watched range of the linear
+//! memory is read or written, to track down guest memory corruptions.
+//!
+//! Every load and store is instrumented to check its accessed bytes against the watched
+//! ranges. On a hit, the access is not made: the execution traps, and
+//! [`Watchpoints::hit`] tells which address was accessed and where.
+//!
+//! Only the plain loads and stores are checked: the SIMD, atomic and bulk memory operators
+//! are not.
+
+use crate::exports::{registered_global, unused_export_name};
+use std::ops::Range;
+use std::sync::Mutex;
+use wasmer::wasmparser::{
+    MemoryImmediate, Operator, Result as WpResult, Type as WpType,
+    TypeOrFuncType as WpTypeOrFuncType,
+};
+use wasmer::{
+    ExportIndex, FrameInfo, FunctionMiddleware, GlobalInit, GlobalType, Instance,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability,
+    RuntimeError, Type, Value,
+};
+use wasmer_types::GlobalIndex;
+use wasmer_vm::ModuleInfo;
+
+/// The kinds of memory accesses a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    /// Loads.
+    Read,
+    /// Stores.
+    Write,
+    /// Loads and stores.
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn watches(self, access: WatchKind) -> bool {
+        self == WatchKind::ReadWrite || self == access
+    }
+}
+
+/// A watched range of the linear memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// The watched bytes.
+    pub range: Range<u64>,
+    /// The accesses to stop on.
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    /// Creates a watchpoint on `range`.
+    pub fn new(range: Range<u64>, kind: WatchKind) -> Self {
+        Self { range, kind }
+    }
+}
+
+/// A memory access which hit a watchpoint.
+#[derive(Debug, Clone)]
+pub struct WatchpointHit {
+    /// The first accessed byte.
+    pub address: u64,
+    /// The kind of access, [`WatchKind::Read`] or [`WatchKind::Write`].
+    pub kind: WatchKind,
+    /// The guest frame of the access: its function and offset in the module.
+    pub frame: Option<FrameInfo>,
+}
+
+/// The globals added to the module by the middleware.
+#[derive(Debug, Clone, Copy)]
+struct WatchGlobals {
+    /// The address operand of the current access.
+    address: GlobalIndex,
+    /// The first accessed byte of the current access.
+    effective_address: GlobalIndex,
+    /// The value operands of the current store, by type.
+    value_i32: GlobalIndex,
+    value_i64: GlobalIndex,
+    value_f32: GlobalIndex,
+    value_f64: GlobalIndex,
+    /// The first accessed byte of the hit access (exported).
+    hit_address: GlobalIndex,
+    /// `0` without hit, `1` for a read and `2` for a write (exported).
+    hit_kind: GlobalIndex,
+}
+
+/// The module-level watchpoints middleware.
+///
+/// # Errors
+///
+/// An instance of `Watchpoints` should not be shared among different modules, since it
+/// tracks module-specific information like the global indices of its state. Attempts to use
+/// a `Watchpoints` instance from multiple modules fail to compile the other modules.
+#[derive(Debug)]
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    globals: Mutex<Option<WatchGlobals>>,
+}
+
+/// The function-level watchpoints middleware.
+#[derive(Debug)]
+pub struct FunctionWatchpoints {
+    watchpoints: Vec<Watchpoint>,
+    globals: WatchGlobals,
+}
+
+impl Watchpoints {
+    /// Creates a `Watchpoints` middleware.
+    pub fn new(watchpoints: Vec<Watchpoint>) -> Self {
+        Self {
+            watchpoints,
+            globals: Mutex::new(None),
+        }
+    }
+
+    /// Returns the watchpoint hit which made `error`, if any, and resets it so the instance
+    /// can be called again.
+    ///
+    /// Returns `None` if the instance Module wasn't processed with the `Watchpoints`
+    /// middleware.
+    pub fn hit(&self, instance: &Instance, error: &RuntimeError) -> Option<WatchpointHit> {
+        let hit_kind = registered_global(instance, "watchpoints", "hit_kind", Type::I32).ok()?;
+        let kind = match hit_kind.get().unwrap_i32() {
+            1 => WatchKind::Read,
+            2 => WatchKind::Write,
+            _ => return None,
+        };
+        hit_kind.set(Value::I32(0)).ok()?;
+        let address = registered_global(instance, "watchpoints", "hit_address", Type::I64)
+            .ok()?
+            .get()
+            .unwrap_i64() as u64;
+
+        Some(WatchpointHit {
+            address,
+            kind,
+            frame: error.trace().first().cloned(),
+        })
+    }
+}
+
+impl ModuleMiddleware for Watchpoints {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        _: &ModuleInfo,
+        _: LocalFunctionIndex,
+    ) -> Result<Box<dyn FunctionMiddleware>, MiddlewareError> {
+        Ok(Box::new(FunctionWatchpoints {
+            watchpoints: self.watchpoints.clone(),
+            globals: self.globals.lock().unwrap().ok_or_else(|| {
+                MiddlewareError::new(
+                    "watchpoints",
+                    "the module wasn't transformed by the middleware",
+                )
+            })?,
+        }))
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        let mut globals = self.globals.lock().unwrap();
+        if globals.is_some() {
+            return Err(MiddlewareError::new(
+                "watchpoints",
+                "a `Watchpoints` can't be used with several modules",
+            ));
+        }
+
+        let mut push_global = |ty: Type, init: GlobalInit| {
+            module_info.global_initializers.push(init);
+            module_info
+                .globals
+                .push(GlobalType::new(ty, Mutability::Var))
+        };
+        let watch_globals = WatchGlobals {
+            address: push_global(Type::I32, GlobalInit::I32Const(0)),
+            effective_address: push_global(Type::I64, GlobalInit::I64Const(0)),
+            value_i32: push_global(Type::I32, GlobalInit::I32Const(0)),
+            value_i64: push_global(Type::I64, GlobalInit::I64Const(0)),
+            value_f32: push_global(Type::F32, GlobalInit::F32Const(0.0)),
+            value_f64: push_global(Type::F64, GlobalInit::F64Const(0.0)),
+            hit_address: push_global(Type::I64, GlobalInit::I64Const(0)),
+            hit_kind: push_global(Type::I32, GlobalInit::I32Const(0)),
+        };
+
+        for (name, global, purpose) in &[
+            (
+                "watchpoint_hit_address",
+                watch_globals.hit_address,
+                "hit_address",
+            ),
+            ("watchpoint_hit_kind", watch_globals.hit_kind, "hit_kind"),
+        ] {
+            let name = unused_export_name(module_info, name);
+            module_info.register_middleware_export("watchpoints", &name, purpose);
+            module_info
+                .exports
+                .insert(name, ExportIndex::Global(*global));
+        }
+        *globals = Some(watch_globals);
+        Ok(())
+    }
+}
+
+/// Returns the kind of access of `operator`, its memory immediate, the number of bytes
+/// accessed and, for stores, the type of the stored value.
+pub(crate) fn memory_access(
+    operator: &Operator,
+) -> Option<(WatchKind, MemoryImmediate, u64, Option<Type>)> {
+    use Operator::*;
+    let read = |memarg: &MemoryImmediate, size| Some((WatchKind::Read, *memarg, size, None));
+    let write =
+        |memarg: &MemoryImmediate, size, ty| Some((WatchKind::Write, *memarg, size, Some(ty)));
+    match operator {
+        I32Load8S { memarg }
+        | I32Load8U { memarg }
+        | I64Load8S { memarg }
+        | I64Load8U { memarg } => read(memarg, 1),
+        I32Load16S { memarg }
+        | I32Load16U { memarg }
+        | I64Load16S { memarg }
+        | I64Load16U { memarg } => read(memarg, 2),
+        I32Load { memarg } | F32Load { memarg } | I64Load32S { memarg } | I64Load32U { memarg } => {
+            read(memarg, 4)
+        }
+        I64Load { memarg } | F64Load { memarg } => read(memarg, 8),
+        I32Store8 { memarg } => write(memarg, 1, Type::I32),
+        I32Store16 { memarg } => write(memarg, 2, Type::I32),
+        I32Store { memarg } => write(memarg, 4, Type::I32),
+        I64Store8 { memarg } => write(memarg, 1, Type::I64),
+        I64Store16 { memarg } => write(memarg, 2, Type::I64),
+        I64Store32 { memarg } => write(memarg, 4, Type::I64),
+        I64Store { memarg } => write(memarg, 8, Type::I64),
+        F32Store { memarg } => write(memarg, 4, Type::F32),
+        F64Store { memarg } => write(memarg, 8, Type::F64),
+        _ => None,
+    }
+}
+
+impl FunctionWatchpoints {
+    fn value_global(&self, ty: Type) -> u32 {
+        let global = match ty {
+            Type::I32 => self.globals.value_i32,
+            Type::I64 => self.globals.value_i64,
+            Type::F32 => self.globals.value_f32,
+            _ => self.globals.value_f64,
+        };
+        global.as_u32()
+    }
+}
+
+impl FunctionMiddleware for FunctionWatchpoints {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> WpResult<()> {
+        let (kind, memarg, size, value_ty) = match memory_access(&operator) {
+            Some(access) => access,
+            None => {
+                state.push_operator(operator);
+                return Ok(());
+            }
+        };
+        let watched = self
+            .watchpoints
+            .iter()
+            .filter(|watchpoint| watchpoint.kind.watches(kind))
+            .map(|watchpoint| watchpoint.range.clone())
+            .collect::<Vec<_>>();
+        if watched.is_empty() {
+            state.push_operator(operator);
+            return Ok(());
+        }
+
+        let address = self.globals.address.as_u32();
+        let effective_address = self.globals.effective_address.as_u32();
+        let hit_kind = if kind == WatchKind::Read { 1 } else { 2 };
+        // Save the operands, and compute the first accessed byte without overflow.
+        if let Some(ty) = value_ty {
+            state.push_operator(Operator::GlobalSet {
+                global_index: self.value_global(ty),
+            });
+        }
+        state.extend(&[
+            Operator::GlobalSet {
+                global_index: address,
+            },
+            Operator::GlobalGet {
+                global_index: address,
+            },
+            Operator::I64ExtendI32U,
+            Operator::I64Const {
+                value: i64::from(memarg.offset),
+            },
+            Operator::I64Add,
+            Operator::GlobalSet {
+                global_index: effective_address,
+            },
+        ]);
+
+        for range in watched {
+            state.extend(&[
+                // if effective_address < range.end && effective_address + size > range.start
+                Operator::GlobalGet {
+                    global_index: effective_address,
+                },
+                Operator::I64Const {
+                    value: range.end as i64,
+                },
+                Operator::I64LtU,
+                Operator::GlobalGet {
+                    global_index: effective_address,
+                },
+                Operator::I64Const { value: size as i64 },
+                Operator::I64Add,
+                Operator::I64Const {
+                    value: range.start as i64,
+                },
+                Operator::I64GtU,
+                Operator::I32And,
+                Operator::If {
+                    ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+                },
+                Operator::GlobalGet {
+                    global_index: effective_address,
+                },
+                Operator::GlobalSet {
+                    global_index: self.globals.hit_address.as_u32(),
+                },
+                Operator::I32Const { value: hit_kind },
+                Operator::GlobalSet {
+                    global_index: self.globals.hit_kind.as_u32(),
+                },
+                Operator::Unreachable,
+                Operator::End,
+            ]);
+        }
+
+        // Restore the operands and make the access.
+        state.push_operator(Operator::GlobalGet {
+            global_index: address,
+        });
+        if let Some(ty) = value_ty {
+            state.push_operator(Operator::GlobalGet {
+                global_index: self.value_global(ty),
+            });
+        }
+        state.push_operator(operator);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use crate::error::Error;
+use crate::instance::{Imports, Instance};
+use std::sync::Arc;
+use wasmer::wasmparser::Operator;
+use wasmer::{CompilerConfig, Cranelift, Store, JIT};
+use wasmer_middlewares::Metering;
+
+/// An engine compiling and running WebAssembly modules.
+///
+/// Cloning an engine is cheap, and the clones share their modules.
+#[derive(Debug, Clone)]
+pub struct Engine {
+    store: Store,
+}
+
+impl Engine {
+    /// Creates an engine compiling the modules without metering.
+    pub fn new() -> Self {
+        Self::with_compiler(Cranelift::default())
+    }
+
+    /// Creates an engine compiling the modules with metering: every
+    /// instance starts with `initial_points`, and each operator it executes
+    /// costs one point. The execution traps when there are no points left.
+    pub fn metered(initial_points: u64) -> Self {
+        let mut compiler = Cranelift::default();
+        compiler.push_middleware(Arc::new(metering(initial_points)));
+        Self::with_compiler(compiler)
+    }
+
+    /// Creates an engine that can't compile modules, but only deserialize
+    /// them, like the metered ones.
+    pub fn headless() -> Self {
+        Self {
+            store: Store::new(&JIT::headless().engine()),
+        }
+    }
+
+    fn with_compiler(compiler: impl CompilerConfig + 'static) -> Self {
+        Self {
+            store: Store::new(&JIT::new(compiler).engine()),
+        }
+    }
+
+    /// Compiles a module from its WebAssembly binary or text.
+    pub fn compile(&self, wasm: &[u8]) -> Result<Module, Error> {
+        let module = wasmer::Module::new(&self.store, wasm)
+            .map_err(|error| Error::Compile(error.to_string()))?;
+        Ok(Module { module })
+    }
+
+    /// Deserializes a module serialized with [`Module::serialize`].
+    ///
+    /// # Safety
+    ///
+    /// The bytes must come from [`Module::serialize`], with the same
+    /// version of this crate. They contain machine code that is run
+    /// without being validated.
+    pub unsafe fn deserialize(&self, bytes: &[u8]) -> Result<Module, Error> {
+        let module = wasmer::Module::deserialize(&self.store, bytes)
+            .map_err(|error| Error::Deserialize(error.to_string()))?;
+        Ok(Module { module })
+    }
+
+    /// Instantiates `module`, with `imports` providing its imports.
+    pub fn instantiate(&self, module: &Module, imports: &Imports) -> Result<Instance, Error> {
+        Instance::new(&self.store, &module.module, imports)
+    }
+}
+
+/// The metering of the metered engines, which also reads and writes the
+/// points of their instances.
+pub(crate) fn metering(initial_points: u64) -> Metering<fn(&Operator) -> u64> {
+    let cost_function: fn(&Operator) -> u64 = |_| 1;
+    Metering::new(initial_points, cost_function)
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A compiled WebAssembly module.
+#[derive(Debug, Clone)]
+pub struct Module {
+    module: wasmer::Module,
+}
+
+impl Module {
+    /// Serializes the module, to deserialize it later with
+    /// [`Engine::deserialize`] without compiling it again.
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        self.module
+            .serialize()
+            .map_err(|error| Error::Serialize(error.to_string()))
+    }
+}
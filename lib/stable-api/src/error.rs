@@ -0,0 +1,31 @@
+use thiserror::Error;
+
+/// An error of the stable API.
+///
+/// The errors of the engine are described by their message only, which is not part of the
+/// stability guarantees.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The module can't be compiled.
+    #[error("compilation failed: {0}")]
+    Compile(String),
+    /// The module can't be serialized.
+    #[error("serialization failed: {0}")]
+    Serialize(String),
+    /// The serialized module can't be deserialized.
+    #[error("deserialization failed: {0}")]
+    Deserialize(String),
+    /// The module can't be instantiated, or its start function failed.
+    #[error("instantiation failed: {0}")]
+    Instantiate(String),
+    /// The export doesn't exist, or has an unexpected type.
+    #[error("export error: {0}")]
+    Export(String),
+    /// The call trapped, or a host function it called failed.
+    #[error("call failed: {0}")]
+    Call(String),
+    /// The module isn't metered.
+    #[error("the module is not metered")]
+    NotMetered,
+}
@@ -0,0 +1,173 @@
+use crate::engine::metering;
+use crate::error::Error;
+use crate::value::{Value, ValueType};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use wasmer::{Exports, Function, FunctionType, ImportObject, RuntimeError, Store, Type};
+
+type HostFunction = dyn Fn(&[Value]) -> Result<Vec<Value>, String> + Send + Sync;
+
+/// The host functions imported by the modules.
+///
+/// The same `Imports` can instantiate many modules, with any [`Engine`].
+///
+/// [`Engine`]: crate::Engine
+#[derive(Clone, Default)]
+pub struct Imports {
+    functions: Vec<Import>,
+}
+
+#[derive(Clone)]
+struct Import {
+    namespace: String,
+    name: String,
+    params: Vec<ValueType>,
+    results: Vec<ValueType>,
+    function: Arc<HostFunction>,
+}
+
+impl Imports {
+    /// Creates an empty set of imports.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the host function `namespace.name`, taking `params` and
+    /// returning `results`.
+    ///
+    /// The function is called with arguments of the types of `params`, and
+    /// must return values of the types of `results`. An error makes the
+    /// call of the guest fail with [`Error::Call`].
+    pub fn function<F>(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        params: &[ValueType],
+        results: &[ValueType],
+        function: F,
+    ) -> &mut Self
+    where
+        F: Fn(&[Value]) -> Result<Vec<Value>, String> + Send + Sync + 'static,
+    {
+        self.functions.push(Import {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            params: params.to_vec(),
+            results: results.to_vec(),
+            function: Arc::new(function),
+        });
+        self
+    }
+
+    /// Adapts the imports to the engine, creating the host functions in
+    /// `store`.
+    fn to_import_object(&self, store: &Store) -> ImportObject {
+        let mut namespaces = BTreeMap::<&str, Exports>::new();
+        for import in self.functions.iter() {
+            let ty = FunctionType::new(
+                import
+                    .params
+                    .iter()
+                    .map(|ty| ty.to_type())
+                    .collect::<Vec<Type>>(),
+                import
+                    .results
+                    .iter()
+                    .map(|ty| ty.to_type())
+                    .collect::<Vec<Type>>(),
+            );
+            let function = import.function.clone();
+            let function = Function::new(store, &ty, move |args| {
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        Value::from_val(arg)
+                            .ok_or_else(|| RuntimeError::new("unsupported argument type"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let results = function(&args).map_err(RuntimeError::new)?;
+                Ok(results.into_iter().map(Value::to_val).collect())
+            });
+            namespaces
+                .entry(import.namespace.as_str())
+                .or_insert_with(Exports::new)
+                .insert(import.name.as_str(), function);
+        }
+        let mut import_object = ImportObject::new();
+        for (namespace, exports) in namespaces {
+            import_object.register(namespace, exports);
+        }
+        import_object
+    }
+}
+
+impl fmt::Debug for Imports {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                self.functions
+                    .iter()
+                    .map(|import| format!("{}.{}", import.namespace, import.name)),
+            )
+            .finish()
+    }
+}
+
+/// An instance of a [`Module`], created with [`Engine::instantiate`].
+///
+/// [`Module`]: crate::Module
+/// [`Engine::instantiate`]: crate::Engine::instantiate
+#[derive(Debug, Clone)]
+pub struct Instance {
+    instance: wasmer::Instance,
+}
+
+impl Instance {
+    pub(crate) fn new(
+        store: &Store,
+        module: &wasmer::Module,
+        imports: &Imports,
+    ) -> Result<Self, Error> {
+        let instance = wasmer::Instance::new(module, &imports.to_import_object(store))
+            .map_err(|error| Error::Instantiate(error.to_string()))?;
+        Ok(Self { instance })
+    }
+
+    /// Calls the exported function `name` with `args`, and returns its
+    /// results.
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Vec<Value>, Error> {
+        let function = self
+            .instance
+            .exports
+            .get_function(name)
+            .map_err(|error| Error::Export(error.to_string()))?;
+        let args = args.iter().copied().map(Value::to_val).collect::<Vec<_>>();
+        let results = function
+            .call(&args)
+            .map_err(|error| Error::Call(error.message()))?;
+        results
+            .iter()
+            .map(|result| {
+                Value::from_val(result).ok_or_else(|| {
+                    Error::Export(format!("`{}` returns a value of an unsupported type", name))
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the points left to the instance of a metered module.
+    pub fn remaining_points(&self) -> Result<u64, Error> {
+        metering(0)
+            .try_get_remaining_points(&self.instance)
+            .map_err(|_| Error::NotMetered)
+    }
+
+    /// Sets the points left to the instance of a metered module, which can
+    /// run again if it ran out of points.
+    pub fn set_remaining_points(&self, points: u64) -> Result<(), Error> {
+        metering(0)
+            .try_set_remaining_points(&self.instance, points)
+            .map_err(|_| Error::NotMetered)
+    }
+}
@@ -0,0 +1,52 @@
+//! The `wasmer-stable-api` crate is a minimal embedding API of Wasmer with semver
+//! stability guarantees.
+//!
+//! It covers what the embedders running metered WebAssembly contracts need: compiling
+//! a [`Module`] with an [`Engine`], serializing it and deserializing it in a headless
+//! engine, instantiating it with [`Imports`], and calling the functions of the
+//! [`Instance`] while getting and setting its remaining points.
+//!
+//! The types of `wasmer` never appear in this API, they are adapted to the types of this
+//! crate instead, so upgrading the engine doesn't change the code of the embedder. A
+//! breaking change of this API is a new major version of the crate, whatever the changes
+//! of the engine.
+//!
+//! # Example
+//!
+//! ```
+//! use wasmer_stable_api::{Engine, Imports, Value, ValueType};
+//!
+//! # fn main() -> Result<(), wasmer_stable_api::Error> {
+//! let engine = Engine::metered(1000);
+//! let module = engine.compile(
+//!     br#"(module
+//!         (import "env" "double" (func $double (param i32) (result i32)))
+//!         (func (export "run") (param i32) (result i32)
+//!             (call $double (local.get 0))))"#,
+//! )?;
+//!
+//! let mut imports = Imports::new();
+//! imports.function("env", "double", &[ValueType::I32], &[ValueType::I32], |args| {
+//!     match args {
+//!         [Value::I32(value)] => Ok(vec![Value::I32(value * 2)]),
+//!         _ => Err("unexpected arguments".to_string()),
+//!     }
+//! });
+//! let instance = engine.instantiate(&module, &imports)?;
+//! assert_eq!(instance.call("run", &[Value::I32(21)])?, vec![Value::I32(42)]);
+//! assert!(instance.remaining_points()? < 1000);
+//! # Ok(())
+//! # }
+//! ```
+
+#![deny(missing_docs, unused_extern_crates)]
+
+mod engine;
+mod error;
+mod instance;
+mod value;
+
+pub use crate::engine::{Engine, Module};
+pub use crate::error::Error;
+pub use crate::instance::{Imports, Instance};
+pub use crate::value::{Value, ValueType};
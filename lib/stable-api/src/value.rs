@@ -0,0 +1,72 @@
+use wasmer::{Type, Val};
+
+/// The type of a [`Value`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    /// A 32-bit integer.
+    I32,
+    /// A 64-bit integer.
+    I64,
+    /// A 32-bit float.
+    F32,
+    /// A 64-bit float.
+    F64,
+}
+
+impl ValueType {
+    /// Adapts the type to the engine.
+    pub(crate) fn to_type(self) -> Type {
+        match self {
+            Self::I32 => Type::I32,
+            Self::I64 => Type::I64,
+            Self::F32 => Type::F32,
+            Self::F64 => Type::F64,
+        }
+    }
+}
+
+/// A value passed to or returned by a function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    /// A 32-bit integer.
+    I32(i32),
+    /// A 64-bit integer.
+    I64(i64),
+    /// A 32-bit float.
+    F32(f32),
+    /// A 64-bit float.
+    F64(f64),
+}
+
+impl Value {
+    /// Returns the type of the value.
+    pub fn ty(&self) -> ValueType {
+        match self {
+            Self::I32(_) => ValueType::I32,
+            Self::I64(_) => ValueType::I64,
+            Self::F32(_) => ValueType::F32,
+            Self::F64(_) => ValueType::F64,
+        }
+    }
+
+    /// Adapts a value of the engine, if it has a type supported by this API.
+    pub(crate) fn from_val(val: &Val) -> Option<Self> {
+        match val {
+            Val::I32(value) => Some(Self::I32(*value)),
+            Val::I64(value) => Some(Self::I64(*value)),
+            Val::F32(value) => Some(Self::F32(*value)),
+            Val::F64(value) => Some(Self::F64(*value)),
+            _ => None,
+        }
+    }
+
+    /// Adapts the value to the engine.
+    pub(crate) fn to_val(self) -> Val {
+        match self {
+            Self::I32(value) => Val::I32(value),
+            Self::I64(value) => Val::I64(value),
+            Self::F32(value) => Val::F32(value),
+            Self::F64(value) => Val::F64(value),
+        }
+    }
+}
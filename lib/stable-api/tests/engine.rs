@@ -0,0 +1,53 @@
+use wasmer_stable_api::{Engine, Error, Imports, Value};
+
+const LOOP: &[u8] = br#"(module
+    (func (export "count") (param i32) (result i32)
+        (local $i i32)
+        (loop $continue
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br_if $continue (i32.lt_u (local.get $i) (local.get 0))))
+        (local.get $i)))"#;
+
+#[test]
+fn metered_modules_run_in_headless_engines() -> Result<(), Error> {
+    let module = Engine::metered(10_000).compile(LOOP)?;
+    let serialized = module.serialize()?;
+
+    let engine = Engine::headless();
+    assert!(matches!(engine.compile(LOOP), Err(Error::Compile(_))));
+    let module = unsafe { engine.deserialize(&serialized)? };
+    let instance = engine.instantiate(&module, &Imports::new())?;
+    assert_eq!(
+        instance.call("count", &[Value::I32(10)])?,
+        vec![Value::I32(10)]
+    );
+    let remaining_points = instance.remaining_points()?;
+    assert!(remaining_points < 10_000);
+
+    instance.set_remaining_points(10)?;
+    assert!(matches!(
+        instance.call("count", &[Value::I32(1000)]),
+        Err(Error::Call(_))
+    ));
+
+    // The instance runs again once it's given new points.
+    instance.set_remaining_points(10_000)?;
+    assert_eq!(
+        instance.call("count", &[Value::I32(10)])?,
+        vec![Value::I32(10)]
+    );
+    Ok(())
+}
+
+#[test]
+fn unmetered_modules_have_no_points() -> Result<(), Error> {
+    let engine = Engine::new();
+    let module = engine.compile(LOOP)?;
+    let instance = engine.instantiate(&module, &Imports::new())?;
+    assert_eq!(instance.remaining_points(), Err(Error::NotMetered));
+    assert!(matches!(
+        instance.call("missing", &[]),
+        Err(Error::Export(_))
+    ));
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use crate::vmcontext::VMGlobalDefinition;
 use std::cell::UnsafeCell;
 use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Mutex;
 use thiserror::Error;
 use wasmer_types::{GlobalType, Mutability, Type, Value};
@@ -11,7 +12,8 @@ pub struct Global {
     ty: GlobalType,
     // TODO: this box may be unnecessary
     vm_global_definition: Box<UnsafeCell<VMGlobalDefinition>>,
-    // used to synchronize gets/sets
+    // used to synchronize the gets/sets of the vectors, the numbers being
+    // accessed atomically
     lock: Mutex<()>,
 }
 
@@ -20,7 +22,7 @@ pub struct Global {
 /// TODO: look into other reasons that make something not `Send`
 unsafe impl Send for Global {}
 /// # Safety
-/// This is safe to share between threads because it uses a `Mutex` internally.
+/// This is safe to share between threads because it uses atomics and a `Mutex` internally.
 unsafe impl Sync for Global {}
 
 /// Error type describing things that can go wrong when operating on Wasm Globals.
@@ -65,17 +67,16 @@ impl Global {
 
     /// Get a value from the global.
     pub fn get<T>(&self) -> Value<T> {
-        let _global_guard = self.lock.lock().unwrap();
-        unsafe {
-            let definition = &*self.vm_global_definition.get();
-            match self.ty().ty {
-                Type::I32 => Value::I32(definition.to_i32()),
-                Type::I64 => Value::I64(definition.to_i64()),
-                Type::F32 => Value::F32(definition.to_f32()),
-                Type::F64 => Value::F64(definition.to_f64()),
-                Type::V128 => Value::V128(definition.to_u128()),
-                _ => unimplemented!("Global::get for {:?}", self.ty),
+        match self.ty().ty {
+            Type::I32 => Value::I32(self.atomic_u32().load(Ordering::SeqCst) as i32),
+            Type::I64 => Value::I64(self.atomic_u64().load(Ordering::SeqCst) as i64),
+            Type::F32 => Value::F32(f32::from_bits(self.atomic_u32().load(Ordering::SeqCst))),
+            Type::F64 => Value::F64(f64::from_bits(self.atomic_u64().load(Ordering::SeqCst))),
+            Type::V128 => {
+                let _global_guard = self.lock.lock().unwrap();
+                unsafe { Value::V128((*self.vm_global_definition.get()).to_u128()) }
             }
+            _ => unimplemented!("Global::get for {:?}", self.ty),
         }
     }
 
@@ -84,7 +85,6 @@ impl Global {
     /// # Safety
     /// The caller should check that the `val` comes from the same store as this global.
     pub unsafe fn set<T>(&self, val: Value<T>) -> Result<(), GlobalError> {
-        let _global_guard = self.lock.lock().unwrap();
         if self.ty().mutability != Mutability::Var {
             return Err(GlobalError::ImmutableGlobalCannotBeSet);
         }
@@ -99,21 +99,38 @@ impl Global {
 
     /// Set a value from the global (unchecked)
     ///
+    /// The numbers are stored atomically, so that the instances sharing
+    /// this global see either the previous value or the new one.
+    ///
     /// # Safety
     /// The caller should check that the `val` comes from the same store as this global.
-    /// The caller should also ensure that this global is synchronized. Otherwise, use
-    /// `set` instead.
     pub unsafe fn set_unchecked<T>(&self, val: Value<T>) -> Result<(), GlobalError> {
-        // ideally we'd use atomics for the global value rather than needing to lock it
-        let definition = &mut *self.vm_global_definition.get();
         match val {
-            Value::I32(i) => *definition.as_i32_mut() = i,
-            Value::I64(i) => *definition.as_i64_mut() = i,
-            Value::F32(f) => *definition.as_f32_mut() = f,
-            Value::F64(f) => *definition.as_f64_mut() = f,
-            Value::V128(x) => *definition.as_bytes_mut() = x.to_ne_bytes(),
+            Value::I32(i) => self.atomic_u32().store(i as u32, Ordering::SeqCst),
+            Value::I64(i) => self.atomic_u64().store(i as u64, Ordering::SeqCst),
+            Value::F32(f) => self.atomic_u32().store(f.to_bits(), Ordering::SeqCst),
+            Value::F64(f) => self.atomic_u64().store(f.to_bits(), Ordering::SeqCst),
+            Value::V128(x) => {
+                // The generated code reads vectors without synchronization:
+                // only the host accesses are serialized.
+                let _global_guard = self.lock.lock().unwrap();
+                let definition = &mut *self.vm_global_definition.get();
+                *definition.as_bytes_mut() = x.to_ne_bytes();
+            }
             _ => unimplemented!("Global::set for {:?}", val.ty()),
         }
         Ok(())
     }
+
+    /// The 32 bits of the `i32` and `f32` values, accessed atomically.
+    fn atomic_u32(&self) -> &AtomicU32 {
+        // The definition is aligned on 16 bytes, and the values are stored
+        // in its first bytes.
+        unsafe { &*(self.vmglobal().as_ptr() as *const AtomicU32) }
+    }
+
+    /// The 64 bits of the `i64` and `f64` values, accessed atomically.
+    fn atomic_u64(&self) -> &AtomicU64 {
+        unsafe { &*(self.vmglobal().as_ptr() as *const AtomicU64) }
+    }
 }
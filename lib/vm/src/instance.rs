@@ -10,9 +10,9 @@
 use crate::export::VMExport;
 use crate::global::Global;
 use crate::imports::Imports;
-use crate::memory::{Memory, MemoryError};
+use crate::memory::{Memory, MemoryError, MemoryStyle};
 use crate::table::Table;
-use crate::trap::{catch_traps, init_traps, Trap, TrapCode};
+use crate::trap::{catch_traps, init_traps, MemoryAccessFault, Trap, TrapCode};
 use crate::vmcontext::{
     VMBuiltinFunctionsArray, VMCallerCheckedAnyfunc, VMContext, VMFunctionBody,
     VMFunctionEnvironment, VMFunctionImport, VMFunctionKind, VMGlobalDefinition, VMGlobalImport,
@@ -26,12 +26,12 @@ use more_asserts::assert_lt;
 use std::alloc::{self, Layout};
 use std::any::Any;
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::ffi;
 use std::fmt;
 use std::ptr::NonNull;
-use std::sync::{atomic, Arc};
+use std::sync::{atomic, Arc, RwLock};
 use std::{mem, ptr, slice};
 use wasmer_types::entity::{packed_option::ReservedValue, BoxedSlice, EntityRef, PrimaryMap};
 use wasmer_types::{
@@ -40,6 +40,29 @@ use wasmer_types::{
     SignatureIndex, TableIndex, TableInitializer,
 };
 
+lazy_static::lazy_static! {
+    /// The `VMContext`s of the live instances.
+    ///
+    /// Host functions have their environment where WebAssembly functions
+    /// have their `VMContext`, so the `vmctx` of a function is checked
+    /// against this set before it's read as a `VMContext`.
+    static ref LIVE_VMCTXS: RwLock<HashSet<usize>> = RwLock::new(HashSet::new());
+}
+
+/// Runs `f` with the instance of `vmctx`, if it's the `VMContext` of a
+/// live instance, and not the environment of a host function.
+pub(crate) fn with_live_instance<R>(
+    vmctx: *mut VMContext,
+    f: impl FnOnce(&Instance) -> R,
+) -> Option<R> {
+    let live_vmctxs = LIVE_VMCTXS.read().unwrap();
+    if vmctx.is_null() || !live_vmctxs.contains(&(vmctx as usize)) {
+        return None;
+    }
+    // Safety: the instance can't be deallocated while the set is locked.
+    Some(f(unsafe { (*vmctx).instance() }))
+}
+
 /// The function pointer to call with data and an [`Instance`] pointer to
 /// finish initializing the host env.
 pub type ImportInitializerFuncPtr =
@@ -88,6 +111,11 @@ pub(crate) struct Instance {
     /// get removed. A missing entry is considered equivalent to an empty slice.
     passive_data: RefCell<HashMap<DataIndex, Arc<[u8]>>>,
 
+    /// Deferred passive data segments from our module that have not been
+    /// loaded nor dropped yet. They are loaded into `passive_data` the
+    /// first time `memory.init` uses them.
+    deferred_data: RefCell<HashSet<DataIndex>>,
+
     /// Hosts can store arbitrary per-instance information here.
     host_state: Box<dyn Any>,
 
@@ -102,6 +130,13 @@ pub(crate) struct Instance {
     /// functions from other Wasm modules.
     import_initializers: ImportInitializerThunks,
 
+    /// The call counters of the local functions, incremented by the
+    /// compiled code when the module was compiled to count the calls.
+    /// They are only kept alive here, the compiled code accesses them
+    /// through the `vmctx`.
+    #[allow(dead_code)]
+    call_counters: Option<Arc<[atomic::AtomicU64]>>,
+
     /// Additional context used by compiled WebAssembly code. This
     /// field is last, and represents a dynamically-sized array that
     /// extends beyond the nominal end of the struct (similar to a
@@ -135,7 +170,7 @@ impl Instance {
         &self.module
     }
 
-    fn module_ref(&self) -> &ModuleInfo {
+    pub(crate) fn module_ref(&self) -> &ModuleInfo {
         &*self.module
     }
 
@@ -231,6 +266,37 @@ impl Instance {
         }
     }
 
+    /// Returns the details of a faulting access at the native address
+    /// `addr`, if it's within the reservation of one of the memories.
+    pub(crate) fn memory_access_fault(&self, addr: usize) -> Option<MemoryAccessFault> {
+        self.module.memories.keys().find_map(|index| {
+            let memory = match self.module.local_memory_index(index) {
+                Some(local_index) => &self.memories[local_index],
+                None => &self.imported_memory(index).from,
+            };
+            let definition = self.get_memory(index);
+            let reserved = match memory.style() {
+                MemoryStyle::Static {
+                    bound,
+                    offset_guard_size,
+                } => bound.bytes().0 as u64 + offset_guard_size,
+                MemoryStyle::Dynamic { offset_guard_size } => {
+                    definition.current_length as u64 + offset_guard_size
+                }
+            };
+            let address = (addr as u64).checked_sub(definition.base as u64)?;
+            if address >= reserved {
+                return None;
+            }
+            Some(MemoryAccessFault {
+                memory_index: index,
+                address,
+                access_size: None,
+                memory_size: definition.current_length as u64,
+            })
+        })
+    }
+
     /// Return the indexed `VMMemoryDefinition`.
     fn memory(&self, index: LocalMemoryIndex) -> VMMemoryDefinition {
         unsafe { *self.memory_ptr(index).as_ref() }
@@ -285,6 +351,11 @@ impl Instance {
         unsafe { self.vmctx_plus_offset(self.offsets.vmctx_builtin_functions_begin()) }
     }
 
+    /// Return a pointer to the pointer to the call counters.
+    fn call_counters_ptr(&self) -> *mut *mut u64 {
+        unsafe { self.vmctx_plus_offset(self.offsets.vmctx_call_counters()) }
+    }
+
     /// Return a reference to the vmctx used by compiled wasm code.
     fn vmctx(&self) -> &VMContext {
         &self.vmctx
@@ -634,6 +705,7 @@ impl Instance {
         // https://webassembly.github.io/bulk-memory-operations/core/exec/instructions.html#exec-memory-init
 
         let memory = self.get_memory(memory_index);
+        self.load_deferred_data(data_index, src, len)?;
         let passive_data = self.passive_data.borrow();
         let data = passive_data
             .get(&data_index)
@@ -660,10 +732,51 @@ impl Instance {
         Ok(())
     }
 
+    /// Loads the given deferred data segment into `passive_data`, if it
+    /// isn't loaded yet and the source range is within its bounds.
+    fn load_deferred_data(&self, data_index: DataIndex, src: u32, len: u32) -> Result<(), Trap> {
+        if !self.deferred_data.borrow().contains(&data_index) {
+            return Ok(());
+        }
+        let expected_len = self.module.deferred_data[&data_index];
+        if src
+            .checked_add(len)
+            .map_or(true, |n| n as usize > expected_len)
+        {
+            // `memory_init` traps without needing the data.
+            return Ok(());
+        }
+
+        let index = data_index.index();
+        let loader = self.module.data_loader.as_ref().ok_or_else(|| {
+            Trap::new_from_user(format!("no loader for the deferred data segment {}", index).into())
+        })?;
+        let data = loader.0.load(data_index).map_err(|message| {
+            Trap::new_from_user(
+                format!("cannot load the data segment {}: {}", index, message).into(),
+            )
+        })?;
+        if data.len() != expected_len {
+            return Err(Trap::new_from_user(
+                format!(
+                    "the loaded data segment {} has {} bytes instead of {}",
+                    index,
+                    data.len(),
+                    expected_len
+                )
+                .into(),
+            ));
+        }
+        self.deferred_data.borrow_mut().remove(&data_index);
+        self.passive_data.borrow_mut().insert(data_index, data);
+        Ok(())
+    }
+
     /// Drop the given data segment, truncating its length to zero.
     pub(crate) fn data_drop(&self, data_index: DataIndex) {
         let mut passive_data = self.passive_data.borrow_mut();
         passive_data.remove(&data_index);
+        self.deferred_data.borrow_mut().remove(&data_index);
     }
 
     /// Get a table by index regardless of whether it is locally-defined or an
@@ -820,6 +933,10 @@ impl InstanceAllocator {
     /// dropped and deallocated.
     unsafe fn deallocate_instance(&mut self) {
         let instance_ptr = self.instance.as_ptr();
+        LIVE_VMCTXS
+            .write()
+            .unwrap()
+            .remove(&(self.as_ref().vmctx_ptr() as usize));
 
         ptr::drop_in_place(instance_ptr);
         std::alloc::dealloc(instance_ptr as *mut u8, self.instance_layout);
@@ -844,6 +961,16 @@ impl InstanceAllocator {
     unsafe fn as_mut<'a>(&'a mut self) -> &'a mut Instance {
         self.instance.as_mut()
     }
+
+    /// Return a reference to the module of the `Instance`.
+    pub fn module_ref(&self) -> &ModuleInfo {
+        self.as_ref().module_ref()
+    }
+
+    /// Return a raw pointer to the vmctx of the `Instance`.
+    pub fn vmctx_ptr(&self) -> *mut VMContext {
+        self.as_ref().vmctx_ptr()
+    }
 }
 
 /// TODO: Review this super carefully.
@@ -943,6 +1070,21 @@ impl Drop for InstanceAllocator {
     }
 }
 
+/// A weak reference to an `Instance`, telling whether it has been
+/// deallocated, that is whether all its `InstanceAllocator`s (held by
+/// its `InstanceHandle` and its exports) have been dropped.
+#[derive(Debug, Clone)]
+pub struct WeakInstanceRef {
+    strong: std::sync::Weak<atomic::AtomicUsize>,
+}
+
+impl WeakInstanceRef {
+    /// Returns `true` if the instance hasn't been deallocated yet.
+    pub fn is_alive(&self) -> bool {
+        self.strong.strong_count() > 0
+    }
+}
+
 /// A handle holding an `InstanceAllocator`, which holds an `Instance`
 /// of a WebAssembly module.
 ///
@@ -994,6 +1136,7 @@ impl InstanceHandle {
     ///   all the local tables.
     /// - The memory at `instance.memories_ptr()` must be initialized with data for
     ///   all the local memories.
+    /// - `call_counters`, if any, must have a counter for each local function.
     #[allow(clippy::too_many_arguments)]
     pub unsafe fn new(
         instance_ptr: NonNull<u8>,
@@ -1008,6 +1151,7 @@ impl InstanceHandle {
         vmshared_signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
         host_state: Box<dyn Any>,
         import_initializers: ImportInitializerThunks,
+        call_counters: Option<Arc<[atomic::AtomicU64]>>,
     ) -> Result<Self, Trap> {
         // `NonNull<u8>` here actually means `NonNull<Instance>`. See
         // `Self::allocate_instance` to understand why.
@@ -1018,7 +1162,17 @@ impl InstanceHandle {
             .map(|m| m.vmglobal())
             .collect::<PrimaryMap<LocalGlobalIndex, _>>()
             .into_boxed_slice();
-        let passive_data = RefCell::new(module.passive_data.clone());
+        let passive_data = RefCell::new(
+            module
+                .passive_data
+                .iter()
+                .map(|(data_index, data)| (*data_index, data.clone()))
+                .collect(),
+        );
+        let deferred_data = RefCell::new(module.deferred_data.keys().copied().collect());
+        let call_counters_ptr = call_counters
+            .as_ref()
+            .map_or(ptr::null_mut(), |counters| counters.as_ptr() as *mut u64);
 
         let handle = {
             let instance_layout = InstanceAllocator::instance_layout(&offsets);
@@ -1033,9 +1187,11 @@ impl InstanceHandle {
                 function_call_trampolines: finished_function_call_trampolines,
                 passive_elements: Default::default(),
                 passive_data,
+                deferred_data,
                 host_state,
                 signal_handler: Cell::new(None),
                 import_initializers,
+                call_counters,
                 vmctx: VMContext {},
             };
 
@@ -1097,6 +1253,7 @@ impl InstanceHandle {
             instance.builtin_functions_ptr() as *mut VMBuiltinFunctionsArray,
             VMBuiltinFunctionsArray::initialized(),
         );
+        ptr::write(instance.call_counters_ptr(), call_counters_ptr);
 
         // Ensure that our signal handlers are ready for action.
         init_traps();
@@ -1106,6 +1263,11 @@ impl InstanceHandle {
         initialize_passive_elements(instance);
         initialize_globals(instance);
 
+        LIVE_VMCTXS
+            .write()
+            .unwrap()
+            .insert(instance.vmctx_ptr() as usize);
+
         Ok(handle)
     }
 
@@ -1114,6 +1276,14 @@ impl InstanceHandle {
         &self.instance
     }
 
+    /// Returns a weak reference to the instance, to find out when it's
+    /// deallocated.
+    pub fn downgrade(&self) -> WeakInstanceRef {
+        WeakInstanceRef {
+            strong: Arc::downgrade(&self.instance.strong),
+        }
+    }
+
     /// Get the locations of where the local `VMMemoryDefinition`s should be stored.
     ///
     /// This function lets us create `Memory` objects on the host with backing
@@ -1212,6 +1382,42 @@ impl InstanceHandle {
         Ok(())
     }
 
+    /// Pre-faults the memory pages covered by the data initializers, by
+    /// writing each page back to itself, and returns the number of
+    /// pages touched.
+    ///
+    /// Initializers out of the memory bounds are ignored.
+    pub fn prefault_data_segments(&self, data_initializers: &[DataInitializer<'_>]) -> usize {
+        let instance = self.instance().as_ref();
+        let page_size = region::page::size();
+        let mut touched = 0;
+        for init in data_initializers {
+            let start = get_memory_init_start(init, instance);
+            unsafe {
+                let mem_slice = get_memory_slice(init, instance);
+                let to_touch = match start
+                    .checked_add(init.data.len())
+                    .and_then(|end| mem_slice.get_mut(start..end))
+                {
+                    Some(to_touch) if !to_touch.is_empty() => to_touch,
+                    _ => continue,
+                };
+                let segment_start = to_touch.as_mut_ptr() as usize;
+                let segment_end = segment_start + to_touch.len();
+                let mut page = segment_start / page_size * page_size;
+                while page < segment_end {
+                    // The first page can start before the segment; touch
+                    // its first byte within the segment.
+                    let byte = page.max(segment_start) as *mut u8;
+                    ptr::write_volatile(byte, ptr::read_volatile(byte));
+                    touched += 1;
+                    page += page_size;
+                }
+            }
+        }
+        touched
+    }
+
     /// Return a reference to the vmctx used by compiled wasm code.
     pub fn vmctx(&self) -> &VMContext {
         self.instance().as_ref().vmctx()
@@ -39,10 +39,17 @@ pub mod libcalls;
 pub use crate::export::*;
 pub use crate::global::*;
 pub use crate::imports::Imports;
-pub use crate::instance::{ImportInitializerFuncPtr, InstanceHandle};
-pub use crate::memory::{LinearMemory, Memory, MemoryError, MemoryStyle};
+pub use crate::instance::{
+    ImportInitializerFuncPtr, InstanceAllocator, InstanceHandle, WeakInstanceRef,
+};
+pub use crate::memory::{
+    LinearMemory, Memory, MemoryError, MemoryGrowEvent, MemoryGrowFailureReason, MemoryStyle,
+};
 pub use crate::mmap::Mmap;
-pub use crate::module::{ExportsIterator, ImportsIterator, ModuleInfo};
+pub use crate::module::{
+    DataLoader, DataSegmentLoader, ExportsIterator, ImportsIterator, MiddlewareExport, ModuleInfo,
+    ModuleNames, ModuleSymbols, MIDDLEWARE_REGISTRY_SECTION,
+};
 pub use crate::probestack::PROBESTACK;
 pub use crate::sig_registry::SignatureRegistry;
 pub use crate::table::{LinearTable, Table, TableStyle};
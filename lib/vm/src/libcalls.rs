@@ -36,10 +36,11 @@
 //!   ```
 
 use crate::probestack::PROBESTACK;
-use crate::trap::{raise_lib_trap, Trap, TrapCode};
+use crate::trap::{raise_lib_trap, resume_panic, Trap, TrapCode};
 use crate::vmcontext::VMContext;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 use wasmer_types::{DataIndex, ElemIndex, LocalMemoryIndex, MemoryIndex, TableIndex};
 
 /// Implementation of f32.ceil
@@ -132,6 +133,15 @@ pub extern "C" fn wasmer_f64_nearest(x: f64) -> f64 {
     }
 }
 
+/// Runs `f`, which may call host code (a custom `Memory` or `Table`), carrying its
+/// panics across the WebAssembly frames instead of unwinding through them.
+unsafe fn catch_host_panic<R>(f: impl FnOnce() -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(panic) => resume_panic(panic),
+    }
+}
+
 /// Implementation of memory.grow for locally-defined 32-bit memories.
 ///
 /// # Safety
@@ -145,10 +155,12 @@ pub unsafe extern "C" fn wasmer_memory32_grow(
     let instance = (&*vmctx).instance();
     let memory_index = LocalMemoryIndex::from_u32(memory_index);
 
-    instance
-        .memory_grow(memory_index, delta)
-        .map(|pages| pages.0)
-        .unwrap_or(u32::max_value())
+    catch_host_panic(|| {
+        instance
+            .memory_grow(memory_index, delta)
+            .map(|pages| pages.0)
+            .unwrap_or(u32::max_value())
+    })
 }
 
 /// Implementation of memory.grow for imported 32-bit memories.
@@ -164,10 +176,12 @@ pub unsafe extern "C" fn wasmer_imported_memory32_grow(
     let instance = (&*vmctx).instance();
     let memory_index = MemoryIndex::from_u32(memory_index);
 
-    instance
-        .imported_memory_grow(memory_index, delta)
-        .map(|pages| pages.0)
-        .unwrap_or(u32::max_value())
+    catch_host_panic(|| {
+        instance
+            .imported_memory_grow(memory_index, delta)
+            .map(|pages| pages.0)
+            .unwrap_or(u32::max_value())
+    })
 }
 
 /// Implementation of memory.size for locally-defined 32-bit memories.
@@ -179,7 +193,7 @@ pub unsafe extern "C" fn wasmer_memory32_size(vmctx: *mut VMContext, memory_inde
     let instance = (&*vmctx).instance();
     let memory_index = LocalMemoryIndex::from_u32(memory_index);
 
-    instance.memory_size(memory_index).0
+    catch_host_panic(|| instance.memory_size(memory_index).0)
 }
 
 /// Implementation of memory.size for imported 32-bit memories.
@@ -194,7 +208,7 @@ pub unsafe extern "C" fn wasmer_imported_memory32_size(
     let instance = (&*vmctx).instance();
     let memory_index = MemoryIndex::from_u32(memory_index);
 
-    instance.imported_memory_size(memory_index).0
+    catch_host_panic(|| instance.imported_memory_size(memory_index).0)
 }
 
 /// Implementation of `table.copy`.
@@ -216,7 +230,7 @@ pub unsafe extern "C" fn wasmer_table_copy(
         let instance = (&*vmctx).instance();
         let dst_table = instance.get_table(dst_table_index);
         let src_table = instance.get_table(src_table_index);
-        dst_table.copy(src_table, dst, src, len)
+        catch_host_panic(|| dst_table.copy(src_table, dst, src, len))
     };
     if let Err(trap) = result {
         raise_lib_trap(trap);
@@ -240,7 +254,7 @@ pub unsafe extern "C" fn wasmer_table_init(
         let table_index = TableIndex::from_u32(table_index);
         let elem_index = ElemIndex::from_u32(elem_index);
         let instance = (&*vmctx).instance();
-        instance.table_init(table_index, elem_index, dst, src, len)
+        catch_host_panic(|| instance.table_init(table_index, elem_index, dst, src, len))
     };
     if let Err(trap) = result {
         raise_lib_trap(trap);
@@ -60,6 +60,34 @@ pub enum MemoryError {
     Generic(String),
 }
 
+/// Why a linear memory could not grow.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MemoryGrowFailureReason {
+    /// The new size would exceed the maximum declared by the memory type.
+    DeclaredMaximum,
+    /// The new size would exceed what WebAssembly can index.
+    IndexRange,
+    /// The host could not allocate (or commit) the memory.
+    AllocationFailed(String),
+    /// The growth was refused by the embedder, for example by a custom
+    /// [`Memory`] implementation limiting memory usage.
+    Denied(String),
+}
+
+/// A failed attempt to grow a linear memory.
+///
+/// The guest always observes these failures as `memory.grow` returning
+/// `-1`; the event lets the host tell them apart.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MemoryGrowEvent {
+    /// The size in pages before the attempt.
+    pub current: Pages,
+    /// The attempted amount to grow by in pages.
+    pub attempted_delta: Pages,
+    /// Why the memory could not grow.
+    pub reason: MemoryGrowFailureReason,
+}
+
 /// Implementation styles for WebAssembly linear memory.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MemoryStyle {
@@ -109,6 +137,11 @@ pub trait Memory: fmt::Debug + Send + Sync {
     /// Grow memory by the specified amount of wasm pages.
     fn grow(&self, delta: Pages) -> Result<Pages, MemoryError>;
 
+    /// Returns the last failed attempt to grow this memory, if any.
+    fn last_grow_failure(&self) -> Option<MemoryGrowEvent> {
+        None
+    }
+
     /// Return a [`VMMemoryDefinition`] for exposing the memory to compiled wasm code.
     ///
     /// The pointer returned in [`VMMemoryDefinition`] must be valid for the lifetime of this memory.
@@ -140,6 +173,9 @@ pub struct LinearMemory {
     // Records whether we're using a bounds-checking strategy which requires
     // handlers to catch trapping accesses.
     pub(crate) needs_signal_handlers: bool,
+
+    /// The last failed attempt to grow this memory.
+    last_grow_failure: Mutex<Option<MemoryGrowEvent>>,
 }
 
 /// A type to help manage who is responsible for the backing memory of them
@@ -283,6 +319,7 @@ impl LinearMemory {
             },
             memory: *memory,
             style: style.clone(),
+            last_grow_failure: Mutex::new(None),
         })
     }
 
@@ -299,34 +336,10 @@ impl LinearMemory {
             }
         }
     }
-}
-
-impl Memory for LinearMemory {
-    /// Returns the type for this memory.
-    fn ty(&self) -> &MemoryType {
-        &self.memory
-    }
-
-    /// Returns the memory style for this memory.
-    fn style(&self) -> &MemoryStyle {
-        &self.style
-    }
-
-    /// Returns the number of allocated wasm pages.
-    fn size(&self) -> Pages {
-        // TODO: investigate this function for race conditions
-        unsafe {
-            let md_ptr = self.get_vm_memory_definition();
-            let md = md_ptr.as_ref();
-            Bytes::from(md.current_length).into()
-        }
-    }
 
-    /// Grow memory by the specified amount of wasm pages.
-    ///
-    /// Returns `None` if memory can't be grown by the specified amount
-    /// of wasm pages.
-    fn grow(&self, delta: Pages) -> Result<Pages, MemoryError> {
+    /// Grow memory by the specified amount of wasm pages, without
+    /// recording failures.
+    fn grow_pages(&self, delta: Pages) -> Result<Pages, MemoryError> {
         let mut mmap_guard = self.mmap.lock().unwrap();
         let mmap = mmap_guard.borrow_mut();
         // Optimization of memory.grow 0 calls.
@@ -405,6 +418,61 @@ impl Memory for LinearMemory {
 
         Ok(prev_pages)
     }
+}
+
+impl Memory for LinearMemory {
+    /// Returns the type for this memory.
+    fn ty(&self) -> &MemoryType {
+        &self.memory
+    }
+
+    /// Returns the memory style for this memory.
+    fn style(&self) -> &MemoryStyle {
+        &self.style
+    }
+
+    /// Returns the number of allocated wasm pages.
+    fn size(&self) -> Pages {
+        // TODO: investigate this function for race conditions
+        unsafe {
+            let md_ptr = self.get_vm_memory_definition();
+            let md = md_ptr.as_ref();
+            Bytes::from(md.current_length).into()
+        }
+    }
+
+    /// Grow memory by the specified amount of wasm pages.
+    ///
+    /// Returns `None` if memory can't be grown by the specified amount
+    /// of wasm pages.
+    fn grow(&self, delta: Pages) -> Result<Pages, MemoryError> {
+        let current = self.size();
+        self.grow_pages(delta).map_err(|error| {
+            let reason = match &error {
+                MemoryError::CouldNotGrow { .. } => match self.maximum {
+                    Some(maximum) if current.0 as u64 + delta.0 as u64 > maximum.0 as u64 => {
+                        MemoryGrowFailureReason::DeclaredMaximum
+                    }
+                    _ => MemoryGrowFailureReason::IndexRange,
+                },
+                MemoryError::Region(message) => {
+                    MemoryGrowFailureReason::AllocationFailed(message.clone())
+                }
+                other => MemoryGrowFailureReason::Denied(other.to_string()),
+            };
+            *self.last_grow_failure.lock().unwrap() = Some(MemoryGrowEvent {
+                current,
+                attempted_delta: delta,
+                reason,
+            });
+            error
+        })
+    }
+
+    /// Returns the last failed attempt to grow this memory, if any.
+    fn last_grow_failure(&self) -> Option<MemoryGrowEvent> {
+        self.last_grow_failure.lock().unwrap().clone()
+    }
 
     /// Return a `VMMemoryDefinition` for exposing the memory to compiled wasm code.
     fn vmmemory(&self) -> NonNull<VMMemoryDefinition> {
@@ -47,6 +47,44 @@ impl Mmap {
         Self::accessible_reserved(rounded_size, rounded_size)
     }
 
+    /// Create a new `Mmap` pointing to at least `size` bytes of page-aligned memory for JIT
+    /// code, mapped with `MAP_JIT` as required by the hardened runtime of macOS.
+    ///
+    /// The memory is read-write, except on aarch64 where it's also executable: the threads
+    /// then switch between writing and executing it with `pthread_jit_write_protect_np`.
+    #[cfg(target_os = "macos")]
+    pub fn jit_with_at_least(size: usize) -> Result<Self, String> {
+        let page_size = region::page::size();
+        let rounded_size = round_up_to_page_size(size, page_size);
+        if rounded_size == 0 {
+            return Ok(Self::new());
+        }
+
+        let protection = if cfg!(target_arch = "aarch64") {
+            libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC
+        } else {
+            libc::PROT_READ | libc::PROT_WRITE
+        };
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                rounded_size,
+                protection,
+                libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_JIT,
+                -1,
+                0,
+            )
+        };
+        if ptr as isize == -1_isize {
+            return Err(io::Error::last_os_error().to_string());
+        }
+
+        Ok(Self {
+            ptr: ptr as usize,
+            len: rounded_size,
+        })
+    }
+
     /// Create a new `Mmap` pointing to `accessible_size` bytes of page-aligned accessible memory,
     /// within a reserved mapping of `mapping_size` bytes. `accessible_size` and `mapping_size`
     /// must be native page-size multiples.
@@ -227,6 +265,65 @@ impl Mmap {
         Ok(())
     }
 
+    /// Map the first `len` bytes of `file` over the first `len` bytes of the memory,
+    /// readable and executable. `len` must be a native page-size multiple.
+    ///
+    /// The mapping is private, but never written: the pages are the ones of the page
+    /// cache, shared with the other mappings of the file, in this or in other processes.
+    #[cfg(not(target_os = "windows"))]
+    pub fn map_file_executable(&mut self, file: &std::fs::File, len: usize) -> Result<(), String> {
+        use std::os::unix::io::AsRawFd;
+        let page_size = region::page::size();
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+
+        if len == 0 {
+            return Ok(());
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                self.ptr as *mut libc::c_void,
+                len,
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE | libc::MAP_FIXED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr as isize == -1_isize {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+
+    /// Make the first `len` bytes of the memory readable and executable, with the
+    /// branch target identification enforced: the indirect branches to them fault
+    /// unless they land on a BTI instruction. `len` must be a native page-size multiple.
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    pub fn enforce_branch_targets(&mut self, len: usize) -> Result<(), String> {
+        /// The `PROT_BTI` protection flag of arm64 Linux.
+        const PROT_BTI: libc::c_int = 0x10;
+
+        let page_size = region::page::size();
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+
+        if len == 0 {
+            return Ok(());
+        }
+        let result = unsafe {
+            libc::mprotect(
+                self.ptr as *mut libc::c_void,
+                len,
+                libc::PROT_READ | libc::PROT_EXEC | PROT_BTI,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+
     /// Return the allocated memory as a slice of u8.
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
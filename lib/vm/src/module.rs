@@ -6,7 +6,7 @@
 
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::iter::ExactSizeIterator;
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
@@ -39,8 +39,39 @@ impl Default for ModuleId {
     }
 }
 
+/// Loads the passive data segments deferred with
+/// [`ModuleInfo::defer_passive_data`], when `memory.init` first uses them.
+pub trait DataSegmentLoader: Send + Sync {
+    /// Returns the content of the passive data segment `data_index`.
+    fn load(&self, data_index: DataIndex) -> Result<Arc<[u8]>, String>;
+}
+
+impl<F> DataSegmentLoader for F
+where
+    F: Fn(DataIndex) -> Result<Arc<[u8]>, String> + Send + Sync,
+{
+    fn load(&self, data_index: DataIndex) -> Result<Arc<[u8]>, String> {
+        self(data_index)
+    }
+}
+
+/// A [`DataSegmentLoader`] attached to a [`ModuleInfo`].
+#[derive(Clone)]
+pub struct DataLoader(pub Arc<dyn DataSegmentLoader>);
+
+impl fmt::Debug for DataLoader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataLoader").finish()
+    }
+}
+
 /// A translated WebAssembly module, excluding the function bodies and
 /// memory initializers.
+///
+/// All its maps are ordered, by index or by insertion, so that the same
+/// module always serializes to the same bytes. The items added by the
+/// middlewares, like the exports, are inserted after the ones of the
+/// module.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModuleInfo {
     /// A unique identifier (within this process) for this module.
@@ -53,6 +84,10 @@ pub struct ModuleInfo {
     /// The name of this wasm module, often found in the wasm file.
     pub name: Option<String>,
 
+    /// The BLAKE3 hash of the wasm binary the module was compiled from,
+    /// if known.
+    pub hash: Option<[u8; 32]>,
+
     /// Imported entities with the (module, field, index_of_the_import)
     ///
     /// Keeping the `index_of_the_import` is important, as there can be
@@ -70,16 +105,30 @@ pub struct ModuleInfo {
     pub table_initializers: Vec<TableInitializer>,
 
     /// WebAssembly passive elements.
-    pub passive_elements: HashMap<ElemIndex, Box<[FunctionIndex]>>,
+    pub passive_elements: BTreeMap<ElemIndex, Box<[FunctionIndex]>>,
 
     /// WebAssembly passive data segments.
-    pub passive_data: HashMap<DataIndex, Arc<[u8]>>,
+    pub passive_data: BTreeMap<DataIndex, Arc<[u8]>>,
+
+    /// The length of the passive data segments deferred with
+    /// [`ModuleInfo::defer_passive_data`].
+    pub deferred_data: BTreeMap<DataIndex, usize>,
+
+    /// Loads the deferred passive data segments.
+    ///
+    /// We skip serialization/deserialization of this field, as it
+    /// must be provided by the embedder.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub data_loader: Option<DataLoader>,
 
     /// WebAssembly global initializers.
     pub global_initializers: PrimaryMap<LocalGlobalIndex, GlobalInit>,
 
     /// WebAssembly function names.
-    pub function_names: HashMap<FunctionIndex, String>,
+    pub function_names: BTreeMap<FunctionIndex, String>,
+
+    /// The other names of the extended name section.
+    pub names: ModuleNames,
 
     /// WebAssembly function signatures.
     pub signatures: PrimaryMap<SignatureIndex, FunctionType>,
@@ -102,6 +151,10 @@ pub struct ModuleInfo {
     /// The data for each CustomSection in the module.
     pub custom_sections_data: PrimaryMap<CustomSectionIndex, Arc<[u8]>>,
 
+    /// The globals exported by the middlewares, see
+    /// [`ModuleInfo::register_middleware_export`].
+    pub middleware_exports: Vec<MiddlewareExport>,
+
     /// Number of imported functions in the module.
     pub num_imported_functions: usize,
 
@@ -115,20 +168,100 @@ pub struct ModuleInfo {
     pub num_imported_globals: usize,
 }
 
+/// The names of the extended name section, besides the module and
+/// function names.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModuleNames {
+    /// The names of the locals (parameters included), by function.
+    pub locals: BTreeMap<FunctionIndex, BTreeMap<u32, String>>,
+
+    /// The names of the labels, by function and in the order of the
+    /// `block`, `loop` and `if` instructions of its body.
+    pub labels: BTreeMap<FunctionIndex, BTreeMap<u32, String>>,
+
+    /// The names of the function types.
+    pub types: BTreeMap<SignatureIndex, String>,
+
+    /// The names of the tables.
+    pub tables: BTreeMap<TableIndex, String>,
+
+    /// The names of the linear memories.
+    pub memories: BTreeMap<MemoryIndex, String>,
+
+    /// The names of the globals.
+    pub globals: BTreeMap<GlobalIndex, String>,
+}
+
+/// The names and debug information of a module, stripped from it with
+/// [`ModuleInfo::strip_symbols`] so they can be stored separately from
+/// the artifact.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModuleSymbols {
+    /// The name of the module.
+    pub name: Option<String>,
+
+    /// The names of the functions.
+    pub function_names: BTreeMap<FunctionIndex, String>,
+
+    /// The other names of the extended name section.
+    pub names: ModuleNames,
+
+    /// The `name` and `.debug*` custom sections, by name.
+    pub custom_sections: Vec<(String, Arc<[u8]>)>,
+}
+
+impl ModuleSymbols {
+    /// Returns `true` if a custom section with this name holds
+    /// symbols or debug information.
+    pub fn is_symbol_section(name: &str) -> bool {
+        name == "name" || name.starts_with(".debug")
+    }
+}
+
+/// The name of the custom section reserved for the middleware registry.
+///
+/// The registry is kept in [`ModuleInfo::middleware_exports`], out of
+/// reach of the modules: the custom sections with this name are dropped
+/// when translating a module, so that tooling reading the sections can't
+/// be misled either.
+pub const MIDDLEWARE_REGISTRY_SECTION: &str = "wasmer.middleware";
+
+/// A global exported by a middleware, recorded with
+/// [`ModuleInfo::register_middleware_export`].
+///
+/// Tooling can find the state of a middleware (like the remaining points
+/// of the metering) from its purpose, instead of hard-coding the export
+/// names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MiddlewareExport {
+    /// The name of the middleware, like `metering`.
+    pub middleware: String,
+
+    /// The name of the export.
+    pub export: String,
+
+    /// What the export holds, like `remaining_points`.
+    pub purpose: String,
+}
+
 impl ModuleInfo {
     /// Allocates the module data structures.
     pub fn new() -> Self {
         Self {
             id: ModuleId::default(),
             name: None,
+            hash: None,
             imports: IndexMap::new(),
             exports: IndexMap::new(),
             start_function: None,
             table_initializers: Vec::new(),
-            passive_elements: HashMap::new(),
-            passive_data: HashMap::new(),
+            passive_elements: BTreeMap::new(),
+            passive_data: BTreeMap::new(),
+            deferred_data: BTreeMap::new(),
+            data_loader: None,
             global_initializers: PrimaryMap::new(),
-            function_names: HashMap::new(),
+            function_names: BTreeMap::new(),
+            names: ModuleNames::default(),
             signatures: PrimaryMap::new(),
             functions: PrimaryMap::new(),
             tables: PrimaryMap::new(),
@@ -140,9 +273,101 @@ impl ModuleInfo {
             num_imported_globals: 0,
             custom_sections: IndexMap::new(),
             custom_sections_data: PrimaryMap::new(),
+            middleware_exports: Vec::new(),
         }
     }
 
+    /// Removes the passive data segments of at least `min_len` bytes from
+    /// the module and returns them.
+    ///
+    /// The segments are loaded with the loader set by
+    /// [`ModuleInfo::set_data_loader`] the first time `memory.init` uses
+    /// them in an instance; until then they don't need to be resident.
+    pub fn defer_passive_data(&mut self, min_len: usize) -> Vec<(DataIndex, Arc<[u8]>)> {
+        let deferred_indices = self
+            .passive_data
+            .iter()
+            .filter(|(_, data)| data.len() >= min_len)
+            .map(|(data_index, _)| *data_index)
+            .collect::<Vec<_>>();
+        let mut deferred = deferred_indices
+            .into_iter()
+            .filter_map(|data_index| self.passive_data.remove_entry(&data_index))
+            .collect::<Vec<_>>();
+        deferred.sort_by_key(|(data_index, _)| *data_index);
+        for (data_index, data) in deferred.iter() {
+            self.deferred_data.insert(*data_index, data.len());
+        }
+        deferred
+    }
+
+    /// Sets the loader of the passive data segments deferred with
+    /// [`ModuleInfo::defer_passive_data`].
+    pub fn set_data_loader(&mut self, loader: Arc<dyn DataSegmentLoader>) {
+        self.data_loader = Some(DataLoader(loader));
+    }
+
+    /// Removes the module name, the function names, the other names and the custom sections
+    /// holding symbols or debug information, and returns them.
+    ///
+    /// The data of the removed custom sections is released, but their
+    /// indices are not reused.
+    pub fn strip_symbols(&mut self) -> ModuleSymbols {
+        let mut symbols = ModuleSymbols {
+            name: self.name.take(),
+            function_names: std::mem::take(&mut self.function_names),
+            names: std::mem::take(&mut self.names),
+            custom_sections: Vec::new(),
+        };
+        let custom_sections_data = &mut self.custom_sections_data;
+        self.custom_sections.retain(|name, index| {
+            if !ModuleSymbols::is_symbol_section(name) {
+                return true;
+            }
+            let data = std::mem::replace(&mut custom_sections_data[*index], Arc::from(&[][..]));
+            symbols.custom_sections.push((name.clone(), data));
+            false
+        });
+        symbols
+    }
+
+    /// Attaches symbols previously removed with [`ModuleInfo::strip_symbols`].
+    ///
+    /// The symbols replace the ones the module may have.
+    pub fn attach_symbols(&mut self, symbols: ModuleSymbols) {
+        self.name = symbols.name;
+        self.function_names = symbols.function_names;
+        self.names = symbols.names;
+        for (name, data) in symbols.custom_sections {
+            match self.custom_sections.get(&name) {
+                Some(index) => self.custom_sections_data[*index] = data,
+                None => {
+                    let index = self.custom_sections_data.push(data);
+                    self.custom_sections.insert(name, index);
+                }
+            }
+        }
+    }
+
+    /// Records that the middleware `middleware` exports the global
+    /// `export`, holding its `purpose`.
+    ///
+    /// This is meant to be called by the `transform_module_info` of the
+    /// middlewares adding exports.
+    pub fn register_middleware_export(&mut self, middleware: &str, export: &str, purpose: &str) {
+        self.middleware_exports.push(MiddlewareExport {
+            middleware: middleware.to_string(),
+            export: export.to_string(),
+            purpose: purpose.to_string(),
+        });
+    }
+
+    /// Returns the globals exported by the middlewares, recorded with
+    /// [`ModuleInfo::register_middleware_export`].
+    pub fn middleware_exports(&self) -> Vec<MiddlewareExport> {
+        self.middleware_exports.clone()
+    }
+
     /// Get the given passive element, if it exists.
     pub fn get_passive_element(&self, index: ElemIndex) -> Option<&[FunctionIndex]> {
         self.passive_elements.get(&index).map(|es| &**es)
@@ -9,6 +9,9 @@ mod traphandlers;
 pub use trapcode::TrapCode;
 pub use traphandlers::{
     catch_traps, catch_traps_with_result, raise_lib_trap, raise_user_trap, wasmer_call_trampoline,
-    Trap,
+    MemoryAccessFault, Trap,
+};
+pub use traphandlers::{
+    host_panic_policy, init_traps, remaining_native_stack, resume_panic, set_host_panic_policy,
+    HostCallTrap, HostPanicPolicy,
 };
-pub use traphandlers::{init_traps, resume_panic};
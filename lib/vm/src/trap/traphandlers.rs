@@ -5,7 +5,7 @@
 //! signalhandling mechanisms.
 
 use super::trapcode::TrapCode;
-use crate::instance::{Instance, SignalHandler};
+use crate::instance::{with_live_instance, Instance, SignalHandler};
 use crate::vmcontext::{VMFunctionBody, VMFunctionEnvironment, VMTrampoline};
 use backtrace::Backtrace;
 use std::any::Any;
@@ -14,7 +14,9 @@ use std::error::Error;
 use std::io;
 use std::mem;
 use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Once;
+use wasmer_types::MemoryIndex;
 
 extern "C" {
     fn RegisterSetjmp(
@@ -95,6 +97,7 @@ cfg_if::cfg_if! {
             let mut stackaddr: *mut libc::c_void = ptr::null_mut();
             let mut stacksize: libc::size_t = 0;
             libc::pthread_attr_getstack(&thread_attrs, &mut stackaddr, &mut stacksize);
+            libc::pthread_attr_destroy(&mut thread_attrs);
             (stackaddr as usize, stacksize)
         }
 
@@ -110,10 +113,13 @@ cfg_if::cfg_if! {
                 libc::SIGILL => &PREV_SIGILL,
                 _ => panic!("unknown signal: {}", signum),
             };
+            let fault_addr = match signum {
+                libc::SIGSEGV | libc::SIGBUS => Some((*siginfo).si_addr() as usize),
+                _ => None,
+            };
             // We try to get the Code trap associated to this signal
-            let maybe_signal_trap = match signum {
-                libc::SIGSEGV | libc::SIGBUS => {
-                    let addr = (*siginfo).si_addr() as usize;
+            let maybe_signal_trap = match fault_addr {
+                Some(addr) => {
                     let (stackaddr, stacksize) = thread_stack();
                     // The stack and its guard page covers the
                     // range [stackaddr - guard pages .. stackaddr + stacksize).
@@ -124,7 +130,7 @@ cfg_if::cfg_if! {
                         Some(TrapCode::HeapAccessOutOfBounds)
                     }
                 }
-                _ => None,
+                None => None,
             };
             let handled = tls::with(|info| {
                 // If no wasm code is executing, we don't handle this as a wasm
@@ -143,6 +149,7 @@ cfg_if::cfg_if! {
                 // out what to do based on the result of the trap handling.
                 let jmp_buf = info.handle_trap(
                     get_pc(context),
+                    fault_addr,
                     false,
                     maybe_signal_trap,
                     |handler| handler(signum, siginfo, context),
@@ -278,8 +285,16 @@ cfg_if::cfg_if! {
                     Some(info) => info,
                     None => return EXCEPTION_CONTINUE_SEARCH,
                 };
+                // For access violations, the second parameter of the
+                // record is the inaccessible address.
+                let fault_addr = if record.ExceptionCode == EXCEPTION_ACCESS_VIOLATION {
+                    Some(record.ExceptionInformation[1])
+                } else {
+                    None
+                };
                 let jmp_buf = info.handle_trap(
                     (*(*exception_info).ContextRecord).Rip as *const u8,
+                    fault_addr,
                     record.ExceptionCode == EXCEPTION_STACK_OVERFLOW,
                     // TODO: fix the signal trap associated to memory access in Windows
                     None,
@@ -317,6 +332,25 @@ fn real_init() {
     }
 }
 
+/// Returns the native stack left to the current thread, in bytes, or
+/// `None` if it can't be determined on this platform.
+pub fn remaining_native_stack() -> Option<usize> {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            // The address of a local approximates the stack pointer.
+            let marker = 0u8;
+            let here = &marker as *const u8 as usize;
+            let (stackaddr, _) = unsafe { thread_stack() };
+            if stackaddr == 0 {
+                return None;
+            }
+            here.checked_sub(stackaddr)
+        } else {
+            None
+        }
+    }
+}
+
 /// Raises a user-defined trap immediately.
 ///
 /// This function performs as-if a wasm trap was just executed, only the trap
@@ -324,12 +358,19 @@ fn real_init() {
 /// payload is then returned from `wasmer_call` and `wasmer_call_trampoline`
 /// below.
 ///
+/// Without wasm code on the stack, when a host function is called directly by
+/// the host, it panics with a [`HostCallTrap`] payload instead.
+///
 /// # Safety
 ///
 /// Only safe to call when wasm code is on the stack, aka `wasmer_call` or
-/// `wasmer_call_trampoline` must have been previously called.
+/// `wasmer_call_trampoline` must have been previously called, or from a
+/// host function called by the host.
 pub unsafe fn raise_user_trap(data: Box<dyn Error + Send + Sync>) -> ! {
-    tls::with(|info| info.unwrap().unwind_with(UnwindReason::UserTrap(data)))
+    tls::with(|info| match info {
+        Some(info) => info.unwind_with(UnwindReason::UserTrap(data, Backtrace::new_unresolved())),
+        None => std::panic::resume_unwind(Box::new(HostCallTrap(data))),
+    })
 }
 
 /// Raises a trap from inside library code immediately.
@@ -346,15 +387,54 @@ pub unsafe fn raise_lib_trap(trap: Trap) -> ! {
     tls::with(|info| info.unwrap().unwind_with(UnwindReason::LibTrap(trap)))
 }
 
+/// The payload of the panic raised by [`raise_user_trap`] when no wasm code
+/// is on the stack.
+#[derive(Debug)]
+pub struct HostCallTrap(pub Box<dyn Error + Send + Sync>);
+
+/// What to do when host code called by wasm code panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostPanicPolicy {
+    /// Unwind the wasm frames like a trap, then resume the panic, with its
+    /// payload, in the host code which called wasm. This is the default.
+    Resume,
+    /// Abort the process.
+    Abort,
+}
+
+static ABORT_ON_HOST_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// Sets what to do when host code called by wasm code panics, for the whole
+/// process.
+pub fn set_host_panic_policy(policy: HostPanicPolicy) {
+    ABORT_ON_HOST_PANIC.store(policy == HostPanicPolicy::Abort, Ordering::SeqCst);
+}
+
+/// Returns what to do when host code called by wasm code panics.
+pub fn host_panic_policy() -> HostPanicPolicy {
+    if ABORT_ON_HOST_PANIC.load(Ordering::SeqCst) {
+        HostPanicPolicy::Abort
+    } else {
+        HostPanicPolicy::Resume
+    }
+}
+
 /// Carries a Rust panic across wasm code and resumes the panic on the other
-/// side.
+/// side, or aborts the process, depending on the [`HostPanicPolicy`].
+///
+/// Without wasm code on the stack, the panic is resumed right away.
 ///
 /// # Safety
 ///
 /// Only safe to call when wasm code is on the stack, aka `wasmer_call` or
-/// `wasmer_call_trampoline` must have been previously called.
+/// `wasmer_call_trampoline` must have been previously called, or from a
+/// host function called by the host.
 pub unsafe fn resume_panic(payload: Box<dyn Any + Send>) -> ! {
-    tls::with(|info| info.unwrap().unwind_with(UnwindReason::Panic(payload)))
+    tls::with(|info| match info {
+        Some(_) if host_panic_policy() == HostPanicPolicy::Abort => std::process::abort(),
+        Some(info) => info.unwind_with(UnwindReason::Panic(payload)),
+        None => std::panic::resume_unwind(payload),
+    })
 }
 
 #[cfg(target_os = "windows")]
@@ -373,11 +453,31 @@ fn reset_guard_page() {
 #[cfg(not(target_os = "windows"))]
 fn reset_guard_page() {}
 
+/// Details of an access to a linear memory that faulted because it was
+/// out of bounds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryAccessFault {
+    /// The index of the memory in the instance that was running.
+    pub memory_index: MemoryIndex,
+    /// The accessed address in the linear memory.
+    pub address: u64,
+    /// The width of the access in bytes, when known.
+    pub access_size: Option<u32>,
+    /// The size of the memory in bytes at the time of the access.
+    pub memory_size: u64,
+}
+
 /// Stores trace message with backtrace.
 #[derive(Debug)]
 pub enum Trap {
     /// A user-raised trap through `raise_user_trap`.
-    User(Box<dyn Error + Send + Sync>),
+    User {
+        /// The error raised.
+        error: Box<dyn Error + Send + Sync>,
+        /// Native stack backtrace at the time the trap was raised, if it
+        /// was raised from a host function called by Wasm
+        backtrace: Option<Backtrace>,
+    },
 
     /// A trap raised from machine code generated from Wasm
     Wasm {
@@ -387,6 +487,9 @@ pub enum Trap {
         backtrace: Backtrace,
         /// Optional trapcode associated to the signal that caused the trap
         signal_trap: Option<TrapCode>,
+        /// The faulting memory access, if the trap was caused by an out of
+        /// bounds access to a memory of the running instance
+        memory_fault: Option<MemoryAccessFault>,
     },
 
     /// A trap raised manually from the Wasmer VM
@@ -408,6 +511,7 @@ impl Trap {
             pc,
             backtrace,
             signal_trap,
+            memory_fault: None,
         }
     }
 
@@ -423,11 +527,13 @@ impl Trap {
         }
     }
 
-    /// Construct a new Out of Memory (OOM) `Trap`.
-    ///
-    /// Internally saves a backtrace when constructed.
+    /// Construct a new user `Trap` with the given error, without a
+    /// backtrace.
     pub fn new_from_user(error: Box<dyn Error + Send + Sync>) -> Self {
-        Self::User(error)
+        Self::User {
+            error,
+            backtrace: None,
+        }
     }
 }
 
@@ -526,12 +632,13 @@ pub struct CallThreadState {
 enum UnwindReason {
     None,
     Panic(Box<dyn Any + Send>),
-    UserTrap(Box<dyn Error + Send + Sync>),
+    UserTrap(Box<dyn Error + Send + Sync>, Backtrace),
     LibTrap(Trap),
     RuntimeTrap {
         backtrace: Backtrace,
         pc: usize,
         signal_trap: Option<TrapCode>,
+        fault_addr: Option<usize>,
     },
 }
 
@@ -556,18 +663,28 @@ impl CallThreadState {
                     debug_assert_eq!(ret, 1);
                     Ok(())
                 }
-                UnwindReason::UserTrap(data) => {
+                UnwindReason::UserTrap(error, backtrace) => {
                     debug_assert_eq!(ret, 0);
-                    Err(Trap::new_from_user(data))
+                    Err(Trap::User {
+                        error,
+                        backtrace: Some(backtrace),
+                    })
                 }
                 UnwindReason::LibTrap(trap) => Err(trap),
                 UnwindReason::RuntimeTrap {
                     backtrace,
                     pc,
                     signal_trap,
+                    fault_addr,
                 } => {
                     debug_assert_eq!(ret, 0);
-                    Err(Trap::new_from_wasm(pc, backtrace, signal_trap))
+                    let memory_fault = fault_addr.and_then(|addr| self.memory_access_fault(addr));
+                    Err(Trap::Wasm {
+                        pc,
+                        backtrace,
+                        signal_trap,
+                        memory_fault,
+                    })
                 }
                 UnwindReason::Panic(panic) => {
                     debug_assert_eq!(ret, 0);
@@ -595,6 +712,15 @@ impl CallThreadState {
         }
     }
 
+    /// Returns the details of a faulting access at the native address
+    /// `addr`, if it's in a memory of the called instance.
+    fn memory_access_fault(&self, addr: usize) -> Option<MemoryAccessFault> {
+        // The called function may be a host function, whose environment
+        // isn't a `VMContext`.
+        let vmctx = unsafe { self.vmctx.vmctx };
+        with_live_instance(vmctx, |instance| instance.memory_access_fault(addr)).flatten()
+    }
+
     fn unwind_with(&self, reason: UnwindReason) -> ! {
         self.unwind.replace(reason);
         unsafe {
@@ -605,6 +731,7 @@ impl CallThreadState {
     /// Trap handler using our thread-local state.
     ///
     /// * `pc` - the program counter the trap happened at
+    /// * `fault_addr` - the faulting address, for memory access violations
     /// * `reset_guard_page` - whether or not to reset the guard page,
     ///   currently Windows specific
     /// * `call_handler` - a closure used to invoke the platform-specific
@@ -622,6 +749,7 @@ impl CallThreadState {
     fn handle_trap(
         &self,
         pc: *const u8,
+        fault_addr: Option<usize>,
         reset_guard_page: bool,
         signal_trap: Option<TrapCode>,
         call_handler: impl Fn(&SignalHandler) -> bool,
@@ -670,6 +798,7 @@ impl CallThreadState {
             backtrace,
             signal_trap,
             pc: pc as usize,
+            fault_addr,
         });
         self.handling_trap.set(false);
         self.jmp_buf.get()
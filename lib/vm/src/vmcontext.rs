@@ -7,6 +7,7 @@
 use crate::global::Global;
 use crate::instance::Instance;
 use crate::memory::Memory;
+use crate::module::ModuleInfo;
 use crate::table::Table;
 use crate::trap::{Trap, TrapCode};
 use std::any::Any;
@@ -725,6 +726,9 @@ impl VMSharedSignatureIndex {
     }
 }
 
+/// The index of the uninitialized table elements, which matches no
+/// registered signature: the compilers rely on it to check that an element
+/// is initialized and has the expected signature with a single comparison.
 impl Default for VMSharedSignatureIndex {
     fn default() -> Self {
         Self::new(u32::MAX)
@@ -951,6 +955,16 @@ impl VMContext {
     pub unsafe fn host_state(&self) -> &dyn Any {
         self.instance().host_state()
     }
+
+    /// Return the module info of the associated `Instance`.
+    ///
+    /// # Safety
+    /// This is unsafe because it doesn't work on just any `VMContext`, it must
+    /// be a `VMContext` allocated as part of an `Instance`.
+    #[inline]
+    pub unsafe fn module_info(&self) -> &ModuleInfo {
+        self.instance().module_ref()
+    }
 }
 
 ///
@@ -434,10 +434,12 @@ impl VMOffsets {
             .unwrap()
     }
 
-    /// Return the size of the [`VMContext`] allocation.
+    /// The offset of the pointer to the call counters of the local
+    /// functions, an array of `u64` indexed by `LocalFunctionIndex`.
     ///
-    /// [`VMContext`]: crate::vmcontext::VMContext
-    pub fn size_of_vmctx(&self) -> u32 {
+    /// The pointer is null when the module was compiled without counting
+    /// the function calls.
+    pub fn vmctx_call_counters(&self) -> u32 {
         self.vmctx_builtin_functions_begin()
             .checked_add(
                 VMBuiltinFunctionIndex::builtin_functions_total_number()
@@ -447,6 +449,15 @@ impl VMOffsets {
             .unwrap()
     }
 
+    /// Return the size of the [`VMContext`] allocation.
+    ///
+    /// [`VMContext`]: crate::vmcontext::VMContext
+    pub fn size_of_vmctx(&self) -> u32 {
+        self.vmctx_call_counters()
+            .checked_add(u32::from(self.pointer_size))
+            .unwrap()
+    }
+
     /// Return the offset to [`VMSharedSignatureIndex`] index `index`.
     ///
     /// [`VMSharedSignatureIndex`]: crate::vmcontext::VMSharedSignatureIndex
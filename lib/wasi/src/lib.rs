@@ -16,20 +16,32 @@
 mod macros;
 mod ptr;
 mod state;
+mod stats;
 mod syscalls;
 mod utils;
 
+use crate::stats::StatsCounters;
 use crate::syscalls::*;
 
+#[cfg(unix)]
+pub use crate::state::SocketListener;
 pub use crate::state::{
-    Fd, WasiFile, WasiFs, WasiFsError, WasiState, WasiStateBuilder, WasiStateCreationError,
-    ALL_RIGHTS, VIRTUAL_ROOT_FD,
+    hash_file_content, ArgsLimits, ChaChaRng, Entropy, EntropyError, EntropySource, ExitFn, Fd,
+    FileHash, FixedClock, FsManifest, FsManifestDiff, Group, HostCapabilities, HostSocket,
+    HttpAccess, HttpClient, HttpRequest, HttpResponse, HttpResponseFile, LogFile, LogOutput,
+    LogStream, LogicalClock, ManifestEntry, PathAccess, PathHook, ProcFs, ScaledClock,
+    SharedSegment, SleepFn, User, UserDatabase, WasiClock, WasiExtensions, WasiFile, WasiFs,
+    WasiFsError, WasiState, WasiStateBuilder, WasiStateCreationError, YieldFn, ALL_RIGHTS,
+    BUILTIN_EXTENSIONS, CAPABILITIES_VERSION, DEFAULT_MAX_ARGS_SIZE, VIRTUAL_ROOT_FD,
 };
+pub use crate::stats::{SyscallClass, WasiStats};
 pub use crate::syscalls::types;
 pub use crate::utils::{get_wasi_version, is_wasi_module, WasiVersion};
 
 use thiserror::Error;
-use wasmer::{imports, Function, ImportObject, LazyInit, Memory, Module, Store, WasmerEnv};
+use wasmer::{
+    imports, Function, ImportObject, LazyInit, Memory, Module, RuntimeError, Store, Val, WasmerEnv,
+};
 #[cfg(all(target_os = "macos", target_arch = "aarch64",))]
 use wasmer::{FunctionType, ValType};
 
@@ -45,6 +57,26 @@ pub enum WasiError {
     UnknownWasiVersion,
 }
 
+/// The exit of a WASI program through `proc_exit`, with its exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GuestExit(pub syscalls::types::__wasi_exitcode_t);
+
+impl GuestExit {
+    /// Returns whether the program exited successfully, with the code 0.
+    pub fn is_success(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// How a call into a WASI program ended, when it didn't fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WasiCallOutcome {
+    /// The function returned these values.
+    Returned(Box<[Val]>),
+    /// The program exited.
+    Exited(GuestExit),
+}
+
 /// The environment provided to the WASI imports.
 #[derive(Debug, Clone, WasmerEnv)]
 pub struct WasiEnv {
@@ -55,6 +87,7 @@ pub struct WasiEnv {
     /// if the lock is held and the Wasm calls into a host function that tries
     /// to lock this mutex, the program will deadlock.
     pub state: Arc<Mutex<WasiState>>,
+    stats: Arc<StatsCounters>,
     #[wasmer(export)]
     memory: LazyInit<Memory>,
 }
@@ -63,6 +96,7 @@ impl WasiEnv {
     pub fn new(state: WasiState) -> Self {
         Self {
             state: Arc::new(Mutex::new(state)),
+            stats: Arc::new(StatsCounters::default()),
             memory: LazyInit::new(),
         }
     }
@@ -92,6 +126,65 @@ impl WasiEnv {
         self.state.lock().unwrap()
     }
 
+    /// Calls `function`, an export of the WASI program of this environment,
+    /// and returns an exit of the program as a [`GuestExit`] instead of a
+    /// [`RuntimeError`], so that an exit with the code 0 doesn't look like
+    /// a failure.
+    ///
+    /// When the program exits, its open files are flushed, as a process
+    /// exit would. Custom exit errors set with
+    /// [`HostCapabilities::exit`] are returned as errors.
+    ///
+    /// ```no_run
+    /// # use wasmer::{Instance, Module, Store};
+    /// # use wasmer_wasi::{WasiCallOutcome, WasiState};
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// # let module = Module::from_file(&store, "program.wasm")?;
+    /// let mut wasi_env = WasiState::new("program").finalize()?;
+    /// let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+    /// let start = instance.exports.get_function("_start")?;
+    /// match wasi_env.call(start, &[])? {
+    ///     WasiCallOutcome::Exited(exit) if !exit.is_success() => {
+    ///         eprintln!("the program exited with the code {}", exit.0)
+    ///     }
+    ///     _ => {}
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call(
+        &self,
+        function: &Function,
+        params: &[Val],
+    ) -> Result<WasiCallOutcome, RuntimeError> {
+        let error = match function.call(params) {
+            Ok(results) => return Ok(WasiCallOutcome::Returned(results)),
+            Err(error) => error,
+        };
+        match error.downcast::<WasiError>() {
+            Ok(WasiError::Exit(code)) => {
+                self.state().fs.flush_all();
+                Ok(WasiCallOutcome::Exited(GuestExit(code)))
+            }
+            Ok(error) => Err(RuntimeError::new(error.to_string())),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Get a snapshot of the syscalls, I/O and memory used by the WASI
+    /// program so far, across all the clones of this environment.
+    pub fn stats(&self) -> WasiStats {
+        self.stats.snapshot()
+    }
+
+    pub(crate) fn record_syscall(&self, class: SyscallClass) {
+        let memory_size = self
+            .memory_ref()
+            .map(|memory| memory.size().bytes().0 as u64);
+        self.stats.syscall(class, memory_size);
+    }
+
     /// Get a reference to the memory
     pub fn memory(&self) -> &Memory {
         self.memory_ref()
@@ -251,9 +344,15 @@ fn generate_import_object_snapshot1(store: &Store, env: WasiEnv) -> ImportObject
             "proc_raise" => Function::new_native_with_env(store, env.clone(), proc_raise),
             "random_get" => Function::new_native_with_env(store, env.clone(), random_get),
             "sched_yield" => Function::new_native_with_env(store, env.clone(), sched_yield),
+            "sock_accept" => Function::new_native_with_env(store, env.clone(), sock_accept),
             "sock_recv" => Function::new_native_with_env(store, env.clone(), sock_recv),
             "sock_send" => Function::new_native_with_env(store, env.clone(), sock_send),
             "sock_shutdown" => Function::new_native_with_env(store, env.clone(), sock_shutdown),
+        },
+        "wasix_32v1" => {
+            "wasix_get_capabilities" => Function::new_native_with_env(store, env.clone(), wasix_get_capabilities),
+            "wasix_http_request" => Function::new_native_with_env(store, env.clone(), wasix_http_request),
+            "wasix_http_response_headers" => Function::new_native_with_env(store, env.clone(), wasix_http_response_headers),
         }
     }
 }
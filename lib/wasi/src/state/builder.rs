@@ -1,7 +1,13 @@
 //! Builder system for configuring a [`WasiState`] and creating it.
 
-use crate::state::{WasiFile, WasiFs, WasiFsError, WasiState};
-use crate::syscalls::types::{__WASI_STDERR_FILENO, __WASI_STDIN_FILENO, __WASI_STDOUT_FILENO};
+#[cfg(unix)]
+use crate::state::SocketListener;
+use crate::state::{
+    strings_size, ArgsLimits, Entropy, Fd, FsManifest, FsManifestDiff, HostCapabilities,
+    HostSocket, HttpAccess, LogFile, LogOutput, PathHook, ProcFs, SharedSegment, UserDatabase,
+    WasiClock, WasiExtensions, WasiFile, WasiFs, WasiFsError, WasiState, VIRTUAL_ROOT_FD,
+};
+use crate::syscalls::types::*;
 use crate::WasiEnv;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
@@ -37,11 +43,22 @@ pub struct WasiStateBuilder {
     args: Vec<Vec<u8>>,
     envs: Vec<(Vec<u8>, Vec<u8>)>,
     preopens: Vec<PreopenedDir>,
+    fs_manifest: Option<FsManifest>,
     #[allow(clippy::type_complexity)]
     setup_fs_fn: Option<Box<dyn Fn(&mut WasiFs) -> Result<(), String> + Send>>,
     stdout_override: Option<Box<dyn WasiFile>>,
     stderr_override: Option<Box<dyn WasiFile>>,
     stdin_override: Option<Box<dyn WasiFile>>,
+    log_output: Option<LogOutput>,
+    path_hook: Option<Box<dyn PathHook>>,
+    shared_segments: Vec<(String, SharedSegment)>,
+    sockets: Vec<(String, Box<dyn WasiFile>)>,
+    users: Option<UserDatabase>,
+    proc_fs: bool,
+    args_limits: ArgsLimits,
+    capabilities: HostCapabilities,
+    extensions: WasiExtensions,
+    http: Option<HttpAccess>,
 }
 
 impl std::fmt::Debug for WasiStateBuilder {
@@ -50,10 +67,21 @@ impl std::fmt::Debug for WasiStateBuilder {
             .field("args", &self.args)
             .field("envs", &self.envs)
             .field("preopens", &self.preopens)
+            .field("fs_manifest", &self.fs_manifest)
             .field("setup_fs_fn exists", &self.setup_fs_fn.is_some())
             .field("stdout_override exists", &self.stdout_override.is_some())
             .field("stderr_override exists", &self.stderr_override.is_some())
             .field("stdin_override exists", &self.stdin_override.is_some())
+            .field("log_output", &self.log_output)
+            .field("path_hook exists", &self.path_hook.is_some())
+            .field("shared_segments", &self.shared_segments)
+            .field("sockets", &self.sockets)
+            .field("users", &self.users)
+            .field("proc_fs", &self.proc_fs)
+            .field("args_limits", &self.args_limits)
+            .field("capabilities", &self.capabilities)
+            .field("extensions", &self.extensions)
+            .field("http", &self.http)
             .finish()
     }
 }
@@ -65,6 +93,10 @@ pub enum WasiStateCreationError {
     EnvironmentVariableFormatError(String),
     #[error("argument contains null byte: `{0}`")]
     ArgumentContainsNulByte(String),
+    #[error("arguments too large: {0} bytes, the limit is {1}")]
+    ArgumentsTooLarge(u64, u32),
+    #[error("environment too large: {0} bytes, the limit is {1}")]
+    EnvironmentTooLarge(u64, u32),
     #[error("preopened directory not found: `{0}`")]
     PreopenedDirectoryNotFound(PathBuf),
     #[error("preopened directory error: `{0}`")]
@@ -75,6 +107,8 @@ pub enum WasiStateCreationError {
     WasiFsCreationError(String),
     #[error("wasi filesystem setup error: `{0}`")]
     WasiFsSetupError(String),
+    #[error("preopened directories don't match their manifest:\n{0}")]
+    FsManifestMismatch(FsManifestDiff),
     #[error(transparent)]
     WasiFsError(WasiFsError),
 }
@@ -282,6 +316,344 @@ impl WasiStateBuilder {
         self
     }
 
+    /// Log the lines the program writes to `stdout` and `stderr` with
+    /// `tracing`, instead of writing them to the `stdout` and `stderr` of
+    /// the host.
+    ///
+    /// The lines are logged with the pid and the module name of the
+    /// program, see [`LogFile`]. The files set with
+    /// [`WasiStateBuilder::stdout`] and [`WasiStateBuilder::stderr`] take
+    /// precedence.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{LogOutput, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .log_output(LogOutput {
+    ///        max_lines_per_second: 100,
+    ///        ..LogOutput::default()
+    ///    })
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn log_output(&mut self, log_output: LogOutput) -> &mut Self {
+        self.log_output = Some(log_output);
+
+        self
+    }
+
+    /// Expose a [`SharedSegment`] to the WASI program as the file `/{name}`.
+    ///
+    /// Keep a clone of the segment to access the same bytes from the host.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{SharedSegment, WasiState};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let segment = SharedSegment::new(4096);
+    /// WasiState::new("program_name")
+    ///    .shared_segment("frames", segment.clone())
+    ///    .build()?;
+    /// // The WASI program can now read and write `/frames`.
+    /// segment.set::<u32>(0, 42)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn shared_segment(&mut self, name: &str, segment: SharedSegment) -> &mut Self {
+        self.shared_segments.push((name.to_string(), segment));
+
+        self
+    }
+
+    /// Expose a connected [`HostSocket`] to the WASI program as the stream
+    /// socket `/{name}`, for `sock_send` and `sock_recv`.
+    ///
+    /// Keep a [`HostSocket::try_clone`] of the socket to tune its buffers
+    /// or to shut it down from the host.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use std::net::TcpStream;
+    /// # use wasmer_wasi::{HostSocket, WasiState};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let socket = HostSocket::new(TcpStream::connect("127.0.0.1:6379")?)?;
+    /// socket.set_send_buffer_size(256 * 1024)?;
+    /// WasiState::new("program_name")
+    ///    .socket("redis", socket)
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn socket(&mut self, name: &str, socket: HostSocket) -> &mut Self {
+        self.sockets.push((name.to_string(), Box::new(socket)));
+
+        self
+    }
+
+    /// Expose a [`SocketListener`] to the WASI program as the listening
+    /// socket `/{name}`, for `sock_accept`.
+    ///
+    /// Keep a clone of the listener to hand the connections accepted by
+    /// the host over to the program.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{SocketListener, WasiState};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let listener = SocketListener::new()?;
+    /// WasiState::new("program_name")
+    ///    .socket_listener("http", listener.clone())
+    ///    .build()?;
+    /// let connection = listener.connect()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(unix)]
+    pub fn socket_listener(&mut self, name: &str, listener: SocketListener) -> &mut Self {
+        self.sockets.push((name.to_string(), Box::new(listener)));
+
+        self
+    }
+
+    /// Set the complete expected content of the preopened directories.
+    ///
+    /// [`build`] fails with [`WasiStateCreationError::FsManifestMismatch`]
+    /// and the differences if any file or directory is missing, unexpected
+    /// or modified. [`FsManifest::snapshot`] records the manifest of a
+    /// reference environment.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{hash_file_content, FsManifest, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// let mut manifest = FsManifest::new();
+    /// manifest
+    ///     .dir("data/models")
+    ///     .file("data/config.json", hash_file_content(b"{}"));
+    /// WasiState::new("program_name")
+    ///    .map_dir("data", "/srv/data")?
+    ///    .fs_manifest(manifest)
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`build`]: WasiStateBuilder::build
+    pub fn fs_manifest(&mut self, fs_manifest: FsManifest) -> &mut Self {
+        self.fs_manifest = Some(fs_manifest);
+
+        self
+    }
+
+    /// Set the hook called with every path passed by the WASI program to
+    /// the file system, before it's resolved. It can rewrite the path, or
+    /// make the syscall fail with an error.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{types::__WASI_EACCES, PathAccess, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .preopen_dir("tenants")?
+    ///    .path_hook(|access: &PathAccess| {
+    ///        println!("{}: {}", access.syscall, access.path);
+    ///        if access.path.contains("..") {
+    ///            return Err(__WASI_EACCES);
+    ///        }
+    ///        Ok(Some(format!("tenant-42/{}", access.path)))
+    ///    })
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn path_hook(&mut self, path_hook: impl PathHook + 'static) -> &mut Self {
+        self.path_hook = Some(Box::new(path_hook));
+
+        self
+    }
+
+    /// Set the users and groups seen by the WASI program.
+    ///
+    /// They are synthesized into the virtual files `/etc/passwd` and
+    /// `/etc/group`, and `HOME`, `USER` and `LOGNAME` default to the ones
+    /// of the current user. The users of the host are never exposed.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{UserDatabase, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .users(UserDatabase::single_user("alice", 1000, 1000))
+    ///    .build()?;
+    /// // The program runs as `alice`, with `HOME=/home/alice`.
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn users(&mut self, users: UserDatabase) -> &mut Self {
+        self.users = Some(users);
+
+        self
+    }
+
+    /// Enable or disable the read-only `/proc` describing the WASI program
+    /// itself: its pid, memory usage, open file descriptors and uptime.
+    ///
+    /// It's disabled by default. See [`ProcFs`] for the entries.
+    pub fn proc_fs(&mut self, enabled: bool) -> &mut Self {
+        self.proc_fs = enabled;
+
+        self
+    }
+
+    /// Set the limits on the total sizes of the arguments and of the
+    /// environment.
+    ///
+    /// They default to [`DEFAULT_MAX_ARGS_SIZE`] each. [`build`] fails with
+    /// [`WasiStateCreationError::ArgumentsTooLarge`] or
+    /// [`WasiStateCreationError::EnvironmentTooLarge`] if they're exceeded.
+    ///
+    /// [`DEFAULT_MAX_ARGS_SIZE`]: crate::DEFAULT_MAX_ARGS_SIZE
+    /// [`build`]: WasiStateBuilder::build
+    pub fn args_limits(&mut self, args_limits: ArgsLimits) -> &mut Self {
+        self.args_limits = args_limits;
+
+        self
+    }
+
+    /// Set the source of the randomness returned by `random_get`.
+    ///
+    /// Defaults to [`Entropy::Os`]. Use [`Entropy::seeded`] for
+    /// deterministic executions, or [`Entropy::Deny`] to make any use of
+    /// randomness fail.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{Entropy, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .entropy(Entropy::seeded([7; 32]))
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn entropy(&mut self, entropy: Entropy) -> &mut Self {
+        self.capabilities.entropy = entropy;
+
+        self
+    }
+
+    /// Set the clock answering `clock_time_get` and `clock_res_get`
+    /// instead of the host clocks.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{LogicalClock, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// // Every read advances the time by one millisecond.
+    /// WasiState::new("program_name")
+    ///    .clock(LogicalClock::new(0, 1_000_000))
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn clock(&mut self, clock: impl WasiClock + 'static) -> &mut Self {
+        self.capabilities.clock = Some(Box::new(clock));
+
+        self
+    }
+
+    /// Set all the host capabilities at once: the randomness, the clocks,
+    /// and how the program sleeps, yields and exits.
+    ///
+    /// It replaces the entropy and the clock set so far.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{HostCapabilities, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// let mut capabilities = HostCapabilities::deterministic(42);
+    /// capabilities.exit = Some(Box::new(|code| format!("exit {}", code).into()));
+    /// WasiState::new("program_name")
+    ///    .capabilities(capabilities)
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn capabilities(&mut self, capabilities: HostCapabilities) -> &mut Self {
+        self.capabilities = capabilities;
+
+        self
+    }
+
+    /// Advertise the extension `name` at `version` to the program, in the
+    /// capabilities returned by the `wasix_get_capabilities` syscall.
+    ///
+    /// Hosts adding their own syscalls to the import object advertise
+    /// them, so that the programs check for them before calling them. The
+    /// [`BUILTIN_EXTENSIONS`] are always advertised.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .extension("acme_kv_store", 2)
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`BUILTIN_EXTENSIONS`]: crate::BUILTIN_EXTENSIONS
+    pub fn extension(&mut self, name: &str, version: u32) -> &mut Self {
+        self.extensions.insert(name, version);
+
+        self
+    }
+
+    /// Let the program send HTTP requests through the host with
+    /// `wasix_http_request`, to the hosts allowed by `http`.
+    ///
+    /// The `wasix_http_client` extension is advertised to the program.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{HttpAccess, HttpClient, HttpRequest, HttpResponse};
+    /// # use wasmer_wasi::{WasiState, WasiStateCreationError};
+    /// # struct MyClient;
+    /// # impl HttpClient for MyClient {
+    /// #     fn send(&self, _: HttpRequest) -> std::io::Result<HttpResponse> { unimplemented!() }
+    /// # }
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .http(
+    ///        HttpAccess::new(MyClient)
+    ///            .allow_host("api.example.com")
+    ///            .header("api.example.com", "Authorization", "Bearer token"),
+    ///    )
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn http(&mut self, http: HttpAccess) -> &mut Self {
+        self.http = Some(http);
+
+        self
+    }
+
     /// Setup the WASI filesystem before running
     // TODO: improve ergonomics on this function
     pub fn setup_fs(
@@ -365,7 +737,24 @@ impl WasiStateBuilder {
         #[allow(deprecated)]
         let mut wasi_fs = WasiFs::new_with_preopen(&self.preopens)
             .map_err(WasiStateCreationError::WasiFsCreationError)?;
+        if let Some(fs_manifest) = &self.fs_manifest {
+            let diff = fs_manifest.diff(&wasi_fs).map_err(|e| {
+                WasiStateCreationError::PreopenedDirectoryError(format!(
+                    "could not verify the file system manifest: {}",
+                    e
+                ))
+            })?;
+            if !diff.is_empty() {
+                return Err(WasiStateCreationError::FsManifestMismatch(diff));
+            }
+        }
         // set up the file system, overriding base files and calling the setup function
+        if let Some(log_output) = &self.log_output {
+            let program_name = String::from_utf8_lossy(&self.args[0]);
+            let (stdout, stderr) = LogFile::for_program(&program_name, log_output);
+            self.stdout_override.get_or_insert_with(|| Box::new(stdout));
+            self.stderr_override.get_or_insert_with(|| Box::new(stderr));
+        }
         if let Some(stdin_override) = self.stdin_override.take() {
             wasi_fs
                 .swap_file(__WASI_STDIN_FILENO, stdin_override)
@@ -381,24 +770,124 @@ impl WasiStateBuilder {
                 .swap_file(__WASI_STDERR_FILENO, stderr_override)
                 .map_err(WasiStateCreationError::WasiFsError)?;
         }
+        wasi_fs.path_hook = self.path_hook.take();
+        for (name, segment) in self.shared_segments.iter() {
+            validate_mapped_dir_alias(name)?;
+            let rights = __WASI_RIGHT_FD_READ
+                | __WASI_RIGHT_FD_WRITE
+                | __WASI_RIGHT_FD_SEEK
+                | __WASI_RIGHT_FD_TELL
+                | __WASI_RIGHT_FD_FILESTAT_GET
+                | __WASI_RIGHT_FD_FILESTAT_SET_SIZE
+                | __WASI_RIGHT_POLL_FD_READWRITE;
+            wasi_fs
+                .open_file_at(
+                    VIRTUAL_ROOT_FD,
+                    Box::new(segment.clone()),
+                    Fd::READ | Fd::WRITE,
+                    name.clone(),
+                    rights,
+                    rights,
+                    0,
+                )
+                .map_err(WasiStateCreationError::WasiFsError)?;
+        }
+        for (name, socket) in self.sockets.drain(..) {
+            validate_mapped_dir_alias(&name)?;
+            let rights = __WASI_RIGHT_FD_READ
+                | __WASI_RIGHT_FD_WRITE
+                | __WASI_RIGHT_FD_FDSTAT_SET_FLAGS
+                | __WASI_RIGHT_FD_FILESTAT_GET
+                | __WASI_RIGHT_POLL_FD_READWRITE
+                | __WASI_RIGHT_SOCK_SHUTDOWN;
+            wasi_fs
+                .open_file_at(
+                    VIRTUAL_ROOT_FD,
+                    socket,
+                    Fd::READ | Fd::WRITE,
+                    name,
+                    rights,
+                    rights,
+                    0,
+                )
+                .map_err(WasiStateCreationError::WasiFsError)?;
+        }
+        let mut envs = self.envs.clone();
+        if let Some(users) = &self.users {
+            let root = wasi_fs
+                .get_fd(VIRTUAL_ROOT_FD)
+                .map_err(|e| WasiStateCreationError::WasiFsError(WasiFsError::from_wasi_err(e)))?
+                .inode;
+            let etc = wasi_fs
+                .create_virtual_dir(root, "etc")
+                .map_err(WasiStateCreationError::WasiFsError)?;
+            for (name, content) in [("passwd", users.passwd()), ("group", users.group())].iter() {
+                wasi_fs
+                    .create_virtual_file(etc, name, content.as_bytes().to_vec())
+                    .map_err(WasiStateCreationError::WasiFsError)?;
+            }
+            if let Some(user) = users.current_user() {
+                let user_envs = [
+                    ("HOME", &user.home),
+                    ("USER", &user.name),
+                    ("LOGNAME", &user.name),
+                ];
+                for (key, value) in user_envs.iter() {
+                    if !envs.iter().any(|(env_key, _)| env_key == key.as_bytes()) {
+                        envs.push((key.as_bytes().to_vec(), value.as_bytes().to_vec()));
+                    }
+                }
+            }
+        }
+        let proc_fs = if self.proc_fs {
+            Some(
+                ProcFs::create(&mut wasi_fs, &self.capabilities)
+                    .map_err(WasiStateCreationError::WasiFsError)?,
+            )
+        } else {
+            None
+        };
+        let envs = envs
+            .iter()
+            .map(|(key, value)| {
+                let mut env = Vec::with_capacity(key.len() + value.len() + 1);
+                env.extend_from_slice(&key);
+                env.push(b'=');
+                env.extend_from_slice(&value);
+
+                env
+            })
+            .collect::<Vec<_>>();
+        let args_size = strings_size(&self.args);
+        if args_size > self.args_limits.max_args_size as u64 {
+            return Err(WasiStateCreationError::ArgumentsTooLarge(
+                args_size,
+                self.args_limits.max_args_size,
+            ));
+        }
+        let envs_size = strings_size(&envs);
+        if envs_size > self.args_limits.max_envs_size as u64 {
+            return Err(WasiStateCreationError::EnvironmentTooLarge(
+                envs_size,
+                self.args_limits.max_envs_size,
+            ));
+        }
         if let Some(f) = &self.setup_fs_fn {
             f(&mut wasi_fs).map_err(WasiStateCreationError::WasiFsSetupError)?;
         }
+        let mut extensions = self.extensions.clone();
+        if self.http.is_some() {
+            extensions.insert("wasix_http_client", 1);
+        }
         Ok(WasiState {
             fs: wasi_fs,
             args: self.args.clone(),
-            envs: self
-                .envs
-                .iter()
-                .map(|(key, value)| {
-                    let mut env = Vec::with_capacity(key.len() + value.len() + 1);
-                    env.extend_from_slice(&key);
-                    env.push(b'=');
-                    env.extend_from_slice(&value);
-
-                    env
-                })
-                .collect(),
+            envs,
+            capabilities: std::mem::take(&mut self.capabilities),
+            proc_fs,
+            args_limits: self.args_limits,
+            extensions,
+            http: self.http.take(),
         })
     }
 
@@ -517,6 +1006,8 @@ impl PreopenDirBuilder {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::state::{hash_file_content, ManifestEntry, PathAccess};
+    use std::io::{self, Read, Seek, Write};
 
     #[test]
     fn env_var_errors() {
@@ -557,6 +1048,233 @@ mod test {
         );
     }
 
+    #[test]
+    fn sockets_are_stream_sockets() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let state = create_wasi_state("test_prog")
+            .socket("peer", HostSocket::new(stream).unwrap())
+            .build()
+            .unwrap();
+
+        let fdstat = state.fs.fdstat(VIRTUAL_ROOT_FD + 1).unwrap();
+        assert_eq!(fdstat.fs_filetype, __WASI_FILETYPE_SOCKET_STREAM);
+        assert_eq!(fdstat.fs_flags & __WASI_FDFLAG_NONBLOCK, 0);
+    }
+
+    #[test]
+    fn shared_segment_is_shared_with_the_host() {
+        let segment = SharedSegment::new(8);
+        let mut state = create_wasi_state("test_prog")
+            .shared_segment("shm", segment.clone())
+            .build()
+            .unwrap();
+
+        let inode = state.fs.get_fd(VIRTUAL_ROOT_FD + 1).unwrap().inode;
+        let file = match &mut state.fs.inodes[inode].kind {
+            crate::state::Kind::File {
+                handle: Some(handle),
+                ..
+            } => handle,
+            _ => panic!("the shared segment must be a file"),
+        };
+        file.write_all(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            segment.get::<u32>(0),
+            Some(u32::from_le_bytes([1, 2, 3, 4]))
+        );
+
+        segment.set::<u8>(7, 9).unwrap();
+        let mut buf = [0; 4];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 0, 9]);
+    }
+
+    #[test]
+    fn shared_segment_does_not_grow_past_its_max_size() {
+        let segment = SharedSegment::with_max_size(4, 6);
+        let mut file = segment.clone();
+        file.seek(io::SeekFrom::Start(4)).unwrap();
+        assert_eq!(file.write(&[1, 2, 3, 4]).unwrap(), 2);
+        assert_eq!(segment.len(), 6);
+        let error = file.write(&[5]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+        file.seek(io::SeekFrom::Start(u32::MAX as u64)).unwrap();
+        assert!(file.write(&[5]).is_err());
+        assert!(segment.write_at(usize::MAX, &[5]).is_err());
+        assert!(segment.set::<u32>(4, 7).is_err());
+        assert_eq!(
+            file.set_len(1 << 40),
+            Err(WasiFsError::UnknownError(__WASI_EFBIG))
+        );
+        assert_eq!(segment.len(), 6);
+    }
+
+    #[test]
+    fn users_are_synthesized_into_etc() {
+        let users = UserDatabase::single_user("alice", 1000, 1000);
+        let mut state = create_wasi_state("test_prog")
+            .env("HOME", "/tmp")
+            .users(users.clone())
+            .build()
+            .unwrap();
+
+        let passwd = state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, "etc/passwd", false)
+            .unwrap();
+        match &state.fs.inodes[passwd].kind {
+            crate::state::Kind::Buffer { buffer } => assert_eq!(buffer, users.passwd().as_bytes()),
+            _ => panic!("/etc/passwd must be an in-memory file"),
+        }
+        assert!(state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, "etc/group", false)
+            .is_ok());
+        assert!(state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, "etc/shadow", false)
+            .is_err());
+
+        // The environment of the embedder has the priority.
+        assert!(state.envs.contains(&b"HOME=/tmp".to_vec()));
+        assert!(state.envs.contains(&b"USER=alice".to_vec()));
+        assert!(!state.envs.contains(&b"HOME=/home/alice".to_vec()));
+    }
+
+    #[test]
+    fn in_memory_files_are_not_directories() {
+        let mut state = create_wasi_state("test_prog")
+            .users(UserDatabase::single_user("alice", 1000, 1000))
+            .build()
+            .unwrap();
+        assert_eq!(
+            state
+                .fs
+                .get_inode_at_path(VIRTUAL_ROOT_FD, "etc/passwd/alice", false)
+                .unwrap_err(),
+            __WASI_ENOTDIR
+        );
+    }
+
+    #[test]
+    fn proc_fs_describes_the_program() {
+        let state = create_wasi_state("test_prog").build().unwrap();
+        assert!(state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, "proc", false)
+            .is_err());
+
+        let mut state = create_wasi_state("/bin/test_prog")
+            .arg("--verbose")
+            .proc_fs(true)
+            .build()
+            .unwrap();
+        let buffer = |state: &WasiState, path: &str| {
+            let inode = state
+                .fs
+                .get_inode_at_path(VIRTUAL_ROOT_FD, path, false)
+                .unwrap();
+            match &state.fs.inodes[inode].kind {
+                crate::state::Kind::Buffer { buffer } => buffer.clone(),
+                _ => panic!("{} must be an in-memory file", path),
+            }
+        };
+
+        let status = state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, "proc/self/status", false)
+            .unwrap();
+        state.refresh_proc_entry(status, 2 * 65536);
+        let status = String::from_utf8(buffer(&state, "proc/self/status")).unwrap();
+        assert!(status.starts_with("Name:\ttest_prog\n"));
+        assert!(status.contains("\nPid:\t1\n"));
+        assert!(status.contains("\nVmRSS:\t128 kB\n"));
+
+        let cmdline = state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, "proc/self/cmdline", false)
+            .unwrap();
+        state.refresh_proc_entry(cmdline, 0);
+        assert_eq!(
+            buffer(&state, "proc/self/cmdline"),
+            b"/bin/test_prog\0--verbose\0"
+        );
+
+        let fd_dir = state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, "proc/self/fd", false)
+            .unwrap();
+        let uptime = state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, "proc/uptime", false)
+            .unwrap();
+        let uptime_fd = state
+            .fs
+            .create_fd(__WASI_RIGHT_FD_READ, 0, 0, Fd::READ, uptime)
+            .unwrap();
+        let uptime_entry = format!("proc/self/fd/{}", uptime_fd);
+        state.refresh_proc_entry(fd_dir, 0);
+        assert_eq!(buffer(&state, "proc/self/fd/1"), b"stdout");
+        assert_eq!(buffer(&state, "proc/self/fd/3"), b"/");
+        assert_eq!(buffer(&state, &uptime_entry), b"uptime");
+
+        state.fs.close_fd(uptime_fd).unwrap();
+        state.refresh_proc_entry(fd_dir, 0);
+        assert!(state
+            .fs
+            .get_inode_at_path(VIRTUAL_ROOT_FD, &uptime_entry, false)
+            .is_err());
+    }
+
+    #[test]
+    fn args_and_envs_are_limited() {
+        let limits = ArgsLimits {
+            max_args_size: 16,
+            max_envs_size: 8,
+        };
+        // `test_prog\0--h\0` and `A=1234\0`.
+        assert!(create_wasi_state("test_prog")
+            .arg("--h")
+            .env("A", "1234")
+            .args_limits(limits)
+            .build()
+            .is_ok());
+        assert_eq!(
+            create_wasi_state("test_prog")
+                .arg("--help")
+                .args_limits(limits)
+                .build()
+                .unwrap_err(),
+            WasiStateCreationError::ArgumentsTooLarge(17, 16)
+        );
+        assert_eq!(
+            create_wasi_state("test_prog")
+                .env("A", "123456")
+                .args_limits(limits)
+                .build()
+                .unwrap_err(),
+            WasiStateCreationError::EnvironmentTooLarge(9, 8)
+        );
+    }
+
+    #[test]
+    fn path_hook_rewrites_and_denies_paths() {
+        let state = create_wasi_state("test_prog")
+            .path_hook(|access: &PathAccess| match access.path {
+                "secret" => Err(__WASI_EACCES),
+                "data" => Ok(Some(format!("tenant/{}", access.path))),
+                _ => Ok(None),
+            })
+            .build()
+            .unwrap();
+
+        let hook_path = |path: &'static str| state.fs.hook_path("path_open", VIRTUAL_ROOT_FD, path);
+        assert_eq!(hook_path("data").unwrap(), "tenant/data");
+        assert_eq!(hook_path("other").unwrap(), "other");
+        assert_eq!(hook_path("secret").unwrap_err(), __WASI_EACCES);
+    }
+
     #[test]
     fn nul_character_in_args() {
         let output = create_wasi_state("test_prog").arg("--h\0elp").build();
@@ -572,4 +1290,65 @@ mod test {
             _ => assert!(false),
         }
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn fs_manifest_records_host_symlinks_without_following_them() {
+        let host_dir = std::env::temp_dir().join(format!("wasi-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(host_dir.join("sub")).unwrap();
+        std::fs::write(host_dir.join("sub/a.txt"), b"a").unwrap();
+        // A link cycle, which would never end if it were followed.
+        std::os::unix::fs::symlink("..", host_dir.join("sub/loop")).unwrap();
+
+        let state = create_wasi_state("test_prog")
+            .map_dir("data", &host_dir)
+            .unwrap()
+            .build()
+            .unwrap();
+        let manifest = FsManifest::snapshot(&state.fs).unwrap();
+        assert!(manifest
+            .entries()
+            .any(|(path, entry)| path == "data/sub/loop"
+                && *entry == ManifestEntry::Symlink(hash_file_content(b".."))));
+        let verified = create_wasi_state("test_prog")
+            .map_dir("data", &host_dir)
+            .unwrap()
+            .fs_manifest(manifest.clone())
+            .build();
+
+        std::fs::write(host_dir.join("sub/a.txt"), b"b").unwrap();
+        let mismatched = create_wasi_state("test_prog")
+            .map_dir("data", &host_dir)
+            .unwrap()
+            .fs_manifest(manifest)
+            .build();
+        std::fs::remove_dir_all(&host_dir).unwrap();
+
+        assert!(verified.is_ok());
+        match mismatched {
+            Err(WasiStateCreationError::FsManifestMismatch(diff)) => {
+                assert_eq!(diff.changed.len(), 1);
+                assert_eq!(diff.changed[0].0, "data/sub/a.txt");
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn seeded_entropy_survives_freezing() {
+        let mut state = create_wasi_state("test_prog")
+            .entropy(Entropy::seeded([1; 32]))
+            .build()
+            .unwrap();
+        let mut first = [0; 8];
+        state.capabilities.entropy.fill(&mut first).unwrap();
+
+        let mut unfrozen = WasiState::unfreeze(&state.freeze().unwrap()).unwrap();
+        let mut expected = [0; 8];
+        let mut actual = [0; 8];
+        state.capabilities.entropy.fill(&mut expected).unwrap();
+        unfrozen.capabilities.entropy.fill(&mut actual).unwrap();
+        assert_ne!(first, actual);
+        assert_eq!(expected, actual);
+    }
 }
@@ -0,0 +1,145 @@
+//! The view of the host system given to a WASI program: its randomness,
+//! its clocks, how it sleeps, yields and exits.
+//!
+//! Each capability defaults to the host. Overriding all of them in a
+//! [`HostCapabilities`] fully virtualizes what the program can observe
+//! of the system, for example to replay an execution.
+
+use super::{ChaChaRng, Entropy, LogicalClock, WasiClock};
+use crate::syscalls::types::*;
+use crate::WasiError;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Sleeps for the timeouts of `poll_oneoff`.
+pub type SleepFn = dyn Fn(Duration) + Send;
+
+/// Yields the execution in `sched_yield`.
+pub type YieldFn = dyn Fn() + Send;
+
+/// Returns the error a WASI program stops with when it calls `proc_exit`
+/// with the given code.
+pub type ExitFn = dyn Fn(__wasi_exitcode_t) -> Box<dyn Error + Send + Sync> + Send;
+
+/// The host capabilities of a WASI program, set with
+/// [`WasiStateBuilder::capabilities`].
+///
+/// Only the entropy is kept by [`WasiState::freeze`]: the other
+/// capabilities are back to the host once unfrozen.
+///
+/// [`WasiStateBuilder::capabilities`]: crate::WasiStateBuilder::capabilities
+/// [`WasiState::freeze`]: crate::WasiState::freeze
+#[derive(Default, Serialize, Deserialize)]
+pub struct HostCapabilities {
+    /// The source of the randomness returned by `random_get`.
+    pub entropy: Entropy,
+    /// The clock answering `clock_time_get` and `clock_res_get` (and the
+    /// absolute deadlines of `poll_oneoff`), the host clocks if `None`.
+    #[serde(skip)]
+    pub clock: Option<Box<dyn WasiClock>>,
+    /// How `poll_oneoff` waits for its timeouts when it has no file to
+    /// poll, a thread sleep if `None`.
+    #[serde(skip)]
+    pub sleep: Option<Box<SleepFn>>,
+    /// What `sched_yield` does, a thread yield if `None`.
+    #[serde(skip)]
+    pub sched_yield: Option<Box<YieldFn>>,
+    /// The error `proc_exit` stops the program with,
+    /// [`WasiError::Exit`] if `None`.
+    #[serde(skip)]
+    pub exit: Option<Box<ExitFn>>,
+}
+
+impl HostCapabilities {
+    /// Creates capabilities all delegating to the host.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates capabilities hiding the host entirely: the randomness is
+    /// seeded with `seed`, the clocks advance by a microsecond at every
+    /// read, and sleeping and yielding return right away.
+    pub fn deterministic(seed: u64) -> Self {
+        Self {
+            entropy: Entropy::Seeded(ChaChaRng::seed_from_u64(seed)),
+            clock: Some(Box::new(LogicalClock::new(0, 1_000))),
+            sleep: Some(Box::new(|_| {})),
+            sched_yield: Some(Box::new(|| {})),
+            exit: None,
+        }
+    }
+
+    /// Sleeps for `duration`.
+    pub fn sleep(&self, duration: Duration) {
+        match &self.sleep {
+            Some(sleep) => sleep(duration),
+            None => std::thread::sleep(duration),
+        }
+    }
+
+    /// Yields the execution.
+    pub fn sched_yield(&self) {
+        match &self.sched_yield {
+            Some(sched_yield) => sched_yield(),
+            None => std::thread::yield_now(),
+        }
+    }
+
+    /// Returns the error the program stops with when it exits with
+    /// `code`.
+    pub fn exit_error(&self, code: __wasi_exitcode_t) -> Box<dyn Error + Send + Sync> {
+        match &self.exit {
+            Some(exit) => exit(code),
+            None => Box::new(WasiError::Exit(code)),
+        }
+    }
+}
+
+impl fmt::Debug for HostCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostCapabilities")
+            .field("entropy", &self.entropy)
+            .field("clock exists", &self.clock.is_some())
+            .field("sleep exists", &self.sleep.is_some())
+            .field("sched_yield exists", &self.sched_yield.is_some())
+            .field("exit exists", &self.exit.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deterministic_capabilities_hide_the_host() {
+        let mut capabilities = HostCapabilities::deterministic(42);
+        let clock = capabilities.clock.as_ref().unwrap();
+        assert_eq!(clock.time_get(__WASI_CLOCK_REALTIME, 0), Ok(0));
+        assert_eq!(clock.time_get(__WASI_CLOCK_MONOTONIC, 0), Ok(1_000));
+
+        let mut bytes = [0; 8];
+        capabilities.entropy.fill(&mut bytes).unwrap();
+        let mut expected = [0; 8];
+        ChaChaRng::seed_from_u64(42).fill(&mut expected);
+        assert_eq!(bytes, expected);
+
+        // Sleeping doesn't wait for the host.
+        capabilities.sleep(Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn exit_errors_default_to_wasi_exits() {
+        let mut capabilities = HostCapabilities::new();
+        let error = capabilities.exit_error(3);
+        assert!(matches!(
+            error.downcast_ref::<WasiError>(),
+            Some(WasiError::Exit(3))
+        ));
+
+        capabilities.exit = Some(Box::new(|code| format!("exited with {}", code).into()));
+        assert_eq!(capabilities.exit_error(3).to_string(), "exited with 3");
+    }
+}
@@ -0,0 +1,174 @@
+//! Virtual clocks, overriding the host clocks seen by WASI programs.
+//!
+//! A [`WasiClock`] answers `clock_time_get` and `clock_res_get` (and the
+//! absolute deadlines of `poll_oneoff`) instead of the host, which allows
+//! deterministic executions and time-travel testing.
+
+use crate::syscalls::types::*;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// A clock overriding the host clocks.
+///
+/// Closures taking the clock id and the precision implement it too.
+pub trait WasiClock: Send {
+    /// Returns the time of the clock `clock_id`, in nanoseconds.
+    fn time_get(
+        &self,
+        clock_id: __wasi_clockid_t,
+        precision: __wasi_timestamp_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t>;
+
+    /// Returns the resolution of the clock `clock_id`, in nanoseconds.
+    ///
+    /// Defaults to one nanosecond.
+    fn res_get(&self, _clock_id: __wasi_clockid_t) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        Ok(1)
+    }
+}
+
+impl<F> WasiClock for F
+where
+    F: Fn(__wasi_clockid_t, __wasi_timestamp_t) -> Result<__wasi_timestamp_t, __wasi_errno_t>
+        + Send,
+{
+    fn time_get(
+        &self,
+        clock_id: __wasi_clockid_t,
+        precision: __wasi_timestamp_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        self(clock_id, precision)
+    }
+}
+
+impl fmt::Debug for dyn WasiClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WasiClock")
+    }
+}
+
+/// Returns `clock_id` if it is a known clock, `EINVAL` otherwise.
+fn check_clock_id(clock_id: __wasi_clockid_t) -> Result<__wasi_clockid_t, __wasi_errno_t> {
+    match clock_id {
+        __WASI_CLOCK_REALTIME
+        | __WASI_CLOCK_MONOTONIC
+        | __WASI_CLOCK_PROCESS_CPUTIME_ID
+        | __WASI_CLOCK_THREAD_CPUTIME_ID => Ok(clock_id),
+        _ => Err(__WASI_EINVAL),
+    }
+}
+
+/// A clock stopped at a fixed time, for all the clock ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub __wasi_timestamp_t);
+
+impl WasiClock for FixedClock {
+    fn time_get(
+        &self,
+        clock_id: __wasi_clockid_t,
+        _precision: __wasi_timestamp_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        check_clock_id(clock_id).map(|_| self.0)
+    }
+}
+
+/// A clock advancing by a fixed step every time it is read, so that
+/// the time only depends on the number of reads.
+#[derive(Debug)]
+pub struct LogicalClock {
+    next: AtomicU64,
+    step: u64,
+}
+
+impl LogicalClock {
+    /// Creates a clock starting at `start` and advancing by `step`
+    /// nanoseconds on every read.
+    pub fn new(start: __wasi_timestamp_t, step: u64) -> Self {
+        Self {
+            next: AtomicU64::new(start),
+            step,
+        }
+    }
+}
+
+impl WasiClock for LogicalClock {
+    fn time_get(
+        &self,
+        clock_id: __wasi_clockid_t,
+        _precision: __wasi_timestamp_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        check_clock_id(clock_id)?;
+        Ok(self.next.fetch_add(self.step, Ordering::SeqCst))
+    }
+
+    fn res_get(&self, _clock_id: __wasi_clockid_t) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        Ok(self.step.max(1))
+    }
+}
+
+/// A clock running at a multiple of the host monotonic time, from a
+/// chosen origin.
+#[derive(Debug)]
+pub struct ScaledClock {
+    origin: __wasi_timestamp_t,
+    factor: f64,
+    started: Instant,
+}
+
+impl ScaledClock {
+    /// Creates a clock starting now at `origin` and running `factor`
+    /// times as fast as the host.
+    pub fn new(origin: __wasi_timestamp_t, factor: f64) -> Self {
+        Self {
+            origin,
+            factor,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl WasiClock for ScaledClock {
+    fn time_get(
+        &self,
+        clock_id: __wasi_clockid_t,
+        _precision: __wasi_timestamp_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        check_clock_id(clock_id)?;
+        let elapsed = self.started.elapsed().as_nanos() as f64 * self.factor;
+        Ok(self.origin.saturating_add(elapsed as __wasi_timestamp_t))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn logical_clock_advances_on_reads() {
+        let clock = LogicalClock::new(1_000, 10);
+        assert_eq!(clock.time_get(__WASI_CLOCK_MONOTONIC, 0), Ok(1_000));
+        assert_eq!(clock.time_get(__WASI_CLOCK_REALTIME, 0), Ok(1_010));
+        assert_eq!(clock.time_get(42, 0), Err(__WASI_EINVAL));
+        assert_eq!(clock.res_get(__WASI_CLOCK_MONOTONIC), Ok(10));
+    }
+
+    #[test]
+    fn functions_are_clocks() {
+        fn realtime_only(
+            clock_id: __wasi_clockid_t,
+            _precision: __wasi_timestamp_t,
+        ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+            match clock_id {
+                __WASI_CLOCK_REALTIME => Ok(7),
+                _ => Err(__WASI_ENOTSUP),
+            }
+        }
+        let clock: Box<dyn WasiClock> = Box::new(realtime_only);
+        assert_eq!(clock.time_get(__WASI_CLOCK_REALTIME, 0), Ok(7));
+        assert_eq!(
+            clock.time_get(__WASI_CLOCK_MONOTONIC, 0),
+            Err(__WASI_ENOTSUP)
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Sources of the randomness given to WASI programs by `random_get`.
+//!
+//! By default the randomness comes from the operating system. Deterministic
+//! executions (replay, consensus) can pin it with a seeded [`ChaChaRng`],
+//! or deny it entirely.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+/// An error while getting randomness.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EntropyError {
+    /// The WASI program is not allowed to get randomness.
+    #[error("randomness is denied")]
+    Denied,
+    /// The entropy source failed.
+    #[error("the entropy source failed: {0}")]
+    Failed(String),
+}
+
+/// A custom source of randomness.
+pub trait EntropySource: fmt::Debug + Send {
+    /// Fills `buf` with random bytes.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), EntropyError>;
+}
+
+/// Where the randomness given to the WASI program comes from.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Entropy {
+    /// The operating system's random number generator.
+    Os,
+    /// A ChaCha20 generator: the same seed always gives the same bytes.
+    Seeded(ChaChaRng),
+    /// No randomness: `random_get` fails with `ENOTCAPABLE`.
+    Deny,
+    /// A custom source.
+    ///
+    /// A [`WasiState`](crate::WasiState) using a custom source can't be
+    /// frozen.
+    #[serde(skip)]
+    Custom(Box<dyn EntropySource>),
+}
+
+impl Default for Entropy {
+    fn default() -> Self {
+        Self::Os
+    }
+}
+
+impl Entropy {
+    /// Creates a [`Entropy::Seeded`] source from a 32-byte seed.
+    pub fn seeded(seed: [u8; 32]) -> Self {
+        Self::Seeded(ChaChaRng::from_seed(seed))
+    }
+
+    /// Fills `buf` with random bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) -> Result<(), EntropyError> {
+        match self {
+            Self::Os => getrandom::getrandom(buf).map_err(|e| EntropyError::Failed(e.to_string())),
+            Self::Seeded(rng) => {
+                rng.fill(buf);
+                Ok(())
+            }
+            Self::Deny => Err(EntropyError::Denied),
+            Self::Custom(source) => source.fill(buf),
+        }
+    }
+}
+
+/// A ChaCha20 random number generator.
+///
+/// It generates the ChaCha20 keystream of the seed, with a zero nonce.
+/// It only keeps the seed and its position in the stream, so it can be
+/// serialized along with the WASI state and resumed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChaChaRng {
+    key: [u32; 8],
+    position: u64,
+}
+
+impl ChaChaRng {
+    /// Creates a generator from a 32-byte seed, used as the ChaCha20 key.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let mut key = [0; 8];
+        for (word, bytes) in key.iter_mut().zip(seed.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        Self { key, position: 0 }
+    }
+
+    /// Creates a generator from a `u64` seed, stored little-endian in the
+    /// first bytes of the key.
+    pub fn seed_from_u64(seed: u64) -> Self {
+        let mut bytes = [0; 32];
+        bytes[..8].copy_from_slice(&seed.to_le_bytes());
+        Self::from_seed(bytes)
+    }
+
+    /// Returns the number of bytes generated so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Fills `buf` with the next bytes of the keystream.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let block = self.block(self.position / 64);
+            let offset = (self.position % 64) as usize;
+            let len = (64 - offset).min(buf.len() - filled);
+            buf[filled..filled + len].copy_from_slice(&block[offset..offset + len]);
+            filled += len;
+            self.position += len as u64;
+        }
+    }
+
+    /// Returns the keystream block `counter`.
+    fn block(&self, counter: u64) -> [u8; 64] {
+        let mut initial = [0u32; 16];
+        initial[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+        initial[4..12].copy_from_slice(&self.key);
+        initial[12] = counter as u32;
+        initial[13] = (counter >> 32) as u32;
+
+        let mut state = initial;
+        for _ in 0..10 {
+            quarter_round(&mut state, 0, 4, 8, 12);
+            quarter_round(&mut state, 1, 5, 9, 13);
+            quarter_round(&mut state, 2, 6, 10, 14);
+            quarter_round(&mut state, 3, 7, 11, 15);
+            quarter_round(&mut state, 0, 5, 10, 15);
+            quarter_round(&mut state, 1, 6, 11, 12);
+            quarter_round(&mut state, 2, 7, 8, 13);
+            quarter_round(&mut state, 3, 4, 9, 14);
+        }
+
+        let mut block = [0; 64];
+        for (i, bytes) in block.chunks_exact_mut(4).enumerate() {
+            bytes.copy_from_slice(&state[i].wrapping_add(initial[i]).to_le_bytes());
+        }
+        block
+    }
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chacha_matches_the_reference_keystream() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let mut bytes = [0; 16];
+        rng.fill(&mut bytes);
+        assert_eq!(
+            bytes,
+            [
+                0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+                0xbd, 0x28
+            ]
+        );
+    }
+
+    #[test]
+    fn chacha_output_does_not_depend_on_buffer_sizes() {
+        let mut whole = [0; 150];
+        ChaChaRng::seed_from_u64(42).fill(&mut whole);
+
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let mut split = [0; 150];
+        rng.fill(&mut split[..10]);
+        rng.fill(&mut split[10..70]);
+        rng.fill(&mut split[70..]);
+        assert_eq!(&whole[..], &split[..]);
+        assert_eq!(rng.position(), 150);
+    }
+
+    #[test]
+    fn denied_entropy_fails() {
+        assert_eq!(Entropy::Deny.fill(&mut [0; 4]), Err(EntropyError::Denied));
+    }
+}
@@ -0,0 +1,115 @@
+//! The extensions of WASI supported by the runtime, which programs
+//! discover with the `wasix_get_capabilities` syscall.
+//!
+//! A program built for newer runtimes checks the extensions it relies on
+//! before calling them, so that it can do without the missing ones
+//! instead of failing on older runtimes. Hosts providing their own
+//! syscalls advertise them with [`WasiStateBuilder::extension`].
+//!
+//! [`WasiStateBuilder::extension`]: crate::WasiStateBuilder::extension
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The version of the format of the capabilities.
+pub const CAPABILITIES_VERSION: u32 = 1;
+
+/// The extensions the runtime always supports, with their version.
+pub const BUILTIN_EXTENSIONS: &[(&str, u32)] = &[("wasix_get_capabilities", 1)];
+
+/// The extensions of WASI advertised to a program, by name, with their
+/// version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasiExtensions {
+    extensions: BTreeMap<String, u32>,
+}
+
+impl Default for WasiExtensions {
+    fn default() -> Self {
+        Self {
+            extensions: BUILTIN_EXTENSIONS
+                .iter()
+                .map(|(name, version)| (name.to_string(), *version))
+                .collect(),
+        }
+    }
+}
+
+impl WasiExtensions {
+    /// Creates the set of the [`BUILTIN_EXTENSIONS`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advertises the extension `name` at `version`, replacing its
+    /// previous version.
+    pub fn insert(&mut self, name: &str, version: u32) {
+        self.extensions.insert(name.to_string(), version);
+    }
+
+    /// Returns the advertised version of the extension `name`.
+    pub fn version(&self, name: &str) -> Option<u32> {
+        self.extensions.get(name).copied()
+    }
+
+    /// Returns the advertised extensions, by name, with their version.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.extensions
+            .iter()
+            .map(|(name, version)| (name.as_str(), *version))
+    }
+
+    /// Returns the capabilities returned by `wasix_get_capabilities`: a
+    /// JSON object like
+    /// `{"version":1,"extensions":{"wasix_get_capabilities":1}}`.
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"version\":{},\"extensions\":{{", CAPABILITIES_VERSION);
+        for (i, (name, version)) in self.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_json_string(&mut json, name);
+            write!(json, ":{}", version).unwrap();
+        }
+        json.push_str("}}");
+        json
+    }
+}
+
+fn write_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capabilities_are_json() {
+        let mut extensions = WasiExtensions::new();
+        assert_eq!(
+            extensions.to_json(),
+            r#"{"version":1,"extensions":{"wasix_get_capabilities":1}}"#
+        );
+        extensions.insert("acme_kv", 2);
+        extensions.insert("say \"hi\"\n", 1);
+        assert_eq!(extensions.version("acme_kv"), Some(2));
+        assert_eq!(
+            extensions.to_json(),
+            concat!(
+                r#"{"version":1,"extensions":{"acme_kv":2,"say \"hi\"\u000a":1,"#,
+                r#""wasix_get_capabilities":1}}"#
+            )
+        );
+    }
+}
@@ -0,0 +1,416 @@
+//! The HTTP client capability of WASI programs, the `wasix_http_client`
+//! extension.
+//!
+//! The requests of the program are sent by the host, through the
+//! [`HttpClient`] of the embedder, so that the program doesn't need a
+//! socket and TLS stack of its own. The [`HttpAccess`] restricts the
+//! requests to allowed hosts, and adds the headers of the host (usually
+//! credentials) that the program never sees.
+//!
+//! The program sends a request with `wasix_http_request`, and reads the
+//! body of the response from the file descriptor it returns, as it
+//! arrives.
+
+use crate::state::{WasiFile, WasiFsError};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read, Seek, Write};
+use std::sync::Arc;
+use url::Url;
+
+/// A request of a WASI program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    /// The method, like `GET`.
+    pub method: String,
+    /// The absolute `http` or `https` URL, as parsed by the runtime to
+    /// check its host: the client must not parse the URL of the program
+    /// again.
+    pub url: Url,
+    /// The headers, by name and value.
+    pub headers: Vec<(String, String)>,
+    /// The body, empty if none.
+    pub body: Vec<u8>,
+}
+
+/// The response to an [`HttpRequest`].
+pub struct HttpResponse {
+    /// The status code.
+    pub status: u16,
+    /// The headers, by name and value.
+    pub headers: Vec<(String, String)>,
+    /// The body, read by the program as it needs it.
+    pub body: Box<dyn Read + Send>,
+}
+
+impl fmt::Debug for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+/// The HTTP client of the host sending the requests of WASI programs.
+///
+/// The client is shared by the threads of the program, which may send
+/// requests concurrently.
+pub trait HttpClient: Send + Sync {
+    /// Sends `request`, and returns the response once its headers are
+    /// received.
+    fn send(&self, request: HttpRequest) -> io::Result<HttpResponse>;
+}
+
+/// The HTTP access of a WASI program, set with
+/// [`WasiStateBuilder::http`].
+///
+/// No host is allowed by default.
+///
+/// [`WasiStateBuilder::http`]: crate::WasiStateBuilder::http
+pub struct HttpAccess {
+    client: Arc<dyn HttpClient>,
+    allowed_hosts: Vec<String>,
+    headers: Vec<(String, String, String)>,
+}
+
+impl HttpAccess {
+    /// Creates an access sending the requests with `client`.
+    pub fn new(client: impl HttpClient + 'static) -> Self {
+        Self {
+            client: Arc::new(client),
+            allowed_hosts: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Allows the requests to `host`, or to its subdomains if it's like
+    /// `*.example.com`.
+    pub fn allow_host(mut self, host: &str) -> Self {
+        self.allowed_hosts.push(host.to_ascii_lowercase());
+        self
+    }
+
+    /// Adds the header `name` to the requests to `host`, replacing the
+    /// header of the program if any. `host` is matched like in
+    /// [`HttpAccess::allow_host`].
+    pub fn header(mut self, host: &str, name: &str, value: &str) -> Self {
+        self.headers.push((
+            host.to_ascii_lowercase(),
+            name.to_string(),
+            value.to_string(),
+        ));
+        self
+    }
+
+    /// Checks that `request` is allowed, and adds the headers of the host.
+    pub(crate) fn authorize(
+        &self,
+        mut request: HttpRequest,
+    ) -> Result<AuthorizedRequest, __wasi_errno_t> {
+        let host = url_host(&request.url).ok_or(__WASI_EINVAL)?;
+        if !self
+            .allowed_hosts
+            .iter()
+            .any(|pattern| host_matches(pattern, host))
+        {
+            return Err(__WASI_EACCES);
+        }
+        for (pattern, name, value) in &self.headers {
+            if host_matches(pattern, host) {
+                request
+                    .headers
+                    .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+                request.headers.push((name.clone(), value.clone()));
+            }
+        }
+        Ok(AuthorizedRequest {
+            client: self.client.clone(),
+            request,
+        })
+    }
+}
+
+/// A request checked by [`HttpAccess::authorize`], sent without holding
+/// the state of the program, as the response may take a while.
+pub(crate) struct AuthorizedRequest {
+    client: Arc<dyn HttpClient>,
+    request: HttpRequest,
+}
+
+impl AuthorizedRequest {
+    pub(crate) fn send(self) -> Result<HttpResponse, __wasi_errno_t> {
+        self.client
+            .send(self.request)
+            .map_err(|error| WasiFsError::from(error).into_wasi_err())
+    }
+}
+
+impl fmt::Debug for HttpAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The values of the headers are usually credentials.
+        let headers = self
+            .headers
+            .iter()
+            .map(|(host, name, _)| (host, name))
+            .collect::<Vec<_>>();
+        f.debug_struct("HttpAccess")
+            .field("allowed_hosts", &self.allowed_hosts)
+            .field("headers", &headers)
+            .finish()
+    }
+}
+
+/// Returns the host of an absolute `http` or `https` URL, lowercase and
+/// without the brackets of IPv6 addresses.
+fn url_host(url: &Url) -> Option<&str> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    // Credentials in the URL could hide the actual host from a careless
+    // reader, and the host injects its own.
+    if !url.username().is_empty() || url.password().is_some() {
+        return None;
+    }
+    let host = url.host_str()?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|ipv6| ipv6.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return None;
+    }
+    Some(host)
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .map_or(false, |subdomain| subdomain.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+/// Parses the head of a request written by the program: the method and
+/// the URL on the first line, then a header per line, like
+///
+/// ```text
+/// POST https://api.example.com/v1/items
+/// Content-Type: application/json
+/// ```
+pub(crate) fn parse_request_head(head: &str, body: Vec<u8>) -> Option<HttpRequest> {
+    let mut lines = head.lines().filter(|line| !line.is_empty());
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let url = Url::parse(request_line.next()?).ok()?;
+    if method.is_empty() || request_line.next().is_some() {
+        return None;
+    }
+    let headers = lines
+        .map(|line| {
+            let colon = line.find(':')?;
+            let name = line[..colon].trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some((name.to_string(), line[colon + 1..].trim().to_string()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(HttpRequest {
+        method,
+        url,
+        headers,
+        body,
+    })
+}
+
+/// The read-only file the program reads the body of a response from.
+///
+/// The body is not serialized: a deserialized response is empty.
+#[derive(Serialize, Deserialize)]
+pub struct HttpResponseFile {
+    status: u16,
+    headers: Vec<(String, String)>,
+    #[serde(skip)]
+    body: Option<Box<dyn Read + Send>>,
+}
+
+impl HttpResponseFile {
+    pub(crate) fn new(response: HttpResponse) -> Self {
+        Self {
+            status: response.status,
+            headers: response.headers,
+            body: Some(response.body),
+        }
+    }
+
+    /// Returns the status code of the response.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the headers of the response as written to the program, a
+    /// `name: value` per line.
+    pub fn headers_text(&self) -> String {
+        self.headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\n", name, value))
+            .collect()
+    }
+}
+
+impl fmt::Debug for HttpResponseFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpResponseFile")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+impl Read for HttpResponseFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.body {
+            Some(body) => body.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Seek for HttpResponseFile {
+    fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not seek an HTTP response",
+        ))
+    }
+}
+
+impl Write for HttpResponseFile {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not write to an HTTP response",
+        ))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for HttpResponseFile {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+    fn last_modified(&self) -> u64 {
+        0
+    }
+    fn created_time(&self) -> u64 {
+        0
+    }
+    fn size(&self) -> u64 {
+        0
+    }
+    fn set_len(&mut self, _new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        Ok(())
+    }
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        // The body arrives as it's read.
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Answers every request with the headers it received.
+    #[derive(Default)]
+    struct EchoClient {
+        requests: Arc<Mutex<Vec<HttpRequest>>>,
+    }
+
+    impl HttpClient for EchoClient {
+        fn send(&self, request: HttpRequest) -> io::Result<HttpResponse> {
+            self.requests.lock().unwrap().push(request);
+            Ok(HttpResponse {
+                status: 200,
+                headers: vec![("content-length".to_string(), "2".to_string())],
+                body: Box::new(io::Cursor::new(b"ok".to_vec())),
+            })
+        }
+    }
+
+    #[test]
+    fn requests_are_restricted_to_the_allowed_hosts() {
+        let access = HttpAccess::new(EchoClient::default())
+            .allow_host("api.example.com")
+            .allow_host("*.cdn.example.com");
+        let send = |url: &str| {
+            let head = format!("GET {}\n", url);
+            access
+                .authorize(parse_request_head(&head, Vec::new()).unwrap())
+                .and_then(AuthorizedRequest::send)
+                .map(|response| response.status)
+        };
+        assert_eq!(send("https://API.example.com:443/v1?q=1"), Ok(200));
+        assert_eq!(send("http://eu.cdn.example.com/logo.png"), Ok(200));
+        assert_eq!(send("https://cdn.example.com/"), Err(__WASI_EACCES));
+        assert_eq!(send("https://evilapi.example.com/"), Err(__WASI_EACCES));
+        assert_eq!(
+            send("https://api.example.com@evil.com/"),
+            Err(__WASI_EINVAL)
+        );
+        assert_eq!(send("ftp://api.example.com/"), Err(__WASI_EINVAL));
+        // The URL is parsed once, by the runtime: the client can't end up
+        // with another host than the checked one.
+        assert_eq!(
+            send("https://evil.com\\@api.example.com/"),
+            Err(__WASI_EACCES)
+        );
+        for url in &["/v1/items", "https://api.example.com%2Fevil.com/"] {
+            let head = format!("GET {}\n", url);
+            assert!(parse_request_head(&head, Vec::new()).is_none());
+        }
+    }
+
+    #[test]
+    fn host_headers_replace_the_ones_of_the_program() {
+        let client = EchoClient::default();
+        let requests = client.requests.clone();
+        let access = HttpAccess::new(client)
+            .allow_host("api.example.com")
+            .header("api.example.com", "Authorization", "Bearer secret");
+        let head = "POST https://api.example.com/items\n\
+                    authorization: Bearer guess\n\
+                    Content-Type: text/plain\n";
+        let request = parse_request_head(head, b"item".to_vec()).unwrap();
+        let request = access.authorize(request).unwrap();
+        let mut response = HttpResponseFile::new(request.send().unwrap());
+
+        let sent = requests.lock().unwrap().pop().unwrap();
+        assert_eq!(sent.method, "POST");
+        assert_eq!(sent.url.as_str(), "https://api.example.com/items");
+        assert_eq!(
+            sent.headers,
+            vec![
+                ("Content-Type".to_string(), "text/plain".to_string()),
+                ("Authorization".to_string(), "Bearer secret".to_string()),
+            ]
+        );
+        assert_eq!(sent.body, b"item");
+        assert_eq!(response.headers_text(), "content-length: 2\n");
+        let mut body = String::new();
+        response.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "ok");
+        assert!(!format!("{:?}", access).contains("secret"));
+    }
+}
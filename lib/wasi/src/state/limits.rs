@@ -0,0 +1,54 @@
+//! Limits on the arguments and environment of WASI programs.
+//!
+//! The arguments and environment variables are copied into the memory of
+//! the program when it starts, so large blocks inflate the memory of every
+//! instance.
+
+use serde::{Deserialize, Serialize};
+
+/// The default limit of the arguments, and of the environment: 1 MiB.
+pub const DEFAULT_MAX_ARGS_SIZE: u32 = 1024 * 1024;
+
+/// The limits on the total sizes of the arguments and of the environment
+/// of a WASI program, set with [`WasiStateBuilder::args_limits`].
+///
+/// A size is the one reported by `args_sizes_get` and
+/// `environ_sizes_get`: the sum of the lengths of the strings, each with
+/// its nul terminator. Building a [`WasiState`] exceeding a limit fails,
+/// and the syscalls return `EINVAL` if the state was modified to exceed
+/// it afterwards.
+///
+/// [`WasiStateBuilder::args_limits`]: crate::WasiStateBuilder::args_limits
+/// [`WasiState`]: crate::WasiState
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArgsLimits {
+    /// The maximum size of the arguments, program name included.
+    pub max_args_size: u32,
+    /// The maximum size of the environment variables, as `KEY=value`.
+    pub max_envs_size: u32,
+}
+
+impl ArgsLimits {
+    /// No limits, but the sizes must still fit the 32-bit sizes of WASI.
+    pub fn unlimited() -> Self {
+        Self {
+            max_args_size: u32::MAX,
+            max_envs_size: u32::MAX,
+        }
+    }
+}
+
+impl Default for ArgsLimits {
+    fn default() -> Self {
+        Self {
+            max_args_size: DEFAULT_MAX_ARGS_SIZE,
+            max_envs_size: DEFAULT_MAX_ARGS_SIZE,
+        }
+    }
+}
+
+/// The size of `strings` once copied into the memory of the program, with
+/// their nul terminators.
+pub(crate) fn strings_size(strings: &[Vec<u8>]) -> u64 {
+    strings.iter().map(|string| string.len() as u64 + 1).sum()
+}
@@ -0,0 +1,215 @@
+//! Connections accepted by the host and handed over to WASI programs
+//! serving them, see [`SocketListener`].
+
+use crate::state::{HostSocket, WasiFile, WasiFsError};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+
+/// A listening socket of a WASI program, whose connections are accepted
+/// by the host and handed over to the program.
+///
+/// The program accepts the connections with `sock_accept`, like the ones
+/// of a listening socket of its own. The host keeps a clone of the
+/// listener, and hands each connection over with
+/// [`SocketListener::connect`], which returns the host end of a Unix
+/// socket pair: the bytes written to it are received by the program, and
+/// the bytes sent by the program are read from it. Both ends are real
+/// sockets, so the bodies stream through the kernel buffers without being
+/// buffered whole, and the program waits for them with `poll_oneoff`.
+///
+/// This lets an HTTP server of the host front the program, for example by
+/// copying the bytes of the connections it accepts:
+///
+/// ```ignore
+/// let listener = SocketListener::new()?;
+/// let state = WasiState::new("server").socket_listener("http", listener.clone()).build()?;
+/// // ...
+/// loop {
+///     let (mut client, _) = tcp_listener.accept().await?;
+///     let guest = tokio::net::UnixStream::from_std(listener.connect()?)?;
+///     tokio::spawn(async move {
+///         let mut guest = guest;
+///         tokio::io::copy_bidirectional(&mut client, &mut guest).await
+///     });
+/// }
+/// ```
+///
+/// The listener is not serialized: a deserialized listener has no
+/// connections, and can't get any.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SocketListener {
+    #[serde(skip)]
+    shared: Option<Arc<Shared>>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// The program ends of the connections not accepted yet.
+    pending: Mutex<VecDeque<UnixStream>>,
+    /// Holds a byte per pending connection, so that the listener is
+    /// readable, for `poll_oneoff`, while a connection is pending.
+    ready_reader: UnixStream,
+    ready_writer: UnixStream,
+}
+
+impl SocketListener {
+    /// Creates a listener without connections.
+    pub fn new() -> io::Result<Self> {
+        let (ready_reader, ready_writer) = UnixStream::pair()?;
+        ready_reader.set_nonblocking(true)?;
+        // `connect` writes while holding the pending connections, which
+        // `accept` needs to drain the bytes.
+        ready_writer.set_nonblocking(true)?;
+        Ok(Self {
+            shared: Some(Arc::new(Shared {
+                pending: Mutex::new(VecDeque::new()),
+                ready_reader,
+                ready_writer,
+            })),
+        })
+    }
+
+    /// Hands a new connection over to the program, and returns the host
+    /// end of it.
+    ///
+    /// The connection is pending until the program accepts it. Dropping
+    /// or shutting down the host end closes the connection for the
+    /// program.
+    ///
+    /// Fails with [`io::ErrorKind::WouldBlock`], like a full backlog, if
+    /// the program lets so many connections pend that the listener can't
+    /// keep track of them.
+    pub fn connect(&self) -> io::Result<UnixStream> {
+        let shared = self.shared()?;
+        let (host, program) = UnixStream::pair()?;
+        // The byte is written with the connection, so that they are never
+        // out of sync for `accept`.
+        let mut pending = shared.pending.lock().unwrap();
+        (&shared.ready_writer).write_all(&[0])?;
+        pending.push_back(program);
+        Ok(host)
+    }
+
+    /// Returns the number of connections the program hasn't accepted yet.
+    pub fn pending_connections(&self) -> usize {
+        self.shared
+            .as_ref()
+            .map_or(0, |shared| shared.pending.lock().unwrap().len())
+    }
+
+    /// Accepts the first pending connection, or fails with
+    /// [`io::ErrorKind::WouldBlock`] if there's none.
+    pub(crate) fn accept(&self) -> io::Result<HostSocket> {
+        let shared = self.shared()?;
+        let mut pending = shared.pending.lock().unwrap();
+        let program = pending
+            .pop_front()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::WouldBlock))?;
+        (&shared.ready_reader).read_exact(&mut [0])?;
+        HostSocket::from_unix(program)
+    }
+
+    fn shared(&self) -> io::Result<&Shared> {
+        self.shared
+            .as_deref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "detached listener"))
+    }
+}
+
+impl Read for SocketListener {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::NotConnected))
+    }
+}
+
+impl Seek for SocketListener {
+    fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not seek a socket",
+        ))
+    }
+}
+
+impl Write for SocketListener {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::NotConnected))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for SocketListener {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+    fn last_modified(&self) -> u64 {
+        0
+    }
+    fn created_time(&self) -> u64 {
+        0
+    }
+    fn size(&self) -> u64 {
+        0
+    }
+    fn set_len(&mut self, _new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        Ok(())
+    }
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        Ok(0)
+    }
+    fn get_raw_fd(&self) -> Option<i32> {
+        self.shared
+            .as_ref()
+            .map(|shared| shared.ready_reader.as_raw_fd())
+    }
+    fn is_socket(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::IoSlice;
+
+    #[test]
+    fn connections_are_handed_over_in_order() {
+        let listener = SocketListener::new().unwrap();
+        let program_listener = listener.clone();
+        let error = program_listener.accept().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+
+        let mut first = listener.connect().unwrap();
+        let mut second = listener.connect().unwrap();
+        assert_eq!(program_listener.pending_connections(), 2);
+        first.write_all(b"first").unwrap();
+        second.write_all(b"second").unwrap();
+
+        let mut buf = [0; 16];
+        let mut accepted = program_listener.accept().unwrap();
+        assert_eq!(accepted.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"first");
+        accepted
+            .write_vectored(&[IoSlice::new(b"200 "), IoSlice::new(b"OK")])
+            .unwrap();
+        first.read_exact(&mut buf[..6]).unwrap();
+        assert_eq!(&buf[..6], b"200 OK");
+
+        let mut accepted = program_listener.accept().unwrap();
+        assert_eq!(accepted.read(&mut buf).unwrap(), 6);
+        assert_eq!(program_listener.pending_connections(), 0);
+        assert!(program_listener.accept().is_err());
+    }
+}
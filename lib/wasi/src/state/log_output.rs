@@ -0,0 +1,294 @@
+//! Routing the output of WASI programs to the logs of the host.
+//!
+//! With [`WasiStateBuilder::log_output`], the `stdout` and `stderr` of the
+//! program are [`LogFile`]s: every line the program writes becomes a
+//! `tracing` event (and a `log` record), with the pid and the module name
+//! of the program as fields, so that the output of many programs shows up
+//! interleaved in the logs of the host. The pid is the one the program
+//! sees in its `/proc`, see [`ProcFs`].
+//!
+//! [`WasiStateBuilder::log_output`]: crate::WasiStateBuilder::log_output
+//! [`ProcFs`]: crate::ProcFs
+
+use super::procfs::PID;
+use crate::state::{WasiFile, WasiFsError};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Seek, Write};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How the output of a WASI program is logged, see
+/// [`WasiStateBuilder::log_output`].
+///
+/// [`WasiStateBuilder::log_output`]: crate::WasiStateBuilder::log_output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogOutput {
+    /// The module name logged with every line, the program name if
+    /// `None`.
+    pub module_name: Option<String>,
+    /// The number of lines logged per second and per stream. The lines
+    /// over the limit are dropped, and their number is logged once the
+    /// next second starts.
+    pub max_lines_per_second: u32,
+    /// The longer lines are logged in several parts.
+    pub max_line_len: usize,
+}
+
+impl Default for LogOutput {
+    fn default() -> Self {
+        Self {
+            module_name: None,
+            max_lines_per_second: 1_000,
+            max_line_len: 4096,
+        }
+    }
+}
+
+/// The output streams of a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogStream {
+    /// `stdout`, logged at the `INFO` level.
+    Stdout,
+    /// `stderr`, logged at the `WARN` level.
+    Stderr,
+}
+
+/// A line of output, or the number of lines dropped by the rate limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LogLine {
+    Line(String),
+    Dropped(u64),
+}
+
+/// A write-only file logging the lines written to it, see the
+/// [module documentation](self).
+///
+/// An incomplete last line is logged when the file is flushed or dropped.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogFile {
+    stream: LogStream,
+    pid: u32,
+    module_name: String,
+    config: LogOutput,
+    buffer: Vec<u8>,
+    #[serde(skip)]
+    window_start: Option<Instant>,
+    #[serde(skip)]
+    window_lines: u32,
+    #[serde(skip)]
+    dropped_lines: u64,
+}
+
+impl LogFile {
+    /// Creates the files logging the `stdout` and `stderr` of the program
+    /// `program_name`.
+    pub fn for_program(program_name: &str, config: &LogOutput) -> (Self, Self) {
+        let module_name = config
+            .module_name
+            .clone()
+            .unwrap_or_else(|| program_name.to_string());
+        let file = |stream| Self {
+            stream,
+            pid: PID,
+            module_name: module_name.clone(),
+            config: config.clone(),
+            buffer: Vec::new(),
+            window_start: None,
+            window_lines: 0,
+            dropped_lines: 0,
+        };
+        (file(LogStream::Stdout), file(LogStream::Stderr))
+    }
+
+    /// Returns the pid logged with the lines.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Splits the buffered output into lines, rate limited at `now`.
+    fn take_lines(&mut self, now: Instant, flush: bool) -> Vec<LogLine> {
+        let max_line_len = self.config.max_line_len.max(1);
+        let mut lines = Vec::new();
+        loop {
+            let newline = self.buffer.iter().position(|&b| b == b'\n');
+            let len = match newline {
+                Some(position) if position <= max_line_len => position + 1,
+                _ if self.buffer.len() > max_line_len => max_line_len,
+                _ if flush && !self.buffer.is_empty() => self.buffer.len(),
+                _ => break,
+            };
+            let line = self.buffer.drain(..len).collect::<Vec<_>>();
+            if let Some(dropped) = self.start_window(now) {
+                lines.push(LogLine::Dropped(dropped));
+            }
+            if self.window_lines < self.config.max_lines_per_second {
+                self.window_lines += 1;
+                let line = String::from_utf8_lossy(&line);
+                lines.push(LogLine::Line(
+                    line.trim_end_matches(&['\n', '\r'][..]).to_string(),
+                ));
+            } else {
+                self.dropped_lines += 1;
+            }
+        }
+        if flush && self.dropped_lines > 0 {
+            lines.push(LogLine::Dropped(std::mem::take(&mut self.dropped_lines)));
+        }
+        lines
+    }
+
+    /// Starts a new rate limit window if the current one is over, and
+    /// returns the number of lines it dropped.
+    fn start_window(&mut self, now: Instant) -> Option<u64> {
+        match self.window_start {
+            Some(start) if now.duration_since(start) < Duration::from_secs(1) => return None,
+            _ => {}
+        }
+        self.window_start = Some(now);
+        self.window_lines = 0;
+        Some(std::mem::take(&mut self.dropped_lines)).filter(|&dropped| dropped > 0)
+    }
+
+    fn log(&mut self, flush: bool) {
+        for line in self.take_lines(Instant::now(), flush) {
+            let (pid, module) = (self.pid, self.module_name.as_str());
+            match (self.stream, line) {
+                (LogStream::Stdout, LogLine::Line(line)) => {
+                    info!(target: "wasi::stdout", pid, module, "{}", line)
+                }
+                (LogStream::Stderr, LogLine::Line(line)) => {
+                    warn!(target: "wasi::stderr", pid, module, "{}", line)
+                }
+                (stream, LogLine::Dropped(dropped)) => warn!(
+                    target: "wasi::output",
+                    pid,
+                    module,
+                    "{} lines of {:?} dropped by the rate limit",
+                    dropped,
+                    stream
+                ),
+            }
+        }
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        self.log(true);
+    }
+}
+
+impl Read for LogFile {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not read from a log file",
+        ))
+    }
+}
+
+impl Seek for LogFile {
+    fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not seek a log file",
+        ))
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.log(false);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.log(true);
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for LogFile {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+    fn last_modified(&self) -> u64 {
+        0
+    }
+    fn created_time(&self) -> u64 {
+        0
+    }
+    fn size(&self) -> u64 {
+        0
+    }
+    fn set_len(&mut self, _new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        Ok(())
+    }
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn log_file(max_lines_per_second: u32, max_line_len: usize) -> LogFile {
+        let config = LogOutput {
+            max_lines_per_second,
+            max_line_len,
+            ..LogOutput::default()
+        };
+        LogFile::for_program("program", &config).0
+    }
+
+    fn line(line: &str) -> LogLine {
+        LogLine::Line(line.to_string())
+    }
+
+    #[test]
+    fn output_is_split_into_lines() {
+        let mut file = log_file(100, 8);
+        let now = Instant::now();
+        file.buffer.extend_from_slice(b"hello\r\nwor");
+        assert_eq!(file.take_lines(now, false), vec![line("hello")]);
+        file.buffer.extend_from_slice(b"ld\n0123456789\npartial");
+        assert_eq!(
+            file.take_lines(now, false),
+            vec![line("world"), line("01234567"), line("89")]
+        );
+        assert_eq!(file.take_lines(now, true), vec![line("partial")]);
+        assert!(file.buffer.is_empty());
+    }
+
+    #[test]
+    fn lines_over_the_rate_limit_are_dropped() {
+        let mut file = log_file(2, 100);
+        let now = Instant::now();
+        file.buffer.extend_from_slice(b"a\nb\nc\nd\n");
+        assert_eq!(file.take_lines(now, false), vec![line("a"), line("b")]);
+        file.buffer.extend_from_slice(b"e\n");
+        assert_eq!(file.take_lines(now, false), vec![]);
+        file.buffer.extend_from_slice(b"f\n");
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            file.take_lines(later, false),
+            vec![LogLine::Dropped(3), line("f")]
+        );
+    }
+
+    #[test]
+    fn programs_log_the_pid_of_their_proc_fs() {
+        let config = LogOutput::default();
+        let (stdout, stderr) = LogFile::for_program("program", &config);
+        assert_eq!(stdout.pid(), PID);
+        assert_eq!(stderr.pid(), PID);
+        assert_eq!(stdout.module_name, "program");
+    }
+}
@@ -0,0 +1,264 @@
+//! Manifests of the expected content of the file systems mounted for WASI
+//! programs.
+//!
+//! A [`FsManifest`] lists every file and directory of the preopened
+//! directories, with the hashes of the files. Verifying it before starting
+//! a program guarantees the program sees exactly the declared file system,
+//! for reproducible and attestable environments.
+
+use super::{Kind, WasiFs, VIRTUAL_ROOT_FD};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The BLAKE3 hash of the content of a file.
+pub type FileHash = [u8; 32];
+
+/// Returns the hash of `content` as recorded in a [`FsManifest`].
+pub fn hash_file_content(content: &[u8]) -> FileHash {
+    blake3::hash(content).into()
+}
+
+/// An entry of a [`FsManifest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ManifestEntry {
+    /// A directory.
+    Dir,
+    /// A file, with the hash of its content.
+    File(FileHash),
+    /// A symbolic link, with the hash of its target path.
+    Symlink(FileHash),
+}
+
+impl fmt::Display for ManifestEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dir => f.write_str("directory"),
+            Self::File(hash) => {
+                f.write_str("file ")?;
+                write_hash(f, hash)
+            }
+            Self::Symlink(hash) => {
+                f.write_str("symlink ")?;
+                write_hash(f, hash)
+            }
+        }
+    }
+}
+
+/// The complete expected content of the preopened directories of a WASI
+/// program, verified with [`WasiStateBuilder::fs_manifest`].
+///
+/// The paths are the ones seen by the program from the virtual root, like
+/// `data/config.json` for the file `config.json` of the directory
+/// preopened as `data`. The preopened directories themselves are not
+/// listed. The symbolic links of the host are recorded with their target,
+/// without being followed, so that the link cycles are harmless.
+///
+/// [`WasiStateBuilder::fs_manifest`]: crate::WasiStateBuilder::fs_manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsManifest {
+    entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl FsManifest {
+    /// Creates an empty manifest.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the manifest of the preopened directories of `fs`, as they
+    /// currently are on the host.
+    pub fn snapshot(fs: &WasiFs) -> io::Result<Self> {
+        let mut manifest = Self::new();
+        let root = fs
+            .get_fd(VIRTUAL_ROOT_FD)
+            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "no virtual root"))?
+            .inode;
+        if let Kind::Root { entries } = &fs.inodes[root].kind {
+            for (name, inode) in entries.iter() {
+                if let Kind::Dir { path, .. } = &fs.inodes[*inode].kind {
+                    manifest.add_host_dir(name.trim_end_matches('/'), path)?;
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Declares the directory `path`.
+    pub fn dir(&mut self, path: &str) -> &mut Self {
+        self.entries.insert(normalize(path), ManifestEntry::Dir);
+        self
+    }
+
+    /// Declares the file `path`, whose content has the hash `hash`.
+    pub fn file(&mut self, path: &str, hash: FileHash) -> &mut Self {
+        self.entries
+            .insert(normalize(path), ManifestEntry::File(hash));
+        self
+    }
+
+    /// Declares the symbolic link `path`, whose target is `target`.
+    pub fn symlink(&mut self, path: &str, target: &str) -> &mut Self {
+        self.entries.insert(
+            normalize(path),
+            ManifestEntry::Symlink(hash_file_content(target.as_bytes())),
+        );
+        self
+    }
+
+    /// The declared entries, sorted by path.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &ManifestEntry)> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.as_str(), entry))
+    }
+
+    /// Returns the differences between `self`, the expected content, and
+    /// the preopened directories of `fs`, empty if they match.
+    pub fn diff(&self, fs: &WasiFs) -> io::Result<FsManifestDiff> {
+        let actual = Self::snapshot(fs)?;
+        let mut diff = FsManifestDiff::default();
+        for (path, expected) in self.entries.iter() {
+            match actual.entries.get(path) {
+                None => diff.missing.push(path.clone()),
+                Some(entry) if entry != expected => {
+                    diff.changed.push((path.clone(), *expected, *entry))
+                }
+                Some(_) => {}
+            }
+        }
+        for path in actual.entries.keys() {
+            if !self.entries.contains_key(path) {
+                diff.unexpected.push(path.clone());
+            }
+        }
+        Ok(diff)
+    }
+
+    /// Records the content of the host directory `host_path`, seen by the
+    /// program as `path`.
+    fn add_host_dir(&mut self, path: &str, host_path: &Path) -> io::Result<()> {
+        for dir_entry in fs::read_dir(host_path)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name();
+            let entry_path = format!("{}/{}", path, name.to_string_lossy());
+            let host_entry_path = dir_entry.path();
+            let file_type = fs::symlink_metadata(&host_entry_path)?.file_type();
+            if file_type.is_symlink() {
+                let target = fs::read_link(&host_entry_path)?;
+                self.symlink(&entry_path, &target.to_string_lossy());
+            } else if file_type.is_dir() {
+                self.dir(&entry_path);
+                self.add_host_dir(&entry_path, &host_entry_path)?;
+            } else {
+                // The files are hashed as they are read, whatever their size.
+                let mut hasher = blake3::Hasher::new();
+                io::copy(&mut fs::File::open(&host_entry_path)?, &mut hasher)?;
+                self.file(&entry_path, hasher.finalize().into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_hash(f: &mut fmt::Formatter<'_>, hash: &FileHash) -> fmt::Result {
+    for byte in hash.iter() {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+/// Removes the leading `/` and `./`, and the trailing `/`, of the paths
+/// of a manifest.
+fn normalize(path: &str) -> String {
+    let mut path = path;
+    loop {
+        if let Some(rest) = path.strip_prefix("./") {
+            path = rest;
+        } else if let Some(rest) = path.strip_prefix('/') {
+            path = rest;
+        } else {
+            break;
+        }
+    }
+    path.trim_end_matches('/').to_string()
+}
+
+/// The differences between a [`FsManifest`] and the actual file system.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsManifestDiff {
+    /// The declared entries missing from the file system.
+    pub missing: Vec<String>,
+    /// The entries of the file system not declared in the manifest.
+    pub unexpected: Vec<String>,
+    /// The entries of a different kind or content than declared, with the
+    /// declared and actual entries.
+    pub changed: Vec<(String, ManifestEntry, ManifestEntry)>,
+}
+
+impl FsManifestDiff {
+    /// Returns whether the file system matches the manifest.
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for FsManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for path in self.missing.iter() {
+            writeln!(f, "- {}", path)?;
+        }
+        for path in self.unexpected.iter() {
+            writeln!(f, "+ {}", path)?;
+        }
+        for (path, expected, actual) in self.changed.iter() {
+            writeln!(f, "~ {}: expected {}, found {}", path, expected, actual)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paths_are_normalized() {
+        let mut manifest = FsManifest::new();
+        manifest
+            .dir("/data/")
+            .file("./data/a.txt", hash_file_content(b"a"));
+        let entries = manifest.entries().collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                ("data", &ManifestEntry::Dir),
+                ("data/a.txt", &ManifestEntry::File(hash_file_content(b"a"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_is_displayed_like_a_patch() {
+        let diff = FsManifestDiff {
+            missing: vec!["data/a.txt".to_string()],
+            unexpected: vec!["data/b.txt".to_string()],
+            changed: vec![(
+                "data/c".to_string(),
+                ManifestEntry::Dir,
+                ManifestEntry::File([0xab; 32]),
+            )],
+        };
+        assert_eq!(
+            diff.to_string(),
+            format!(
+                "- data/a.txt\n+ data/b.txt\n~ data/c: expected directory, found file {}\n",
+                "ab".repeat(32)
+            )
+        );
+    }
+}
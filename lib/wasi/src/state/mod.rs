@@ -16,17 +16,45 @@
 #![allow(clippy::cognitive_complexity, clippy::too_many_arguments)]
 
 mod builder;
+mod capabilities;
+mod clock;
+mod entropy;
+mod extensions;
+mod http;
+mod limits;
+#[cfg(unix)]
+mod listener;
+mod log_output;
+mod manifest;
+mod path_hook;
+mod procfs;
+mod socket;
 mod types;
+mod users;
 
 pub use self::builder::*;
+pub use self::capabilities::*;
+pub use self::clock::*;
+pub use self::entropy::*;
+pub use self::extensions::*;
+pub use self::http::*;
+pub use self::limits::*;
+#[cfg(unix)]
+pub use self::listener::*;
+pub use self::log_output::*;
+pub use self::manifest::*;
+pub use self::path_hook::*;
+pub use self::procfs::*;
+pub use self::socket::*;
 pub use self::types::*;
+pub use self::users::*;
 use crate::syscalls::types::*;
 use generational_arena::Arena;
 pub use generational_arena::Index as Inode;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::{
-    borrow::Borrow,
+    borrow::{Borrow, Cow},
     cell::Cell,
     fs,
     io::Write,
@@ -91,8 +119,9 @@ pub enum Kind {
         /// The entries of a directory are lazily filled.
         entries: HashMap<String, Inode>,
     },
-    /// The same as Dir but without the irrelevant bits
-    /// The root is immutable after creation; generally the Kind::Root
+    /// The same as Dir but without the irrelevant bits, for the virtual
+    /// root and the other virtual directories (like `/etc`)
+    /// They are immutable after creation; generally the Kind::Root
     /// branch of whatever code you're writing will be a simpler version of
     /// your Kind::Dir logic
     Root {
@@ -163,6 +192,9 @@ pub struct WasiFs {
     inode_counter: Cell<u64>,
     /// for fds still open after the file has been deleted
     pub orphan_fds: HashMap<Inode, InodeVal>,
+    /// The hook rewriting or denying the paths passed to the syscalls.
+    #[serde(skip)]
+    pub path_hook: Option<Box<dyn PathHook>>,
 }
 
 impl WasiFs {
@@ -428,6 +460,7 @@ impl WasiFs {
             next_fd: Cell::new(3),
             inode_counter: Cell::new(1024),
             orphan_fds: HashMap::new(),
+            path_hook: None,
         };
         wasi_fs.create_stdin();
         wasi_fs.create_stdout();
@@ -491,6 +524,25 @@ impl WasiFs {
         self.std_dev_get_mut(__WASI_STDIN_FILENO)
     }
 
+    /// Flushes every open file, the standard devices included.
+    ///
+    /// Returns the number of files which failed to flush.
+    pub fn flush_all(&mut self) -> usize {
+        let mut failures = 0;
+        for (_, inode) in self.inodes.iter_mut() {
+            if let Kind::File {
+                handle: Some(handle),
+                ..
+            } = &mut inode.kind
+            {
+                if handle.flush().is_err() {
+                    failures += 1;
+                }
+            }
+        }
+        failures
+    }
+
     /// Internal helper function to get a standard device handle.
     /// Expects one of `__WASI_STDIN_FILENO`, `__WASI_STDOUT_FILENO`, `__WASI_STDERR_FILENO`.
     fn std_dev_get(&self, fd: __wasi_fd_t) -> Result<&Option<Box<dyn WasiFile>>, WasiFsError> {
@@ -655,6 +707,88 @@ impl WasiFs {
         }
     }
 
+    /// Creates the virtual directory `name` in the virtual directory
+    /// `parent`, and returns its inode. The directory has no host
+    /// counterpart, and is immutable once its entries are created.
+    pub(crate) fn create_virtual_dir(
+        &mut self,
+        parent: Inode,
+        name: &str,
+    ) -> Result<Inode, WasiFsError> {
+        let stat = __wasi_filestat_t {
+            st_filetype: __WASI_FILETYPE_DIRECTORY,
+            ..__wasi_filestat_t::default()
+        };
+        let kind = Kind::Root {
+            entries: HashMap::new(),
+        };
+        self.create_virtual_entry(parent, name, kind, stat)
+    }
+
+    /// Creates the in-memory file `name` with `content` in the virtual
+    /// directory `parent`, and returns its inode.
+    pub(crate) fn create_virtual_file(
+        &mut self,
+        parent: Inode,
+        name: &str,
+        content: Vec<u8>,
+    ) -> Result<Inode, WasiFsError> {
+        let stat = __wasi_filestat_t {
+            st_filetype: __WASI_FILETYPE_REGULAR_FILE,
+            st_size: content.len() as u64,
+            ..__wasi_filestat_t::default()
+        };
+        let kind = Kind::Buffer { buffer: content };
+        self.create_virtual_entry(parent, name, kind, stat)
+    }
+
+    fn create_virtual_entry(
+        &mut self,
+        parent: Inode,
+        name: &str,
+        kind: Kind,
+        stat: __wasi_filestat_t,
+    ) -> Result<Inode, WasiFsError> {
+        match &self.inodes[parent].kind {
+            Kind::Root { entries } if entries.contains_key(name) => {
+                return Err(WasiFsError::AlreadyExists)
+            }
+            Kind::Root { .. } => {}
+            _ => return Err(WasiFsError::BaseNotDirectory),
+        }
+        let inode = self.create_inode_with_stat(kind, false, name.to_string(), stat);
+        if let Kind::Root { entries } = &mut self.inodes[parent].kind {
+            entries.insert(name.to_string(), inode);
+        }
+        Ok(inode)
+    }
+
+    /// Passes `path`, relative to `dirfd`, to the path hook of `syscall`,
+    /// and returns the path to use.
+    pub(crate) fn hook_path<'a>(
+        &self,
+        syscall: &'static str,
+        dirfd: __wasi_fd_t,
+        path: &'a str,
+    ) -> Result<Cow<'a, str>, __wasi_errno_t> {
+        let hook = match &self.path_hook {
+            Some(hook) => hook,
+            None => return Ok(Cow::Borrowed(path)),
+        };
+        let access = PathAccess {
+            syscall,
+            dirfd,
+            path,
+        };
+        Ok(match hook.access(&access)? {
+            Some(rewritten) => {
+                debug!("=> path hook rewrote {} to {}", path, rewritten);
+                Cow::Owned(rewritten)
+            }
+            None => Cow::Borrowed(path),
+        })
+    }
+
     /// Change the backing of a given file descriptor
     /// Returns the old backing
     /// TODO: add examples
@@ -749,7 +883,7 @@ impl WasiFs {
             // loading inodes as necessary
             'symlink_resolution: while symlink_count < MAX_SYMLINKS {
                 match &mut self.inodes[cur_inode].kind {
-                    Kind::Buffer { .. } => unimplemented!("state::get_inode_at_path for buffers"),
+                    Kind::Buffer { .. } => return Err(__WASI_ENOTDIR),
                     Kind::Dir {
                         ref mut entries,
                         ref path,
@@ -1147,7 +1281,11 @@ impl WasiFs {
         debug!("fdstat: {:?}", fd);
 
         Ok(__wasi_fdstat_t {
-            fs_filetype: match self.inodes[fd.inode].kind {
+            fs_filetype: match &self.inodes[fd.inode].kind {
+                Kind::File {
+                    handle: Some(handle),
+                    ..
+                } if handle.is_socket() => __WASI_FILETYPE_SOCKET_STREAM,
                 Kind::File { .. } => __WASI_FILETYPE_REGULAR_FILE,
                 Kind::Dir { .. } => __WASI_FILETYPE_DIRECTORY,
                 Kind::Symlink { .. } => __WASI_FILETYPE_SYMBOLIC_LINK,
@@ -1453,6 +1591,7 @@ impl WasiFs {
 
     /// Closes an open FD, handling all details such as FD being preopen
     pub(crate) fn close_fd(&mut self, fd: __wasi_fd_t) -> Result<(), __wasi_errno_t> {
+        let is_preopen_fd = self.preopen_fds.contains(&fd);
         let inodeval_mut = self.get_inodeval_mut(fd)?;
         let is_preopened = inodeval_mut.is_preopened;
 
@@ -1499,8 +1638,13 @@ impl WasiFs {
                     return Err(__WASI_EINVAL);
                 }
             }
-            Kind::Root { .. } => return Err(__WASI_EACCES),
-            Kind::Symlink { .. } | Kind::Buffer { .. } => return Err(__WASI_EINVAL),
+            // The virtual directories and files can be opened many times,
+            // only their descriptors are closed.
+            Kind::Root { .. } if is_preopen_fd => return Err(__WASI_EACCES),
+            Kind::Root { .. } | Kind::Buffer { .. } => {
+                self.fd_map.remove(&fd);
+            }
+            Kind::Symlink { .. } => return Err(__WASI_EINVAL),
         }
 
         Ok(())
@@ -1540,6 +1684,23 @@ pub struct WasiState {
     pub fs: WasiFs,
     pub args: Vec<Vec<u8>>,
     pub envs: Vec<Vec<u8>>,
+    /// The randomness, clocks, sleeping, yielding and exiting of the
+    /// program.
+    #[serde(default)]
+    pub capabilities: HostCapabilities,
+    /// The generated `/proc`, if enabled.
+    #[serde(default)]
+    pub proc_fs: Option<ProcFs>,
+    /// The limits on the sizes of `args` and `envs`.
+    #[serde(default)]
+    pub args_limits: ArgsLimits,
+    /// The extensions advertised by `wasix_get_capabilities`.
+    #[serde(default)]
+    pub extensions: WasiExtensions,
+    /// The HTTP access of `wasix_http_request`, if any. It's back to none
+    /// once unfrozen.
+    #[serde(skip)]
+    pub http: Option<HttpAccess>,
 }
 
 impl WasiState {
@@ -1559,6 +1720,20 @@ impl WasiState {
     pub fn unfreeze(bytes: &[u8]) -> Option<Self> {
         bincode::deserialize(bytes).ok()
     }
+
+    /// Regenerates `inode` before it's opened if it's an entry of the
+    /// `/proc` of the program, whose memory is `memory_size` bytes.
+    pub(crate) fn refresh_proc_entry(&mut self, inode: Inode, memory_size: u64) {
+        if let Some(proc_fs) = &self.proc_fs {
+            proc_fs.refresh(
+                inode,
+                &mut self.fs,
+                &self.args,
+                &self.capabilities,
+                memory_size,
+            );
+        }
+    }
 }
 
 pub fn host_file_type_to_wasi_file_type(file_type: fs::FileType) -> __wasi_filetype_t {
@@ -0,0 +1,48 @@
+//! Hooks on the paths passed by WASI programs to the file system.
+//!
+//! A [`PathHook`] sees the path of every path-taking syscall before it's
+//! resolved, and can rewrite it or deny the access, which allows remapping
+//! the file system of each tenant or logging its accesses without
+//! implementing a whole file system.
+
+use crate::syscalls::types::*;
+use std::fmt;
+
+/// A path passed by the program to a syscall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathAccess<'a> {
+    /// The name of the syscall, like `path_open`.
+    pub syscall: &'static str,
+    /// The directory the path is relative to.
+    pub dirfd: __wasi_fd_t,
+    /// The path, as passed by the program.
+    pub path: &'a str,
+}
+
+/// A hook called for every path passed to a syscall, set with
+/// [`WasiStateBuilder::path_hook`].
+///
+/// The syscalls taking two paths, like `path_rename`, call it for each
+/// of them. Closures taking a [`PathAccess`] implement it too.
+///
+/// [`WasiStateBuilder::path_hook`]: crate::WasiStateBuilder::path_hook
+pub trait PathHook: Send {
+    /// Returns the path the syscall uses instead of `access.path`, `None`
+    /// to keep it, or the error the syscall fails with.
+    fn access(&self, access: &PathAccess) -> Result<Option<String>, __wasi_errno_t>;
+}
+
+impl<F> PathHook for F
+where
+    F: Fn(&PathAccess) -> Result<Option<String>, __wasi_errno_t> + Send,
+{
+    fn access(&self, access: &PathAccess) -> Result<Option<String>, __wasi_errno_t> {
+        self(access)
+    }
+}
+
+impl fmt::Debug for dyn PathHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PathHook")
+    }
+}
@@ -0,0 +1,222 @@
+//! A read-only `/proc` describing the WASI program itself.
+//!
+//! Many ported programs read `/proc` to find their pid, their memory usage
+//! or their open files, and fail without it. A [`ProcFs`] synthesizes these
+//! entries in the virtual file system, and regenerates them every time they
+//! are opened. The processes of the host are never exposed.
+
+use super::{HostCapabilities, Inode, Kind, WasiFs, WasiFsError, VIRTUAL_ROOT_FD};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The pid of the WASI program, the only process of its `/proc`. It's
+/// also the pid logged with its output, see [`LogFile`].
+///
+/// [`LogFile`]: super::LogFile
+pub(crate) const PID: u32 = 1;
+
+/// The size of the pages counted in `/proc/self/statm`.
+const PAGE_SIZE: u64 = 4096;
+
+/// The generated entries of `/proc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum ProcEntry {
+    /// `/proc/uptime`, the time since the program started.
+    Uptime,
+    /// `/proc/self/cmdline`, the nul-terminated arguments.
+    Cmdline,
+    /// `/proc/self/status`, the name, pid and memory usage.
+    Status,
+    /// `/proc/self/statm`, the memory usage in pages.
+    Statm,
+    /// `/proc/self/fd`, an entry named after each open file descriptor.
+    FdDir,
+}
+
+/// The `/proc` of a WASI program, enabled with
+/// [`WasiStateBuilder::proc_fs`].
+///
+/// The program is pid 1 and has a single thread. `/proc/self` has the
+/// `cmdline`, `status` and `statm` files, and the `fd` directory listing
+/// the open file descriptors; `/proc/uptime` counts from the creation of
+/// the [`WasiState`].
+///
+/// [`WasiStateBuilder::proc_fs`]: crate::WasiStateBuilder::proc_fs
+/// [`WasiState`]: crate::WasiState
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcFs {
+    /// The time the program started, read like [`now`].
+    started_at: __wasi_timestamp_t,
+    entries: HashMap<Inode, ProcEntry>,
+}
+
+impl ProcFs {
+    /// Creates the `/proc` directory in the virtual root of `fs`.
+    pub(crate) fn create(
+        fs: &mut WasiFs,
+        capabilities: &HostCapabilities,
+    ) -> Result<Self, WasiFsError> {
+        let root = fs
+            .get_fd(VIRTUAL_ROOT_FD)
+            .map_err(WasiFsError::from_wasi_err)?
+            .inode;
+        let proc_dir = fs.create_virtual_dir(root, "proc")?;
+        let self_dir = fs.create_virtual_dir(proc_dir, "self")?;
+
+        let mut entries = HashMap::new();
+        let uptime = fs.create_virtual_file(proc_dir, "uptime", Vec::new())?;
+        entries.insert(uptime, ProcEntry::Uptime);
+        for (name, entry) in [
+            ("cmdline", ProcEntry::Cmdline),
+            ("status", ProcEntry::Status),
+            ("statm", ProcEntry::Statm),
+        ]
+        .iter()
+        {
+            let inode = fs.create_virtual_file(self_dir, name, Vec::new())?;
+            entries.insert(inode, *entry);
+        }
+        let fd_dir = fs.create_virtual_dir(self_dir, "fd")?;
+        entries.insert(fd_dir, ProcEntry::FdDir);
+
+        Ok(Self {
+            started_at: now(capabilities),
+            entries,
+        })
+    }
+
+    /// Regenerates `inode` if it's an entry of `/proc`, before it's
+    /// opened. `memory_size` is the size of the memory of the program, in
+    /// bytes.
+    pub(crate) fn refresh(
+        &self,
+        inode: Inode,
+        fs: &mut WasiFs,
+        args: &[Vec<u8>],
+        capabilities: &HostCapabilities,
+        memory_size: u64,
+    ) {
+        let entry = match self.entries.get(&inode) {
+            Some(entry) => *entry,
+            None => return,
+        };
+        let content = match entry {
+            ProcEntry::Uptime => {
+                let uptime = now(capabilities).saturating_sub(self.started_at);
+                format!(
+                    "{}.{:02} 0.00\n",
+                    uptime / 1_000_000_000,
+                    uptime % 1_000_000_000 / 10_000_000
+                )
+                .into_bytes()
+            }
+            ProcEntry::Cmdline => {
+                let mut cmdline = Vec::new();
+                for arg in args {
+                    cmdline.extend_from_slice(arg);
+                    cmdline.push(0);
+                }
+                cmdline
+            }
+            ProcEntry::Status => {
+                let kilobytes = memory_size / 1024;
+                format!(
+                    "Name:\t{}\nState:\tR (running)\nPid:\t{}\nPPid:\t0\nThreads:\t1\n\
+                     VmSize:\t{} kB\nVmRSS:\t{} kB\n",
+                    program_name(args),
+                    PID,
+                    kilobytes,
+                    kilobytes
+                )
+                .into_bytes()
+            }
+            ProcEntry::Statm => {
+                let pages = memory_size / PAGE_SIZE;
+                format!("{} {} 0 0 0 {} 0\n", pages, pages, pages).into_bytes()
+            }
+            ProcEntry::FdDir => {
+                refresh_fd_dir(fs, inode);
+                return;
+            }
+        };
+        set_content(fs, inode, content);
+    }
+}
+
+/// Reads the monotonic clock of `capabilities`, or the host realtime
+/// clock if there is none.
+fn now(capabilities: &HostCapabilities) -> __wasi_timestamp_t {
+    match &capabilities.clock {
+        Some(clock) => clock.time_get(__WASI_CLOCK_MONOTONIC, 1).unwrap_or(0),
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_nanos() as __wasi_timestamp_t)
+            .unwrap_or(0),
+    }
+}
+
+/// The name of the program, like the `comm` of Linux: the file name of
+/// the first argument, truncated to 15 bytes.
+fn program_name(args: &[Vec<u8>]) -> String {
+    let arg0 = args.first().map(|arg| &arg[..]).unwrap_or(&[]);
+    let name = arg0.rsplit(|b| *b == b'/').next().unwrap_or(&[]);
+    String::from_utf8_lossy(&name[..name.len().min(15)])
+        .chars()
+        .filter(|c| *c != '\n')
+        .collect()
+}
+
+fn set_content(fs: &mut WasiFs, inode: Inode, content: Vec<u8>) {
+    let inode_val = &mut fs.inodes[inode];
+    inode_val.stat.st_size = content.len() as u64;
+    if let Kind::Buffer { buffer } = &mut inode_val.kind {
+        *buffer = content;
+    }
+}
+
+/// Lists the open file descriptors in the `fd` directory, each entry
+/// holding the name of the file.
+fn refresh_fd_dir(fs: &mut WasiFs, dir: Inode) {
+    let open_fds = fs
+        .fd_map
+        .iter()
+        .map(|(fd, fd_val)| (fd.to_string(), fs.inodes[fd_val.inode].name.clone()))
+        .collect::<HashMap<_, _>>();
+    let old_entries = match &mut fs.inodes[dir].kind {
+        Kind::Root { entries } => std::mem::take(entries),
+        _ => return,
+    };
+
+    let mut entries = HashMap::new();
+    for (name, inode) in old_entries {
+        if open_fds.contains_key(&name) {
+            entries.insert(name, inode);
+        } else if !fs.fd_map.values().any(|fd_val| fd_val.inode == inode) {
+            // Safe: the entry was removed from the directory, and no file
+            // descriptor refers to it.
+            unsafe { fs.remove_inode(inode) };
+        }
+    }
+    if let Kind::Root {
+        entries: dir_entries,
+    } = &mut fs.inodes[dir].kind
+    {
+        *dir_entries = entries;
+    }
+
+    for (name, file_name) in open_fds {
+        let existing = match &fs.inodes[dir].kind {
+            Kind::Root { entries } => entries.get(&name).cloned(),
+            _ => None,
+        };
+        match existing {
+            Some(inode) => set_content(fs, inode, file_name.into_bytes()),
+            None => {
+                // The name is new in the directory, this can't fail.
+                let _ = fs.create_virtual_file(dir, &name, file_name.into_bytes());
+            }
+        }
+    }
+}
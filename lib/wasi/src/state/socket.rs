@@ -0,0 +1,314 @@
+//! Host sockets exposed to WASI programs, see
+//! [`WasiStateBuilder::socket`].
+//!
+//! The host socket is always non-blocking: `sock_send` and `sock_recv`
+//! move as many bytes as the socket takes in one call, copied through a
+//! buffer of the host, and return `EAGAIN` when it takes none. The
+//! blocking of the program, if its fd doesn't have the `NONBLOCK` flag, is
+//! done by the runtime, waiting for the readiness of the socket with the
+//! state of the program unlocked.
+//!
+//! [`WasiStateBuilder::socket`]: crate::WasiStateBuilder::socket
+
+use crate::state::{host_file_bytes_available, WasiFile, WasiFsError};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::io::{self, IoSlice, IoSliceMut, Read, Seek, Write};
+use std::net::{Shutdown, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+/// The kernel buffers of a socket.
+#[derive(Debug, Clone, Copy)]
+enum Buffer {
+    Send,
+    Recv,
+}
+
+#[cfg(unix)]
+impl Buffer {
+    fn option(self) -> libc::c_int {
+        match self {
+            Self::Send => libc::SO_SNDBUF,
+            Self::Recv => libc::SO_RCVBUF,
+        }
+    }
+}
+
+/// The connected sockets of the host.
+#[derive(Debug)]
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// Forwards a call to the socket of a `Stream`, whatever its kind.
+macro_rules! with_stream {
+    ($stream:expr, $socket:ident => $call:expr) => {
+        match $stream {
+            Stream::Tcp($socket) => $call,
+            #[cfg(unix)]
+            Stream::Unix($socket) => $call,
+        }
+    };
+}
+
+/// A connected socket of the host, TCP or Unix, exposed to a WASI program
+/// as a stream socket.
+///
+/// The socket is not serialized: a deserialized socket is disconnected.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HostSocket {
+    #[serde(skip)]
+    stream: Option<Stream>,
+}
+
+impl HostSocket {
+    /// Wraps `stream`, switching it to non-blocking mode.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream: Some(Stream::Tcp(stream)),
+        })
+    }
+
+    /// Wraps the Unix socket `stream`, switching it to non-blocking mode.
+    ///
+    /// The host end of a [`UnixStream::pair`] hands a connection over to
+    /// the program without going through the network, see
+    /// [`SocketListener`].
+    ///
+    /// [`SocketListener`]: crate::SocketListener
+    #[cfg(unix)]
+    pub fn from_unix(stream: UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream: Some(Stream::Unix(stream)),
+        })
+    }
+
+    /// Creates a handle on the same socket, for the host to tune it or to
+    /// shut it down while the program uses it.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        let stream = match self.stream()? {
+            Stream::Tcp(socket) => Stream::Tcp(socket.try_clone()?),
+            #[cfg(unix)]
+            Stream::Unix(socket) => Stream::Unix(socket.try_clone()?),
+        };
+        Ok(Self {
+            stream: Some(stream),
+        })
+    }
+
+    /// Shuts down the reading half, the writing half, or both halves of
+    /// the socket.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        with_stream!(self.stream()?, socket => socket.shutdown(how))
+    }
+
+    /// Returns the size of the kernel send buffer of the socket.
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        self.get_buffer_size(Buffer::Send)
+    }
+
+    /// Sets the size of the kernel send buffer of the socket. Larger
+    /// buffers let a program send more before `sock_send` returns `EAGAIN`.
+    ///
+    /// The operating system may round or cap the size.
+    pub fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        self.set_buffer_size(Buffer::Send, size)
+    }
+
+    /// Returns the size of the kernel receive buffer of the socket.
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        self.get_buffer_size(Buffer::Recv)
+    }
+
+    /// Sets the size of the kernel receive buffer of the socket.
+    ///
+    /// The operating system may round or cap the size.
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        self.set_buffer_size(Buffer::Recv, size)
+    }
+
+    fn stream(&self) -> io::Result<&Stream> {
+        self.stream
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "disconnected socket"))
+    }
+
+    fn stream_mut(&mut self) -> io::Result<&mut Stream> {
+        self.stream
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "disconnected socket"))
+    }
+
+    #[cfg(unix)]
+    fn raw_fd(&self) -> io::Result<i32> {
+        use std::os::unix::io::AsRawFd;
+        Ok(with_stream!(self.stream()?, socket => socket.as_raw_fd()))
+    }
+
+    #[cfg(unix)]
+    fn get_buffer_size(&self, buffer: Buffer) -> io::Result<usize> {
+        let mut size: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                self.raw_fd()?,
+                libc::SOL_SOCKET,
+                buffer.option(),
+                &mut size as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(size as usize)
+    }
+
+    #[cfg(unix)]
+    fn set_buffer_size(&self, buffer: Buffer, size: usize) -> io::Result<()> {
+        let size = size.min(libc::c_int::max_value() as usize) as libc::c_int;
+        let result = unsafe {
+            libc::setsockopt(
+                self.raw_fd()?,
+                libc::SOL_SOCKET,
+                buffer.option(),
+                &size as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn get_buffer_size(&self, _buffer: Buffer) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "socket buffer sizes are not supported on this platform yet",
+        ))
+    }
+
+    #[cfg(not(unix))]
+    fn set_buffer_size(&self, _buffer: Buffer, _size: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "socket buffer sizes are not supported on this platform yet",
+        ))
+    }
+}
+
+impl Read for HostSocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        with_stream!(self.stream_mut()?, socket => socket.read(buf))
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        with_stream!(self.stream_mut()?, socket => socket.read_vectored(bufs))
+    }
+}
+
+impl Seek for HostSocket {
+    fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not seek a socket",
+        ))
+    }
+}
+
+impl Write for HostSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        with_stream!(self.stream_mut()?, socket => socket.write(buf))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        with_stream!(self.stream_mut()?, socket => socket.write_vectored(bufs))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for HostSocket {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+    fn last_modified(&self) -> u64 {
+        0
+    }
+    fn created_time(&self) -> u64 {
+        0
+    }
+    fn size(&self) -> u64 {
+        0
+    }
+    fn set_len(&mut self, _new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        Err(WasiFsError::PermissionDenied)
+    }
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        Ok(())
+    }
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        match self.get_raw_fd() {
+            Some(fd) => host_file_bytes_available(fd),
+            None => Err(WasiFsError::NotConnected),
+        }
+    }
+
+    #[cfg(unix)]
+    fn get_raw_fd(&self) -> Option<i32> {
+        self.raw_fd().ok()
+    }
+
+    fn is_socket(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn socket_pair() -> (HostSocket, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (HostSocket::new(client).unwrap(), server)
+    }
+
+    #[test]
+    fn vectored_io_goes_through_the_socket() {
+        let (mut socket, mut peer) = socket_pair();
+        let written = socket
+            .write_vectored(&[IoSlice::new(b"hello "), IoSlice::new(b"world")])
+            .unwrap();
+        let mut received = vec![0; written];
+        peer.read_exact(&mut received).unwrap();
+        assert_eq!(&received, &b"hello world"[..written]);
+
+        let mut buf = [0; 4];
+        let error = socket.read(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn buffer_sizes_are_tunable_by_the_host() {
+        let (socket, _peer) = socket_pair();
+        let host = socket.try_clone().unwrap();
+        host.set_send_buffer_size(64 * 1024).unwrap();
+        assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+        host.set_recv_buffer_size(32 * 1024).unwrap();
+        assert!(socket.recv_buffer_size().unwrap() >= 32 * 1024);
+    }
+}
@@ -3,7 +3,7 @@ use crate::syscalls::types::*;
 use serde::{de, Deserialize, Serialize};
 use std::any::Any;
 #[cfg(unix)]
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::{
     fs,
@@ -210,6 +210,12 @@ pub trait WasiFile: fmt::Debug + Send + Write + Read + Seek + 'static + Upcastab
     fn get_raw_fd(&self) -> Option<i32> {
         None
     }
+
+    /// Returns whether the file is a stream socket, the only kind of file `sock_send` and
+    /// `sock_recv` accept.  Default returns `false`
+    fn is_socket(&self) -> bool {
+        false
+    }
 }
 
 // Implementation of `Upcastable` taken from https://users.rust-lang.org/t/why-does-downcasting-not-work-for-subtraits/33286/7 .
@@ -360,37 +366,64 @@ impl PollEventBuilder {
     }
 }
 
+/// Waits until one of the `selfs` files is ready for its requested `events`,
+/// or until `timeout` elapses (`None` waits indefinitely).
+///
+/// Files without an underlying host fd can't be waited on, so they are
+/// always reported as ready for what they were polled for.
+///
+/// Returns the number of files that are ready.
 #[cfg(unix)]
 pub(crate) fn poll(
     selfs: &[&dyn WasiFile],
     events: &[PollEventSet],
     seen_events: &mut [PollEventSet],
+    timeout: Option<std::time::Duration>,
 ) -> Result<u32, WasiFsError> {
     if !(selfs.len() == events.len() && events.len() == seen_events.len()) {
         return Err(WasiFsError::InvalidInput);
     }
-    let mut fds = selfs
-        .iter()
-        .enumerate()
-        .filter_map(|(i, s)| s.get_raw_fd().map(|rfd| (i, rfd)))
-        .map(|(i, host_fd)| libc::pollfd {
-            fd: host_fd,
-            events: poll_event_set_to_platform_poll_events(events[i]),
-            revents: 0,
-        })
-        .collect::<Vec<_>>();
-    let result = unsafe { libc::poll(fds.as_mut_ptr(), selfs.len() as _, 1) };
+    let mut ready = 0;
+    let mut indices = Vec::with_capacity(selfs.len());
+    let mut fds = Vec::with_capacity(selfs.len());
+    for (i, s) in selfs.iter().enumerate() {
+        match s.get_raw_fd() {
+            Some(host_fd) => {
+                indices.push(i);
+                fds.push(libc::pollfd {
+                    fd: host_fd,
+                    events: poll_event_set_to_platform_poll_events(events[i]),
+                    revents: 0,
+                });
+            }
+            None => {
+                seen_events[i] = events[i];
+                ready += 1;
+            }
+        }
+    }
+    let timeout_ms: libc::c_int = match timeout {
+        _ if ready > 0 => 0,
+        // Round up, so that we never wake up before the timeout elapsed.
+        Some(timeout) => {
+            let ms = (timeout.as_nanos() + 999_999) / 1_000_000;
+            ms.min(libc::c_int::max_value() as u128) as libc::c_int
+        }
+        None => -1,
+    };
+    let result = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout_ms) };
 
     if result < 0 {
         // TODO: check errno and return value
         return Err(WasiFsError::IOError);
     }
     // convert result and write back values
-    for (i, fd) in fds.into_iter().enumerate() {
+    for (i, fd) in indices.into_iter().zip(fds.into_iter()) {
         seen_events[i] = platform_poll_events_to_pollevent_set(fd.revents);
     }
     // unwrap is safe because we check for negative values above
-    Ok(result.try_into().unwrap())
+    let result: u32 = result.try_into().unwrap();
+    Ok(result + ready)
 }
 
 #[cfg(not(unix))]
@@ -398,10 +431,47 @@ pub(crate) fn poll(
     _selfs: &[&dyn WasiFile],
     _events: &[PollEventSet],
     _seen_events: &mut [PollEventSet],
-) -> Result<(), WasiFsError> {
+    _timeout: Option<std::time::Duration>,
+) -> Result<u32, WasiFsError> {
     unimplemented!("HostFile::poll in WasiFile is not implemented for non-Unix-like targets yet");
 }
 
+/// Waits until the host fd `host_fd` is ready for its requested `events`,
+/// or until `timeout` elapses.
+///
+/// Unlike [`poll`], it doesn't borrow the file: the caller can unlock the
+/// state while it waits, and look the file up again afterwards.
+#[cfg(unix)]
+pub(crate) fn poll_host_fd(
+    host_fd: i32,
+    events: PollEventSet,
+    timeout: std::time::Duration,
+) -> Result<(), WasiFsError> {
+    let mut fd = libc::pollfd {
+        fd: host_fd,
+        events: poll_event_set_to_platform_poll_events(events),
+        revents: 0,
+    };
+    let timeout_ms = timeout.as_millis().min(libc::c_int::max_value() as u128) as libc::c_int;
+    let result = unsafe { libc::poll(&mut fd, 1, timeout_ms) };
+    if result < 0 && io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+        return Err(WasiFsError::IOError);
+    }
+    Ok(())
+}
+
+/// Without a way to wait on the fd, sleeps for `timeout`: the caller
+/// retries its operation afterwards anyway.
+#[cfg(not(unix))]
+pub(crate) fn poll_host_fd(
+    _host_fd: i32,
+    _events: PollEventSet,
+    timeout: std::time::Duration,
+) -> Result<(), WasiFsError> {
+    std::thread::sleep(timeout);
+    Ok(())
+}
+
 pub trait WasiPath {}
 
 /// A thin wrapper around `std::fs::File`
@@ -671,7 +741,7 @@ impl From<io::Error> for WasiFsError {
 }
 
 #[cfg(unix)]
-fn host_file_bytes_available(host_fd: i32) -> Result<usize, WasiFsError> {
+pub(crate) fn host_file_bytes_available(host_fd: i32) -> Result<usize, WasiFsError> {
     let mut bytes_found = 0 as libc::c_int;
     let result = unsafe { libc::ioctl(host_fd, libc::FIONREAD, &mut bytes_found) };
 
@@ -686,7 +756,7 @@ fn host_file_bytes_available(host_fd: i32) -> Result<usize, WasiFsError> {
 }
 
 #[cfg(not(unix))]
-fn host_file_bytes_available(_raw_fd: i32) -> Result<usize, WasiFsError> {
+pub(crate) fn host_file_bytes_available(_raw_fd: i32) -> Result<usize, WasiFsError> {
     unimplemented!("host_file_bytes_available not yet implemented for non-Unix-like targets.  This probably means the program tried to use wasi::poll_oneoff")
 }
 
@@ -970,6 +1040,195 @@ impl WasiFile for Stdin {
     }
 }
 
+/// A memory segment shared between the host and a WASI program.
+///
+/// The segment is exposed to the WASI program as a regular file, see
+/// [`WasiStateBuilder::shared_segment`]; the host keeps a clone of the
+/// `SharedSegment` and reads or writes the very same bytes without going
+/// through stdio or sockets. Clones share the data, but each one has its
+/// own cursor for the `Read`/`Write`/`Seek` implementations.
+///
+/// The segment never grows past its maximum size, so that the WASI program
+/// can't make the host allocate without bounds: the writes past it fail
+/// with `ENOSPC`.
+///
+/// The data is not serialized: a deserialized segment is detached from the
+/// host and empty.
+///
+/// [`WasiStateBuilder::shared_segment`]: crate::WasiStateBuilder::shared_segment
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SharedSegment {
+    #[serde(skip)]
+    data: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+    #[serde(skip)]
+    cursor: u64,
+    #[serde(skip)]
+    max_size: usize,
+}
+
+impl SharedSegment {
+    /// Creates a zeroed segment of `size` bytes, which can't grow.
+    pub fn new(size: usize) -> Self {
+        Self::with_max_size(size, size)
+    }
+
+    /// Creates a zeroed segment of `size` bytes, which can grow up to
+    /// `max_size` bytes.
+    pub fn with_max_size(size: usize, max_size: usize) -> Self {
+        Self {
+            data: std::sync::Arc::new(std::sync::Mutex::new(vec![0; size])),
+            cursor: 0,
+            max_size: max_size.max(size),
+        }
+    }
+
+    /// Returns the size in bytes the segment can grow up to.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Returns the current size of the segment in bytes.
+    pub fn len(&self) -> usize {
+        self.data.lock().unwrap().len()
+    }
+
+    /// Returns whether the segment is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies bytes starting at `offset` into `buf`, and returns how many
+    /// bytes were copied.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.data.lock().unwrap();
+        let available = data.get(offset..).unwrap_or(&[]);
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        len
+    }
+
+    /// Copies `buf` into the segment starting at `offset`, growing the
+    /// segment if needed.
+    ///
+    /// Fails with [`io::ErrorKind::WriteZero`], without copying anything,
+    /// if the bytes don't fit in the maximum size of the segment.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> io::Result<()> {
+        let end = match offset.checked_add(buf.len()) {
+            Some(end) if end <= self.max_size => end,
+            _ => return Err(segment_full()),
+        };
+        let mut data = self.data.lock().unwrap();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[offset..end].copy_from_slice(buf);
+        Ok(())
+    }
+
+    /// Reads a value of type `T` at `offset`, if it fits in the segment.
+    pub fn get<T: wasmer::ValueType>(&self, offset: usize) -> Option<T> {
+        let data = self.data.lock().unwrap();
+        let bytes = data.get(offset..offset.checked_add(std::mem::size_of::<T>())?)?;
+        // `ValueType` guarantees that any bit pattern is a valid `T`.
+        Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    /// Writes `value` at `offset`, growing the segment if needed, like
+    /// [`SharedSegment::write_at`].
+    pub fn set<T: wasmer::ValueType>(&self, offset: usize, value: T) -> io::Result<()> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(&value as *const T as *const u8, std::mem::size_of::<T>())
+        };
+        self.write_at(offset, bytes)
+    }
+
+    /// Runs `f` with exclusive access to the whole segment.
+    pub fn with_data<R>(&self, f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+        f(&mut self.data.lock().unwrap())
+    }
+}
+
+impl Read for SharedSegment {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read_at(self.cursor as usize, buf);
+        self.cursor += read as u64;
+        Ok(read)
+    }
+}
+
+fn segment_full() -> io::Error {
+    io::Error::new(io::ErrorKind::WriteZero, "the shared segment is full")
+}
+
+impl Write for SharedSegment {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Writes what fits, like a file on a full disk.
+        let cursor = usize::try_from(self.cursor).map_err(|_| segment_full())?;
+        let len = self.max_size.saturating_sub(cursor).min(buf.len());
+        if len == 0 && !buf.is_empty() {
+            return Err(segment_full());
+        }
+        self.write_at(cursor, &buf[..len])?;
+        self.cursor += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SharedSegment {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let new_cursor = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::End(offset) => (self.len() as i64).checked_add(offset).map(|c| c as u64),
+            io::SeekFrom::Current(offset) => {
+                (self.cursor as i64).checked_add(offset).map(|c| c as u64)
+            }
+        };
+        match new_cursor {
+            Some(cursor) if (cursor as i64) >= 0 => {
+                self.cursor = cursor;
+                Ok(cursor)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for SharedSegment {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+    fn last_modified(&self) -> u64 {
+        0
+    }
+    fn created_time(&self) -> u64 {
+        0
+    }
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+    fn set_len(&mut self, new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        if new_size > self.max_size as u64 {
+            return Err(WasiFsError::UnknownError(__WASI_EFBIG));
+        }
+        self.with_data(|data| data.resize(new_size as usize, 0));
+        Ok(())
+    }
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        Ok(())
+    }
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        Ok(self.len().saturating_sub(self.cursor as usize))
+    }
+}
+
 /*
 TODO: Think about using this
 trait WasiFdBacking: std::fmt::Debug {
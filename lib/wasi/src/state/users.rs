@@ -0,0 +1,246 @@
+//! The users and groups seen by WASI programs.
+//!
+//! WASI has no user database, so programs looking up their user (shells,
+//! version control tools, ...) read `/etc/passwd` and `/etc/group`. A
+//! [`UserDatabase`] synthesizes these files in the virtual file system,
+//! without exposing the users of the host.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// A user of the [`UserDatabase`], a line of `/etc/passwd`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    /// The login name.
+    pub name: String,
+    /// The user ID.
+    pub uid: u32,
+    /// The ID of the primary group.
+    pub gid: u32,
+    /// The full name, or any comment.
+    pub gecos: String,
+    /// The home directory.
+    pub home: String,
+    /// The login shell.
+    pub shell: String,
+}
+
+impl User {
+    /// Creates a user with its home in `/home/{name}` and `/bin/sh` as
+    /// shell.
+    pub fn new(name: &str, uid: u32, gid: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            uid,
+            gid,
+            gecos: String::new(),
+            home: format!("/home/{}", name),
+            shell: "/bin/sh".to_string(),
+        }
+    }
+
+    /// Sets the home directory.
+    pub fn home(mut self, home: &str) -> Self {
+        self.home = home.to_string();
+        self
+    }
+
+    /// Sets the login shell.
+    pub fn shell(mut self, shell: &str) -> Self {
+        self.shell = shell.to_string();
+        self
+    }
+}
+
+/// A group of the [`UserDatabase`], a line of `/etc/group`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    /// The group name.
+    pub name: String,
+    /// The group ID.
+    pub gid: u32,
+    /// The names of the users having this group as a supplementary one.
+    pub members: Vec<String>,
+}
+
+impl Group {
+    /// Creates a group without supplementary members.
+    pub fn new(name: &str, gid: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            gid,
+            members: Vec::new(),
+        }
+    }
+}
+
+/// The users and groups of a WASI program, set with
+/// [`WasiStateBuilder::users`].
+///
+/// The program runs as the [current user](UserDatabase::current_user),
+/// which defaults to the first one.
+///
+/// [`WasiStateBuilder::users`]: crate::WasiStateBuilder::users
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserDatabase {
+    users: Vec<User>,
+    groups: Vec<Group>,
+    current_uid: Option<u32>,
+}
+
+impl UserDatabase {
+    /// Creates an empty database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a database with `root` and a single user running the
+    /// program, each in its own group.
+    pub fn single_user(name: &str, uid: u32, gid: u32) -> Self {
+        let mut database = Self::new();
+        database
+            .add_user(User::new("root", 0, 0).home("/root"))
+            .add_group(Group::new("root", 0));
+        if uid != 0 {
+            database
+                .add_user(User::new(name, uid, gid))
+                .add_group(Group::new(name, gid));
+        }
+        database.current_uid = Some(uid);
+        database
+    }
+
+    /// Adds a user, replacing the one with the same ID.
+    pub fn add_user(&mut self, user: User) -> &mut Self {
+        self.users.retain(|existing| existing.uid != user.uid);
+        self.users.push(user);
+        self
+    }
+
+    /// Adds a group, replacing the one with the same ID.
+    pub fn add_group(&mut self, group: Group) -> &mut Self {
+        self.groups.retain(|existing| existing.gid != group.gid);
+        self.groups.push(group);
+        self
+    }
+
+    /// Sets the ID of the user running the program.
+    pub fn set_current_uid(&mut self, uid: u32) -> &mut Self {
+        self.current_uid = Some(uid);
+        self
+    }
+
+    /// The user running the program.
+    pub fn current_user(&self) -> Option<&User> {
+        match self.current_uid {
+            Some(uid) => self.user_by_uid(uid),
+            None => self.users.first(),
+        }
+    }
+
+    /// Looks up a user by ID, like `getpwuid`.
+    pub fn user_by_uid(&self, uid: u32) -> Option<&User> {
+        self.users.iter().find(|user| user.uid == uid)
+    }
+
+    /// Looks up a user by name, like `getpwnam`.
+    pub fn user_by_name(&self, name: &str) -> Option<&User> {
+        self.users.iter().find(|user| user.name == name)
+    }
+
+    /// Looks up a group by ID, like `getgrgid`.
+    pub fn group_by_gid(&self, gid: u32) -> Option<&Group> {
+        self.groups.iter().find(|group| group.gid == gid)
+    }
+
+    /// Looks up a group by name, like `getgrnam`.
+    pub fn group_by_name(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// The content of `/etc/passwd`.
+    pub fn passwd(&self) -> String {
+        let mut passwd = String::new();
+        for user in self.users.iter() {
+            writeln!(
+                passwd,
+                "{}:x:{}:{}:{}:{}:{}",
+                sanitize(&user.name),
+                user.uid,
+                user.gid,
+                sanitize(&user.gecos),
+                sanitize(&user.home),
+                sanitize(&user.shell)
+            )
+            .unwrap();
+        }
+        passwd
+    }
+
+    /// The content of `/etc/group`.
+    pub fn group(&self) -> String {
+        let mut group_file = String::new();
+        for group in self.groups.iter() {
+            let members = group
+                .members
+                .iter()
+                .map(|member| sanitize(member))
+                .collect::<Vec<_>>()
+                .join(",");
+            writeln!(
+                group_file,
+                "{}:x:{}:{}",
+                sanitize(&group.name),
+                group.gid,
+                members
+            )
+            .unwrap();
+        }
+        group_file
+    }
+}
+
+/// Removes the characters that would break the lines of the files.
+fn sanitize(field: &str) -> String {
+    field
+        .chars()
+        .filter(|c| !matches!(c, ':' | ',' | '\n'))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn single_user_database() {
+        let database = UserDatabase::single_user("alice", 1000, 100);
+        assert_eq!(
+            database.passwd(),
+            "root:x:0:0::/root:/bin/sh\nalice:x:1000:100::/home/alice:/bin/sh\n"
+        );
+        assert_eq!(database.group(), "root:x:0:\nalice:x:100:\n");
+        assert_eq!(database.current_user().unwrap().name, "alice");
+        assert_eq!(database.user_by_name("root").unwrap().uid, 0);
+        assert_eq!(database.group_by_gid(100).unwrap().name, "alice");
+
+        let root = UserDatabase::single_user("root", 0, 0);
+        assert_eq!(root.passwd(), "root:x:0:0::/root:/bin/sh\n");
+        assert_eq!(root.current_user().unwrap().home, "/root");
+    }
+
+    #[test]
+    fn fields_cant_break_lines() {
+        let mut database = UserDatabase::new();
+        let mut user = User::new("eve", 1, 1);
+        user.gecos = "Eve:\nroot:x:0:0".to_string();
+        let mut group = Group::new("eve", 1);
+        group.members = vec!["a,b".to_string(), "c".to_string()];
+        database.add_user(user).add_group(group);
+        assert_eq!(
+            database.passwd(),
+            "eve:x:1:1:Everootx00:/home/eve:/bin/sh\n"
+        );
+        assert_eq!(database.group(), "eve:x:1:ab,c\n");
+    }
+}
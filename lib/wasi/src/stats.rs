@@ -0,0 +1,111 @@
+//! Accounting of what a WASI program does: syscalls, I/O and memory.
+//!
+//! The counters live in the [`WasiEnv`](crate::WasiEnv) and are shared by
+//! all its clones; [`WasiEnv::stats`](crate::WasiEnv::stats) takes a
+//! snapshot of them.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The classes of WASI syscalls, by name prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SyscallClass {
+    /// `args_*` and `environ_*`.
+    Args,
+    /// `clock_*`.
+    Clock,
+    /// `fd_*`.
+    Fd,
+    /// `path_*`.
+    Path,
+    /// `poll_oneoff`.
+    Poll,
+    /// `proc_*`.
+    Proc,
+    /// `random_get`.
+    Random,
+    /// `sched_yield`.
+    Sched,
+    /// `sock_*`.
+    Sock,
+}
+
+impl SyscallClass {
+    /// All the classes.
+    pub const ALL: [Self; 9] = [
+        Self::Args,
+        Self::Clock,
+        Self::Fd,
+        Self::Path,
+        Self::Poll,
+        Self::Proc,
+        Self::Random,
+        Self::Sched,
+        Self::Sock,
+    ];
+}
+
+/// A snapshot of the accounting of a WASI program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WasiStats {
+    /// The number of syscalls made, by class. Classes without calls are
+    /// omitted.
+    pub syscalls: BTreeMap<SyscallClass, u64>,
+    /// The bytes read by `fd_read` and `fd_pread`.
+    pub bytes_read: u64,
+    /// The bytes written by `fd_write` and `fd_pwrite`.
+    pub bytes_written: u64,
+    /// The largest memory size seen at a syscall, in bytes.
+    pub peak_memory: u64,
+}
+
+impl WasiStats {
+    /// Returns the total number of syscalls made.
+    pub fn total_syscalls(&self) -> u64 {
+        self.syscalls.values().sum()
+    }
+}
+
+/// The counters behind [`WasiStats`].
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounters {
+    syscalls: [AtomicU64; 9],
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    peak_memory: AtomicU64,
+}
+
+impl StatsCounters {
+    pub(crate) fn syscall(&self, class: SyscallClass, memory_size: Option<u64>) {
+        self.syscalls[class as usize].fetch_add(1, Ordering::Relaxed);
+        if let Some(memory_size) = memory_size {
+            self.peak_memory.fetch_max(memory_size, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn read(&self, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn written(&self, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> WasiStats {
+        WasiStats {
+            syscalls: SyscallClass::ALL
+                .iter()
+                .map(|class| {
+                    (
+                        *class,
+                        self.syscalls[*class as usize].load(Ordering::Relaxed),
+                    )
+                })
+                .filter(|(_, count)| *count > 0)
+                .collect(),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            peak_memory: self.peak_memory.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -17,11 +17,11 @@ use self::types::*;
 use crate::{
     ptr::{Array, WasmPtr},
     state::{
-        self, host_file_type_to_wasi_file_type, iterate_poll_events, poll, Fd, HostFile, Inode,
-        InodeVal, Kind, PollEvent, PollEventBuilder, WasiFile, WasiFsError, WasiState,
-        MAX_SYMLINKS,
+        self, host_file_type_to_wasi_file_type, iterate_poll_events, poll, poll_host_fd,
+        EntropyError, Fd, HostFile, Inode, InodeVal, Kind, PollEvent, PollEventBuilder, WasiFile,
+        WasiFsError, WasiState, MAX_SYMLINKS,
     },
-    WasiEnv, WasiError,
+    SyscallClass, WasiEnv,
 };
 use std::borrow::Borrow;
 use std::cell::Cell;
@@ -117,6 +117,16 @@ fn write_buffer_array(
     __WASI_ESUCCESS
 }
 
+/// Returns the size of `strings` once copied into the memory of the
+/// program, or `EINVAL` if it exceeds `limit`.
+fn checked_strings_size(strings: &[Vec<u8>], limit: u32) -> Result<u32, __wasi_errno_t> {
+    let size = state::strings_size(strings);
+    if size > limit as u64 {
+        return Err(__WASI_EINVAL);
+    }
+    Ok(size as u32)
+}
+
 fn get_current_time_in_nanos() -> Result<__wasi_timestamp_t, __wasi_errno_t> {
     let now = std::time::SystemTime::now();
     let duration = now
@@ -140,8 +150,13 @@ pub fn args_get(
     argv_buf: WasmPtr<u8, Array>,
 ) -> __wasi_errno_t {
     debug!("wasi::args_get");
+    env.record_syscall(SyscallClass::Args);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
+    wasi_try!(checked_strings_size(
+        &state.args,
+        state.args_limits.max_args_size
+    ));
     let result = write_buffer_array(memory, &*state.args, argv, argv_buf);
 
     debug!(
@@ -175,13 +190,17 @@ pub fn args_sizes_get(
     argv_buf_size: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::args_sizes_get");
+    env.record_syscall(SyscallClass::Args);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let argc = wasi_try!(argc.deref(memory));
     let argv_buf_size = wasi_try!(argv_buf_size.deref(memory));
 
     let argc_val = state.args.len() as u32;
-    let argv_buf_size_val = state.args.iter().map(|v| v.len() as u32 + 1).sum();
+    let argv_buf_size_val = wasi_try!(checked_strings_size(
+        &state.args,
+        state.args_limits.max_args_size
+    ));
     argc.set(argc_val);
     argv_buf_size.set(argv_buf_size_val);
 
@@ -204,10 +223,17 @@ pub fn clock_res_get(
     resolution: WasmPtr<__wasi_timestamp_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::clock_res_get");
-    let memory = env.memory();
+    env.record_syscall(SyscallClass::Clock);
+    let (memory, state) = env.get_memory_and_wasi_state(0);
 
     let out_addr = wasi_try!(resolution.deref(memory));
-    platform_clock_res_get(clock_id, out_addr)
+    match &state.capabilities.clock {
+        Some(clock) => {
+            out_addr.set(wasi_try!(clock.res_get(clock_id)));
+            __WASI_ESUCCESS
+        }
+        None => platform_clock_res_get(clock_id, out_addr),
+    }
 }
 
 /// ### `clock_time_get()`
@@ -230,10 +256,11 @@ pub fn clock_time_get(
         "wasi::clock_time_get clock_id: {}, precision: {}",
         clock_id, precision
     );
-    let memory = env.memory();
+    env.record_syscall(SyscallClass::Clock);
+    let (memory, state) = env.get_memory_and_wasi_state(0);
 
     let out_addr = wasi_try!(time.deref(memory));
-    let result = platform_clock_time_get(clock_id, precision, out_addr);
+    let result = clock_time_get_inner(&state, clock_id, precision, out_addr);
     debug!(
         "time: {} => {}",
         wasi_try!(time.deref(memory)).get(),
@@ -242,6 +269,23 @@ pub fn clock_time_get(
     result
 }
 
+/// Reads the clock `clock_id` of `state`, or of the host if `state` has
+/// no clock.
+fn clock_time_get_inner(
+    state: &WasiState,
+    clock_id: __wasi_clockid_t,
+    precision: __wasi_timestamp_t,
+    time: &Cell<__wasi_timestamp_t>,
+) -> __wasi_errno_t {
+    match &state.capabilities.clock {
+        Some(clock) => {
+            time.set(wasi_try!(clock.time_get(clock_id, precision)));
+            __WASI_ESUCCESS
+        }
+        None => platform_clock_time_get(clock_id, precision, time),
+    }
+}
+
 /// ### `environ_get()`
 /// Read environment variable data.
 /// The sizes of the buffers should match that returned by [`environ_sizes_get()`](#environ_sizes_get).
@@ -256,8 +300,13 @@ pub fn environ_get(
     environ_buf: WasmPtr<u8, Array>,
 ) -> __wasi_errno_t {
     debug!("wasi::environ_get");
+    env.record_syscall(SyscallClass::Args);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
+    wasi_try!(checked_strings_size(
+        &state.envs,
+        state.args_limits.max_envs_size
+    ));
     write_buffer_array(memory, &*state.envs, environ, environ_buf)
 }
 
@@ -274,13 +323,17 @@ pub fn environ_sizes_get(
     environ_buf_size: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::environ_sizes_get");
+    env.record_syscall(SyscallClass::Args);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let environ_count = wasi_try!(environ_count.deref(memory));
     let environ_buf_size = wasi_try!(environ_buf_size.deref(memory));
 
     let env_var_count = state.envs.len() as u32;
-    let env_buf_size = state.envs.iter().map(|v| v.len() as u32 + 1).sum();
+    let env_buf_size = wasi_try!(checked_strings_size(
+        &state.envs,
+        state.args_limits.max_envs_size
+    ));
     environ_count.set(env_var_count);
     environ_buf_size.set(env_buf_size);
 
@@ -311,6 +364,7 @@ pub fn fd_advise(
     advice: __wasi_advice_t,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_advise: fd={}", fd);
+    env.record_syscall(SyscallClass::Fd);
 
     // this is used for our own benefit, so just returning success is a valid
     // implementation for now
@@ -333,6 +387,7 @@ pub fn fd_allocate(
     len: __wasi_filesize_t,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_allocate");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
     let inode = fd_entry.inode;
@@ -374,6 +429,7 @@ pub fn fd_allocate(
 ///     If `fd` is invalid or not open
 pub fn fd_close(env: &WasiEnv, fd: __wasi_fd_t) -> __wasi_errno_t {
     debug!("wasi::fd_close: fd={}", fd);
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
@@ -390,6 +446,7 @@ pub fn fd_close(env: &WasiEnv, fd: __wasi_fd_t) -> __wasi_errno_t {
 ///     The file descriptor to sync
 pub fn fd_datasync(env: &WasiEnv, fd: __wasi_fd_t) -> __wasi_errno_t {
     debug!("wasi::fd_datasync");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
     if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_DATASYNC) {
@@ -421,6 +478,7 @@ pub fn fd_fdstat_get(
         fd,
         buf_ptr.offset()
     );
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
 
@@ -445,6 +503,7 @@ pub fn fd_fdstat_set_flags(
     flags: __wasi_fdflags_t,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_fdstat_set_flags");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
 
@@ -472,6 +531,7 @@ pub fn fd_fdstat_set_rights(
     fs_rights_inheriting: __wasi_rights_t,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_fdstat_set_rights");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
 
@@ -502,6 +562,7 @@ pub fn fd_filestat_get(
     buf: WasmPtr<__wasi_filestat_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_filestat_get");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
     if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_FILESTAT_GET) {
@@ -529,6 +590,7 @@ pub fn fd_filestat_set_size(
     st_size: __wasi_filesize_t,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_filestat_set_size");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
     let inode = fd_entry.inode;
@@ -573,6 +635,7 @@ pub fn fd_filestat_set_times(
     fst_flags: __wasi_fstflags_t,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_filestat_set_times");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
 
@@ -647,6 +710,7 @@ pub fn fd_pread(
     nread: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_pread: fd={}, offset={}", fd, offset);
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let iov_cells = wasi_try!(iovs.deref(memory, 0, iovs_len));
@@ -692,13 +756,16 @@ pub fn fd_pread(
                 Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
                 Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_pread"),
                 Kind::Buffer { buffer } => {
-                    wasi_try!(read_bytes(&buffer[(offset as usize)..], memory, iov_cells))
+                    // The buffer may have shrunk under the fd.
+                    let offset = std::cmp::min(offset, buffer.len() as u64) as usize;
+                    wasi_try!(read_bytes(&buffer[offset..], memory, iov_cells))
                 }
             }
         }
     };
 
     nread_cell.set(bytes_read);
+    env.stats.read(bytes_read as u64);
     debug!("Success: {} bytes read", bytes_read);
     __WASI_ESUCCESS
 }
@@ -717,6 +784,7 @@ pub fn fd_prestat_get(
     buf: WasmPtr<__wasi_prestat_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_prestat_get: fd={}", fd);
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let prestat_ptr = wasi_try!(buf.deref(memory));
@@ -736,6 +804,7 @@ pub fn fd_prestat_dir_name(
         "wasi::fd_prestat_dir_name: fd={}, path_len={}",
         fd, path_len
     );
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let path_chars = wasi_try!(path.deref(memory, 0, path_len));
 
@@ -795,6 +864,7 @@ pub fn fd_pwrite(
     nwritten: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_pwrite");
+    env.record_syscall(SyscallClass::Fd);
     // TODO: refactor, this is just copied from `fd_write`...
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let iovs_arr_cell = wasi_try!(iovs.deref(memory, 0, iovs_len));
@@ -846,16 +916,16 @@ pub fn fd_pwrite(
                     return __WASI_EISDIR;
                 }
                 Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_pwrite"),
-                Kind::Buffer { buffer } => wasi_try!(write_bytes(
-                    &mut buffer[(offset as usize)..],
-                    memory,
-                    iovs_arr_cell
-                )),
+                Kind::Buffer { buffer } => {
+                    let offset = std::cmp::min(offset, buffer.len() as u64) as usize;
+                    wasi_try!(write_bytes(&mut buffer[offset..], memory, iovs_arr_cell))
+                }
             }
         }
     };
 
     nwritten_cell.set(bytes_written);
+    env.stats.written(bytes_written as u64);
 
     __WASI_ESUCCESS
 }
@@ -880,6 +950,7 @@ pub fn fd_read(
     nread: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_read: fd={}", fd);
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let iovs_arr_cell = wasi_try!(iovs.deref(memory, 0, iovs_len));
@@ -923,6 +994,7 @@ pub fn fd_read(
                 }
                 Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_read"),
                 Kind::Buffer { buffer } => {
+                    let offset = std::cmp::min(offset, buffer.len());
                     wasi_try!(read_bytes(&buffer[offset..], memory, iovs_arr_cell))
                 }
             };
@@ -936,6 +1008,7 @@ pub fn fd_read(
     };
 
     nread_cell.set(bytes_read);
+    env.stats.read(bytes_read as u64);
 
     __WASI_ESUCCESS
 }
@@ -964,6 +1037,7 @@ pub fn fd_readdir(
     bufused: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_readdir");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     // TODO: figure out how this is supposed to work;
     // is it supposed to pack the buffer full every time until it can't? or do one at a time?
@@ -1014,12 +1088,18 @@ pub fn fd_readdir(
                 entry_vec.sort_by(|a, b| a.0.cmp(&b.0));
                 entry_vec
             };
+            // Only the entries of the virtual root are absolute.
+            let prefix = if state.fs.inodes[working_dir.inode].name == "/" {
+                "/"
+            } else {
+                ""
+            };
             sorted_entries
                 .into_iter()
                 .map(|(name, inode)| {
                     let entry = &state.fs.inodes[inode];
                     (
-                        format!("/{}", entry.name),
+                        format!("{}{}", prefix, entry.name),
                         entry.stat.st_filetype,
                         entry.stat.st_ino,
                     )
@@ -1074,6 +1154,7 @@ pub fn fd_readdir(
 ///     Location to copy file descriptor to
 pub fn fd_renumber(env: &WasiEnv, from: __wasi_fd_t, to: __wasi_fd_t) -> __wasi_errno_t {
     debug!("wasi::fd_renumber: from={}, to={}", from, to);
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.fd_map.get(&from).ok_or(__WASI_EBADF));
     let new_fd_entry = Fd {
@@ -1107,6 +1188,7 @@ pub fn fd_seek(
     newoffset: WasmPtr<__wasi_filesize_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_seek: fd={}, offset={}", fd, offset);
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let new_offset_cell = wasi_try!(newoffset.deref(memory));
 
@@ -1170,6 +1252,7 @@ pub fn fd_seek(
 /// - `__WASI_ENOTCAPABLE`
 pub fn fd_sync(env: &WasiEnv, fd: __wasi_fd_t) -> __wasi_errno_t {
     debug!("wasi::fd_sync");
+    env.record_syscall(SyscallClass::Fd);
     debug!("=> fd={}", fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
@@ -1208,6 +1291,7 @@ pub fn fd_tell(
     offset: WasmPtr<__wasi_filesize_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_tell");
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let offset_cell = wasi_try!(offset.deref(memory));
 
@@ -1251,6 +1335,7 @@ pub fn fd_write(
     } else {
         trace!("wasi::fd_write: fd={}", fd);
     }
+    env.record_syscall(SyscallClass::Fd);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let iovs_arr_cell = wasi_try!(iovs.deref(memory, 0, iovs_len));
     let nwritten_cell = wasi_try!(nwritten.deref(memory));
@@ -1301,6 +1386,7 @@ pub fn fd_write(
                 }
                 Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_write"),
                 Kind::Buffer { buffer } => {
+                    let offset = std::cmp::min(offset, buffer.len());
                     wasi_try!(write_bytes(&mut buffer[offset..], memory, iovs_arr_cell))
                 }
             };
@@ -1315,6 +1401,7 @@ pub fn fd_write(
     };
 
     nwritten_cell.set(bytes_written);
+    env.stats.written(bytes_written as u64);
 
     __WASI_ESUCCESS
 }
@@ -1339,6 +1426,7 @@ pub fn path_create_directory(
     path_len: u32,
 ) -> __wasi_errno_t {
     debug!("wasi::path_create_directory");
+    env.record_syscall(SyscallClass::Path);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let working_dir = wasi_try!(state.fs.get_fd(fd));
@@ -1349,6 +1437,7 @@ pub fn path_create_directory(
         return __WASI_EACCES;
     }
     let path_string = get_input_str!(memory, path, path_len);
+    let path_string = &*wasi_try!(state.fs.hook_path("path_create_directory", fd, path_string));
     debug!("=> fd: {}, path: {}", fd, &path_string);
 
     let path = std::path::PathBuf::from(path_string);
@@ -1444,6 +1533,7 @@ pub fn path_filestat_get(
     buf: WasmPtr<__wasi_filestat_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::path_filestat_get");
+    env.record_syscall(SyscallClass::Path);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let root_dir = wasi_try!(state.fs.get_fd(fd));
@@ -1452,6 +1542,7 @@ pub fn path_filestat_get(
         return __WASI_EACCES;
     }
     let path_string = get_input_str!(memory, path, path_len);
+    let path_string = &*wasi_try!(state.fs.hook_path("path_filestat_get", fd, path_string));
 
     debug!("=> base_fd: {}, path: {}", fd, &path_string);
 
@@ -1503,6 +1594,7 @@ pub fn path_filestat_set_times(
     fst_flags: __wasi_fstflags_t,
 ) -> __wasi_errno_t {
     debug!("wasi::path_filestat_set_times");
+    env.record_syscall(SyscallClass::Path);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.get_fd(fd));
     let fd_inode = fd_entry.inode;
@@ -1517,6 +1609,9 @@ pub fn path_filestat_set_times(
     }
 
     let path_string = get_input_str!(memory, path, path_len);
+    let path_string = &*wasi_try!(state
+        .fs
+        .hook_path("path_filestat_set_times", fd, path_string));
     debug!("=> base_fd: {}, path: {}", fd, &path_string);
 
     let file_inode = wasi_try!(state.fs.get_inode_at_path(
@@ -1591,12 +1686,15 @@ pub fn path_link(
     new_path_len: u32,
 ) -> __wasi_errno_t {
     debug!("wasi::path_link");
+    env.record_syscall(SyscallClass::Path);
     if old_flags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0 {
         debug!("  - will follow symlinks when opening path");
     }
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let old_path_str = get_input_str!(memory, old_path, old_path_len);
+    let old_path_str = &*wasi_try!(state.fs.hook_path("path_link", old_fd, old_path_str));
     let new_path_str = get_input_str!(memory, new_path, new_path_len);
+    let new_path_str = &*wasi_try!(state.fs.hook_path("path_link", new_fd, new_path_str));
     let source_fd = wasi_try!(state.fs.get_fd(old_fd));
     let target_fd = wasi_try!(state.fs.get_fd(new_fd));
     debug!(
@@ -1676,6 +1774,7 @@ pub fn path_open(
     fd: WasmPtr<__wasi_fd_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::path_open");
+    env.record_syscall(SyscallClass::Path);
     if dirflags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0 {
         debug!("  - will follow symlinks when opening path");
     }
@@ -1701,6 +1800,7 @@ pub fn path_open(
         return __WASI_EACCES;
     }
     let path_string = get_input_str!(memory, path, path_len);
+    let path_string = &*wasi_try!(state.fs.hook_path("path_open", dirfd, path_string));
 
     debug!("=> fd: {}, path: {}", dirfd, &path_string);
 
@@ -1715,7 +1815,10 @@ pub fn path_open(
     // TODO: traverse rights of dirs properly
     // COMMENTED OUT: WASI isn't giving appropriate rights here when opening
     //              TODO: look into this; file a bug report if this is a bug
-    let adjusted_rights = /*fs_rights_base &*/ working_dir_rights_inheriting;
+    let mut adjusted_rights = /*fs_rights_base &*/ working_dir_rights_inheriting;
+    if let Ok(inode) = maybe_inode {
+        state.refresh_proc_entry(inode, memory.size().bytes().0 as u64);
+    }
     let inode = if let Ok(inode) = maybe_inode {
         // Happy path, we found the file we're trying to open
         match &mut state.fs.inodes[inode].kind {
@@ -1774,7 +1877,23 @@ pub fn path_open(
                     false,
                 )));
             }
-            Kind::Buffer { .. } => unimplemented!("wasi::path_open for Buffer type files"),
+            Kind::Buffer { .. } => {
+                // The in-memory files are the read-only virtual files, like
+                // `/etc/passwd`.
+                if o_flags & __WASI_O_DIRECTORY != 0 {
+                    return __WASI_ENOTDIR;
+                }
+                if o_flags & __WASI_O_EXCL != 0 {
+                    return __WASI_EEXIST;
+                }
+                if o_flags & __WASI_O_TRUNC != 0 || fs_rights_base & __WASI_RIGHT_FD_WRITE != 0 {
+                    return __WASI_EACCES;
+                }
+                adjusted_rights &= !(__WASI_RIGHT_FD_WRITE
+                    | __WASI_RIGHT_FD_ALLOCATE
+                    | __WASI_RIGHT_FD_FILESTAT_SET_SIZE);
+                open_flags |= Fd::READ;
+            }
             Kind::Dir { .. } | Kind::Root { .. } => {
                 // TODO: adjust these to be correct
                 if o_flags & __WASI_O_EXCL != 0 && path_arg.exists() {
@@ -1937,6 +2056,7 @@ pub fn path_readlink(
     buf_used: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::path_readlink");
+    env.record_syscall(SyscallClass::Path);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let base_dir = wasi_try!(state.fs.fd_map.get(&dir_fd).ok_or(__WASI_EBADF));
@@ -1944,6 +2064,7 @@ pub fn path_readlink(
         return __WASI_EACCES;
     }
     let path_str = get_input_str!(memory, path, path_len);
+    let path_str = &*wasi_try!(state.fs.hook_path("path_readlink", dir_fd, path_str));
     let inode = wasi_try!(state.fs.get_inode_at_path(dir_fd, path_str, false));
 
     if let Kind::Symlink { relative_path, .. } = &state.fs.inodes[inode].kind {
@@ -1980,10 +2101,12 @@ pub fn path_remove_directory(
 ) -> __wasi_errno_t {
     // TODO check if fd is a dir, ensure it's within sandbox, etc.
     debug!("wasi::path_remove_directory");
+    env.record_syscall(SyscallClass::Path);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let base_dir = wasi_try!(state.fs.fd_map.get(&fd), __WASI_EBADF);
     let path_str = get_input_str!(memory, path, path_len);
+    let path_str = &*wasi_try!(state.fs.hook_path("path_remove_directory", fd, path_str));
 
     let inode = wasi_try!(state.fs.get_inode_at_path(fd, path_str, false));
     let (parent_inode, childs_name) =
@@ -2061,10 +2184,13 @@ pub fn path_rename(
         "wasi::path_rename: old_fd = {}, new_fd = {}",
         old_fd, new_fd
     );
+    env.record_syscall(SyscallClass::Path);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let source_str = get_input_str!(memory, old_path, old_path_len);
+    let source_str = &*wasi_try!(state.fs.hook_path("path_rename", old_fd, source_str));
     let source_path = std::path::Path::new(source_str);
     let target_str = get_input_str!(memory, new_path, new_path_len);
+    let target_str = &*wasi_try!(state.fs.hook_path("path_rename", new_fd, target_str));
     let target_path = std::path::Path::new(target_str);
     debug!("=> rename from {} to {}", source_str, target_str);
 
@@ -2168,9 +2294,12 @@ pub fn path_symlink(
     new_path_len: u32,
 ) -> __wasi_errno_t {
     debug!("wasi::path_symlink");
+    env.record_syscall(SyscallClass::Path);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let old_path_str = get_input_str!(memory, old_path, old_path_len);
+    let old_path_str = &*wasi_try!(state.fs.hook_path("path_symlink", fd, old_path_str));
     let new_path_str = get_input_str!(memory, new_path, new_path_len);
+    let new_path_str = &*wasi_try!(state.fs.hook_path("path_symlink", fd, new_path_str));
     let base_fd = wasi_try!(state.fs.get_fd(fd));
     if !has_rights(base_fd.rights, __WASI_RIGHT_PATH_SYMLINK) {
         return __WASI_EACCES;
@@ -2245,6 +2374,7 @@ pub fn path_unlink_file(
     path_len: u32,
 ) -> __wasi_errno_t {
     debug!("wasi::path_unlink_file");
+    env.record_syscall(SyscallClass::Path);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let base_dir = wasi_try!(state.fs.fd_map.get(&fd).ok_or(__WASI_EBADF));
@@ -2252,6 +2382,7 @@ pub fn path_unlink_file(
         return __WASI_EACCES;
     }
     let path_str = get_input_str!(memory, path, path_len);
+    let path_str = &*wasi_try!(state.fs.hook_path("path_unlink_file", fd, path_str));
     debug!("Requested file: {}", path_str);
 
     let inode = wasi_try!(state.fs.get_inode_at_path(fd, path_str, false));
@@ -2340,6 +2471,7 @@ pub fn poll_oneoff(
     nevents: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::poll_oneoff");
+    env.record_syscall(SyscallClass::Poll);
     debug!("  => nsubscriptions = {}", nsubscriptions);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
@@ -2349,14 +2481,13 @@ pub fn poll_oneoff(
     let out_ptr = wasi_try!(nevents.deref(memory));
 
     let mut fds = vec![];
+    let mut fd_subs = vec![];
     let mut clock_subs = vec![];
     let mut in_events = vec![];
-    let mut total_ns_slept = 0;
 
     for sub in subscription_array.iter() {
         let s: WasiSubscription = wasi_try!(sub.get().try_into());
         let mut peb = PollEventBuilder::new();
-        let mut ns_to_sleep = 0;
 
         let fd = match s.event_type {
             EventType::Read(__wasi_subscription_fs_readwrite_t { fd }) => {
@@ -2387,15 +2518,9 @@ pub fn poll_oneoff(
                 Some(fd)
             }
             EventType::Clock(clock_info) => {
-                if clock_info.clock_id == __WASI_CLOCK_REALTIME {
-                    // this is a hack
-                    // TODO: do this properly
-                    ns_to_sleep = clock_info.timeout;
-                    clock_subs.push(clock_info);
-                    None
-                } else {
-                    unimplemented!("Polling not implemented for clocks yet");
-                }
+                let timeout = wasi_try!(clock_subscription_timeout(&state, &clock_info));
+                clock_subs.push((s.user_data, timeout));
+                None
             }
         };
 
@@ -2441,25 +2566,42 @@ pub fn poll_oneoff(
                 }
             };
             fds.push(wasi_file_ref);
-        } else {
-            let remaining_ns = ns_to_sleep as i64 - total_ns_slept as i64;
-            if remaining_ns > 0 {
-                debug!("Sleeping for {} nanoseconds", remaining_ns);
-                let duration = std::time::Duration::from_nanos(remaining_ns as u64);
-                std::thread::sleep(duration);
-                total_ns_slept += remaining_ns;
-            }
+            fd_subs.push((s.user_data, s.event_type.raw_tag()));
         }
     }
+    // Block until a file is ready or the earliest timer expires. All the
+    // timers share this single wait; the ones that didn't expire yet stay
+    // pending for a later call.
+    let earliest_timeout = clock_subs.iter().map(|(_, timeout)| *timeout).min();
+    let start = std::time::Instant::now();
     let mut seen_events = vec![Default::default(); in_events.len()];
-    wasi_try!(poll(
-        fds.as_slice(),
-        in_events.as_slice(),
-        seen_events.as_mut_slice()
-    )
-    .map_err(|e| e.into_wasi_err()));
+    let fds_ready = if fds.is_empty() {
+        if let Some(earliest) = earliest_timeout {
+            debug!("Sleeping for {} nanoseconds", earliest);
+            state
+                .capabilities
+                .sleep(std::time::Duration::from_nanos(earliest));
+        }
+        0
+    } else {
+        wasi_try!(poll(
+            fds.as_slice(),
+            in_events.as_slice(),
+            seen_events.as_mut_slice(),
+            earliest_timeout.map(std::time::Duration::from_nanos),
+        )
+        .map_err(|e| e.into_wasi_err()))
+    };
+    let mut elapsed = start.elapsed().as_nanos() as __wasi_timestamp_t;
+    if fds_ready == 0 {
+        // Nothing else woke us up, so the earliest timer has expired.
+        elapsed = elapsed.max(earliest_timeout.unwrap_or(0));
+    }
 
     for (i, seen_event) in seen_events.into_iter().enumerate() {
+        if seen_event == 0 {
+            continue;
+        }
         let mut flags = 0;
         let mut error = __WASI_EAGAIN;
         let mut bytes_available = 0;
@@ -2481,10 +2623,11 @@ pub fn poll_oneoff(
                 }
             }
         }
+        let (userdata, type_) = fd_subs[i];
         let event = __wasi_event_t {
-            userdata: subscription_array[i].get().userdata,
+            userdata,
             error,
-            type_: subscription_array[i].get().type_,
+            type_,
             u: unsafe {
                 __wasi_event_u {
                     fd_readwrite: __wasi_event_fd_readwrite_t {
@@ -2497,10 +2640,11 @@ pub fn poll_oneoff(
         event_array[events_seen].set(event);
         events_seen += 1;
     }
-    for clock_info in clock_subs {
+
+    clock_subs.retain(|(_, timeout)| *timeout <= elapsed);
+    for (userdata, _) in clock_subs {
         let event = __wasi_event_t {
-            // TOOD: review userdata value
-            userdata: 0,
+            userdata,
             error: __WASI_ESUCCESS,
             type_: __WASI_EVENTTYPE_CLOCK,
             u: unsafe {
@@ -2519,14 +2663,35 @@ pub fn poll_oneoff(
     __WASI_ESUCCESS
 }
 
+/// Computes how long (in nanoseconds) a clock subscription of `poll_oneoff`
+/// has to wait before it expires, given its clock and flags.
+fn clock_subscription_timeout(
+    state: &WasiState,
+    clock_info: &__wasi_subscription_clock_t,
+) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    // Reading the clock also rejects unknown clock ids.
+    let now = Cell::new(0);
+    let result = clock_time_get_inner(state, clock_info.clock_id, clock_info.precision, &now);
+    if result != __WASI_ESUCCESS {
+        return Err(result);
+    }
+    if clock_info.flags & __WASI_SUBSCRIPTION_CLOCK_ABSTIME == 0 {
+        Ok(clock_info.timeout)
+    } else {
+        Ok(clock_info.timeout.saturating_sub(now.get()))
+    }
+}
+
 pub fn proc_exit(env: &WasiEnv, code: __wasi_exitcode_t) {
     debug!("wasi::proc_exit, {}", code);
-    RuntimeError::raise(Box::new(WasiError::Exit(code)));
-    unreachable!();
+    env.record_syscall(SyscallClass::Proc);
+    let error = env.state().capabilities.exit_error(code);
+    RuntimeError::raise(error);
 }
 
 pub fn proc_raise(env: &WasiEnv, sig: __wasi_signal_t) -> __wasi_errno_t {
     debug!("wasi::proc_raise");
+    env.record_syscall(SyscallClass::Proc);
     unimplemented!("wasi::proc_raise")
 }
 
@@ -2539,17 +2704,19 @@ pub fn proc_raise(env: &WasiEnv, sig: __wasi_signal_t) -> __wasi_errno_t {
 ///     The number of bytes that will be written
 pub fn random_get(env: &WasiEnv, buf: WasmPtr<u8, Array>, buf_len: u32) -> __wasi_errno_t {
     debug!("wasi::random_get buf_len: {}", buf_len);
-    let memory = env.memory();
+    env.record_syscall(SyscallClass::Random);
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let buf = wasi_try!(buf.deref(memory, 0, buf_len));
 
     let res = unsafe {
         let u8_buffer = &mut *(buf as *const [_] as *mut [_] as *mut [u8]);
-        getrandom::getrandom(u8_buffer)
+        state.capabilities.entropy.fill(u8_buffer)
     };
     match res {
         Ok(()) => __WASI_ESUCCESS,
-        Err(_) => __WASI_EIO,
+        Err(EntropyError::Denied) => __WASI_ENOTCAPABLE,
+        Err(EntropyError::Failed(_)) => __WASI_EIO,
     }
 }
 
@@ -2557,10 +2724,88 @@ pub fn random_get(env: &WasiEnv, buf: WasmPtr<u8, Array>, buf_len: u32) -> __was
 /// Yields execution of the thread
 pub fn sched_yield(env: &WasiEnv) -> __wasi_errno_t {
     debug!("wasi::sched_yield");
-    ::std::thread::yield_now();
+    env.record_syscall(SyscallClass::Sched);
+    env.state().capabilities.sched_yield();
+    __WASI_ESUCCESS
+}
+
+/// ### `sock_accept()`
+/// Accept a new connection on a listening socket
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The listening socket
+/// - `__wasi_fdflags_t fd_flags`
+///     The flags of the file descriptor of the connection, only
+///     `__WASI_FDFLAG_NONBLOCK` is supported
+/// Output:
+/// - `__wasi_fd_t *ro_fd`
+///     The file descriptor of the connection
+///
+/// Returns `__WASI_EAGAIN` if no connection is pending and the listening
+/// socket has the `__WASI_FDFLAG_NONBLOCK` flag, waits for one otherwise
+pub fn sock_accept(
+    env: &WasiEnv,
+    sock: __wasi_fd_t,
+    fd_flags: __wasi_fdflags_t,
+    ro_fd: WasmPtr<__wasi_fd_t>,
+) -> __wasi_errno_t {
+    debug!("wasi::sock_accept: sock={}", sock);
+    env.record_syscall(SyscallClass::Sock);
+    if fd_flags & !__WASI_FDFLAG_NONBLOCK != 0 {
+        return __WASI_ENOTSUP;
+    }
+    let connection = wasi_try!(retry_on_socket(
+        env,
+        sock,
+        __WASI_RIGHT_FD_READ,
+        PollEvent::PollIn,
+        |_, socket, nonblocking| accept_connection(socket, nonblocking),
+    ));
+
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
+    let kind = Kind::File {
+        handle: Some(Box::new(connection)),
+        path: std::path::PathBuf::new(),
+        fd: None,
+    };
+    let inode = wasi_try!(state.fs.create_inode(kind, false, "connection".to_string()));
+    let rights = __WASI_RIGHT_FD_READ
+        | __WASI_RIGHT_FD_WRITE
+        | __WASI_RIGHT_FD_FDSTAT_SET_FLAGS
+        | __WASI_RIGHT_FD_FILESTAT_GET
+        | __WASI_RIGHT_POLL_FD_READWRITE
+        | __WASI_RIGHT_SOCK_SHUTDOWN;
+    let new_fd =
+        wasi_try!(state
+            .fs
+            .create_fd(rights, rights, fd_flags, Fd::READ | Fd::WRITE, inode));
+
+    let ro_fd = wasi_try!(ro_fd.deref(memory));
+    ro_fd.set(new_fd);
+
     __WASI_ESUCCESS
 }
 
+/// ### `sock_recv()`
+/// Receive data from a socket
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The socket from which data will be received
+/// - `const __wasi_iovec_t *ri_data`
+///     Vectors where data will be stored
+/// - `u32 ri_data_len`
+///     Length of data in `ri_data`
+/// - `__wasi_riflags_t ri_flags`
+///     `__WASI_SOCK_RECV_WAITALL` to wait until the vectors are full or the
+///     connection is closed. `__WASI_SOCK_RECV_PEEK` is not supported
+/// Output:
+/// - `u32 *ro_datalen`
+///     Number of bytes received, 0 once the connection is closed
+/// - `__wasi_roflags_t *ro_flags`
+///     Always 0 on stream sockets
+///
+/// Returns `__WASI_EAGAIN` if no data is available and the socket has the
+/// `__WASI_FDFLAG_NONBLOCK` flag, waits for data otherwise
 pub fn sock_recv(
     env: &WasiEnv,
     sock: __wasi_fd_t,
@@ -2570,9 +2815,80 @@ pub fn sock_recv(
     ro_datalen: WasmPtr<u32>,
     ro_flags: WasmPtr<__wasi_roflags_t>,
 ) -> __wasi_errno_t {
-    debug!("wasi::sock_recv");
-    unimplemented!("wasi::sock_recv")
+    debug!("wasi::sock_recv: sock={}", sock);
+    env.record_syscall(SyscallClass::Sock);
+    if ri_flags & __WASI_SOCK_RECV_PEEK != 0 {
+        return __WASI_ENOTSUP;
+    }
+    let wait_all = ri_flags & __WASI_SOCK_RECV_WAITALL != 0;
+
+    let mut bytes_read = 0;
+    let mut buffer = Vec::new();
+    wasi_try!(retry_on_socket(
+        env,
+        sock,
+        __WASI_RIGHT_FD_READ,
+        PollEvent::PollIn,
+        |memory, socket, nonblocking| {
+            let iovs_arr_cell = ri_data.deref(memory, 0, ri_data_len)?;
+            let bufs = guest_iovecs(memory, iovs_arr_cell)?;
+            let total_len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+            while bytes_read < total_len {
+                buffer.resize((total_len - bytes_read).min(SOCKET_CHUNK_SIZE), 0);
+                match socket.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        scatter_into_guest(&bufs, bytes_read, &buffer[..n]);
+                        bytes_read += n;
+                        if !wait_all {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        if !nonblocking {
+                            return Ok(None);
+                        }
+                        // Return what was received before the socket ran out of data.
+                        if bytes_read > 0 {
+                            break;
+                        }
+                        return Err(__WASI_EAGAIN);
+                    }
+                    Err(e) => return Err(WasiFsError::from(e).into_wasi_err()),
+                }
+            }
+            Ok(Some(()))
+        },
+    ));
+
+    let memory = env.memory();
+    let ro_datalen = wasi_try!(ro_datalen.deref(memory));
+    ro_datalen.set(bytes_read as u32);
+    let ro_flags = wasi_try!(ro_flags.deref(memory));
+    ro_flags.set(0);
+
+    __WASI_ESUCCESS
 }
+
+/// ### `sock_send()`
+/// Send data to a socket, in a single write
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The socket to which data will be sent
+/// - `const __wasi_ciovec_t *si_data`
+///     Vectors holding the data to send
+/// - `u32 si_data_len`
+///     Length of data in `si_data`
+/// - `__wasi_siflags_t si_flags`
+///     Unused
+/// Output:
+/// - `u32 *so_datalen`
+///     Number of bytes sent, which may be less than the length of the
+///     data when the send buffer of the socket is full
+///
+/// Returns `__WASI_EAGAIN` if the send buffer is full and the socket has
+/// the `__WASI_FDFLAG_NONBLOCK` flag, waits for room in the buffer otherwise
 pub fn sock_send(
     env: &WasiEnv,
     sock: __wasi_fd_t,
@@ -2581,10 +2897,377 @@ pub fn sock_send(
     si_flags: __wasi_siflags_t,
     so_datalen: WasmPtr<u32>,
 ) -> __wasi_errno_t {
-    debug!("wasi::sock_send");
-    unimplemented!("wasi::sock_send")
+    debug!("wasi::sock_send: sock={}", sock);
+    env.record_syscall(SyscallClass::Sock);
+
+    let mut buffer = Vec::new();
+    let bytes_written = wasi_try!(retry_on_socket(
+        env,
+        sock,
+        __WASI_RIGHT_FD_WRITE,
+        PollEvent::PollOut,
+        |memory, socket, nonblocking| {
+            let iovs_arr_cell = si_data.deref(memory, 0, si_data_len)?;
+            let bufs = guest_ciovecs(memory, iovs_arr_cell)?;
+            gather_from_guest(&bufs, SOCKET_CHUNK_SIZE, &mut buffer);
+            if buffer.is_empty() {
+                return Ok(Some(0));
+            }
+            loop {
+                match socket.write(&buffer) {
+                    Ok(n) => return Ok(Some(n)),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock && !nonblocking => {
+                        return Ok(None)
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(__WASI_EAGAIN),
+                    Err(e) => return Err(WasiFsError::from(e).into_wasi_err()),
+                }
+            }
+        },
+    ));
+
+    let memory = env.memory();
+    let so_datalen = wasi_try!(so_datalen.deref(memory));
+    so_datalen.set(bytes_written as u32);
+
+    __WASI_ESUCCESS
+}
+
+/// The most bytes `sock_send` and `sock_recv` copy between the memory of
+/// the program and the socket at once.
+const SOCKET_CHUNK_SIZE: usize = 64 * 1024;
+
+/// How long a blocking socket syscall waits for its socket before looking
+/// it up again, in case the program closed it from another thread.
+const SOCKET_WAIT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Runs `op` on the socket behind `sock` until it completes.
+///
+/// `op` returns `Ok(None)` when the socket isn't ready and the fd is
+/// blocking: the state is then unlocked while waiting for `event` on the
+/// socket, so that the other threads of the program can make progress,
+/// and `op` runs again on the socket looked up anew.
+fn retry_on_socket<T>(
+    env: &WasiEnv,
+    sock: __wasi_fd_t,
+    rights: __wasi_rights_t,
+    event: PollEvent,
+    mut op: impl FnMut(&Memory, &mut dyn WasiFile, bool) -> Result<Option<T>, __wasi_errno_t>,
+) -> Result<T, __wasi_errno_t> {
+    loop {
+        let (memory, mut state) = env.get_memory_and_wasi_state(0);
+        let (socket, nonblocking) = get_socket(&mut state, sock, rights)?;
+        if let Some(result) = op(memory, socket.as_mut(), nonblocking)? {
+            return Ok(result);
+        }
+        let host_fd = socket.get_raw_fd().ok_or(__WASI_ENOTSUP)?;
+        drop(state);
+
+        let events = PollEventBuilder::new().add(event).build();
+        poll_host_fd(host_fd, events, SOCKET_WAIT_INTERVAL).map_err(WasiFsError::into_wasi_err)?;
+    }
+}
+
+/// Returns the socket behind `sock` if the fd has the `rights`, and whether
+/// the fd is non-blocking.
+fn get_socket(
+    state: &mut WasiState,
+    sock: __wasi_fd_t,
+    rights: __wasi_rights_t,
+) -> Result<(&mut Box<dyn WasiFile>, bool), __wasi_errno_t> {
+    let fd_entry = state.fs.get_fd(sock)?;
+    if !has_rights(fd_entry.rights, rights) {
+        return Err(__WASI_EACCES);
+    }
+    let nonblocking = fd_entry.flags & __WASI_FDFLAG_NONBLOCK != 0;
+    let inode = fd_entry.inode;
+    match &mut state.fs.inodes[inode].kind {
+        Kind::File {
+            handle: Some(handle),
+            ..
+        } if handle.is_socket() => Ok((handle, nonblocking)),
+        _ => Err(__WASI_ENOTSOCK),
+    }
+}
+
+/// Accepts a connection on the listening `socket`, see [`retry_on_socket`].
+#[cfg(unix)]
+fn accept_connection(
+    socket: &dyn WasiFile,
+    nonblocking: bool,
+) -> Result<Option<state::HostSocket>, __wasi_errno_t> {
+    let listener = socket
+        .downcast_ref::<state::SocketListener>()
+        .ok_or(__WASI_EINVAL)?;
+    match listener.accept() {
+        Ok(connection) => Ok(Some(connection)),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock && !nonblocking => Ok(None),
+        Err(e) => Err(WasiFsError::from(e).into_wasi_err()),
+    }
+}
+
+#[cfg(not(unix))]
+fn accept_connection(
+    _socket: &dyn WasiFile,
+    _nonblocking: bool,
+) -> Result<Option<state::HostSocket>, __wasi_errno_t> {
+    Err(__WASI_ENOTSUP)
+}
+
+/// Returns the buffers of the program described by `iovs_arr_cell`.
+///
+/// The buffers may overlap, so they are only accessed through their cells,
+/// see [`scatter_into_guest`].
+fn guest_iovecs<'a>(
+    memory: &'a Memory,
+    iovs_arr_cell: &[Cell<__wasi_iovec_t>],
+) -> Result<Vec<&'a [Cell<u8>]>, __wasi_errno_t> {
+    iovs_arr_cell
+        .iter()
+        .map(|iov| {
+            let iov_inner = iov.get();
+            iov_inner.buf.deref(memory, 0, iov_inner.buf_len)
+        })
+        .collect()
+}
+
+/// Returns the buffers of the program described by `iovs_arr_cell`, see
+/// [`gather_from_guest`].
+fn guest_ciovecs<'a>(
+    memory: &'a Memory,
+    iovs_arr_cell: &[Cell<__wasi_ciovec_t>],
+) -> Result<Vec<&'a [Cell<u8>]>, __wasi_errno_t> {
+    iovs_arr_cell
+        .iter()
+        .map(|iov| {
+            let iov_inner = iov.get();
+            iov_inner.buf.deref(memory, 0, iov_inner.buf_len)
+        })
+        .collect()
+}
+
+/// Copies `bytes` into `bufs`, starting `skip` bytes into them.
+fn scatter_into_guest(bufs: &[&[Cell<u8>]], mut skip: usize, mut bytes: &[u8]) {
+    for buf in bufs {
+        if bytes.is_empty() {
+            break;
+        }
+        if skip >= buf.len() {
+            skip -= buf.len();
+            continue;
+        }
+        let n = (buf.len() - skip).min(bytes.len());
+        for (cell, &byte) in buf[skip..skip + n].iter().zip(bytes) {
+            cell.set(byte);
+        }
+        bytes = &bytes[n..];
+        skip = 0;
+    }
 }
+
+/// Copies the first `max_len` bytes of `bufs` into `out`.
+fn gather_from_guest(bufs: &[&[Cell<u8>]], max_len: usize, out: &mut Vec<u8>) {
+    out.clear();
+    for buf in bufs {
+        let n = (max_len - out.len()).min(buf.len());
+        out.extend(buf[..n].iter().map(Cell::get));
+        if out.len() == max_len {
+            break;
+        }
+    }
+}
+
+/// ### `sock_shutdown()`
+/// Shut down the reading half, the writing half, or both halves of a
+/// connected socket
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The socket to shut down
+/// - `__wasi_sdflags_t how`
+///     `__WASI_SHUT_RD`, `__WASI_SHUT_WR`, or both
+///
+/// Returns `__WASI_ENOTSUP` on sockets that aren't connected sockets of
+/// the host, like listening sockets
 pub fn sock_shutdown(env: &WasiEnv, sock: __wasi_fd_t, how: __wasi_sdflags_t) -> __wasi_errno_t {
-    debug!("wasi::sock_shutdown");
-    unimplemented!("wasi::sock_shutdown")
+    debug!("wasi::sock_shutdown: sock={}, how={}", sock, how);
+    env.record_syscall(SyscallClass::Sock);
+    let mut state = env.state();
+    let how = match how {
+        __WASI_SHUT_RD => std::net::Shutdown::Read,
+        __WASI_SHUT_WR => std::net::Shutdown::Write,
+        _ if how == __WASI_SHUT_RD | __WASI_SHUT_WR => std::net::Shutdown::Both,
+        _ => return __WASI_EINVAL,
+    };
+    let (socket, _) = wasi_try!(get_socket(&mut state, sock, __WASI_RIGHT_SOCK_SHUTDOWN));
+    let socket = wasi_try!(socket
+        .downcast_ref::<state::HostSocket>()
+        .ok_or(__WASI_ENOTSUP));
+    wasi_try!(socket
+        .shutdown(how)
+        .map_err(|e| WasiFsError::from(e).into_wasi_err()));
+
+    __WASI_ESUCCESS
+}
+
+/// ### `wasix_get_capabilities()`
+/// Get the extensions of WASI supported by the runtime, with their version
+/// Inputs:
+/// - `u32 buf_len`
+///     Space available pointed to by `buf`
+/// Outputs:
+/// - `char *buf`
+///     The capabilities, as a JSON object like
+///     `{"version":1,"extensions":{"wasix_get_capabilities":1}}`
+/// - `u32 buf_used`
+///     The size of the capabilities, written even if `buf` is too small,
+///     in which case `__WASI_EOVERFLOW` is returned
+pub fn wasix_get_capabilities(
+    env: &WasiEnv,
+    buf: WasmPtr<u8, Array>,
+    buf_len: u32,
+    buf_used: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    debug!("wasi::wasix_get_capabilities");
+    env.record_syscall(SyscallClass::Proc);
+    let (memory, state) = env.get_memory_and_wasi_state(0);
+    let capabilities = state.extensions.to_json();
+    let bytes = capabilities.as_bytes();
+
+    let bytes_out = wasi_try!(buf_used.deref(memory));
+    bytes_out.set(bytes.len() as u32);
+    if bytes.len() > buf_len as usize {
+        return __WASI_EOVERFLOW;
+    }
+
+    let out = wasi_try!(buf.deref(memory, 0, bytes.len() as u32));
+    for (cell, &b) in out.iter().zip(bytes) {
+        cell.set(b);
+    }
+
+    __WASI_ESUCCESS
+}
+
+/// ### `wasix_http_request()`
+/// Send an HTTP request through the HTTP client of the host, the
+/// `wasix_http_client` extension
+/// Inputs:
+/// - `const char *head`
+///     The method and the URL on the first line, then a `name: value`
+///     header per line
+/// - `u32 head_len`
+///     The number of bytes to read from `head`
+/// - `const u8 *body`
+///     The body of the request
+/// - `u32 body_len`
+///     The number of bytes to read from `body`
+/// Outputs:
+/// - `u16 *status`
+///     The status code of the response
+/// - `__wasi_fd_t *fd`
+///     The file descriptor to read the body of the response from, as it
+///     arrives, and to close once done
+///
+/// Returns `__WASI_ENOSYS` if the host doesn't provide HTTP, and
+/// `__WASI_EACCES` if the host of the URL isn't allowed
+pub fn wasix_http_request(
+    env: &WasiEnv,
+    head: WasmPtr<u8, Array>,
+    head_len: u32,
+    body: WasmPtr<u8, Array>,
+    body_len: u32,
+    status: WasmPtr<u16>,
+    fd: WasmPtr<__wasi_fd_t>,
+) -> __wasi_errno_t {
+    debug!("wasi::wasix_http_request");
+    env.record_syscall(SyscallClass::Sock);
+    let (memory, state) = env.get_memory_and_wasi_state(0);
+    let http = wasi_try!(state.http.as_ref().ok_or(__WASI_ENOSYS));
+    let head = get_input_str!(memory, head, head_len);
+    let body = wasi_try!(body.deref(memory, 0, body_len))
+        .iter()
+        .map(Cell::get)
+        .collect();
+    let request = wasi_try!(state::parse_request_head(head, body).ok_or(__WASI_EINVAL));
+    debug!("=> {} {}", request.method, request.url);
+    let request = wasi_try!(http.authorize(request));
+    // The other threads of the program keep running while the host waits
+    // for the response.
+    drop(state);
+    let response = wasi_try!(request.send());
+    let status_code = response.status;
+
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
+
+    let kind = Kind::File {
+        handle: Some(Box::new(state::HttpResponseFile::new(response))),
+        path: std::path::PathBuf::new(),
+        fd: None,
+    };
+    let inode = wasi_try!(state
+        .fs
+        .create_inode(kind, false, "http-response".to_string()));
+    let rights = __WASI_RIGHT_FD_READ
+        | __WASI_RIGHT_FD_FDSTAT_SET_FLAGS
+        | __WASI_RIGHT_FD_FILESTAT_GET
+        | __WASI_RIGHT_POLL_FD_READWRITE;
+    let new_fd = wasi_try!(state.fs.create_fd(rights, 0, 0, Fd::READ, inode));
+
+    let status = wasi_try!(status.deref(memory));
+    status.set(status_code);
+    let fd = wasi_try!(fd.deref(memory));
+    fd.set(new_fd);
+
+    __WASI_ESUCCESS
+}
+
+/// ### `wasix_http_response_headers()`
+/// Get the headers of a response of `wasix_http_request()`
+/// Inputs:
+/// - `__wasi_fd_t fd`
+///     The file descriptor of the response
+/// - `u32 buf_len`
+///     Space available pointed to by `buf`
+/// Outputs:
+/// - `char *buf`
+///     The headers, a `name: value` per line
+/// - `u32 buf_used`
+///     The size of the headers, written even if `buf` is too small, in
+///     which case `__WASI_EOVERFLOW` is returned
+pub fn wasix_http_response_headers(
+    env: &WasiEnv,
+    fd: __wasi_fd_t,
+    buf: WasmPtr<u8, Array>,
+    buf_len: u32,
+    buf_used: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    debug!("wasi::wasix_http_response_headers: fd={}", fd);
+    env.record_syscall(SyscallClass::Sock);
+    let (memory, state) = env.get_memory_and_wasi_state(0);
+    let inode = wasi_try!(state.fs.get_fd(fd)).inode;
+    let headers = match &state.fs.inodes[inode].kind {
+        Kind::File {
+            handle: Some(handle),
+            ..
+        } => wasi_try!(
+            handle.downcast_ref::<state::HttpResponseFile>(),
+            __WASI_EINVAL
+        )
+        .headers_text(),
+        _ => return __WASI_EINVAL,
+    };
+    let bytes = headers.as_bytes();
+
+    let bytes_out = wasi_try!(buf_used.deref(memory));
+    bytes_out.set(bytes.len() as u32);
+    if bytes.len() > buf_len as usize {
+        return __WASI_EOVERFLOW;
+    }
+
+    let out = wasi_try!(buf.deref(memory, 0, bytes.len() as u32));
+    for (cell, &b) in out.iter().zip(bytes) {
+        cell.set(b);
+    }
+
+    __WASI_ESUCCESS
 }
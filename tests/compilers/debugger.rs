@@ -0,0 +1,99 @@
+use crate::utils::get_store_with_middlewares;
+use anyhow::Result;
+use wasmer_middlewares::debugger::{Breakpoint, Debugger, StopReason};
+
+use std::sync::Arc;
+use wasmer::*;
+
+#[test]
+fn debugger_steps_and_breaks() -> Result<()> {
+    // The body of `add`: the local declarations (1 byte), `local.get 0` (2 bytes),
+    // `local.get 1` (2 bytes), `i32.add` at offset 5 and `end`.
+    let debugger = Arc::new(Debugger::new(vec![Breakpoint {
+        function: 0,
+        offset: 5,
+    }]));
+    let store = get_store_with_middlewares(std::iter::once(
+        debugger.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (func (export "add") (param i32 i32) (result i32)
+           (i32.add (local.get 0)
+                    (local.get 1)))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let session = debugger.session(&instance)?;
+    let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+
+    session.set_breakpoint_enabled(0, false);
+    assert_eq!(add.call(1, 2)?, 3);
+    assert_eq!(session.steps(), 4);
+
+    session.reset_steps();
+    session.set_step_limit(Some(2));
+    let error = add.call(1, 2).unwrap_err();
+    let stop = session.stop(&error).expect("step stop");
+    assert_eq!(stop.reason, StopReason::Step);
+    assert_eq!(stop.steps, 2);
+    assert_eq!(stop.backtrace[0].func_index(), 0);
+
+    session.reset_steps();
+    session.set_step_limit(None);
+    session.set_breakpoint_enabled(0, true);
+    let error = add.call(1, 2).unwrap_err();
+    let stop = session.stop(&error).expect("breakpoint stop");
+    assert_eq!(stop.reason, StopReason::Breakpoint(0));
+    assert_eq!(stop.steps, 2);
+    assert!(session.stop(&error).is_none());
+    Ok(())
+}
+
+#[test]
+fn debugger_saves_the_parameters_at_a_stop() -> Result<()> {
+    let debugger = Arc::new(Debugger::new(vec![]));
+    let store = get_store_with_middlewares(std::iter::once(
+        debugger.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (global (export "debug_steps") (mut i64) (i64.const 7))
+        (func (export "mix") (param i32 f64 i32) (result i32)
+           (local.set 0 (i32.const 5))
+           (i32.add (local.get 0)
+                    (local.get 2)))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let session = debugger.session(&instance)?;
+    let mix: NativeFunc<(i32, f64, i32), i32> = instance.exports.get_native_function("mix")?;
+
+    // Stop before the `i32.add`, after the parameter 0 was set.
+    session.set_step_limit(Some(4));
+    let error = mix.call(1, 2.5, 3).unwrap_err();
+    let stop = session.stop(&error).expect("step stop");
+    assert_eq!(stop.function, 0);
+    assert_eq!(
+        stop.params,
+        vec![
+            Some(Value::I32(5)),
+            Some(Value::F64(2.5)),
+            Some(Value::I32(3))
+        ]
+    );
+
+    // The guest keeps its own exports.
+    let guest_steps = instance.exports.get_global("debug_steps")?;
+    assert_eq!(guest_steps.get().unwrap_i64(), 7);
+    assert_eq!(session.steps(), 4);
+    Ok(())
+}
+
+#[test]
+fn debugger_sessions_need_an_instrumented_module() -> Result<()> {
+    let debugger = Debugger::new(vec![]);
+    let store = get_store_with_middlewares(std::iter::empty());
+    let module = Module::new(&store, r#"(module (func (export "run")))"#)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    assert!(debugger.session(&instance).is_err());
+    Ok(())
+}
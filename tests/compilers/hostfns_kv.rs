@@ -0,0 +1,96 @@
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_hostfns_kv::{generate_import_object_from_env, KvEnv, MemoryKvBackend};
+
+/// A guest with a bump allocator and the regions of two records.
+const WAT: &str = r#"
+(module
+  (import "env" "db_read" (func $db_read (param i32) (result i32)))
+  (import "env" "db_write" (func $db_write (param i32 i32)))
+  (import "env" "db_remove" (func $db_remove (param i32)))
+  (import "env" "db_scan" (func $db_scan (param i32 i32 i32) (result i32)))
+  (import "env" "db_next" (func $db_next (param i32) (result i32)))
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+
+  ;; Regions `foo`, `bar`, `baz` and `qux`.
+  (data (i32.const 0) "\64\00\00\00\03\00\00\00\03\00\00\00")
+  (data (i32.const 12) "\6e\00\00\00\03\00\00\00\03\00\00\00")
+  (data (i32.const 24) "\78\00\00\00\03\00\00\00\03\00\00\00")
+  (data (i32.const 36) "\82\00\00\00\03\00\00\00\03\00\00\00")
+  (data (i32.const 100) "foo")
+  (data (i32.const 110) "bar")
+  (data (i32.const 120) "baz")
+  (data (i32.const 130) "qux")
+
+  (func (export "allocate") (param $size i32) (result i32)
+    (local $region i32)
+    (local.set $region (global.get $heap))
+    (i32.store (local.get $region) (i32.add (local.get $region) (i32.const 12)))
+    (i32.store offset=4 (local.get $region) (local.get $size))
+    (i32.store offset=8 (local.get $region) (i32.const 0))
+    (global.set $heap
+      (i32.add (global.get $heap) (i32.add (local.get $size) (i32.const 12))))
+    (local.get $region))
+
+  (func (export "write_records")
+    (call $db_write (i32.const 0) (i32.const 12))
+    (call $db_write (i32.const 24) (i32.const 36)))
+  (func (export "read_foo") (result i32) (call $db_read (i32.const 0)))
+  (func (export "remove_foo") (call $db_remove (i32.const 0)))
+  (func (export "scan") (result i32) (call $db_scan (i32.const 0) (i32.const 0) (i32.const 1)))
+  (func (export "next") (param i32) (result i32) (call $db_next (local.get 0))))
+"#;
+
+fn read_region(memory: &Memory, region_ptr: u32) -> Vec<u8> {
+    let view = memory.view::<u32>();
+    let offset = view[region_ptr as usize / 4].get() as usize;
+    let length = view[region_ptr as usize / 4 + 2].get() as usize;
+    memory.view::<u8>()[offset..offset + length]
+        .iter()
+        .map(|cell| cell.get())
+        .collect()
+}
+
+#[test]
+fn kv_host_functions() -> Result<()> {
+    let store = get_store(false);
+    let module = Module::new(&store, WAT)?;
+    let env = KvEnv::new(MemoryKvBackend::default());
+    let instance = Instance::new(
+        &module,
+        &generate_import_object_from_env(&store, env.clone()),
+    )?;
+    let memory = instance.exports.get_memory("memory")?;
+
+    let write_records: NativeFunc = instance.exports.get_native_function("write_records")?;
+    write_records.call()?;
+    assert_eq!(
+        env.backend.lock().unwrap().get(b"baz"),
+        Some(b"qux".to_vec())
+    );
+
+    let read_foo: NativeFunc<(), u32> = instance.exports.get_native_function("read_foo")?;
+    let value = read_foo.call()?;
+    assert_eq!(read_region(memory, value), b"bar");
+
+    let scan: NativeFunc<(), u32> = instance.exports.get_native_function("scan")?;
+    let next: NativeFunc<u32, u32> = instance.exports.get_native_function("next")?;
+    let iterator = scan.call()?;
+    assert_eq!(
+        read_region(memory, next.call(iterator)?),
+        b"bazqux\0\0\0\x03"
+    );
+    assert_eq!(
+        read_region(memory, next.call(iterator)?),
+        b"foobar\0\0\0\x03"
+    );
+    assert_eq!(next.call(iterator)?, 0);
+
+    let remove_foo: NativeFunc = instance.exports.get_native_function("remove_foo")?;
+    remove_foo.call()?;
+    assert_eq!(read_foo.call()?, 0);
+
+    Ok(())
+}
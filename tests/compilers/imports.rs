@@ -343,3 +343,38 @@ fn dynamic_function_with_env_wasmer_env_init_works() -> Result<()> {
     f.call()?;
     Ok(())
 }
+
+#[test]
+fn all_unresolved_imports_are_reported() -> Result<()> {
+    let store = get_store(false);
+    let module = get_module(&store)?;
+    let error = Instance::new(
+        &module,
+        &imports! {
+            "host" => {
+                "0" => Function::new_native(&store, || {}),
+                "2" => Function::new_native(&store, |_a: i32| {}),
+            },
+        },
+    )
+    .unwrap_err();
+
+    let unresolved_imports = match error {
+        InstantiationError::Link(LinkError::Imports(unresolved_imports)) => unresolved_imports,
+        error => panic!("unexpected error: {}", error),
+    };
+    let names = unresolved_imports
+        .iter()
+        .map(|import| (import.module.as_str(), import.name.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![("host", "1"), ("host", "2"), ("host", "3")]);
+    assert!(matches!(
+        unresolved_imports[0].error,
+        ImportError::UnknownImport(_)
+    ));
+    assert!(matches!(
+        unresolved_imports[1].error,
+        ImportError::IncompatibleType(_, _)
+    ));
+    Ok(())
+}
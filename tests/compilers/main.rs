@@ -4,16 +4,25 @@
 //! implementation, such as: singlepass, cranelift or llvm depending
 //! on what's available on the target.
 
+mod debugger;
+mod hostfns_kv;
 mod imports;
 mod metering;
 mod middlewares;
 mod multi_value_imports;
 mod native_functions;
+mod precompile_validate;
 mod serialize;
+mod shadow_memory;
 mod traps;
 mod utils;
 mod wasi;
+mod wasi_exit;
+mod wasi_in_memory_files;
+mod wasi_sockets;
+mod wasix;
 mod wast;
+mod watchpoints;
 
 pub use crate::utils::get_compiler;
 pub use crate::wasi::run_wasi;
@@ -1,6 +1,6 @@
-use crate::utils::get_store_with_middlewares;
+use crate::utils::{get_store, get_store_with_middlewares};
 use anyhow::Result;
-use wasmer_middlewares::Metering;
+use wasmer_middlewares::{FunctionPoints, Metering, MeteringPoints};
 
 use std::sync::Arc;
 use wasmer::wasmparser::Operator;
@@ -166,3 +166,429 @@ fn complex_loop() -> Result<()> {
     f.call(10_000_000, 4).unwrap_err();
     Ok(())
 }
+
+#[test]
+fn calibration_produces_a_usable_cost_table() -> Result<()> {
+    use wasmer_middlewares::calibration::{Calibration, CostTable};
+
+    let table = Calibration::new(1_000_000_000)
+        .iterations(1_000)
+        .runs(1)
+        .run(&get_store_with_middlewares(std::iter::empty()))?;
+    for class in &[
+        "numeric",
+        "division",
+        "float",
+        "memory",
+        "global",
+        "call",
+        "call_indirect",
+        "control",
+    ] {
+        assert!(table.costs[*class] >= 1);
+    }
+    assert_eq!(CostTable::from_toml(&table.to_toml()), Ok(table.clone()));
+
+    let table: &'static CostTable = Box::leak(Box::new(table));
+    let metering = Arc::new(Metering::new(u64::MAX, move |op: &Operator| table.cost(op)));
+    let store = get_store_with_middlewares(std::iter::once(metering as Arc<dyn ModuleMiddleware>));
+    let module = Module::new(&store, r#"(module (func (export "f")))"#)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let f: NativeFunc = instance.exports.get_native_function("f")?;
+    f.call()?;
+    Ok(())
+}
+
+#[test]
+fn prewarm_runs_the_warmup_within_its_own_limit() -> Result<()> {
+    let metering = Arc::new(Metering::new(100, cost_always_one));
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (memory 2)
+        (data (i32.const 65530) "crosses a page")
+        (func (export "warmup") (loop (br 0)))
+        (func (export "quick_warmup"))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+
+    assert!(metering.prewarm(&instance, "warmup", 1_000).is_err());
+    assert_eq!(metering.get_remaining_points(&instance), 100);
+
+    let touched = metering.prewarm(&instance, "quick_warmup", 1_000)?;
+    assert!(touched >= 2);
+    assert_eq!(metering.get_remaining_points(&instance), 100);
+    Ok(())
+}
+
+#[test]
+fn middleware_exports_are_registered() -> Result<()> {
+    let store = get_store_with_middlewares(std::iter::once(Arc::new(Metering::new(
+        10,
+        cost_always_one,
+    )) as Arc<dyn ModuleMiddleware>));
+    let module = Module::new(&store, r#"(module (func (export "f")))"#)?;
+
+    let expected = vec![
+        MiddlewareExport {
+            middleware: "metering".to_string(),
+            export: "remaining_points".to_string(),
+            purpose: "remaining_points".to_string(),
+        },
+        MiddlewareExport {
+            middleware: "metering".to_string(),
+            export: "points_exhausted".to_string(),
+            purpose: "points_exhausted".to_string(),
+        },
+    ];
+    assert_eq!(module.middleware_exports(), expected);
+
+    let serialized = module.serialize()?;
+    let module = unsafe { Module::deserialize(&store, &serialized)? };
+    assert_eq!(module.middleware_exports(), expected);
+
+    let instance = Instance::new(&module, &imports! {})?;
+    let remaining_points = instance.exports.get_global(&expected[0].export)?;
+    assert_eq!(remaining_points.get(), Value::I64(10));
+    Ok(())
+}
+
+#[test]
+fn middleware_registry_sections_of_the_modules_are_dropped() -> Result<()> {
+    let store = get_store(false);
+    let mut wasm =
+        wat2wasm(br#"(module (global (export "fake_points") (mut i64) (i64.const 1000000)))"#)?
+            .into_owned();
+    // A custom section forging the registry of the metering.
+    let entry = b"metering\tfake_points\tremaining_points\n";
+    let mut section = vec![MIDDLEWARE_REGISTRY_SECTION.len() as u8];
+    section.extend_from_slice(MIDDLEWARE_REGISTRY_SECTION.as_bytes());
+    section.extend_from_slice(entry);
+    wasm.push(0);
+    wasm.push(section.len() as u8);
+    wasm.extend(section);
+
+    let module = Module::new(&store, wasm)?;
+    assert_eq!(
+        module.custom_sections(MIDDLEWARE_REGISTRY_SECTION).count(),
+        0
+    );
+    assert!(module.middleware_exports().is_empty());
+    let instance = Instance::new(&module, &imports! {})?;
+    let metering = Metering::new(10, cost_always_one);
+    assert!(matches!(
+        metering.try_get_remaining_points(&instance),
+        Err(ExportError::Missing(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn metered_call_reports_the_consumed_points() -> Result<()> {
+    let metering = Arc::new(Metering::new(10, cost_always_one));
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (func (export "add") (param i32 i32) (result i32)
+           (i32.add (local.get 0)
+                    (local.get 1)))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+
+    let call = metering.metered_call(&instance, || add.call(4, 6));
+    assert_eq!(call.consumed_points(), 4);
+    assert_eq!(call.into_result()?, 10);
+    assert_eq!(metering.get_remaining_points(&instance), 6);
+
+    metering.set_remaining_points(&instance, 2);
+    let call = metering.metered_call(&instance, || add.call(4, 6));
+    assert!(call.result().is_err());
+    assert_eq!(call.consumed_points(), 0);
+    Ok(())
+}
+
+#[test]
+fn remaining_points_of_unmetered_instances_are_errors() -> Result<()> {
+    let metering = Arc::new(Metering::new(10, cost_always_one));
+    let wat = r#"(module
+        (global (export "remaining_points") i32 (i32.const 0))
+)"#;
+
+    let module = Module::new(&get_store(false), "(module)")?;
+    let instance = Instance::new(&module, &imports! {})?;
+    assert!(matches!(
+        metering.try_get_remaining_points(&instance),
+        Err(ExportError::Missing(_))
+    ));
+    assert!(metering.try_set_remaining_points(&instance, 1).is_err());
+
+    // A `remaining_points` export of the module isn't mistaken for the metering global.
+    let module = Module::new(&get_store(false), wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    assert!(matches!(
+        metering.try_get_remaining_points(&instance),
+        Err(ExportError::Missing(_))
+    ));
+
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let module = Module::new(&store, "(module)")?;
+    let instance = Instance::new(&module, &imports! {})?;
+    metering.try_set_remaining_points(&instance, 7)?;
+    assert_eq!(metering.try_get_remaining_points(&instance)?, 7);
+    Ok(())
+}
+
+#[test]
+fn exhausted_points_are_told_apart_from_unreachable() -> Result<()> {
+    let metering = Arc::new(Metering::new(10, cost_always_one));
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (func (export "spin") (loop (br 0)))
+        (func (export "crash") unreachable)
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let spin: NativeFunc = instance.exports.get_native_function("spin")?;
+    let crash: NativeFunc = instance.exports.get_native_function("crash")?;
+
+    assert!(crash.call().is_err());
+    assert!(matches!(
+        metering.get_points(&instance),
+        MeteringPoints::Remaining(_)
+    ));
+
+    assert!(spin.call().is_err());
+    assert_eq!(metering.get_points(&instance), MeteringPoints::Exhausted);
+
+    metering.set_remaining_points(&instance, 5);
+    assert_eq!(metering.get_points(&instance), MeteringPoints::Remaining(5));
+    Ok(())
+}
+
+#[test]
+fn metering_does_not_shadow_exports() -> Result<()> {
+    let wat = r#"(module
+        (global (export "remaining_points") i32 (i32.const 42))
+        (global (export "gas") i32 (i32.const 43))
+)"#;
+
+    let metering = Arc::new(Metering::new(10, cost_always_one));
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let remaining_points = instance.exports.get_global("remaining_points")?;
+    assert_eq!(remaining_points.get(), Value::I32(42));
+    assert_eq!(metering.get_remaining_points(&instance), 10);
+    let renamed = instance.exports.get_global("remaining_points_1")?;
+    assert_eq!(renamed.get(), Value::I64(10));
+
+    let metering =
+        Arc::new(Metering::new(10, cost_always_one).with_export_names("gas", "gas_exhausted"));
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    assert_eq!(instance.exports.get_global("gas")?.get(), Value::I32(43));
+    assert_eq!(instance.exports.get_global("gas_1")?.get(), Value::I64(10));
+    assert_eq!(
+        instance.exports.get_global("gas_exhausted")?.get(),
+        Value::I32(0)
+    );
+    assert_eq!(
+        metering.get_points(&instance),
+        MeteringPoints::Remaining(10)
+    );
+    Ok(())
+}
+
+#[test]
+fn metering_is_reused_across_modules() -> Result<()> {
+    let metering = Arc::new(Metering::new(10, cost_always_one));
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let add = r#"(module
+        (func (export "add") (param i32 i32) (result i32)
+           (i32.add (local.get 0)
+                    (local.get 1)))
+)"#;
+    // The globals of the metering state get other indices in this module.
+    let add_with_globals = r#"(module
+        (global $a (mut i32) (i32.const 1))
+        (global $b (mut i32) (i32.const 2))
+        (func (export "add") (param i32 i32) (result i32)
+           (global.set $a (local.get 0))
+           (global.set $b (local.get 1))
+           (i32.add (global.get $a)
+                    (global.get $b)))
+)"#;
+    let first = Instance::new(&Module::new(&store, add)?, &imports! {})?;
+    let second = Instance::new(&Module::new(&store, add_with_globals)?, &imports! {})?;
+
+    let add: NativeFunc<(i32, i32), i32> = second.exports.get_native_function("add")?;
+    assert_eq!(add.call(4, 6)?, 10);
+    assert_eq!(metering.get_remaining_points(&second), 2);
+    let add: NativeFunc<(i32, i32), i32> = first.exports.get_native_function("add")?;
+    assert_eq!(add.call(4, 6)?, 10);
+    assert_eq!(metering.get_remaining_points(&first), 6);
+    Ok(())
+}
+
+#[test]
+fn forged_registry_sections_do_not_redirect_the_metering() -> Result<()> {
+    let metering = Arc::new(Metering::new(10, cost_always_one));
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let mut wasm = wat2wasm(
+        br#"(module
+        (global (export "fake_points") (mut i64) (i64.const 1000000))
+        (func (export "add") (param i32 i32) (result i32)
+           (i32.add (local.get 0)
+                    (local.get 1))))"#,
+    )?
+    .into_owned();
+    // A custom section claiming that the metering keeps its points in
+    // `fake_points`, next to the entries of the actual metering.
+    let entry = b"metering\tfake_points\tremaining_points\n";
+    let mut section = vec![MIDDLEWARE_REGISTRY_SECTION.len() as u8];
+    section.extend_from_slice(MIDDLEWARE_REGISTRY_SECTION.as_bytes());
+    section.extend_from_slice(entry);
+    wasm.push(0);
+    wasm.push(section.len() as u8);
+    wasm.extend(section);
+
+    let module = Module::new(&store, wasm)?;
+    assert!(module
+        .middleware_exports()
+        .iter()
+        .all(|export| export.export != "fake_points"));
+    let instance = Instance::new(&module, &imports! {})?;
+    let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+    assert_eq!(add.call(4, 6)?, 10);
+    assert_eq!(metering.get_remaining_points(&instance), 6);
+    assert_eq!(
+        instance.exports.get_global("fake_points")?.get(),
+        Value::I64(1_000_000)
+    );
+    Ok(())
+}
+
+#[test]
+fn points_are_counted_per_function() -> Result<()> {
+    let metering = Arc::new(Metering::new(100, cost_always_one).with_function_points());
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (func $double (param i32) (result i32)
+           (i32.mul (local.get 0) (i32.const 2)))
+        (func (export "run") (result i32)
+           (call $double (i32.const 21)))
+)"#;
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let run: NativeFunc<(), i32> = instance.exports.get_native_function("run")?;
+    assert_eq!(run.call()?, 42);
+    assert_eq!(run.call()?, 42);
+
+    assert_eq!(
+        metering.get_function_points(&instance),
+        vec![
+            FunctionPoints {
+                index: FunctionIndex::from_u32(0),
+                name: Some("double".to_string()),
+                points: 8,
+            },
+            FunctionPoints {
+                index: FunctionIndex::from_u32(1),
+                name: Some("run".to_string()),
+                points: 6,
+            },
+        ]
+    );
+    assert_eq!(metering.get_remaining_points(&instance), 86);
+
+    let metering = Arc::new(Metering::new(100, cost_always_one));
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    assert!(matches!(
+        metering.try_get_function_points(&instance),
+        Err(ExportError::Missing(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn points_used_survive_top_ups() -> Result<()> {
+    let metering = Arc::new(Metering::new(10, cost_always_one).with_points_used());
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (func (export "add") (param i32 i32) (result i32)
+           (i32.add (local.get 0)
+                    (local.get 1)))
+)"#;
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+
+    add.call(4, 6)?;
+    add.call(4, 6)?;
+    assert_eq!(metering.get_remaining_points(&instance), 2);
+    assert_eq!(metering.get_points_used(&instance), 8);
+    assert!(add.call(4, 6).is_err());
+    assert_eq!(metering.get_points_used(&instance), 8);
+
+    metering.set_remaining_points(&instance, 10);
+    add.call(4, 6)?;
+    assert_eq!(metering.get_remaining_points(&instance), 6);
+    assert_eq!(metering.get_points_used(&instance), 12);
+    let points_used = instance.exports.get_global("points_used")?;
+    assert_eq!(points_used.get(), Value::I64(12));
+    Ok(())
+}
+
+#[test]
+fn counters_saturate_instead_of_wrapping() -> Result<()> {
+    // The 4 operators of `add` cost 2^64 points, more than a `u64`.
+    fn cost_huge(_: &Operator) -> u64 {
+        1 << 62
+    }
+    let metering = Arc::new(
+        Metering::new(u64::MAX, cost_huge)
+            .with_points_used()
+            .with_saturating_counters(),
+    );
+    let store = get_store_with_middlewares(std::iter::once(
+        metering.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (func (export "add") (param i32 i32) (result i32)
+           (i32.add (local.get 0)
+                    (local.get 1)))
+)"#;
+    let instance = Instance::new(&Module::new(&store, wat)?, &imports! {})?;
+    let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+
+    add.call(4, 6)?;
+    assert_eq!(metering.get_remaining_points(&instance), 0);
+    assert_eq!(metering.get_points_used(&instance), u64::MAX);
+    assert!(add.call(4, 6).is_err());
+
+    metering.set_remaining_points(&instance, u64::MAX);
+    add.call(4, 6)?;
+    assert_eq!(metering.get_points_used(&instance), u64::MAX);
+    Ok(())
+}
@@ -16,10 +16,14 @@ struct Add2Mul {
 }
 
 impl ModuleMiddleware for Add2MulGen {
-    fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
-        Box::new(Add2Mul {
+    fn generate_function_middleware(
+        &self,
+        _: &ModuleInfo,
+        _: LocalFunctionIndex,
+    ) -> Result<Box<dyn FunctionMiddleware>, MiddlewareError> {
+        Ok(Box::new(Add2Mul {
             value_off: self.value_off,
-        })
+        }))
     }
 }
 
@@ -56,8 +60,12 @@ struct Fusion {
 }
 
 impl ModuleMiddleware for FusionGen {
-    fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
-        Box::new(Fusion { state: 0 })
+    fn generate_function_middleware(
+        &self,
+        _: &ModuleInfo,
+        _: LocalFunctionIndex,
+    ) -> Result<Box<dyn FunctionMiddleware>, MiddlewareError> {
+        Ok(Box::new(Fusion { state: 0 }))
     }
 }
 
@@ -0,0 +1,52 @@
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+
+#[test]
+fn precompile_validate_accepts_supported_modules() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"(module
+        (func (export "add") (param i32 i32) (result i32)
+           (i32.add (local.get 0) (local.get 1))))"#;
+    let binary = wat2wasm(wat.as_bytes())?;
+    assert!(Module::precompile_validate(&store, &binary).is_ok());
+    Ok(())
+}
+
+#[test]
+fn precompile_validate_reports_validation_errors() -> Result<()> {
+    let store = get_store(false);
+    let wat = "(module (func (result i32) (i64.const 1)))";
+    let binary = wat2wasm(wat.as_bytes())?;
+    let errors = Module::precompile_validate(&store, &binary).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0].unlocated(), CompileError::Validate(_)));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "test-singlepass"), ignore)]
+fn precompile_validate_reports_unsupported_operators() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"(module
+        (memory 1)
+        (func $ok (result i32) (i32.const 0))
+        (func $clear (param i32 i32)
+           (memory.fill (local.get 0) (i32.const 0) (local.get 1))
+           (memory.fill (local.get 1) (i32.const 0) (local.get 0))))"#;
+    let binary = wat2wasm(wat.as_bytes())?;
+    let errors = Module::precompile_validate(&store, &binary).unwrap_err();
+    // Every unsupported operator is reported once, at its first use.
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        errors[0].unlocated(),
+        CompileError::UnsupportedFeature(feature) if feature == "operator `MemoryFill`"
+    ));
+    let location = errors[0].location().expect("the operator is in a function");
+    assert_eq!(
+        location.local_function_index,
+        LocalFunctionIndex::from_u32(1)
+    );
+    assert_eq!(location.function_name.as_deref(), Some("clear"));
+    Ok(())
+}
@@ -1,6 +1,9 @@
-use crate::utils::{get_headless_store, get_store};
+use crate::utils::{get_engine, get_headless_store, get_store, get_store_with_middlewares};
 use anyhow::Result;
+use std::sync::Arc;
+use wasmer::wasmparser::Operator;
 use wasmer::*;
+use wasmer_middlewares::Metering;
 
 #[test]
 fn test_serialize() -> Result<()> {
@@ -75,3 +78,690 @@ fn test_deserialize() -> Result<()> {
     assert_eq!(result.to_vec(), vec![Value::I64(1500)]);
     Ok(())
 }
+
+#[test]
+fn test_stripped_symbols_can_be_attached() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module $name
+            (func $crash (export "crash") unreachable)
+        )
+    "#;
+
+    let mut module = Module::new(&store, wat)?;
+    let symbols = module
+        .strip_symbols()
+        .expect("the module is not instantiated");
+    assert_eq!(symbols.name.as_deref(), Some("name"));
+    assert_eq!(module.name(), None);
+    let serialized_bytes = module.serialize()?;
+    let serialized_symbols = serialize_symbols(&symbols)?;
+
+    let headless_store = get_headless_store();
+    let crash_function_name = |module: &Module| -> Result<Option<String>> {
+        let instance = Instance::new(module, &imports! {})?;
+        let crash = instance.exports.get_function("crash")?;
+        let trace = crash.call(&[]).unwrap_err().trace().to_vec();
+        Ok(trace[0].function_name().map(ToString::to_string))
+    };
+
+    let stripped = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+    assert_eq!(stripped.name(), None);
+    assert_eq!(crash_function_name(&stripped)?, None);
+
+    let mut symbolicated = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+    assert!(symbolicated.attach_symbols(deserialize_symbols(&serialized_symbols)?));
+    assert_eq!(symbolicated.name(), Some("name"));
+    assert_eq!(
+        crash_function_name(&symbolicated)?,
+        Some("crash".to_string())
+    );
+    Ok(())
+}
+
+#[test]
+fn test_module_hash_survives_serialization() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"(module (func (export "run")))"#;
+
+    let module = Module::new(&store, wat)?;
+    let hash = module
+        .hash()
+        .expect("the module was compiled from a binary");
+    assert_eq!(
+        Module::new(&store, wat2wasm(wat.as_bytes())?)?.hash(),
+        Some(hash)
+    );
+    assert_ne!(Module::new(&store, "(module)")?.hash(), Some(hash));
+
+    let serialized_bytes = module.serialize()?;
+    let headless_store = get_headless_store();
+    let deserialized_module = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+    assert_eq!(deserialized_module.hash(), Some(hash));
+
+    assert_eq!(module.fingerprint()?, module.fingerprint()?);
+    assert_ne!(
+        module.fingerprint()?,
+        Module::new(&store, "(module)")?.fingerprint()?
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "test-jit")]
+fn test_deserialize_rejects_other_format_versions() -> Result<()> {
+    use wasmer_engine::ARTIFACT_FORMAT_VERSION;
+
+    let store = get_store(false);
+    let module = Module::new(&store, r#"(module (func (export "run")))"#)?;
+    let mut serialized_bytes = module.serialize()?;
+    let header_len = b"\0wasmer-jit".len();
+    let version = &mut serialized_bytes[header_len..header_len + 4];
+    assert_eq!(version, &ARTIFACT_FORMAT_VERSION.to_le_bytes()[..]);
+    version.copy_from_slice(&(ARTIFACT_FORMAT_VERSION + 1).to_le_bytes());
+
+    let headless_store = get_headless_store();
+    match unsafe { Module::deserialize(&headless_store, &serialized_bytes) } {
+        Err(DeserializeError::Incompatible(message)) => assert!(message.contains("version")),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("an artifact of another format version was deserialized"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_deserialize_compressed() -> Result<()> {
+    let store = get_store(false);
+    let wat = format!(
+        r#"(module
+            (memory 1)
+            (data (i32.const 0) "{}")
+            (func (export "load") (param i32) (result i32)
+                (i32.load8_u (local.get 0))))"#,
+        "wasmer ".repeat(1000)
+    );
+
+    let module = Module::new(&store, wat)?;
+    let serialized_bytes = module.serialize()?;
+    let compressed_bytes = module.serialize_compressed(3)?;
+    assert!(compressed_bytes.len() <= serialized_bytes.len());
+    #[cfg(all(feature = "test-jit", feature = "compression"))]
+    assert!(compressed_bytes.len() < serialized_bytes.len() / 2);
+
+    let headless_store = get_headless_store();
+    let deserialized_module = unsafe { Module::deserialize(&headless_store, &compressed_bytes)? };
+    let instance = Instance::new(&deserialized_module, &imports! {})?;
+    let load: NativeFunc<i32, i32> = instance.exports.get_native_function("load")?;
+    assert_eq!(load.call(3)?, b'm' as i32);
+    Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "test-llvm", feature = "test-jit"))]
+fn test_deserialize_rejects_less_hardened_artifacts() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"(module (func (export "run")))"#;
+    let module = Module::new(&store, wat)?;
+    assert_eq!(module.artifact().code_hardening(), CodeHardening::none());
+    let serialized_bytes = module.serialize()?;
+
+    let hardening = CodeHardening {
+        branch_target_identification: true,
+        ..CodeHardening::none()
+    };
+    let mut compiler = wasmer_compiler_llvm::LLVM::new();
+    compiler.code_hardening(hardening);
+    let hardened_store = Store::new(&wasmer_engine_jit::JIT::new(compiler).engine());
+    let error = unsafe { Module::deserialize(&hardened_store, &serialized_bytes) }.unwrap_err();
+    assert!(matches!(error, DeserializeError::Incompatible(_)));
+
+    let hardened_module = Module::new(&hardened_store, wat)?;
+    assert_eq!(hardened_module.artifact().code_hardening(), hardening);
+    let serialized_bytes = hardened_module.serialize()?;
+    let deserialized_module = unsafe { Module::deserialize(&hardened_store, &serialized_bytes)? };
+    assert_eq!(deserialized_module.artifact().code_hardening(), hardening);
+    // Engines without a compiler accept any hardening.
+    unsafe { Module::deserialize(&get_headless_store(), &serialized_bytes)? };
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "test-jit")]
+fn test_headless_engines_enforce_the_required_hardening() -> Result<()> {
+    let store = get_store(false);
+    let module = Module::new(&store, r#"(module (func (export "run")))"#)?;
+    assert!(
+        !module
+            .artifact()
+            .code_hardening()
+            .branch_target_identification
+    );
+    let serialized_bytes = module.serialize()?;
+
+    let required = CodeHardening {
+        branch_target_identification: true,
+        ..CodeHardening::none()
+    };
+    let engine = wasmer_engine_jit::JIT::headless()
+        .required_code_hardening(required)
+        .engine();
+    assert_eq!(engine.required_code_hardening(), required);
+    let error =
+        unsafe { Module::deserialize(&Store::new(&engine), &serialized_bytes) }.unwrap_err();
+    match error {
+        DeserializeError::Incompatible(message) => {
+            assert!(message.contains("branch target identification"))
+        }
+        error => panic!("unexpected error: {}", error),
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "test-cranelift", feature = "test-jit"))]
+fn test_cranelift_spectre_hardening() -> Result<()> {
+    let wat = r#"(module
+        (type $t (func (result i32)))
+        (table 2 funcref)
+        (elem (i32.const 0) $f)
+        (func $f (result i32) (i32.const 42))
+        (func (export "call") (param i32) (result i32)
+            (call_indirect (type $t) (local.get 0))))"#;
+    let compiler = wasmer_compiler_cranelift::Cranelift::new();
+    let store = Store::new(&wasmer_engine_jit::JIT::new(compiler).engine());
+    let module = Module::new(&store, wat)?;
+    let expected = CodeHardening {
+        spectre_bounds_checks: true,
+        ..CodeHardening::none()
+    };
+    assert_eq!(module.artifact().code_hardening(), expected);
+
+    let hardening = CodeHardening {
+        spectre_table_index_masking: true,
+        ..expected
+    };
+    let mut compiler = wasmer_compiler_cranelift::Cranelift::new();
+    compiler.code_hardening(hardening);
+    let store = Store::new(&wasmer_engine_jit::JIT::new(compiler).engine());
+    let module = Module::new(&store, wat)?;
+    assert_eq!(module.artifact().code_hardening(), hardening);
+    let instance = Instance::new(&module, &imports! {})?;
+    let call = instance.exports.get_native_function::<i32, i32>("call")?;
+    assert_eq!(call.call(0)?, 42);
+    // The masked index still traps out of bounds.
+    assert!(call.call(2).is_err());
+    assert!(call.call(-1).is_err());
+
+    let mut compiler = wasmer_compiler_cranelift::Cranelift::new();
+    compiler.code_hardening(CodeHardening {
+        retpolines: true,
+        ..expected
+    });
+    let store = Store::new(&wasmer_engine_jit::JIT::new(compiler).engine());
+    assert!(matches!(
+        Module::new(&store, wat),
+        Err(CompileError::UnsupportedFeature(_))
+    ));
+    Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "test-cranelift", feature = "test-jit"))]
+fn test_spectre_mitigations_are_only_enforced_when_required() -> Result<()> {
+    let wat = r#"(module (func (export "run")))"#;
+    let mut compiler = wasmer_compiler_cranelift::Cranelift::new();
+    compiler.code_hardening(CodeHardening::none());
+    let store = Store::new(&wasmer_engine_jit::JIT::new(compiler).engine());
+    let serialized_bytes = Module::new(&store, wat)?.serialize()?;
+
+    // The default Cranelift engine applies the Spectre bounds checks, but
+    // loads the artifacts compiled without them.
+    let compiler = wasmer_compiler_cranelift::Cranelift::new();
+    let store = Store::new(&wasmer_engine_jit::JIT::new(compiler).engine());
+    unsafe { Module::deserialize(&store, &serialized_bytes)? };
+
+    let compiler = wasmer_compiler_cranelift::Cranelift::new();
+    let engine = wasmer_engine_jit::JIT::new(compiler)
+        .required_code_hardening(CodeHardening {
+            spectre_bounds_checks: true,
+            ..CodeHardening::none()
+        })
+        .engine();
+    let error =
+        unsafe { Module::deserialize(&Store::new(&engine), &serialized_bytes) }.unwrap_err();
+    assert!(matches!(error, DeserializeError::Incompatible(_)));
+    Ok(())
+}
+
+#[test]
+fn test_deferred_passive_data_is_loaded_on_memory_init() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (memory (export "memory") 1)
+            (data "small")
+            (data "a much larger asset")
+            (func (export "load") (param i32)
+                (memory.init 1 (local.get 0) (i32.const 2) (i32.const 5)))
+            (func (export "load_out_of_bounds")
+                (memory.init 1 (i32.const 0) (i32.const 16) (i32.const 8)))
+        )
+    "#;
+
+    let mut module = Module::new(&store, wat)?;
+    let deferred = module
+        .defer_passive_data(8)
+        .expect("the module is not instantiated");
+    assert_eq!(deferred.len(), 1);
+    let serialized_bytes = module.serialize()?;
+
+    let loads = Arc::new(AtomicUsize::new(0));
+    let headless_store = get_headless_store();
+    let mut module = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+    let loader_loads = loads.clone();
+    assert!(module.set_data_loader(move |data_index: DataIndex| {
+        loader_loads.fetch_add(1, Ordering::SeqCst);
+        deferred
+            .iter()
+            .find(|(index, _)| *index == data_index)
+            .map(|(_, data)| data.clone())
+            .ok_or_else(|| "unknown segment".to_string())
+    }));
+
+    let instance = Instance::new(&module, &imports! {})?;
+    assert_eq!(loads.load(Ordering::SeqCst), 0);
+
+    let load_out_of_bounds = instance.exports.get_function("load_out_of_bounds")?;
+    assert!(load_out_of_bounds.call(&[]).is_err());
+    assert_eq!(loads.load(Ordering::SeqCst), 0);
+
+    let load = instance.exports.get_native_function::<i32, ()>("load")?;
+    load.call(0)?;
+    load.call(8)?;
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+    let memory = instance.exports.get_memory("memory")?;
+    let bytes = memory.view::<u8>()[0..13]
+        .iter()
+        .map(|cell| cell.get())
+        .collect::<Vec<_>>();
+    assert_eq!(&bytes, b"much \0\0\0much ");
+    Ok(())
+}
+
+#[test]
+fn test_stores_sharing_an_engine_share_trampolines() -> Result<()> {
+    let engine = get_engine(false);
+    let wat = r#"
+        (module
+            (func (export "add") (param i32 i32) (result i32)
+                (i32.add (local.get 0) (local.get 1)))
+            (func (export "sub") (param i32 i32) (result i32)
+                (i32.sub (local.get 0) (local.get 1))))
+    "#;
+    let serialized_bytes = Module::new(&Store::new(&engine), wat)?.serialize()?;
+
+    // Like a host creating a fresh store per request.
+    for i in 0..3 {
+        let store = Store::new(&engine);
+        let module = unsafe { Module::deserialize(&store, &serialized_bytes)? };
+        let instance = Instance::new(&module, &imports! {})?;
+        let add = instance.exports.get_function("add")?;
+        let sub = instance.exports.get_function("sub")?;
+        assert_eq!(
+            add.call(&[Val::I32(i), Val::I32(2)])?.to_vec(),
+            vec![Val::I32(i + 2)]
+        );
+        assert_eq!(
+            sub.call(&[Val::I32(i), Val::I32(2)])?.to_vec(),
+            vec![Val::I32(i - 2)]
+        );
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "test-jit")]
+fn test_deserialize_deduplicates_functions() -> Result<()> {
+    use wasmer_engine::Artifact;
+    use wasmer_engine_jit::JIT;
+    use wasmer_types::entity::EntityRef;
+    use wasmer_types::LocalFunctionIndex;
+
+    let store = get_store(false);
+    let wat = r#"
+        (module
+        (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1)))
+        (func (export "add_again") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1)))
+        )
+    "#;
+    let serialized_bytes = Module::new(&store, wat)?.serialize()?;
+
+    let headless_store = Store::new(&JIT::headless().deduplicate_functions(true).engine());
+    let first = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+    let second = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+    let add = LocalFunctionIndex::new(0);
+    let add_again = LocalFunctionIndex::new(1);
+    let first_functions = first.artifact().finished_functions();
+    let second_functions = second.artifact().finished_functions();
+    assert_eq!(*first_functions[add], *first_functions[add_again]);
+    assert_eq!(*first_functions[add], *second_functions[add]);
+
+    for module in [first, second].iter() {
+        let instance = Instance::new(module, &imports! {})?;
+        let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+        assert_eq!(add.call(1, 2)?, 3);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "test-jit")]
+fn test_deduplicated_functions_have_frame_info() -> Result<()> {
+    use wasmer_engine_jit::JIT;
+
+    let store = get_store(false);
+    let module_wat = |name: &str| {
+        format!(
+            r#"(module ${}
+            (func $fail (export "fail") unreachable))"#,
+            name
+        )
+    };
+    let first_bytes = Module::new(&store, module_wat("first"))?.serialize()?;
+    let second_bytes = Module::new(&store, module_wat("second"))?.serialize()?;
+
+    let headless_store = Store::new(&JIT::headless().deduplicate_functions(true).engine());
+    let first = unsafe { Module::deserialize(&headless_store, &first_bytes)? };
+    let second = unsafe { Module::deserialize(&headless_store, &second_bytes)? };
+    // The body of `fail` is in the code of the first module, which is
+    // never instantiated.
+    drop(first);
+
+    let instance = Instance::new(&second, &imports! {})?;
+    let fail: NativeFunc<(), ()> = instance.exports.get_native_function("fail")?;
+    let error = fail.call().unwrap_err();
+    let trace = error.trace();
+    assert_eq!(trace.len(), 1);
+    assert_eq!(trace[0].module_name(), "second");
+    assert_eq!(trace[0].function_name(), Some("fail"));
+    Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "test-jit", target_os = "linux"))]
+fn test_deserialize_shares_code_mappings() -> Result<()> {
+    use wasmer_engine::Artifact;
+    use wasmer_engine_jit::JIT;
+    use wasmer_types::entity::EntityRef;
+    use wasmer_types::LocalFunctionIndex;
+
+    let store = get_store(false);
+    let wat = r#"
+        (module
+        (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1)))
+        )
+    "#;
+    let serialized_bytes = Module::new(&store, wat)?.serialize()?;
+    let temp_dir = tempfile::tempdir()?;
+    let directory = temp_dir.path().join("code");
+
+    // Two engines loading the same module map its code from one file.
+    let mut modules = Vec::new();
+    for _ in 0..2 {
+        let engine = JIT::headless()
+            .share_code_mappings(true)
+            .shared_code_directory(&directory)
+            .engine();
+        assert!(engine.shares_code_mappings());
+        let headless_store = Store::new(&engine);
+        let module = unsafe { Module::deserialize(&headless_store, &serialized_bytes)? };
+        let add = *module.artifact().finished_functions()[LocalFunctionIndex::new(0)];
+        let maps = std::fs::read_to_string("/proc/self/maps")?;
+        let mapping = maps
+            .lines()
+            .find(|line| {
+                let range = line.split(' ').next().unwrap();
+                let mut bounds = range
+                    .split('-')
+                    .map(|bound| usize::from_str_radix(bound, 16).unwrap());
+                let (start, end) = (bounds.next().unwrap(), bounds.next().unwrap());
+                (start..end).contains(&(add as usize))
+            })
+            .unwrap();
+        assert!(mapping.contains(directory.to_str().unwrap()), "{}", mapping);
+
+        let instance = Instance::new(&module, &imports! {})?;
+        let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+        assert_eq!(add.call(1, 2)?, 3);
+        modules.push(module);
+    }
+    assert_eq!(std::fs::read_dir(&directory)?.count(), 1);
+
+    // The file is removed with the engines.
+    drop(modules);
+    assert_eq!(std::fs::read_dir(&directory)?.count(), 0);
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "test-jit")]
+fn test_serialization_is_deterministic() -> Result<()> {
+    let wat = r#"
+        (module $names
+        (import "env" "f" (func $f))
+        (memory $heap 1)
+        (table $table 2 funcref)
+        (global $g (mut i32) (i32.const 0))
+        (func $a (param $x i32) (result i32) (local.get $x))
+        (func $b (call $f))
+        (func $c)
+        (elem func $a $b)
+        (elem func $c)
+        (data "first")
+        (data "second")
+        (data "third")
+        (export "a" (func $a))
+        (export "b" (func $b))
+        (export "c" (func $c))
+        (export "heap" (memory $heap))
+        )
+    "#;
+    let serialize = || -> Result<Vec<u8>> {
+        let metering = Arc::new(Metering::new(10, |_: &Operator| 1));
+        let store =
+            get_store_with_middlewares(std::iter::once(metering as Arc<dyn ModuleMiddleware>));
+        Ok(Module::new(&store, wat)?.serialize()?)
+    };
+
+    let serialized_bytes = serialize()?;
+    for _ in 0..4 {
+        assert_eq!(serialize()?, serialized_bytes);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "test-cranelift", feature = "test-jit"))]
+fn test_function_calls_are_counted() -> Result<()> {
+    use crate::utils::get_compiler;
+    use wasmer_engine_jit::JIT;
+
+    let wat = r#"(module
+        (func $double (param i32) (result i32)
+            (i32.mul (local.get 0) (i32.const 2)))
+        (func (export "run") (param i32) (result i32)
+            (call $double (call $double (local.get 0)))))"#;
+    let store = Store::new(&JIT::new(get_compiler(false)).engine());
+    let module = Module::new(&store, wat)?;
+    assert_eq!(module.function_call_counts(), None);
+
+    let compiler = get_compiler(false);
+    let store = Store::new(&JIT::new(compiler).count_function_calls(true).engine());
+    let module = Module::new(&store, wat)?;
+    let double = FunctionIndex::from_u32(0);
+    let run = FunctionIndex::from_u32(1);
+    assert_eq!(
+        module.function_call_counts(),
+        Some(vec![(double, 0), (run, 0)])
+    );
+
+    for _ in 0..2 {
+        let instance = Instance::new(&module, &imports! {})?;
+        let run: NativeFunc<i32, i32> = instance.exports.get_native_function("run")?;
+        assert_eq!(run.call(3)?, 12);
+    }
+    assert_eq!(
+        module.function_call_counts(),
+        Some(vec![(double, 4), (run, 2)])
+    );
+
+    // The deserialized modules get their own counters.
+    let serialized = module.serialize()?;
+    let module = unsafe { Module::deserialize(&get_headless_store(), &serialized)? };
+    assert_eq!(
+        module.function_call_counts(),
+        Some(vec![(double, 0), (run, 0)])
+    );
+    let instance = Instance::new(&module, &imports! {})?;
+    let run_function: NativeFunc<i32, i32> = instance.exports.get_native_function("run")?;
+    assert_eq!(run_function.call(1)?, 4);
+    assert_eq!(
+        module.function_call_counts(),
+        Some(vec![(double, 2), (run, 1)])
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(all(not(feature = "test-cranelift"), feature = "test-jit"))]
+fn test_function_calls_are_not_counted_without_counters() -> Result<()> {
+    use crate::utils::get_compiler;
+    use wasmer_engine_jit::JIT;
+
+    let store = Store::new(
+        &JIT::new(get_compiler(false))
+            .count_function_calls(true)
+            .engine(),
+    );
+    let module = Module::new(&store, r#"(module (func (export "run")))"#)?;
+    assert_eq!(module.function_call_counts(), None);
+    let instance = Instance::new(&module, &imports! {})?;
+    let run: NativeFunc<(), ()> = instance.exports.get_native_function("run")?;
+    run.call()?;
+    assert_eq!(module.function_call_counts(), None);
+    Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "test-jit", target_arch = "x86_64"))]
+fn test_deserialize_best_match() -> Result<()> {
+    use crate::utils::get_compiler;
+
+    let store_for = |cpu_features| {
+        let target = Target::new(Triple::host(), cpu_features);
+        Store::new(&JIT::new(get_compiler(false)).target(target).engine())
+    };
+    // x86-64 requires SSE2, every host has more features.
+    let mut baseline_features = CpuFeature::set();
+    baseline_features.insert(CpuFeature::SSE2);
+    let baseline_store = store_for(baseline_features);
+    let host_store = store_for(CpuFeature::for_host());
+    assert!(CpuFeature::for_host().len() > baseline_features.len());
+
+    let wat = r#"(module (func (export "add") (param i32 i32) (result i32)
+        (i32.add (local.get 0) (local.get 1))))"#;
+    let baseline = Module::new(&baseline_store, wat)?;
+    let host = Module::new(&host_store, wat)?;
+    let serialized = Module::serialize_multi_target(&[baseline.clone(), host.clone()])?;
+
+    for store in &[&baseline_store, &host_store] {
+        let module = unsafe { Module::deserialize_best_match(store, &serialized)? };
+        let instance = Instance::new(&module, &imports! {})?;
+        let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+        assert_eq!(add.call(1, 2)?, 3);
+    }
+
+    // The baseline engine can't run the artifact compiled for the host
+    // features.
+    let serialized = Module::serialize_multi_target(&[host])?;
+    match unsafe { Module::deserialize_best_match(&baseline_store, &serialized) } {
+        Err(DeserializeError::Incompatible(_)) => {}
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("the host artifact was selected for the baseline engine"),
+    }
+
+    let other = Module::new(&baseline_store, "(module)")?;
+    assert!(Module::serialize_multi_target(&[baseline, other]).is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg(all(
+    feature = "test-cranelift",
+    feature = "test-jit",
+    target_arch = "x86_64"
+))]
+fn test_feature_tiers_are_selected_at_load_time() -> Result<()> {
+    let mut baseline_features = CpuFeature::set();
+    baseline_features.insert(CpuFeature::SSE2);
+    let mut unsupported_features = CpuFeature::for_host();
+    unsupported_features.insert(CpuFeature::AVX512VL);
+    unsupported_features.insert(CpuFeature::AVX512DQ);
+
+    let mut compiler = wasmer_compiler_cranelift::Cranelift::new();
+    compiler.feature_tiers(vec![CpuFeature::for_host(), unsupported_features]);
+    let target = Target::new(Triple::host(), baseline_features);
+    let store = Store::new(&JIT::new(compiler).target(target).engine());
+
+    let wat = r#"(module (func (export "add") (param i32 i32) (result i32)
+        (i32.add (local.get 0) (local.get 1))))"#;
+    let module = Module::new(&store, wat)?;
+    let loaded_feature_tier = |module: &Module| {
+        let artifact = module.artifact().downcast_ref::<JITArtifact>().unwrap();
+        artifact.loaded_feature_tier()
+    };
+    assert_eq!(loaded_feature_tier(&module), Some(CpuFeature::for_host()));
+
+    // The tiers are kept in the serialized artifact.
+    let module = unsafe { Module::deserialize(&store, &module.serialize()?)? };
+    assert_eq!(loaded_feature_tier(&module), Some(CpuFeature::for_host()));
+    let instance = Instance::new(&module, &imports! {})?;
+    let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+    assert_eq!(add.call(1, 2)?, 3);
+    Ok(())
+}
+
+#[test]
+#[cfg(all(
+    feature = "test-cranelift",
+    feature = "test-jit",
+    target_arch = "x86_64"
+))]
+fn test_feature_tiers_reuse_the_transformed_module() -> Result<()> {
+    use wasmer_middlewares::debugger::Debugger;
+
+    // A `Debugger` transforms a single module: the tiers must not
+    // transform the module again.
+    let debugger = Arc::new(Debugger::new(vec![]));
+    let mut compiler = wasmer_compiler_cranelift::Cranelift::new();
+    compiler.push_middleware(debugger.clone());
+    compiler.feature_tiers(vec![CpuFeature::for_host()]);
+    let store = Store::new(&JIT::new(compiler).engine());
+
+    let wat = r#"(module (func (export "add") (param i32 i32) (result i32)
+        (i32.add (local.get 0) (local.get 1))))"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let session = debugger.session(&instance)?;
+    let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add")?;
+    assert_eq!(add.call(1, 2)?, 3);
+    assert_eq!(session.steps(), 4);
+    Ok(())
+}
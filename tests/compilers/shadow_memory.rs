@@ -0,0 +1,145 @@
+use crate::utils::get_store_with_middlewares;
+use anyhow::Result;
+use wasmer_middlewares::shadow_memory::ShadowMemory;
+
+use std::sync::Arc;
+use wasmer::*;
+
+#[test]
+fn shadow_memory_catches_uninitialized_reads() -> Result<()> {
+    let shadow_memory = Arc::new(ShadowMemory::new(Pages(4)));
+    let store = get_store_with_middlewares(std::iter::once(
+        shadow_memory.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (memory (export "memory") 1)
+        (data (i32.const 0) "hi")
+        (func (export "poke") (param i32 i32)
+           (i32.store (local.get 0) (local.get 1)))
+        (func (export "peek") (param i32) (result i32)
+           (i32.load (local.get 0)))
+        (func (export "peek8") (param i32) (result i32)
+           (i32.load8_u (local.get 0)))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let poke: NativeFunc<(i32, i32), ()> = instance.exports.get_native_function("poke")?;
+    let peek: NativeFunc<i32, i32> = instance.exports.get_native_function("peek")?;
+    let peek8: NativeFunc<i32, i32> = instance.exports.get_native_function("peek8")?;
+
+    // The loads are only checked once enabled.
+    assert_eq!(peek.call(100)?, 0);
+    shadow_memory.enable(&instance);
+    assert_eq!(peek8.call(0)?, i32::from(b'h'));
+
+    let error = peek.call(100).unwrap_err();
+    let read = shadow_memory
+        .uninitialized_read(&instance, &error)
+        .expect("uninitialized read");
+    assert_eq!(read.address, 100);
+    assert_eq!(read.size, 4);
+    assert_eq!(read.frame.map(|frame| frame.func_index()), Some(1));
+    assert!(shadow_memory
+        .uninitialized_read(&instance, &error)
+        .is_none());
+
+    poke.call(100, 7)?;
+    assert_eq!(peek.call(100)?, 7);
+    // The bytes 104 and 105 were never written.
+    let error = peek.call(102).unwrap_err();
+    let read = shadow_memory.uninitialized_read(&instance, &error);
+    assert_eq!(read.map(|read| read.address), Some(102));
+
+    shadow_memory.mark_initialized(&instance, 200..204);
+    assert_eq!(peek.call(200)?, 0);
+    Ok(())
+}
+
+#[test]
+fn shadow_memory_hides_the_shadow_pages() -> Result<()> {
+    let shadow_memory = Arc::new(ShadowMemory::new(Pages(4)));
+    let store = get_store_with_middlewares(std::iter::once(
+        shadow_memory.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (memory 1)
+        (func (export "size") (result i32)
+           (memory.size))
+        (func (export "grow") (param i32) (result i32)
+           (memory.grow (local.get 0)))
+        (func (export "poke") (param i32 i32)
+           (i32.store (local.get 0) (local.get 1)))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let size: NativeFunc<(), i32> = instance.exports.get_native_function("size")?;
+    let grow: NativeFunc<i32, i32> = instance.exports.get_native_function("grow")?;
+    let poke: NativeFunc<(i32, i32), ()> = instance.exports.get_native_function("poke")?;
+
+    assert_eq!(size.call()?, 1);
+    assert_eq!(grow.call(1)?, 1);
+    assert_eq!(size.call()?, 2);
+    assert_eq!(grow.call(3)?, -1);
+    assert_eq!(size.call()?, 2);
+
+    // The whole memory has 6 pages, but the guest only sees 2 of them.
+    poke.call(2 * 65536 - 4, 7)?;
+    let error = poke.call(2 * 65536, 7).unwrap_err();
+    assert_eq!(error.trap_code(), Some(TrapCode::HeapAccessOutOfBounds));
+    assert!(shadow_memory
+        .uninitialized_read(&instance, &error)
+        .is_none());
+    Ok(())
+}
+
+#[test]
+fn shadow_memory_does_not_shadow_exports() -> Result<()> {
+    let shadow_memory = Arc::new(ShadowMemory::new(Pages(4)));
+    let store = get_store_with_middlewares(std::iter::once(
+        shadow_memory.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (memory 1)
+        (global (export "shadow_memory") i32 (i32.const 42))
+        (func (export "shadow_enabled") (result i32) (i32.const 43))
+        (func (export "peek") (param i32) (result i32)
+           (i32.load (local.get 0)))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let peek: NativeFunc<i32, i32> = instance.exports.get_native_function("peek")?;
+    let shadow_enabled: NativeFunc<(), i32> =
+        instance.exports.get_native_function("shadow_enabled")?;
+    assert_eq!(
+        instance.exports.get_global("shadow_memory")?.get(),
+        Value::I32(42)
+    );
+
+    shadow_memory.enable(&instance);
+    assert_eq!(shadow_enabled.call()?, 43);
+    let error = peek.call(100).unwrap_err();
+    let read = shadow_memory.uninitialized_read(&instance, &error);
+    assert_eq!(read.map(|read| read.address), Some(100));
+    shadow_memory.mark_initialized(&instance, 100..104);
+    assert_eq!(peek.call(100)?, 0);
+    Ok(())
+}
+
+#[test]
+fn shadow_memory_rejects_the_memories_it_cannot_shadow() -> Result<()> {
+    for wat in &[
+        r#"(module (import "env" "memory" (memory 1)))"#,
+        r#"(module (memory 5))"#,
+    ] {
+        let shadow_memory = Arc::new(ShadowMemory::new(Pages(4)));
+        let store =
+            get_store_with_middlewares(std::iter::once(shadow_memory as Arc<dyn ModuleMiddleware>));
+        match Module::new(&store, wat) {
+            Err(CompileError::Wasm(WasmError::Middleware(error))) => {
+                assert_eq!(error.name, "shadow_memory");
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+    Ok(())
+}
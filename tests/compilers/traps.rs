@@ -203,6 +203,41 @@ RuntimeError: unreachable
     Ok(())
 }
 
+#[test]
+fn trap_trace_uses_the_embedded_source_map() -> Result<()> {
+    let store = get_store(false);
+    let mut wasm = wat2wasm(br#"(module (func (export "die") unreachable))"#)?.to_vec();
+
+    // A source map mapping the whole module to the line 5 of `main.ts`.
+    let url = "data:application/json;base64,\
+        eyJ2ZXJzaW9uIjozLCJzb3VyY2VzIjpbIm1haW4udHMiXSwibWFwcGluZ3MiOiJBQUlBIn0=";
+    let mut section = vec![16];
+    section.extend_from_slice(b"sourceMappingURL");
+    section.push(url.len() as u8);
+    section.extend_from_slice(url.as_bytes());
+    assert!(section.len() < 128);
+    wasm.push(0);
+    wasm.push(section.len() as u8);
+    wasm.extend_from_slice(&section);
+
+    let module = Module::new(&store, &wasm)?;
+    assert_eq!(module.source_mapping_url().as_deref(), Some(url));
+    let instance = Instance::new(&module, &imports! {})?;
+    let die = instance.exports.get_function("die")?;
+
+    let e = die.call(&[]).unwrap_err();
+    let location = e.trace()[0].source_location().expect("source location");
+    assert_eq!(location.to_string(), "main.ts:5:1");
+    assert!(e.to_string().ends_with(" at main.ts:5:1"));
+
+    let source_map = SourceMap::from_data_url(url)?;
+    assert_eq!(
+        source_map.lookup(e.trace()[0].module_offset()).as_ref(),
+        Some(location)
+    );
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(
     any(
@@ -264,6 +299,72 @@ RuntimeError: unreachable
     Ok(())
 }
 
+#[test]
+#[cfg_attr(
+    any(
+        feature = "test-singlepass",
+        feature = "test-llvm",
+        feature = "test-native",
+        target_arch = "aarch64",
+    ),
+    ignore
+)]
+fn trap_trace_continues_through_host_functions() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module $callee
+            (func $die unreachable)
+            (func (export "fail") call $die)
+        )
+    "#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let fail = instance.exports.get_function("fail")?.clone();
+    let host = Function::new(&store, &FunctionType::new(vec![], vec![]), move |_| {
+        fail.call(&[])?;
+        Ok(vec![])
+    });
+
+    let wat = r#"
+        (module $caller
+            (import "" "host" (func $host))
+            (func $middle call $host)
+            (func (export "run") call $middle)
+        )
+    "#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(
+        &module,
+        &imports! {
+            "" => {
+                "host" => host
+            }
+        },
+    )?;
+    let run = instance.exports.get_function("run")?;
+
+    let e = run.call(&[]).err().expect("error calling function");
+    let frames = e
+        .trace()
+        .iter()
+        .map(|frame| (frame.module_name().to_string(), frame.func_index()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        frames,
+        vec![
+            ("callee".to_string(), 0),
+            ("callee".to_string(), 1),
+            ("caller".to_string(), 1),
+            ("caller".to_string(), 2),
+        ]
+    );
+    assert_eq!(e.host_boundaries(), &[2]);
+    let display = e.to_string();
+    assert!(display.contains("(callee[1]:0x"));
+    assert!(display.contains("\n    at <host>\n    at middle (caller[1]:0x"));
+    Ok(())
+}
+
 #[test]
 fn trap_start_function_import() -> Result<()> {
     let store = get_store(false);
@@ -299,6 +400,142 @@ fn trap_start_function_import() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn trap_start_function_import_with_allowed_features() -> Result<()> {
+    let store = get_store(false).with_allowed_features(Features::new());
+    let binary = r#"
+        (module $a
+            (import "" "" (func $foo))
+            (start $foo)
+        )
+    "#;
+
+    let module = Module::new(&store, &binary)?;
+    let sig = FunctionType::new(vec![], vec![]);
+    let func = Function::new(&store, &sig, |_| Err(RuntimeError::new("user trap")));
+    let err = Instance::new(
+        &module,
+        &imports! {
+            "" => {
+                "" => func
+            }
+        },
+    )
+    .err()
+    .unwrap();
+    match err {
+        InstantiationError::Start(err) => assert_eq!(err.message(), "user trap"),
+        err => panic!("It should be a start error, got {}", err),
+    }
+    Ok(())
+}
+
+#[test]
+fn guest_abort_with_message() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (import "wasmer" "abort" (func $abort (param i32 i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "invalid input")
+            (func (export "abort") (call $abort (i32.const 16) (i32.const 13)))
+            (func (export "abort_out_of_bounds") (call $abort (i32.const 65530) (i32.const 13)))
+        )
+    "#;
+
+    let module = Module::new(&store, &wat)?;
+    let instance = Instance::new(
+        &module,
+        &imports! {
+            "wasmer" => {
+                "abort" => Function::new_guest_abort(&store),
+            }
+        },
+    )?;
+
+    let abort: NativeFunc = instance.exports.get_native_function("abort")?;
+    let err = abort.call().unwrap_err();
+    assert_eq!(err.guest_abort_message(), Some("invalid input"));
+    assert_eq!(err.message(), "guest aborted: invalid input");
+
+    let abort: NativeFunc = instance
+        .exports
+        .get_native_function("abort_out_of_bounds")?;
+    let err = abort.call().unwrap_err();
+    assert!(err.guest_abort_message().unwrap().contains("out of bounds"));
+
+    Ok(())
+}
+
+#[test]
+fn rust_panic_is_decoded() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (func $_ZN4core9panicking5panic17h0123456789abcdefE unreachable)
+            (func $_ZN8contract7execute17h0123456789abcdefE (export "execute")
+                call $_ZN4core9panicking5panic17h0123456789abcdefE)
+            (func (export "unreachable") unreachable)
+        )
+    "#;
+
+    let module = Module::new(&store, &wat)?;
+    assert!(RustPanic::is_rust_module(&module));
+    let instance = Instance::new(&module, &imports! {})?;
+
+    let unreachable: NativeFunc = instance.exports.get_native_function("unreachable")?;
+    let err = unreachable.call().unwrap_err();
+    assert!(RustPanic::decode(&err).is_none());
+
+    let execute: NativeFunc = instance.exports.get_native_function("execute")?;
+    let err = execute.call().unwrap_err();
+    let panic = RustPanic::decode(&err).expect("the trap of a Rust panic");
+    assert_eq!(panic.message(), None);
+    assert_eq!(panic.backtrace().len(), 1);
+    assert!(panic.backtrace()[0].starts_with("contract::execute"));
+
+    let stderr = "thread 'main' panicked at 'index out of bounds', src/lib.rs:3:5\n";
+    let panic = panic.with_output(stderr);
+    assert_eq!(panic.message(), Some("index out of bounds"));
+    assert_eq!(panic.location(), Some("src/lib.rs:3:5"));
+
+    let err = panic.into_runtime_error();
+    assert!(err
+        .message()
+        .starts_with("guest panicked at src/lib.rs:3:5: index out of bounds"));
+    assert!(err.downcast::<RustPanic>().is_ok());
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(target_arch = "aarch64", ignore)]
+fn out_of_bounds_trap_has_access_details() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (memory 1)
+            (func (export "load") (param i32) (result i32)
+                (i32.load (local.get 0)))
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let load: NativeFunc<i32, i32> = instance.exports.get_native_function("load")?;
+
+    let e = load.call(0x10010).unwrap_err();
+    let fault = e.memory_access_fault().expect("the fault should be known");
+    assert_eq!(fault.memory_index.as_u32(), 0);
+    assert_eq!(fault.address, 0x10010);
+    assert_eq!(fault.memory_size, 0x10000);
+    assert!(e
+        .message()
+        .contains("(memory 0, address 0x10010, memory size 65536 bytes)"));
+
+    Ok(())
+}
+
 #[test]
 fn rust_panic_import() -> Result<()> {
     let store = get_store(false);
@@ -389,6 +626,25 @@ fn rust_panic_start_function() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn host_function_called_by_the_host() -> Result<()> {
+    let store = get_store(false);
+    let fails = Function::new_native(&store, || -> Result<(), RuntimeError> {
+        Err(RuntimeError::new("this is a host error"))
+    });
+    let fails: NativeFunc<(), ()> = fails.native()?;
+    assert_eq!(fails.call().unwrap_err().message(), "this is a host error");
+
+    let panics = Function::new_native(&store, || panic!("this is a panic"));
+    let panics: NativeFunc<(), ()> = panics.native()?;
+    let err = panic::catch_unwind(AssertUnwindSafe(|| {
+        drop(panics.call());
+    }))
+    .unwrap_err();
+    assert_eq!(err.downcast_ref::<&'static str>(), Some(&"this is a panic"));
+    Ok(())
+}
+
 #[test]
 fn mismatched_arguments() -> Result<()> {
     let store = get_store(false);
@@ -518,3 +774,107 @@ fn present_after_module_drop() -> Result<()> {
         // assert_eq!(t.trace()[0].func_index(), 0);
     }
 }
+
+#[test]
+#[cfg(feature = "test-jit")]
+fn trap_sink_receives_the_traps() -> Result<()> {
+    use crate::utils::get_compiler;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Records(Mutex<Vec<TrapRecord>>);
+
+    impl TrapSink for Records {
+        fn on_trap(&self, record: &TrapRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    let records = Arc::new(Records::default());
+    let engine = JIT::new(get_compiler(false))
+        .trap_sink(records.clone())
+        .engine();
+    let store = Store::new(&engine);
+    let module = Module::new(
+        &store,
+        r#"(module
+            (func (export "ok"))
+            (func $die (export "die") unreachable)
+            (func (export "call_die") (call $die)))"#,
+    )?;
+    let instance = Instance::new(&module, &imports! {})?;
+
+    instance.exports.get_function("ok")?.call(&[])?;
+    assert!(records.0.lock().unwrap().is_empty());
+
+    instance.exports.get_function("die")?.call(&[]).unwrap_err();
+    let call_die: NativeFunc<(), ()> = instance.exports.get_native_function("call_die")?;
+    call_die.call().unwrap_err();
+
+    let records = records.0.lock().unwrap();
+    assert_eq!(records.len(), 2);
+    for record in records.iter() {
+        assert_eq!(record.trap_code, Some(TrapCode::UnreachableCodeReached));
+        assert_eq!(record.module_hash, module.hash());
+        assert_eq!(record.frames[0].func_index(), 0);
+    }
+    assert_eq!(records[0].frames.len(), 1);
+    assert_eq!(records[1].frames.len(), 2);
+    assert_eq!(records[0].instance_id, records[1].instance_id);
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "test-jit")]
+fn trap_sink_receives_the_traps_of_reexported_host_functions() -> Result<()> {
+    use crate::utils::get_compiler;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Records(Mutex<Vec<TrapRecord>>);
+
+    impl TrapSink for Records {
+        fn on_trap(&self, record: &TrapRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    let records = Arc::new(Records::default());
+    let engine = JIT::new(get_compiler(false))
+        .trap_sink(records.clone())
+        .engine();
+    let store = Store::new(&engine);
+    // The `vmctx` of a re-exported import is the environment of the host
+    // function, not the context of the exporting instance.
+    let module = Module::new(
+        &store,
+        r#"(module
+            (func $fail (import "host" "fail"))
+            (export "fail" (func $fail)))"#,
+    )?;
+    let fail = Function::new_native(&store, || -> Result<(), RuntimeError> {
+        Err(RuntimeError::new("host failure"))
+    });
+    let instance = Instance::new(&module, &imports! { "host" => { "fail" => fail.clone() } })?;
+
+    fail.call(&[]).unwrap_err();
+    assert!(records.0.lock().unwrap().is_empty());
+
+    instance
+        .exports
+        .get_function("fail")?
+        .call(&[])
+        .unwrap_err();
+    let reexported: NativeFunc<(), ()> = instance.exports.get_native_function("fail")?;
+    reexported.call().unwrap_err();
+
+    let records = records.0.lock().unwrap();
+    assert_eq!(records.len(), 2);
+    for record in records.iter() {
+        assert_eq!(record.trap_code, None);
+        assert_eq!(record.message, "host failure");
+        assert_eq!(record.module_hash, module.hash());
+    }
+    assert_eq!(records[0].instance_id, records[1].instance_id);
+    Ok(())
+}
@@ -38,3 +38,38 @@ pub fn run_wasi(wast_path: &str, base_dir: &str, compiler: &str) -> anyhow::Resu
 
     Ok(())
 }
+
+#[test]
+fn wasi_env_accounts_syscalls_and_io() -> anyhow::Result<()> {
+    use wasmer::{Instance, Module};
+    use wasmer_wasi::{SyscallClass, WasiState};
+
+    let store = get_store(false);
+    let wat = r#"(module
+        (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "random_get"
+            (func $random_get (param i32 i32) (result i32)))
+        (memory (export "memory") 2)
+        ;; An iovec pointing to "hello".
+        (data (i32.const 0) "\10\00\00\00\05\00\00\00")
+        (data (i32.const 16) "hello")
+        (func (export "_start")
+            (drop (call $random_get (i32.const 32) (i32.const 8)))
+            (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 24)))
+            (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 24)))))"#;
+    let module = Module::new(&store, wat)?;
+    let mut wasi_env = WasiState::new("stats").finalize()?;
+    let import_object = wasi_env.import_object(&module)?;
+    let instance = Instance::new(&module, &import_object)?;
+    instance.exports.get_function("_start")?.call(&[])?;
+
+    let stats = wasi_env.stats();
+    assert_eq!(stats.syscalls.get(&SyscallClass::Fd), Some(&2));
+    assert_eq!(stats.syscalls.get(&SyscallClass::Random), Some(&1));
+    assert_eq!(stats.total_syscalls(), 3);
+    assert_eq!(stats.bytes_written, 10);
+    assert_eq!(stats.bytes_read, 0);
+    assert_eq!(stats.peak_memory, 2 * 65536);
+    Ok(())
+}
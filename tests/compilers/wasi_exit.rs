@@ -0,0 +1,40 @@
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_wasi::{GuestExit, WasiCallOutcome, WasiState};
+
+fn run(wat: &str) -> Result<Result<WasiCallOutcome, RuntimeError>> {
+    let store = get_store(false);
+    let module = Module::new(&store, wat)?;
+    let mut wasi_env = WasiState::new("exit").finalize()?;
+    let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+    let start = instance.exports.get_function("_start")?;
+    Ok(wasi_env.call(start, &[]))
+}
+
+#[test]
+fn proc_exit_is_returned_as_a_guest_exit() -> Result<()> {
+    let wat = r#"(module
+        (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        (memory (export "memory") 1)
+        (func (export "_start")
+           (call $proc_exit (i32.const 0))
+           unreachable))"#;
+    let outcome = run(wat)?.expect("an exit is not an error");
+    assert_eq!(outcome, WasiCallOutcome::Exited(GuestExit(0)));
+
+    let wat = wat.replace("(i32.const 0)", "(i32.const 3)");
+    let outcome = run(&wat)?.expect("an exit is not an error");
+    assert_eq!(outcome, WasiCallOutcome::Exited(GuestExit(3)));
+    Ok(())
+}
+
+#[test]
+fn guest_failures_stay_errors() -> Result<()> {
+    let wat = r#"(module
+        (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        (memory (export "memory") 1)
+        (func (export "_start") unreachable))"#;
+    assert!(run(wat)?.is_err());
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_wasi::{UserDatabase, WasiEnv, WasiState, WasiStateBuilder};
+
+/// Instantiates a guest opening `path` and reading it through the WASI
+/// syscalls, with the negated errno as result on failure.
+fn instantiate(builder: &mut WasiStateBuilder, path: &str) -> Result<(Instance, WasiEnv)> {
+    let wat = format!(
+        r#"(module
+        (import "wasi_snapshot_preview1" "path_open"
+            (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_pread"
+            (func $fd_pread (param i32 i32 i32 i64 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_filestat_set_size"
+            (func $fd_filestat_set_size (param i32 i64) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "{path}")
+        ;; A single iovec of 512 bytes at 128.
+        (data (i32.const 72) "\80\00\00\00\00\02\00\00")
+        (func $result (param $errno i32) (param $address i32) (result i32)
+            (if (result i32) (local.get $errno)
+                (then (i32.sub (i32.const 0) (local.get $errno)))
+                (else (i32.load (local.get $address)))))
+        (func (export "open") (result i32)
+            (call $result
+                (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const {len})
+                    (i32.const 0) (i64.const 0x400006) (i64.const 0) (i32.const 0) (i32.const 64))
+                (i32.const 64)))
+        (func (export "read") (param $fd i32) (result i32)
+            (call $result
+                (call $fd_read (local.get $fd) (i32.const 72) (i32.const 1) (i32.const 68))
+                (i32.const 68)))
+        (func (export "pread") (param $fd i32) (param $offset i64) (result i32)
+            (call $result
+                (call $fd_pread
+                    (local.get $fd) (i32.const 72) (i32.const 1) (local.get $offset) (i32.const 68))
+                (i32.const 68)))
+        (func (export "set_size") (param $fd i32) (param $size i64) (result i32)
+            (call $fd_filestat_set_size (local.get $fd) (local.get $size))))"#,
+        path = path,
+        len = path.len(),
+    );
+    let store = get_store(false);
+    let module = Module::new(&store, wat)?;
+    let mut wasi_env = builder.finalize()?;
+    let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+    Ok((instance, wasi_env))
+}
+
+#[test]
+fn in_memory_files_are_read_up_to_their_end() -> Result<()> {
+    let mut builder = WasiState::new("test_prog");
+    builder.users(UserDatabase::single_user("alice", 1000, 1000));
+    let (instance, _wasi_env) = instantiate(&mut builder, "etc/passwd")?;
+    let open: NativeFunc<(), i32> = instance.exports.get_native_function("open")?;
+    let read: NativeFunc<i32, i32> = instance.exports.get_native_function("read")?;
+    let pread: NativeFunc<(i32, i64), i32> = instance.exports.get_native_function("pread")?;
+    let set_size: NativeFunc<(i32, i64), i32> = instance.exports.get_native_function("set_size")?;
+
+    let fd = open.call()?;
+    assert!(fd > 0, "path_open failed with {}", -fd);
+    let len = read.call(fd)?;
+    assert!(len > 4);
+    assert_eq!(read.call(fd)?, 0);
+    assert_eq!(pread.call(fd, len as i64 + 100)?, 0);
+    assert_eq!(pread.call(fd, 1)?, len - 1);
+
+    // The file shrinks under the offset of the fd.
+    assert_eq!(set_size.call(fd, 4)?, 0);
+    assert_eq!(read.call(fd)?, 0);
+    assert_eq!(pread.call(fd, len as i64)?, 0);
+    assert_eq!(pread.call(fd, 2)?, 2);
+    Ok(())
+}
+
+#[test]
+fn proc_files_shrunk_under_an_fd_are_read_up_to_their_end() -> Result<()> {
+    let mut builder = WasiState::new("test_prog");
+    builder.proc_fs(true);
+    let (instance, _wasi_env) = instantiate(&mut builder, "proc/self/status")?;
+    let open: NativeFunc<(), i32> = instance.exports.get_native_function("open")?;
+    let read: NativeFunc<i32, i32> = instance.exports.get_native_function("read")?;
+    let pread: NativeFunc<(i32, i64), i32> = instance.exports.get_native_function("pread")?;
+    let set_size: NativeFunc<(i32, i64), i32> = instance.exports.get_native_function("set_size")?;
+
+    let fd = open.call()?;
+    assert!(fd > 0, "path_open failed with {}", -fd);
+    let len = read.call(fd)?;
+    assert!(len > 0);
+
+    // The status shrinks under the offset of the fd, then is regenerated
+    // when it's opened again.
+    assert_eq!(set_size.call(fd, 0)?, 0);
+    assert_eq!(read.call(fd)?, 0);
+    assert_eq!(pread.call(fd, 1)?, 0);
+    let other_fd = open.call()?;
+    assert!(other_fd > 0, "path_open failed with {}", -other_fd);
+    assert_eq!(read.call(other_fd)?, len);
+    assert_eq!(read.call(fd)?, 0);
+    assert_eq!(pread.call(fd, 0)?, len);
+    Ok(())
+}
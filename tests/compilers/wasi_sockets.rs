@@ -0,0 +1,150 @@
+use crate::utils::get_store;
+use anyhow::Result;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use wasmer::*;
+use wasmer_wasi::types::*;
+use wasmer_wasi::{HostSocket, WasiEnv, WasiState, WasiStateBuilder, VIRTUAL_ROOT_FD};
+
+/// The fd of the only socket given to the guest.
+const SOCK: i32 = VIRTUAL_ROOT_FD as i32 + 1;
+
+#[cfg(unix)]
+type SockAccept = NativeFunc<(i32, i32, i32), i32>;
+type SockRecv = NativeFunc<(i32, i32, i32, i32, i32, i32), i32>;
+type SockSend = NativeFunc<(i32, i32, i32, i32, i32), i32>;
+type SockShutdown = NativeFunc<(i32, i32), i32>;
+
+/// Instantiates a guest exporting the socket syscalls as they are.
+fn instantiate(builder: &mut WasiStateBuilder) -> Result<(Instance, WasiEnv)> {
+    let wat = r#"(module
+        (import "wasi_snapshot_preview1" "sock_accept"
+            (func $sock_accept (param i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "sock_recv"
+            (func $sock_recv (param i32 i32 i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "sock_send"
+            (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "sock_shutdown"
+            (func $sock_shutdown (param i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (export "sock_accept" (func $sock_accept))
+        (export "sock_recv" (func $sock_recv))
+        (export "sock_send" (func $sock_send))
+        (export "sock_shutdown" (func $sock_shutdown)))"#;
+    let store = get_store(false);
+    let module = Module::new(&store, wat)?;
+    let mut wasi_env = builder.finalize()?;
+    let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+    Ok((instance, wasi_env))
+}
+
+/// Returns the two ends of a TCP connection.
+fn connection() -> Result<(TcpStream, TcpStream)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let stream = TcpStream::connect(listener.local_addr()?)?;
+    let (peer, _) = listener.accept()?;
+    Ok((stream, peer))
+}
+
+/// Writes the iovecs `(buf, buf_len)` of the guest at `offset`.
+fn write_iovecs(memory: &Memory, offset: usize, iovecs: &[(u32, u32)]) {
+    let view = memory.view::<u32>();
+    for (i, &(buf, buf_len)) in iovecs.iter().enumerate() {
+        view[offset / 4 + 2 * i].set(buf);
+        view[offset / 4 + 2 * i + 1].set(buf_len);
+    }
+}
+
+fn read_bytes(memory: &Memory, range: std::ops::Range<usize>) -> Vec<u8> {
+    memory.view::<u8>()[range]
+        .iter()
+        .map(|cell| cell.get())
+        .collect()
+}
+
+#[test]
+fn sockets_send_receive_and_shut_down() -> Result<()> {
+    let (stream, mut peer) = connection()?;
+    let mut builder = WasiState::new("test_prog");
+    builder.socket("peer", HostSocket::new(stream)?);
+    let (instance, _wasi_env) = instantiate(&mut builder)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let sock_recv: SockRecv = instance.exports.get_native_function("sock_recv")?;
+    let sock_send: SockSend = instance.exports.get_native_function("sock_send")?;
+    let sock_shutdown: SockShutdown = instance.exports.get_native_function("sock_shutdown")?;
+
+    // The iovecs overlap: the bytes of the second one overwrite the end of
+    // the first one. The rest of the data arrives while the guest waits.
+    peer.write_all(b"abcd")?;
+    let writer = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        peer.write_all(b"efgh").map(|()| peer)
+    });
+    write_iovecs(memory, 0, &[(100, 4), (102, 4)]);
+    let waitall = i32::from(__WASI_SOCK_RECV_WAITALL);
+    assert_eq!(sock_recv.call(SOCK, 0, 2, waitall, 16, 20)?, 0);
+    assert_eq!(memory.view::<u32>()[4].get(), 8);
+    assert_eq!(read_bytes(memory, 100..106), b"abefgh");
+    let mut peer = writer.join().unwrap()?;
+
+    memory.view::<u8>()[200..211]
+        .iter()
+        .zip(b"hello world")
+        .for_each(|(cell, &byte)| cell.set(byte));
+    write_iovecs(memory, 0, &[(200, 6), (206, 5)]);
+    assert_eq!(sock_send.call(SOCK, 0, 2, 0, 16)?, 0);
+    assert_eq!(memory.view::<u32>()[4].get(), 11);
+    let mut sent = [0; 11];
+    peer.read_exact(&mut sent)?;
+    assert_eq!(&sent, b"hello world");
+
+    assert_eq!(sock_shutdown.call(SOCK, 4)?, i32::from(__WASI_EINVAL));
+    assert_eq!(sock_shutdown.call(SOCK, i32::from(__WASI_SHUT_WR))?, 0);
+    assert_eq!(peer.read(&mut sent)?, 0);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn sock_accept_waits_for_the_connections_of_the_host() -> Result<()> {
+    let listener = wasmer_wasi::SocketListener::new()?;
+    let mut builder = WasiState::new("test_prog");
+    builder.socket_listener("http", listener.clone());
+    let (instance, _wasi_env) = instantiate(&mut builder)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let sock_accept: SockAccept = instance.exports.get_native_function("sock_accept")?;
+    let sock_recv: SockRecv = instance.exports.get_native_function("sock_recv")?;
+    let sock_shutdown: SockShutdown = instance.exports.get_native_function("sock_shutdown")?;
+
+    let append = i32::from(__WASI_FDFLAG_APPEND);
+    assert_eq!(
+        sock_accept.call(SOCK, append, 16)?,
+        i32::from(__WASI_ENOTSUP)
+    );
+
+    // The connection arrives while the guest waits.
+    let host_listener = listener.clone();
+    let connector = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        host_listener.connect()
+    });
+    assert_eq!(sock_accept.call(SOCK, 0, 16)?, 0);
+    let mut host = connector.join().unwrap()?;
+    let connection = memory.view::<u32>()[4].get() as i32;
+    assert_ne!(connection, SOCK);
+    assert_eq!(listener.pending_connections(), 0);
+
+    host.write_all(b"ping")?;
+    write_iovecs(memory, 0, &[(100, 4)]);
+    let waitall = i32::from(__WASI_SOCK_RECV_WAITALL);
+    assert_eq!(sock_recv.call(connection, 0, 1, waitall, 24, 28)?, 0);
+    assert_eq!(read_bytes(memory, 100..104), b"ping");
+
+    // Accepted connections can be shut down, unlike the listener.
+    let both = i32::from(__WASI_SHUT_RD | __WASI_SHUT_WR);
+    assert_eq!(sock_shutdown.call(SOCK, both)?, i32::from(__WASI_ENOTSUP));
+    assert_eq!(sock_shutdown.call(connection, both)?, 0);
+    assert_eq!(host.read(&mut [0; 4])?, 0);
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_wasi::{WasiEnv, WasiState, WasiStateBuilder};
+
+/// Instantiates a guest getting the capabilities of the runtime into a
+/// buffer at 256 through the `wasix_get_capabilities` syscall.
+fn instantiate(builder: &mut WasiStateBuilder) -> Result<(Instance, WasiEnv)> {
+    let wat = r#"(module
+        (import "wasi_snapshot_preview1" "sched_yield" (func (result i32)))
+        (import "wasix_32v1" "wasix_get_capabilities"
+            (func $wasix_get_capabilities (param i32 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (func (export "get_capabilities") (param $buf_len i32) (result i32)
+            (call $wasix_get_capabilities (i32.const 256) (local.get $buf_len) (i32.const 0))))"#;
+    let store = get_store(false);
+    let module = Module::new(&store, wat)?;
+    let mut wasi_env = builder.finalize()?;
+    let instance = Instance::new(&module, &wasi_env.import_object(&module)?)?;
+    Ok((instance, wasi_env))
+}
+
+/// Returns the size written by the guest at 0, and the bytes at 256.
+fn read_capabilities(instance: &Instance) -> Result<(u32, Vec<u8>)> {
+    let view = instance.exports.get_memory("memory")?.view::<u8>();
+    let bytes = |range: std::ops::Range<usize>| {
+        view[range]
+            .iter()
+            .map(|cell| cell.get())
+            .collect::<Vec<_>>()
+    };
+    let mut len = [0; 4];
+    len.copy_from_slice(&bytes(0..4));
+    let len = u32::from_le_bytes(len);
+    Ok((len, bytes(256..256 + len as usize)))
+}
+
+#[test]
+fn wasix_get_capabilities_advertises_the_extensions() -> Result<()> {
+    let mut builder = WasiState::new("test_prog");
+    builder.extension("acme_kv_store", 2);
+    let (instance, _wasi_env) = instantiate(&mut builder)?;
+    let get_capabilities: NativeFunc<i32, i32> =
+        instance.exports.get_native_function("get_capabilities")?;
+
+    let expected = r#"{"version":1,"extensions":{"acme_kv_store":2,"wasix_get_capabilities":1}}"#;
+    assert_eq!(get_capabilities.call(4096)?, 0);
+    let (len, capabilities) = read_capabilities(&instance)?;
+    assert_eq!(len as usize, expected.len());
+    assert_eq!(String::from_utf8(capabilities)?, expected);
+
+    // The size is written even if the buffer is too small.
+    let view = instance.exports.get_memory("memory")?.view::<u8>();
+    for cell in view[256..512].iter() {
+        cell.set(0);
+    }
+    assert_eq!(
+        get_capabilities.call(10)?,
+        i32::from(wasmer_wasi::types::__WASI_EOVERFLOW)
+    );
+    let (len, capabilities) = read_capabilities(&instance)?;
+    assert_eq!(len as usize, expected.len());
+    assert!(capabilities.iter().all(|&b| b == 0));
+    Ok(())
+}
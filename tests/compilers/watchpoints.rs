@@ -0,0 +1,97 @@
+use crate::utils::get_store_with_middlewares;
+use anyhow::Result;
+use wasmer_middlewares::watchpoints::{WatchKind, Watchpoint, Watchpoints};
+
+use std::sync::Arc;
+use wasmer::*;
+
+#[test]
+fn watchpoint_stops_on_watched_writes() -> Result<()> {
+    let watchpoints = Arc::new(Watchpoints::new(vec![Watchpoint::new(
+        100..104,
+        WatchKind::Write,
+    )]));
+    let store = get_store_with_middlewares(std::iter::once(
+        watchpoints.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (memory (export "memory") 1)
+        (func (export "poke") (param i32 i32)
+           (i32.store offset=2 (local.get 0) (local.get 1)))
+        (func (export "peek") (param i32) (result i32)
+           (i32.load (local.get 0)))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let poke: NativeFunc<(i32, i32), ()> = instance.exports.get_native_function("poke")?;
+    let peek: NativeFunc<i32, i32> = instance.exports.get_native_function("peek")?;
+
+    poke.call(0, 7)?;
+    assert_eq!(peek.call(100)?, 0);
+
+    // The store covers the bytes 102..106.
+    let error = poke.call(100, 7).unwrap_err();
+    let hit = watchpoints.hit(&instance, &error).expect("watchpoint hit");
+    assert_eq!(hit.address, 102);
+    assert_eq!(hit.kind, WatchKind::Write);
+    assert_eq!(hit.frame.map(|frame| frame.func_index()), Some(0));
+    assert_eq!(peek.call(102)?, 0);
+
+    // The hit is reset, and other accesses still work.
+    assert!(watchpoints.hit(&instance, &error).is_none());
+    poke.call(200, 7)?;
+    assert_eq!(peek.call(202)?, 7);
+    Ok(())
+}
+
+#[test]
+fn watchpoints_keep_the_exports_of_the_guest() -> Result<()> {
+    let watchpoints = Arc::new(Watchpoints::new(vec![Watchpoint::new(
+        100..104,
+        WatchKind::Read,
+    )]));
+    let store = get_store_with_middlewares(std::iter::once(
+        watchpoints.clone() as Arc<dyn ModuleMiddleware>
+    ));
+    let wat = r#"(module
+        (memory (export "memory") 1)
+        (global (export "watchpoint_hit_kind") (mut i32) (i32.const 7))
+        (func (export "peek") (param i32) (result i32)
+           (i32.load (local.get 0)))
+)"#;
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let peek: NativeFunc<i32, i32> = instance.exports.get_native_function("peek")?;
+
+    let error = peek.call(98).unwrap_err();
+    let hit = watchpoints.hit(&instance, &error).expect("watchpoint hit");
+    assert_eq!(hit.address, 98);
+    assert_eq!(hit.kind, WatchKind::Read);
+    let guest_global = instance.exports.get_global("watchpoint_hit_kind")?;
+    assert_eq!(guest_global.get().unwrap_i32(), 7);
+
+    // The instances of modules without the middleware have no hits, whatever they export.
+    let plain_store = get_store_with_middlewares(std::iter::empty());
+    let plain_module = Module::new(&plain_store, wat)?;
+    let plain_instance = Instance::new(&plain_module, &imports! {})?;
+    assert!(watchpoints.hit(&plain_instance, &error).is_none());
+    Ok(())
+}
+
+#[test]
+fn watchpoints_reject_a_second_module() -> Result<()> {
+    let watchpoints = Arc::new(Watchpoints::new(vec![Watchpoint::new(
+        100..104,
+        WatchKind::Write,
+    )]));
+    let store =
+        get_store_with_middlewares(std::iter::once(watchpoints as Arc<dyn ModuleMiddleware>));
+    Module::new(&store, r#"(module (memory 1))"#)?;
+    match Module::new(&store, r#"(module (memory 2))"#) {
+        Err(CompileError::Wasm(WasmError::Middleware(error))) => {
+            assert_eq!(error.name, "watchpoints");
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    Ok(())
+}